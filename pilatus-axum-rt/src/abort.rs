@@ -19,8 +19,10 @@ pub(super) fn register_services(c: &mut ServiceCollection) {
         let config = c.get::<HttpAbortSettings>("http_abort").unwrap_or_default();
         Arc::new(AbortService::new(config.limit))
     });
+    c.register_instance(pilatus::ServiceInfo::shared::<Arc<AbortService>>());
     c.with::<Registered<Arc<AbortService>>>()
         .register(|c| AbortServiceInterface(Box::new(move |id| c.add(id))));
+    c.register_instance(pilatus::ServiceInfo::transient::<AbortServiceInterface>());
 
     #[rustfmt::skip]
     c.register_web("abort", |x| x
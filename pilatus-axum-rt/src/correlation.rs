@@ -0,0 +1,45 @@
+use axum::{
+    extract::Request,
+    http::{header::CONTENT_LENGTH, HeaderValue},
+    middleware::Next,
+};
+use pilatus_axum::{extract::CorrelationId, ApiError, Body, Response, CORRELATION_ID_HEADER};
+use tracing::{info_span, Instrument};
+
+/// Longer ids provided by clients are replaced, so they can't bloat the logs
+const MAX_CORRELATION_ID_LEN: usize = 128;
+
+/// Assigns each request a correlation id, which is recorded in the tracing span of the request,
+/// returned in the `x-correlation-id` header and added to the body of [`ApiError`] responses
+pub(super) async fn correlate_requests(mut request: Request, next: Next) -> Response {
+    let id = request
+        .headers()
+        .get(CORRELATION_ID_HEADER)
+        .and_then(|x| x.to_str().ok())
+        .filter(|x| !x.is_empty() && x.len() <= MAX_CORRELATION_ID_LEN)
+        .map(str::to_string)
+        .unwrap_or_else(|| uuid::Uuid::new_v4().to_string());
+    request.extensions_mut().insert(CorrelationId(id.clone()));
+
+    let span = info_span!("request", correlation_id = %id);
+    let mut response = next.run(request).instrument(span).await;
+    if let Some(error) = response.extensions_mut().remove::<ApiError>() {
+        response = replace_api_error(
+            response,
+            error.with_correlation_id(CorrelationId(id.clone())),
+        );
+    }
+    if let Ok(value) = HeaderValue::from_str(&id) {
+        response.headers_mut().insert(CORRELATION_ID_HEADER, value);
+    }
+    response
+}
+
+/// Keeps status and headers of `response`, but renders `error` as body
+pub(super) fn replace_api_error(response: Response, error: ApiError) -> Response {
+    let (mut parts, _) = response.into_parts();
+    parts.headers.remove(CONTENT_LENGTH);
+    let body = serde_json::to_vec(&error).expect("ApiError is always serializable");
+    parts.extensions.insert(error);
+    Response::from_parts(parts, Body::from(body))
+}
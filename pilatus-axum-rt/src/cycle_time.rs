@@ -0,0 +1,107 @@
+use std::{collections::HashMap, fmt::Write};
+
+use axum::http::header::CONTENT_TYPE;
+use minfac::ServiceCollection;
+use pilatus::{device::DeviceId, CycleTimeStats, CycleTimes};
+use pilatus_axum::{
+    extract::{InjectRegistered, Json, Path},
+    IntoResponse, ServiceCollectionExtensions,
+};
+
+pub(super) fn register_services(c: &mut ServiceCollection) {
+    #[rustfmt::skip]
+    c.register_web("cycle_time", |x| x
+        .http("", |m| m.get(get_stats).summary("Rolling cycle time statistics of all configured devices"))
+        .http("/metrics", |m| m.get(get_metrics).summary("Statistics in the Prometheus text format"))
+        .http("/:device_id/history", |m| m.get(get_history).summary("Periodic samples of the statistics, oldest first"))
+    );
+}
+
+async fn get_stats(
+    InjectRegistered(cycle_times): InjectRegistered<CycleTimes>,
+) -> impl IntoResponse {
+    Json(cycle_times.stats())
+}
+
+async fn get_history(
+    Path(device_id): Path<DeviceId>,
+    InjectRegistered(cycle_times): InjectRegistered<CycleTimes>,
+) -> impl IntoResponse {
+    Json(cycle_times.history(device_id))
+}
+
+async fn get_metrics(
+    InjectRegistered(cycle_times): InjectRegistered<CycleTimes>,
+) -> impl IntoResponse {
+    (
+        [(CONTENT_TYPE, "text/plain; version=0.0.4")],
+        render_metrics(&cycle_times.stats()),
+    )
+}
+
+fn render_metrics(stats: &HashMap<DeviceId, CycleTimeStats>) -> String {
+    let mut devices: Vec<_> = stats.iter().collect();
+    devices.sort_by_key(|(id, _)| **id);
+    let metrics: [(&str, &str, fn(&CycleTimeStats) -> Option<f64>); 8] = [
+        ("cycles", "Cycles within the rolling window", |s| {
+            Some(s.cycles as f64)
+        }),
+        ("rate_per_minute", "Finished cycles per minute", |s| {
+            s.rate_per_minute
+        }),
+        ("mean_ms", "Mean cycle time", |s| Some(s.mean_ms)),
+        ("p95_ms", "95th percentile of the cycle time", |s| {
+            Some(s.p95_ms)
+        }),
+        ("max_ms", "Maximum cycle time", |s| Some(s.max_ms)),
+        ("jitter_ms", "Standard deviation of the cycle time", |s| {
+            Some(s.jitter_ms)
+        }),
+        ("open_triggers", "Triggers without result yet", |s| {
+            Some(s.open_triggers as f64)
+        }),
+        ("takt_exceeded", "1, if the p95 exceeds the takt", |s| {
+            Some(if s.takt_exceeded { 1. } else { 0. })
+        }),
+    ];
+    let mut out = String::new();
+    for (name, help, value) in metrics {
+        let _ = writeln!(out, "# HELP pilatus_cycle_time_{name} {help}");
+        let _ = writeln!(out, "# TYPE pilatus_cycle_time_{name} gauge");
+        for (id, s) in &devices {
+            if let Some(v) = value(s) {
+                let _ = writeln!(out, "pilatus_cycle_time_{name}{{device_id=\"{id}\"}} {v}");
+            }
+        }
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn render_gauges_per_device() {
+        let id = DeviceId::new_v4();
+        let metrics = render_metrics(&HashMap::from([(
+            id,
+            CycleTimeStats {
+                cycles: 3,
+                mean_ms: 12.5,
+                takt_exceeded: true,
+                ..Default::default()
+            },
+        )]));
+        assert!(metrics.contains(&format!(
+            "pilatus_cycle_time_cycles{{device_id=\"{id}\"}} 3\n"
+        )));
+        assert!(metrics.contains(&format!(
+            "pilatus_cycle_time_mean_ms{{device_id=\"{id}\"}} 12.5\n"
+        )));
+        assert!(metrics.contains(&format!(
+            "pilatus_cycle_time_takt_exceeded{{device_id=\"{id}\"}} 1\n"
+        )));
+        assert!(!metrics.contains("pilatus_cycle_time_rate_per_minute{"));
+    }
+}
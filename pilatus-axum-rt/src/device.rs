@@ -1,18 +1,137 @@
-use minfac::ServiceCollection;
-use pilatus::{device::RecipeRunner, RecipeId};
+use std::{
+    collections::{BTreeSet, HashMap},
+    time::Duration,
+};
+
+use minfac::{Registered, ServiceCollection};
+use pilatus::{
+    device::{
+        ActorSystem, DeviceCapability, DeviceId, DeviceRuntimeStatus, DeviceStateStore,
+        DeviceStatistics, DeviceStatusRegistry, RecipeRunner, ScratchRecipe,
+    },
+    DeviceConfig, DeviceGroupId, GenericConfig, MaintenanceError, RecipeId, RecipeService, Role,
+};
 use pilatus_axum::{
-    extract::{InjectRegistered, Path},
-    http::StatusCode,
-    ServiceCollectionExtensions,
+    extract::{CurrentUser, InjectAll, InjectRegistered, Json, Path},
+    http::{header::CONTENT_TYPE, StatusCode},
+    IntoResponse, ServiceCollectionExtensions,
 };
 
 pub(super) fn register_services(c: &mut ServiceCollection) {
+    c.with::<Registered<GenericConfig>>().register(|c| {
+        c.get::<ScratchRecipeConfig>("scratch_recipe")
+            .unwrap_or_default()
+    });
+    c.register_instance(pilatus::ServiceInfo::transient::<ScratchRecipeConfig>());
+
     #[rustfmt::skip]
     c.register_web("recipe", |r| r
         .http("/start/:id", |m| m.get(set_active))
+        .http("/scratch/start", |m| m.put(start_scratch))
+        .http("/scratch/stop", |m| m.put(stop_scratch))
+        .http("/group/:group_id/restart", |m| m.put(restart_group))
+        .http("/device/:device_id/state", |m| m.get(get_device_state).summary("Runtime state of a device for diagnostics"))
+    );
+    #[rustfmt::skip]
+    c.register_web("device", |r| r
+        .http("/status", |m| m.get(get_device_status).summary("Lifecycle and persisted statistics of all devices of the running recipe"))
+        .http("/:device_id/recording", |m| m.get(get_recording).summary("Recorded messages of a device, one JSON object per line"))
+        .http("/:device_id/capabilities", |m| m.get(get_device_capabilities).summary("Names of the registered capabilities a device supports, e.g. `image_keys`"))
     );
 }
 
+#[derive(serde::Serialize)]
+struct DeviceStatusWithStatistics {
+    #[serde(flatten)]
+    status: DeviceRuntimeStatus,
+    /// Values are flushed periodically by the devices, so they might lag behind
+    #[serde(skip_serializing_if = "std::collections::BTreeMap::is_empty")]
+    statistics: std::collections::BTreeMap<String, u64>,
+}
+
+async fn get_device_status(
+    InjectRegistered(status): InjectRegistered<DeviceStatusRegistry>,
+    InjectRegistered(store): InjectRegistered<DeviceStateStore>,
+) -> Json<Vec<DeviceStatusWithStatistics>> {
+    Json(
+        status
+            .all()
+            .into_iter()
+            .map(|status| DeviceStatusWithStatistics {
+                statistics: DeviceStatistics::read(&store.scope(status.device_id))
+                    .unwrap_or_default(),
+                status,
+            })
+            .collect(),
+    )
+}
+
+/// Recording is enabled per device_type with `actor_system.record_messages` in the config
+async fn get_recording(
+    InjectRegistered(actor_system): InjectRegistered<ActorSystem>,
+    Path(device_id): Path<DeviceId>,
+) -> Result<impl IntoResponse, (StatusCode, String)> {
+    let recorder = actor_system.message_recorder(device_id).ok_or_else(|| {
+        (
+            StatusCode::NOT_FOUND,
+            format!("Messages of device {device_id} are not recorded"),
+        )
+    })?;
+    let mut body = Vec::new();
+    recorder
+        .write_to(&mut body)
+        .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
+    Ok(([(CONTENT_TYPE, "application/x-ndjson")], body))
+}
+
+async fn get_device_capabilities(
+    InjectRegistered(actor_system): InjectRegistered<ActorSystem>,
+    InjectAll(capabilities): InjectAll<DeviceCapability>,
+    Path(device_id): Path<DeviceId>,
+) -> Json<BTreeSet<&'static str>> {
+    Json(actor_system.device_capabilities(device_id, capabilities))
+}
+
+async fn get_device_state(
+    InjectRegistered(store): InjectRegistered<DeviceStateStore>,
+    Path(device_id): Path<DeviceId>,
+) -> Result<Json<std::collections::BTreeMap<String, serde_json::Value>>, (StatusCode, String)> {
+    store
+        .entries(device_id)
+        .map(Json)
+        .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))
+}
+
+/// Devices can't be started, stopped or restarted while the system is in maintenance mode
+fn runner_error(e: anyhow::Error) -> (StatusCode, String) {
+    let status = if e.is::<MaintenanceError>() {
+        StatusCode::CONFLICT
+    } else {
+        StatusCode::BAD_REQUEST
+    };
+    (status, e.to_string())
+}
+
+/// Restarts all devices of the group in the active recipe
+async fn restart_group(
+    InjectRegistered(runner): InjectRegistered<RecipeRunner>,
+    InjectRegistered(service): InjectRegistered<RecipeService>,
+    Path(group_id): Path<DeviceGroupId>,
+) -> Result<(), (StatusCode, String)> {
+    let state = service.state().await;
+    let (_, active) = state.recipes().active();
+    if !active.groups.contains_key(&group_id) {
+        return Err((
+            StatusCode::NOT_FOUND,
+            format!("Active recipe has no group {group_id}"),
+        ));
+    }
+    runner
+        .restart_devices(active.devices_in_group(group_id).collect())
+        .await
+        .map_err(runner_error)
+}
+
 async fn set_active(
     InjectRegistered(runner): InjectRegistered<RecipeRunner>,
     Path(recipe_id): Path<RecipeId>,
@@ -22,3 +141,62 @@ async fn set_active(
         .await
         .map_err(|x| (StatusCode::BAD_REQUEST, x.to_string()))
 }
+
+#[derive(serde::Deserialize)]
+#[serde(default, deny_unknown_fields)]
+struct ScratchRecipeConfig {
+    /// Scratch recipes stop the active recipe, so they mustn't be left running for longer
+    max_timeout_secs: u64,
+}
+
+impl Default for ScratchRecipeConfig {
+    fn default() -> Self {
+        Self {
+            max_timeout_secs: 60 * 60,
+        }
+    }
+}
+
+#[derive(serde::Deserialize)]
+#[serde(deny_unknown_fields)]
+struct ScratchRecipeRequest {
+    devices: HashMap<DeviceId, DeviceConfig>,
+    timeout_secs: u64,
+}
+
+/// Only operators and admins may replace the running devices
+fn require_operator(current: &CurrentUser) -> Result<(), (StatusCode, String)> {
+    current
+        .require(Role::Operator)
+        .map_err(|(status, e)| (status, e.to_string()))
+}
+
+async fn start_scratch(
+    InjectRegistered(runner): InjectRegistered<RecipeRunner>,
+    InjectRegistered(config): InjectRegistered<ScratchRecipeConfig>,
+    current: CurrentUser,
+    Json(request): Json<ScratchRecipeRequest>,
+) -> Result<(), (StatusCode, String)> {
+    require_operator(&current)?;
+    if request.timeout_secs > config.max_timeout_secs {
+        return Err((
+            StatusCode::BAD_REQUEST,
+            format!("timeout_secs must not exceed {}", config.max_timeout_secs),
+        ));
+    }
+    runner
+        .run_scratch_recipe(ScratchRecipe::new(
+            request.devices,
+            Duration::from_secs(request.timeout_secs),
+        ))
+        .await
+        .map_err(runner_error)
+}
+
+async fn stop_scratch(
+    InjectRegistered(runner): InjectRegistered<RecipeRunner>,
+    current: CurrentUser,
+) -> Result<(), (StatusCode, String)> {
+    require_operator(&current)?;
+    runner.stop_scratch_recipe().await.map_err(runner_error)
+}
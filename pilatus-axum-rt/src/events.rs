@@ -0,0 +1,90 @@
+use futures::{SinkExt, StreamExt};
+use minfac::ServiceCollection;
+use pilatus::{EventBus, EventQuery, SystemEvent, SystemEventKind};
+use pilatus_axum::{
+    extract::{
+        ws::{Message, WebSocket, WebSocketUpgrade},
+        CurrentUser, InjectRegistered, Json, Query,
+    },
+    IntoResponse, ServiceCollectionExtensions,
+};
+use serde::Deserialize;
+use tracing::debug;
+
+pub(super) fn register_services(c: &mut ServiceCollection) {
+    #[rustfmt::skip]
+    c.register_web("system", |x| x
+        .http("/events", |m| m.get(list_events))
+        .http("/events/live", |m| m.get(stream_events))
+        .http("/events/user_action", |m| m.post(publish_user_action))
+    );
+}
+
+async fn list_events(
+    InjectRegistered(bus): InjectRegistered<EventBus>,
+    Query(query): Query<EventQuery>,
+) -> Json<Vec<SystemEvent>> {
+    Json(bus.query(&query))
+}
+
+async fn stream_events(
+    upgrade: WebSocketUpgrade,
+    InjectRegistered(bus): InjectRegistered<EventBus>,
+) -> impl IntoResponse {
+    upgrade.into_inner().on_upgrade(move |socket| async move {
+        debug!("Subscribe system events");
+        handle_socket(socket, bus).await;
+        debug!("System event subscription ended.");
+    })
+}
+
+async fn handle_socket(socket: WebSocket, bus: EventBus) {
+    let (mut socket_tx, mut socket_rx) = socket.split();
+    let mut events = bus.subscribe();
+    tokio::select!(
+        _ = async {
+            while let Some(event) = events.next().await {
+                let Ok(data) = serde_json::to_string(&event) else {
+                    continue;
+                };
+                if socket_tx.send(Message::Text(data)).await.is_err() {
+                    break;
+                }
+            }
+        } => {},
+        _ = async {
+            while let Some(r) = socket_rx.next().await {
+                if r.is_err() {
+                    break;
+                }
+            }
+        } => {}
+    );
+    let _ignore_if_not_closeable = socket_rx
+        .reunite(socket_tx)
+        .expect("Guaranted to be same source")
+        .close()
+        .await;
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct UserAction {
+    action: String,
+    #[serde(default)]
+    details: Option<String>,
+}
+
+/// Allows frontends to record operator actions in the event history.
+/// Only authenticated users may do so, so every action refers to the user who performed it
+async fn publish_user_action(
+    InjectRegistered(bus): InjectRegistered<EventBus>,
+    CurrentUser(user): CurrentUser,
+    Json(UserAction { action, details }): Json<UserAction>,
+) -> Json<SystemEvent> {
+    Json(bus.publish(SystemEventKind::UserAction {
+        action,
+        details,
+        user: Some(user.name),
+    }))
+}
@@ -1,6 +1,6 @@
 use pilatus_axum::{
     extract::{InjectAll, Json},
-    DeviceTopicWebComponentLocation, IntoResponse, ServiceCollectionExtensions,
+    BasePath, DeviceTopicWebComponentLocation, IntoResponse, ServiceCollectionExtensions,
     WebComponentLocation, WebComponentLocations,
 };
 use serde::Serialize;
@@ -15,10 +15,15 @@ struct FrontendConfig {
 }
 
 async fn frontend_config(
+    base_path: BasePath,
     InjectAll(device_topic): InjectAll<DeviceTopicWebComponentLocation>,
     InjectAll(raw): InjectAll<WebComponentLocation>,
 ) -> impl IntoResponse {
     Json(FrontendConfig {
-        web_component_locations: device_topic.map(Into::into).chain(raw).collect(),
+        web_component_locations: device_topic
+            .map(Into::into)
+            .chain(raw)
+            .collect::<WebComponentLocations>()
+            .with_base_path(base_path),
     })
 }
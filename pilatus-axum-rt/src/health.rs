@@ -0,0 +1,36 @@
+use minfac::ServiceCollection;
+use pilatus::HealthState;
+use pilatus_axum::{
+    extract::{InjectRegistered, Json},
+    http::StatusCode,
+    IntoResponse, ServiceCollectionExtensions,
+};
+
+pub(super) fn register_services(c: &mut ServiceCollection) {
+    #[rustfmt::skip]
+    c.register_web("health", |x| x
+        .http("", |m| m.get(get_health))
+        .http("/ready", |m| m.get(get_ready))
+    );
+}
+
+async fn get_health(InjectRegistered(health): InjectRegistered<HealthState>) -> impl IntoResponse {
+    let report = health.report();
+    let status = if report.is_healthy() {
+        StatusCode::OK
+    } else {
+        StatusCode::SERVICE_UNAVAILABLE
+    };
+    (status, Json(report))
+}
+
+/// Unavailable until all mandatory self-tests of the active recipe passed
+async fn get_ready(InjectRegistered(health): InjectRegistered<HealthState>) -> impl IntoResponse {
+    let report = health.report();
+    let status = if report.is_ready() {
+        StatusCode::OK
+    } else {
+        StatusCode::SERVICE_UNAVAILABLE
+    };
+    (status, Json(report))
+}
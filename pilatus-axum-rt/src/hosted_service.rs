@@ -4,8 +4,8 @@ use anyhow::{Context, Result};
 use axum::routing::get_service;
 use futures::{channel::oneshot, FutureExt};
 use minfac::{Registered, ServiceCollection, WeakServiceProvider};
-use pilatus::{prelude::*, GenericConfig, OnceExtractor, SystemShutdown};
-use pilatus_axum::MinfacRouter;
+use pilatus::{prelude::*, GenericConfig, MessageCatalog, OnceExtractor, SystemShutdown};
+use pilatus_axum::{BasePath, MinfacRouter, RouteGroup};
 use serde::Deserialize;
 use tokio::net::TcpListener;
 use tower_http::{cors::CorsLayer, services::ServeDir};
@@ -17,6 +17,7 @@ pub(super) fn register_services(c: &mut ServiceCollection) {
         Registered<GenericConfig>,
         Registered<SystemShutdown>,
         Registered<Arc<PrivateState>>,
+        Registered<Arc<MessageCatalog>>,
     )>()
     .register_hosted_service("Main Webserver", axum_service);
     c.register_shared(|| {
@@ -24,38 +25,104 @@ pub(super) fn register_services(c: &mut ServiceCollection) {
         Arc::new(PrivateState(tx.into(), rx.shared()))
     })
     .alias(|s| pilatus_axum::Stats::new(s.1.clone()));
+    c.register_instance(pilatus::ServiceInfo::shared::<pilatus_axum::Stats>());
 }
 
 #[derive(Debug, Deserialize, serde::Serialize)]
 #[serde(default)]
 #[serde(deny_unknown_fields)]
 struct WebConfig {
+    /// Headless runtimes don't serve anything, even if the webserver is registered
+    enabled: bool,
+    /// Used if no `listeners` are configured
     socket: SocketAddr,
     frontend: PathBuf,
     body_limit: usize,
+    /// Prefix of all routes (e.g. `/line-3`), if pilatus is served behind a path-prefixing reverse proxy.
+    /// Requests are accepted with and without prefix, so the proxy may strip it or not
+    base_path: String,
+    /// Honour `X-Forwarded-Prefix`, `X-Forwarded-Host` and `X-Forwarded-Proto` when generating links.
+    /// Only enable it, if all clients connect through a reverse proxy which sets or strips these headers
+    trust_forwarded_headers: bool,
+    /// E.g. the complete API on an internal port and a restricted operator API on another
+    listeners: Vec<ListenerConfig>,
+}
+
+#[derive(Debug, Clone, Deserialize, serde::Serialize)]
+#[serde(deny_unknown_fields)]
+struct ListenerConfig {
+    socket: SocketAddr,
+    /// Prefixes of the route groups (e.g. `["recipe", "image"]`) served under `/api`. All groups are served, if missing
+    #[serde(default)]
+    routes: Option<Vec<String>>,
+    /// Serve the frontend for paths outside of `/api`
+    #[serde(default = "serve_frontend")]
+    frontend: bool,
+    #[serde(default)]
+    tls: Option<TlsConfig>,
+}
+
+fn serve_frontend() -> bool {
+    true
+}
+
+/// PEM encoded files. Requires the `tls` feature
+#[derive(Debug, Clone, Deserialize, serde::Serialize)]
+#[serde(deny_unknown_fields)]
+struct TlsConfig {
+    cert: PathBuf,
+    key: PathBuf,
+}
+
+impl WebConfig {
+    fn listeners(&self) -> Vec<ListenerConfig> {
+        if self.listeners.is_empty() {
+            vec![ListenerConfig {
+                socket: self.socket,
+                routes: None,
+                frontend: true,
+                tls: None,
+            }]
+        } else {
+            self.listeners.clone()
+        }
+    }
+}
+
+impl ListenerConfig {
+    fn serves(&self, group: &RouteGroup) -> bool {
+        self.routes
+            .as_ref()
+            .map_or(true, |routes| routes.iter().any(|r| r == group.prefix))
+    }
 }
 
 struct PrivateState(
-    OnceExtractor<oneshot::Sender<SocketAddr>>,
-    futures::future::Shared<oneshot::Receiver<SocketAddr>>,
+    OnceExtractor<oneshot::Sender<Vec<SocketAddr>>>,
+    futures::future::Shared<oneshot::Receiver<Vec<SocketAddr>>>,
 );
 
 impl Default for WebConfig {
     fn default() -> Self {
         Self {
+            enabled: true,
             socket: SocketAddr::from(([0, 0, 0, 0], 80)),
             frontend: "dist".into(),
             body_limit: 8 * 1024 * 1024,
+            base_path: String::new(),
+            trust_forwarded_headers: false,
+            listeners: Vec::new(),
         }
     }
 }
 
 async fn axum_service(
-    (provider, config, shutdown, private_state): (
+    (provider, config, shutdown, private_state, catalog): (
         WeakServiceProvider,
         GenericConfig,
         SystemShutdown,
         Arc<PrivateState>,
+        Arc<MessageCatalog>,
     ),
 ) -> Result<(), anyhow::Error> {
     let web_config = config.get::<WebConfig>("web").unwrap_or_default();
@@ -64,31 +131,95 @@ async fn axum_service(
         serde_json::to_string(&web_config).unwrap(),
         &config
     );
-    info!(
-        "Starting axum on port {} with frontend on path {:?}",
-        web_config.socket, web_config.frontend
-    );
+    if !web_config.enabled {
+        info!("Webserver is disabled");
+        shutdown.await;
+        return Ok(());
+    }
 
-    let listener = TcpListener::bind(&web_config.socket)
-        .await
-        .context("Cannot open TCP-Connection for webserver. Is pilatus running already?")?;
+    let groups = provider
+        .get_all::<MinfacRouter>()
+        .map(|x| x.extract_unchecked())
+        .collect::<Vec<_>>();
+    let mut addresses = Vec::new();
+    let mut servers = Vec::new();
+    for listener_config in web_config.listeners() {
+        info!(
+            "Starting axum on {} with routes {:?} and frontend on path {:?}",
+            listener_config.socket,
+            listener_config.routes.as_deref().unwrap_or(&["*".into()]),
+            listener_config.frontend.then_some(&web_config.frontend)
+        );
+        let listener = TcpListener::bind(&listener_config.socket)
+            .await
+            .with_context(|| {
+                format!(
+                    "Cannot listen on {} for webserver. Is pilatus running already?",
+                    listener_config.socket
+                )
+            })?;
+        addresses.push(listener.local_addr()?);
+        let router = create_router(
+            &listener_config,
+            &web_config,
+            &groups,
+            &provider,
+            catalog.clone(),
+        );
+        servers.push(serve(
+            listener,
+            router,
+            listener_config.tls,
+            shutdown.clone(),
+        ));
+    }
     private_state
         .0
         .extract_unchecked()
-        .send(listener.local_addr()?)
+        .send(addresses)
         .expect("Receiver is stored within DI-Container");
 
-    let router = axum::Router::new()
-        .nest(
-            "/api",
-            provider
-                .get_all::<MinfacRouter>()
-                .fold(axum::Router::new(), |acc, n| {
-                    acc.merge(n.extract_unchecked())
-                }),
-        )
-        .fallback_service(get_service(ServeDir::new(web_config.frontend)))
-        .layer(super::inject::InjectLayer(provider))
+    futures::future::try_join_all(servers).await?;
+    Ok(())
+}
+
+fn create_router(
+    listener_config: &ListenerConfig,
+    web_config: &WebConfig,
+    groups: &[RouteGroup],
+    provider: &WeakServiceProvider,
+    catalog: Arc<MessageCatalog>,
+) -> axum::Router {
+    let base_path = BasePath::new(&web_config.base_path)
+        .with_trusted_forwarded_headers(web_config.trust_forwarded_headers);
+    let router = axum::Router::new();
+    #[cfg(feature = "openapi")]
+    let router = if listener_config.routes.is_none() {
+        router.merge(swagger_ui(provider, &base_path))
+    } else {
+        router
+    };
+    let router = router.nest(
+        "/api",
+        groups
+            .iter()
+            .filter(|g| listener_config.serves(g))
+            .fold(axum::Router::new(), |acc, g| acc.merge(g.router.clone())),
+    );
+    let router = if listener_config.frontend {
+        router.fallback_service(get_service(ServeDir::new(&web_config.frontend)))
+    } else {
+        router
+    };
+    let router = router
+        .layer(axum::middleware::from_fn_with_state(
+            catalog,
+            super::localization::localize_errors,
+        ))
+        .layer(axum::middleware::from_fn(
+            super::correlation::correlate_requests,
+        ))
+        .layer(super::inject::InjectLayer(provider.clone()))
         .layer(
             CorsLayer::new()
                 .allow_origin(tower_http::cors::Any)
@@ -96,15 +227,67 @@ async fn axum_service(
                 .allow_headers(tower_http::cors::Any),
         )
         .layer(axum::extract::DefaultBodyLimit::max(web_config.body_limit))
-        .layer(tower_http::trace::TraceLayer::new_for_http())
-        .into_make_service();
-    axum::serve(listener, router)
-        .with_graceful_shutdown(async move {
-            shutdown.await;
-            info!("Shutdown is triggered. If HostedServices still hangs, it might be related to https://github.com/hyperium/hyper-util/pull/101");
-        })
-        .await?;
-    Ok(())
+        .layer(tower_http::trace::TraceLayer::new_for_http());
+    base_path.apply(router)
+}
+
+async fn serve(
+    listener: TcpListener,
+    router: axum::Router,
+    tls: Option<TlsConfig>,
+    shutdown: SystemShutdown,
+) -> Result<()> {
+    match tls {
+        None => {
+            axum::serve(listener, router.into_make_service())
+                .with_graceful_shutdown(async move {
+                    shutdown.await;
+                    info!("Shutdown is triggered. If HostedServices still hangs, it might be related to https://github.com/hyperium/hyper-util/pull/101");
+                })
+                .await?;
+            Ok(())
+        }
+        #[cfg(feature = "tls")]
+        Some(tls) => {
+            let rustls = axum_server::tls_rustls::RustlsConfig::from_pem_file(&tls.cert, &tls.key)
+                .await
+                .with_context(|| format!("Cannot load TLS certificate {:?}", tls.cert))?;
+            let handle = axum_server::Handle::new();
+            let shutdown_handle = handle.clone();
+            tokio::spawn(async move {
+                shutdown.await;
+                shutdown_handle.graceful_shutdown(Some(std::time::Duration::from_secs(5)));
+            });
+            axum_server::from_tcp_rustls(listener.into_std()?, rustls)
+                .handle(handle)
+                .serve(router.into_make_service())
+                .await?;
+            Ok(())
+        }
+        #[cfg(not(feature = "tls"))]
+        Some(_) => Err(anyhow::anyhow!(
+            "Listener on {} requires TLS, but pilatus-axum-rt was built without the 'tls' feature",
+            listener.local_addr()?
+        )),
+    }
+}
+
+#[cfg(feature = "openapi")]
+fn swagger_ui(
+    provider: &WeakServiceProvider,
+    base_path: &BasePath,
+) -> utoipa_swagger_ui::SwaggerUi {
+    let docs = provider
+        .get_all::<pilatus_axum::RouteDocs>()
+        .collect::<Vec<_>>();
+    let spec =
+        pilatus_axum::openapi::openapi_spec("Pilatus", env!("CARGO_PKG_VERSION"), base_path, &docs);
+    // Routes are matched without the prefix, but the browser has to request the spec with it
+    utoipa_swagger_ui::SwaggerUi::new("/api-docs")
+        .url("/api-docs/openapi.json", spec)
+        .config(utoipa_swagger_ui::Config::from(
+            base_path.link("/api-docs/openapi.json"),
+        ))
 }
 
 #[cfg(test)]
@@ -119,5 +302,28 @@ mod tests {
         let adr: WebConfig = serde_json::from_str(raw).unwrap();
         assert_eq!(adr.socket.ip().to_string(), "0.0.0.0");
         assert_eq!(adr.frontend, WebConfig::default().frontend);
+        assert!(adr.enabled);
+        assert_eq!(1, adr.listeners().len());
+    }
+
+    #[test]
+    fn filter_route_groups_per_listener() {
+        let raw = r#"{
+            "listeners": [
+                { "socket": "127.0.0.1:8080" },
+                { "socket": "0.0.0.0:80", "routes": ["recipe", "image"], "frontend": false }
+            ]
+        }"#;
+        let config: WebConfig = serde_json::from_str(raw).unwrap();
+        let listeners = config.listeners();
+        let group = |prefix| RouteGroup {
+            prefix,
+            router: axum::Router::new(),
+        };
+        assert!(listeners[0].serves(&group("user")));
+        assert!(listeners[0].frontend);
+        assert!(listeners[1].serves(&group("image")));
+        assert!(!listeners[1].serves(&group("user")));
+        assert!(!listeners[1].frontend);
     }
 }
@@ -1,23 +1,37 @@
-use std::time::SystemTime;
+use std::{collections::HashMap, time::SystemTime};
 
 use axum::{extract::Query, response::sse::Event};
 use futures::{stream::BoxStream, Stream, StreamExt};
 use image::{ImageEncoder, ImageResult};
 use minfac::ServiceCollection;
-use pilatus::device::{ActorSystem, DeviceId, DynamicIdentifier};
+use pilatus::{
+    device::{ActorError, ActorSystem, DeviceCapability, DeviceId, DynamicIdentifier},
+    HealthState, ResourceAction, WorkerPoolKind, WorkerPools,
+};
 use pilatus_axum::{
-    extract::{ws::WebSocketUpgrade, InjectRegistered, Json, Path},
+    extract::{ws::WebSocketUpgrade, InjectRegistered, Json, Path, WebActorSystem},
     http::StatusCode,
-    image::{DefaultImageStreamer, ImageStreamer, LocalizableImageStreamer, StreamingImageFormat},
+    image::{
+        DefaultImageStreamer, ImageStreamer, LocalizableImageStreamer, StreamingImageFormat,
+        StreamingImageSelection, SubscriberOptions,
+    },
     sse::Sse,
-    AppendHeaders, Html, IntoResponse, ServiceCollectionExtensions,
+    ApiError, AppendHeaders, Html, IntoResponse, ServiceCollectionExtensions,
 };
 use pilatus_engineering::image::{
-    DynamicImage, GetImageMessage, ImageWithMeta, LumaImage, StreamImageError,
-    SubscribeDynamicImageMessage, SubscribeImageMessage, SubscribeLocalizableImageMessage,
+    ConvertedImage, DynamicImage, GetImageMessage, GetImageStatisticsMessage, ImageConverter,
+    ImageKey, ImageMeta, ImageWithMeta, ListImageKeysMessage, LumaImage, PixelFormat,
+    SpecificImageKey, StreamImageError, SubscribeDynamicImageMessage, SubscribeImageMessage,
+    SubscribeImageQuery, SubscribeLocalizableImageMessage,
 };
 use tracing::{debug, warn};
 
+use measure::ProjectorCache;
+
+mod measure;
+#[cfg(feature = "webrtc")]
+mod webrtc;
+
 pub(super) fn register_services(c: &mut ServiceCollection) {
     #[rustfmt::skip]
     c.register_web("image", |x| x
@@ -25,18 +39,32 @@ pub(super) fn register_services(c: &mut ServiceCollection) {
         .http("/list/subscribe", |m| m.get(list_subscribe_devices))
         .http("/list/stream", |m| m.get(list_stream_devices))
         .http("/list/stream/localizable", |m| m.get(list_localizable_stream_devices))
+        .http("/list/keys", |m| m.get(list_image_keys).summary("Keys of additional images per producer, which declares them"))
         .http("/stream", |m| m.get(stream_image_handler))
         .http("/subscribe", |m| m.get(subscribe_image_handler))
         .http("/stream/localizable", |m| m.get(stream_localizable_image_handler))
         .http("/viewer", |m| m.get(image_viewer))
+        .http("/:device_id/keys", |m| m.get(device_image_keys).summary("Keys of additional images of a producer"))
         .http("/:device_id/single", |m| m.get(single_luma_image_handler))
         .http("/:device_id/frame_intervals", |m| m.get(stream_frame_interval))
+        .http("/:device_id/snapshot", |m| m.get(snapshot_handler))
+        .http("/:device_id/statistics", |m| m.get(statistics_handler))
     );
+    c.register_instance(DeviceCapability::of::<SubscribeDynamicImageMessage>(
+        "stream_image",
+    ));
+    c.register_instance(DeviceCapability::of::<SubscribeLocalizableImageMessage>(
+        "stream_localizable_image",
+    ));
+    c.register_instance(DeviceCapability::of::<ListImageKeysMessage>("image_keys"));
+    measure::register_services(c);
+    #[cfg(feature = "webrtc")]
+    webrtc::register_services(c);
 }
 
 async fn stream_frame_interval(
     Path(device_id): Path<DeviceId>,
-    InjectRegistered(actor_system): InjectRegistered<ActorSystem>,
+    WebActorSystem(actor_system): WebActorSystem,
 ) -> Result<Sse<impl Stream<Item = Result<Event, anyhow::Error>>>, StatusCode> {
     let sender = actor_system
         .ask(device_id, SubscribeImageMessage::default())
@@ -59,7 +87,8 @@ async fn stream_frame_interval(
 
 async fn single_luma_image_handler(
     Path(device_id): Path<DeviceId>,
-    InjectRegistered(actor_system): InjectRegistered<ActorSystem>,
+    WebActorSystem(actor_system): WebActorSystem,
+    InjectRegistered(pools): InjectRegistered<WorkerPools>,
 ) -> Result<impl IntoResponse, StatusCode> {
     let img = LumaImage::from(
         actor_system
@@ -67,31 +96,34 @@ async fn single_luma_image_handler(
             .await
             .map_err(|_| StatusCode::BAD_REQUEST)?,
     );
-    pilatus::execute_blocking(move || {
-        let dims = img.dimensions();
-        let mut buf = Vec::with_capacity(dims.0.get() as usize * dims.1.get() as usize / 4);
-        let codec = image::codecs::png::PngEncoder::new(&mut buf);
-        codec.write_image(
-            img.buffer(),
-            dims.0.get(),
-            dims.1.get(),
-            image::ExtendedColorType::L8,
-        )?;
-        let name = chrono::Utc::now().format("%Y-%m-%d %H:%M:%S");
-        ImageResult::Ok((
-            AppendHeaders([(
-                "Content-Disposition",
-                format!("attachment; filename=\"{name}.png\""),
-            )]),
-            buf,
-        ))
-    })
-    .await
-    .map_err(|_| StatusCode::BAD_REQUEST)
+    pools
+        .get(WorkerPoolKind::Encode)
+        .execute_blocking(move || {
+            let dims = img.dimensions();
+            let mut buf = Vec::with_capacity(dims.0.get() as usize * dims.1.get() as usize / 4);
+            let codec = image::codecs::png::PngEncoder::new(&mut buf);
+            codec.write_image(
+                img.buffer(),
+                dims.0.get(),
+                dims.1.get(),
+                image::ExtendedColorType::L8,
+            )?;
+            let name = chrono::Utc::now().format("%Y-%m-%d %H:%M:%S");
+            ImageResult::Ok((
+                AppendHeaders([(
+                    "Content-Disposition",
+                    format!("attachment; filename=\"{name}.png\""),
+                )]),
+                buf,
+            ))
+        })
+        .await
+        .map_err(|_| StatusCode::BAD_REQUEST)
 }
 
 async fn single_dynamic_image_handler(
-    InjectRegistered(actor_system): InjectRegistered<ActorSystem>,
+    WebActorSystem(actor_system): WebActorSystem,
+    InjectRegistered(pools): InjectRegistered<WorkerPools>,
     Query(id): Query<DynamicIdentifier>,
 ) -> Result<impl IntoResponse, StatusCode> {
     let img = actor_system
@@ -103,19 +135,148 @@ async fn single_dynamic_image_handler(
         .ok_or(StatusCode::BAD_REQUEST)?
         .map_err(|_| StatusCode::BAD_REQUEST)?
         .image;
-    pilatus::execute_blocking(move || {
-        let buf = img.encode_png()?;
-        let name = chrono::Utc::now().format("%Y-%m-%d_%H-%M-%S-%f");
-        anyhow::Ok((
-            AppendHeaders([(
+    pools
+        .get(WorkerPoolKind::Encode)
+        .execute_blocking(move || {
+            let buf = img.encode_png()?;
+            let name = chrono::Utc::now().format("%Y-%m-%d_%H-%M-%S-%f");
+            anyhow::Ok((
+                AppendHeaders([(
+                    "Content-Disposition",
+                    format!("attachment; filename=\"{name}.png\""),
+                )]),
+                buf,
+            ))
+        })
+        .await
+        .map_err(|_| StatusCode::BAD_REQUEST)
+}
+
+#[derive(Debug, Default, Clone, Copy, serde::Deserialize)]
+#[serde(rename_all = "lowercase")]
+enum SnapshotFormat {
+    #[default]
+    Png,
+    Jpeg,
+}
+
+impl SnapshotFormat {
+    fn content_type(self) -> &'static str {
+        match self {
+            SnapshotFormat::Png => "image/png",
+            SnapshotFormat::Jpeg => "image/jpeg",
+        }
+    }
+
+    fn extension(self) -> &'static str {
+        match self {
+            SnapshotFormat::Png => "png",
+            SnapshotFormat::Jpeg => "jpg",
+        }
+    }
+
+    fn encode(self, image: DynamicImage) -> anyhow::Result<Vec<u8>> {
+        match self {
+            SnapshotFormat::Png => Ok(image.encode_png()?),
+            SnapshotFormat::Jpeg => {
+                let (width, height) = image.dimensions();
+                // JPEG has no 16bit support
+                let luma8 = match ImageConverter::new([PixelFormat::Luma8]).convert(image)? {
+                    ConvertedImage::Luma8(x) => x,
+                    x => anyhow::bail!("Unsupported image format: {:?}", x.format()),
+                };
+                let mut buf = Vec::with_capacity(luma8.buffer().len() / 4);
+                image::codecs::jpeg::JpegEncoder::new_with_quality(&mut buf, 90).write_image(
+                    luma8.buffer(),
+                    width.get(),
+                    height.get(),
+                    image::ExtendedColorType::L8,
+                )?;
+                Ok(buf)
+            }
+        }
+    }
+}
+
+#[derive(serde::Deserialize)]
+struct SnapshotQuery {
+    #[serde(default)]
+    format: SnapshotFormat,
+    key: Option<SpecificImageKey>,
+}
+
+/// Plain HTTP alternative to the websocket protocols for documentation tools and simple integrations
+async fn snapshot_handler(
+    Path(device_id): Path<DeviceId>,
+    Query(SnapshotQuery { format, key }): Query<SnapshotQuery>,
+    WebActorSystem(actor_system): WebActorSystem,
+    InjectRegistered(pools): InjectRegistered<WorkerPools>,
+) -> Result<impl IntoResponse, (StatusCode, String)> {
+    let key = key.map(ImageKey::from).unwrap_or(ImageKey::unspecified());
+    let image = fetch_snapshot(&actor_system, device_id, &key).await?;
+    let encoded = pools
+        .get(WorkerPoolKind::Encode)
+        .execute_blocking(move || format.encode(image))
+        .await
+        .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
+    let name = chrono::Utc::now().format("%Y-%m-%d_%H-%M-%S-%f");
+    Ok((
+        AppendHeaders([
+            ("Content-Type", format.content_type().to_string()),
+            (
                 "Content-Disposition",
-                format!("attachment; filename=\"{name}.png\""),
-            )]),
-            buf,
-        ))
-    })
-    .await
-    .map_err(|_| StatusCode::BAD_REQUEST)
+                format!("inline; filename=\"{name}.{}\"", format.extension()),
+            ),
+        ]),
+        encoded,
+    ))
+}
+
+/// Histogram and exposure metrics without transferring the frame. Query: `?bins=64`
+async fn statistics_handler(
+    Path(device_id): Path<DeviceId>,
+    Query(msg): Query<GetImageStatisticsMessage>,
+    WebActorSystem(actor_system): WebActorSystem,
+) -> Result<impl IntoResponse, (StatusCode, String)> {
+    actor_system
+        .ask(device_id, msg)
+        .await
+        .map(Json)
+        .map_err(|e| (StatusCode::BAD_REQUEST, format!("Bummer, it failed: {e:?}")))
+}
+
+/// Devices without dynamic image support are asked for a single luma image instead
+async fn fetch_snapshot(
+    actor_system: &ActorSystem,
+    device_id: DeviceId,
+    key: &ImageKey,
+) -> Result<DynamicImage, (StatusCode, String)> {
+    let missing_key = || (StatusCode::NOT_FOUND, format!("Unknown image key {key:?}"));
+    match actor_system
+        .ask(device_id, SubscribeDynamicImageMessage::default())
+        .await
+    {
+        Ok(mut stream) => {
+            let image = stream
+                .next()
+                .await
+                .ok_or_else(|| (StatusCode::NOT_FOUND, "Stream ended".to_string()))?
+                .map_err(|e| (StatusCode::BAD_GATEWAY, e.to_string()))?;
+            image.by_name(key).cloned().ok_or_else(missing_key)
+        }
+        Err(ActorError::UnknownMessageType(_)) => {
+            let image = actor_system
+                .ask(device_id, GetImageMessage::default())
+                .await
+                .map_err(|e| (StatusCode::NOT_FOUND, e.to_string()))?;
+            image
+                .by_name(key)
+                .cloned()
+                .map(DynamicImage::Luma8)
+                .ok_or_else(missing_key)
+        }
+        Err(e) => Err((StatusCode::NOT_FOUND, e.to_string())),
+    }
 }
 
 #[cfg(debug_assertions)]
@@ -135,37 +296,95 @@ async fn image_viewer() -> Html<&'static str> {
     include_str!("../resources/image_viewer.html").into()
 }
 
-async fn list_subscribe_devices(
-    InjectRegistered(actor_system): InjectRegistered<ActorSystem>,
-) -> impl IntoResponse {
+async fn list_subscribe_devices(WebActorSystem(actor_system): WebActorSystem) -> impl IntoResponse {
     Json(actor_system.list_devices_for_message_type::<SubscribeDynamicImageMessage>())
 }
 
-async fn list_stream_devices(
-    InjectRegistered(actor_system): InjectRegistered<ActorSystem>,
-) -> impl IntoResponse {
+async fn list_stream_devices(WebActorSystem(actor_system): WebActorSystem) -> impl IntoResponse {
     Json(actor_system.list_devices_for_message_type::<SubscribeImageMessage>())
 }
 
 async fn list_localizable_stream_devices(
-    InjectRegistered(actor_system): InjectRegistered<ActorSystem>,
+    WebActorSystem(actor_system): WebActorSystem,
 ) -> impl IntoResponse {
     Json(actor_system.list_devices_for_message_type::<SubscribeLocalizableImageMessage>())
 }
 
+async fn list_image_keys(
+    WebActorSystem(actor_system): WebActorSystem,
+) -> Json<HashMap<DeviceId, Vec<SpecificImageKey>>> {
+    let mut result = HashMap::new();
+    for device_id in actor_system.list_devices_for_message_type::<ListImageKeysMessage>() {
+        match actor_system
+            .ask(device_id, ListImageKeysMessage::default())
+            .await
+        {
+            Ok(keys) => {
+                result.insert(device_id, keys);
+            }
+            Err(e) => debug!("Couldn't list image keys of {device_id}: {e:?}"),
+        }
+    }
+    Json(result)
+}
+
+async fn device_image_keys(
+    WebActorSystem(actor_system): WebActorSystem,
+    Path(device_id): Path<DeviceId>,
+) -> Result<Json<Vec<SpecificImageKey>>, ApiError> {
+    actor_system
+        .ask(device_id, ListImageKeysMessage::default())
+        .await
+        .map(Json)
+        .map_err(ApiError::from)
+}
+
+fn refuse_if_overloaded(health: &HealthState) -> Result<(), (StatusCode, String)> {
+    if health.is_action_active(ResourceAction::RefuseSubscriptions) {
+        warn!("Refuse image subscription due to high resource usage");
+        Err((
+            StatusCode::SERVICE_UNAVAILABLE,
+            "Refuse new subscriptions due to high resource usage".to_string(),
+        ))
+    } else {
+        Ok(())
+    }
+}
+
 async fn subscribe_image_handler(
     upgrade: WebSocketUpgrade,
-    Query(StreamQuery { device_id, format }): Query<StreamQuery>,
-    InjectRegistered(actor_system): InjectRegistered<ActorSystem>,
-) -> Result<impl IntoResponse, impl IntoResponse> {
+    Query(StreamQuery {
+        device_id,
+        format,
+        keys,
+        max_fps,
+        max_message_size,
+    }): Query<StreamQuery>,
+    WebActorSystem(actor_system): WebActorSystem,
+    InjectRegistered(health): InjectRegistered<HealthState>,
+) -> Result<impl IntoResponse, (StatusCode, String)> {
     debug!("Start streaming websocket images: {device_id:?}");
+    refuse_if_overloaded(&health)?;
+    let additional = keys
+        .as_deref()
+        .map(parse_key_selection)
+        .transpose()
+        .map_err(|e| (StatusCode::BAD_REQUEST, e))?
+        .unwrap_or_default();
+    let msg = SubscribeDynamicImageMessage::from(SubscribeImageQuery::with_keys(
+        additional.iter().map(|(key, _)| key.clone()),
+    ));
+    let selection = StreamingImageSelection { format, additional };
 
-    ImageStreamer::<SubscribeDynamicImageMessage, BoxStream<'static, _>, _>::stream_image(
+    ImageStreamer::<SubscribeDynamicImageMessage, BoxStream<'static, _>, _>::stream_image_with_options(
         upgrade,
         device_id,
         actor_system,
-        move |x: Result<ImageWithMeta<DynamicImage>, StreamImageError<DynamicImage>>| async move {
-            Ok((x, format))
+        msg,
+        SubscriberOptions::try_with_max_fps(max_fps)?.with_max_message_size(max_message_size),
+        move |x: Result<ImageWithMeta<DynamicImage>, StreamImageError<DynamicImage>>| {
+            let selection = selection.clone();
+            async move { Ok((x, selection)) }
         },
     )
     .await
@@ -177,27 +396,74 @@ async fn subscribe_image_handler(
 
 async fn stream_image_handler(
     upgrade: WebSocketUpgrade,
-    Query(StreamQuery { device_id, .. }): Query<StreamQuery>,
-    InjectRegistered(actor_system): InjectRegistered<ActorSystem>,
-) -> Result<impl IntoResponse, impl IntoResponse> {
+    Query(StreamQuery {
+        device_id,
+        max_fps,
+        max_message_size,
+        ..
+    }): Query<StreamQuery>,
+    WebActorSystem(actor_system): WebActorSystem,
+    InjectRegistered(health): InjectRegistered<HealthState>,
+) -> Result<impl IntoResponse, (StatusCode, String)> {
     debug!("Start streaming images: {device_id:?}");
-    DefaultImageStreamer::stream_image(upgrade, device_id, actor_system, |x| async { Ok(x.image) })
-        .await
-        .map_err(|e| {
-            warn!("Couldn't establish connection: {e:?}");
-            e
-        })
+    refuse_if_overloaded(&health)?;
+    DefaultImageStreamer::stream_image_with_options(
+        upgrade,
+        device_id,
+        actor_system,
+        Default::default(),
+        SubscriberOptions::try_with_max_fps(max_fps)?.with_max_message_size(max_message_size),
+        |x| async { Ok(x.image) },
+    )
+    .await
+    .map_err(|e| {
+        warn!("Couldn't establish connection: {e:?}");
+        e
+    })
 }
 
 async fn stream_localizable_image_handler(
     upgrade: WebSocketUpgrade,
-    Query(StreamQuery { device_id, .. }): Query<StreamQuery>,
-    InjectRegistered(actor_system): InjectRegistered<ActorSystem>,
-) -> Result<impl IntoResponse, impl IntoResponse> {
+    Query(StreamQuery {
+        device_id,
+        max_fps,
+        max_message_size,
+        ..
+    }): Query<StreamQuery>,
+    WebActorSystem(actor_system): WebActorSystem,
+    InjectRegistered(health): InjectRegistered<HealthState>,
+    InjectRegistered(projectors): InjectRegistered<ProjectorCache>,
+) -> Result<impl IntoResponse, (StatusCode, String)> {
     debug!("Start streaming images: {device_id:?}");
-    LocalizableImageStreamer::stream_image(upgrade, device_id, actor_system, |x| async {
-        Ok(x.image)
-    })
+    refuse_if_overloaded(&health)?;
+    // Remembers the projectors of sent frames for click-to-measure
+    let resolved_device_id = device_id.or_else(|| {
+        match actor_system
+            .list_devices_for_message_type::<SubscribeLocalizableImageMessage>()
+            .as_slice()
+        {
+            [single] => Some(*single),
+            _ => None,
+        }
+    });
+    LocalizableImageStreamer::stream_image_with_options(
+        upgrade,
+        device_id,
+        actor_system,
+        Default::default(),
+        SubscriberOptions::try_with_max_fps(max_fps)?.with_max_message_size(max_message_size),
+        move |x| {
+            if let (Some(device_id), Some(projector)) = (resolved_device_id, x.projector.clone()) {
+                projectors.insert(device_id, x.frame_id, projector);
+            }
+            // Clients pass the frame_id of the meta to the measure endpoints
+            let meta = ImageMeta {
+                hash: x.hash,
+                frame_id: x.frame_id,
+            };
+            async move { Ok((x.image, meta)) }
+        },
+    )
     .await
     .map_err(|e| {
         warn!("Couldn't establish connection: {e:?}");
@@ -210,4 +476,191 @@ struct StreamQuery {
     device_id: Option<DeviceId>,
     #[serde(default)]
     format: StreamingImageFormat,
+    /// Additional images as comma separated list of `key` or `key:format` (e.g. `overlay:jpeg,raw:raw`)
+    keys: Option<String>,
+    max_fps: Option<f32>,
+    /// Larger frames are sent in chunks (see `pilatus::ImageChunk`)
+    max_message_size: Option<usize>,
+}
+
+fn parse_key_selection(input: &str) -> Result<Vec<(ImageKey, StreamingImageFormat)>, String> {
+    input
+        .split(',')
+        .filter(|x| !x.is_empty())
+        .map(|entry| {
+            let (key, format) = match entry.split_once(':') {
+                Some((key, format)) => (key, format),
+                None => (entry, "jpeg"),
+            };
+            let format = match format.to_ascii_lowercase().as_str() {
+                "jpeg" => StreamingImageFormat::Jpeg,
+                "raw" => StreamingImageFormat::Raw,
+                x => return Err(format!("Unknown image format '{x}'")),
+            };
+            let key = ImageKey::try_from(std::borrow::Cow::Owned(key.to_string()))
+                .map_err(|e| e.to_string())?;
+            Ok((key, format))
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_keys_with_and_without_format() {
+        let keys = parse_key_selection("overlay,raw:raw").unwrap();
+        assert_eq!(
+            vec![
+                (
+                    ImageKey::try_from("overlay").unwrap(),
+                    StreamingImageFormat::Jpeg
+                ),
+                (
+                    ImageKey::try_from("raw").unwrap(),
+                    StreamingImageFormat::Raw
+                ),
+            ],
+            keys
+        );
+        assert!(parse_key_selection("overlay:png").is_err());
+    }
+
+    #[tokio::test]
+    async fn list_keys_of_declaring_producers() {
+        async fn list_keys(
+            _: &mut (),
+            _: ListImageKeysMessage,
+        ) -> pilatus::device::ActorResult<ListImageKeysMessage> {
+            Ok(vec![SpecificImageKey::try_from("overlay").unwrap()])
+        }
+        let actor_system = ActorSystem::new();
+        let declaring = DeviceId::new_v4();
+        let device = actor_system
+            .register(declaring)
+            .add_handler(list_keys)
+            .execute(());
+        let undeclared = DeviceId::new_v4();
+        let _undeclared = actor_system.register::<()>(undeclared);
+        let list = async {
+            let Json(keys) = list_image_keys(WebActorSystem(actor_system.clone())).await;
+            let capabilities = [
+                DeviceCapability::of::<ListImageKeysMessage>("image_keys"),
+                DeviceCapability::of::<SubscribeDynamicImageMessage>("stream_image"),
+            ];
+            assert_eq!(
+                std::collections::BTreeSet::from(["image_keys"]),
+                actor_system.device_capabilities(declaring, capabilities)
+            );
+            actor_system.forget_senders();
+            keys
+        };
+        let (_, keys) = futures::future::join(device, list).await;
+        assert_eq!(
+            HashMap::from([(
+                declaring,
+                vec![SpecificImageKey::try_from("overlay").unwrap()]
+            )]),
+            keys
+        );
+    }
+
+    fn luma8(width: u32, height: u32) -> LumaImage {
+        LumaImage::new_vec(
+            vec![64; (width * height) as usize],
+            width.try_into().unwrap(),
+            height.try_into().unwrap(),
+        )
+    }
+
+    fn luma16(width: u32, height: u32) -> DynamicImage {
+        DynamicImage::Luma16(pilatus_engineering::image::GenericImage::new_vec(
+            vec![0x8000; (width * height) as usize],
+            width.try_into().unwrap(),
+            height.try_into().unwrap(),
+        ))
+    }
+
+    #[test]
+    fn encode_snapshot_formats() {
+        let png = SnapshotFormat::Png
+            .encode(DynamicImage::Luma8(luma8(16, 8)))
+            .unwrap();
+        assert!(png.starts_with(b"\x89PNG"));
+
+        // Only the most significant byte of 16bit images is kept
+        let jpeg = SnapshotFormat::Jpeg.encode(luma16(16, 8)).unwrap();
+        let decoded = image::load_from_memory_with_format(&jpeg, image::ImageFormat::Jpeg)
+            .unwrap()
+            .to_luma8();
+        assert_eq!((16, 8), decoded.dimensions());
+        assert!(decoded.pixels().all(|p| p.0[0].abs_diff(128) <= 2));
+    }
+
+    #[tokio::test]
+    async fn fetch_snapshot_by_key() {
+        async fn subscribe(
+            _: &mut (),
+            _: SubscribeDynamicImageMessage,
+        ) -> pilatus::device::ActorResult<SubscribeDynamicImageMessage> {
+            let mut image = ImageWithMeta::with_hash(DynamicImage::Luma8(luma8(4, 2)), None);
+            image.insert(ImageKey::try_from("overlay").unwrap(), luma16(2, 2));
+            Ok(futures::stream::iter([Ok(image)]).boxed())
+        }
+        let actor_system = ActorSystem::new();
+        let id = DeviceId::new_v4();
+        let device = actor_system.register(id).add_handler(subscribe).execute(());
+        let fetch = async {
+            let main = fetch_snapshot(&actor_system, id, &ImageKey::unspecified()).await;
+            assert!(matches!(main, Ok(DynamicImage::Luma8(_))));
+            let overlay = ImageKey::try_from("overlay").unwrap();
+            let overlay = fetch_snapshot(&actor_system, id, &overlay).await;
+            assert!(matches!(overlay, Ok(DynamicImage::Luma16(_))));
+            let unknown = ImageKey::try_from("unknown").unwrap();
+            let unknown = fetch_snapshot(&actor_system, id, &unknown).await;
+            assert!(matches!(unknown, Err((StatusCode::NOT_FOUND, _))));
+            let unknown_device =
+                fetch_snapshot(&actor_system, DeviceId::new_v4(), &ImageKey::unspecified()).await;
+            assert!(matches!(unknown_device, Err((StatusCode::NOT_FOUND, _))));
+            actor_system.forget_senders();
+        };
+        futures::future::join(device, fetch).await;
+    }
+
+    #[tokio::test]
+    async fn fall_back_to_luma_images_for_snapshots() {
+        async fn get_image(
+            _: &mut (),
+            _: GetImageMessage,
+        ) -> pilatus::device::ActorResult<GetImageMessage> {
+            Ok(ImageWithMeta::with_hash(luma8(4, 2), None))
+        }
+        let actor_system = ActorSystem::new();
+        let id = DeviceId::new_v4();
+        let device = actor_system.register(id).add_handler(get_image).execute(());
+        let fetch = async {
+            let response = snapshot_handler(
+                Path(id),
+                Query(SnapshotQuery {
+                    format: SnapshotFormat::Jpeg,
+                    key: None,
+                }),
+                WebActorSystem(actor_system.clone()),
+                InjectRegistered(WorkerPools::new(&pilatus::WorkerPoolsConfig::default())),
+            )
+            .await
+            .map_err(|(_, e)| e)
+            .unwrap()
+            .into_response();
+            actor_system.forget_senders();
+            response
+        };
+        let (_, response) = futures::future::join(device, fetch).await;
+        assert_eq!(StatusCode::OK, response.status());
+        let headers = response.headers();
+        assert_eq!("image/jpeg", headers["Content-Type"]);
+        let disposition = headers["Content-Disposition"].to_str().unwrap();
+        assert!(disposition.ends_with(".jpg\""), "{disposition}");
+    }
 }
@@ -0,0 +1,286 @@
+//! Click-to-measure: Clients send pixel coordinates of a streamed frame and receive world coordinates
+//!
+//! Projectors only exist server-side, so the localizable stream remembers the projectors of the latest frames per device.
+//! Requests identify the frame by the `frame_id` in the meta of the streamed frame. Entries of stopped devices are removed.
+
+use std::{
+    collections::{HashMap, VecDeque},
+    sync::{Arc, Mutex},
+};
+
+use futures::{SinkExt, StreamExt};
+use minfac::{Registered, ServiceCollection};
+use pilatus::{device::DeviceId, prelude::*, EventBus, SystemEventKind, SystemShutdown};
+use pilatus_axum::{
+    extract::{
+        ws::{Message, WebSocket, WebSocketUpgrade},
+        InjectRegistered, Json, Path,
+    },
+    http::StatusCode,
+    IntoResponse, ServiceCollectionExtensions,
+};
+use pilatus_engineering::{
+    image::{DynamicPointProjector, FrameId, PointProjector},
+    InvertibleTransform,
+};
+use serde::{Deserialize, Serialize};
+use tracing::debug;
+
+/// Clients usually click on the frame they see, which is at most a few frames old
+const FRAMES_PER_DEVICE: usize = 16;
+
+pub(super) fn register_services(c: &mut ServiceCollection) {
+    c.register_shared(|| Arc::new(ProjectorCache::default()))
+        .alias(|x| ProjectorCache::clone(&x));
+    c.register_instance(pilatus::ServiceInfo::shared::<ProjectorCache>());
+    c.with::<(
+        Registered<ProjectorCache>,
+        Registered<EventBus>,
+        Registered<SystemShutdown>,
+    )>()
+    .register_hosted_service("Projector Cache Cleanup", remove_stopped_devices);
+
+    #[rustfmt::skip]
+    c.register_web("image", |x| x
+        .http("/:device_id/measure", |m| m.post(measure_handler).summary("World coordinates of pixels in a recently streamed localizable frame"))
+        .http("/:device_id/measure/live", |m| m.get(measure_socket_handler))
+    );
+}
+
+/// Projectors of the most recently streamed frames per device
+#[derive(Clone, Default)]
+pub(super) struct ProjectorCache(Arc<Mutex<HashMap<DeviceId, VecDeque<CachedProjector>>>>);
+
+struct CachedProjector {
+    frame_id: FrameId,
+    projector: DynamicPointProjector,
+}
+
+impl ProjectorCache {
+    pub(super) fn insert(
+        &self,
+        device_id: DeviceId,
+        frame_id: FrameId,
+        projector: DynamicPointProjector,
+    ) {
+        let mut lock = self.0.lock().expect("Never poisoned");
+        let frames = lock.entry(device_id).or_default();
+        // Subscribers of the same device stream the same frames
+        if frames.iter().any(|x| x.frame_id == frame_id) {
+            return;
+        }
+        if frames.len() == FRAMES_PER_DEVICE {
+            frames.pop_front();
+        }
+        frames.push_back(CachedProjector {
+            frame_id,
+            projector,
+        });
+    }
+
+    fn remove_device(&self, device_id: DeviceId) {
+        self.0.lock().expect("Never poisoned").remove(&device_id);
+    }
+
+    fn get(&self, device_id: DeviceId, frame_id: FrameId) -> Option<DynamicPointProjector> {
+        let lock = self.0.lock().expect("Never poisoned");
+        lock.get(&device_id)?
+            .iter()
+            .rev()
+            .find(|x| x.frame_id == frame_id)
+            .map(|x| x.projector.clone())
+    }
+
+    fn measure(
+        &self,
+        device_id: DeviceId,
+        request: MeasureRequest,
+    ) -> Result<Vec<WorldPoint>, (StatusCode, String)> {
+        let projector = self.get(device_id, request.frame_id).ok_or_else(|| {
+            (
+                StatusCode::NOT_FOUND,
+                format!(
+                    "Frame {:?} is not streamed recently or has no projector",
+                    request.frame_id
+                ),
+            )
+        })?;
+        request
+            .points
+            .into_iter()
+            .map(|point| project(&*projector, point))
+            .collect::<anyhow::Result<_>>()
+            .map_err(|e| (StatusCode::UNPROCESSABLE_ENTITY, format!("{e:?}")))
+    }
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct MeasureRequest {
+    frame_id: FrameId,
+    /// Pixel coordinates (x=col, y=row)
+    points: Vec<[f64; 2]>,
+}
+
+async fn remove_stopped_devices(
+    (cache, bus, shutdown): (ProjectorCache, EventBus, SystemShutdown),
+) -> anyhow::Result<()> {
+    let mut events = bus.subscribe().take_until(shutdown);
+    while let Some(event) = events.next().await {
+        if let SystemEventKind::DeviceStopped { device_id, .. } = event.kind {
+            cache.remove_device(device_id);
+        }
+    }
+    Ok(())
+}
+
+#[derive(Debug, Serialize)]
+struct WorldPoint {
+    x_mm: f64,
+    y_mm: f64,
+    z_mm: f64,
+    /// World distance of half a pixel around the point. Clicks cannot be more precise than that
+    error_mm: f64,
+}
+
+fn project(
+    projector: &(dyn PointProjector + Send + Sync),
+    [x, y]: [f64; 2],
+) -> anyhow::Result<WorldPoint> {
+    let to_mm = |(x, y): (f64, f64)| -> anyhow::Result<[f64; 3]> {
+        let t = projector.project_to_world_plane(
+            &InvertibleTransform::from_rotation_before_translation((x, y), 0.),
+        )?;
+        Ok([t.m41 * 1000., t.m42 * 1000., t.m43 * 1000.])
+    };
+    let center = to_mm((x, y))?;
+    let mut error_mm: f64 = 0.;
+    for neighbour in [(x - 0.5, y), (x + 0.5, y), (x, y - 0.5), (x, y + 0.5)] {
+        let p = to_mm(neighbour)?;
+        let distance = (0..3)
+            .map(|i| (p[i] - center[i]).powi(2))
+            .sum::<f64>()
+            .sqrt();
+        error_mm = error_mm.max(distance);
+    }
+    Ok(WorldPoint {
+        x_mm: center[0],
+        y_mm: center[1],
+        z_mm: center[2],
+        error_mm,
+    })
+}
+
+async fn measure_handler(
+    Path(device_id): Path<DeviceId>,
+    InjectRegistered(cache): InjectRegistered<ProjectorCache>,
+    Json(request): Json<MeasureRequest>,
+) -> Result<impl IntoResponse, (StatusCode, String)> {
+    cache.measure(device_id, request).map(Json)
+}
+
+/// Same as the POST endpoint, but without a request per click. Each text message is answered with the points or `{"error": "..."}`
+async fn measure_socket_handler(
+    Path(device_id): Path<DeviceId>,
+    upgrade: WebSocketUpgrade,
+    InjectRegistered(cache): InjectRegistered<ProjectorCache>,
+) -> impl IntoResponse {
+    upgrade.into_inner().on_upgrade(move |socket| async move {
+        debug!("Start measuring on {device_id}");
+        handle_socket(socket, device_id, cache).await;
+        debug!("Stop measuring on {device_id}");
+    })
+}
+
+async fn handle_socket(mut socket: WebSocket, device_id: DeviceId, cache: ProjectorCache) {
+    while let Some(Ok(message)) = socket.next().await {
+        let Message::Text(text) = message else {
+            continue;
+        };
+        let response = match serde_json::from_str::<MeasureRequest>(&text) {
+            Ok(request) => match cache.measure(device_id, request) {
+                Ok(points) => serde_json::to_string(&points),
+                Err((_, error)) => serde_json::to_string(&serde_json::json!({ "error": error })),
+            },
+            Err(e) => serde_json::to_string(&serde_json::json!({ "error": e.to_string() })),
+        };
+        let Ok(response) = response else {
+            continue;
+        };
+        if socket.send(Message::Text(response)).await.is_err() {
+            break;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use pilatus_engineering::{InvertibleTransform3d, InvertibleTransform3dRaw};
+
+    use super::*;
+
+    /// 0.1mm per pixel
+    struct Scale;
+
+    impl PointProjector for Scale {
+        fn project_to_world_plane(
+            &self,
+            transform: &InvertibleTransform,
+        ) -> Result<InvertibleTransform3d, anyhow::Error> {
+            Ok(InvertibleTransform3d::new_unchecked(
+                InvertibleTransform3dRaw {
+                    m11: 1.,
+                    m12: 0.,
+                    m13: 0.,
+                    m21: 0.,
+                    m22: 1.,
+                    m23: 0.,
+                    m31: 0.,
+                    m32: 0.,
+                    m33: 1.,
+                    m41: transform.m31 * 0.0001,
+                    m42: transform.m32 * 0.0001,
+                    m43: 0.,
+                },
+            ))
+        }
+    }
+
+    #[test]
+    fn measure_recently_streamed_frames_only() {
+        let cache = ProjectorCache::default();
+        let device_id = DeviceId::new_v4();
+        let frames: Vec<_> = (0..=FRAMES_PER_DEVICE).map(|_| FrameId::next()).collect();
+        for frame_id in frames.iter() {
+            // Every subscriber of the device inserts the same frames
+            cache.insert(device_id, *frame_id, Arc::new(Scale));
+            cache.insert(device_id, *frame_id, Arc::new(Scale));
+        }
+        let request = |frame_id| MeasureRequest {
+            frame_id,
+            points: vec![[100., 20.]],
+        };
+
+        let (status, _) = cache.measure(device_id, request(frames[0])).unwrap_err();
+        assert_eq!(StatusCode::NOT_FOUND, status);
+
+        let points = cache
+            .measure(device_id, request(frames[FRAMES_PER_DEVICE]))
+            .unwrap();
+        let WorldPoint {
+            x_mm,
+            y_mm,
+            error_mm,
+            ..
+        } = &points[0];
+        assert!((x_mm - 10.).abs() < 1e-9, "{x_mm}");
+        assert!((y_mm - 2.).abs() < 1e-9, "{y_mm}");
+        assert!((error_mm - 0.05).abs() < 1e-9, "{error_mm}");
+
+        cache.remove_device(device_id);
+        let (status, _) = cache
+            .measure(device_id, request(frames[FRAMES_PER_DEVICE]))
+            .unwrap_err();
+        assert_eq!(StatusCode::NOT_FOUND, status);
+    }
+}
@@ -0,0 +1,343 @@
+//! Low-latency alternative to the JPEG-over-WebSocket stream for operator live views.
+//! The client posts its SDP-Offer to `/api/image/webrtc/offer` and receives the answer once ICE-gathering is complete.
+//! Frames are encoded as H.264 and sent as a single video track. Frames published while the encoder is busy
+//! are dropped by the subscription, so slow clients don't accumulate latency.
+
+use std::{sync::Arc, time::Duration};
+
+use bytes::Bytes;
+use futures::StreamExt;
+use minfac::ServiceCollection;
+use openh264::{encoder::Encoder, formats::YUVSlices};
+use pilatus::{device::DeviceId, HealthState};
+use pilatus_axum::{
+    extract::{InjectRegistered, Json, Query, WebActorSystem},
+    http::StatusCode,
+    ServiceCollectionExtensions,
+};
+use pilatus_engineering::image::{LumaImage, SubscribeImageMessage};
+use tokio::sync::watch;
+use tracing::{debug, warn};
+use webrtc::{
+    api::{
+        interceptor_registry::register_default_interceptors,
+        media_engine::{MediaEngine, MIME_TYPE_H264},
+        APIBuilder, API,
+    },
+    interceptor::registry::Registry,
+    media::Sample,
+    peer_connection::{
+        configuration::RTCConfiguration, peer_connection_state::RTCPeerConnectionState,
+        sdp::session_description::RTCSessionDescription,
+    },
+    rtp_transceiver::rtp_codec::RTCRtpCodecCapability,
+    track::track_local::{track_local_static_sample::TrackLocalStaticSample, TrackLocal},
+};
+
+use super::refuse_if_overloaded;
+
+pub(super) fn register_services(c: &mut ServiceCollection) {
+    #[rustfmt::skip]
+    c.register_web("image/webrtc", |x| x
+        .http("/offer", |m| m.post(offer_handler))
+    );
+}
+
+#[derive(serde::Deserialize)]
+struct OfferQuery {
+    device_id: DeviceId,
+}
+
+async fn offer_handler(
+    Query(OfferQuery { device_id }): Query<OfferQuery>,
+    WebActorSystem(actor_system): WebActorSystem,
+    InjectRegistered(health): InjectRegistered<HealthState>,
+    Json(offer): Json<RTCSessionDescription>,
+) -> Result<Json<RTCSessionDescription>, (StatusCode, String)> {
+    refuse_if_overloaded(&health)?;
+    let images = actor_system
+        .ask(device_id, SubscribeImageMessage::default())
+        .await
+        .map_err(|e| (StatusCode::NOT_FOUND, e.to_string()))?;
+
+    let internal = |e: webrtc::Error| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string());
+    let api = build_api().map_err(internal)?;
+    let peer = Arc::new(
+        api.new_peer_connection(RTCConfiguration::default())
+            .await
+            .map_err(internal)?,
+    );
+    let track = Arc::new(TrackLocalStaticSample::new(
+        RTCRtpCodecCapability {
+            mime_type: MIME_TYPE_H264.to_owned(),
+            ..Default::default()
+        },
+        "video".to_owned(),
+        format!("pilatus-{device_id}"),
+    ));
+    peer.add_track(track.clone() as Arc<dyn TrackLocal + Send + Sync>)
+        .await
+        .map_err(internal)?;
+
+    let (state_tx, mut state_rx) = watch::channel(RTCPeerConnectionState::New);
+    peer.on_peer_connection_state_change(Box::new(move |state| {
+        debug!("WebRTC connection state changed: {state}");
+        let _ignore_no_receiver = state_tx.send(state);
+        Box::pin(async {})
+    }));
+
+    peer.set_remote_description(offer).await.map_err(internal)?;
+    let answer = peer.create_answer(None).await.map_err(internal)?;
+    let mut gathering_complete = peer.gathering_complete_promise().await;
+    peer.set_local_description(answer).await.map_err(internal)?;
+    let _ = gathering_complete.recv().await;
+    let local = peer.local_description().await.ok_or_else(|| {
+        (
+            StatusCode::INTERNAL_SERVER_ERROR,
+            "No local description after ICE-gathering".to_string(),
+        )
+    })?;
+
+    tokio::spawn(async move {
+        let closed = async move {
+            while state_rx.changed().await.is_ok() {
+                if matches!(
+                    *state_rx.borrow(),
+                    RTCPeerConnectionState::Failed
+                        | RTCPeerConnectionState::Closed
+                        | RTCPeerConnectionState::Disconnected
+                ) {
+                    break;
+                }
+            }
+        };
+        let send = send_frames(images.map(|x| x.image), track);
+        futures::pin_mut!(closed, send);
+        futures::future::select(closed, send).await;
+        if let Err(e) = peer.close().await {
+            debug!("Couldn't close WebRTC connection: {e}");
+        }
+    });
+
+    Ok(Json(local))
+}
+
+fn build_api() -> Result<API, webrtc::Error> {
+    let mut media_engine = MediaEngine::default();
+    media_engine.register_default_codecs()?;
+    let registry = register_default_interceptors(Registry::new(), &mut media_engine)?;
+    Ok(APIBuilder::new()
+        .with_media_engine(media_engine)
+        .with_interceptor_registry(registry)
+        .build())
+}
+
+async fn send_frames(
+    mut images: impl futures::Stream<Item = Arc<LumaImage>> + Unpin,
+    track: Arc<TrackLocalStaticSample>,
+) {
+    let mut encoder = match Encoder::new() {
+        Ok(x) => Some(x),
+        Err(e) => {
+            warn!("Couldn't create H.264 encoder: {e}");
+            return;
+        }
+    };
+    let mut last_frame = tokio::time::Instant::now();
+    while let Some(image) = images.next().await {
+        let Some(mut moved_encoder) = encoder.take() else {
+            return;
+        };
+        let encoded = tokio::task::spawn_blocking(move || {
+            let result = encode_luma(&mut moved_encoder, &image);
+            (moved_encoder, result)
+        })
+        .await;
+        let data = match encoded {
+            Ok((returned, Ok(data))) => {
+                encoder = Some(returned);
+                data
+            }
+            Ok((_, Err(e))) => {
+                warn!("Couldn't encode frame for WebRTC: {e}");
+                return;
+            }
+            Err(_) => return,
+        };
+        let now = tokio::time::Instant::now();
+        let duration = now.duration_since(last_frame).max(Duration::from_millis(1));
+        last_frame = now;
+        if let Err(e) = track
+            .write_sample(&Sample {
+                data,
+                duration,
+                ..Default::default()
+            })
+            .await
+        {
+            debug!("Stop sending WebRTC frames: {e}");
+            return;
+        }
+    }
+}
+
+/// Gray images are sent with neutral chroma planes
+fn encode_luma(encoder: &mut Encoder, image: &LumaImage) -> anyhow::Result<Bytes> {
+    let (width, height) = image.dimensions();
+    let (width, height) = (width.get() as usize, height.get() as usize);
+    // YUV420 requires even dimensions
+    let (even_width, even_height) = (width & !1, height & !1);
+    if even_width == 0 || even_height == 0 {
+        anyhow::bail!("Image is too small for H.264: {width}x{height}");
+    }
+    let chroma = vec![128u8; (even_width / 2) * (even_height / 2)];
+    let luma = &image.buffer()[..width * even_height];
+    let yuv = YUVSlices::new(
+        (luma, &chroma, &chroma),
+        (even_width, even_height),
+        (width, even_width / 2, even_width / 2),
+    );
+    let bitstream = encoder.encode(&yuv)?;
+    Ok(Bytes::from(bitstream.to_vec()))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::num::NonZeroU32;
+
+    use futures::stream::BoxStream;
+    use pilatus::{device::ActorSystem, ResourceAction};
+    use pilatus_engineering::image::BroadcastImage;
+    use webrtc::{
+        peer_connection::sdp::sdp_type::RTCSdpType,
+        rtp_transceiver::{
+            rtp_codec::RTPCodecType, rtp_transceiver_direction::RTCRtpTransceiverDirection,
+            RTCRtpTransceiverInit,
+        },
+    };
+
+    use super::*;
+
+    fn gray(width: u32, height: u32) -> LumaImage {
+        LumaImage::new_vec(
+            vec![128; (width * height) as usize],
+            NonZeroU32::new(width).unwrap(),
+            NonZeroU32::new(height).unwrap(),
+        )
+    }
+
+    #[test]
+    fn encode_images_with_odd_dimensions() {
+        let mut encoder = Encoder::new().unwrap();
+        let encoded = encode_luma(&mut encoder, &gray(65, 49)).unwrap();
+        assert!(!encoded.is_empty());
+    }
+
+    #[test]
+    fn reject_images_without_chroma_pixel() {
+        let mut encoder = Encoder::new().unwrap();
+        assert!(encode_luma(&mut encoder, &gray(1, 16)).is_err());
+        assert!(encode_luma(&mut encoder, &gray(16, 1)).is_err());
+    }
+
+    #[tokio::test]
+    async fn stop_sending_when_images_end() {
+        let track = Arc::new(TrackLocalStaticSample::new(
+            RTCRtpCodecCapability {
+                mime_type: MIME_TYPE_H264.to_owned(),
+                ..Default::default()
+            },
+            "video".to_owned(),
+            "test".to_owned(),
+        ));
+        let images = futures::stream::iter([Arc::new(gray(64, 48)), Arc::new(gray(64, 48))]);
+        tokio::time::timeout(Duration::from_secs(10), send_frames(images, track))
+            .await
+            .expect("Should stop after the last image");
+    }
+
+    #[tokio::test]
+    async fn refuse_offers_while_overloaded() {
+        let health = HealthState::default();
+        health.update(|r| {
+            r.active_actions.insert(ResourceAction::RefuseSubscriptions);
+        });
+        let Err((code, _)) = offer_handler(
+            Query(OfferQuery {
+                device_id: DeviceId::new_v4(),
+            }),
+            WebActorSystem(ActorSystem::new()),
+            InjectRegistered(health),
+            Json(RTCSessionDescription::default()),
+        )
+        .await
+        else {
+            panic!("Expected refused offer");
+        };
+        assert_eq!(StatusCode::SERVICE_UNAVAILABLE, code);
+    }
+
+    #[tokio::test]
+    async fn answer_offers_with_h264_track() -> anyhow::Result<()> {
+        async fn subscribe(
+            _: &mut (),
+            _: SubscribeImageMessage,
+        ) -> pilatus::device::ActorResult<SubscribeImageMessage> {
+            let pending: BoxStream<'static, BroadcastImage> = futures::stream::pending().boxed();
+            Ok(pending)
+        }
+        let actor_system = ActorSystem::new();
+        let device_id = DeviceId::new_v4();
+        let device = actor_system
+            .register(device_id)
+            .add_handler(subscribe)
+            .execute(());
+
+        let client = build_api()?
+            .new_peer_connection(RTCConfiguration::default())
+            .await?;
+        client
+            .add_transceiver_from_kind(
+                RTPCodecType::Video,
+                Some(RTCRtpTransceiverInit {
+                    direction: RTCRtpTransceiverDirection::Recvonly,
+                    send_encodings: vec![],
+                }),
+            )
+            .await?;
+        let offer = client.create_offer(None).await?;
+        let mut gathering_complete = client.gathering_complete_promise().await;
+        client.set_local_description(offer).await?;
+        let _ = gathering_complete.recv().await;
+        let offer = client.local_description().await.unwrap();
+
+        let negotiate = async {
+            let unknown = offer_handler(
+                Query(OfferQuery {
+                    device_id: DeviceId::new_v4(),
+                }),
+                WebActorSystem(actor_system.clone()),
+                InjectRegistered(HealthState::default()),
+                Json(offer.clone()),
+            )
+            .await;
+            assert!(matches!(unknown, Err((StatusCode::NOT_FOUND, _))));
+
+            offer_handler(
+                Query(OfferQuery { device_id }),
+                WebActorSystem(actor_system.clone()),
+                InjectRegistered(HealthState::default()),
+                Json(offer),
+            )
+            .await
+        };
+        let Json(answer) = tokio::select! {
+            _ = device => panic!("Device should not stop"),
+            x = negotiate => x.map_err(|(code, e)| anyhow::anyhow!("{code}: {e}"))?,
+        };
+        assert_eq!(RTCSdpType::Answer, answer.sdp_type);
+        assert!(answer.sdp.contains("H264"), "{}", answer.sdp);
+        client.close().await?;
+        Ok(())
+    }
+}
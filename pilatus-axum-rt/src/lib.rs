@@ -1,27 +1,42 @@
 mod abort;
+mod correlation;
+mod cycle_time;
 mod device;
+mod events;
 mod frontend_config;
+mod health;
 mod hosted_service;
 #[cfg(feature = "engineering")]
 mod image;
 mod inject;
+mod localization;
 mod logo;
 mod logs;
+mod maintenance;
 mod recipe;
+mod system_info;
 mod time;
+mod user;
 mod ws;
 mod zip_writer_wrapper;
 
 pub extern "C" fn register(collection: &mut minfac::ServiceCollection) {
     abort::register_services(collection);
+    cycle_time::register_services(collection);
     device::register_services(collection);
+    events::register_services(collection);
+    health::register_services(collection);
     hosted_service::register_services(collection);
     #[cfg(feature = "engineering")]
     image::register_services(collection);
+    localization::register_services(collection);
     recipe::register_services(collection);
+    system_info::register_services(collection);
     time::register_services(collection);
+    user::register_services(collection);
     ws::register_services(collection);
     logo::register_services(collection);
     logs::register_services(collection);
+    maintenance::register_services(collection);
     frontend_config::register_services(collection);
 }
@@ -0,0 +1,88 @@
+use std::sync::Arc;
+
+use axum::{
+    extract::{Request, State},
+    http::{
+        header::{ACCEPT_LANGUAGE, CONTENT_LANGUAGE, CONTENT_LENGTH},
+        HeaderValue,
+    },
+    middleware::Next,
+};
+use minfac::{Registered, ServiceCollection};
+use pilatus::{GenericConfig, LocalizedError, MessageCatalog};
+use pilatus_axum::{
+    extract::{InjectRegistered, Json, Path},
+    http::StatusCode,
+    ApiError, Body, IntoResponse, Response, ServiceCollectionExtensions,
+};
+
+/// The catalog is configured with the key "localization", e.g. `{ "de": { "unknown_recipe_id": "Unbekanntes Rezept {recipe_id}" } }`
+pub(super) fn register_services(c: &mut ServiceCollection) {
+    c.with::<Registered<GenericConfig>>()
+        .register_shared(|config| {
+            Arc::new(
+                config
+                    .get::<MessageCatalog>("localization")
+                    .unwrap_or_default(),
+            )
+        });
+    c.register_instance(pilatus::ServiceInfo::shared::<Arc<MessageCatalog>>());
+
+    #[rustfmt::skip]
+    c.register_web("localization", |r| r
+        .http("/:locale", |m| m.get(get_templates).summary("Message templates of a locale by error code"))
+    );
+}
+
+async fn get_templates(
+    InjectRegistered(catalog): InjectRegistered<Arc<MessageCatalog>>,
+    Path(locale): Path<String>,
+) -> Result<impl IntoResponse, (StatusCode, String)> {
+    catalog
+        .locale(&locale)
+        .cloned()
+        .map(Json)
+        .ok_or_else(|| (StatusCode::NOT_FOUND, format!("Unknown locale '{locale}'")))
+}
+
+/// Translates responses of `LocalizedErrorResponse` and `ApiError` according to the `Accept-Language` of the request
+pub(super) async fn localize_errors(
+    State(catalog): State<Arc<MessageCatalog>>,
+    request: Request,
+    next: Next,
+) -> Response {
+    let accept_language = request
+        .headers()
+        .get(ACCEPT_LANGUAGE)
+        .and_then(|x| x.to_str().ok())
+        .map(str::to_string);
+    let response = next.run(request).await;
+    let Some(accept_language) = accept_language else {
+        return response;
+    };
+    if let Some(error) = response.extensions().get::<ApiError>() {
+        let Some((locale, message)) = catalog.translate(&accept_language, &error.to_localized())
+        else {
+            return response;
+        };
+        let error = error.clone().with_message(message);
+        let mut response = super::correlation::replace_api_error(response, error);
+        if let Ok(locale) = HeaderValue::from_str(&locale) {
+            response.headers_mut().insert(CONTENT_LANGUAGE, locale);
+        }
+        return response;
+    }
+    let Some((locale, message)) = response
+        .extensions()
+        .get::<LocalizedError>()
+        .and_then(|error| catalog.translate(&accept_language, error))
+    else {
+        return response;
+    };
+    let (mut parts, _) = response.into_parts();
+    parts.headers.remove(CONTENT_LENGTH);
+    if let Ok(locale) = HeaderValue::from_str(&locale) {
+        parts.headers.insert(CONTENT_LANGUAGE, locale);
+    }
+    Response::from_parts(parts, Body::from(message))
+}
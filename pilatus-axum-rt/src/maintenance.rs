@@ -0,0 +1,65 @@
+use minfac::ServiceCollection;
+use pilatus::{EventBus, MaintenanceMode, MaintenanceStatus, Role, SystemEventKind};
+use pilatus_axum::{
+    extract::{CurrentUser, InjectRegistered, Json},
+    http::StatusCode,
+    ServiceCollectionExtensions,
+};
+use serde::Deserialize;
+
+pub(super) fn register_services(c: &mut ServiceCollection) {
+    #[rustfmt::skip]
+    c.register_web("maintenance", |r| r
+        .http("", |m| m
+            .get(get_maintenance).summary("Current maintenance mode, null if the system operates normally")
+            .put(set_maintenance).summary("Pause producers and reject recipe changes, e.g. during mechanical maintenance")
+        )
+    );
+}
+
+async fn get_maintenance(
+    InjectRegistered(maintenance): InjectRegistered<MaintenanceMode>,
+) -> Json<Option<MaintenanceStatus>> {
+    Json(maintenance.status())
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct SetMaintenanceRequest {
+    enabled: bool,
+    #[serde(default)]
+    reason: Option<String>,
+}
+
+/// Only authenticated operators and admins may change the maintenance mode
+async fn set_maintenance(
+    InjectRegistered(maintenance): InjectRegistered<MaintenanceMode>,
+    InjectRegistered(bus): InjectRegistered<EventBus>,
+    current: CurrentUser,
+    Json(SetMaintenanceRequest { enabled, reason }): Json<SetMaintenanceRequest>,
+) -> Result<Json<Option<MaintenanceStatus>>, (StatusCode, String)> {
+    current
+        .require(Role::Operator)
+        .map_err(|(status, e)| (status, e.to_string()))?;
+    let user = Some(current.0.name);
+    let changed = if enabled {
+        let was_enabled = maintenance.status().is_some();
+        maintenance.enable(reason.clone(), user.clone());
+        !was_enabled
+    } else {
+        maintenance.disable()
+    };
+    if changed {
+        bus.publish(SystemEventKind::UserAction {
+            action: if enabled {
+                "maintenance_enabled"
+            } else {
+                "maintenance_disabled"
+            }
+            .into(),
+            details: reason,
+            user,
+        });
+    }
+    Ok(Json(maintenance.status()))
+}
@@ -0,0 +1,133 @@
+use futures::io::{AsyncRead, AsyncWrite};
+use pilatus::{
+    decrypt_if_encrypted, EncryptingWriter, EntryReader, EntryWriter, TarEntryReader,
+    TarEntryWriter,
+};
+use pilatus_axum::http::{HeaderMap, StatusCode};
+
+use super::import::ZipReaderWrapper;
+use crate::zip_writer_wrapper::ZipWriterWrapper;
+
+/// Imports and exports are en-/decrypted with the password in this header, if present.
+/// It is never accepted as query parameter, because URLs end up in logs and browser histories
+/// Browsers can't set it on a WebSocket, so imports also accept it in the first socket message or when creating an upload
+pub(super) const PASSWORD_HEADER: &str = "x-recipe-password";
+
+pub(super) fn password_from_headers(
+    headers: &HeaderMap,
+) -> Result<Option<String>, (StatusCode, String)> {
+    Ok(headers
+        .get(PASSWORD_HEADER)
+        .map(|x| {
+            x.to_str().map(ToString::to_string).map_err(|_| {
+                (
+                    StatusCode::BAD_REQUEST,
+                    "Invalid password header".to_string(),
+                )
+            })
+        })
+        .transpose()?
+        .filter(|x| !x.is_empty()))
+}
+
+/// Archive formats for recipe import/export, selected by their content-type
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub(super) enum ArchiveFormat {
+    #[default]
+    Zip,
+    Tar,
+    TarZstd,
+}
+
+impl ArchiveFormat {
+    /// Unknown or missing content-types fall back to zip to stay compatible with older clients
+    pub fn from_content_type(content_type: Option<&str>) -> Self {
+        let Some(content_type) = content_type else {
+            return Self::Zip;
+        };
+        content_type
+            .split(',')
+            .map(|x| x.split(';').next().unwrap_or_default().trim())
+            .find_map(|x| match x {
+                "application/zip" => Some(Self::Zip),
+                "application/x-tar" => Some(Self::Tar),
+                "application/zstd" | "application/x-zstd" | "application/x-tar+zstd" => {
+                    Some(Self::TarZstd)
+                }
+                _ => None,
+            })
+            .unwrap_or_default()
+    }
+
+    pub fn content_type(self) -> &'static str {
+        match self {
+            ArchiveFormat::Zip => "application/zip",
+            ArchiveFormat::Tar => "application/x-tar",
+            ArchiveFormat::TarZstd => "application/zstd",
+        }
+    }
+
+    pub fn file_extension(self) -> &'static str {
+        match self {
+            ArchiveFormat::Zip => "pilatusrecipe",
+            ArchiveFormat::Tar => "pilatusrecipe.tar",
+            ArchiveFormat::TarZstd => "pilatusrecipe.tar.zst",
+        }
+    }
+
+    /// The archive is encrypted as a whole if a password is provided
+    pub async fn writer<W: AsyncWrite + Unpin + Send + 'static>(
+        self,
+        raw: W,
+        password: Option<&str>,
+    ) -> std::io::Result<Box<dyn EntryWriter>> {
+        Ok(match password {
+            Some(password) => self.plain_writer(EncryptingWriter::new(raw, password).await?),
+            None => self.plain_writer(raw),
+        })
+    }
+
+    fn plain_writer<W: AsyncWrite + Unpin + Send + 'static>(self, raw: W) -> Box<dyn EntryWriter> {
+        match self {
+            ArchiveFormat::Zip => ZipWriterWrapper::new_boxed(raw),
+            ArchiveFormat::Tar => TarEntryWriter::new_boxed(raw),
+            ArchiveFormat::TarZstd => TarEntryWriter::new_zstd_boxed(raw),
+        }
+    }
+
+    /// Encrypted archives are detected automatically. Reading them fails without the correct password
+    pub fn reader<'a, R: AsyncRead + Unpin + Send + 'a>(
+        self,
+        raw: R,
+        password: Option<String>,
+    ) -> Box<dyn EntryReader + 'a> {
+        let raw = decrypt_if_encrypted(raw, password);
+        match self {
+            ArchiveFormat::Zip => Box::new(ZipReaderWrapper::new(raw)),
+            ArchiveFormat::Tar => Box::new(TarEntryReader::new(raw)),
+            ArchiveFormat::TarZstd => Box::new(TarEntryReader::new_zstd(raw)),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn select_by_content_type() {
+        assert_eq!(ArchiveFormat::Zip, ArchiveFormat::from_content_type(None));
+        assert_eq!(
+            ArchiveFormat::TarZstd,
+            ArchiveFormat::from_content_type(Some("text/html, application/zstd;q=0.9"))
+        );
+        assert_eq!(
+            ArchiveFormat::Tar,
+            ArchiveFormat::from_content_type(Some("application/x-tar"))
+        );
+        assert_eq!(
+            ArchiveFormat::Zip,
+            ArchiveFormat::from_content_type(Some("*/*"))
+        );
+    }
+}
@@ -0,0 +1,126 @@
+//! Long-polling fallback for `/recipe/stream`, for networks which block websockets
+use std::{collections::VecDeque, sync::Arc, time::Duration};
+
+use futures::StreamExt;
+use minfac::{Registered, ServiceCollection};
+use pilatus::{prelude::*, RecipeService, SystemShutdown};
+use pilatus_axum::{
+    extract::{InjectRegistered, Json, Query},
+    ServiceCollectionExtensions,
+};
+use tokio::sync::watch;
+use uuid::Uuid;
+
+/// Clients which are further behind just receive the latest key
+const MAX_HISTORY: usize = 100;
+const DEFAULT_TIMEOUT: Duration = Duration::from_secs(30);
+const MAX_TIMEOUT: Duration = Duration::from_secs(120);
+
+pub(super) fn register_services(c: &mut ServiceCollection) {
+    c.register_shared(|| Arc::new(RecipeChangeLog(watch::channel(VecDeque::new()).0)));
+    c.register_instance(pilatus::ServiceInfo::shared::<Arc<RecipeChangeLog>>());
+    c.with::<(
+        Registered<Arc<RecipeChangeLog>>,
+        Registered<RecipeService>,
+        Registered<SystemShutdown>,
+    )>()
+    .register_hosted_service("Recipe Change Log", record_changes);
+
+    #[rustfmt::skip]
+    c.register_web("recipe", |r| r
+        .http("/changes", |m| m.get(poll_changes))
+    );
+}
+
+/// Most recent transaction keys, oldest first
+struct RecipeChangeLog(watch::Sender<VecDeque<Uuid>>);
+
+async fn record_changes(
+    (log, service, shutdown): (Arc<RecipeChangeLog>, RecipeService, SystemShutdown),
+) -> anyhow::Result<()> {
+    let record = service.get_update_receiver().for_each(|key| {
+        log.0.send_modify(|history| {
+            if history.len() == MAX_HISTORY {
+                history.pop_front();
+            }
+            history.push_back(key);
+        });
+        futures::future::ready(())
+    });
+    futures::future::select(std::pin::pin!(record), shutdown).await;
+    Ok(())
+}
+
+#[derive(serde::Deserialize)]
+#[serde(deny_unknown_fields)]
+struct ChangesQuery {
+    /// Last transaction key the client knows about. Without it, the request waits for the next change
+    since: Option<Uuid>,
+    timeout_ms: Option<u64>,
+}
+
+/// Responds with all transaction keys after `since` as soon as there are any.
+/// An empty list means that nothing changed until the timeout elapsed.
+async fn poll_changes(
+    InjectRegistered(log): InjectRegistered<Arc<RecipeChangeLog>>,
+    Query(ChangesQuery { since, timeout_ms }): Query<ChangesQuery>,
+) -> Json<Vec<Uuid>> {
+    let timeout = timeout_ms
+        .map(Duration::from_millis)
+        .unwrap_or(DEFAULT_TIMEOUT)
+        .min(MAX_TIMEOUT);
+    let mut receiver = log.0.subscribe();
+    let mut since = since.or_else(|| receiver.borrow_and_update().back().copied());
+    let wait = async {
+        loop {
+            if let Some(keys) = keys_since(&receiver.borrow_and_update(), since) {
+                return keys;
+            }
+            if receiver.changed().await.is_err() {
+                return Vec::new();
+            }
+            // A client without a known key waits for whatever comes next
+            since = since.or(Some(Uuid::nil()));
+        }
+    };
+    Json(
+        tokio::time::timeout(timeout, wait)
+            .await
+            .unwrap_or_default(),
+    )
+}
+
+/// None if the client is up to date
+fn keys_since(history: &VecDeque<Uuid>, since: Option<Uuid>) -> Option<Vec<Uuid>> {
+    let since = since?;
+    let latest = *history.back()?;
+    if latest == since {
+        return None;
+    }
+    Some(match history.iter().position(|x| *x == since) {
+        Some(pos) => history.iter().skip(pos + 1).copied().collect(),
+        None => vec![latest],
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn return_keys_after_known_key() {
+        let keys: Vec<_> = (0..3).map(|_| Uuid::new_v4()).collect();
+        let history: VecDeque<_> = keys.iter().copied().collect();
+        assert_eq!(None, keys_since(&history, Some(keys[2])));
+        assert_eq!(
+            Some(vec![keys[1], keys[2]]),
+            keys_since(&history, Some(keys[0]))
+        );
+        assert_eq!(
+            Some(vec![keys[2]]),
+            keys_since(&history, Some(Uuid::new_v4()))
+        );
+        assert_eq!(None, keys_since(&VecDeque::new(), Some(keys[0])));
+        assert_eq!(None, keys_since(&history, None));
+    }
+}
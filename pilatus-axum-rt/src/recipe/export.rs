@@ -1,36 +1,205 @@
-use futures::FutureExt;
+use std::{
+    collections::HashMap,
+    sync::{Arc, Mutex},
+    time::Duration,
+};
+
+use futures::{SinkExt, StreamExt};
 use minfac::ServiceCollection;
-use pilatus::{RecipeExporter, RecipeId};
+use pilatus::{GenericConfig, RecipeExporter, RecipeId};
 use pilatus_axum::{
-    extract::{InjectRegistered, Path},
-    http::StatusCode,
-    AppendHeaders, IntoResponse, IoStreamBody, ServiceCollectionExtensions,
+    extract::{
+        ws::{Message, WebSocket, WebSocketUpgrade},
+        InjectRegistered, Path, Query,
+    },
+    http::{header::ACCEPT, HeaderMap, StatusCode},
+    AppendHeaders, IntoResponse, IoStreamBody, ServiceCollectionExtensions, SpoolConfig,
+};
+use serde::Deserialize;
+use tracing::debug;
+use uuid::Uuid;
+
+use super::{
+    archive_format::{password_from_headers, ArchiveFormat},
+    progress::{CountingWriter, ProgressEntryWriter, ProgressTracker, PROGRESS_INTERVAL},
 };
 
-use crate::zip_writer_wrapper::ZipWriterWrapper;
+/// Progress sockets are closed, if no export with their id starts in time
+const PROGRESS_START_TIMEOUT: Duration = Duration::from_secs(30);
 
 pub(super) fn register_services(c: &mut ServiceCollection) {
+    c.register_shared(|| Arc::new(ExportProgress::default()))
+        .alias(|x| ExportProgress::clone(&x));
+    c.register_instance(pilatus::ServiceInfo::shared::<ExportProgress>());
+
     #[rustfmt::skip]
     c.register_web("recipe", |r| r
         .http("/:id/export",|m| m.get(export_recipe))
+        .http("/export/progress/:progress_id",|m| m.get(stream_export_progress))
     );
 }
+
+/// Progress of running exports. Clients choose the id, so they can subscribe before the download starts
+#[derive(Clone, Default)]
+pub(super) struct ExportProgress(Arc<Mutex<HashMap<Uuid, ProgressTracker>>>);
+
+impl ExportProgress {
+    fn tracker(&self, id: Uuid) -> ProgressTracker {
+        let mut lock = self.0.lock().expect("Never poisoned");
+        lock.entry(id).or_default().clone()
+    }
+
+    /// The tracker is finished and removed when the guard is dropped, even if the download was cancelled
+    fn start(&self, id: Uuid) -> StartedExport {
+        StartedExport {
+            tracker: self.tracker(id),
+            progress: self.clone(),
+            id,
+        }
+    }
+
+    fn remove(&self, id: Uuid) {
+        self.0.lock().expect("Never poisoned").remove(&id);
+    }
+
+    /// Subscribers of exports which never started must not leak their tracker
+    fn remove_unstarted(&self, id: Uuid) {
+        let mut lock = self.0.lock().expect("Never poisoned");
+        if lock.get(&id).is_some_and(|x| !x.snapshot().is_started()) {
+            lock.remove(&id);
+        }
+    }
+}
+
+struct StartedExport {
+    tracker: ProgressTracker,
+    progress: ExportProgress,
+    id: Uuid,
+}
+
+impl Drop for StartedExport {
+    fn drop(&mut self) {
+        self.tracker.finish();
+        self.progress.remove(self.id);
+    }
+}
+
+#[derive(Deserialize)]
+struct ExportQuery {
+    /// Progress is reported on `/recipe/export/progress/{progress_id}`, if present
+    progress_id: Option<Uuid>,
+}
+
 async fn export_recipe(
     Path(recipe_id): Path<RecipeId>,
     InjectRegistered(service): InjectRegistered<RecipeExporter>,
+    InjectRegistered(config): InjectRegistered<GenericConfig>,
+    InjectRegistered(progress): InjectRegistered<ExportProgress>,
+    Query(ExportQuery { progress_id }): Query<ExportQuery>,
+    headers: HeaderMap,
 ) -> Result<impl IntoResponse, (StatusCode, String)> {
+    let format =
+        ArchiveFormat::from_content_type(headers.get(ACCEPT).and_then(|x| x.to_str().ok()));
+    let password = password_from_headers(&headers)?;
     Ok((
-        AppendHeaders([(
-            "Content-Disposition",
-            format!("attachment; filename=\"{recipe_id}.pilatusrecipe\""),
-        )]),
-        IoStreamBody::with_writer(move |w| {
-            async move {
-                service
-                    .export(recipe_id, ZipWriterWrapper::new_boxed(w))
-                    .await
-            }
-            .fuse()
-        }),
+        AppendHeaders([
+            ("Content-Type", format.content_type().to_string()),
+            (
+                "Content-Disposition",
+                format!(
+                    "attachment; filename=\"{recipe_id}.{}\"",
+                    format.file_extension()
+                ),
+            ),
+        ]),
+        IoStreamBody::with_spooled_writer(
+            SpoolConfig::default().with_dir(config.temp_dir()),
+            move |w| async move {
+                let Some(progress_id) = progress_id else {
+                    let writer = format.writer(w, password.as_deref()).await?;
+                    return service.export(recipe_id, writer).await;
+                };
+                // The writer task is aborted, if the client cancels the download
+                let started = progress.start(progress_id);
+                let tracker = &started.tracker;
+                let writer = format
+                    .writer(CountingWriter::new(w, tracker.clone()), password.as_deref())
+                    .await?;
+                let writer = ProgressEntryWriter::new_boxed(writer, tracker.clone());
+                service.export(recipe_id, writer).await
+            },
+        ),
     ))
 }
+
+/// Sends the progress periodically until the export is finished
+async fn stream_export_progress(
+    upgrade: WebSocketUpgrade,
+    Path(progress_id): Path<Uuid>,
+    InjectRegistered(progress): InjectRegistered<ExportProgress>,
+) -> impl IntoResponse {
+    let tracker = progress.tracker(progress_id);
+    upgrade.into_inner().on_upgrade(move |socket| async move {
+        debug!("Subscribe progress of export {progress_id}");
+        send_export_progress(socket, tracker).await;
+        progress.remove_unstarted(progress_id);
+        debug!("Progress subscription of export {progress_id} ended.");
+    })
+}
+
+async fn send_export_progress(socket: WebSocket, tracker: ProgressTracker) {
+    let (mut socket_tx, mut socket_rx) = socket.split();
+    let mut interval = tokio::time::interval(PROGRESS_INTERVAL);
+    let start = tokio::time::Instant::now();
+    tokio::select!(
+        _ = async {
+            loop {
+                interval.tick().await;
+                let snapshot = tracker.snapshot();
+                if !snapshot.is_started() && start.elapsed() > PROGRESS_START_TIMEOUT {
+                    break;
+                }
+                let Ok(data) = serde_json::to_string(&snapshot) else {
+                    break;
+                };
+                if socket_tx.send(Message::Text(data)).await.is_err() || snapshot.is_finished() {
+                    break;
+                }
+            }
+        } => {},
+        _ = async {
+            while let Some(r) = socket_rx.next().await {
+                if r.is_err() {
+                    break;
+                }
+            }
+        } => {}
+    );
+    let _ignore_if_not_closeable = socket_rx
+        .reunite(socket_tx)
+        .expect("Guaranted to be same source")
+        .close()
+        .await;
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn finish_and_remove_cancelled_exports() {
+        let progress = ExportProgress::default();
+        let id = Uuid::new_v4();
+        let subscribed = progress.tracker(id);
+
+        let started = progress.start(id);
+        let export = async move {
+            let _started = started;
+            futures::future::pending::<()>().await
+        };
+        assert!(futures::FutureExt::now_or_never(export).is_none());
+
+        assert!(subscribed.snapshot().is_finished());
+        assert!(progress.0.lock().unwrap().is_empty());
+    }
+}
@@ -1,15 +1,21 @@
 use bytes::Bytes;
+use futures::{FutureExt, TryStreamExt};
 use minfac::ServiceCollection;
 use pilatus::{
     device::{ActorSystem, DeviceId},
-    AddFileMessage, DeleteFileMessage, GetFileMessage, ListFilesMessage, RelativeDirectoryPathBuf,
-    RelativeFilePath,
+    AddFileMessage, CopyFileMessage, DeleteFileMessage, EntryWriter, FileListQuery, GetFileMessage,
+    ListFileVersionsMessage, ListFilesMessage, ListFilesPagedMessage, ListFilesRecursiveMessage,
+    MaintenanceMode, MoveFileMessage, RelativeDirectoryPathBuf, RelativeFilePath,
+    RestoreFileVersionMessage, StreamFileMessage,
 };
 use pilatus_axum::{
-    extract::{InjectRegistered, Json, Path},
+    extract::{InjectRegistered, Json, Path, Query, WebActorSystem},
     http::StatusCode,
-    IntoResponse, ServiceCollectionExtensions,
+    AppendHeaders, IntoResponse, IoStreamBody, ServiceCollectionExtensions,
 };
+use serde::Deserialize;
+
+use crate::zip_writer_wrapper::ZipWriterWrapper;
 
 pub(super) fn register_services(c: &mut ServiceCollection) {
     #[rustfmt::skip]
@@ -18,6 +24,22 @@ pub(super) fn register_services(c: &mut ServiceCollection) {
             .get(list_files))
         .http("/list/:device_id", |m| m
             .get(list_files_root))
+        .http("/page/:device_id/*path", |m| m
+            .get(list_files_paged))
+        .http("/page/:device_id", |m| m
+            .get(list_files_paged_root))
+        .http("/versions/:device_id/*filename", |m| m
+            .get(list_file_versions))
+        .http("/restore/:device_id/:version/*filename", |m| m
+            .put(restore_file_version))
+        .http("/copy/:device_id", |m| m
+            .put(copy_file))
+        .http("/move/:device_id", |m| m
+            .put(move_file))
+        .http("/zip/:device_id/*path", |m| m
+            .get(download_zip))
+        .http("/zip/:device_id", |m| m
+            .get(download_zip_root))
         .http("/:device_id/*filename", |m| m
             .get(get_file)
             .put(add_file)
@@ -27,7 +49,7 @@ pub(super) fn register_services(c: &mut ServiceCollection) {
 
 async fn get_file(
     Path((device_id, path)): Path<(DeviceId, RelativeFilePath)>,
-    InjectRegistered(actor_system): InjectRegistered<ActorSystem>,
+    WebActorSystem(actor_system): WebActorSystem,
 ) -> Result<impl IntoResponse, (StatusCode, String)> {
     actor_system
         .ask(device_id, GetFileMessage { path })
@@ -35,10 +57,19 @@ async fn get_file(
         .map_err(|e| (StatusCode::BAD_REQUEST, format!("Bummer, it failed: {e:?}")))
 }
 
+/// Files are part of the recipe, so they mustn't change during maintenance either
+fn ensure_no_maintenance(maintenance: &MaintenanceMode) -> Result<(), (StatusCode, String)> {
+    maintenance
+        .ensure_inactive()
+        .map_err(|e| (StatusCode::SERVICE_UNAVAILABLE, e.to_string()))
+}
+
 async fn delete_file(
     Path((device_id, path)): Path<(DeviceId, RelativeFilePath)>,
-    InjectRegistered(actor_system): InjectRegistered<ActorSystem>,
+    WebActorSystem(actor_system): WebActorSystem,
+    InjectRegistered(maintenance): InjectRegistered<MaintenanceMode>,
 ) -> Result<impl IntoResponse, (StatusCode, String)> {
+    ensure_no_maintenance(&maintenance)?;
     actor_system
         .ask(device_id, DeleteFileMessage { path })
         .await
@@ -47,9 +78,11 @@ async fn delete_file(
 
 async fn add_file(
     Path((device_id, path)): Path<(DeviceId, RelativeFilePath)>,
-    InjectRegistered(actor_system): InjectRegistered<ActorSystem>,
+    WebActorSystem(actor_system): WebActorSystem,
+    InjectRegistered(maintenance): InjectRegistered<MaintenanceMode>,
     data: Bytes,
 ) -> Result<impl IntoResponse, (StatusCode, String)> {
+    ensure_no_maintenance(&maintenance)?;
     actor_system
         .ask(device_id, AddFileMessage { path, data })
         .await
@@ -58,14 +91,14 @@ async fn add_file(
 
 async fn list_files_root(
     Path(device_id): Path<DeviceId>,
-    inj: InjectRegistered<ActorSystem>,
+    inj: WebActorSystem,
 ) -> Result<impl IntoResponse, (StatusCode, String)> {
     list_files(Path((device_id, RelativeDirectoryPathBuf::root())), inj).await
 }
 
 async fn list_files(
     Path((device_id, path)): Path<(DeviceId, RelativeDirectoryPathBuf)>,
-    InjectRegistered(actor_system): InjectRegistered<ActorSystem>,
+    WebActorSystem(actor_system): WebActorSystem,
 ) -> Result<impl IntoResponse, (StatusCode, String)> {
     let files = actor_system
         .ask(device_id, ListFilesMessage { path })
@@ -74,3 +107,150 @@ async fn list_files(
 
     Ok(Json(files))
 }
+
+async fn list_files_paged_root(
+    Path(device_id): Path<DeviceId>,
+    query: Query<FileListQuery>,
+    inj: WebActorSystem,
+) -> Result<impl IntoResponse, (StatusCode, String)> {
+    list_files_paged(
+        Path((device_id, RelativeDirectoryPathBuf::root())),
+        query,
+        inj,
+    )
+    .await
+}
+
+async fn list_files_paged(
+    Path((device_id, path)): Path<(DeviceId, RelativeDirectoryPathBuf)>,
+    Query(query): Query<FileListQuery>,
+    WebActorSystem(actor_system): WebActorSystem,
+) -> Result<impl IntoResponse, (StatusCode, String)> {
+    let page = actor_system
+        .ask(device_id, ListFilesPagedMessage { path, query })
+        .await
+        .map_err(|e| (StatusCode::BAD_REQUEST, format!("Bummer, it failed: {e:?}")))?;
+
+    Ok(Json(page))
+}
+
+async fn list_file_versions(
+    Path((device_id, path)): Path<(DeviceId, RelativeFilePath)>,
+    WebActorSystem(actor_system): WebActorSystem,
+) -> Result<impl IntoResponse, (StatusCode, String)> {
+    let versions = actor_system
+        .ask(device_id, ListFileVersionsMessage { path })
+        .await
+        .map_err(|e| (StatusCode::BAD_REQUEST, format!("Bummer, it failed: {e:?}")))?;
+
+    Ok(Json(versions))
+}
+
+async fn restore_file_version(
+    Path((device_id, version, path)): Path<(DeviceId, u64, RelativeFilePath)>,
+    WebActorSystem(actor_system): WebActorSystem,
+    InjectRegistered(maintenance): InjectRegistered<MaintenanceMode>,
+) -> Result<impl IntoResponse, (StatusCode, String)> {
+    ensure_no_maintenance(&maintenance)?;
+    actor_system
+        .ask(device_id, RestoreFileVersionMessage { path, version })
+        .await
+        .map_err(|e| (StatusCode::BAD_REQUEST, format!("Bummer, it failed: {e:?}")))
+}
+
+#[derive(Debug, Deserialize)]
+struct FileTransfer {
+    from: RelativeFilePath,
+    to: RelativeFilePath,
+}
+
+async fn copy_file(
+    Path(device_id): Path<DeviceId>,
+    WebActorSystem(actor_system): WebActorSystem,
+    InjectRegistered(maintenance): InjectRegistered<MaintenanceMode>,
+    Json(FileTransfer { from, to }): Json<FileTransfer>,
+) -> Result<impl IntoResponse, (StatusCode, String)> {
+    ensure_no_maintenance(&maintenance)?;
+    actor_system
+        .ask(device_id, CopyFileMessage { from, to })
+        .await
+        .map_err(|e| (StatusCode::BAD_REQUEST, format!("Bummer, it failed: {e:?}")))
+}
+
+async fn move_file(
+    Path(device_id): Path<DeviceId>,
+    WebActorSystem(actor_system): WebActorSystem,
+    InjectRegistered(maintenance): InjectRegistered<MaintenanceMode>,
+    Json(FileTransfer { from, to }): Json<FileTransfer>,
+) -> Result<impl IntoResponse, (StatusCode, String)> {
+    ensure_no_maintenance(&maintenance)?;
+    actor_system
+        .ask(device_id, MoveFileMessage { from, to })
+        .await
+        .map_err(|e| (StatusCode::BAD_REQUEST, format!("Bummer, it failed: {e:?}")))
+}
+
+async fn download_zip_root(
+    Path(device_id): Path<DeviceId>,
+    inj: WebActorSystem,
+) -> Result<impl IntoResponse, (StatusCode, String)> {
+    download_zip(Path((device_id, RelativeDirectoryPathBuf::root())), inj).await
+}
+
+/// Entries are relative to the requested directory
+async fn download_zip(
+    Path((device_id, path)): Path<(DeviceId, RelativeDirectoryPathBuf)>,
+    WebActorSystem(actor_system): WebActorSystem,
+) -> Result<impl IntoResponse, (StatusCode, String)> {
+    let files = actor_system
+        .ask(device_id, ListFilesRecursiveMessage { path: path.clone() })
+        .await
+        .map_err(|e| (StatusCode::BAD_REQUEST, format!("Bummer, it failed: {e:?}")))?;
+    let archive_name = path
+        .file_name()
+        .and_then(|x| x.to_str())
+        .map(ToString::to_string)
+        .unwrap_or_else(|| device_id.to_string());
+
+    Ok((
+        AppendHeaders([(
+            "Content-Disposition",
+            format!("attachment; filename=\"{archive_name}.zip\""),
+        )]),
+        IoStreamBody::with_writer(move |w| {
+            zip_files(
+                actor_system,
+                device_id,
+                path,
+                files,
+                ZipWriterWrapper::new_streaming_boxed(w),
+            )
+            .fuse()
+        }),
+    ))
+}
+
+async fn zip_files(
+    actor_system: ActorSystem,
+    device_id: DeviceId,
+    root: RelativeDirectoryPathBuf,
+    files: Vec<RelativeFilePath>,
+    mut writer: Box<dyn EntryWriter>,
+) -> anyhow::Result<()> {
+    for file in files {
+        let entry_path = file
+            .get_path()
+            .strip_prefix(&root)?
+            .to_str()
+            .ok_or_else(|| anyhow::anyhow!("invalid UTF-8"))?
+            .replace('\\', "/");
+        let data = actor_system
+            .ask(device_id, StreamFileMessage { path: file })
+            .await?;
+        writer
+            .insert(entry_path, &mut data.into_async_read())
+            .await?;
+    }
+    writer.close().await?;
+    Ok(())
+}
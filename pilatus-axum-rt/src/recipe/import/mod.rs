@@ -2,22 +2,37 @@ use std::collections::HashSet;
 
 use axum::{
     extract::ws::{Message, WebSocket},
+    http::{HeaderMap, StatusCode},
     response::IntoResponse,
 };
+use futures::{future::Either, stream::SplitSink, SinkExt, StreamExt};
 use minfac::ServiceCollection;
-use pilatus::{ImportRecipeError, IntoMergeStrategy, RecipeId, RecipeImporter, VariableConflict};
+use pilatus::{
+    ImportRecipeError, ImportRecipesOptions, IntoMergeStrategy, RecipeId, RecipeImporter,
+    VariableConflict,
+};
 use pilatus_axum::{
-    extract::{ws::WebSocketUpgrade, InjectRegistered},
+    extract::{ws::WebSocketUpgrade, InjectRegistered, Query},
     ServiceCollectionExtensions,
 };
 use tracing::{debug, error, info, warn};
+use uuid::Uuid;
 
+use upload::Uploads;
 use websocket_reader::AsyncWebsocketReader;
-use zip_reader_wrapper::ZipReaderWrapper;
+pub(super) use zip_reader_wrapper::ZipReaderWrapper;
 
-use super::zip_to_io_error;
+use super::{
+    archive_format::{password_from_headers, ArchiveFormat},
+    progress::{
+        ArchiveProgress, CountingReader, ProgressEntryReader, ProgressTracker, PROGRESS_INTERVAL,
+    },
+    zip_to_io_error,
+};
 
 pub(super) fn register_services(c: &mut ServiceCollection) {
+    upload::register_services(c);
+
     #[rustfmt::skip]
     c.register_web("recipe", |r| r
         .http("/import",|m| m.get(import_recipes))
@@ -26,36 +41,125 @@ pub(super) fn register_services(c: &mut ServiceCollection) {
 
 #[cfg(test)]
 mod tests;
+mod upload;
 mod websocket_reader;
 mod zip_reader_wrapper;
 
+#[derive(serde::Deserialize)]
+struct ImportQuery {
+    /// Websockets can't transmit a content-type, so the archive format is passed as query parameter
+    content_type: Option<String>,
+    /// Imports a completed resumable upload instead of receiving the archive over the socket
+    upload_id: Option<Uuid>,
+    /// Accepts archives of older versions, which don't contain a manifest to verify their integrity
+    #[serde(default)]
+    allow_without_manifest: bool,
+}
+
 async fn import_recipes(
     InjectRegistered(service): InjectRegistered<RecipeImporter>,
+    InjectRegistered(uploads): InjectRegistered<Uploads>,
+    Query(ImportQuery {
+        content_type,
+        upload_id,
+        allow_without_manifest,
+    }): Query<ImportQuery>,
+    headers: HeaderMap,
     ws: WebSocketUpgrade,
-) -> impl IntoResponse {
-    ws.on_upgrade(move |s| async {
-        if let Err(e) = import_recipes_upgraded(s, service).await {
+) -> Result<impl IntoResponse, (StatusCode, String)> {
+    // Required for encrypted archives
+    let password = password_from_headers(&headers)?;
+    let format = ArchiveFormat::from_content_type(content_type.as_deref());
+    let upload = upload_id.map(|id| (id, uploads));
+    Ok(ws.on_upgrade(move |s| async move {
+        let options = ImportRecipesOptions {
+            allow_without_manifest,
+            ..Default::default()
+        };
+        if let Err(e) = import_recipes_upgraded(s, service, format, password, upload, options).await
+        {
             debug!("Error during upload: {e}")
         }
-    })
+    }))
 }
 
 async fn import_recipes_upgraded(
-    mut socket: WebSocket,
+    socket: WebSocket,
     service: RecipeImporter,
+    format: ArchiveFormat,
+    password: Option<String>,
+    upload: Option<(Uuid, Uploads)>,
+    options: ImportRecipesOptions,
 ) -> Result<(), axum::Error> {
-    let mut result = service
-        .import(
-            &mut ZipReaderWrapper::new(futures_lite::io::BufReader::new(
-                tokio_util::compat::TokioAsyncReadCompatExt::compat(AsyncWebsocketReader::new(
-                    &mut socket,
-                )),
-            )),
-            Default::default(),
-        )
-        .await;
+    let (mut socket, mut stream) = socket.split();
+    let tracker = ProgressTracker::default();
+    let mut password = password;
+    let raw: Box<dyn futures::AsyncRead + Unpin + Send + '_> = match &upload {
+        Some((id, uploads)) => {
+            let file = match uploads.completed(*id) {
+                Ok((path, upload_password)) => {
+                    password = password.or(upload_password);
+                    tokio::fs::File::open(path).await.map_err(|e| e.to_string())
+                }
+                Err(e) => Err(e),
+            };
+            match file {
+                Ok(file) => Box::new(tokio_util::compat::TokioAsyncReadCompatExt::compat(file)),
+                Err(e) => {
+                    return abort_import(&mut socket, format!("Cannot import upload: {e}")).await
+                }
+            }
+        }
+        None => {
+            // Browsers can't set the password header on a WebSocket, so they send it before the archive
+            let first = match stream.next().await {
+                Some(Ok(Message::Text(x))) => match serde_json::from_str::<ImportCredentials>(&x) {
+                    Ok(credentials) => {
+                        password = Some(credentials.password).filter(|x| !x.is_empty());
+                        None
+                    }
+                    Err(e) => {
+                        let msg = format!("Invalid credentials: {e}");
+                        return abort_import(&mut socket, msg).await;
+                    }
+                },
+                first => first,
+            };
+            Box::new(tokio_util::compat::TokioAsyncReadCompatExt::compat(
+                AsyncWebsocketReader::new(futures::stream::iter(first).chain(&mut stream)),
+            ))
+        }
+    };
+    let mut result = {
+        let mut reader = ProgressEntryReader::new(
+            format.reader(CountingReader::new(raw, tracker.clone()), password),
+            tracker.clone(),
+        );
+        let import = std::pin::pin!(service.import(&mut reader, options));
+        let report = std::pin::pin!(async {
+            let mut interval = tokio::time::interval(PROGRESS_INTERVAL);
+            loop {
+                interval.tick().await;
+                let progress = ImportServerMessage::Progress(tracker.snapshot());
+                if let Err(e) = socket.send(progress.into_message()).await {
+                    return e;
+                }
+            }
+        });
+        match futures::future::select(import, report).await {
+            Either::Left((result, _)) => result,
+            Either::Right((e, _)) => return Err(e),
+        }
+    };
+    tracker.finish();
+    socket
+        .send(ImportServerMessage::Progress(tracker.snapshot()).into_message())
+        .await?;
 
-    async fn abort_import(socket: &mut WebSocket, msg: String) -> Result<(), axum::Error> {
+    async fn abort_import(
+        socket: &mut SplitSink<WebSocket, Message>,
+        msg: String,
+    ) -> Result<(), axum::Error> {
         debug!(msg);
         socket
             .send(ImportServerMessage::Error(msg).into_message())
@@ -69,6 +173,9 @@ async fn import_recipes_upgraded(
                 return abort_import(&mut socket, "Import contains active recipe.".to_string())
                     .await;
             }
+            ImportRecipeError::Maintenance(e) => {
+                return abort_import(&mut socket, e.to_string()).await;
+            }
             ImportRecipeError::InvalidFormat(msg) => {
                 return abort_import(&mut socket, format!("Invalid format: {msg}")).await;
             }
@@ -96,7 +203,7 @@ async fn import_recipes_upgraded(
                     .await?;
 
                 let strategy = loop {
-                    let Some(r) = socket.recv().await else {
+                    let Some(r) = stream.next().await else {
                         info!("Recipe import aborted by client");
                         return Ok(());
                     };
@@ -124,6 +231,10 @@ async fn import_recipes_upgraded(
             }
         }
     }
+    // Failed imports keep the upload, so it can be imported again without uploading it again
+    if let Some((id, uploads)) = upload {
+        uploads.finish(id);
+    }
     socket
         .send(ImportServerMessage::Success.into_message())
         .await?;
@@ -131,11 +242,21 @@ async fn import_recipes_upgraded(
     Ok(())
 }
 
+/// Optional first message of an import over the socket, an alternative to the password header
+#[derive(serde::Deserialize)]
+#[cfg_attr(feature = "ts", derive(ts_rs::TS), ts(export))]
+struct ImportCredentials {
+    password: String,
+}
+
 #[derive(Debug, serde::Serialize)]
+#[cfg_attr(feature = "ts", derive(ts_rs::TS), ts(export))]
 enum ImportServerMessage {
     Success,
     Error(String),
     Conflicts(HashSet<RecipeId>, Vec<VariableConflict>),
+    /// Sent periodically while the archive is received and once after it is read completely
+    Progress(ArchiveProgress),
 }
 
 impl ImportServerMessage {
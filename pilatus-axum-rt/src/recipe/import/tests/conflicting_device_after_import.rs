@@ -46,6 +46,7 @@ async fn import_recipe_with_different_id_but_including_existing_deviceid(
             ImportRecipesOptions {
                 merge_strategy,
                 is_dry_run: false,
+                ..Default::default()
             },
         )
         .await;
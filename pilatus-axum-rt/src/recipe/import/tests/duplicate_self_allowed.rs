@@ -50,6 +50,7 @@ async fn duplicate_self_allowed() {
             ImportRecipesOptions {
                 merge_strategy: IntoMergeStrategy::Duplicate,
                 is_dry_run: false,
+                ..Default::default()
             },
         )
         .await;
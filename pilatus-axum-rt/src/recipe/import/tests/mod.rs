@@ -13,6 +13,7 @@ mod replace_self_allowed;
 mod replace_without_files;
 mod success_replace;
 mod with_variables;
+mod without_manifest;
 
 async fn build_zip(
     recipe_id: RecipeId,
@@ -37,6 +37,7 @@ async fn replace_self_allowed() {
             ImportRecipesOptions {
                 merge_strategy: IntoMergeStrategy::Replace,
                 is_dry_run: false,
+                ..Default::default()
             },
         )
         .await;
@@ -31,6 +31,7 @@ async fn replace_without_files() {
             ImportRecipesOptions {
                 merge_strategy: IntoMergeStrategy::Replace,
                 is_dry_run: false,
+                ..Default::default()
             },
         )
         .await
@@ -38,6 +38,7 @@ async fn same_device_replaces_with_all_files() {
             ImportRecipesOptions {
                 merge_strategy: IntoMergeStrategy::Replace,
                 is_dry_run: false,
+                ..Default::default()
             },
         )
         .await;
@@ -0,0 +1,50 @@
+use futures::io::Cursor;
+use pilatus::{EntryWriter, ImportRecipeError, ImportRecipesOptions, Recipe, RecipeId};
+use pilatus_rt::RecipeServiceFassade;
+
+use crate::recipe::import::ZipReaderWrapper;
+
+#[tokio::test]
+async fn reject_archives_without_manifest_unless_allowed() {
+    let (_dir, rsb) = RecipeServiceFassade::create_temp_builder();
+    let rs = rsb.build();
+    let recipe_id: RecipeId = "legacy".parse().unwrap();
+    let recipe = serde_json::to_vec(&Recipe::default()).unwrap();
+    let data = super::writer_into_vec_unchecked(move |mut w| async move {
+        w.insert("variables.json".into(), &mut &b"{}"[..]).await?;
+        w.insert(format!("{recipe_id}/recipe.json"), &mut &recipe[..])
+            .await?;
+        w.close().await?;
+        Ok(())
+    })
+    .await;
+
+    let result = rs
+        .create_importer()
+        .import(
+            &mut ZipReaderWrapper::new(Cursor::new(data.clone())),
+            ImportRecipesOptions {
+                is_dry_run: false,
+                ..Default::default()
+            },
+        )
+        .await;
+    assert!(
+        matches!(result, Err(ImportRecipeError::InvalidFormat(_))),
+        "{result:?}"
+    );
+    assert_eq!(1, rs.state().await.recipes().iter_without_backup().count());
+
+    rs.create_importer()
+        .import(
+            &mut ZipReaderWrapper::new(Cursor::new(data)),
+            ImportRecipesOptions {
+                is_dry_run: false,
+                allow_without_manifest: true,
+                ..Default::default()
+            },
+        )
+        .await
+        .expect("Legacy archives are allowed explicitly");
+    assert_eq!(2, rs.state().await.recipes().iter_without_backup().count());
+}
@@ -0,0 +1,613 @@
+//! Resumable uploads of recipe archives, e.g. over flaky Wi-Fi, where the import WebSocket might drop
+//!
+//! Clients create an upload, send the archive in chunks with a `Content-Range` header and ask for the
+//! stored offset after a connection loss. Once complete, the upload is imported over the import WebSocket
+//! with `upload_id`, which still negotiates conflicts. Uploads don't survive a restart.
+//! Encrypted archives need the password header when the upload is created, because browsers can't set it on the WebSocket.
+//! A single upload may not exceed the disk budget of spooled downloads, and chunks are refused if the disk of the
+//! `temp_dir` doesn't have enough space left for them.
+
+use std::{
+    collections::HashMap,
+    io,
+    path::PathBuf,
+    sync::{Arc, Mutex},
+    time::Duration,
+};
+
+use futures::{Stream, StreamExt};
+use minfac::{Registered, ServiceCollection};
+use pilatus::{prelude::*, GenericConfig, SystemShutdown};
+use pilatus_axum::{
+    extract::{Body, InjectRegistered, Json, Path, Query},
+    http::{header::CONTENT_RANGE, HeaderMap, StatusCode},
+    IntoResponse, ServiceCollectionExtensions, SpoolConfig,
+};
+use serde::{Deserialize, Serialize};
+use tokio::{
+    io::{AsyncSeekExt, AsyncWriteExt},
+    time::Instant,
+};
+use tracing::{debug, warn};
+use uuid::Uuid;
+
+use crate::recipe::archive_format::password_from_headers;
+
+/// Uploads without a chunk for this long are removed by the periodic sweep
+const UPLOAD_EXPIRY: Duration = Duration::from_secs(24 * 60 * 60);
+const UPLOAD_SWEEP_INTERVAL: Duration = Duration::from_secs(60 * 60);
+
+pub(super) fn register_services(c: &mut ServiceCollection) {
+    c.register_shared(|| Arc::new(Uploads::default()))
+        .alias(|x| Uploads::clone(&x));
+    c.register_instance(pilatus::ServiceInfo::shared::<Uploads>());
+    c.with::<(Registered<Uploads>, Registered<SystemShutdown>)>()
+        .register_hosted_service("Expired Upload Cleanup", remove_expired_uploads);
+
+    #[rustfmt::skip]
+    c.register_web("recipe", |r| r
+        .http("/import/upload", |m| m.post(create_upload).summary("Start a resumable upload of a recipe archive"))
+        .http("/import/upload/:upload_id", |m| m
+            .get(get_upload).summary("Offset to resume the upload at")
+            .post(append_chunk).summary("Append the chunk in the Content-Range header")
+            .delete(delete_upload))
+    );
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+#[cfg_attr(feature = "ts", derive(ts_rs::TS), ts(export))]
+struct UploadStatus {
+    id: Uuid,
+    /// Bytes stored so far. The next chunk has to start here
+    offset: u64,
+    /// Unknown until the client tells it on creation or with a chunk
+    size: Option<u64>,
+    complete: bool,
+}
+
+#[derive(Clone)]
+pub(super) struct Uploads {
+    uploads: Arc<Mutex<HashMap<Uuid, Upload>>>,
+    /// Largest archive a single upload may store
+    max_size: u64,
+}
+
+impl Default for Uploads {
+    fn default() -> Self {
+        Self {
+            uploads: Default::default(),
+            max_size: SpoolConfig::default().disk_budget,
+        }
+    }
+}
+
+struct Upload {
+    path: PathBuf,
+    offset: u64,
+    size: Option<u64>,
+    /// Decrypts the archive on import
+    password: Option<String>,
+    last_activity: Instant,
+    /// Chunks of the same upload must not be written concurrently, e.g. by a retry while the old request is still running
+    writing: bool,
+}
+
+impl Upload {
+    fn status(&self, id: Uuid) -> UploadStatus {
+        UploadStatus {
+            id,
+            offset: self.offset,
+            size: self.size,
+            complete: self.size == Some(self.offset),
+        }
+    }
+}
+
+type UploadError = (StatusCode, String);
+
+fn unknown_upload(id: Uuid) -> UploadError {
+    (StatusCode::NOT_FOUND, format!("Unknown upload {id}"))
+}
+
+fn invalid_range(message: impl std::fmt::Display) -> UploadError {
+    (
+        StatusCode::BAD_REQUEST,
+        format!("Invalid Content-Range: {message}"),
+    )
+}
+
+fn too_large(max_size: u64) -> UploadError {
+    (
+        StatusCode::PAYLOAD_TOO_LARGE,
+        format!("Uploads are limited to {max_size} bytes"),
+    )
+}
+
+fn io_error(e: io::Error) -> UploadError {
+    (StatusCode::INTERNAL_SERVER_ERROR, format!("Io: {e}"))
+}
+
+/// Parsed `Content-Range: bytes {start}-{end}/{size}`, where size can be `*`
+#[derive(Debug, PartialEq)]
+struct ChunkRange {
+    start: u64,
+    len: u64,
+    size: Option<u64>,
+}
+
+impl std::str::FromStr for ChunkRange {
+    type Err = UploadError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let invalid = || invalid_range(s);
+        let (range, size) = s
+            .strip_prefix("bytes ")
+            .and_then(|x| x.split_once('/'))
+            .ok_or_else(invalid)?;
+        let (start, end) = range.split_once('-').ok_or_else(invalid)?;
+        let start: u64 = start.trim().parse().map_err(|_| invalid())?;
+        let end: u64 = end.trim().parse().map_err(|_| invalid())?;
+        let size = match size.trim() {
+            "*" => None,
+            x => Some(x.parse::<u64>().map_err(|_| invalid())?),
+        };
+        if end < start || size.is_some_and(|size| end >= size) {
+            return Err(invalid());
+        }
+        Ok(Self {
+            start,
+            len: (end - start).checked_add(1).ok_or_else(invalid)?,
+            size,
+        })
+    }
+}
+
+impl Uploads {
+    #[cfg(test)]
+    fn with_max_size(max_size: u64) -> Self {
+        Self {
+            max_size,
+            ..Default::default()
+        }
+    }
+
+    fn create(
+        &self,
+        dir: PathBuf,
+        size: Option<u64>,
+        password: Option<String>,
+    ) -> Result<UploadStatus, UploadError> {
+        if size.is_some_and(|size| size > self.max_size) {
+            return Err(too_large(self.max_size));
+        }
+        let id = Uuid::new_v4();
+        let mut lock = self.uploads.lock().expect("Never poisoned");
+        let upload = Upload {
+            path: dir.join(format!("pilatus-upload-{id}")),
+            offset: 0,
+            size,
+            password,
+            last_activity: Instant::now(),
+            writing: false,
+        };
+        let status = upload.status(id);
+        lock.insert(id, upload);
+        Ok(status)
+    }
+
+    fn remove_expired(&self) {
+        let mut lock = self.uploads.lock().expect("Never poisoned");
+        lock.retain(|id, upload| {
+            let expired = !upload.writing && upload.last_activity.elapsed() > UPLOAD_EXPIRY;
+            if expired {
+                debug!("Remove expired upload {id}");
+                remove_file_in_background(upload.path.clone());
+            }
+            !expired
+        });
+    }
+
+    fn status(&self, id: Uuid) -> Result<UploadStatus, UploadError> {
+        let lock = self.uploads.lock().expect("Never poisoned");
+        lock.get(&id)
+            .map(|x| x.status(id))
+            .ok_or_else(|| unknown_upload(id))
+    }
+
+    fn remove(&self, id: Uuid) -> Result<(), UploadError> {
+        let mut lock = self.uploads.lock().expect("Never poisoned");
+        if lock.get(&id).ok_or_else(|| unknown_upload(id))?.writing {
+            return Err((
+                StatusCode::CONFLICT,
+                format!("Upload {id} is still receiving a chunk"),
+            ));
+        }
+        if let Some(upload) = lock.remove(&id) {
+            remove_file_in_background(upload.path);
+        }
+        Ok(())
+    }
+
+    /// Path and password of the archive, if all bytes are received
+    pub(super) fn completed(&self, id: Uuid) -> Result<(PathBuf, Option<String>), String> {
+        let lock = self.uploads.lock().expect("Never poisoned");
+        match lock.get(&id) {
+            Some(x) if x.writing => Err(format!("Upload {id} is still receiving a chunk")),
+            Some(x) if x.size == Some(x.offset) => Ok((x.path.clone(), x.password.clone())),
+            Some(x) => Err(format!(
+                "Upload {id} is incomplete. Received {} bytes",
+                x.offset
+            )),
+            None => Err(format!("Unknown upload {id}")),
+        }
+    }
+
+    /// Removes the upload after it was imported
+    pub(super) fn finish(&self, id: Uuid) {
+        if let Err((_, e)) = self.remove(id) {
+            warn!("Cannot remove imported upload: {e}");
+        }
+    }
+
+    /// Appends as much of the chunk as is received, so a dropped connection only loses the bytes in flight
+    async fn append(
+        &self,
+        id: Uuid,
+        range: ChunkRange,
+        data: impl Stream<Item = Result<bytes::Bytes, impl std::fmt::Display>>,
+    ) -> Result<UploadStatus, UploadError> {
+        let (path, offset) = {
+            let mut lock = self.uploads.lock().expect("Never poisoned");
+            let upload = lock.get_mut(&id).ok_or_else(|| unknown_upload(id))?;
+            if upload.writing {
+                return Err((
+                    StatusCode::CONFLICT,
+                    format!("Upload {id} is still receiving another chunk"),
+                ));
+            }
+            if range.start != upload.offset {
+                return Err((
+                    StatusCode::CONFLICT,
+                    format!(
+                        "Chunk starts at {}, but the upload continues at {}",
+                        range.start, upload.offset
+                    ),
+                ));
+            }
+            if range.size.is_some_and(|size| size > self.max_size) {
+                return Err(too_large(self.max_size));
+            }
+            match (upload.size, range.size) {
+                (Some(known), Some(size)) if known != size => {
+                    return Err(invalid_range(format!(
+                        "Upload has {known} bytes, but the chunk specifies {size}"
+                    )));
+                }
+                (None, Some(size)) => upload.size = Some(size),
+                _ => {}
+            }
+            let Some(end) = range
+                .start
+                .checked_add(range.len)
+                .filter(|end| *end <= self.max_size)
+            else {
+                return Err(too_large(self.max_size));
+            };
+            if upload.size.is_some_and(|size| end > size) {
+                return Err(invalid_range("Chunk exceeds the size of the upload"));
+            }
+            upload.writing = true;
+            (upload.path.clone(), upload.offset)
+        };
+
+        // Axum drops the request, if the client disconnects while sending
+        let mut guard = AppendGuard {
+            uploads: self,
+            id,
+            start: offset,
+            written: 0,
+        };
+        let result = async {
+            ensure_disk_space(&path, range.len).await?;
+            write_at(&path, offset, range.len, data, &mut guard.written).await
+        }
+        .await;
+        drop(guard);
+        result?;
+        self.status(id)
+    }
+}
+
+/// Stores the offset after the bytes written so far and accepts the next chunk
+struct AppendGuard<'a> {
+    uploads: &'a Uploads,
+    id: Uuid,
+    start: u64,
+    written: u64,
+}
+
+impl Drop for AppendGuard<'_> {
+    fn drop(&mut self) {
+        let mut lock = self.uploads.uploads.lock().expect("Never poisoned");
+        if let Some(upload) = lock.get_mut(&self.id) {
+            upload.writing = false;
+            upload.offset = self.start + self.written;
+            upload.last_activity = Instant::now();
+        }
+    }
+}
+
+/// Counts the bytes in `written` as they are stored, so they are kept even if the stream or the file fails afterwards
+async fn write_at(
+    path: &std::path::Path,
+    offset: u64,
+    max_len: u64,
+    data: impl Stream<Item = Result<bytes::Bytes, impl std::fmt::Display>>,
+    written: &mut u64,
+) -> Result<(), UploadError> {
+    if let Some(parent) = path.parent() {
+        tokio::fs::create_dir_all(parent).await.map_err(io_error)?;
+    }
+    let mut file = tokio::fs::OpenOptions::new()
+        .create(true)
+        .write(true)
+        .truncate(false)
+        .open(path)
+        .await
+        .map_err(io_error)?;
+    // Drops bytes which were written without updating the offset, e.g. if the runtime was stopped while writing
+    file.set_len(offset).await.map_err(io_error)?;
+    file.seek(io::SeekFrom::Start(offset))
+        .await
+        .map_err(io_error)?;
+    futures::pin_mut!(data);
+    while let Some(chunk) = data.next().await {
+        let chunk = chunk.map_err(|e| {
+            (
+                StatusCode::BAD_REQUEST,
+                format!("Chunk was interrupted: {e}"),
+            )
+        })?;
+        let remaining = (max_len - *written) as usize;
+        if chunk.len() > remaining {
+            return Err(invalid_range(format!(
+                "Chunk is longer than {max_len} bytes"
+            )));
+        }
+        file.write_all(&chunk).await.map_err(io_error)?;
+        file.flush().await.map_err(io_error)?;
+        *written += chunk.len() as u64;
+    }
+    Ok(())
+}
+
+/// Free space of the disk with the longest mount point containing `path`. None, if it cannot be determined
+fn available_space(path: &std::path::Path) -> Option<u64> {
+    // The temp_dir is created with the first chunk
+    let path = path.ancestors().find_map(|x| x.canonicalize().ok())?;
+    let disks = sysinfo::Disks::new_with_refreshed_list();
+    disks
+        .iter()
+        .filter(|d| path.starts_with(d.mount_point()))
+        .max_by_key(|d| d.mount_point().as_os_str().len())
+        .map(|d| d.available_space())
+}
+
+/// Chunks are accepted if the free space is unknown, as the upload would only fail once the disk is full
+async fn ensure_disk_space(path: &std::path::Path, needed: u64) -> Result<(), UploadError> {
+    let path = path.to_path_buf();
+    let available = tokio::task::spawn_blocking(move || available_space(&path))
+        .await
+        .ok()
+        .flatten();
+    match available {
+        Some(available) if available < needed => Err((
+            StatusCode::INSUFFICIENT_STORAGE,
+            format!("Only {available} bytes are available for a chunk of {needed} bytes"),
+        )),
+        _ => Ok(()),
+    }
+}
+
+async fn remove_expired_uploads(
+    (uploads, shutdown): (Uploads, SystemShutdown),
+) -> anyhow::Result<()> {
+    let sweep = std::pin::pin!(async {
+        let mut interval = tokio::time::interval(UPLOAD_SWEEP_INTERVAL);
+        loop {
+            interval.tick().await;
+            uploads.remove_expired();
+        }
+    });
+    futures::future::select(sweep, shutdown).await;
+    Ok(())
+}
+
+fn remove_file_in_background(path: PathBuf) {
+    tokio::spawn(async move {
+        if let Err(e) = tokio::fs::remove_file(&path).await {
+            if e.kind() != io::ErrorKind::NotFound {
+                warn!("Cannot remove upload {path:?}: {e}");
+            }
+        }
+    });
+}
+
+#[derive(Deserialize)]
+struct CreateUploadQuery {
+    /// Total size of the archive, if known in advance
+    size: Option<u64>,
+}
+
+async fn create_upload(
+    InjectRegistered(uploads): InjectRegistered<Uploads>,
+    InjectRegistered(config): InjectRegistered<GenericConfig>,
+    Query(CreateUploadQuery { size }): Query<CreateUploadQuery>,
+    headers: HeaderMap,
+) -> Result<Json<UploadStatus>, (StatusCode, String)> {
+    let password = password_from_headers(&headers)?;
+    let dir = config.temp_dir();
+    if let Some(size) = size {
+        ensure_disk_space(&dir, size).await?;
+    }
+    Ok(Json(uploads.create(dir, size, password)?))
+}
+
+async fn get_upload(
+    Path(upload_id): Path<Uuid>,
+    InjectRegistered(uploads): InjectRegistered<Uploads>,
+) -> Result<Json<UploadStatus>, (StatusCode, String)> {
+    Ok(Json(uploads.status(upload_id)?))
+}
+
+async fn append_chunk(
+    Path(upload_id): Path<Uuid>,
+    InjectRegistered(uploads): InjectRegistered<Uploads>,
+    headers: HeaderMap,
+    body: Body,
+) -> Result<Json<UploadStatus>, (StatusCode, String)> {
+    let range = headers
+        .get(CONTENT_RANGE)
+        .ok_or_else(|| invalid_range("Header is missing"))?
+        .to_str()
+        .map_err(invalid_range)?
+        .parse()?;
+    Ok(Json(
+        uploads
+            .append(upload_id, range, body.into_data_stream())
+            .await?,
+    ))
+}
+
+async fn delete_upload(
+    Path(upload_id): Path<Uuid>,
+    InjectRegistered(uploads): InjectRegistered<Uploads>,
+) -> Result<impl IntoResponse, (StatusCode, String)> {
+    uploads.remove(upload_id)?;
+    Ok(StatusCode::NO_CONTENT)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn chunk(data: &'static [u8]) -> impl Stream<Item = Result<bytes::Bytes, io::Error>> {
+        futures::stream::iter([Ok(bytes::Bytes::from_static(data))])
+    }
+
+    #[test]
+    fn parse_content_range() {
+        assert_eq!(
+            ChunkRange {
+                start: 10,
+                len: 5,
+                size: None
+            },
+            "bytes 10-14/*".parse::<ChunkRange>().unwrap()
+        );
+        assert!("bytes 10-14/12".parse::<ChunkRange>().is_err());
+        assert!("10-14/20".parse::<ChunkRange>().is_err());
+        assert!(format!("bytes 0-{}/*", u64::MAX)
+            .parse::<ChunkRange>()
+            .is_err());
+    }
+
+    #[tokio::test]
+    async fn reject_uploads_exceeding_the_max_size() {
+        let dir = tempfile::tempdir().unwrap();
+        let uploads = Uploads::with_max_size(4);
+        let range = |x: &str| x.parse::<ChunkRange>().unwrap();
+
+        let (code, _) = uploads
+            .create(dir.path().to_path_buf(), Some(5), None)
+            .unwrap_err();
+        assert_eq!(StatusCode::PAYLOAD_TOO_LARGE, code);
+
+        let id = uploads
+            .create(dir.path().to_path_buf(), None, None)
+            .unwrap()
+            .id;
+        uploads
+            .append(id, range("bytes 0-2/*"), chunk(b"abc"))
+            .await
+            .unwrap();
+        let (code, _) = uploads
+            .append(id, range("bytes 3-4/*"), chunk(b"de"))
+            .await
+            .unwrap_err();
+        assert_eq!(StatusCode::PAYLOAD_TOO_LARGE, code);
+        let (code, _) = uploads
+            .append(id, range(&format!("bytes 3-{}/*", u64::MAX)), chunk(b"d"))
+            .await
+            .unwrap_err();
+        assert_eq!(StatusCode::PAYLOAD_TOO_LARGE, code);
+
+        let status = uploads
+            .append(id, range("bytes 3-3/4"), chunk(b"d"))
+            .await
+            .unwrap();
+        assert!(status.complete, "{status:?}");
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn sweep_expired_uploads() {
+        let dir = tempfile::tempdir().unwrap();
+        let uploads = Uploads::default();
+        let range = |x: &str| x.parse::<ChunkRange>().unwrap();
+        let expiring = uploads
+            .create(dir.path().to_path_buf(), None, None)
+            .unwrap()
+            .id;
+        let active = uploads
+            .create(dir.path().to_path_buf(), None, None)
+            .unwrap()
+            .id;
+
+        tokio::time::advance(UPLOAD_EXPIRY / 2).await;
+        uploads
+            .append(active, range("bytes 0-2/*"), chunk(b"abc"))
+            .await
+            .unwrap();
+        tokio::time::advance(UPLOAD_EXPIRY / 2 + Duration::from_secs(1)).await;
+        uploads.remove_expired();
+
+        assert_eq!(
+            StatusCode::NOT_FOUND,
+            uploads.status(expiring).unwrap_err().0
+        );
+        assert_eq!(3, uploads.status(active).unwrap().offset);
+    }
+
+    #[tokio::test]
+    async fn resume_after_interrupted_chunk() {
+        let dir = tempfile::tempdir().unwrap();
+        let uploads = Uploads::default();
+        let id = uploads
+            .create(dir.path().to_path_buf(), None, None)
+            .unwrap()
+            .id;
+        let range = |x: &str| x.parse::<ChunkRange>().unwrap();
+
+        let interrupted = futures::stream::iter([
+            Ok(bytes::Bytes::from_static(b"abc")),
+            Err(io::Error::from(io::ErrorKind::ConnectionReset)),
+        ]);
+        assert!(uploads
+            .append(id, range("bytes 0-5/*"), interrupted)
+            .await
+            .is_err());
+        assert_eq!(3, uploads.status(id).unwrap().offset);
+        assert!(uploads
+            .append(id, range("bytes 0-5/*"), chunk(b"abcdef"))
+            .await
+            .is_err());
+
+        let status = uploads
+            .append(id, range("bytes 3-5/6"), chunk(b"def"))
+            .await
+            .unwrap();
+        assert!(status.complete, "{status:?}");
+        let (path, _) = uploads.completed(id).unwrap();
+        assert_eq!(b"abcdef", &tokio::fs::read(path).await.unwrap()[..]);
+    }
+}
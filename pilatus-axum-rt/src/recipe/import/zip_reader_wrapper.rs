@@ -6,7 +6,9 @@ use pilatus::{EntryItem, EntryReader};
 
 use super::zip_to_io_error;
 
-pub(super) struct ZipReaderWrapper<'a, T: AsyncBufRead + Unpin + Send + 'a>(ZipStates<'a, T>);
+pub(in crate::recipe) struct ZipReaderWrapper<'a, T: AsyncBufRead + Unpin + Send + 'a>(
+    ZipStates<'a, T>,
+);
 
 impl<'a, T: AsyncBufRead + Unpin + Send + 'a> ZipReaderWrapper<'a, T> {
     pub fn new(raw: T) -> Self {
@@ -1,46 +1,63 @@
+use std::collections::HashSet;
 use std::fmt::{self, Display, Formatter, Write};
 use std::io::{self, ErrorKind};
 
 use async_zip::error::ZipError;
-use futures::{SinkExt, Stream, StreamExt};
+use futures::{Stream, StreamExt};
 use minfac::ServiceCollection;
 use pilatus::RecipeService;
 use pilatus::{
-    device::DeviceId, Name, ParameterUpdate, RecipeId, RecipeMetadata, TransactionError,
-    TransactionOptions,
+    device::{DeviceId, DeviceStatusRegistry, RecipeRunner},
+    ApprovalState, DeviceGroupId, Name, ParameterUpdate, RecipeId, RecipeMetadata,
+    TransactionError,
 };
 use pilatus_axum::{
     extract::{
-        ws::{Message, WebSocket, WebSocketUpgrade},
-        InjectRegistered, Json, Path, Query,
+        ws::{Keepalive, Message, WebSocket, WebSocketUpgrade},
+        CurrentUser, InjectRegistered, Json, Path, Query, Transaction,
     },
     http::StatusCode,
-    IntoResponse, ServiceCollectionExtensions,
+    ApiError, IntoResponse, ServiceCollectionExtensions,
 };
 use sealedstruct::ValidationErrors;
 use tracing::debug;
-use uuid::Uuid;
 
+mod archive_format;
+mod changes;
 mod export;
 mod file;
 mod import;
+mod progress;
 
 pub(super) fn register_services(c: &mut ServiceCollection) {
     #[rustfmt::skip]
     c.register_web("recipe", |r| r
-        .http("/get_all", |m| m.get(get_all))
+        .http("/get_all", |m| m.get(get_all).summary("All recipes including the active one"))
+        .http("/stats", |m| m.get(get_stats).summary("Device counts, folder sizes and last change of all recipes"))
+        .http("/variables/usages", |m| m.get(get_variable_usages).summary("Recipes, devices and parameter paths which reference each variable"))
         .http("/new_default", |m| m.put(add_default_recipe))
         .http("/stream",|m| m.get(stream_recipe_update_handler))
-        .http("/commit", |m| m.put(commit_active))
-        .http("/restore", |m| m.put(restore_active))
+        .http("/commit", |m| m.put(commit_active).summary("Commit changes of the active recipe"))
+        .http("/restore", |m| m.put(restore_active).summary("Discard uncommitted changes of the active recipe"))
         .http("/:id/meta", |m| m.put(update_recipe_metadata))
-        .http("/:id/clone", |m| m.put(clone_recipe))
-        .http("/:id", |m| m.delete(delete_recipe))
+        .http("/:id/approval", |m| m.put(update_recipe_approval).summary("Move a recipe between draft, review, released and locked"))
+        .http("/:id/clone", |m| m.put(clone_recipe).summary("Clone a recipe with new device ids"))
+        .http("/:id", |m| m.delete(delete_recipe).summary("Delete an inactive recipe"))
         .http("/:id/device/:device_id/params", |m| m.put(update_device_params))
+        .http("/:id/device/:device_id/params/preview", |m| m.post(preview_device_params).summary("Validated params and their changes, without applying them"))
         .http("/:id/device/:device_id/name", |m| m.put(update_device_name))
+        .http("/:id/device/:device_id/simulated", |m| m.put(update_device_simulated))
+        .http("/:id/device/:device_id/enabled", |m| m.put(update_device_enabled))
+        .http("/:id/device/:device_id/locked", |m| m.put(update_device_locked))
+        .http("/:id/device/:device_id/notes", |m| m.put(update_device_notes))
+        .http("/:id/device/:device_id/group", |m| m.put(update_device_group))
+        .http("/:id/group", |m| m.put(add_device_group))
+        .http("/:id/group/:group_id", |m| m.delete(delete_device_group))
+        .http("/:id/group/:group_id/name", |m| m.put(rename_device_group))
         .http("/:id/device/:device_id/committed", |m| m.put(restore_committed))
     );
 
+    changes::register_services(c);
     file::register_services(c);
     export::register_services(c);
     import::register_services(c);
@@ -71,39 +88,79 @@ pub fn zip_to_io_error(e: ZipError) -> io::Error {
     )
 }
 
+#[derive(serde::Deserialize)]
+struct StreamRecipeUpdateQuery {
+    /// Additionally send `{"device_status": DeviceRuntimeStatus}` whenever a device changes its lifecycle
+    #[serde(default)]
+    device_status: bool,
+}
+
 async fn stream_recipe_update_handler(
     upgrade: WebSocketUpgrade,
     InjectRegistered(service): InjectRegistered<RecipeService>,
+    InjectRegistered(status): InjectRegistered<DeviceStatusRegistry>,
+    Query(query): Query<StreamRecipeUpdateQuery>,
 ) -> Result<impl IntoResponse, (StatusCode, String)> {
-    let watcher = service.get_update_receiver();
+    let transactions = service.get_update_receiver().map(|x| x.to_string());
+    let watcher = if query.device_status {
+        futures::stream::select(
+            transactions,
+            status.subscribe().filter_map(|x| async move {
+                serde_json::to_string(&serde_json::json!({ "device_status": x })).ok()
+            }),
+        )
+        .boxed()
+    } else {
+        transactions.boxed()
+    };
 
+    let keepalive = upgrade.keepalive();
     Ok(upgrade.into_inner().on_upgrade(move |socket| async move {
         debug!("Subscribe recipe update broadcast");
-        handle_socket(socket, watcher).await;
+        handle_socket(socket, watcher, keepalive).await;
         debug!("Recipe update subscription ended.");
     }))
 }
 
-async fn handle_socket(socket: WebSocket, watcher: impl Stream<Item = Uuid>) {
+async fn handle_socket(
+    socket: WebSocket,
+    watcher: impl Stream<Item = String>,
+    keepalive: Keepalive,
+) {
     let (mut socket_tx, mut socket_rx) = socket.split();
     futures::pin_mut!(watcher);
     {
         tokio::select!(
             _ = async {
-                while let Some(data) = watcher.next().await {
-                    if socket_tx
-                        .send(Message::Text(data.to_string()))
-                        .await
-                        .is_err()
-                    {
+                let mut ping = Box::pin(keepalive.next_ping());
+                loop {
+                    let (msg, pinged) = tokio::select! {
+                        data = watcher.next() => match data {
+                            Some(data) => (Message::Text(data), false),
+                            None => break,
+                        },
+                        ping = &mut ping => match ping {
+                            Some(ping) => (ping, true),
+                            None => {
+                                debug!("Drop recipe update socket, which didn't respond to pings");
+                                break;
+                            }
+                        },
+                    };
+                    if pinged {
+                        ping = Box::pin(keepalive.next_ping());
+                    }
+                    if let Err(e) = keepalive.send(&mut socket_tx, msg).await {
+                        debug!("Drop recipe update socket: {e:?}");
                         break;
                     }
                 }
             } => {},
             _ = async {
                 while let Some(r) = socket_rx.next().await {
-                    if r.is_err() {
-                        break;
+                    match r {
+                        Ok(msg) => keepalive.on_message(&msg),
+                        Err(_) => break,
                     }
                 }
             } => {}
@@ -116,40 +173,57 @@ async fn handle_socket(socket: WebSocket, watcher: impl Stream<Item = Uuid>) {
         .await;
 }
 
+async fn get_stats(
+    InjectRegistered(service): InjectRegistered<RecipeService>,
+) -> Result<impl IntoResponse, (StatusCode, String)> {
+    service.stats().await.map(Json).map_err(|e| {
+        (
+            StatusCode::INTERNAL_SERVER_ERROR,
+            format!("Bummer, it failed: {e:?}"),
+        )
+    })
+}
+
 async fn delete_recipe(
     InjectRegistered(service): InjectRegistered<RecipeService>,
     Path(recipe_id): Path<RecipeId>,
-    Query(options): Query<TransactionOptions>,
-) -> Result<(), (StatusCode, String)> {
+    Transaction(options): Transaction,
+) -> Result<(), ApiError> {
     service
         .delete_recipe_with(recipe_id, options)
         .await
-        .map_err(transaction_error_to_http_resonse)
+        .map_err(ApiError::from)
 }
 
 async fn clone_recipe(
     InjectRegistered(service): InjectRegistered<RecipeService>,
     Path(recipe_id): Path<RecipeId>,
-    Query(options): Query<TransactionOptions>,
-) -> Result<impl IntoResponse, (StatusCode, String)> {
+    Transaction(options): Transaction,
+) -> Result<impl IntoResponse, ApiError> {
     let recipe = service
         .duplicate_recipe_with(recipe_id, options)
         .await
-        .map_err(transaction_error_to_http_resonse)?;
+        .map_err(ApiError::from)?;
     Ok(Json(recipe))
 }
 
 async fn add_default_recipe(
     InjectRegistered(service): InjectRegistered<RecipeService>,
-    Query(options): Query<TransactionOptions>,
-) -> Result<impl IntoResponse, (StatusCode, String)> {
+    Transaction(options): Transaction,
+) -> Result<impl IntoResponse, ApiError> {
     let recipe = service
         .add_new_default_recipe_with(options)
         .await
-        .map_err(transaction_error_to_http_resonse)?;
+        .map_err(ApiError::from)?;
     Ok(Json(recipe))
 }
 
+async fn get_variable_usages(
+    InjectRegistered(service): InjectRegistered<RecipeService>,
+) -> impl IntoResponse {
+    Json(service.state().await.recipes().variable_usages())
+}
+
 async fn get_all(InjectRegistered(service): InjectRegistered<RecipeService>) -> impl IntoResponse {
     let recipes = service.state().await;
     Json(recipes)
@@ -158,15 +232,15 @@ async fn get_all(InjectRegistered(service): InjectRegistered<RecipeService>) ->
 async fn update_device_params(
     InjectRegistered(service): InjectRegistered<RecipeService>,
     Path((recipe_id, device_id)): Path<(RecipeId, DeviceId)>,
-    Query(options): Query<TransactionOptions>,
+    Transaction(options): Transaction,
     Json(param_update): Json<ParameterUpdate>,
-) -> Result<(), (StatusCode, String)> {
+) -> Result<(), ApiError> {
     service
         .update_device_params_with(recipe_id, device_id, param_update, options)
         .await
         .map_err(|e| {
-            struct DeviceConfigWrapper(ValidationErrors);
-            impl Display for DeviceConfigWrapper {
+            struct DeviceConfigWrapper<'a>(&'a ValidationErrors);
+            impl Display for DeviceConfigWrapper<'_> {
                 fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
                     for x in self.0.iter() {
                         f.write_str(&x.reason)?;
@@ -175,82 +249,212 @@ async fn update_device_params(
                     Ok(())
                 }
             }
-            (
-                StatusCode::BAD_REQUEST,
-                match e {
-                    TransactionError::InvalidDeviceConfig(e) => DeviceConfigWrapper(e).to_string(),
-                    _ => e.to_string(),
-                },
-            )
+            match e {
+                TransactionError::InvalidDeviceConfig(ref validation) => {
+                    let message = DeviceConfigWrapper(validation).to_string();
+                    ApiError::from(e).with_message(message)
+                }
+                e => ApiError::from(e),
+            }
         })
 }
 
+async fn preview_device_params(
+    InjectRegistered(service): InjectRegistered<RecipeService>,
+    Path((recipe_id, device_id)): Path<(RecipeId, DeviceId)>,
+    Json(param_update): Json<ParameterUpdate>,
+) -> Result<impl IntoResponse, ApiError> {
+    service
+        .preview_device_params(recipe_id, device_id, param_update)
+        .await
+        .map(Json)
+        .map_err(ApiError::from)
+}
+
 async fn update_recipe_metadata(
     InjectRegistered(service): InjectRegistered<RecipeService>,
     Path(id): Path<RecipeId>,
-    Query(options): Query<TransactionOptions>,
+    Transaction(options): Transaction,
     Json(data): Json<RecipeMetadata>,
-) -> Result<(), (StatusCode, String)> {
+) -> Result<(), ApiError> {
     service
         .update_recipe_metadata_with(id, data, options)
         .await
-        .map_err(transaction_error_to_http_resonse)
+        .map_err(ApiError::from)
 }
 
-#[derive(serde::Deserialize)]
-struct TransactionIdWrapper {
-    key: Option<Uuid>,
+/// The transition is recorded in the recipe's history with the user as author
+async fn update_recipe_approval(
+    InjectRegistered(service): InjectRegistered<RecipeService>,
+    CurrentUser(user): CurrentUser,
+    Path(id): Path<RecipeId>,
+    Transaction(options): Transaction,
+    Json(state): Json<ApprovalState>,
+) -> Result<(), ApiError> {
+    let options = options.with_author(user.name).with_role(user.role);
+    service
+        .update_recipe_approval_with(id, state, options)
+        .await
+        .map_err(ApiError::from)
 }
 
 async fn commit_active(
     InjectRegistered(service): InjectRegistered<RecipeService>,
-    Query(options): Query<TransactionIdWrapper>,
-) -> Result<(), (StatusCode, String)> {
+    Transaction(options): Transaction,
+) -> Result<(), ApiError> {
     service
-        .commit_active_with(options.key.unwrap_or_else(Uuid::new_v4))
+        .commit_active_with(options)
         .await
-        .map_err(transaction_error_to_http_resonse)
+        .map_err(ApiError::from)
 }
 
 async fn restore_active(
     InjectRegistered(service): InjectRegistered<RecipeService>,
-    Query(options): Query<TransactionIdWrapper>,
-) -> Result<(), (StatusCode, String)> {
+    Transaction(options): Transaction,
+) -> Result<(), ApiError> {
     service
-        .restore_active_with(options.key.unwrap_or_else(Uuid::new_v4))
+        .restore_active_with(options)
         .await
-        .map_err(transaction_error_to_http_resonse)
+        .map_err(ApiError::from)
 }
 
 async fn restore_committed(
     InjectRegistered(service): InjectRegistered<RecipeService>,
     Path((recipe_id, device_id)): Path<(RecipeId, DeviceId)>,
-    Query(options): Query<TransactionIdWrapper>,
-) -> Result<(), (StatusCode, String)> {
+    Transaction(options): Transaction,
+) -> Result<(), ApiError> {
     service
-        .restore_committed(
-            recipe_id,
-            device_id,
-            options.key.unwrap_or_else(Uuid::new_v4),
-        )
+        .restore_committed_with(recipe_id, device_id, options)
         .await
-        .map_err(transaction_error_to_http_resonse)
+        .map_err(ApiError::from)
 }
 
 async fn update_device_name(
     InjectRegistered(service): InjectRegistered<RecipeService>,
     Path((recipe_id, device_id)): Path<(RecipeId, DeviceId)>,
-    Query(options): Query<TransactionOptions>,
+    Transaction(options): Transaction,
     device_name: String,
-) -> Result<(), (StatusCode, String)> {
+) -> Result<(), ApiError> {
     let device_name =
         Name::new(device_name).map_err(|e| (StatusCode::UNPROCESSABLE_ENTITY, e.to_string()))?;
     service
         .update_device_name_with(recipe_id, device_id, device_name, options)
         .await
-        .map_err(transaction_error_to_http_resonse)
+        .map_err(ApiError::from)
 }
 
-fn transaction_error_to_http_resonse(e: TransactionError) -> (StatusCode, String) {
-    (StatusCode::BAD_REQUEST, e.to_string())
+async fn update_device_simulated(
+    InjectRegistered(service): InjectRegistered<RecipeService>,
+    Path((recipe_id, device_id)): Path<(RecipeId, DeviceId)>,
+    Transaction(options): Transaction,
+    Json(simulated): Json<bool>,
+) -> Result<(), ApiError> {
+    service
+        .update_device_simulated_with(recipe_id, device_id, simulated, options)
+        .await
+        .map_err(ApiError::from)
+}
+
+/// Changes in the active recipe take effect immediately: Disabled devices are stopped.
+/// Enabling a device restarts the active recipe, as devices can't join a running recipe
+async fn update_device_enabled(
+    InjectRegistered(service): InjectRegistered<RecipeService>,
+    InjectRegistered(runner): InjectRegistered<RecipeRunner>,
+    Path((recipe_id, device_id)): Path<(RecipeId, DeviceId)>,
+    Transaction(options): Transaction,
+    Json(enabled): Json<bool>,
+) -> Result<(), ApiError> {
+    let active_enabled = {
+        let state = service.state().await;
+        let (active_id, active) = state.recipes().active();
+        match active.device_by_id(device_id) {
+            Ok(device) if active_id == recipe_id => Some(device.enabled),
+            _ => None,
+        }
+    };
+    service
+        .update_device_enabled_with(recipe_id.clone(), device_id, enabled, options)
+        .await?;
+    let restart = match active_enabled {
+        Some(previous) if previous != enabled && enabled => runner.select_recipe(recipe_id).await,
+        Some(previous) if previous != enabled => {
+            runner.restart_devices(HashSet::from([device_id])).await
+        }
+        _ => return Ok(()),
+    };
+    restart.map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()).into())
+}
+
+async fn update_device_locked(
+    InjectRegistered(service): InjectRegistered<RecipeService>,
+    Path((recipe_id, device_id)): Path<(RecipeId, DeviceId)>,
+    Transaction(options): Transaction,
+    Json(locked): Json<bool>,
+) -> Result<(), ApiError> {
+    service
+        .update_device_locked_with(recipe_id, device_id, locked, options)
+        .await
+        .map_err(ApiError::from)
+}
+
+async fn update_device_notes(
+    InjectRegistered(service): InjectRegistered<RecipeService>,
+    Path((recipe_id, device_id)): Path<(RecipeId, DeviceId)>,
+    Transaction(options): Transaction,
+    notes: String,
+) -> Result<(), ApiError> {
+    service
+        .update_device_notes_with(recipe_id, device_id, notes, options)
+        .await
+        .map_err(ApiError::from)
+}
+
+async fn add_device_group(
+    InjectRegistered(service): InjectRegistered<RecipeService>,
+    Path(recipe_id): Path<RecipeId>,
+    Transaction(options): Transaction,
+    name: String,
+) -> Result<Json<DeviceGroupId>, ApiError> {
+    let name = Name::new(name).map_err(|e| (StatusCode::UNPROCESSABLE_ENTITY, e.to_string()))?;
+    service
+        .add_device_group_with(recipe_id, name, options)
+        .await
+        .map(Json)
+        .map_err(ApiError::from)
+}
+
+async fn rename_device_group(
+    InjectRegistered(service): InjectRegistered<RecipeService>,
+    Path((recipe_id, group_id)): Path<(RecipeId, DeviceGroupId)>,
+    Transaction(options): Transaction,
+    name: String,
+) -> Result<(), ApiError> {
+    let name = Name::new(name).map_err(|e| (StatusCode::UNPROCESSABLE_ENTITY, e.to_string()))?;
+    service
+        .rename_device_group_with(recipe_id, group_id, name, options)
+        .await
+        .map_err(ApiError::from)
+}
+
+async fn delete_device_group(
+    InjectRegistered(service): InjectRegistered<RecipeService>,
+    Path((recipe_id, group_id)): Path<(RecipeId, DeviceGroupId)>,
+    Transaction(options): Transaction,
+) -> Result<(), ApiError> {
+    service
+        .delete_device_group_with(recipe_id, group_id, options)
+        .await
+        .map_err(ApiError::from)
+}
+
+async fn update_device_group(
+    InjectRegistered(service): InjectRegistered<RecipeService>,
+    Path((recipe_id, device_id)): Path<(RecipeId, DeviceId)>,
+    Transaction(options): Transaction,
+    Json(group_id): Json<Option<DeviceGroupId>>,
+) -> Result<(), ApiError> {
+    service
+        .update_device_group_with(recipe_id, device_id, group_id, options)
+        .await
+        .map_err(ApiError::from)
 }
@@ -0,0 +1,255 @@
+//! Progress of recipe imports and exports, so clients can show a progress bar and detect stalls
+//!
+//! The archive bytes are counted below the archive format and the entries above it, so the progress is the same for all formats
+
+use std::{
+    io,
+    pin::Pin,
+    sync::{Arc, Mutex},
+    task::{Context, Poll},
+    time::Duration,
+};
+
+use futures::{
+    future::BoxFuture,
+    io::{AsyncRead, AsyncWrite},
+    FutureExt,
+};
+use pilatus::{EntryItem, EntryReader, EntryWriter, PinReader};
+
+/// Clients receive a progress frame this often while an archive is processed
+pub(super) const PROGRESS_INTERVAL: Duration = Duration::from_millis(500);
+
+#[derive(Debug, Clone, Default, PartialEq, serde::Serialize)]
+#[cfg_attr(feature = "ts", derive(ts_rs::TS), ts(export))]
+pub(super) struct ArchiveProgress {
+    /// Archive bytes received or sent so far
+    bytes: u64,
+    /// Entry which is currently read or written
+    entry: Option<String>,
+    /// Started entries, including the current one
+    entries: usize,
+    /// None, if the archive doesn't tell in advance, e.g. for imports
+    entries_total: Option<usize>,
+    finished: bool,
+}
+
+impl ArchiveProgress {
+    pub(super) fn is_finished(&self) -> bool {
+        self.finished
+    }
+
+    pub(super) fn is_started(&self) -> bool {
+        self.bytes > 0 || self.entries > 0
+    }
+}
+
+/// Cheap to clone, all clones share the same progress
+#[derive(Debug, Clone, Default)]
+pub(super) struct ProgressTracker(Arc<Mutex<ArchiveProgress>>);
+
+impl ProgressTracker {
+    pub(super) fn snapshot(&self) -> ArchiveProgress {
+        self.0.lock().expect("Never poisoned").clone()
+    }
+
+    pub(super) fn finish(&self) {
+        self.update(|p| p.finished = true);
+    }
+
+    fn update(&self, f: impl FnOnce(&mut ArchiveProgress)) {
+        f(&mut self.0.lock().expect("Never poisoned"));
+    }
+
+    fn start_entry(&self, name: String) {
+        self.update(|p| {
+            p.entry = Some(name);
+            p.entries += 1;
+        });
+    }
+}
+
+/// Counts the bytes of the raw archive
+pub(super) struct CountingReader<R> {
+    inner: R,
+    tracker: ProgressTracker,
+}
+
+impl<R> CountingReader<R> {
+    pub(super) fn new(inner: R, tracker: ProgressTracker) -> Self {
+        Self { inner, tracker }
+    }
+}
+
+impl<R: AsyncRead + Unpin> AsyncRead for CountingReader<R> {
+    fn poll_read(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &mut [u8],
+    ) -> Poll<io::Result<usize>> {
+        let result = Pin::new(&mut self.inner).poll_read(cx, buf);
+        if let Poll::Ready(Ok(n)) = result {
+            self.tracker.update(|p| p.bytes += n as u64);
+        }
+        result
+    }
+}
+
+/// Counts the bytes of the raw archive
+pub(super) struct CountingWriter<W> {
+    inner: W,
+    tracker: ProgressTracker,
+}
+
+impl<W> CountingWriter<W> {
+    pub(super) fn new(inner: W, tracker: ProgressTracker) -> Self {
+        Self { inner, tracker }
+    }
+}
+
+impl<W: AsyncWrite + Unpin> AsyncWrite for CountingWriter<W> {
+    fn poll_write(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &[u8],
+    ) -> Poll<io::Result<usize>> {
+        let result = Pin::new(&mut self.inner).poll_write(cx, buf);
+        if let Poll::Ready(Ok(n)) = result {
+            self.tracker.update(|p| p.bytes += n as u64);
+        }
+        result
+    }
+
+    fn poll_flush(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        Pin::new(&mut self.inner).poll_flush(cx)
+    }
+
+    fn poll_close(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        Pin::new(&mut self.inner).poll_close(cx)
+    }
+}
+
+pub(super) struct ProgressEntryReader<'a> {
+    inner: Box<dyn EntryReader + 'a>,
+    tracker: ProgressTracker,
+}
+
+impl<'a> ProgressEntryReader<'a> {
+    pub(super) fn new(inner: Box<dyn EntryReader + 'a>, tracker: ProgressTracker) -> Self {
+        Self { inner, tracker }
+    }
+}
+
+impl EntryReader for ProgressEntryReader<'_> {
+    fn next(&mut self) -> BoxFuture<'_, Option<io::Result<EntryItem>>> {
+        async move {
+            let next = self.inner.next().await;
+            if let Some(Ok(item)) = &next {
+                self.tracker.start_entry(item.filename.clone());
+            }
+            next
+        }
+        .boxed()
+    }
+}
+
+pub(super) struct ProgressEntryWriter {
+    inner: Box<dyn EntryWriter>,
+    tracker: ProgressTracker,
+}
+
+impl ProgressEntryWriter {
+    pub(super) fn new_boxed(inner: Box<dyn EntryWriter>, tracker: ProgressTracker) -> Box<Self> {
+        Box::new(Self { inner, tracker })
+    }
+}
+
+impl EntryWriter for ProgressEntryWriter {
+    fn insert<'a>(
+        &'a mut self,
+        path: String,
+        data: &'a mut dyn PinReader,
+    ) -> BoxFuture<'a, io::Result<()>> {
+        self.tracker.start_entry(path.clone());
+        self.inner.insert(path, data)
+    }
+
+    fn insert_sized<'a>(
+        &'a mut self,
+        path: String,
+        size: u64,
+        data: &'a mut dyn PinReader,
+    ) -> BoxFuture<'a, io::Result<()>> {
+        self.tracker.start_entry(path.clone());
+        self.inner.insert_sized(path, size, data)
+    }
+
+    fn close(self: Box<Self>) -> BoxFuture<'static, io::Result<()>> {
+        self.inner.close()
+    }
+
+    fn announce_entries(&mut self, total: usize) {
+        self.tracker.update(|p| p.entries_total = Some(total));
+        self.inner.announce_entries(total);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use futures::{io::BufReader, AsyncReadExt};
+    use tokio_util::compat::TokioAsyncWriteCompatExt;
+
+    use super::*;
+    use crate::recipe::import::ZipReaderWrapper;
+    use crate::zip_writer_wrapper::ZipWriterWrapper;
+
+    #[tokio::test]
+    async fn count_archive_bytes_and_entries() {
+        let tracker = ProgressTracker::default();
+        let (mut r, w) = tokio::io::duplex(1 << 16);
+        let mut writer = ProgressEntryWriter::new_boxed(
+            ZipWriterWrapper::new_boxed(CountingWriter::new(w.compat_write(), tracker.clone())),
+            tracker.clone(),
+        );
+        writer.announce_entries(2);
+        writer.insert("a".into(), &mut &b"foo"[..]).await.unwrap();
+        writer.insert("b".into(), &mut &b"bar"[..]).await.unwrap();
+        writer.close().await.unwrap();
+        let mut buf = Vec::new();
+        tokio::io::AsyncReadExt::read_to_end(&mut r, &mut buf)
+            .await
+            .unwrap();
+        let written = tracker.snapshot();
+        assert_eq!(
+            (Some("b"), 2, Some(2)),
+            (
+                written.entry.as_deref(),
+                written.entries,
+                written.entries_total
+            )
+        );
+        assert_eq!(buf.len() as u64, written.bytes);
+
+        let tracker = ProgressTracker::default();
+        let raw = CountingReader::new(futures::io::Cursor::new(buf.clone()), tracker.clone());
+        let mut reader = ProgressEntryReader::new(
+            Box::new(ZipReaderWrapper::new(BufReader::new(raw))),
+            tracker.clone(),
+        );
+        while let Some(entry) = reader.next().await {
+            let mut content = Vec::new();
+            entry
+                .unwrap()
+                .reader
+                .read_to_end(&mut content)
+                .await
+                .unwrap();
+        }
+        let read = tracker.snapshot();
+        assert_eq!(
+            (Some("b"), 2, None),
+            (read.entry.as_deref(), read.entries, read.entries_total)
+        );
+        assert!(read.bytes > 0 && read.bytes <= buf.len() as u64, "{read:?}");
+    }
+}
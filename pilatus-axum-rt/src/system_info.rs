@@ -0,0 +1,29 @@
+use minfac::ServiceCollection;
+use pilatus::{RegistrarServices, ServiceInfo, SystemInfo, WorkerPoolUtilization, WorkerPools};
+use pilatus_axum::{
+    extract::{InjectAll, InjectRegistered, Json},
+    ServiceCollectionExtensions,
+};
+
+pub(super) fn register_services(c: &mut ServiceCollection) {
+    #[rustfmt::skip]
+    c.register_web("system", |x| x
+        .http("/info", |m| m.get(get_info).summary("Machine id, version, loaded plugins and license"))
+        .http("/services", |m| m.get(list_services).summary("Devices, hosted services and web prefixes grouped by the registrar which provided them"))
+        .http("/worker_pools", |m| m.get(get_worker_pools).summary("Threads, busy and queued jobs of the pools for blocking work"))
+    );
+}
+
+async fn get_info(InjectRegistered(info): InjectRegistered<SystemInfo>) -> Json<SystemInfo> {
+    Json(info)
+}
+
+async fn list_services(InjectAll(infos): InjectAll<ServiceInfo>) -> Json<Vec<RegistrarServices>> {
+    Json(pilatus::group_by_registrar(infos))
+}
+
+async fn get_worker_pools(
+    InjectRegistered(pools): InjectRegistered<WorkerPools>,
+) -> Json<Vec<WorkerPoolUtilization>> {
+    Json(pools.utilization())
+}
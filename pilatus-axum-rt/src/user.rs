@@ -0,0 +1,155 @@
+use chrono::{Duration, Utc};
+use minfac::ServiceCollection;
+use pilatus::{IssuedToken, Role, TokenInfo, User, UserError, UserService};
+use pilatus_axum::{
+    extract::{CurrentUser, InjectRegistered, Json, OptionalUser, Path},
+    http::StatusCode,
+    ServiceCollectionExtensions,
+};
+use serde::Deserialize;
+use uuid::Uuid;
+
+pub(super) fn register_services(c: &mut ServiceCollection) {
+    #[rustfmt::skip]
+    c.register_web("user", |r| r
+        .http("/login", |m| m.post(login).summary("Issue an API token"))
+        .http("/me", |m| m.get(me))
+        .http("/tokens", |m| m.get(list_tokens))
+        .http("/tokens/:id", |m| m.delete(revoke_token))
+        .http("/list", |m| m.get(list_users).summary("All users (admin only)"))
+        .http("/:name", |m| m.put(set_user).delete(delete_user).summary("Create/update (admin only, unless there are no users yet) or delete a user"))
+    );
+}
+
+fn to_http_error(e: UserError) -> (StatusCode, String) {
+    let status = match e {
+        UserError::InvalidCredentials => StatusCode::UNAUTHORIZED,
+        UserError::UnknownUser(_) | UserError::UnknownToken(_) => StatusCode::NOT_FOUND,
+        UserError::Invalid(_) => StatusCode::UNPROCESSABLE_ENTITY,
+        UserError::AlreadyInitialized => StatusCode::UNAUTHORIZED,
+        UserError::Other(_) => StatusCode::INTERNAL_SERVER_ERROR,
+    };
+    (status, e.to_string())
+}
+
+fn forbidden((status, message): (StatusCode, &'static str)) -> (StatusCode, String) {
+    (status, message.to_string())
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct LoginRequest {
+    name: String,
+    password: String,
+    #[serde(default = "default_label")]
+    label: String,
+    /// Token never expires, if absent
+    expires_in_secs: Option<i64>,
+}
+
+/// Longest lifetime of tokens with an expiry. Tokens without expiry are requested by omitting `expires_in_secs`
+const MAX_TOKEN_LIFETIME_SECS: i64 = 10 * 366 * 24 * 60 * 60;
+
+fn default_label() -> String {
+    "login".into()
+}
+
+async fn login(
+    InjectRegistered(users): InjectRegistered<UserService>,
+    Json(request): Json<LoginRequest>,
+) -> Result<Json<IssuedToken>, (StatusCode, String)> {
+    let expires = request
+        .expires_in_secs
+        .map(|secs| {
+            Some(secs)
+                .filter(|x| (1..=MAX_TOKEN_LIFETIME_SECS).contains(x))
+                .and_then(Duration::try_seconds)
+                .and_then(|x| Utc::now().checked_add_signed(x))
+                .ok_or_else(|| {
+                    (
+                        StatusCode::BAD_REQUEST,
+                        format!("expires_in_secs must be between 1 and {MAX_TOKEN_LIFETIME_SECS}"),
+                    )
+                })
+        })
+        .transpose()?;
+    users
+        .login(&request.name, &request.password, request.label, expires)
+        .await
+        .map(Json)
+        .map_err(to_http_error)
+}
+
+async fn me(CurrentUser(user): CurrentUser) -> Json<User> {
+    Json(user)
+}
+
+async fn list_tokens(
+    InjectRegistered(users): InjectRegistered<UserService>,
+    CurrentUser(user): CurrentUser,
+) -> Json<Vec<TokenInfo>> {
+    Json(users.tokens(&user.name).await)
+}
+
+async fn revoke_token(
+    InjectRegistered(users): InjectRegistered<UserService>,
+    CurrentUser(user): CurrentUser,
+    Path(id): Path<Uuid>,
+) -> Result<(), (StatusCode, String)> {
+    users
+        .revoke_token(&user.name, id)
+        .await
+        .map_err(to_http_error)
+}
+
+async fn list_users(
+    InjectRegistered(users): InjectRegistered<UserService>,
+    current: CurrentUser,
+) -> Result<Json<Vec<User>>, (StatusCode, String)> {
+    current.require(Role::Admin).map_err(forbidden)?;
+    Ok(Json(users.users().await))
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct SetUserRequest {
+    password: String,
+    role: Role,
+}
+
+async fn set_user(
+    InjectRegistered(users): InjectRegistered<UserService>,
+    OptionalUser(current): OptionalUser,
+    Path(name): Path<String>,
+    Json(SetUserRequest { password, role }): Json<SetUserRequest>,
+) -> Result<(), (StatusCode, String)> {
+    match current {
+        Some(user) => CurrentUser(user).require(Role::Admin).map_err(forbidden)?,
+        // The first user can be created without authentication. It should be an admin, so others can be added later
+        None if role == Role::Admin => {
+            return users
+                .create_first_user(User { name, role }, password)
+                .await
+                .map_err(to_http_error)
+        }
+        None => {
+            return Err((
+                StatusCode::UNAUTHORIZED,
+                "Authentication required".to_string(),
+            ))
+        }
+    }
+    users
+        .set_user(User { name, role }, password)
+        .await
+        .map_err(to_http_error)
+}
+
+async fn delete_user(
+    InjectRegistered(users): InjectRegistered<UserService>,
+    current: CurrentUser,
+    Path(name): Path<String>,
+) -> Result<(), (StatusCode, String)> {
+    current.require(Role::Admin).map_err(forbidden)?;
+    users.delete_user(&name).await.map_err(to_http_error)
+}
@@ -1,41 +1,67 @@
 use futures::{future::Abortable, stream::AbortRegistration, FutureExt};
-use minfac::ServiceCollection;
-use pilatus::device::FinalizeRecipeExecution;
-use pilatus_axum::extract::ws::{Dropper, WebSocketDropperService};
+use minfac::{Registered, ServiceCollection};
+use pilatus::{device::FinalizeRecipeExecution, GenericConfig, SystemShutdown};
+use pilatus_axum::extract::ws::{
+    CloseNotifier, CloseReason, Dropper, KeepaliveConfig, WebSocketDropperService,
+};
 use std::{
     future::pending,
     sync::{Arc, RwLock},
 };
 
 pub(super) fn register_services(c: &mut ServiceCollection) {
-    let mut finalizer = c.register_shared(|| Arc::new(WsFinalizeRecipeExecution::default()));
+    c.register_instance(pilatus::ServiceInfo::shared::<Arc<WsFinalizeRecipeExecution>>());
+    let mut finalizer = c
+        .with::<(Registered<SystemShutdown>, Registered<GenericConfig>)>()
+        .register_shared(|(shutdown, config)| {
+            let keepalive = config.get("websocket").unwrap_or_default();
+            Arc::new(WsFinalizeRecipeExecution::new(shutdown, keepalive))
+        });
     finalizer.alias(|x| x as Arc<dyn FinalizeRecipeExecution>);
     finalizer.alias(|x| x as Arc<dyn WebSocketDropperService>);
 }
 
-struct WsFinalizeRecipeExecution(RwLock<(Dropper, AbortRegistration)>);
+struct WsFinalizeRecipeExecution {
+    current: RwLock<(Dropper, AbortRegistration, CloseNotifier)>,
+    shutdown: SystemShutdown,
+    keepalive: KeepaliveConfig,
+}
 
-impl Default for WsFinalizeRecipeExecution {
-    fn default() -> Self {
-        Self(std::sync::RwLock::new(Dropper::pair()))
+impl WsFinalizeRecipeExecution {
+    fn new(shutdown: SystemShutdown, keepalive: KeepaliveConfig) -> Self {
+        Self {
+            current: RwLock::new(Dropper::pair()),
+            shutdown,
+            keepalive,
+        }
     }
 }
 
 impl WebSocketDropperService for WsFinalizeRecipeExecution {
     fn create_dropper(&self) -> Dropper {
-        let lock = self.0.read().unwrap();
+        let lock = self.current.read().unwrap();
         lock.0.clone()
     }
+
+    fn keepalive_config(&self) -> KeepaliveConfig {
+        self.keepalive
+    }
 }
 
 impl FinalizeRecipeExecution for WsFinalizeRecipeExecution {
     fn finalize_recipe_execution(&self) -> futures::future::BoxFuture<'_, ()> {
-        let reg = {
-            let mut lock = self.0.write().unwrap();
+        let (_, reg, notifier) = {
+            let mut lock = self.current.write().unwrap();
             let mut old = Dropper::pair();
             std::mem::swap(&mut old, &mut lock);
-            old.1
+            old
+        };
+        let reason = if self.shutdown.clone().now_or_never().is_some() {
+            CloseReason::ShuttingDown
+        } else {
+            CloseReason::RecipeChanged
         };
+        notifier.notify(reason);
         async {
             Abortable::new(pending::<()>(), reg).await.ok();
         }
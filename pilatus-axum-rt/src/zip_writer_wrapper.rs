@@ -5,11 +5,27 @@ use futures::{future::BoxFuture, AsyncReadExt, FutureExt};
 use pilatus::{EntryWriter, PinReader};
 use std::io;
 
-pub struct ZipWriterWrapper<W: AsyncWrite + Unpin + Send + 'static>(ZipFileWriter<W>);
+pub struct ZipWriterWrapper<W: AsyncWrite + Unpin + Send + 'static> {
+    inner: ZipFileWriter<W>,
+    stream_entries: bool,
+}
 
 impl<W: AsyncWrite + Unpin + Send + 'static> ZipWriterWrapper<W> {
     pub fn new_boxed(raw: W) -> Box<Self> {
-        Box::new(Self(ZipFileWriter::new(raw)))
+        Box::new(Self {
+            inner: ZipFileWriter::new(raw),
+            stream_entries: false,
+        })
+    }
+
+    /// Entries are compressed while they are read instead of being loaded into memory first.
+    /// Such entries use data descriptors, which the stream reader of the recipe import doesn't support.
+    /// Only use it for archives which are not imported again (e.g. file downloads)
+    pub fn new_streaming_boxed(raw: W) -> Box<Self> {
+        Box::new(Self {
+            inner: ZipFileWriter::new(raw),
+            stream_entries: true,
+        })
     }
 }
 
@@ -22,32 +38,24 @@ impl<W: AsyncWrite + Unpin + Send + 'static> EntryWriter for ZipWriterWrapper<W>
         async move {
             let entry = ZipEntryBuilder::new(path.into(), Compression::Deflate).build();
 
-            // start BadCode
+            if self.stream_entries {
+                let mut writer = self
+                    .inner
+                    .write_entry_stream(entry)
+                    .await
+                    .map_err(zip_to_io_error)?;
+                futures::io::copy(data, &mut writer).await?;
+                writer.close().await.map_err(zip_to_io_error)?;
+                return Ok(());
+            }
+
+            // async_zip can only stream-read entries without data descriptors, which the recipe import relies on
             let mut materialized = Vec::with_capacity(entry.uncompressed_size() as _);
             data.read_to_end(&mut materialized).await?;
-            self.0
+            self.inner
                 .write_entry_whole(entry, &materialized)
                 .await
                 .map_err(zip_to_io_error)?;
-
-            // end BadCode
-            /*
-            // This code would be better, but async_zip removed support in the 0.0.15 version. The following Error was raised
-            // ZipError::FeatureNotSupported("stream reading entries with data descriptors (planned to be reintroduced)")
-            // So this should be supported again soon
-
-            let mut writer = self
-                .0
-                .write_entry_stream(entry)
-                .await
-                .map_err(zip_to_io_error)?;
-            copy(
-                tokio_util::compat::TokioAsyncReadCompatExt::compat(data),
-                &mut writer,
-            )
-            .await?;
-            writer.close().await.map_err(zip_to_io_error)?;
-            */
             Ok(())
         }
         .boxed()
@@ -55,7 +63,7 @@ impl<W: AsyncWrite + Unpin + Send + 'static> EntryWriter for ZipWriterWrapper<W>
 
     fn close(self: Box<Self>) -> BoxFuture<'static, io::Result<()>> {
         async move {
-            ZipFileWriter::close(self.0)
+            ZipFileWriter::close(self.inner)
                 .await
                 .map(|_| ())
                 .map_err(zip_to_io_error)
@@ -63,3 +71,53 @@ impl<W: AsyncWrite + Unpin + Send + 'static> EntryWriter for ZipWriterWrapper<W>
         .boxed()
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use async_zip::base::read::mem::ZipFileReader;
+    use tokio_util::compat::TokioAsyncWriteCompatExt;
+
+    use super::*;
+
+    #[tokio::test]
+    async fn stream_entries_without_loading_them() -> io::Result<()> {
+        let (mut r, w) = tokio::io::duplex(64);
+        let mut writer = ZipWriterWrapper::new_streaming_boxed(w.compat_write());
+        let large = vec![7u8; 1_000_000];
+        let write = async {
+            writer
+                .insert("a/large.bin".into(), &mut futures::io::Cursor::new(&large))
+                .await?;
+            writer
+                .insert("small.txt".into(), &mut futures::io::Cursor::new(b"data"))
+                .await?;
+            writer.close().await
+        };
+        let mut buf = Vec::new();
+        let (write, read) = futures::join!(
+            write,
+            tokio::io::AsyncReadExt::read_to_end(&mut r, &mut buf)
+        );
+        write?;
+        read?;
+
+        let reader = ZipFileReader::new(buf).await.map_err(zip_to_io_error)?;
+        let names = reader
+            .file()
+            .entries()
+            .iter()
+            .map(|x| x.filename().as_str().unwrap().to_string())
+            .collect::<Vec<_>>();
+        assert_eq!(vec!["a/large.bin", "small.txt"], names);
+        let mut content = Vec::new();
+        reader
+            .reader_with_entry(0)
+            .await
+            .map_err(zip_to_io_error)?
+            .read_to_end_checked(&mut content)
+            .await
+            .map_err(zip_to_io_error)?;
+        assert_eq!(large, content);
+        Ok(())
+    }
+}
@@ -0,0 +1,152 @@
+use std::{fs::File, io::Write, path::Path};
+
+use pilatus_rt::Runtime;
+use reqwest::{header::CONTENT_TYPE, RequestBuilder, StatusCode};
+use serde_json::json;
+
+fn configure_runtime(dir: &Path) -> anyhow::Result<Runtime> {
+    let mut file = File::create(dir.join("config.json"))?;
+    file.write_all(
+        br#"{
+            "web": {
+                "socket": "0.0.0.0:0"
+            }
+        }"#,
+    )?;
+    file.flush()?;
+    Ok(Runtime::with_root(dir)
+        .register(pilatus_axum_rt::register)
+        .configure())
+}
+
+fn with_json(request: RequestBuilder, body: serde_json::Value) -> RequestBuilder {
+    request
+        .header(CONTENT_TYPE, "application/json")
+        .body(body.to_string())
+}
+
+/// Creates the user with the token of an admin, or as first user without one, and returns its bearer token
+async fn login(
+    base: &str,
+    client: &reqwest::Client,
+    admin_token: Option<&str>,
+    name: &str,
+    role: &str,
+) -> anyhow::Result<String> {
+    let mut create = client.put(format!("{base}/user/{name}"));
+    if let Some(token) = admin_token {
+        create = create.bearer_auth(token);
+    }
+    let status = with_json(create, json!({ "password": "secret", "role": role }))
+        .send()
+        .await?
+        .status();
+    anyhow::ensure!(status == StatusCode::OK, "Cannot create user: {status}");
+    let body = with_json(
+        client.post(format!("{base}/user/login")),
+        json!({ "name": name, "password": "secret" }),
+    )
+    .send()
+    .await?
+    .bytes()
+    .await?;
+    let token = serde_json::from_slice::<serde_json::Value>(&body)?;
+    Ok(token["token"]
+        .as_str()
+        .ok_or_else(|| anyhow::anyhow!("No token in {token}"))?
+        .to_string())
+}
+
+#[test]
+fn reject_scratch_recipes_during_maintenance() -> anyhow::Result<()> {
+    let dir = tempfile::tempdir()?;
+    let rt = configure_runtime(dir.path())?;
+
+    let web_stats: pilatus_axum::Stats = rt.provider.get().unwrap();
+    rt.run_until_finished(async {
+        let port = web_stats.socket_addr().await.port();
+        let base = format!("http://127.0.0.1:{port}/api");
+        let client = reqwest::Client::new();
+        let token = login(&base, &client, None, "admin", "admin").await.unwrap();
+
+        let status = with_json(
+            client
+                .put(format!("{base}/maintenance"))
+                .bearer_auth(&token),
+            json!({ "enabled": true, "reason": "Replacing the conveyor belt" }),
+        )
+        .send()
+        .await
+        .unwrap()
+        .status();
+        assert_eq!(StatusCode::OK, status);
+
+        let status = with_json(
+            client
+                .put(format!("{base}/recipe/scratch/start"))
+                .bearer_auth(&token),
+            json!({ "devices": {}, "timeout_secs": 10 }),
+        )
+        .send()
+        .await
+        .unwrap()
+        .status();
+        assert_eq!(StatusCode::CONFLICT, status);
+        let status = client
+            .put(format!("{base}/recipe/scratch/stop"))
+            .bearer_auth(&token)
+            .send()
+            .await
+            .unwrap()
+            .status();
+        assert_eq!(StatusCode::CONFLICT, status);
+    });
+    Ok(())
+}
+
+#[test]
+fn restrict_scratch_recipes_to_operators() -> anyhow::Result<()> {
+    let dir = tempfile::tempdir()?;
+    let rt = configure_runtime(dir.path())?;
+
+    let web_stats: pilatus_axum::Stats = rt.provider.get().unwrap();
+    rt.run_until_finished(async {
+        let port = web_stats.socket_addr().await.port();
+        let base = format!("http://127.0.0.1:{port}/api");
+        let client = reqwest::Client::new();
+        let admin = login(&base, &client, None, "admin", "admin").await.unwrap();
+        let viewer = login(&base, &client, Some(admin.as_str()), "viewer", "viewer")
+            .await
+            .unwrap();
+        let start = |token: Option<&str>, timeout_secs: u64| {
+            let mut request = client.put(format!("{base}/recipe/scratch/start"));
+            if let Some(token) = token {
+                request = request.bearer_auth(token);
+            }
+            with_json(
+                request,
+                json!({ "devices": {}, "timeout_secs": timeout_secs }),
+            )
+            .send()
+        };
+
+        let status = start(None, 10).await.unwrap().status();
+        assert_eq!(StatusCode::UNAUTHORIZED, status);
+        let status = start(Some(viewer.as_str()), 10).await.unwrap().status();
+        assert_eq!(StatusCode::FORBIDDEN, status);
+        let status = start(Some(admin.as_str()), 24 * 60 * 60)
+            .await
+            .unwrap()
+            .status();
+        assert_eq!(StatusCode::BAD_REQUEST, status);
+        let status = client
+            .put(format!("{base}/recipe/scratch/stop"))
+            .bearer_auth(&viewer)
+            .send()
+            .await
+            .unwrap()
+            .status();
+        assert_eq!(StatusCode::FORBIDDEN, status);
+    });
+    Ok(())
+}
@@ -1,5 +1,5 @@
 use std::collections::HashMap;
-use std::{fs::File, io::Write, sync::Arc};
+use std::{fs::File, io::Write, path::Path, sync::Arc};
 
 use futures::{sink::SinkExt, StreamExt};
 use minfac::{Registered, ServiceCollection};
@@ -13,11 +13,8 @@ use reqwest::StatusCode;
 use tokio_tungstenite::connect_async;
 use tokio_tungstenite::tungstenite::Message;
 
-#[test]
-fn upload_zip() -> anyhow::Result<()> {
-    let dir = tempfile::tempdir()?;
-
-    let mut file = File::create(dir.path().join("config.json"))?;
+fn configure_runtime(dir: &Path) -> anyhow::Result<Runtime> {
+    let mut file = File::create(dir.join("config.json"))?;
     file.write_all(
         br#"{
             "web": {
@@ -57,10 +54,16 @@ fn upload_zip() -> anyhow::Result<()> {
             .register_device("testdevice", validator, device);
     }
 
-    let rt = Runtime::with_root(dir.path())
+    Ok(Runtime::with_root(dir)
         .register(pilatus_axum_rt::register)
         .register(register_test_services)
-        .configure();
+        .configure())
+}
+
+#[test]
+fn upload_zip() -> anyhow::Result<()> {
+    let dir = tempfile::tempdir()?;
+    let rt = configure_runtime(dir.path())?;
 
     let web_stats: pilatus_axum::Stats = rt.provider.get().unwrap();
     let recipe_service = rt.provider.get().unwrap();
@@ -69,7 +72,9 @@ fn upload_zip() -> anyhow::Result<()> {
         assert_ne!(port, 80);
         let base = format!("http://127.0.0.1:{port}/api");
         let client = reqwest::Client::new();
-        let (clone_id, data) = generate_zip(&base, &client, recipe_service).await.unwrap();
+        let (clone_id, data) = generate_zip(&base, &client, recipe_service, None)
+            .await
+            .unwrap();
 
         let (mut sock, _response) =
             connect_async(format!("ws://127.0.0.1:{port}/api/recipe/import"))
@@ -103,10 +108,136 @@ fn upload_zip() -> anyhow::Result<()> {
     Ok(())
 }
 
+#[test]
+fn import_resumable_upload() -> anyhow::Result<()> {
+    let dir = tempfile::tempdir()?;
+    let rt = configure_runtime(dir.path())?;
+
+    let web_stats: pilatus_axum::Stats = rt.provider.get().unwrap();
+    let recipe_service = rt.provider.get().unwrap();
+    rt.run_until_finished(async {
+        let port = web_stats.socket_addr().await.port();
+        let base = format!("http://127.0.0.1:{port}/api");
+        let client = reqwest::Client::new();
+        let (clone_id, data) = generate_zip(&base, &client, recipe_service, Some("secret"))
+            .await
+            .unwrap();
+
+        let created = client
+            .post(format!("{base}/recipe/import/upload?size={}", data.len()))
+            // Browsers can't set it on the import WebSocket, so encrypted uploads keep it until they are imported
+            .header("x-recipe-password", "secret")
+            .send()
+            .await
+            .unwrap()
+            .bytes()
+            .await
+            .unwrap();
+        let created = serde_json::from_slice::<serde_json::Value>(&created).unwrap();
+        let upload_id = created["id"].as_str().unwrap().to_string();
+        let upload_url = format!("{base}/recipe/import/upload/{upload_id}");
+        let (first, second) = data.split_at(data.len() / 2);
+        for (start, chunk) in [(0, first), (first.len(), second)] {
+            let response = client
+                .post(&upload_url)
+                .header(
+                    "Content-Range",
+                    format!("bytes {start}-{}/{}", start + chunk.len() - 1, data.len()),
+                )
+                .body(chunk.to_vec())
+                .send()
+                .await
+                .unwrap();
+            assert_eq!(StatusCode::OK, response.status());
+        }
+
+        let (mut sock, _response) = connect_async(format!(
+            "ws://127.0.0.1:{port}/api/recipe/import?upload_id={upload_id}"
+        ))
+        .await
+        .unwrap();
+        let msg = loop {
+            match sock.next().await {
+                Some(Ok(Message::Text(msg))) if msg.starts_with("{\"Progress\"") => {}
+                Some(Ok(Message::Text(msg))) => break msg,
+                answer => panic!("Expected text response {answer:?}"),
+            }
+        };
+        assert_eq!(msg, "\"Success\"");
+
+        let (_, all) = get_current(&base, &client).await.unwrap();
+        assert!(all.contains(&clone_id));
+        let status = client.get(&upload_url).send().await.unwrap().status();
+        assert_eq!(
+            StatusCode::NOT_FOUND,
+            status,
+            "Imported uploads are removed"
+        );
+    });
+    Ok(())
+}
+
+#[test]
+fn import_encrypted_archive_with_password_message() -> anyhow::Result<()> {
+    let dir = tempfile::tempdir()?;
+    let rt = configure_runtime(dir.path())?;
+
+    let web_stats: pilatus_axum::Stats = rt.provider.get().unwrap();
+    let recipe_service = rt.provider.get().unwrap();
+    rt.run_until_finished(async {
+        let port = web_stats.socket_addr().await.port();
+        let base = format!("http://127.0.0.1:{port}/api");
+        let client = reqwest::Client::new();
+        let (clone_id, data) = generate_zip(&base, &client, recipe_service, Some("secret"))
+            .await
+            .unwrap();
+
+        let import = |password: &'static str| {
+            let data = data.clone();
+            async move {
+                let (mut sock, _response) =
+                    connect_async(format!("ws://127.0.0.1:{port}/api/recipe/import"))
+                        .await
+                        .unwrap();
+                sock.send(Message::Text(format!("{{\"password\":\"{password}\"}}")))
+                    .await
+                    .unwrap();
+                sock.send(Message::Binary((data.len() as u64).to_le_bytes().to_vec()))
+                    .await
+                    .unwrap();
+                // The server stops reading once decryption fails
+                for chunk in data.chunks(1024) {
+                    if sock.send(Message::Binary(chunk.to_vec())).await.is_err() {
+                        break;
+                    }
+                }
+                loop {
+                    match sock.next().await {
+                        Some(Ok(Message::Text(msg))) if msg.starts_with("{\"Progress\"") => {}
+                        Some(Ok(Message::Text(msg))) => break msg,
+                        answer => panic!("Expected text response {answer:?}"),
+                    }
+                }
+            }
+        };
+
+        let msg = import("wrong").await;
+        assert!(msg.starts_with("{\"Error\""), "{msg}");
+        let (_, all) = get_current(&base, &client).await.unwrap();
+        assert!(!all.contains(&clone_id));
+
+        assert_eq!(import("secret").await, "\"Success\"");
+        let (_, all) = get_current(&base, &client).await.unwrap();
+        assert!(all.contains(&clone_id));
+    });
+    Ok(())
+}
+
 async fn generate_zip(
     base: &str,
     client: &reqwest::Client,
     s: Arc<RecipeServiceFassade>,
+    password: Option<&str>,
 ) -> anyhow::Result<(RecipeId, Vec<u8>)> {
     let (active_id, _) = get_current(base, client).await?;
     let clone_response_body = client
@@ -132,13 +263,11 @@ async fn generate_zip(
     s.add_device_to_recipe(clone_id.clone(), DeviceConfig::mock(42))
         .await?;
 
-    let export_response_body = client
-        .get(format!("{base}/recipe/{}/export", clone_id))
-        .send()
-        .await?
-        .bytes()
-        .await?
-        .to_vec();
+    let mut export = client.get(format!("{base}/recipe/{}/export", clone_id));
+    if let Some(password) = password {
+        export = export.header("x-recipe-password", password);
+    }
+    let export_response_body = export.send().await?.bytes().await?.to_vec();
 
     assert!(!export_response_body.is_empty());
 
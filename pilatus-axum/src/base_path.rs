@@ -0,0 +1,224 @@
+use std::{convert::Infallible, sync::Arc};
+
+use async_trait::async_trait;
+use axum::{
+    extract::{Request, State},
+    http::{request::Parts, HeaderMap, Uri},
+    middleware::Next,
+    Router,
+};
+use tower::Layer;
+
+use super::{extract::FromRequestParts, Response};
+
+pub const FORWARDED_PREFIX_HEADER: &str = "x-forwarded-prefix";
+pub const FORWARDED_PROTO_HEADER: &str = "x-forwarded-proto";
+pub const FORWARDED_HOST_HEADER: &str = "x-forwarded-host";
+
+/// Path prefix under which clients reach pilatus, e.g. `/line-3` behind a reverse proxy serving `https://factory/line-3/api/...`
+///
+/// Use it as extractor to generate links, which are valid for the client:
+/// ```ignore
+/// async fn handler(base: BasePath) -> String {
+///     base.link("/api/recipe")
+/// }
+/// ```
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct BasePath {
+    prefix: Arc<str>,
+    /// `scheme://host` of the client request, if a proxy provided `X-Forwarded-Host`
+    origin: Option<Arc<str>>,
+    /// Clients could otherwise redirect generated links by sending the headers themselves
+    trust_forwarded_headers: bool,
+}
+
+impl BasePath {
+    /// Accepts `""`, `"/"`, `"prefix"` or `"/prefix/"`
+    pub fn new(prefix: &str) -> Self {
+        let trimmed = prefix.trim().trim_matches('/');
+        Self {
+            prefix: if trimmed.is_empty() {
+                "".into()
+            } else {
+                format!("/{trimmed}").into()
+            },
+            origin: None,
+            trust_forwarded_headers: false,
+        }
+    }
+
+    /// Lets `X-Forwarded-Prefix`, `X-Forwarded-Host` and `X-Forwarded-Proto` override the configured prefix.
+    /// Only enable it, if pilatus is exclusively reachable through a reverse proxy which sets these headers
+    pub fn with_trusted_forwarded_headers(self, trust_forwarded_headers: bool) -> Self {
+        Self {
+            trust_forwarded_headers,
+            ..self
+        }
+    }
+
+    /// Empty if pilatus is served at the root
+    pub fn as_str(&self) -> &str {
+        &self.prefix
+    }
+
+    pub fn origin(&self) -> Option<&str> {
+        self.origin.as_deref()
+    }
+
+    /// Prefixes paths like `/api/recipe`. Relative paths and urls are returned unchanged
+    pub fn link(&self, path: &str) -> String {
+        if path.starts_with('/') && !path.starts_with("//") {
+            format!("{}{}", self.prefix, path)
+        } else {
+            path.to_string()
+        }
+    }
+
+    /// Like `link`, but includes scheme and host if the request passed a reverse proxy
+    pub fn absolute_link(&self, path: &str) -> String {
+        match &self.origin {
+            Some(origin) if path.starts_with('/') => format!("{origin}{}", self.link(path)),
+            _ => self.link(path),
+        }
+    }
+
+    /// Routes requests with and without the configured prefix, so it doesn't matter whether the proxy strips it.
+    /// Handlers receive the prefix of the client as `BasePath` extension, which respects `X-Forwarded-Prefix` if trusted
+    pub fn apply(self, router: Router) -> Router {
+        Router::new().fallback_service(
+            axum::middleware::from_fn_with_state(self, resolve_base_path).layer(router),
+        )
+    }
+
+    fn strip(&self, uri: &Uri) -> Option<Uri> {
+        if self.prefix.is_empty() {
+            return None;
+        }
+        let rest = uri.path().strip_prefix(&*self.prefix)?;
+        let path = match rest {
+            "" => "/",
+            x if x.starts_with('/') => x,
+            _ => return None,
+        };
+        let path_and_query = match uri.query() {
+            Some(q) => format!("{path}?{q}"),
+            None => path.to_string(),
+        };
+        let mut parts = uri.clone().into_parts();
+        parts.path_and_query = Some(path_and_query.parse().ok()?);
+        Uri::from_parts(parts).ok()
+    }
+
+    fn for_request(&self, headers: &HeaderMap) -> Self {
+        if !self.trust_forwarded_headers {
+            return self.clone();
+        }
+        let header = |name| headers.get(name).and_then(|x| x.to_str().ok());
+        let mut this = header(FORWARDED_PREFIX_HEADER)
+            .map(|x| BasePath::new(x).with_trusted_forwarded_headers(true))
+            .unwrap_or_else(|| self.clone());
+        this.origin = header(FORWARDED_HOST_HEADER).map(|host| {
+            // Proxies in a chain append their values
+            let host = host.split(',').next().unwrap_or(host).trim();
+            let proto = header(FORWARDED_PROTO_HEADER)
+                .and_then(|x| x.split(',').next())
+                .map_or("http", str::trim);
+            format!("{proto}://{host}").into()
+        });
+        this
+    }
+}
+
+async fn resolve_base_path(
+    State(configured): State<BasePath>,
+    mut request: Request,
+    next: Next,
+) -> Response {
+    if let Some(stripped) = configured.strip(request.uri()) {
+        *request.uri_mut() = stripped;
+    }
+    let base = configured.for_request(request.headers());
+    request.extensions_mut().insert(base);
+    next.run(request).await
+}
+
+#[async_trait]
+impl<S: Send + Sync> FromRequestParts<S> for BasePath {
+    type Rejection = Infallible;
+
+    async fn from_request_parts(req: &mut Parts, _: &S) -> Result<Self, Self::Rejection> {
+        Ok(req
+            .extensions
+            .get::<BasePath>()
+            .cloned()
+            .unwrap_or_else(|| {
+                // Served without `BasePath::apply`, e.g. in tests
+                BasePath::default().for_request(&req.headers)
+            }))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use axum::{body::Body, routing::get};
+    use tower::ServiceExt;
+
+    use super::*;
+
+    async fn link(base: BasePath) -> String {
+        base.absolute_link("/api/recipe")
+    }
+
+    async fn call(router: Router, request: Request) -> String {
+        let response = router.oneshot(request).await.unwrap();
+        let body = axum::body::to_bytes(response.into_body(), 1024)
+            .await
+            .unwrap();
+        String::from_utf8(body.to_vec()).unwrap()
+    }
+
+    #[test]
+    fn normalize_prefix() {
+        assert_eq!("", BasePath::new("/").as_str());
+        assert_eq!("/line-3", BasePath::new("line-3/").as_str());
+        assert_eq!("/line-3/api/x", BasePath::new("/line-3").link("/api/x"));
+        assert_eq!("http://x/y", BasePath::new("/line-3").link("http://x/y"));
+    }
+
+    fn forwarded() -> Request {
+        Request::get("/api/link")
+            .header(FORWARDED_PREFIX_HEADER, "/proxy")
+            .header(FORWARDED_HOST_HEADER, "factory")
+            .header(FORWARDED_PROTO_HEADER, "https")
+            .body(Body::empty())
+            .unwrap()
+    }
+
+    #[tokio::test]
+    async fn route_with_and_without_configured_prefix() {
+        let router = BasePath::new("/line-3")
+            .with_trusted_forwarded_headers(true)
+            .apply(Router::new().route("/api/link", get(link)));
+        let request = |uri: &'static str| Request::get(uri).body(Body::empty()).unwrap();
+
+        assert_eq!(
+            "/line-3/api/recipe",
+            call(router.clone(), request("/line-3/api/link?x=1")).await
+        );
+        assert_eq!(
+            "/line-3/api/recipe",
+            call(router.clone(), request("/api/link")).await
+        );
+
+        assert_eq!(
+            "https://factory/proxy/api/recipe",
+            call(router, forwarded()).await
+        );
+    }
+
+    #[tokio::test]
+    async fn ignore_forwarded_headers_unless_trusted() {
+        let router = BasePath::new("/line-3").apply(Router::new().route("/api/link", get(link)));
+        assert_eq!("/line-3/api/recipe", call(router, forwarded()).await);
+    }
+}
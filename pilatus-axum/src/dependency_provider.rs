@@ -14,7 +14,8 @@ use minfac::{Registered, Resolvable};
 
 use super::{
     extract::{
-        ws::WebSocketUpgrade, Abort, Body, Inject, InjectAll, InjectRegistered, Json, Path, Query,
+        ws::WebSocketUpgrade, Abort, Body, CurrentUser, Inject, InjectAll, InjectRegistered, Json,
+        OptionalUser, Path, Query, WebActorSystem,
     },
     ws::WebSocketDropperService,
     AbortServiceInterface,
@@ -41,6 +42,21 @@ impl DependencyProvider for Abort {
 }
 impl RecursiveDependencyProvider for Abort {}
 
+impl DependencyProvider for CurrentUser {
+    type Dep = Registered<pilatus::UserService>;
+}
+impl RecursiveDependencyProvider for CurrentUser {}
+
+impl DependencyProvider for OptionalUser {
+    type Dep = Registered<pilatus::UserService>;
+}
+impl RecursiveDependencyProvider for OptionalUser {}
+
+impl DependencyProvider for WebActorSystem {
+    type Dep = Registered<pilatus::device::ActorSystem>;
+}
+impl RecursiveDependencyProvider for WebActorSystem {}
+
 impl DependencyProvider for WebSocketUpgrade {
     type Dep = Registered<Arc<dyn WebSocketDropperService>>;
 }
@@ -4,14 +4,15 @@
 /// The new design still allows all previous workflows by simply adding .take_while() and therefore volunatarely close the stream.
 /// Furthermore, the new design allows errors to contain images, for situations, where e.g.  
 use std::{
+    collections::VecDeque,
     fmt::{self, Debug, Formatter},
     marker::PhantomData,
     num::NonZeroU32,
-    sync::Arc,
+    sync::{Arc, Mutex, OnceLock},
 };
 
 use anyhow::anyhow;
-use bytes::BufMut;
+use bytes::{BufMut, Bytes, BytesMut};
 use futures::{
     channel::{mpsc, oneshot},
     future::Either,
@@ -20,40 +21,170 @@ use futures::{
 };
 use jpeg_encoder::{ColorType, Encoder};
 use pilatus::device::{ActorError, ActorMessage, ActorSystem, DeviceId};
+pub use pilatus::StreamingImageFormat;
+use pilatus::{ImageChunk, ImageFrameCode, RawPixelKind};
 use pilatus_engineering::image::{
-    BroadcastImage, DynamicImage, ImageWithMeta, LocalizableBroadcastImage, LumaImage, RgbImage,
-    StreamImageError, SubscribeImageMessage, SubscribeImageOk, SubscribeLocalizableImageMessage,
-    SubscribeLocalizableImageOk,
+    BroadcastImage, DynamicImage, FrameId, ImageKey, ImageMeta, ImageWithMeta,
+    LocalizableBroadcastImage, LumaImage, RgbImage, StreamImageError, SubscribeImageMessage,
+    SubscribeImageOk, SubscribeLocalizableImageMessage, SubscribeLocalizableImageOk,
 };
 use serde::Serialize;
 use tracing::{debug, trace};
 
 use crate::{
-    extract::ws::{Message, WebSocket, WebSocketUpgrade},
+    extract::ws::{
+        CloseReason, CloseSignal, Keepalive, KeepaliveSendError, Message, WebSocket,
+        WebSocketUpgrade,
+    },
     http::StatusCode,
     IntoResponse,
 };
 pub trait StreamableImage: Sized {
-    fn encode(self) -> anyhow::Result<Vec<u8>>;
+    /// Appends the encoded frame to `buf`. Websocket streams reuse `buf` for all frames of a connection
+    fn encode_into(self, buf: &mut BytesMut) -> anyhow::Result<()>;
+
+    /// Frames with a key are encoded once and shared between all connections of the same device which request the same key
+    fn cache_key(&self) -> Option<EncodeCacheKey> {
+        None
+    }
+
+    fn encode(self) -> anyhow::Result<Vec<u8>> {
+        let mut buf = BytesMut::new();
+        self.encode_into(&mut buf)?;
+        Ok(buf.into())
+    }
 }
 
 impl StreamableImage for Arc<LumaImage> {
-    fn encode(self) -> anyhow::Result<Vec<u8>> {
+    fn encode_into(self, buf: &mut BytesMut) -> anyhow::Result<()> {
         let dims = self.dimensions();
-        encode_legacy(self.buffer(), ColorType::Luma, dims, |_| Ok(()))
+        encode_legacy(buf, self.buffer(), ColorType::Luma, dims, |_| Ok(()))
     }
 }
 
-const OK_CODE: u8 = 0 << 4;
-const MISSED_ITEM_CODE: u8 = 1 << 4;
-const PROCESSING_CODE: u8 = 2 << 4;
-const ACTOR_ERROR_CODE: u8 = 3 << 4;
+/// Buffer which is reused for all frames of a websocket connection.
+/// Encoders write into an allocation, which is sized by the largest frame seen so far, so they don't grow it while writing.
+/// Frames are split off as [`Bytes`] without copying. Once a frame is dropped, e.g. after it was copied into its
+/// websocket message, the next frame reclaims its allocation. Frames kept in the encode cache allocate a new one instead
+#[derive(Default)]
+pub struct FrameBuffer {
+    buf: BytesMut,
+    capacity_hint: usize,
+    device_id: Option<DeviceId>,
+}
+
+impl FrameBuffer {
+    /// Frames are shared with other connections to the same device only
+    pub fn for_device(device_id: Option<DeviceId>) -> Self {
+        Self {
+            device_id,
+            ..Default::default()
+        }
+    }
+
+    pub fn encode(&mut self, image: impl StreamableImage) -> anyhow::Result<Bytes> {
+        match image.cache_key() {
+            Some(key) => EncodeCache::global()
+                .get_or_encode((self.device_id, key), || self.encode_uncached(image)),
+            None => self.encode_uncached(image),
+        }
+    }
+
+    fn encode_uncached(&mut self, image: impl StreamableImage) -> anyhow::Result<Bytes> {
+        // Reclaims the space of the previous frame, if it was dropped already
+        self.buf.reserve(self.capacity_hint);
+        let result = image.encode_into(&mut self.buf);
+        let frame = self.buf.split().freeze();
+        result?;
+        self.capacity_hint = self.capacity_hint.max(frame.len());
+        Ok(frame)
+    }
+}
 
-#[derive(Default, serde::Deserialize, Clone, Copy)]
-pub enum StreamingImageFormat {
-    #[default]
-    Jpeg,
-    Raw,
+/// Identifies an encoded frame. Subscribers of the same device with the same selection receive identical payloads for a frame.
+/// The hash isn't sufficient, because it identifies the producer chain, which emits many frames
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+pub struct EncodeCacheKey {
+    frame_id: FrameId,
+    selection: StreamingImageSelection,
+    quality: u8,
+}
+
+impl EncodeCacheKey {
+    fn new(meta: &ImageMeta, selection: StreamingImageSelection) -> Self {
+        Self {
+            frame_id: meta.frame_id,
+            selection,
+            quality: JPEG_QUALITY,
+        }
+    }
+}
+
+type CachedFrame = Arc<OnceLock<Result<Bytes, String>>>;
+
+/// Encoded frames shared by all websocket connections of the process. The first connection encodes a frame,
+/// concurrent connections with the same key wait for its result instead of encoding it again
+#[derive(Default)]
+struct EncodeCache {
+    entries: Mutex<VecDeque<((Option<DeviceId>, EncodeCacheKey), CachedFrame)>>,
+}
+
+impl EncodeCache {
+    /// Enough for a few producers with different selections. Older frames aren't requested anymore
+    const CAPACITY: usize = 16;
+
+    fn global() -> &'static EncodeCache {
+        static CACHE: OnceLock<EncodeCache> = OnceLock::new();
+        CACHE.get_or_init(EncodeCache::default)
+    }
+
+    fn get_or_encode(
+        &self,
+        key: (Option<DeviceId>, EncodeCacheKey),
+        encode: impl FnOnce() -> anyhow::Result<Bytes>,
+    ) -> anyhow::Result<Bytes> {
+        let entry = {
+            let mut entries = self.entries.lock().expect("Never poisoned");
+            match entries.iter().find(|(k, _)| k == &key) {
+                Some((_, entry)) => entry.clone(),
+                None => {
+                    if entries.len() >= Self::CAPACITY {
+                        entries.pop_front();
+                    }
+                    let entry = CachedFrame::default();
+                    entries.push_back((key, entry.clone()));
+                    entry
+                }
+            }
+        };
+        entry
+            .get_or_init(|| encode().map_err(|e| format!("{e:#}")))
+            .clone()
+            .map_err(|e| anyhow!("{e}"))
+    }
+}
+
+const JPEG_QUALITY: u8 = 80;
+const OK_CODE: u8 = ImageFrameCode::Ok.header_byte();
+const MISSED_ITEM_CODE: u8 = ImageFrameCode::MissedItem.header_byte();
+const PROCESSING_CODE: u8 = ImageFrameCode::Processing.header_byte();
+const ACTOR_ERROR_CODE: u8 = ImageFrameCode::ActorError.header_byte();
+const PRODUCER_RESTARTED_CODE: u8 = ImageFrameCode::ProducerRestarted.header_byte();
+
+/// Format of the main image and the additional images, which are appended in the requested order
+#[derive(Default, Clone, Debug, PartialEq, Eq, Hash)]
+pub struct StreamingImageSelection {
+    pub format: StreamingImageFormat,
+    pub additional: Vec<(ImageKey, StreamingImageFormat)>,
+}
+
+impl From<StreamingImageFormat> for StreamingImageSelection {
+    fn from(format: StreamingImageFormat) -> Self {
+        Self {
+            format,
+            additional: Vec::new(),
+        }
+    }
 }
 
 /// Protocol Spec
@@ -70,25 +201,70 @@ pub enum StreamingImageFormat {
 ///                   |    u32::LE_bytes of ImageSize  |
 ///                   |         encoded Image         |
 ///
+/// ImageSize is 0 if the producer didn't provide a requested image
 impl StreamableImage
     for (
         Result<ImageWithMeta<DynamicImage>, StreamImageError<DynamicImage>>,
         StreamingImageFormat,
     )
 {
-    fn encode(self) -> anyhow::Result<Vec<u8>> {
+    fn encode_into(self, buf: &mut BytesMut) -> anyhow::Result<()> {
+        (self.0, StreamingImageSelection::from(self.1)).encode_into(buf)
+    }
+
+    fn cache_key(&self) -> Option<EncodeCacheKey> {
+        Some(EncodeCacheKey::new(
+            &self.0.as_ref().ok()?.meta,
+            self.1.into(),
+        ))
+    }
+}
+
+impl StreamableImage
+    for (
+        Result<ImageWithMeta<DynamicImage>, StreamImageError<DynamicImage>>,
+        StreamingImageSelection,
+    )
+{
+    fn cache_key(&self) -> Option<EncodeCacheKey> {
+        Some(EncodeCacheKey::new(
+            &self.0.as_ref().ok()?.meta,
+            self.1.clone(),
+        ))
+    }
+
+    fn encode_into(self, buf: &mut BytesMut) -> anyhow::Result<()> {
+        let selection = self.1;
         match self.0 {
-            Ok(x) => self.1.encode_dynamic_image(OK_CODE, x.image, x.meta),
+            Ok(x) => {
+                encode_dynamic_image(buf, selection.format, OK_CODE, &x.image, &x.meta)?;
+                for (key, format) in selection.additional.iter() {
+                    match x.by_name(key) {
+                        Some(image) => append_dynamic_image(buf, *format, image)?,
+                        None => buf.put_u32_le(0),
+                    };
+                }
+                Ok(())
+            }
             Err(e) => match e {
                 StreamImageError::MissedItems(_) => {
-                    encode_meta(vec![MISSED_ITEM_CODE, 0, 0, 0], |_| Ok(()))
-                }
-                StreamImageError::ProcessingError { image, error } => {
-                    self.1
-                        .encode_dynamic_image(PROCESSING_CODE, image, error.to_string())
+                    buf.put_slice(&[MISSED_ITEM_CODE, 0, 0, 0]);
+                    encode_meta(buf, |_| Ok(()))
                 }
+                StreamImageError::ProcessingError { image, error } => encode_dynamic_image(
+                    buf,
+                    selection.format,
+                    PROCESSING_CODE,
+                    &image,
+                    error.to_string(),
+                ),
                 StreamImageError::ActorError(_) => {
-                    encode_meta(vec![ACTOR_ERROR_CODE, 0, 0, 0], |_| Ok(()))
+                    buf.put_slice(&[ACTOR_ERROR_CODE, 0, 0, 0]);
+                    encode_meta(buf, |_| Ok(()))
+                }
+                StreamImageError::ProducerRestarted { new_hash } => {
+                    buf.put_slice(&[PRODUCER_RESTARTED_CODE, 0, 0, 0]);
+                    encode_meta(buf, |b| write_json(b, &new_hash))
                 }
                 _ => Err(anyhow::anyhow!("Unknown error: {e:?}")),
             },
@@ -96,46 +272,41 @@ impl StreamableImage
     }
 }
 
-impl StreamingImageFormat {
-    fn encode_dynamic_image<T: Serialize>(
-        self,
-        code: u8,
-        image: DynamicImage,
-        meta: T,
-    ) -> anyhow::Result<Vec<u8>> {
-        match self {
-            StreamingImageFormat::Jpeg => encode_dynamic_jpeg_image(code, image, meta),
-            StreamingImageFormat::Raw => encode_dynamic_raw_image(code, image, meta),
-        }
+fn encode_dynamic_image<T: Serialize>(
+    buf: &mut BytesMut,
+    format: StreamingImageFormat,
+    code: u8,
+    image: &DynamicImage,
+    meta: T,
+) -> anyhow::Result<()> {
+    let dims = image.dimensions();
+    buf.reserve(dims.0.get() as usize * dims.1.get() as usize / 2);
+    buf.put_slice(&[code, 0, 0, 0]);
+    encode_meta(buf, |b| write_json(b, &meta))?;
+    append_dynamic_image(buf, format, image)
+}
+
+fn append_dynamic_image(
+    buf: &mut BytesMut,
+    format: StreamingImageFormat,
+    image: &DynamicImage,
+) -> anyhow::Result<()> {
+    match format {
+        StreamingImageFormat::Jpeg => append_dynamic_jpeg_image(buf, image),
+        StreamingImageFormat::Raw => append_dynamic_raw_image(buf, image),
     }
 }
 
-fn prepare_dynamic_image_buf<T: Serialize>(
-    flag: u8,
-    meta: T,
-    capacity: usize,
-) -> anyhow::Result<Vec<u8>> {
-    let meta_writer = move |b: &mut Vec<u8>| serde_json::to_writer(b, &meta).map_err(Into::into);
-    let mut buf = Vec::with_capacity(capacity);
-    buf.extend_from_slice(&[flag, 0, 0, 0]);
-    encode_meta(buf, meta_writer)
+fn write_json<T: Serialize>(buf: &mut BytesMut, value: &T) -> anyhow::Result<()> {
+    serde_json::to_writer((&mut *buf).writer(), value).map_err(Into::into)
 }
 
-fn encode_dynamic_raw_image<T: Serialize>(
-    flag: u8,
-    image: DynamicImage,
-    meta: T,
-) -> anyhow::Result<Vec<u8>> {
+fn append_dynamic_raw_image(buf: &mut BytesMut, image: &DynamicImage) -> anyhow::Result<()> {
     let dims = image.dimensions();
-    let buf = prepare_dynamic_image_buf(
-        flag,
-        meta,
-        dims.0.get() as usize * dims.1.get() as usize / 2,
-    )?;
     match image {
-        DynamicImage::Luma8(i) => encode_raw(buf, i.buffer(), DataType::U8, 1, dims),
+        DynamicImage::Luma8(i) => encode_raw(buf, i.buffer(), RawPixelKind::U8, 1, dims),
         DynamicImage::Luma16(i) => {
-            encode_raw(buf, bytes_from_u16(i.buffer())?, DataType::U16, 1, dims)
+            encode_raw(buf, bytes_from_u16(i.buffer())?, RawPixelKind::U16, 1, dims)
         }
         _ => Err(anyhow!("Unsupported image format: {:?}", image)),
     }
@@ -151,17 +322,8 @@ fn bytes_from_u16(from: &[u16]) -> anyhow::Result<&[u8]> {
     Ok(unsafe { std::slice::from_raw_parts(ptr, len) })
 }
 
-fn encode_dynamic_jpeg_image<T: Serialize>(
-    flag: u8,
-    image: DynamicImage,
-    meta: T,
-) -> anyhow::Result<Vec<u8>> {
+fn append_dynamic_jpeg_image(buf: &mut BytesMut, image: &DynamicImage) -> anyhow::Result<()> {
     let dims = image.dimensions();
-    let buf = prepare_dynamic_image_buf(
-        flag,
-        meta,
-        dims.0.get() as usize * dims.1.get() as usize / 2,
-    )?;
     match image {
         DynamicImage::Luma8(i) => encode_jpeg(buf, i.buffer(), ColorType::Luma, dims),
         DynamicImage::Luma16(i) => encode_jpeg(
@@ -178,10 +340,10 @@ fn encode_dynamic_jpeg_image<T: Serialize>(
 }
 
 impl<T: Serialize> StreamableImage for (Arc<LumaImage>, T) {
-    fn encode(self) -> anyhow::Result<Vec<u8>> {
+    fn encode_into(self, buf: &mut BytesMut) -> anyhow::Result<()> {
         let dims = self.0.dimensions();
-        encode_legacy(self.0.buffer(), ColorType::Luma, dims, |b| {
-            serde_json::to_writer(b, &self.1).map_err(Into::into)
+        encode_legacy(buf, self.0.buffer(), ColorType::Luma, dims, |b| {
+            write_json(b, &self.1)
         })
     }
 }
@@ -206,71 +368,67 @@ impl<T: Debug> Debug for RgbImageWithMetadata<T> {
 }
 
 impl<T: Serialize> StreamableImage for RgbImageWithMetadata<T> {
-    fn encode(self) -> anyhow::Result<Vec<u8>> {
+    fn encode_into(self, buf: &mut BytesMut) -> anyhow::Result<()> {
         let dims = self.0.size();
         let packed = self.0.into_packed();
-        encode_legacy(packed.buffer(), ColorType::Rgb, dims, |b| {
-            serde_json::to_writer(b, &self.1).map_err(Into::into)
+        encode_legacy(buf, packed.buffer(), ColorType::Rgb, dims, |b| {
+            write_json(b, &self.1)
         })
     }
 }
 
 fn encode_legacy(
+    buf: &mut BytesMut,
     image: &[u8],
     color: ColorType,
     (width, height): (NonZeroU32, NonZeroU32),
-    meta: impl FnOnce(&mut Vec<u8>) -> anyhow::Result<()>,
-) -> anyhow::Result<Vec<u8>> {
-    let target = Vec::with_capacity(width.get() as usize * height.get() as usize);
-    let (width, height) = (width, height);
-    let mut buf = encode_meta(target, meta)?;
-    let encoder = Encoder::new(&mut buf, 80);
+    meta: impl FnOnce(&mut BytesMut) -> anyhow::Result<()>,
+) -> anyhow::Result<()> {
+    buf.reserve(width.get() as usize * height.get() as usize);
+    encode_meta(buf, meta)?;
+    let encoder = Encoder::new((&mut *buf).writer(), JPEG_QUALITY);
     let t = std::time::Instant::now();
     encoder.encode(image, width.get() as u16, height.get() as u16, color)?;
     trace!("encoding time: {}ms", t.elapsed().as_millis());
-    Ok(buf)
+    Ok(())
 }
 
+/// The encoder writes directly behind the size prefix, which is patched afterwards
 fn encode_jpeg(
-    mut buf: Vec<u8>,
+    buf: &mut BytesMut,
     image: &[u8],
     color: ColorType,
     (width, height): (NonZeroU32, NonZeroU32),
-) -> anyhow::Result<Vec<u8>> {
-    buf.extend_from_slice(&[0, 0, 0, 0]);
+) -> anyhow::Result<()> {
+    buf.put_u32_le(0);
     let offset = buf.len();
-    let encoder = Encoder::new(&mut buf, 80);
+    let encoder = Encoder::new((&mut *buf).writer(), JPEG_QUALITY);
     let t = std::time::Instant::now();
     encoder.encode(image, width.get() as u16, height.get() as u16, color)?;
     trace!("encoding time: {}ms", t.elapsed().as_millis());
     let size = (buf.len() - offset) as u32;
     buf[offset - 4..offset].copy_from_slice(&size.to_le_bytes());
-    Ok(buf)
-}
-
-#[repr(u8)]
-enum DataType {
-    U8,
-    U16,
+    Ok(())
 }
 
 fn encode_raw(
-    mut buf: Vec<u8>,
+    buf: &mut BytesMut,
     image: &[u8],
-    pixel_kind: DataType,
+    pixel_kind: RawPixelKind,
     channels: u16,
     (width, height): (NonZeroU32, NonZeroU32),
-) -> anyhow::Result<Vec<u8>> {
+) -> anyhow::Result<()> {
     // https://stackoverflow.com/questions/45213511/formula-for-memory-alignment
     let unaligned_pixel_start = buf.len() + 4;
     let alignment_bytes = (((unaligned_pixel_start + 7) & !7) - unaligned_pixel_start) as u32;
 
     const HEADER_BYTE_SIZE: u32 = 8;
-    buf.extend_from_slice(&(image.len() as u32 + HEADER_BYTE_SIZE + alignment_bytes).to_le_bytes());
+    buf.reserve(image.len() + (HEADER_BYTE_SIZE + alignment_bytes) as usize + 4);
+    buf.put_u32_le(image.len() as u32 + HEADER_BYTE_SIZE + alignment_bytes);
 
-    buf.extend((0..alignment_bytes).map(|_| 0)); // Guarantee 8Byte aligned
-    buf.push(0u8); // reserved
-    buf.push(pixel_kind as u8);
+    buf.put_bytes(0, alignment_bytes as usize); // Guarantee 8Byte aligned
+    buf.put_u8(0); // reserved
+    buf.put_u8(pixel_kind as u8);
     buf.put_slice(&channels.to_le_bytes());
     buf.put_slice(&width.get().to_le_bytes());
     buf.put_slice(image);
@@ -278,19 +436,80 @@ fn encode_raw(
         "Endoced raw: {:?}, width: {width}, height: {height}",
         &buf[0..buf.len().min(10)]
     );
-    Ok(buf)
+    Ok(())
 }
 
 fn encode_meta(
-    mut buf: Vec<u8>,
-    meta: impl FnOnce(&mut Vec<u8>) -> anyhow::Result<()>,
-) -> anyhow::Result<Vec<u8>> {
+    buf: &mut BytesMut,
+    meta: impl FnOnce(&mut BytesMut) -> anyhow::Result<()>,
+) -> anyhow::Result<()> {
     let offset = buf.len();
-    buf.extend_from_slice(&[0, 0, 0, 0]);
-    (meta)(&mut buf)?;
+    buf.put_u32_le(0);
+    (meta)(buf)?;
     let meta_length = (buf.len() as u32 - (4 + offset as u32)).to_le_bytes();
     buf[offset..(offset + 4)].copy_from_slice(&meta_length);
-    Ok(buf)
+    Ok(())
+}
+
+/// Lower rates are rejected, as a subscriber would wait minutes for its next frame
+pub const MIN_FPS: f32 = 0.01;
+
+/// Options which only affect a single websocket subscriber
+#[derive(Debug, Default, Clone, Copy, serde::Deserialize)]
+#[non_exhaustive]
+pub struct SubscriberOptions {
+    /// Skips frames which arrive faster than this rate, so slow clients don't have to process every frame
+    pub max_fps: Option<f32>,
+    /// Frames exceeding this size are split into [`ImageChunk`]s, e.g. for proxies which limit the websocket message size
+    pub max_message_size: Option<usize>,
+}
+
+impl SubscriberOptions {
+    pub fn with_max_fps(max_fps: Option<f32>) -> Self {
+        Self {
+            max_fps,
+            ..Default::default()
+        }
+    }
+
+    /// Like `with_max_fps`, but rejects rates which are not finite or below [`MIN_FPS`], e.g. from a query string
+    pub fn try_with_max_fps(max_fps: Option<f32>) -> Result<Self, (StatusCode, String)> {
+        match max_fps {
+            Some(x) if !(x.is_finite() && x >= MIN_FPS) => Err((
+                StatusCode::BAD_REQUEST,
+                format!("max_fps must be a finite number of at least {MIN_FPS}, got {x}"),
+            )),
+            _ => Ok(Self::with_max_fps(max_fps)),
+        }
+    }
+
+    pub fn with_max_message_size(mut self, max_message_size: Option<usize>) -> Self {
+        self.max_message_size = max_message_size;
+        self
+    }
+}
+
+fn limit_frame_rate<T: Send + 'static>(
+    stream: BoxStream<'static, T>,
+    max_fps: Option<f32>,
+) -> BoxStream<'static, T> {
+    let Some(min_interval) = max_fps
+        .filter(|x| *x >= MIN_FPS)
+        .and_then(|x| std::time::Duration::try_from_secs_f32(1. / x).ok())
+    else {
+        return stream;
+    };
+    let mut last_sent: Option<std::time::Instant> = None;
+    stream
+        .filter(move |_| {
+            let now = std::time::Instant::now();
+            let pass = last_sent.map_or(true, |last| now.duration_since(last) >= min_interval);
+            if pass {
+                last_sent = Some(now);
+            }
+            std::future::ready(pass)
+        })
+        .boxed()
 }
 
 pub type DefaultImageStreamer =
@@ -328,6 +547,33 @@ where
         })
         .await
     }
+
+    /// Like `stream_image`, but subscribes with a custom message (e.g. to request additional image keys)
+    /// and applies per-subscriber options like frame decimation
+    pub async fn stream_image_with_options<
+        TImg: StreamableImage + Send + Sync + 'static,
+        TFn: Fn(TInputImage) -> TFut + 'static + Send + Sync,
+        TFut: Future<Output = Result<TImg, ActorError<anyhow::Error>>> + 'static + Send,
+    >(
+        upgrade: WebSocketUpgrade,
+        device_id: Option<DeviceId>,
+        actor_system: ActorSystem,
+        msg: TMsg,
+        options: SubscriberOptions,
+        transformer: TFn,
+    ) -> Result<impl IntoResponse, (StatusCode, String)> {
+        Self::try_stream_with_message(
+            upgrade,
+            device_id,
+            actor_system,
+            msg,
+            options,
+            transformer,
+            |_| async { Ok(()) },
+        )
+        .await
+        .map_err(|(_, r)| r)
+    }
     pub async fn bidirectional_stream_image<
         TImg: StreamableImage + Send + Sync + 'static,
         TFn: Fn(TInputImage) -> TFut + 'static + Send + Sync,
@@ -363,22 +609,63 @@ where
         actor_system: ActorSystem,
         transformer: TFn,
         message_handler: TMessageHandler,
+    ) -> Result<impl IntoResponse, (WebSocketUpgrade, (StatusCode, String))> {
+        Self::try_stream_with_message(
+            upgrade,
+            device_id,
+            actor_system,
+            TMsg::default(),
+            SubscriberOptions::default(),
+            transformer,
+            message_handler,
+        )
+        .await
+    }
+
+    async fn try_stream_with_message<
+        TImg: StreamableImage + Send + Sync + 'static,
+        TFn: Fn(TInputImage) -> TFut + 'static + Send + Sync,
+        TFut: Future<Output = Result<TImg, ActorError<anyhow::Error>>> + 'static + Send,
+        TMessageHandler: (Fn(Message) -> TMessageHandlerFuture) + Send + Sync + 'static,
+        TMessageHandlerFuture: Future<Output = Result<(), anyhow::Error>> + 'static + Send,
+    >(
+        upgrade: WebSocketUpgrade,
+        device_id: Option<DeviceId>,
+        actor_system: ActorSystem,
+        msg: TMsg,
+        options: SubscriberOptions,
+        transformer: TFn,
+        message_handler: TMessageHandler,
     ) -> Result<impl IntoResponse, (WebSocketUpgrade, (StatusCode, String))> {
         let broadcast = {
             let mut sender = match actor_system.get_sender_or_single_handler::<TMsg>(device_id) {
                 Ok(x) => x,
                 Err(e) => return Err((upgrade, (StatusCode::NOT_FOUND, e.to_string()))),
             };
-            match sender.ask(TMsg::default()).await {
+            match sender.ask(msg).await {
                 Ok(x) => x,
                 Err(e) => return Err((upgrade, (StatusCode::NOT_FOUND, e.to_string()))),
             }
         }
         .into();
-        Ok(upgrade.on_upgrade(move |socket| async move {
-            Self::handle_socket(socket, broadcast, transformer, message_handler).await;
-            debug!("Websocket subscription ended");
-        }))
+        let broadcast = limit_frame_rate(broadcast, options.max_fps);
+        let keepalive = upgrade.keepalive();
+        Ok(
+            upgrade.on_upgrade_with_close_signal(move |socket, close| async move {
+                Self::handle_socket(
+                    socket,
+                    device_id,
+                    broadcast,
+                    close,
+                    keepalive,
+                    options.max_message_size,
+                    transformer,
+                    message_handler,
+                )
+                .await;
+                debug!("Websocket subscription ended");
+            }),
+        )
     }
 
     async fn handle_socket<
@@ -389,29 +676,85 @@ where
         TMessageHandlerFuture: Future<Output = Result<(), anyhow::Error>> + 'static + Send,
     >(
         socket: WebSocket,
+        device_id: Option<DeviceId>,
         mut broadcast: BoxStream<'static, TInputImage>,
+        mut close: CloseSignal,
+        keepalive: Keepalive,
+        max_message_size: Option<usize>,
         transformer: TFn,
         message_handler: TMessageHandler,
     ) {
         let (mut socket_tx, mut socket_rx) = socket.split();
         let (signal_broadcast_end, mut receive_broadcast_end) = oneshot::channel();
         let (mut tx, rx) = mpsc::channel(10);
+        // Dead clients would hold their broadcaster slot forever otherwise
+        let (signal_dead, receive_dead) = oneshot::channel::<()>();
+        let dead = async move {
+            if receive_dead.await.is_err() {
+                futures::future::pending::<()>().await;
+            }
+            debug!("Drop websocket, which didn't respond to pings");
+        };
+        let pinger = keepalive.clone();
         let encode_task = async move {
-            while let Some(image) = broadcast.next().await {
+            let mut frames = FrameBuffer::for_device(device_id);
+            let mut sequence = 0u32;
+            let reason = loop {
+                let image = match futures::future::select(&mut close, broadcast.next()).await {
+                    Either::Left((reason, _)) => break reason,
+                    Either::Right((Some(image), _)) => image,
+                    Either::Right((None, _)) => break CloseReason::DeviceStopped,
+                };
                 let image = (transformer)(image).await?;
-                let encoded_image = pilatus::execute_blocking(move || image.encode()).await?;
-                tx.send(encoded_image).await?;
-            }
-            debug!("Close connection because broadcast is closed");
+                let (returned, encoded_image) = pilatus::execute_blocking(move || {
+                    let encoded = frames.encode(image);
+                    Ok::<_, anyhow::Error>((frames, encoded))
+                })
+                .await?;
+                frames = returned;
+                let encoded_image = encoded_image?;
+                match max_message_size {
+                    Some(max) if encoded_image.len() > max => {
+                        for chunk in ImageChunk::split(&encoded_image, sequence, max) {
+                            tx.send(Message::Binary(chunk)).await?;
+                        }
+                        sequence = sequence.wrapping_add(1);
+                    }
+                    // Websocket messages own their payload, which costs a copy per subscriber
+                    _ => tx.send(Message::Binary(encoded_image.to_vec())).await?,
+                }
+            };
+            debug!("Close connection: {}", reason.as_str());
+            tx.send(reason.into_message()).await?;
             let _ignore = signal_broadcast_end.send(());
             Ok(()) as anyhow::Result<()>
         };
         let send_task = async move {
             // Without move, encode_task doesn't stop
             let mut moved_rx: mpsc::Receiver<_> = rx;
-            while let Some(x) = moved_rx.next().await {
-                if socket_tx.send(Message::Binary(x)).await.is_err() {
-                    break;
+            // Created once per ping, so frames don't delay it
+            let mut ping = Box::pin(pinger.next_ping());
+            loop {
+                let (msg, pinged) = match futures::future::select(moved_rx.next(), &mut ping).await
+                {
+                    Either::Left((Some(x), _)) => (x, false),
+                    Either::Left((None, _)) => break,
+                    Either::Right((Some(x), _)) => (x, true),
+                    Either::Right((None, _)) => {
+                        let _ = signal_dead.send(());
+                        break;
+                    }
+                };
+                if pinged {
+                    ping = Box::pin(pinger.next_ping());
+                }
+                match pinger.send(&mut socket_tx, msg).await {
+                    Ok(()) => {}
+                    Err(KeepaliveSendError::Closed) => break,
+                    Err(KeepaliveSendError::TimedOut) => {
+                        let _ = signal_dead.send(());
+                        break;
+                    }
                 }
             }
             debug!("Websocket sender finished");
@@ -420,12 +763,129 @@ where
             while let Either::Right((Some(Ok(msg)), _)) =
                 futures::future::select(&mut receive_broadcast_end, socket_rx.next()).await
             {
+                keepalive.on_message(&msg);
                 if (message_handler)(msg).await.is_err() {
                     break;
                 }
             }
         };
 
-        let _ = futures::join!(encode_task, send_task, read_task);
+        let tasks = async {
+            let _ = futures::join!(encode_task, send_task, read_task);
+        };
+        futures::future::select(std::pin::pin!(tasks), std::pin::pin!(dead)).await;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn encode_cache_encodes_identical_frames_once() {
+        let cache = EncodeCache::default();
+        let first = FrameId::next();
+        let key = |frame_id, device_id| {
+            (
+                device_id,
+                EncodeCacheKey {
+                    frame_id,
+                    selection: StreamingImageFormat::Jpeg.into(),
+                    quality: JPEG_QUALITY,
+                },
+            )
+        };
+        let mut encoded = 0;
+        for _ in 0..3 {
+            let frame = cache
+                .get_or_encode(key(first, None), || {
+                    encoded += 1;
+                    Ok(vec![1, 2, 3].into())
+                })
+                .unwrap();
+            assert_eq!(vec![1, 2, 3], frame);
+        }
+        assert_eq!(1, encoded);
+        let device = Some(DeviceId::new_v4());
+        assert_eq!(
+            vec![4],
+            cache
+                .get_or_encode(key(first, device), || Ok(vec![4].into()))
+                .unwrap()
+        );
+        assert_eq!(
+            vec![5],
+            cache
+                .get_or_encode(key(FrameId::next(), None), || Ok(vec![5].into()))
+                .unwrap()
+        );
+        assert_eq!(3, cache.entries.lock().unwrap().len());
+    }
+
+    #[test]
+    fn frame_buffer_matches_single_encode() {
+        let size = NonZeroU32::new(16).unwrap();
+        let image = || {
+            let image = DynamicImage::Luma8(LumaImage::new_vec((0..=255).collect(), size, size));
+            (
+                Ok(ImageWithMeta::with_hash(image, None)),
+                StreamingImageFormat::Raw,
+            )
+        };
+        let expected = image().encode().unwrap();
+        let mut frames = FrameBuffer::default();
+        assert_eq!(expected, frames.encode(image()).unwrap());
+        assert_eq!(expected, frames.encode(image()).unwrap());
+        assert!(frames.capacity_hint >= expected.len());
+
+        // Uncached frames reclaim the allocation of the previous one
+        let luma = Arc::new(LumaImage::new_vec((0..=255).collect(), size, size));
+        let mut frames = FrameBuffer::default();
+        let first = frames.encode(luma.clone()).unwrap();
+        let allocation = first.as_ptr();
+        drop(first);
+        assert_eq!(allocation, frames.encode(luma).unwrap().as_ptr());
+    }
+
+    #[test]
+    fn raw_frames_can_be_decoded() {
+        let (width, height) = (NonZeroU32::new(4).unwrap(), NonZeroU32::new(3).unwrap());
+        let pixels = (0..12).collect::<Vec<u8>>();
+        let image = DynamicImage::Luma8(LumaImage::new_vec(pixels.clone(), width, height));
+        let encoded = (
+            Ok(ImageWithMeta::with_hash(image, None)),
+            StreamingImageFormat::Raw,
+        )
+            .encode()
+            .unwrap();
+
+        let frame = pilatus::ImageFrame::parse(&encoded).unwrap();
+        let main = frame.iter_images().next().flatten().unwrap();
+        let raw = frame.raw_image(main).unwrap();
+        assert_eq!(RawPixelKind::U8, raw.pixel_kind);
+        assert_eq!((4, 3), (raw.width, raw.height));
+        assert_eq!(&pixels[..], raw.pixels);
+    }
+
+    #[test]
+    fn limit_frame_rate_skips_frames() {
+        let frames = futures::stream::iter(0..5).boxed();
+        let received: Vec<_> =
+            futures::executor::block_on(limit_frame_rate(frames, Some(MIN_FPS)).collect());
+        assert_eq!(vec![0], received);
+
+        for invalid in [0., -1., MIN_FPS / 2., f32::NAN, f32::INFINITY] {
+            assert!(SubscriberOptions::try_with_max_fps(Some(invalid)).is_err());
+            let frames = futures::stream::iter(0..5).boxed();
+            let received: Vec<_> =
+                futures::executor::block_on(limit_frame_rate(frames, Some(invalid)).collect());
+            assert_eq!(5, received.len(), "{invalid}");
+        }
+        assert!(SubscriberOptions::try_with_max_fps(Some(MIN_FPS)).is_ok());
+
+        let frames = futures::stream::iter(0..5).boxed();
+        let received: Vec<_> =
+            futures::executor::block_on(limit_frame_rate(frames, None).collect());
+        assert_eq!(vec![0, 1, 2, 3, 4], received);
     }
 }
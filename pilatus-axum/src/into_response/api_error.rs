@@ -0,0 +1,236 @@
+use std::{collections::BTreeMap, convert::Infallible, fmt::Debug};
+
+use async_trait::async_trait;
+use axum::{
+    extract::FromRequestParts,
+    http::{request::Parts, StatusCode},
+    response::{IntoResponse, Response},
+    Json,
+};
+use pilatus::{
+    device::{ActorError, ActorErrorUnknownDevice},
+    AdmissionError, ApprovalError, DeviceLockedError, LocalizableError, LocalizedError,
+    MaintenanceError, TransactionError,
+};
+use serde::Serialize;
+
+use super::{LocalizedErrorResponse, ERROR_CODE_HEADER};
+
+/// Header which identifies a request in logs. Clients may provide it, otherwise the server generates one
+pub const CORRELATION_ID_HEADER: &str = "x-correlation-id";
+
+/// Set by the webserver for each request and recorded in its tracing span
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct CorrelationId(pub String);
+
+#[async_trait]
+impl<S: Send + Sync> FromRequestParts<S> for CorrelationId {
+    type Rejection = Infallible;
+
+    async fn from_request_parts(req: &mut Parts, _: &S) -> Result<Self, Self::Rejection> {
+        Ok(req
+            .extensions
+            .get::<CorrelationId>()
+            .cloned()
+            .unwrap_or_else(|| CorrelationId(uuid::Uuid::new_v4().to_string())))
+    }
+}
+
+/// Error of the HTTP API. It's serialized as JSON, e.g.
+/// `{ "code": "unknown_recipe_id", "message": "...", "details": { "recipe_id": "..." }, "correlation_id": "..." }`
+///
+/// The webserver fills in the correlation id and translates the message like [`LocalizedErrorResponse`]
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct ApiError {
+    #[serde(skip)]
+    pub status: StatusCode,
+    /// Stable identifier in snake_case
+    pub code: String,
+    pub message: String,
+    #[serde(skip_serializing_if = "BTreeMap::is_empty")]
+    pub details: BTreeMap<String, String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub correlation_id: Option<String>,
+}
+
+impl ApiError {
+    pub fn new(status: StatusCode, error: &impl LocalizableError) -> Self {
+        Self::from_localized(status, error.to_localized())
+    }
+
+    pub fn from_localized(status: StatusCode, error: LocalizedError) -> Self {
+        Self {
+            status,
+            code: error.code,
+            message: error.message,
+            details: error.args,
+            correlation_id: None,
+        }
+    }
+
+    pub fn with_message(mut self, message: impl Into<String>) -> Self {
+        self.message = message.into();
+        self
+    }
+
+    pub fn with_correlation_id(mut self, id: CorrelationId) -> Self {
+        self.correlation_id = Some(id.0);
+        self
+    }
+
+    pub fn to_localized(&self) -> LocalizedError {
+        LocalizedError {
+            code: self.code.clone(),
+            message: self.message.clone(),
+            args: self.details.clone(),
+        }
+    }
+}
+
+impl IntoResponse for ApiError {
+    fn into_response(self) -> Response {
+        let mut response = (
+            self.status,
+            [(ERROR_CODE_HEADER, self.code.clone())],
+            Json(&self),
+        )
+            .into_response();
+        response.extensions_mut().insert(self);
+        response
+    }
+}
+
+/// For errors without a dedicated code
+impl From<(StatusCode, String)> for ApiError {
+    fn from(x: (StatusCode, String)) -> Self {
+        LocalizedErrorResponse::from(x).into()
+    }
+}
+
+impl From<LocalizedErrorResponse> for ApiError {
+    fn from(x: LocalizedErrorResponse) -> Self {
+        Self::from_localized(x.status, x.error)
+    }
+}
+
+impl From<TransactionError> for ApiError {
+    fn from(e: TransactionError) -> Self {
+        let status = match &e {
+            TransactionError::UnknownRecipeId(_)
+            | TransactionError::UnknownDevice(_)
+            | TransactionError::UnknownFilePath(_) => StatusCode::NOT_FOUND,
+            TransactionError::RecipeAlreadyExists(_) => StatusCode::CONFLICT,
+            TransactionError::FileSystemError(_) => StatusCode::INTERNAL_SERVER_ERROR,
+            TransactionError::Other(e) if e.is::<DeviceLockedError>() => StatusCode::FORBIDDEN,
+            TransactionError::Other(e) if e.is::<AdmissionError>() => StatusCode::CONFLICT,
+            TransactionError::Other(e) if e.is::<MaintenanceError>() => {
+                StatusCode::SERVICE_UNAVAILABLE
+            }
+            TransactionError::Other(e) => match e.downcast_ref::<ApprovalError>() {
+                Some(ApprovalError::InvalidTransition { .. }) | None => StatusCode::BAD_REQUEST,
+                Some(_) => StatusCode::FORBIDDEN,
+            },
+            _ => StatusCode::BAD_REQUEST,
+        };
+        Self::new(status, &e)
+    }
+}
+
+impl<T: Debug> From<ActorError<T>> for ApiError {
+    fn from(e: ActorError<T>) -> Self {
+        let (status, code) = match &e {
+            ActorError::UnknownDevice(_) => (StatusCode::NOT_FOUND, "actor_unknown_device"),
+            ActorError::UnknownMessageType(_) => {
+                (StatusCode::NOT_FOUND, "actor_unknown_message_type")
+            }
+            ActorError::Custom(_) => (StatusCode::BAD_REQUEST, "actor_custom"),
+            ActorError::Busy(_) => (StatusCode::SERVICE_UNAVAILABLE, "actor_busy"),
+            // https://de.wikipedia.org/wiki/HTTP-Statuscode
+            ActorError::Aborted => (StatusCode::from_u16(499).unwrap(), "actor_aborted"),
+            ActorError::Timeout => (StatusCode::REQUEST_TIMEOUT, "actor_timeout"),
+            ActorError::Remote(_) => (StatusCode::BAD_GATEWAY, "actor_remote"),
+            ActorError::Forbidden(_) => (StatusCode::FORBIDDEN, "actor_forbidden"),
+            _ => (StatusCode::BAD_REQUEST, "other"),
+        };
+        Self {
+            status,
+            code: code.into(),
+            message: e.to_string(),
+            details: match &e {
+                ActorError::Custom(x) => BTreeMap::from([("reason".into(), format!("{x:?}"))]),
+                ActorError::UnknownDevice(x) => unknown_device_details(x),
+                _ => BTreeMap::new(),
+            },
+            correlation_id: None,
+        }
+    }
+}
+
+fn unknown_device_details(e: &ActorErrorUnknownDevice) -> BTreeMap<String, String> {
+    let mut details = BTreeMap::new();
+    match e {
+        ActorErrorUnknownDevice::UnknownDeviceId {
+            device_id,
+            name,
+            msg_type,
+            ..
+        } => {
+            details.insert("device_id".into(), device_id.to_string());
+            if let Some(name) = name {
+                details.insert("device_name".into(), name.to_string());
+            }
+            if let Some(msg_type) = msg_type {
+                details.insert("message_type".into(), msg_type.to_string());
+            }
+        }
+        ActorErrorUnknownDevice::UnknownDeviceName { name, msg_type, .. } => {
+            details.insert("device_name".into(), name.to_string());
+            if let Some(msg_type) = msg_type {
+                details.insert("message_type".into(), msg_type.to_string());
+            }
+        }
+        ActorErrorUnknownDevice::AmbiguousHandler { msg_type, .. } => {
+            details.insert("message_type".into(), msg_type.to_string());
+        }
+    }
+    details
+}
+
+#[cfg(test)]
+mod tests {
+    use pilatus::{device::DeviceId, RecipeId};
+
+    use super::*;
+
+    #[test]
+    fn transaction_errors_keep_their_code() {
+        let error = ApiError::from(TransactionError::UnknownRecipeId(RecipeId::default()));
+        assert_eq!(StatusCode::NOT_FOUND, error.status);
+        assert_eq!("unknown_recipe_id", error.code);
+        assert_eq!(
+            serde_json::json!({
+                "code": "unknown_recipe_id",
+                "message": error.message,
+                "details": { "recipe_id": RecipeId::default().to_string() },
+            }),
+            serde_json::to_value(&error).unwrap()
+        );
+    }
+
+    #[test]
+    fn actor_errors_have_codes() {
+        let error = ApiError::from(ActorError::<()>::Timeout);
+        assert_eq!(StatusCode::REQUEST_TIMEOUT, error.status);
+        assert_eq!("actor_timeout", error.code);
+    }
+
+    #[test]
+    fn unknown_device_names_target() {
+        let device_id = DeviceId::new_v4();
+        let error = ApiError::from(ActorError::<()>::UnknownDevice(
+            ActorErrorUnknownDevice::unknown_id(device_id, "Stopped").with_message_type::<u8>(),
+        ));
+        assert_eq!(Some(&device_id.to_string()), error.details.get("device_id"));
+        assert_eq!(Some(&"u8".to_string()), error.details.get("message_type"));
+    }
+}
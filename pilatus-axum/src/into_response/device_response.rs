@@ -8,6 +8,8 @@ use axum::{
 
 use pilatus::device::{ActorError, ActorMessage};
 
+use super::ApiError;
+
 #[allow(type_alias_bounds)]
 pub type DeviceMessageJsonResponse<T: ActorMessage> = DeviceJsonResponse<T::Output, T::Error>;
 
@@ -30,7 +32,7 @@ impl<T: IntoResponse, TErr: Debug> IntoResponse for DeviceResponse<T, TErr> {
     fn into_response(self) -> axum::response::Response {
         match self.0 {
             Ok(d) => d.into_response(),
-            Err(e) => map_actor_error_to_status_text(e),
+            Err(e) => ApiError::from(e).into_response(),
         }
     }
 }
@@ -39,11 +41,12 @@ impl<T: serde::Serialize, TErr: Debug> IntoResponse for DeviceJsonResponse<T, TE
     fn into_response(self) -> axum::response::Response {
         match self.0 {
             Ok(d) => Json(d).into_response(),
-            Err(e) => map_actor_error_to_status_text(e),
+            Err(e) => ApiError::from(e).into_response(),
         }
     }
 }
 
+/// Plain text variant of [`ApiError::from`] for routes which don't respond with JSON errors yet
 pub fn map_actor_error_to_status_text<T: Debug>(e: ActorError<T>) -> Response {
     (
         match e {
@@ -54,6 +57,8 @@ pub fn map_actor_error_to_status_text<T: Debug>(e: ActorError<T>) -> Response {
             ActorError::Busy(_) => StatusCode::SERVICE_UNAVAILABLE,
             ActorError::Aborted => StatusCode::from_u16(499).unwrap(), // https://de.wikipedia.org/wiki/HTTP-Statuscode
             ActorError::Timeout => StatusCode::REQUEST_TIMEOUT,
+            ActorError::Remote(_) => StatusCode::BAD_GATEWAY,
+            ActorError::Forbidden(_) => StatusCode::FORBIDDEN,
             _ => StatusCode::BAD_REQUEST,
         },
         format!("{e:?}"),
@@ -3,7 +3,10 @@
 
 use anyhow::Error;
 use axum::{body::Body, response::IntoResponse};
-use futures::future::FusedFuture;
+use futures::{future::FusedFuture, stream::Abortable, Future, StreamExt};
+use pilatus::AbortOnDrop;
+
+use super::{spool, SpoolConfig, SpoolWriter};
 
 pub struct IoStreamBody {
     inner: Body,
@@ -57,4 +60,55 @@ impl IoStreamBody {
             }),
         }
     }
+
+    /// Unlike `with_writer`, the producer runs on its own task and isn't slowed down by the client.
+    /// Output which the client didn't receive yet is buffered in memory and temporary files, see [`SpoolConfig`].
+    /// Use it for producers which hold resources while they're running, e.g. recipe exports or actor requests
+    pub fn with_spooled_writer<TFut: Future<Output = Result<(), Error>> + Send + 'static>(
+        config: SpoolConfig,
+        fut: impl FnOnce(SpoolWriter) -> TFut,
+    ) -> Self {
+        let (writer, mut reader) = spool(config);
+        // The producer is aborted if the client disconnects
+        let (abort_on_drop, reg) = AbortOnDrop::new_pair();
+        let producer = tokio::spawn(Abortable::new((fut)(writer), reg));
+        Self {
+            inner: Body::from_stream(async_stream::stream! {
+                let _abort_on_drop = abort_on_drop;
+                while let Some(chunk) = reader.next().await {
+                    match chunk {
+                        Ok(x) => yield Ok(x),
+                        Err(e) => {
+                            yield Err(anyhow::Error::from(e));
+                            return;
+                        }
+                    }
+                }
+                match producer.await {
+                    Ok(Ok(Ok(()))) => {}
+                    Ok(Ok(Err(e))) => yield Err(e),
+                    Ok(Err(e)) => yield Err(anyhow::Error::from(e)),
+                    Err(e) => yield Err(anyhow::Error::from(e)),
+                }
+            }),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn abort_spooled_producer_if_body_is_dropped() {
+        let (tx, rx) = futures::channel::oneshot::channel::<()>();
+        let body =
+            IoStreamBody::with_spooled_writer(SpoolConfig::default(), move |writer| async move {
+                let _guards = (tx, writer);
+                std::future::pending::<()>().await;
+                Ok(())
+            });
+        drop(body);
+        assert!(rx.await.is_err(), "Producer should be dropped");
+    }
 }
@@ -0,0 +1,53 @@
+use std::collections::BTreeMap;
+
+use axum::{
+    http::StatusCode,
+    response::{IntoResponse, Response},
+};
+use pilatus::{LocalizableError, LocalizedError};
+
+/// Header with the stable code of an error response
+pub const ERROR_CODE_HEADER: &str = "x-error-code";
+
+/// Responds with the english message as plain text. The webserver replaces it with a translation,
+/// if the message catalog contains a template for the locale requested via `Accept-Language`
+pub struct LocalizedErrorResponse {
+    pub status: StatusCode,
+    pub error: LocalizedError,
+}
+
+impl LocalizedErrorResponse {
+    pub fn new(status: StatusCode, error: &impl LocalizableError) -> Self {
+        Self {
+            status,
+            error: error.to_localized(),
+        }
+    }
+}
+
+/// For errors without a dedicated code
+impl From<(StatusCode, String)> for LocalizedErrorResponse {
+    fn from((status, message): (StatusCode, String)) -> Self {
+        Self {
+            status,
+            error: LocalizedError {
+                code: "other".into(),
+                args: BTreeMap::from([("reason".into(), message.clone())]),
+                message,
+            },
+        }
+    }
+}
+
+impl IntoResponse for LocalizedErrorResponse {
+    fn into_response(self) -> Response {
+        let mut response = (
+            self.status,
+            [(ERROR_CODE_HEADER, self.error.code.clone())],
+            self.error.message.clone(),
+        )
+            .into_response();
+        response.extensions_mut().insert(self.error);
+        response
+    }
+}
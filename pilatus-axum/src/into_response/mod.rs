@@ -1,7 +1,13 @@
+mod api_error;
 mod device_response;
 mod io_stream_body;
+mod localized_error;
 mod script;
+mod spool;
 
+pub use api_error::*;
 pub use device_response::{DeviceJsonResponse, DeviceMessageJsonResponse, DeviceResponse};
 pub use io_stream_body::*;
+pub use localized_error::*;
 pub use script::*;
+pub use spool::*;
@@ -0,0 +1,283 @@
+//! Bounded buffer between a producer and a slow http client
+//!
+//! Up to `memory_budget` bytes are kept in memory, everything beyond is appended to a temporary file.
+//! The producer only waits, once the file exceeds `disk_budget` as well.
+//! The file is removed as soon as both sides are dropped, e.g. because the client disconnected
+
+use std::{
+    collections::VecDeque,
+    fs::{File, OpenOptions},
+    io::{self, Read, Seek, SeekFrom, Write},
+    path::PathBuf,
+    pin::Pin,
+    sync::{Arc, Mutex},
+    task::{Context, Poll, Waker},
+};
+
+use bytes::Bytes;
+use futures::{AsyncWrite, Stream};
+
+/// Maximum size of chunks which are read back from the spill file
+const READ_CHUNK_SIZE: usize = 64 * 1024;
+
+#[derive(Debug, Clone)]
+pub struct SpoolConfig {
+    pub memory_budget: usize,
+    pub disk_budget: u64,
+    /// Directory of the spill files. It's created with the first spill file
+    pub dir: PathBuf,
+}
+
+impl Default for SpoolConfig {
+    fn default() -> Self {
+        Self {
+            memory_budget: 4 * 1024 * 1024,
+            disk_budget: 4 * 1024 * 1024 * 1024,
+            dir: std::env::temp_dir(),
+        }
+    }
+}
+
+impl SpoolConfig {
+    /// Handlers should spill into [`pilatus::GenericConfig::temp_dir`], as the system temp dir might be too small
+    pub fn with_dir(self, dir: impl Into<PathBuf>) -> Self {
+        Self {
+            dir: dir.into(),
+            ..self
+        }
+    }
+}
+
+pub fn spool(config: SpoolConfig) -> (SpoolWriter, SpoolReader) {
+    let shared = Arc::new(Mutex::new(SpoolState {
+        config,
+        memory: VecDeque::new(),
+        memory_len: 0,
+        file: None,
+        closed: false,
+        reader_dropped: false,
+        reader_waker: None,
+        writer_waker: None,
+    }));
+    (SpoolWriter(shared.clone()), SpoolReader(shared))
+}
+
+struct SpoolState {
+    config: SpoolConfig,
+    memory: VecDeque<Bytes>,
+    memory_len: usize,
+    file: Option<SpillFile>,
+    closed: bool,
+    reader_dropped: bool,
+    reader_waker: Option<Waker>,
+    writer_waker: Option<Waker>,
+}
+
+impl SpoolState {
+    fn spilled(&self) -> u64 {
+        self.file.as_ref().map_or(0, |f| f.write_pos - f.read_pos)
+    }
+
+    fn wake_reader(&mut self) {
+        if let Some(waker) = self.reader_waker.take() {
+            waker.wake();
+        }
+    }
+
+    fn wake_writer(&mut self) {
+        if let Some(waker) = self.writer_waker.take() {
+            waker.wake();
+        }
+    }
+}
+
+/// Data in the file is always newer than data in memory, because nothing is buffered in memory until the file is drained
+struct SpillFile {
+    path: PathBuf,
+    file: File,
+    read_pos: u64,
+    write_pos: u64,
+}
+
+impl SpillFile {
+    fn create(config: &SpoolConfig) -> io::Result<Self> {
+        std::fs::create_dir_all(&config.dir)?;
+        let path = config
+            .dir
+            .join(format!("pilatus-spool-{}", uuid::Uuid::new_v4()));
+        let file = OpenOptions::new()
+            .read(true)
+            .append(true)
+            .create_new(true)
+            .open(&path)?;
+        Ok(Self {
+            path,
+            file,
+            read_pos: 0,
+            write_pos: 0,
+        })
+    }
+
+    fn append(&mut self, buf: &[u8]) -> io::Result<()> {
+        self.file.write_all(buf)?;
+        self.write_pos += buf.len() as u64;
+        Ok(())
+    }
+
+    fn read_chunk(&mut self) -> io::Result<Bytes> {
+        let len = (self.write_pos - self.read_pos).min(READ_CHUNK_SIZE as u64) as usize;
+        let mut buf = vec![0; len];
+        self.file.seek(SeekFrom::Start(self.read_pos))?;
+        self.file.read_exact(&mut buf)?;
+        self.read_pos += len as u64;
+        if self.read_pos == self.write_pos {
+            // Appends start at the beginning again, so the file doesn't grow forever
+            self.file.set_len(0)?;
+            self.read_pos = 0;
+            self.write_pos = 0;
+        }
+        Ok(buf.into())
+    }
+}
+
+impl Drop for SpillFile {
+    fn drop(&mut self) {
+        if let Err(e) = std::fs::remove_file(&self.path) {
+            tracing::warn!("Couldn't remove spool file {:?}: {e}", self.path);
+        }
+    }
+}
+
+/// Spill files are written synchronously. They are local, append-only and written in the chunks of the producer
+pub struct SpoolWriter(Arc<Mutex<SpoolState>>);
+
+impl AsyncWrite for SpoolWriter {
+    fn poll_write(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &[u8],
+    ) -> Poll<io::Result<usize>> {
+        let mut state = self.0.lock().expect("Never poisoned");
+        if state.reader_dropped {
+            return Poll::Ready(Err(io::ErrorKind::BrokenPipe.into()));
+        }
+        let spilled = state.spilled();
+        if spilled == 0 && state.memory_len + buf.len() <= state.config.memory_budget {
+            state.memory.push_back(Bytes::copy_from_slice(buf));
+            state.memory_len += buf.len();
+        } else {
+            let is_empty = spilled == 0 && state.memory_len == 0;
+            // Chunks which are larger than all budgets are accepted if nothing is buffered, otherwise they would wait forever
+            if spilled + buf.len() as u64 > state.config.disk_budget && !is_empty {
+                state.writer_waker = Some(cx.waker().clone());
+                return Poll::Pending;
+            }
+            if state.file.is_none() {
+                state.file = Some(SpillFile::create(&state.config)?);
+            }
+            state.file.as_mut().expect("Just created").append(buf)?;
+        }
+        state.wake_reader();
+        Poll::Ready(Ok(buf.len()))
+    }
+
+    fn poll_flush(self: Pin<&mut Self>, _cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        Poll::Ready(Ok(()))
+    }
+
+    fn poll_close(self: Pin<&mut Self>, _cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        let mut state = self.0.lock().expect("Never poisoned");
+        state.closed = true;
+        state.wake_reader();
+        Poll::Ready(Ok(()))
+    }
+}
+
+/// Producers which fail don't close the writer. The reader ends anyway, so the failure can be reported
+impl Drop for SpoolWriter {
+    fn drop(&mut self) {
+        let mut state = self.0.lock().expect("Never poisoned");
+        state.closed = true;
+        state.wake_reader();
+    }
+}
+
+pub struct SpoolReader(Arc<Mutex<SpoolState>>);
+
+impl Stream for SpoolReader {
+    type Item = io::Result<Bytes>;
+
+    fn poll_next(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        let mut state = self.0.lock().expect("Never poisoned");
+        if let Some(chunk) = state.memory.pop_front() {
+            state.memory_len -= chunk.len();
+            state.wake_writer();
+            return Poll::Ready(Some(Ok(chunk)));
+        }
+        if state.spilled() > 0 {
+            let chunk = state
+                .file
+                .as_mut()
+                .expect("Spilled data is in the file")
+                .read_chunk();
+            state.wake_writer();
+            return Poll::Ready(Some(chunk));
+        }
+        if state.closed {
+            return Poll::Ready(None);
+        }
+        state.reader_waker = Some(cx.waker().clone());
+        Poll::Pending
+    }
+}
+
+impl Drop for SpoolReader {
+    fn drop(&mut self) {
+        let mut state = self.0.lock().expect("Never poisoned");
+        state.reader_dropped = true;
+        state.wake_writer();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use futures::{AsyncWriteExt, StreamExt};
+
+    use super::*;
+
+    #[tokio::test]
+    async fn spill_to_disk_and_keep_order() {
+        let dir = std::env::temp_dir().join(format!("spool-test-{}", uuid::Uuid::new_v4()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let (mut writer, reader) = spool(SpoolConfig {
+            memory_budget: 4,
+            disk_budget: 1024,
+            dir: dir.clone(),
+        });
+        for chunk in [&b"abc"[..], b"defg", b"hi"] {
+            writer.write_all(chunk).await.unwrap();
+        }
+        assert_eq!(1, std::fs::read_dir(&dir).unwrap().count());
+        writer.close().await.unwrap();
+
+        let data = reader
+            .map(|x| x.unwrap())
+            .collect::<Vec<_>>()
+            .await
+            .concat();
+        assert_eq!(b"abcdefghi", &data[..]);
+        drop(writer);
+        assert_eq!(0, std::fs::read_dir(&dir).unwrap().count());
+        std::fs::remove_dir(&dir).unwrap();
+    }
+
+    #[tokio::test]
+    async fn writer_fails_after_client_disconnected() {
+        let (mut writer, reader) = spool(SpoolConfig::default());
+        drop(reader);
+        assert_eq!(
+            io::ErrorKind::BrokenPipe,
+            writer.write_all(b"abc").await.unwrap_err().kind()
+        );
+    }
+}
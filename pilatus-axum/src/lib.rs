@@ -1,11 +1,16 @@
 mod abort;
+mod base_path;
 mod dependency_provider;
 #[cfg(feature = "engineering")]
 pub mod image;
 mod inject;
 mod into_response;
 mod minfac_extensions;
+#[cfg(feature = "openapi")]
+pub mod openapi;
+mod progress;
 mod routing;
+mod user;
 mod web_component;
 mod ws;
 
@@ -19,38 +24,65 @@ pub use axum::{
     http,
     response::{sse, AppendHeaders, Html, IntoResponse, Response},
 };
+pub use base_path::{
+    BasePath, FORWARDED_HOST_HEADER, FORWARDED_PREFIX_HEADER, FORWARDED_PROTO_HEADER,
+};
 pub use dependency_provider::DependencyProvider;
 pub use into_response::*;
 pub use minfac_extensions::ServiceCollectionExtensions;
-pub use routing::{MethodRouter, Router};
+pub use progress::stream_actor_progress;
+pub use routing::{MethodRouter, RouteDoc, RouteDocs, Router};
+pub use user::UNLOCK_TOKEN_HEADER;
 pub use web_component::*;
 
 pub mod extract {
     pub struct Inject<T: minfac::Resolvable>(pub T::ItemPreChecked);
     pub struct InjectRegistered<T: std::any::Any>(pub T);
     pub use super::abort::Abort;
+    pub use super::into_response::CorrelationId;
+    pub use super::user::{CurrentUser, OptionalUser, Transaction, WebActorSystem};
     pub struct InjectAll<T: std::any::Any>(pub ServiceIterator<T>);
     pub use axum::body::Body;
     pub use axum::extract::{FromRequestParts, Json, Path, Query};
     use minfac::ServiceIterator;
 
     pub mod ws {
-        pub use super::super::ws::{Dropper, WebSocketDropperService, WebSocketUpgrade};
+        pub use super::super::ws::{
+            CloseNotifier, CloseReason, CloseSignal, Dropper, Keepalive, KeepaliveConfig,
+            KeepaliveSendError, WebSocketDropperService, WebSocketUpgrade,
+        };
         pub use axum::extract::ws::{Message, WebSocket};
     }
 }
 
-pub type MinfacRouter = pilatus::OnceExtractor<axum::Router>;
+pub type MinfacRouter = pilatus::OnceExtractor<RouteGroup>;
+
+/// Routes of a single `register_web` call. Listeners can select the groups they serve by prefix
+pub struct RouteGroup {
+    pub prefix: &'static str,
+    pub router: axum::Router,
+}
 
 pub struct Stats {
-    socket: Shared<oneshot::Receiver<SocketAddr>>,
+    sockets: Shared<oneshot::Receiver<Vec<SocketAddr>>>,
 }
 impl Stats {
-    pub fn new(socket: Shared<oneshot::Receiver<SocketAddr>>) -> Self {
-        Self { socket }
+    pub fn new(sockets: Shared<oneshot::Receiver<Vec<SocketAddr>>>) -> Self {
+        Self { sockets }
     }
+
+    /// Address of the first listener
     pub async fn socket_addr(&self) -> SocketAddr {
-        self.socket
+        *self
+            .socket_addrs()
+            .await
+            .first()
+            .expect("At least one listener is bound")
+    }
+
+    /// Addresses of all listeners in the order of the config
+    pub async fn socket_addrs(&self) -> Vec<SocketAddr> {
+        self.sockets
             .clone()
             .await
             .expect("always resolved when server started")
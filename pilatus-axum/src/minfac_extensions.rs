@@ -1,4 +1,4 @@
-use super::MinfacRouter;
+use super::{MinfacRouter, RouteDocs};
 
 pub trait ServiceCollectionExtensions {
     fn register_web(&mut self, topic: &'static str, creator: fn(super::Router) -> super::Router);
@@ -7,7 +7,12 @@ pub trait ServiceCollectionExtensions {
 impl ServiceCollectionExtensions for minfac::ServiceCollection {
     fn register_web(&mut self, prefix: &'static str, creator: fn(crate::Router) -> crate::Router) {
         let route = creator(crate::Router::new(prefix));
-        self.register_instance(MinfacRouter::from(route.axum_router));
+        self.register_instance(pilatus::ServiceInfo::web(prefix));
+        self.register_instance(MinfacRouter::from(crate::RouteGroup {
+            prefix,
+            router: route.axum_router,
+        }));
+        self.register_instance(RouteDocs::new(route.docs));
         for checker in route.dependencies {
             (checker)(self);
         }
@@ -62,4 +67,26 @@ mod tests {
             .map(|x| x.extract_unchecked());
         assert_eq!(3, all.count());
     }
+
+    #[test]
+    pub fn collect_route_docs() {
+        let mut collection = ServiceCollection::new();
+        collection.register(|| 42i128);
+        collection.register_web("foo", |r| {
+            r.http("/:id", |x| {
+                x.get(handler::<InjectRegistered<i128>>)
+                    .summary("Get foo")
+                    .delete(handler::<InjectRegistered<i128>>)
+            })
+        });
+        let provider = collection.build().unwrap();
+        let docs = provider.get::<RouteDocs>().unwrap();
+        let docs = docs.iter().collect::<Vec<_>>();
+        assert_eq!(2, docs.len());
+        assert_eq!("/foo/:id", docs[0].path());
+        assert_eq!("/foo/{id}", docs[0].openapi_path());
+        assert_eq!(Some("Get foo"), docs[0].summary());
+        assert_eq!(None, docs[1].summary());
+        assert_eq!(vec!["id"], docs[1].path_parameters().collect::<Vec<_>>());
+    }
 }
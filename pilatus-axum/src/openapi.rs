@@ -0,0 +1,106 @@
+//! OpenAPI specification of all routes registered with `register_web`
+
+use axum::http::Method;
+use utoipa::openapi::{
+    path::{HttpMethod, OperationBuilder, ParameterBuilder, ParameterIn, PathItem},
+    schema::{ObjectBuilder, Schema, Type},
+    InfoBuilder, OpenApi, OpenApiBuilder, PathsBuilder, Required, ResponseBuilder, Server,
+};
+
+use crate::{RouteDoc, RouteDocs};
+
+pub use utoipa;
+
+/// Combines the routes of all `register_web` calls. Paths are relative to the server url `{base_path}/api`
+pub fn openapi_spec<'a>(
+    title: &str,
+    version: &str,
+    base_path: &crate::BasePath,
+    routes: impl IntoIterator<Item = &'a RouteDocs>,
+) -> OpenApi {
+    let paths = routes
+        .into_iter()
+        .flat_map(RouteDocs::iter)
+        .filter_map(|doc| Some((doc.openapi_path(), to_http_method(doc.method())?, doc)))
+        .fold(PathsBuilder::new(), |acc, (path, method, doc)| {
+            acc.path(path, PathItem::new(method, build_operation(doc)))
+        });
+
+    OpenApiBuilder::new()
+        .info(InfoBuilder::new().title(title).version(version))
+        .servers(Some([Server::new(base_path.link("/api"))]))
+        .paths(paths)
+        .build()
+}
+
+fn build_operation(doc: &RouteDoc) -> utoipa::openapi::path::Operation {
+    let builder = doc.path_parameters().fold(
+        OperationBuilder::new()
+            .summary(doc.summary())
+            .description(doc.description())
+            .response("200", ResponseBuilder::new().description("Success").build()),
+        |acc, name| {
+            acc.parameter(
+                ParameterBuilder::new()
+                    .name(name)
+                    .parameter_in(ParameterIn::Path)
+                    .required(Required::True)
+                    .schema(Some(Schema::Object(
+                        ObjectBuilder::new().schema_type(Type::String).build(),
+                    ))),
+            )
+        },
+    );
+    match doc.customize {
+        Some(f) => f(builder),
+        None => builder,
+    }
+    .build()
+}
+
+fn to_http_method(method: &Method) -> Option<HttpMethod> {
+    Some(match *method {
+        Method::GET => HttpMethod::Get,
+        Method::POST => HttpMethod::Post,
+        Method::PUT => HttpMethod::Put,
+        Method::DELETE => HttpMethod::Delete,
+        Method::PATCH => HttpMethod::Patch,
+        Method::HEAD => HttpMethod::Head,
+        Method::OPTIONS => HttpMethod::Options,
+        Method::TRACE => HttpMethod::Trace,
+        _ => return None,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use minfac::ServiceCollection;
+
+    use super::*;
+    use crate::ServiceCollectionExtensions;
+
+    async fn handler() -> &'static str {
+        "TestOutput"
+    }
+
+    #[test]
+    fn spec_contains_all_registered_routes() {
+        let mut collection = ServiceCollection::new();
+        collection.register_web("foo", |r| {
+            r.http("/:id/*path", |m| {
+                m.get(handler).summary("Get foo").post(handler)
+            })
+        });
+        collection.register_web("bar", |r| r.http("", |m| m.delete(handler)));
+        let provider = collection.build().unwrap();
+        let docs = provider.get_all::<RouteDocs>().collect::<Vec<_>>();
+        let spec = openapi_spec("Test", "1.0", &crate::BasePath::default(), &docs);
+
+        let foo = spec.paths.paths.get("/foo/{id}/{path}").unwrap();
+        let get = foo.get.as_ref().unwrap();
+        assert_eq!(Some("Get foo".into()), get.summary);
+        assert_eq!(2, get.parameters.as_ref().unwrap().len());
+        assert!(foo.post.is_some());
+        assert!(spec.paths.paths.get("/bar").unwrap().delete.is_some());
+    }
+}
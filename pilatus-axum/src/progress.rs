@@ -0,0 +1,78 @@
+//! Websocket-Protocol for `ActorSystem::ask_with_progress`
+//! Each event is sent as JSON-Text. Progress-Messages look like `{"Progress": {"fraction": 0.5, "message": "..."}}`,
+//! the last message is either `{"Finished": {"Ok": ...}}` or `{"Finished": {"Err": "..."}}`.
+//! Closing the socket drops the request, which aborts handlers registered with `WithProgress`
+//! If the server drops the request (e.g. on recipe changes), it sends a close frame with a [`crate::extract::ws::CloseReason`]
+
+use std::fmt::Debug;
+
+use axum::{body::Body, extract::ws::Message, http::Response};
+use futures::{future::Either, SinkExt, StreamExt};
+use pilatus::device::{ActorMessage, ActorProgress, ActorProgressEvent, ActorProgressStream};
+use serde::Serialize;
+use tracing::debug;
+
+use crate::extract::ws::WebSocketUpgrade;
+
+#[derive(Serialize)]
+enum ProgressServerMessage<T> {
+    Progress(ActorProgress),
+    Finished(Result<T, String>),
+}
+
+pub fn stream_actor_progress<TMsg>(
+    upgrade: WebSocketUpgrade,
+    mut stream: ActorProgressStream<TMsg>,
+) -> Response<Body>
+where
+    TMsg: ActorMessage,
+    TMsg::Output: Serialize,
+    TMsg::Error: Debug,
+{
+    upgrade.on_upgrade_with_close_signal(move |socket, close| async move {
+        let (mut socket_tx, mut socket_rx) = socket.split();
+        let reason = {
+            let send_task = async {
+                while let Some(event) = stream.next().await {
+                    let msg = match event {
+                        ActorProgressEvent::Progress(p) => ProgressServerMessage::Progress(p),
+                        ActorProgressEvent::Finished(r) => {
+                            ProgressServerMessage::Finished(r.map_err(|e| e.to_string()))
+                        }
+                    };
+                    let Ok(text) = serde_json::to_string(&msg) else {
+                        debug!("Couldn't serialize progress message");
+                        break;
+                    };
+                    if socket_tx.send(Message::Text(text)).await.is_err() {
+                        break;
+                    }
+                }
+            };
+            let receive_task = async {
+                while let Some(Ok(msg)) = socket_rx.next().await {
+                    if let Message::Close(_) = msg {
+                        debug!("Client stopped listening for progress");
+                        break;
+                    }
+                }
+            };
+            futures::pin_mut!(send_task, receive_task);
+            match futures::future::select(close, futures::future::select(send_task, receive_task))
+                .await
+            {
+                Either::Left((reason, _)) => Some(reason),
+                Either::Right(_) => None,
+            }
+        };
+        drop(stream);
+        if let Some(reason) = reason {
+            let _ignore_if_not_sendable = socket_tx.send(reason.into_message()).await;
+        }
+        let _ignore_if_not_closeable = socket_rx
+            .reunite(socket_tx)
+            .expect("Guaranted to be same source")
+            .close()
+            .await;
+    })
+}
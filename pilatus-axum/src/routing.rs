@@ -1,6 +1,6 @@
 use std::marker::PhantomData;
 
-use axum::handler::Handler;
+use axum::{handler::Handler, http::Method};
 use minfac::ServiceCollection;
 
 use super::DependencyProvider;
@@ -9,6 +9,7 @@ pub struct Router {
     prefix: &'static str,
     pub(crate) axum_router: axum::Router,
     pub(crate) dependencies: Vec<fn(&mut ServiceCollection)>,
+    pub(crate) docs: Vec<RouteDoc>,
 }
 impl Router {
     pub(crate) fn new(prefix: &'static str) -> Self {
@@ -16,6 +17,7 @@ impl Router {
             prefix,
             axum_router: Default::default(),
             dependencies: Default::default(),
+            docs: Default::default(),
         }
     }
     pub fn http(
@@ -23,50 +25,169 @@ impl Router {
         path: &'static str,
         f: fn(MethodRouter<()>) -> MethodRouter<()>,
     ) -> Router {
-        let MethodRouter(axum_method_router, deps) = f(MethodRouter::new());
-        self.axum_router = self
-            .axum_router
-            .route(&format!("/{}{path}", self.prefix), axum_method_router);
-        self.dependencies.extend(deps);
+        let MethodRouter {
+            router: axum_method_router,
+            dependencies,
+            docs,
+        } = f(MethodRouter::new());
+        let full_path = format!("/{}{path}", self.prefix);
+        self.axum_router = self.axum_router.route(&full_path, axum_method_router);
+        self.dependencies.extend(dependencies);
+        self.docs.extend(docs.into_iter().map(|mut doc| {
+            doc.path.clone_from(&full_path);
+            doc
+        }));
         self
     }
 }
 
-pub struct MethodRouter<S>(
-    axum::routing::MethodRouter<S>,
-    Vec<fn(&mut ServiceCollection)>,
-);
+pub struct MethodRouter<S> {
+    router: axum::routing::MethodRouter<S>,
+    dependencies: Vec<fn(&mut ServiceCollection)>,
+    docs: Vec<RouteDoc>,
+}
 
 impl<S: Send + Sync + 'static + Clone> MethodRouter<S> {
     fn new() -> Self {
-        Self(Default::default(), Default::default())
+        Self {
+            router: Default::default(),
+            dependencies: Default::default(),
+            docs: Default::default(),
+        }
     }
     pub fn get<T: 'static + DependencyProvider, H: Handler<T, S>>(mut self, handler: H) -> Self {
-        self.0 = self.0.get(handler);
-        self.1.push(|c: &mut ServiceCollection| {
-            c.with::<T::Dep>().register(|_| PhantomData::<T>);
-        });
+        self.router = self.router.get(handler);
+        self.add_dependency::<T>(Method::GET);
         self
     }
     pub fn post<T: 'static + DependencyProvider, H: Handler<T, S>>(mut self, handler: H) -> Self {
-        self.0 = self.0.post(handler);
-        self.1.push(|c: &mut ServiceCollection| {
-            c.with::<T::Dep>().register(|_| PhantomData::<T>);
-        });
+        self.router = self.router.post(handler);
+        self.add_dependency::<T>(Method::POST);
         self
     }
     pub fn put<T: 'static + DependencyProvider, H: Handler<T, S>>(mut self, handler: H) -> Self {
-        self.0 = self.0.put(handler);
-        self.1.push(|c: &mut ServiceCollection| {
-            c.with::<T::Dep>().register(|_| PhantomData::<T>);
-        });
+        self.router = self.router.put(handler);
+        self.add_dependency::<T>(Method::PUT);
         self
     }
     pub fn delete<T: 'static + DependencyProvider, H: Handler<T, S>>(mut self, handler: H) -> Self {
-        self.0 = self.0.delete(handler);
-        self.1.push(|c: &mut ServiceCollection| {
+        self.router = self.router.delete(handler);
+        self.add_dependency::<T>(Method::DELETE);
+        self
+    }
+
+    /// Short description of the most recently added method, which is shown in the OpenAPI specification
+    pub fn summary(mut self, summary: &'static str) -> Self {
+        if let Some(doc) = self.docs.last_mut() {
+            doc.summary = Some(summary);
+        }
+        self
+    }
+
+    /// Detailed description of the most recently added method, which is shown in the OpenAPI specification
+    pub fn description(mut self, description: &'static str) -> Self {
+        if let Some(doc) = self.docs.last_mut() {
+            doc.description = Some(description);
+        }
+        self
+    }
+
+    /// Full control over the OpenAPI operation of the most recently added method (e.g. request and response schemas)
+    /// Path parameters, summary and description are already set when `f` is called
+    #[cfg(feature = "openapi")]
+    pub fn operation(mut self, f: CustomizeOperation) -> Self {
+        if let Some(doc) = self.docs.last_mut() {
+            doc.customize = Some(f);
+        }
+        self
+    }
+
+    fn add_dependency<T: 'static + DependencyProvider>(&mut self, method: Method) {
+        self.dependencies.push(|c: &mut ServiceCollection| {
             c.with::<T::Dep>().register(|_| PhantomData::<T>);
         });
-        self
+        self.docs.push(RouteDoc::new(method));
+    }
+}
+
+#[cfg(feature = "openapi")]
+type CustomizeOperation =
+    fn(utoipa::openapi::path::OperationBuilder) -> utoipa::openapi::path::OperationBuilder;
+
+/// Metadata of a single route, which is collected by `register_web` to generate the API description
+#[derive(Clone, Debug)]
+pub struct RouteDoc {
+    path: String,
+    method: Method,
+    summary: Option<&'static str>,
+    description: Option<&'static str>,
+    #[cfg(feature = "openapi")]
+    pub(crate) customize: Option<CustomizeOperation>,
+}
+
+impl RouteDoc {
+    fn new(method: Method) -> Self {
+        Self {
+            path: String::new(),
+            method,
+            summary: None,
+            description: None,
+            #[cfg(feature = "openapi")]
+            customize: None,
+        }
+    }
+
+    /// Path in axum syntax relative to `/api` (e.g. `/recipe/:id`)
+    pub fn path(&self) -> &str {
+        &self.path
+    }
+
+    pub fn method(&self) -> &Method {
+        &self.method
+    }
+
+    pub fn summary(&self) -> Option<&'static str> {
+        self.summary
+    }
+
+    pub fn description(&self) -> Option<&'static str> {
+        self.description
+    }
+
+    /// Names of all path parameters (`:name` and `*name`)
+    pub fn path_parameters(&self) -> impl Iterator<Item = &str> {
+        self.path.split('/').filter_map(parameter_name)
+    }
+
+    /// Path in OpenAPI syntax (e.g. `/recipe/{id}`)
+    pub fn openapi_path(&self) -> String {
+        self.path
+            .split('/')
+            .map(|s| match parameter_name(s) {
+                Some(name) => format!("{{{name}}}"),
+                None => s.to_string(),
+            })
+            .collect::<Vec<_>>()
+            .join("/")
+    }
+}
+
+fn parameter_name(segment: &str) -> Option<&str> {
+    segment
+        .strip_prefix(':')
+        .or_else(|| segment.strip_prefix('*'))
+}
+
+/// All routes of a single `register_web` call
+#[derive(Clone, Debug, Default)]
+pub struct RouteDocs(std::sync::Arc<[RouteDoc]>);
+
+impl RouteDocs {
+    pub(crate) fn new(docs: Vec<RouteDoc>) -> Self {
+        Self(docs.into())
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = &RouteDoc> {
+        self.0.iter()
     }
 }
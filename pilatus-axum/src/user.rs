@@ -0,0 +1,140 @@
+use async_trait::async_trait;
+use axum::http::{header::AUTHORIZATION, StatusCode};
+use pilatus::{device::ActorSystem, Role, TransactionOptions, User, UserService};
+use serde::Deserialize;
+
+use super::{
+    extract::{FromRequestParts, InjectRegistered, Query},
+    http::request::Parts,
+};
+
+/// User of the bearer token in the `Authorization` header. Rejects requests without valid token
+///
+/// Browsers cannot set headers for WebSockets, so the token can be passed as `?access_token=` as well
+pub struct CurrentUser(pub User);
+
+/// Like `CurrentUser`, but for routes which are accessible anonymously as well
+pub struct OptionalUser(pub Option<User>);
+
+/// Header for the token of [`TransactionOptions::with_unlock_token`]
+pub const UNLOCK_TOKEN_HEADER: &str = "x-unlock-token";
+
+/// `TransactionOptions` from the query. The unlock token is only accepted in the [`UNLOCK_TOKEN_HEADER`].
+/// The options carry the origin of the [`WebActorSystem`], so parameter updates of running devices are marked as web requests.
+/// The authenticated user is recorded as author of the change. Like for the `WebActorSystem`, invalid tokens are treated as anonymous
+pub struct Transaction(pub TransactionOptions);
+
+/// ActorSystem whose messages are marked as `MessageOrigin::Web`, so interceptors can apply policies to web requests.
+/// Requests with invalid tokens are treated as anonymous, as authentication is up to the route
+pub struct WebActorSystem(pub ActorSystem);
+
+impl CurrentUser {
+    pub fn require(&self, role: Role) -> Result<(), (StatusCode, &'static str)> {
+        if self.0.role >= role {
+            Ok(())
+        } else {
+            Err((StatusCode::FORBIDDEN, "Insufficient role"))
+        }
+    }
+}
+
+#[async_trait]
+impl<S: Send + Sync> FromRequestParts<S> for OptionalUser {
+    type Rejection = (StatusCode, &'static str);
+
+    async fn from_request_parts(req: &mut Parts, s: &S) -> Result<Self, Self::Rejection> {
+        let Some(token) = request_token(req, s).await else {
+            return Ok(OptionalUser(None));
+        };
+        let InjectRegistered(users) =
+            InjectRegistered::<UserService>::from_request_parts(req, s).await?;
+        match users.authenticate(&token) {
+            Some(user) => Ok(OptionalUser(Some(user))),
+            None => Err((StatusCode::UNAUTHORIZED, "Invalid or expired token")),
+        }
+    }
+}
+
+#[async_trait]
+impl<S: Send + Sync> FromRequestParts<S> for CurrentUser {
+    type Rejection = (StatusCode, &'static str);
+
+    async fn from_request_parts(req: &mut Parts, s: &S) -> Result<Self, Self::Rejection> {
+        match OptionalUser::from_request_parts(req, s).await? {
+            OptionalUser(Some(user)) => Ok(CurrentUser(user)),
+            OptionalUser(None) => Err((StatusCode::UNAUTHORIZED, "Authentication required")),
+        }
+    }
+}
+
+#[async_trait]
+impl<S: Send + Sync> FromRequestParts<S> for Transaction {
+    type Rejection = (StatusCode, String);
+
+    async fn from_request_parts(req: &mut Parts, s: &S) -> Result<Self, Self::Rejection> {
+        let Query(options) = Query::<TransactionOptions>::from_request_parts(req, s)
+            .await
+            .map_err(|e| (StatusCode::BAD_REQUEST, e.body_text()))?;
+        let WebActorSystem(system) = WebActorSystem::from_request_parts(req, s)
+            .await
+            .map_err(|(code, msg)| (code, msg.to_string()))?;
+        let mut options = options.with_origin_of(&system);
+        if let Some(token) = request_token(req, s).await {
+            let InjectRegistered(users) =
+                InjectRegistered::<UserService>::from_request_parts(req, s)
+                    .await
+                    .map_err(|(code, msg)| (code, msg.to_string()))?;
+            if let Some(user) = users.authenticate(&token) {
+                options = options.with_author(user.name);
+            }
+        }
+        Ok(Transaction(
+            match req
+                .headers
+                .get(UNLOCK_TOKEN_HEADER)
+                .and_then(|x| x.to_str().ok())
+            {
+                Some(token) => options.with_unlock_token(token),
+                None => options,
+            },
+        ))
+    }
+}
+
+#[async_trait]
+impl<S: Send + Sync> FromRequestParts<S> for WebActorSystem {
+    type Rejection = (StatusCode, &'static str);
+
+    async fn from_request_parts(req: &mut Parts, s: &S) -> Result<Self, Self::Rejection> {
+        let InjectRegistered(system) =
+            InjectRegistered::<ActorSystem>::from_request_parts(req, s).await?;
+        let InjectRegistered(users) =
+            InjectRegistered::<UserService>::from_request_parts(req, s).await?;
+        let token = request_token(req, s).await;
+        Ok(WebActorSystem(
+            system.with_web_origin(users.as_ref(), token.as_deref()),
+        ))
+    }
+}
+
+/// Bearer token of the `Authorization` header or the `access_token` query parameter
+async fn request_token<S: Send + Sync>(req: &mut Parts, s: &S) -> Option<String> {
+    #[derive(Deserialize)]
+    struct AccessTokenRequest {
+        access_token: String,
+    }
+
+    let header_token = req
+        .headers
+        .get(AUTHORIZATION)
+        .and_then(|x| x.to_str().ok())
+        .and_then(|x| x.strip_prefix("Bearer "))
+        .map(|x| x.trim().to_string());
+    match header_token {
+        Some(x) => Some(x),
+        None => Query::<AccessTokenRequest>::from_request_parts(req, s)
+            .await
+            .ok()
+            .map(|Query(AccessTokenRequest { access_token })| access_token),
+    }
+}
@@ -2,6 +2,8 @@ use std::borrow::Cow;
 
 use serde::{ser::SerializeMap, Serialize};
 
+use crate::BasePath;
+
 #[derive(serde::Serialize)]
 pub struct DeviceTopicWebComponentLocation {
     pub device_type: &'static str,
@@ -32,20 +34,33 @@ enum WebComponentLocationKind {
     Raw(Cow<'static, str>, Cow<'static, str>),
 }
 
-pub struct WebComponentLocations(Vec<WebComponentLocation>);
+pub struct WebComponentLocations {
+    locations: Vec<WebComponentLocation>,
+    base_path: BasePath,
+}
+
+impl WebComponentLocations {
+    /// Absolute targets like `/plugins/camera.js` are served below the base path of the client
+    pub fn with_base_path(mut self, base_path: BasePath) -> Self {
+        self.base_path = base_path;
+        self
+    }
+}
+
 impl Serialize for WebComponentLocations {
     fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
     where
         S: serde::Serializer,
     {
         let mut map = serializer.serialize_map(None)?;
-        for item in self.0.iter() {
+        for item in self.locations.iter() {
             match &item.0 {
-                WebComponentLocationKind::DeviceTopic(d) => {
-                    map.serialize_entry(&format_args!("{}/{}", d.device_type, d.topic), d.target)?
-                }
+                WebComponentLocationKind::DeviceTopic(d) => map.serialize_entry(
+                    &format_args!("{}/{}", d.device_type, d.topic),
+                    &self.base_path.link(d.target),
+                )?,
                 WebComponentLocationKind::Raw(component_id, target) => {
-                    map.serialize_entry(component_id, target)?
+                    map.serialize_entry(component_id, &self.base_path.link(target))?
                 }
             };
         }
@@ -55,7 +70,10 @@ impl Serialize for WebComponentLocations {
 
 impl FromIterator<WebComponentLocation> for WebComponentLocations {
     fn from_iter<T: IntoIterator<Item = WebComponentLocation>>(iter: T) -> Self {
-        Self(iter.into_iter().collect())
+        Self {
+            locations: iter.into_iter().collect(),
+            base_path: BasePath::default(),
+        }
     }
 }
 
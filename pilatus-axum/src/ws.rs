@@ -1,5 +1,15 @@
 /// Pilatus provides it's own WebSocketUpgrade to avoid running Websockets when changeing recipes
-use std::{future::Future, sync::Arc};
+use std::{
+    borrow::Cow,
+    future::Future,
+    pin::Pin,
+    sync::{
+        atomic::{AtomicU32, Ordering},
+        Arc,
+    },
+    task::{Context, Poll},
+    time::Duration,
+};
 
 use async_trait::async_trait;
 use axum::{
@@ -7,9 +17,12 @@ use axum::{
     http::{self, request::Parts, StatusCode},
 };
 use futures::{
+    channel::oneshot,
+    future::Shared,
     stream::{AbortHandle, AbortRegistration},
-    FutureExt,
+    FutureExt, Sink, SinkExt,
 };
+use serde::Deserialize;
 
 use super::extract::InjectRegistered;
 
@@ -19,6 +32,11 @@ pub struct WebSocketUpgrade {
 }
 
 impl WebSocketUpgrade {
+    /// Pings for a single socket, configured by the [`WebSocketDropperService`]
+    pub fn keepalive(&self) -> Keepalive {
+        Keepalive::new(self.store.keepalive_config())
+    }
+
     // Get access to the raw WebSocketUpgrade, which is not expected to end
     // when switching ActiveRecipe
     pub fn into_inner(self) -> ws::WebSocketUpgrade {
@@ -39,11 +57,196 @@ impl WebSocketUpgrade {
             })
         })
     }
+
+    /// Like [`Self::on_upgrade`], but the callback is notified before the socket is dropped.
+    /// When the [`CloseSignal`] resolves, the callback should send [`CloseReason::into_message`] and return.
+    pub fn on_upgrade_with_close_signal<C, Fut>(
+        self,
+        callback: C,
+    ) -> axum::http::Response<axum::body::Body>
+    where
+        C: FnOnce(ws::WebSocket, CloseSignal) -> Fut + Send + 'static,
+        Fut: Future<Output = ()> + Send + 'static,
+    {
+        let dropper = self.store.create_dropper();
+        self.inner.on_upgrade(move |s| {
+            let signal = dropper.close_signal();
+            callback(s, signal).map(move |x| {
+                drop(dropper);
+                x
+            })
+        })
+    }
+}
+
+/// Sent in the close frame, so frontends can decide whether and when to reconnect.
+/// Codes are in the range reserved for applications (4000-4999)
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CloseReason {
+    /// The active recipe is replaced. Reconnecting succeeds as soon as the next recipe is running
+    RecipeChanged,
+    /// The device providing the data stopped, e.g. because it was restarted or disabled
+    DeviceStopped,
+    /// The server is going down. Clients shouldn't reconnect before it is reachable again
+    ShuttingDown,
+}
+
+impl CloseReason {
+    pub fn code(self) -> u16 {
+        match self {
+            CloseReason::RecipeChanged => 4000,
+            CloseReason::DeviceStopped => 4001,
+            CloseReason::ShuttingDown => 4002,
+        }
+    }
+
+    pub fn from_code(code: u16) -> Option<Self> {
+        [
+            CloseReason::RecipeChanged,
+            CloseReason::DeviceStopped,
+            CloseReason::ShuttingDown,
+        ]
+        .into_iter()
+        .find(|x| x.code() == code)
+    }
+
+    pub fn as_str(self) -> &'static str {
+        match self {
+            CloseReason::RecipeChanged => "recipe-changed",
+            CloseReason::DeviceStopped => "device-stopped",
+            CloseReason::ShuttingDown => "shutting-down",
+        }
+    }
+
+    pub fn into_message(self) -> ws::Message {
+        ws::Message::Close(Some(ws::CloseFrame {
+            code: self.code(),
+            reason: Cow::Borrowed(self.as_str()),
+        }))
+    }
+}
+
+/// Resolves once the server is about to drop the socket. Stays pending if the socket is dropped without a reason
+pub struct CloseSignal(Option<Shared<oneshot::Receiver<CloseReason>>>);
+
+impl Future for CloseSignal {
+    type Output = CloseReason;
+
+    fn poll(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        let Some(inner) = self.0.as_mut() else {
+            return Poll::Pending;
+        };
+        match inner.poll_unpin(cx) {
+            Poll::Ready(Ok(reason)) => Poll::Ready(reason),
+            Poll::Ready(Err(_)) => {
+                self.0 = None;
+                Poll::Pending
+            }
+            Poll::Pending => Poll::Pending,
+        }
+    }
+}
+
+/// Notifies all sockets which use the corresponding [`Dropper`]
+pub struct CloseNotifier(oneshot::Sender<CloseReason>);
+
+impl CloseNotifier {
+    pub fn notify(self, reason: CloseReason) {
+        let _ignore_if_no_socket_listens = self.0.send(reason);
+    }
 }
 
 // Receive handles which has to be Dropp
 pub trait WebSocketDropperService: Send + Sync {
     fn create_dropper(&self) -> Dropper;
+
+    fn keepalive_config(&self) -> KeepaliveConfig {
+        KeepaliveConfig::default()
+    }
+}
+
+/// Configured with `"websocket": { "ping_interval_ms": 15000, "max_missed_pongs": 2 }`.
+/// A `ping_interval_ms` of 0 disables pings
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct KeepaliveConfig {
+    pub ping_interval_ms: u64,
+    pub max_missed_pongs: u32,
+}
+
+impl Default for KeepaliveConfig {
+    fn default() -> Self {
+        Self {
+            ping_interval_ms: 15_000,
+            max_missed_pongs: 2,
+        }
+    }
+}
+
+/// Detects clients which vanished without closing the connection, e.g. due to NAT timeouts.
+/// Without it, such sockets keep their subscriptions forever. Clones share the missed pongs
+#[derive(Debug, Clone)]
+pub struct Keepalive {
+    config: KeepaliveConfig,
+    missed: Arc<AtomicU32>,
+}
+
+impl Keepalive {
+    pub fn new(config: KeepaliveConfig) -> Self {
+        Self {
+            config,
+            missed: Default::default(),
+        }
+    }
+
+    /// Call for each received message. Every message proves that the client is alive, not just pongs
+    pub fn on_message(&self, _msg: &ws::Message) {
+        self.missed.store(0, Ordering::Relaxed);
+    }
+
+    /// Waits for the next ping to send. Returns None, if the client missed too many pongs and should be disconnected.
+    /// Stays pending forever, if pings are disabled
+    pub async fn next_ping(&self) -> Option<ws::Message> {
+        if self.config.ping_interval_ms == 0 {
+            return futures::future::pending().await;
+        }
+        tokio::time::sleep(Duration::from_millis(self.config.ping_interval_ms)).await;
+        if self.missed.fetch_add(1, Ordering::Relaxed) >= self.config.max_missed_pongs {
+            None
+        } else {
+            Some(ws::Message::Ping(Vec::new()))
+        }
+    }
+
+    /// Sends `msg`, but gives up after the time in which a client may miss all its pongs. Clients which stopped
+    /// reading block the send forever otherwise, so their missed pongs would never be counted
+    pub async fn send<S: Sink<ws::Message> + Unpin>(
+        &self,
+        sink: &mut S,
+        msg: ws::Message,
+    ) -> Result<(), KeepaliveSendError> {
+        let send = sink
+            .send(msg)
+            .map(|r| r.map_err(|_| KeepaliveSendError::Closed));
+        if self.config.ping_interval_ms == 0 {
+            return send.await;
+        }
+        let deadline = Duration::from_millis(
+            self.config
+                .ping_interval_ms
+                .saturating_mul(u64::from(self.config.max_missed_pongs) + 1),
+        );
+        tokio::time::timeout(deadline, send)
+            .await
+            .unwrap_or(Err(KeepaliveSendError::TimedOut))
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum KeepaliveSendError {
+    Closed,
+    /// The client didn't accept the message within the deadline of [`Keepalive::send`]
+    TimedOut,
 }
 
 #[async_trait]
@@ -65,13 +268,28 @@ impl<S: Send + Sync> FromRequestParts<S> for WebSocketUpgrade {
 }
 
 #[derive(Clone)]
-#[allow(dead_code)]
-pub struct Dropper(Arc<InnerDropper>);
+pub struct Dropper {
+    _inner: Arc<InnerDropper>,
+    closing: Shared<oneshot::Receiver<CloseReason>>,
+}
 
 impl Dropper {
-    pub fn pair() -> (Self, AbortRegistration) {
+    /// The AbortRegistration is aborted when the last clone of the Dropper is dropped
+    pub fn pair() -> (Self, AbortRegistration, CloseNotifier) {
         let (handle, reg) = futures::future::AbortHandle::new_pair();
-        (Self(Arc::new(InnerDropper(handle))), reg)
+        let (tx, rx) = oneshot::channel();
+        (
+            Self {
+                _inner: Arc::new(InnerDropper(handle)),
+                closing: rx.shared(),
+            },
+            reg,
+            CloseNotifier(tx),
+        )
+    }
+
+    pub fn close_signal(&self) -> CloseSignal {
+        CloseSignal(Some(self.closing.clone()))
     }
 }
 
@@ -82,3 +300,72 @@ impl Drop for InnerDropper {
         self.0.abort();
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn close_reasons_roundtrip() {
+        for reason in [
+            CloseReason::RecipeChanged,
+            CloseReason::DeviceStopped,
+            CloseReason::ShuttingDown,
+        ] {
+            assert_eq!(Some(reason), CloseReason::from_code(reason.code()));
+        }
+        assert_eq!(None, CloseReason::from_code(1000));
+    }
+
+    #[test]
+    fn notify_all_sockets_of_dropper() {
+        let (dropper, _reg, notifier) = Dropper::pair();
+        let first = dropper.close_signal();
+        let second = dropper.clone().close_signal();
+        assert!(dropper.close_signal().now_or_never().is_none());
+        notifier.notify(CloseReason::RecipeChanged);
+        assert_eq!(Some(CloseReason::RecipeChanged), first.now_or_never());
+        assert_eq!(Some(CloseReason::RecipeChanged), second.now_or_never());
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn keepalive_gives_up_after_missed_pongs() {
+        let keepalive = Keepalive::new(KeepaliveConfig {
+            ping_interval_ms: 10,
+            max_missed_pongs: 1,
+        });
+        assert!(keepalive.next_ping().await.is_some());
+        keepalive.on_message(&ws::Message::Pong(Vec::new()));
+        assert!(keepalive.next_ping().await.is_some());
+        assert!(keepalive.next_ping().await.is_none());
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn keepalive_gives_up_on_clients_which_stopped_reading() {
+        let keepalive = Keepalive::new(KeepaliveConfig {
+            ping_interval_ms: 10,
+            max_missed_pongs: 1,
+        });
+        let (mut tx, rx) = futures::channel::mpsc::channel(0);
+        let ping = || ws::Message::Ping(Vec::new());
+        assert_eq!(Ok(()), keepalive.send(&mut tx, ping()).await);
+        let start = tokio::time::Instant::now();
+        assert_eq!(
+            Err(KeepaliveSendError::TimedOut),
+            keepalive.send(&mut tx, ping()).await
+        );
+        assert_eq!(Duration::from_millis(20), start.elapsed());
+        drop(rx);
+        assert_eq!(
+            Err(KeepaliveSendError::Closed),
+            keepalive.send(&mut tx, ping()).await
+        );
+    }
+
+    #[test]
+    fn dropped_notifier_never_resolves_signal() {
+        let (dropper, _reg, notifier) = Dropper::pair();
+        drop(notifier);
+        assert!(dropper.close_signal().now_or_never().is_none());
+    }
+}
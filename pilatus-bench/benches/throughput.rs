@@ -0,0 +1,99 @@
+use criterion::{criterion_group, criterion_main, Criterion, Throughput};
+use pilatus::StreamingImageFormat;
+use pilatus_bench::{
+    ActorRoundTrip, BroadcastFanOut, Downscale, ImageEncoding, Pyramid, RecipeCommit,
+};
+use pilatus_engineering::image::PyramidFilter;
+use tokio::runtime::Runtime;
+
+const WIDTH: u32 = 1280;
+const HEIGHT: u32 = 1024;
+/// Typical sensors of inspection cameras: 5MP and 12MP
+const LARGE_FRAMES: [(&str, u32, u32); 2] = [("5mp", 2448, 2048), ("12mp", 4000, 3000)];
+const PREVIEW_WIDTH: u32 = 640;
+
+fn runtime() -> Runtime {
+    tokio::runtime::Builder::new_multi_thread()
+        .enable_all()
+        .build()
+        .expect("Runtime must be creatable")
+}
+
+fn actor_round_trip(c: &mut Criterion) {
+    let rt = runtime();
+    let scenario = rt.block_on(async { ActorRoundTrip::new() });
+    c.bench_function("actor_round_trip", |b| {
+        b.to_async(&rt)
+            .iter(|| async { scenario.run().await.unwrap() })
+    });
+}
+
+fn broadcast_fan_out(c: &mut Criterion) {
+    let rt = runtime();
+    let mut group = c.benchmark_group("broadcast_fan_out");
+    for subscribers in [1, 4, 16] {
+        let scenario = rt
+            .block_on(BroadcastFanOut::new(subscribers, WIDTH, HEIGHT))
+            .unwrap();
+        let scenario = tokio::sync::Mutex::new(scenario);
+        group.throughput(Throughput::Elements(subscribers as u64));
+        group.bench_function(subscribers.to_string(), |b| {
+            b.to_async(&rt)
+                .iter(|| async { scenario.lock().await.run().await.unwrap() })
+        });
+    }
+    group.finish();
+}
+
+fn image_encoding(c: &mut Criterion) {
+    let mut group = c.benchmark_group("image_encoding");
+    group.throughput(Throughput::Bytes((WIDTH * HEIGHT) as u64));
+    for (name, format) in [
+        ("jpeg", StreamingImageFormat::Jpeg),
+        ("raw", StreamingImageFormat::Raw),
+    ] {
+        let scenario = ImageEncoding::new(format, WIDTH, HEIGHT);
+        group.bench_function(name, |b| b.iter(|| scenario.run().unwrap()));
+    }
+    group.finish();
+}
+
+fn downscale(c: &mut Criterion) {
+    let mut group = c.benchmark_group("downscale");
+    for (name, width, height) in LARGE_FRAMES {
+        group.throughput(Throughput::Bytes((width * height) as u64));
+        let preview = Downscale::new(width, height, PREVIEW_WIDTH);
+        group.bench_function(format!("area_{name}"), |b| {
+            b.iter(|| preview.run().unwrap())
+        });
+        for (filter_name, filter) in [
+            ("area", PyramidFilter::Area),
+            ("gaussian", PyramidFilter::Gaussian),
+        ] {
+            let pyramid = Pyramid::new(filter, 3, width, height);
+            group.bench_function(format!("pyramid_{filter_name}_{name}"), |b| {
+                b.iter(|| pyramid.run().unwrap())
+            });
+        }
+    }
+    group.finish();
+}
+
+fn recipe_commit(c: &mut Criterion) {
+    let rt = runtime();
+    let scenario = tokio::sync::Mutex::new(rt.block_on(RecipeCommit::new()).unwrap());
+    c.bench_function("recipe_commit", |b| {
+        b.to_async(&rt)
+            .iter(|| async { scenario.lock().await.run().await.unwrap() })
+    });
+}
+
+criterion_group!(
+    benches,
+    actor_round_trip,
+    broadcast_fan_out,
+    image_encoding,
+    downscale,
+    recipe_commit
+);
+criterion_main!(benches);
@@ -0,0 +1,122 @@
+//! Prints a JSON report of all scenarios, so results can be compared between builds.
+//! `cargo run --release -p pilatus-bench --features report -- [iterations]`
+
+use std::time::{Duration, Instant};
+
+use pilatus::StreamingImageFormat;
+use pilatus_bench::{
+    ActorRoundTrip, BroadcastFanOut, Downscale, ImageEncoding, Pyramid, RecipeCommit,
+};
+use pilatus_engineering::image::PyramidFilter;
+use serde::Serialize;
+
+const WIDTH: u32 = 1280;
+const HEIGHT: u32 = 1024;
+
+#[derive(Serialize)]
+struct Report {
+    iterations: usize,
+    scenarios: Vec<ScenarioReport>,
+}
+
+#[derive(Serialize)]
+struct ScenarioReport {
+    name: &'static str,
+    mean_ns: u64,
+    p50_ns: u64,
+    p99_ns: u64,
+    ops_per_sec: f64,
+}
+
+impl ScenarioReport {
+    fn new(name: &'static str, mut samples: Vec<Duration>) -> Self {
+        samples.sort();
+        let total: Duration = samples.iter().sum();
+        let mean = total / samples.len() as u32;
+        let percentile = |p: usize| samples[(samples.len() - 1) * p / 100].as_nanos() as u64;
+        Self {
+            name,
+            mean_ns: mean.as_nanos() as u64,
+            p50_ns: percentile(50),
+            p99_ns: percentile(99),
+            ops_per_sec: samples.len() as f64 / total.as_secs_f64(),
+        }
+    }
+}
+
+macro_rules! measure {
+    ($iterations:expr, $run:expr) => {{
+        // Warmup, e.g. for lazily allocated buffers
+        for _ in 0..($iterations / 10).max(1) {
+            $run;
+        }
+        let mut samples = Vec::with_capacity($iterations);
+        for _ in 0..$iterations {
+            let start = Instant::now();
+            $run;
+            samples.push(start.elapsed());
+        }
+        samples
+    }};
+}
+
+#[tokio::main]
+async fn main() -> anyhow::Result<()> {
+    let iterations = match std::env::args().nth(1) {
+        Some(x) => x.parse()?,
+        None => 1000,
+    };
+    anyhow::ensure!(iterations > 0, "At least one iteration is required");
+    let mut scenarios = Vec::new();
+
+    let actor = ActorRoundTrip::new();
+    scenarios.push(ScenarioReport::new(
+        "actor_round_trip",
+        measure!(iterations, actor.run().await?),
+    ));
+
+    for (name, subscribers) in [("broadcast_fan_out_1", 1), ("broadcast_fan_out_16", 16)] {
+        let mut broadcast = BroadcastFanOut::new(subscribers, WIDTH, HEIGHT).await?;
+        scenarios.push(ScenarioReport::new(
+            name,
+            measure!(iterations, broadcast.run().await?),
+        ));
+    }
+
+    for (name, format) in [
+        ("encode_jpeg", StreamingImageFormat::Jpeg),
+        ("encode_raw", StreamingImageFormat::Raw),
+    ] {
+        let encoding = ImageEncoding::new(format, WIDTH, HEIGHT);
+        scenarios.push(ScenarioReport::new(
+            name,
+            measure!(iterations, encoding.run()?),
+        ));
+    }
+
+    let preview = Downscale::new(4000, 3000, 640);
+    scenarios.push(ScenarioReport::new(
+        "downscale_area_12mp",
+        measure!(iterations, preview.run()?),
+    ));
+    let pyramid = Pyramid::new(PyramidFilter::Gaussian, 3, 4000, 3000);
+    scenarios.push(ScenarioReport::new(
+        "pyramid_gaussian_12mp",
+        measure!(iterations, pyramid.run()?),
+    ));
+
+    let mut commit = RecipeCommit::new().await?;
+    scenarios.push(ScenarioReport::new(
+        "recipe_commit",
+        measure!(iterations, commit.run().await?),
+    ));
+
+    println!(
+        "{}",
+        serde_json::to_string_pretty(&Report {
+            iterations,
+            scenarios
+        })?
+    );
+    Ok(())
+}
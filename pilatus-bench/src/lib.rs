@@ -0,0 +1,235 @@
+//! Scenarios shared by the criterion benches and the `perf-report` binary.
+//! Each scenario is set up once and then measured by calling `run` repeatedly.
+
+use std::num::NonZeroU32;
+
+use futures::{future::join_all, stream::BoxStream, FutureExt, StreamExt};
+use pilatus::{
+    device::{ActorMessage, ActorResult, ActorSystem, DeviceId},
+    DeviceConfig, ParameterUpdate, RecipeId, RecipeServiceTrait, StreamingImageFormat,
+    UntypedDeviceParamsWithVariables,
+};
+use pilatus_axum::image::StreamableImage;
+use pilatus_engineering::image::{
+    BroadcastImage, BroadcastState, DynamicImage, GetImageOk, ImageWithMeta, LumaImage,
+    PyramidFilter, RegisterBroadcastHandlersExtension, SubscribeImageMessage,
+};
+use pilatus_rt::RecipeServiceFassade;
+
+/// Ask a actor and wait for the response
+pub struct ActorRoundTrip {
+    system: ActorSystem,
+    id: DeviceId,
+}
+
+struct Ping(u64);
+
+impl ActorMessage for Ping {
+    type Output = u64;
+    type Error = ();
+}
+
+async fn handle_ping(_: &mut (), Ping(x): Ping) -> ActorResult<Ping> {
+    Ok(x + 1)
+}
+
+impl ActorRoundTrip {
+    /// Must be called within a tokio runtime, as the actor is spawned
+    pub fn new() -> Self {
+        let system = ActorSystem::new();
+        let id = DeviceId::new_v4();
+        tokio::spawn(system.register(id).add_handler(handle_ping).execute(()));
+        Self { system, id }
+    }
+
+    pub async fn run(&self) -> anyhow::Result<()> {
+        let result = self
+            .system
+            .ask(self.id, Ping(41))
+            .await
+            .map_err(|e| anyhow::anyhow!("{e:?}"))?;
+        anyhow::ensure!(result == 42, "Unexpected response {result}");
+        Ok(())
+    }
+}
+
+/// Broadcast a image to many subscribers and wait until all of them received it
+pub struct BroadcastFanOut {
+    subscribers: Vec<BoxStream<'static, BroadcastImage>>,
+}
+
+struct BroadcastActorState {
+    image: LumaImage,
+    broadcast: BroadcastState<(), BroadcastActorState>,
+}
+
+impl AsMut<BroadcastState<(), BroadcastActorState>> for BroadcastActorState {
+    fn as_mut(&mut self) -> &mut BroadcastState<(), BroadcastActorState> {
+        &mut self.broadcast
+    }
+}
+
+impl BroadcastFanOut {
+    pub async fn new(subscribers: usize, width: u32, height: u32) -> anyhow::Result<Self> {
+        let system = ActorSystem::new();
+        let id = DeviceId::new_v4();
+        let actor = system.register(id).add_broadcast_handlers();
+        let state = BroadcastActorState {
+            image: test_image(width, height),
+            broadcast: BroadcastState::new(
+                system
+                    .get_weak_untyped_sender(id)
+                    .map_err(|e| anyhow::anyhow!("{e:?}"))?,
+                |s: &mut BroadcastActorState| {
+                    let image = s.image.clone();
+                    async move { Ok(GetImageOk::with_hash(image, None)) }.boxed()
+                },
+                |_| {},
+            ),
+        };
+        tokio::spawn(actor.execute(state));
+
+        let mut streams = Vec::with_capacity(subscribers);
+        for _ in 0..subscribers {
+            streams.push(
+                system
+                    .ask(id, SubscribeImageMessage {})
+                    .await
+                    .map_err(|e| anyhow::anyhow!("{e:?}"))?,
+            );
+        }
+        Ok(Self {
+            subscribers: streams,
+        })
+    }
+
+    pub async fn run(&mut self) -> anyhow::Result<()> {
+        let received = join_all(self.subscribers.iter_mut().map(|s| s.next())).await;
+        anyhow::ensure!(
+            received.iter().all(Option::is_some),
+            "Broadcast stopped unexpectedly"
+        );
+        Ok(())
+    }
+}
+
+/// Encode a image for the streaming protocol
+pub struct ImageEncoding {
+    image: DynamicImage,
+    format: StreamingImageFormat,
+}
+
+impl ImageEncoding {
+    pub fn new(format: StreamingImageFormat, width: u32, height: u32) -> Self {
+        Self {
+            image: DynamicImage::Luma8(test_image(width, height)),
+            format,
+        }
+    }
+
+    /// Returns the number of encoded bytes
+    pub fn run(&self) -> anyhow::Result<usize> {
+        let image = ImageWithMeta::with_hash(self.image.clone(), None);
+        Ok((Ok(image), self.format).encode()?.len())
+    }
+}
+
+/// Downscale a frame to a preview width, keeping the aspect ratio
+pub struct Downscale {
+    image: DynamicImage,
+    width: NonZeroU32,
+    height: NonZeroU32,
+}
+
+impl Downscale {
+    pub fn new(width: u32, height: u32, preview_width: u32) -> Self {
+        let preview_height = (height as u64 * preview_width as u64 / width as u64).max(1) as u32;
+        Self {
+            image: DynamicImage::Luma8(test_image(width, height)),
+            width: NonZeroU32::new(preview_width).expect("Preview width must not be 0"),
+            height: NonZeroU32::new(preview_height).expect("Checked above"),
+        }
+    }
+
+    /// Returns the number of pixels of the preview
+    pub fn run(&self) -> anyhow::Result<usize> {
+        let (width, height) = self.image.downscale(self.width, self.height).dimensions();
+        Ok(width.get() as usize * height.get() as usize)
+    }
+}
+
+/// Build a multi-scale pyramid of a frame
+pub struct Pyramid {
+    image: DynamicImage,
+    levels: usize,
+    filter: PyramidFilter,
+}
+
+impl Pyramid {
+    pub fn new(filter: PyramidFilter, levels: usize, width: u32, height: u32) -> Self {
+        Self {
+            image: DynamicImage::Luma8(test_image(width, height)),
+            levels,
+            filter,
+        }
+    }
+
+    /// Returns the number of created levels
+    pub fn run(&self) -> anyhow::Result<usize> {
+        Ok(self.image.pyramid(self.levels, self.filter).len())
+    }
+}
+
+/// Update device parameters of the active recipe, which writes the recipe to disk
+pub struct RecipeCommit {
+    _dir: tempfile::TempDir,
+    service: RecipeServiceFassade,
+    recipe_id: RecipeId,
+    device_id: DeviceId,
+    counter: u64,
+}
+
+impl RecipeCommit {
+    pub async fn new() -> anyhow::Result<Self> {
+        let (dir, builder) = RecipeServiceFassade::create_temp_builder();
+        let service = builder.build();
+        let device_id = service
+            .add_device_to_active_recipe(DeviceConfig::mock(0u64))
+            .await?;
+        let recipe_id = service.get_active_id().await;
+        Ok(Self {
+            _dir: dir,
+            service,
+            recipe_id,
+            device_id,
+            counter: 0,
+        })
+    }
+
+    pub async fn run(&mut self) -> anyhow::Result<()> {
+        self.counter += 1;
+        self.service
+            .update_device_params(
+                self.recipe_id.clone(),
+                self.device_id,
+                ParameterUpdate {
+                    parameters: UntypedDeviceParamsWithVariables::from_serializable(self.counter)?,
+                    variables: Default::default(),
+                },
+            )
+            .await?;
+        Ok(())
+    }
+}
+
+/// Gradient, so jpeg compression has some work to do
+fn test_image(width: u32, height: u32) -> LumaImage {
+    let data = (0..height)
+        .flat_map(|y| (0..width).map(move |x| (x ^ y) as u8))
+        .collect();
+    LumaImage::new_vec(
+        data,
+        NonZeroU32::new(width).expect("Width must not be 0"),
+        NonZeroU32::new(height).expect("Height must not be 0"),
+    )
+}
@@ -0,0 +1,108 @@
+use std::time::Duration;
+
+use pilatus_engineering::image::{DynamicImage, GenericImage};
+use pilatus_engineering_camera::Exposure;
+
+use super::{Params, Roi};
+
+/// Mean value within `roi` relative to the value range of the image format. None if the ROI is outside of the image
+pub(super) fn brightness(image: &DynamicImage, roi: Option<&Roi>) -> Option<f64> {
+    match image {
+        DynamicImage::Luma8(x) => mean_in_roi(x, roi).map(|m| m / u8::MAX as f64),
+        DynamicImage::Luma16(x) => mean_in_roi(x, roi).map(|m| m / u16::MAX as f64),
+        _ => None,
+    }
+}
+
+fn mean_in_roi<T: Copy + Into<u64>>(image: &GenericImage<T, 1>, roi: Option<&Roi>) -> Option<f64> {
+    let (width, height) = image.dimensions();
+    let (width, height) = (width.get(), height.get());
+    let (x, y, roi_width, roi_height) = match roi {
+        Some(r) => (r.x, r.y, r.width.get(), r.height.get()),
+        None => (0, 0, width, height),
+    };
+    let right = x.saturating_add(roi_width).min(width);
+    let bottom = y.saturating_add(roi_height).min(height);
+    if x >= right || y >= bottom {
+        return None;
+    }
+    let buffer = image.buffer();
+    let sum: u64 = (y..bottom)
+        .flat_map(|row| {
+            let start = (row * width) as usize;
+            &buffer[start + x as usize..start + right as usize]
+        })
+        .map(|&v| v.into())
+        .sum();
+    Some(sum as f64 / ((right - x) as u64 * (bottom - y) as u64) as f64)
+}
+
+/// None if the brightness is within the hysteresis or the limits don't allow further changes
+pub(super) fn next_exposure(
+    params: &Params,
+    current: Exposure,
+    brightness: f64,
+) -> Option<Exposure> {
+    if (brightness - params.target).abs() <= params.hysteresis {
+        return None;
+    }
+    let ratio = if brightness > 0. {
+        (params.target / brightness).clamp(1. / params.max_step, params.max_step)
+    } else {
+        params.max_step
+    };
+    let desired = current.time.as_secs_f64() * 1e6 * current.gain * ratio;
+    let time_us = (desired / params.min_gain)
+        .clamp(params.min_exposure_us as f64, params.max_exposure_us as f64);
+    let gain = (desired / time_us).clamp(params.min_gain, params.max_gain);
+    let next = Exposure::new(Duration::from_micros(time_us.round() as u64), gain);
+    (next != current).then_some(next)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::num::NonZeroU32;
+
+    use pilatus_engineering::image::LumaImage;
+
+    use super::*;
+
+    #[test]
+    fn brightness_within_roi() {
+        let image = DynamicImage::Luma8(LumaImage::new_vec(
+            vec![0, 0, 0, 0, 255, 255, 0, 255, 255],
+            NonZeroU32::new(3).unwrap(),
+            NonZeroU32::new(3).unwrap(),
+        ));
+        let roi = Roi {
+            x: 1,
+            y: 1,
+            width: NonZeroU32::new(5).unwrap(),
+            height: NonZeroU32::new(5).unwrap(),
+        };
+        assert_eq!(Some(1.), brightness(&image, Some(&roi)));
+        assert!((brightness(&image, None).unwrap() - 4. / 9.).abs() < 1e-12);
+        let outside = Roi { x: 3, ..roi };
+        assert_eq!(None, brightness(&image, Some(&outside)));
+    }
+
+    #[test]
+    fn raise_exposure_time_before_gain() {
+        let params = Params::default();
+        let current = Exposure::new(Duration::from_millis(10), 1.);
+        let next = next_exposure(&params, current, 0.25).unwrap();
+        assert_eq!(Exposure::new(Duration::from_millis(20), 1.), next);
+
+        let at_limit = Exposure::new(Duration::from_micros(params.max_exposure_us), 1.);
+        let next = next_exposure(&params, at_limit, 0.1).unwrap();
+        assert_eq!(at_limit.time, next.time);
+        assert_eq!(params.max_step, next.gain);
+    }
+
+    #[test]
+    fn keep_exposure_within_hysteresis() {
+        let params = Params::default();
+        let current = Exposure::new(Duration::from_millis(10), 1.);
+        assert_eq!(None, next_exposure(&params, current, params.target + 0.01));
+    }
+}
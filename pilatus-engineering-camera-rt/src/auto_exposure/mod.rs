@@ -0,0 +1,208 @@
+//! Keeps the brightness of a camera's images at a target level by adjusting its exposure time and gain
+//!
+//! The exposure time is raised first, as gain amplifies noise too. Changes are sent with [`SetExposureMessage`],
+//! so the camera's recipe parameters remain untouched
+
+use std::{num::NonZeroU32, pin::pin, time::Duration};
+
+use futures::{future::Either, StreamExt};
+use minfac::{Registered, ServiceCollection};
+use pilatus::{
+    device::{
+        ActorResult, ActorSystem, DeviceContext, DeviceId, DeviceResult, DeviceValidationContext,
+    },
+    prelude::*,
+    UpdateParamsMessage, UpdateParamsMessageError,
+};
+use pilatus_engineering::image::{StreamImageError, SubscribeDynamicImageMessage};
+use pilatus_engineering_camera::{GetExposureMessage, SetExposureMessage};
+use serde::{Deserialize, Serialize};
+use tokio::sync::watch;
+use tracing::{debug, warn};
+
+mod controller;
+
+pub const DEVICE_TYPE: &str = "engineering-auto-exposure";
+
+/// Wait time before subscribing again, if the source failed or ended its stream
+const RETRY_DELAY: Duration = Duration::from_secs(1);
+
+pub(super) fn register_services(c: &mut ServiceCollection) {
+    c.with::<Registered<ActorSystem>>()
+        .register_device(DEVICE_TYPE, validator, device);
+}
+
+struct DeviceState {
+    params: watch::Sender<Params>,
+}
+
+#[derive(Debug, Deserialize, Serialize, Clone, PartialEq)]
+#[serde(deny_unknown_fields, default)]
+pub struct Params {
+    /// Camera which produces the images and receives the exposure changes. Nothing is adjusted without source
+    source: Option<DeviceId>,
+    /// Region to measure the brightness. The whole image is used if missing
+    roi: Option<Roi>,
+    /// Mean brightness relative to the value range of the image format (0..=1)
+    target: f64,
+    /// No adjustment is made, while the brightness differs less than this from `target`
+    hysteresis: f64,
+    min_exposure_us: u64,
+    max_exposure_us: u64,
+    min_gain: f64,
+    max_gain: f64,
+    /// Maximum factor the brightness is changed by a single adjustment. Prevents oscillation
+    max_step: f64,
+    /// Frames to skip after an adjustment, as they might have been acquired with the previous exposure
+    settle_frames: u32,
+}
+
+impl Default for Params {
+    fn default() -> Self {
+        Self {
+            source: None,
+            roi: None,
+            target: 0.5,
+            hysteresis: 0.05,
+            min_exposure_us: 100,
+            max_exposure_us: 100_000,
+            min_gain: 1.,
+            max_gain: 8.,
+            max_step: 2.,
+            settle_frames: 2,
+        }
+    }
+}
+
+#[derive(Debug, Deserialize, Serialize, Clone, PartialEq)]
+#[serde(deny_unknown_fields)]
+pub struct Roi {
+    x: u32,
+    y: u32,
+    width: NonZeroU32,
+    height: NonZeroU32,
+}
+
+async fn validator(ctx: DeviceValidationContext<'_>) -> Result<Params, UpdateParamsMessageError> {
+    let params = ctx.params_as::<Params>()?;
+    params.validate()?;
+    Ok(params)
+}
+
+impl Params {
+    fn validate(&self) -> Result<(), UpdateParamsMessageError> {
+        let invalid = |path, message: &str| {
+            Err(UpdateParamsMessageError::InvalidField {
+                path,
+                message: message.to_string(),
+            })
+        };
+        if !(self.target > 0. && self.target < 1.) {
+            return invalid("target", "must be between 0 and 1");
+        }
+        if !(self.hysteresis >= 0. && self.hysteresis < self.target) {
+            return invalid("hysteresis", "must be between 0 and target");
+        }
+        if self.min_exposure_us == 0 || self.min_exposure_us > self.max_exposure_us {
+            return invalid("min_exposure_us", "must be > 0 and <= max_exposure_us");
+        }
+        if !(self.min_gain > 0. && self.min_gain <= self.max_gain) {
+            return invalid("min_gain", "must be > 0 and <= max_gain");
+        }
+        if !(self.max_step > 1. && self.max_step.is_finite()) {
+            return invalid("max_step", "must be > 1");
+        }
+        Ok(())
+    }
+}
+
+async fn device(ctx: DeviceContext, params: Params, actor_system: ActorSystem) -> DeviceResult {
+    let (params, params_receiver) = watch::channel(params);
+    let actor = actor_system
+        .register(ctx.id)
+        .add_handler(DeviceState::update_params)
+        .execute(DeviceState { params });
+
+    // The control loop has no purpose anymore, once the device is stopped
+    futures::future::select(
+        pin!(actor),
+        pin!(control_loop(actor_system.clone(), params_receiver)),
+    )
+    .await;
+    Ok(())
+}
+
+impl DeviceState {
+    async fn update_params(
+        &mut self,
+        UpdateParamsMessage { params }: UpdateParamsMessage<Params>,
+    ) -> ActorResult<UpdateParamsMessage<Params>> {
+        self.params.send_replace(params);
+        Ok(())
+    }
+}
+
+/// Restarts the regulation whenever the params change
+async fn control_loop(actor_system: ActorSystem, mut params: watch::Receiver<Params>) {
+    loop {
+        let current = params.borrow_and_update().clone();
+        let Some(source) = current.source else {
+            if params.changed().await.is_err() {
+                return;
+            }
+            continue;
+        };
+        let regulation = pin!(regulate(&actor_system, source, &current));
+        match futures::future::select(regulation, pin!(params.changed())).await {
+            Either::Left((Ok(()), _)) => debug!("Source {source} restarted, subscribe again"),
+            Either::Left((Err(e), _)) => {
+                warn!("Auto exposure for {source} failed: {e:?}");
+                tokio::time::sleep(RETRY_DELAY).await;
+            }
+            Either::Right((Ok(()), _)) => debug!("Restart auto exposure with new params"),
+            Either::Right((Err(_), _)) => return,
+        }
+    }
+}
+
+/// Returns Ok, if the source restarted and can be subscribed again right away
+async fn regulate(
+    actor_system: &ActorSystem,
+    source: DeviceId,
+    params: &Params,
+) -> anyhow::Result<()> {
+    let mut exposure = actor_system
+        .ask(source, GetExposureMessage::default())
+        .await?;
+    let mut images = actor_system
+        .ask(source, SubscribeDynamicImageMessage::default())
+        .await?;
+    let mut skip = 0;
+    while let Some(item) = images.next().await {
+        let image = match item {
+            Ok(x) => x.image,
+            Err(StreamImageError::MissedItems(_)) => continue,
+            Err(StreamImageError::ProducerRestarted { .. }) => return Ok(()),
+            Err(e) => return Err(e.into()),
+        };
+        if skip > 0 {
+            skip -= 1;
+            continue;
+        }
+        let roi = params.roi.clone();
+        let Some(brightness) =
+            tokio::task::spawn_blocking(move || controller::brightness(&image, roi.as_ref()))
+                .await?
+        else {
+            anyhow::bail!("ROI {:?} is outside of the image", params.roi);
+        };
+        if let Some(next) = controller::next_exposure(params, exposure, brightness) {
+            debug!("Brightness {brightness:.3}, change exposure to {next:?}");
+            exposure = actor_system
+                .ask(source, SetExposureMessage::new(next))
+                .await?;
+            skip = params.settle_frames;
+        }
+    }
+    Err(anyhow::anyhow!("Source ended the stream"))
+}
@@ -0,0 +1,289 @@
+//! Republishes the image stream of another pilatus instance, so central stations can process the frames of edge boxes
+//! as if they came from a local camera
+//!
+//! Frames are requested as `StreamingImageFormat::Raw`, so they arrive without compression artifacts
+
+use std::{num::NonZeroU32, pin::pin, sync::Arc, time::Duration};
+
+use futures::{future::Either, StreamExt};
+use minfac::{Registered, ServiceCollection};
+use pilatus::{
+    device::{
+        ActorResult, ActorSystem, DeviceContext, DeviceId, DeviceResult, DeviceValidationContext,
+    },
+    prelude::*,
+    ChunkAssembler, ImageChunk, ImageFrame, ImageFrameCode, MissedItemsError, RawImage,
+    RawPixelKind, UpdateParamsMessage, UpdateParamsMessageError,
+};
+use pilatus_engineering::image::{
+    end_after_producer_restart, DynamicImage, GenericImage, ImageMeta, ImageWithMeta, LumaImage,
+    StableHash, StreamImageError, SubscribeDynamicImageMessage,
+};
+use serde::{Deserialize, Serialize};
+use tokio::sync::{broadcast, watch};
+use tokio_stream::wrappers::{errors::BroadcastStreamRecvError, BroadcastStream};
+use tokio_tungstenite::tungstenite::Message;
+use tracing::{debug, warn};
+
+pub const DEVICE_TYPE: &str = "engineering-stream-bridge";
+
+/// Wait time before connecting again, if the remote closed the connection or couldn't be reached
+const RETRY_DELAY: Duration = Duration::from_secs(1);
+
+type Frame = Result<ImageWithMeta<DynamicImage>, StreamImageError<DynamicImage>>;
+
+pub(super) fn register_services(c: &mut ServiceCollection) {
+    c.with::<Registered<ActorSystem>>()
+        .register_device(DEVICE_TYPE, validator, device);
+}
+
+struct DeviceState {
+    params: watch::Sender<Params>,
+    stream: broadcast::Sender<Frame>,
+}
+
+#[derive(Debug, Deserialize, Serialize, Clone, PartialEq, Default)]
+#[serde(deny_unknown_fields, default)]
+pub struct Params {
+    /// Base url of the remote instance, e.g. `ws://edge-1:8080`. Nothing is republished without url
+    url: Option<String>,
+    /// Device on the remote instance. If missing, the remote picks its only image producer
+    device_id: Option<DeviceId>,
+    /// Token of a user on the remote instance, if it requires authentication
+    access_token: Option<String>,
+}
+
+impl Params {
+    fn subscribe_url(&self) -> Option<String> {
+        let base = self.url.as_deref()?.trim_end_matches('/');
+        let mut url = format!("{base}/api/image/subscribe?format=Raw");
+        if let Some(id) = self.device_id {
+            url.push_str(&format!("&device_id={id}"));
+        }
+        if let Some(token) = &self.access_token {
+            url.push_str(&format!("&access_token={token}"));
+        }
+        Some(url)
+    }
+}
+
+async fn validator(ctx: DeviceValidationContext<'_>) -> Result<Params, UpdateParamsMessageError> {
+    let params = ctx.params_as::<Params>()?;
+    if let Some(url) = &params.url {
+        if !(url.starts_with("ws://") || url.starts_with("wss://")) {
+            return Err(UpdateParamsMessageError::InvalidField {
+                path: "url",
+                message: "must start with ws:// or wss://".into(),
+            });
+        }
+    }
+    Ok(params)
+}
+
+async fn device(ctx: DeviceContext, params: Params, actor_system: ActorSystem) -> DeviceResult {
+    let (params, params_receiver) = watch::channel(params);
+    let stream = broadcast::channel(2).0;
+    let actor = actor_system
+        .register(ctx.id)
+        .add_handler(DeviceState::update_params)
+        .add_handler(DeviceState::subscribe)
+        .execute(DeviceState {
+            params,
+            stream: stream.clone(),
+        });
+
+    // Nobody can subscribe anymore, once the device is stopped
+    futures::future::select(pin!(actor), pin!(bridge_loop(params_receiver, stream))).await;
+    Ok(())
+}
+
+impl DeviceState {
+    async fn update_params(
+        &mut self,
+        UpdateParamsMessage { params }: UpdateParamsMessage<Params>,
+    ) -> ActorResult<UpdateParamsMessage<Params>> {
+        self.params.send_replace(params);
+        Ok(())
+    }
+
+    async fn subscribe(
+        &mut self,
+        _msg: SubscribeDynamicImageMessage,
+    ) -> ActorResult<SubscribeDynamicImageMessage> {
+        Ok(end_after_producer_restart(
+            BroadcastStream::new(self.stream.subscribe()).map(|r| {
+                r.map_err(|BroadcastStreamRecvError::Lagged(e)| {
+                    StreamImageError::MissedItems(MissedItemsError::new(std::num::Saturating(
+                        e.min(u16::MAX as u64) as u16,
+                    )))
+                })?
+            }),
+        ))
+    }
+}
+
+/// Reconnects whenever the params change or the connection is lost
+async fn bridge_loop(mut params: watch::Receiver<Params>, stream: broadcast::Sender<Frame>) {
+    loop {
+        let Some(url) = params.borrow_and_update().subscribe_url() else {
+            if params.changed().await.is_err() {
+                return;
+            }
+            continue;
+        };
+        match futures::future::select(pin!(republish(&url, &stream)), pin!(params.changed())).await
+        {
+            Either::Left((result, _)) => {
+                if let Err(e) = result {
+                    warn!("Stream bridge to {} failed: {e:?}", redact(&url));
+                }
+                tokio::time::sleep(RETRY_DELAY).await;
+            }
+            Either::Right((Err(_), _)) => return,
+            Either::Right((Ok(()), _)) => {
+                debug!("Reconnect stream bridge with new params");
+                let new_hash = serde_json::to_vec(&*params.borrow())
+                    .ok()
+                    .map(StableHash::from_hashable);
+                _ = stream.send(Err(StreamImageError::ProducerRestarted { new_hash }));
+            }
+        }
+    }
+}
+
+async fn republish(url: &str, stream: &broadcast::Sender<Frame>) -> anyhow::Result<()> {
+    let (mut socket, _) = tokio_tungstenite::connect_async(url).await?;
+    debug!("Stream bridge connected to {}", redact(url));
+    let mut chunks = ChunkAssembler::default();
+    while let Some(message) = socket.next().await {
+        let data = match message? {
+            Message::Binary(data) => data,
+            Message::Close(reason) => anyhow::bail!("Remote closed the stream: {reason:?}"),
+            _ => continue,
+        };
+        let data = match ImageChunk::parse(&data) {
+            Some(chunk) => match chunks.push(chunk) {
+                Some(frame) => frame,
+                None => continue,
+            },
+            None => data,
+        };
+        match decode_frame(&data) {
+            // Without subscribers, frames are dropped
+            Ok(Some(frame)) => _ = stream.send(frame),
+            Ok(None) => {}
+            Err(e) => warn!("Couldn't decode frame: {e:?}"),
+        }
+    }
+    Ok(())
+}
+
+/// Returns None for frames which can't be republished, e.g. if the remote device failed
+fn decode_frame(data: &[u8]) -> anyhow::Result<Option<Frame>> {
+    let frame = ImageFrame::parse(data).ok_or_else(|| anyhow::anyhow!("Invalid frame"))?;
+    let main = || {
+        let image = frame
+            .iter_images()
+            .next()
+            .flatten()
+            .ok_or_else(|| anyhow::anyhow!("Frame without image"))?;
+        let raw = frame
+            .raw_image(image)
+            .ok_or_else(|| anyhow::anyhow!("Frame without raw image"))?;
+        decode_raw(raw)
+    };
+    // Meta is padded with zeros, so the images are aligned
+    let meta_len = frame
+        .meta
+        .iter()
+        .rposition(|x| *x != 0)
+        .map_or(0, |x| x + 1);
+    let meta = &frame.meta[..meta_len];
+    Ok(match frame.code {
+        ImageFrameCode::Ok => {
+            let meta: ImageMeta = serde_json::from_slice(meta)?;
+            Some(Ok(ImageWithMeta::with_meta(main()?, meta)))
+        }
+        ImageFrameCode::MissedItem => Some(Err(StreamImageError::MissedItems(
+            MissedItemsError::new(std::num::Saturating(1)),
+        ))),
+        ImageFrameCode::Processing => {
+            let error: String = serde_json::from_slice(meta)?;
+            Some(Err(StreamImageError::ProcessingError {
+                image: main()?,
+                error: Arc::new(anyhow::anyhow!("Remote: {error}")),
+            }))
+        }
+        ImageFrameCode::ProducerRestarted => Some(Err(StreamImageError::ProducerRestarted {
+            new_hash: serde_json::from_slice(meta)?,
+        })),
+        ImageFrameCode::ActorError | ImageFrameCode::Chunk => None,
+    })
+}
+
+fn decode_raw(raw: RawImage) -> anyhow::Result<DynamicImage> {
+    anyhow::ensure!(
+        raw.channels == 1,
+        "Only gray images are supported, got {} channels",
+        raw.channels
+    );
+    let width = NonZeroU32::new(raw.width).ok_or_else(|| anyhow::anyhow!("Empty image"))?;
+    let height = NonZeroU32::new(raw.height).ok_or_else(|| anyhow::anyhow!("Empty image"))?;
+    Ok(match raw.pixel_kind {
+        RawPixelKind::U8 => {
+            DynamicImage::Luma8(LumaImage::new_vec(raw.pixels.to_vec(), width, height))
+        }
+        RawPixelKind::U16 => DynamicImage::Luma16(GenericImage::<u16, 1>::new_vec(
+            raw.pixels
+                .chunks_exact(2)
+                .map(|x| u16::from_le_bytes([x[0], x[1]]))
+                .collect(),
+            width,
+            height,
+        )),
+    })
+}
+
+/// Tokens must not end up in logs
+fn redact(url: &str) -> &str {
+    url.split_once("&access_token=").map_or(url, |(x, _)| x)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn decode_raw_u16() {
+        let pixels = [1u16, 2, 3, 256, 512, 1024]
+            .iter()
+            .flat_map(|x| x.to_le_bytes())
+            .collect::<Vec<_>>();
+        let raw = RawImage {
+            pixel_kind: RawPixelKind::U16,
+            channels: 1,
+            width: 3,
+            height: 2,
+            pixels: &pixels,
+        };
+        let DynamicImage::Luma16(image) = decode_raw(raw).unwrap() else {
+            panic!("Expected 16 bit image");
+        };
+        assert_eq!(&[1, 2, 3, 256, 512, 1024], image.buffer());
+    }
+
+    #[test]
+    fn build_url_without_leaking_token() {
+        let params = Params {
+            url: Some("ws://edge-1:8080/".into()),
+            device_id: None,
+            access_token: Some("secret".into()),
+        };
+        let url = params.subscribe_url().unwrap();
+        assert_eq!(
+            "ws://edge-1:8080/api/image/subscribe?format=Raw&access_token=secret",
+            url
+        );
+        assert!(!redact(&url).contains("secret"));
+    }
+}
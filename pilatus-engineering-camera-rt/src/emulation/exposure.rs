@@ -0,0 +1,87 @@
+//! Simulated exposure: Images are scaled relative to the reference exposure, which leaves them untouched
+
+use std::time::Duration;
+
+use pilatus::device::ActorResult;
+use pilatus_engineering::image::{DynamicImage, GenericImage, LumaImage};
+use pilatus_engineering_camera::{Exposure, GetExposureMessage, SetExposureMessage};
+
+use super::DeviceState;
+
+const REFERENCE_TIME: Duration = Duration::from_millis(10);
+const MIN_TIME: Duration = Duration::from_micros(10);
+const MAX_TIME: Duration = Duration::from_secs(1);
+const MAX_GAIN: f64 = 16.;
+
+pub(super) fn reference_exposure() -> Exposure {
+    Exposure::new(REFERENCE_TIME, 1.)
+}
+
+impl DeviceState {
+    pub(super) async fn get_exposure(
+        &mut self,
+        _msg: GetExposureMessage,
+    ) -> ActorResult<GetExposureMessage> {
+        Ok(self.exposure)
+    }
+
+    pub(super) async fn set_exposure(
+        &mut self,
+        SetExposureMessage { exposure, .. }: SetExposureMessage,
+    ) -> ActorResult<SetExposureMessage> {
+        self.exposure = Exposure::new(
+            exposure.time.clamp(MIN_TIME, MAX_TIME),
+            exposure.gain.clamp(1., MAX_GAIN),
+        );
+        Ok(self.exposure)
+    }
+}
+
+pub(super) fn apply(exposure: Exposure, image: DynamicImage) -> DynamicImage {
+    let factor = exposure.time.as_secs_f64() / REFERENCE_TIME.as_secs_f64() * exposure.gain;
+    if factor == 1. {
+        return image;
+    }
+    match image {
+        DynamicImage::Luma8(x) => {
+            let (width, height) = x.dimensions();
+            let data = x
+                .buffer()
+                .iter()
+                .map(|&v| (v as f64 * factor).min(u8::MAX as f64) as u8)
+                .collect();
+            DynamicImage::Luma8(LumaImage::new_vec(data, width, height))
+        }
+        DynamicImage::Luma16(x) => {
+            let (width, height) = x.dimensions();
+            let data = x
+                .buffer()
+                .iter()
+                .map(|&v| (v as f64 * factor).min(u16::MAX as f64) as u16)
+                .collect();
+            DynamicImage::Luma16(GenericImage::new_vec(data, width, height))
+        }
+        x => x,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::num::NonZeroU32;
+
+    use super::*;
+
+    #[test]
+    fn double_exposure_doubles_brightness() {
+        let image = DynamicImage::Luma8(LumaImage::new_vec(
+            vec![10, 200],
+            NonZeroU32::new(2).unwrap(),
+            NonZeroU32::MIN,
+        ));
+        let DynamicImage::Luma8(scaled) = apply(Exposure::new(REFERENCE_TIME * 2, 1.), image)
+        else {
+            panic!("Expected Luma8");
+        };
+        assert_eq!(&[20, 255], scaled.buffer());
+    }
+}
@@ -0,0 +1,119 @@
+//! Failure modes of real cameras, so downstream devices and frontends can be tested against them
+//!
+//! Faults only depend on the params and the frame number, so test runs are reproducible
+
+use std::time::Duration;
+
+use pilatus::UpdateParamsMessageError;
+use serde::{Deserialize, Serialize};
+
+use super::synthetic::SplitMix64;
+
+#[derive(Debug, Deserialize, Serialize, Clone, Default, PartialEq)]
+#[serde(deny_unknown_fields, default)]
+pub struct FaultParams {
+    /// Every nth frame is not published. 0 disables dropping
+    drop_every_nth: u32,
+    /// Every nth frame is published `delay` milliseconds late. 0 disables delays
+    delay_every_nth: u32,
+    delay: u64,
+    /// Probability between 0 and 1, that a frame is published as ProcessingError
+    error_probability: f64,
+    /// Every nth frame, all subscriptions end and the device rejects new ones for `disappear_duration` milliseconds
+    disappear_every_nth: u32,
+    disappear_duration: u64,
+    /// Different seeds produce errors on different frames
+    seed: u64,
+}
+
+#[derive(Debug, PartialEq)]
+pub(super) enum Fault {
+    Drop,
+    Error,
+    Disappear(Duration),
+}
+
+impl FaultParams {
+    pub(super) fn validate(&self) -> Result<(), UpdateParamsMessageError> {
+        if !(0.0..=1.0).contains(&self.error_probability) {
+            return Err(UpdateParamsMessageError::InvalidField {
+                path: "faults.error_probability",
+                message: format!("{} is not within [0, 1]", self.error_probability),
+            });
+        }
+        Ok(())
+    }
+
+    /// If multiple faults apply to the same frame, disappearing wins over dropping, which wins over errors
+    pub(super) fn fault_for(&self, frame: u32) -> Option<Fault> {
+        if is_nth(self.disappear_every_nth, frame) {
+            Some(Fault::Disappear(Duration::from_millis(
+                self.disappear_duration,
+            )))
+        } else if is_nth(self.drop_every_nth, frame) {
+            Some(Fault::Drop)
+        } else if self.error_probability > 0.0 && self.random(frame) < self.error_probability {
+            Some(Fault::Error)
+        } else {
+            None
+        }
+    }
+
+    pub(super) fn delay_for(&self, frame: u32) -> Duration {
+        if is_nth(self.delay_every_nth, frame) {
+            Duration::from_millis(self.delay)
+        } else {
+            Duration::ZERO
+        }
+    }
+
+    /// Uniformly distributed within [0, 1)
+    fn random(&self, frame: u32) -> f64 {
+        let value = SplitMix64(self.seed ^ frame as u64).next();
+        (value >> 11) as f64 / (1u64 << 53) as f64
+    }
+}
+
+fn is_nth(n: u32, frame: u32) -> bool {
+    n != 0 && frame % n == 0
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn apply_faults_by_priority() {
+        let params = FaultParams {
+            drop_every_nth: 2,
+            disappear_every_nth: 3,
+            disappear_duration: 100,
+            ..Default::default()
+        };
+        assert_eq!(None, params.fault_for(1));
+        assert_eq!(Some(Fault::Drop), params.fault_for(2));
+        assert_eq!(
+            Some(Fault::Disappear(Duration::from_millis(100))),
+            params.fault_for(6)
+        );
+    }
+
+    #[test]
+    fn error_probability_is_roughly_met() {
+        let params = FaultParams {
+            error_probability: 0.25,
+            seed: 7,
+            ..Default::default()
+        };
+        let errors = (1..=1000)
+            .filter(|&frame| params.fault_for(frame) == Some(Fault::Error))
+            .count();
+        assert!((200..300).contains(&errors), "{errors} errors");
+        assert!(FaultParams {
+            error_probability: 1.5,
+            ..Default::default()
+        }
+        .validate()
+        .is_err());
+    }
+}
@@ -1,58 +1,113 @@
-use std::sync::Arc;
+use std::{sync::Arc, time::Duration};
 
+use fault::FaultParams;
 use minfac::{Registered, ServiceCollection};
-use pilatus::device::{HandlerResult, Step2, WithAbort};
+use pilatus::device::{HandlerResult, LiveBindings, Step2, WithProgress};
 use pilatus::{
-    device::{ActorSystem, DeviceContext, DeviceResult, DeviceValidationContext},
+    device::{
+        ActorSystem, DeviceContext, DeviceId, DeviceResult, DeviceStatistics,
+        DeviceValidationContext,
+    },
     prelude::*,
     UpdateParamsMessage, UpdateParamsMessageError,
 };
-use pilatus::{FileService, FileServiceBuilder};
-use pilatus_engineering::image::{DynamicImage, ImageWithMeta, StreamImageError};
+use pilatus::{FileService, FileServiceBuilder, HealthState, WorkerPools};
+use pilatus_engineering::image::{DynamicImage, ImageWithMeta, StableHash, StreamImageError};
+use pilatus_engineering_camera::Exposure;
 use publish_frame::PublisherState;
 use serde::{Deserialize, Serialize};
+use synthetic::SyntheticParams;
+use tokio::time::Instant;
 
+mod exposure;
+mod fault;
 mod list_collections;
+mod pause;
+mod playback;
+mod prefetch;
 mod publish_frame;
 mod record;
+mod statistics;
 mod subscribe;
+mod synthetic;
 
 pub const DEVICE_TYPE: &str = "engineering-emulation-camera";
 
 pub(super) fn register_services(c: &mut ServiceCollection) {
     record::register_services(c);
-    c.with::<(Registered<ActorSystem>, Registered<FileServiceBuilder>)>()
-        .register_device(DEVICE_TYPE, validator, device);
+    playback::register_services(c);
+    c.with::<(
+        Registered<ActorSystem>,
+        Registered<FileServiceBuilder>,
+        Registered<HealthState>,
+        Registered<WorkerPools>,
+    )>()
+    .register_device(DEVICE_TYPE, validator, device);
+    // Changing the interval doesn't restart the producer
+    c.register(|| LiveBindings::new(DEVICE_TYPE).with_param("/interval"));
 }
 
 struct DeviceState {
+    id: DeviceId,
     counter: u32,
+    /// Set by the maintenance mode. Frames are not published in the meantime
+    paused: bool,
+    /// Set while the device pretends to be gone due to fault injection
+    unavailable_until: Option<Instant>,
+    /// Last published frame, used to answer statistics requests without acquiring a new one
+    last_image: Option<DynamicImage>,
+    exposure: Exposure,
     stream: tokio::sync::broadcast::Sender<
         Result<ImageWithMeta<DynamicImage>, StreamImageError<DynamicImage>>,
     >,
     file_service: FileService<()>,
+    /// Builds the FileService of the prefetcher
+    file_service_builder: FileServiceBuilder,
+    /// Decodes the upcoming images in `EmulationMode::Files`. Created on the first publish
+    prefetcher: Option<prefetch::FramePrefetcher>,
+    pools: WorkerPools,
     publisher: Arc<PublisherState>,
     actor_system: ActorSystem,
+    health: HealthState,
+    /// `frames` and `errors` are counted across restarts
+    statistics: DeviceStatistics,
 }
 
 async fn validator(ctx: DeviceValidationContext<'_>) -> Result<Params, UpdateParamsMessageError> {
-    ctx.params_as::<Params>()
+    let params = ctx.params_as::<Params>()?;
+    if let EmulationMode::Synthetic(synthetic) = &params.mode {
+        synthetic.validate()?;
+    }
+    params.faults.validate()?;
+    Ok(params)
 }
 
 async fn device(
     ctx: DeviceContext,
     params: Params,
-    (actor_system, file_service_builder): (ActorSystem, FileServiceBuilder),
+    (actor_system, file_service_builder, health, pools): (
+        ActorSystem,
+        FileServiceBuilder,
+        HealthState,
+        WorkerPools,
+    ),
 ) -> DeviceResult {
     let id = ctx.id;
 
     actor_system
         .register(id)
-        .add_handler(WithAbort::new(DeviceState::record))
+        .add_handler(WithProgress::new(DeviceState::record))
         .add_handler(DeviceState::subscribe)
+        .add_handler(DeviceState::list_image_keys)
         .add_handler(DeviceState::publish_frame)
         .add_handler(DeviceState::update_params)
         .add_handler(DeviceState::list_collections)
+        .add_handler(DeviceState::list_recorded_frames)
+        .add_handler(DeviceState::get_recorded_frame)
+        .add_handler(DeviceState::get_statistics)
+        .add_handler(DeviceState::get_exposure)
+        .add_handler(DeviceState::set_exposure)
+        .add_handler(DeviceState::pause)
         .execute(DeviceState {
             publisher: Arc::new(PublisherState {
                 self_sender: actor_system
@@ -61,10 +116,23 @@ async fn device(
 
                 params,
             }),
-            file_service: file_service_builder.build(ctx.id),
+            file_service: file_service_builder.clone().build(ctx.id),
+            file_service_builder,
+            prefetcher: None,
+            pools,
             stream: tokio::sync::broadcast::channel(1).0,
+            id,
             counter: 0,
+            paused: false,
+            unavailable_until: None,
+            last_image: None,
+            exposure: exposure::reference_exposure(),
             actor_system: actor_system.clone(),
+            health,
+            statistics: DeviceStatistics::load(
+                ctx.state().clone(),
+                DeviceStatistics::DEFAULT_FLUSH_INTERVAL,
+            ),
         })
         .await;
 
@@ -76,12 +144,27 @@ impl DeviceState {
         &mut self,
         UpdateParamsMessage { params }: UpdateParamsMessage<Params>,
     ) -> impl HandlerResult<UpdateParamsMessage<Params>> {
+        if self.publisher.params.restarts_producer(&params) {
+            let new_hash = serde_json::to_vec(&params)
+                .ok()
+                .map(StableHash::from_hashable);
+            // Subscribers receive the restart as last item, because the old sender is dropped
+            let _ignore_without_subscribers = self
+                .stream
+                .send(Err(StreamImageError::ProducerRestarted { new_hash }));
+            self.stream = tokio::sync::broadcast::channel(1).0;
+        }
+        if self.publisher.params.restarts_producer(&params)
+            || self.publisher.params.prefetch != params.prefetch
+        {
+            self.prefetcher = None;
+        }
         let mutable = Arc::make_mut(&mut self.publisher);
         mutable.params = params;
         let weak = Arc::downgrade(&self.publisher);
 
         Step2(async {
-            PublisherState::send_delayed(weak).await;
+            PublisherState::send_delayed(weak, Duration::ZERO).await;
             Ok(())
         })
     }
@@ -92,6 +175,17 @@ impl DeviceState {
 pub struct Params {
     interval: u64,
     file_ending: String,
+    /// Number of files which are decoded ahead in `EmulationMode::Files`
+    prefetch: usize,
+    mode: EmulationMode,
+    faults: FaultParams,
+}
+
+impl Params {
+    /// Subscribers would receive images of another source. Changing the interval or faults keeps the stream
+    fn restarts_producer(&self, new: &Params) -> bool {
+        self.mode != new.mode || self.file_ending != new.file_ending
+    }
 }
 
 impl Default for Params {
@@ -99,10 +193,23 @@ impl Default for Params {
         Self {
             interval: 500,
             file_ending: Default::default(),
+            prefetch: 3,
+            mode: Default::default(),
+            faults: Default::default(),
         }
     }
 }
 
+#[derive(Debug, Deserialize, Serialize, Clone, Default, PartialEq)]
+#[serde(rename_all = "snake_case", tag = "type")]
+pub enum EmulationMode {
+    /// Cycles through the images in the device folder whose name ends with `file_ending`
+    #[default]
+    Files,
+    /// Generated images, which don't require any files
+    Synthetic(SyntheticParams),
+}
+
 pub fn create_default_device_config() -> pilatus::DeviceConfig {
     pilatus::DeviceConfig::new_unchecked(DEVICE_TYPE, DEVICE_TYPE, Params::default())
 }
@@ -0,0 +1,24 @@
+//! Paused cameras keep their subscribers, but don't publish until they are resumed
+
+use std::{sync::Arc, time::Duration};
+
+use pilatus::device::{HandlerResult, PauseProducerMessage, Step2};
+
+use super::{publish_frame::PublisherState, DeviceState};
+
+impl DeviceState {
+    pub(super) async fn pause(
+        &mut self,
+        PauseProducerMessage { paused }: PauseProducerMessage,
+    ) -> impl HandlerResult<PauseProducerMessage> {
+        let resumed = self.paused && !paused;
+        self.paused = paused;
+        let weak = Arc::downgrade(&self.publisher);
+        Step2(async move {
+            if resumed {
+                PublisherState::send_delayed(weak, Duration::ZERO).await;
+            }
+            Ok(())
+        })
+    }
+}
@@ -0,0 +1,277 @@
+//! Browse recorded collections from a browser, without copying the whole dataset from the machine
+
+use std::io::Cursor;
+
+use async_zip::{base::write::ZipFileWriter, Compression, ZipEntryBuilder};
+use futures::{AsyncWrite, TryStreamExt};
+use minfac::ServiceCollection;
+use pilatus::{
+    device::{ActorMessage, ActorResult, ActorSystem, DeviceId},
+    GenericConfig, Name, RelativeDirectoryPathBuf, RelativeFilePath,
+};
+use pilatus_axum::{
+    extract::{InjectRegistered, Json, Path, Query, WebActorSystem},
+    http::StatusCode,
+    AppendHeaders, IntoResponse, IoStreamBody, ServiceCollectionExtensions, SpoolConfig,
+};
+use serde::Deserialize;
+
+use super::{list_collections::ListCollectionsMessage, DeviceState};
+
+pub(super) fn register_services(c: &mut ServiceCollection) {
+    #[rustfmt::skip]
+    c.register_web("engineering/emulation-camera", |r| r
+        .http("/:device_id/collections", |m| m
+            .get(list_collections_web)
+            .summary("Names of the recorded collections"))
+        .http("/:device_id/collections/:collection_name", |m| m
+            .get(list_frames_web)
+            .summary("Frames of a collection, oldest first"))
+        .http("/:device_id/collections/:collection_name/zip", |m| m
+            .get(download_collection_web)
+            .summary("Download all frames of a collection"))
+        .http("/:device_id/collections/:collection_name/frame/*frame", |m| m
+            .get(get_frame_web)
+            .summary("Single frame as png or jpeg. Query: `?format=jpeg`"))
+    )
+}
+
+/// Paths are relative to the collection
+pub(super) struct ListRecordedFramesMessage {
+    pub collection: Name,
+}
+
+impl ActorMessage for ListRecordedFramesMessage {
+    type Output = Vec<RelativeFilePath>;
+    type Error = anyhow::Error;
+}
+
+/// Returns the frame as it was recorded (png)
+pub(super) struct GetRecordedFrameMessage {
+    pub collection: Name,
+    pub frame: RelativeFilePath,
+}
+
+impl ActorMessage for GetRecordedFrameMessage {
+    type Output = Vec<u8>;
+    type Error = anyhow::Error;
+}
+
+impl DeviceState {
+    pub(super) async fn list_recorded_frames(
+        &mut self,
+        ListRecordedFramesMessage { collection }: ListRecordedFramesMessage,
+    ) -> ActorResult<ListRecordedFramesMessage> {
+        Ok(self.recorded_frames(&collection).await?)
+    }
+
+    pub(super) async fn get_recorded_frame(
+        &mut self,
+        GetRecordedFrameMessage { collection, frame }: GetRecordedFrameMessage,
+    ) -> ActorResult<GetRecordedFrameMessage> {
+        let path =
+            RelativeFilePath::new(std::path::Path::new(collection.as_str()).join(frame.get_path()))
+                .map_err(anyhow::Error::from)?;
+        Ok(self
+            .file_service
+            .get_file(&path)
+            .await
+            .map_err(anyhow::Error::from)?)
+    }
+
+    async fn recorded_frames(&self, collection: &Name) -> anyhow::Result<Vec<RelativeFilePath>> {
+        let root = RelativeDirectoryPathBuf::new(collection.as_str())?;
+        let mut frames = Vec::new();
+        let mut pending = vec![root.clone()];
+        while let Some(dir) = pending.pop() {
+            for file in self.file_service.list_files(&dir).await? {
+                frames.push(RelativeFilePath::new(file.get_path().strip_prefix(&root)?)?);
+            }
+            pending.extend(
+                self.file_service
+                    .stream_directories(&dir)
+                    .try_collect::<Vec<_>>()
+                    .await?,
+            );
+        }
+        // File names start with the recording time
+        frames.sort_by(|a, b| a.file_name().cmp(b.file_name()));
+        Ok(frames)
+    }
+}
+
+#[derive(Debug, Default, Clone, Copy, Deserialize)]
+#[serde(rename_all = "lowercase")]
+enum FrameFormat {
+    #[default]
+    Png,
+    Jpeg,
+}
+
+impl FrameFormat {
+    fn content_type(self) -> &'static str {
+        match self {
+            FrameFormat::Png => "image/png",
+            FrameFormat::Jpeg => "image/jpeg",
+        }
+    }
+
+    /// Frames are recorded as png, so only jpeg requires a conversion
+    fn convert(self, png: Vec<u8>) -> anyhow::Result<Vec<u8>> {
+        match self {
+            FrameFormat::Png => Ok(png),
+            FrameFormat::Jpeg => {
+                let image = image::load_from_memory(&png)?;
+                // JPEG has neither 16bit nor alpha support
+                let image = if image.color().has_color() {
+                    image::DynamicImage::ImageRgb8(image.to_rgb8())
+                } else {
+                    image::DynamicImage::ImageLuma8(image.to_luma8())
+                };
+                let mut buf = Vec::new();
+                image.write_to(&mut Cursor::new(&mut buf), image::ImageFormat::Jpeg)?;
+                Ok(buf)
+            }
+        }
+    }
+}
+
+#[derive(Deserialize)]
+struct FrameQuery {
+    #[serde(default)]
+    format: FrameFormat,
+}
+
+async fn list_collections_web(
+    Path(device_id): Path<DeviceId>,
+    WebActorSystem(actor_system): WebActorSystem,
+) -> Result<impl IntoResponse, (StatusCode, String)> {
+    actor_system
+        .ask(device_id, ListCollectionsMessage)
+        .await
+        .map(Json)
+        .map_err(|e| (StatusCode::BAD_REQUEST, e.to_string()))
+}
+
+async fn list_frames_web(
+    Path((device_id, collection)): Path<(DeviceId, Name)>,
+    WebActorSystem(actor_system): WebActorSystem,
+) -> Result<impl IntoResponse, (StatusCode, String)> {
+    actor_system
+        .ask(device_id, ListRecordedFramesMessage { collection })
+        .await
+        .map(Json)
+        .map_err(|e| (StatusCode::BAD_REQUEST, e.to_string()))
+}
+
+async fn get_frame_web(
+    Path((device_id, collection, frame)): Path<(DeviceId, Name, RelativeFilePath)>,
+    Query(FrameQuery { format }): Query<FrameQuery>,
+    WebActorSystem(actor_system): WebActorSystem,
+) -> Result<impl IntoResponse, (StatusCode, String)> {
+    let name = frame.file_name().trim_end_matches(".png").to_string();
+    let png = actor_system
+        .ask(device_id, GetRecordedFrameMessage { collection, frame })
+        .await
+        .map_err(|e| (StatusCode::NOT_FOUND, e.to_string()))?;
+    let encoded = pilatus::execute_blocking(move || format.convert(png))
+        .await
+        .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
+    let extension = match format {
+        FrameFormat::Png => "png",
+        FrameFormat::Jpeg => "jpg",
+    };
+    Ok((
+        AppendHeaders([
+            ("Content-Type", format.content_type().to_string()),
+            (
+                "Content-Disposition",
+                format!("inline; filename=\"{name}.{extension}\""),
+            ),
+        ]),
+        encoded,
+    ))
+}
+
+async fn download_collection_web(
+    Path((device_id, collection)): Path<(DeviceId, Name)>,
+    WebActorSystem(actor_system): WebActorSystem,
+    InjectRegistered(config): InjectRegistered<GenericConfig>,
+) -> Result<impl IntoResponse, (StatusCode, String)> {
+    let frames = actor_system
+        .ask(
+            device_id,
+            ListRecordedFramesMessage {
+                collection: collection.clone(),
+            },
+        )
+        .await
+        .map_err(|e| (StatusCode::BAD_REQUEST, e.to_string()))?;
+
+    Ok((
+        AppendHeaders([(
+            "Content-Disposition",
+            format!("attachment; filename=\"{collection}.zip\""),
+        )]),
+        IoStreamBody::with_spooled_writer(
+            SpoolConfig::default().with_dir(config.temp_dir()),
+            move |w| zip_frames(actor_system, device_id, collection, frames, w),
+        ),
+    ))
+}
+
+/// Frames are fetched one by one. Slow clients are buffered by the spool instead of blocking the device
+async fn zip_frames(
+    actor_system: ActorSystem,
+    device_id: DeviceId,
+    collection: Name,
+    frames: Vec<RelativeFilePath>,
+    writer: impl AsyncWrite + Unpin + Send,
+) -> anyhow::Result<()> {
+    let mut zip = ZipFileWriter::new(writer);
+    for frame in frames {
+        let entry_path = frame
+            .get_path()
+            .to_str()
+            .ok_or_else(|| anyhow::anyhow!("invalid UTF-8"))?
+            .replace('\\', "/");
+        let data = actor_system
+            .ask(
+                device_id,
+                GetRecordedFrameMessage {
+                    collection: collection.clone(),
+                    frame,
+                },
+            )
+            .await?;
+        // Png is compressed already
+        let entry = ZipEntryBuilder::new(entry_path.into(), Compression::Stored).build();
+        zip.write_entry_whole(entry, &data).await?;
+    }
+    zip.close().await?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn convert_png_to_jpeg() {
+        let mut png = Vec::new();
+        image::DynamicImage::ImageLuma16(image::ImageBuffer::from_pixel(
+            4,
+            2,
+            image::Luma([1000u16]),
+        ))
+        .write_to(&mut Cursor::new(&mut png), image::ImageFormat::Png)
+        .unwrap();
+
+        assert_eq!(png, FrameFormat::Png.convert(png.clone()).unwrap());
+        let jpeg = FrameFormat::Jpeg.convert(png).unwrap();
+        assert_eq!(
+            (4, 2),
+            image::GenericImageView::dimensions(&image::load_from_memory(&jpeg).unwrap())
+        );
+    }
+}
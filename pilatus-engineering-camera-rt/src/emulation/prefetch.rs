@@ -0,0 +1,196 @@
+use futures::StreamExt;
+use pilatus::{FileService, RelativeDirectoryPath, RelativeFilePath, WorkerPool};
+use pilatus_engineering::image::DynamicImage;
+use tokio::{sync::mpsc, task::JoinHandle};
+
+pub(super) struct PrefetchedFrame {
+    /// Position of the file within the sorted files of the device folder
+    pub index: usize,
+    pub image: DynamicImage,
+}
+
+/// Reads and decodes the upcoming files in the background, so large images don't block the actor
+/// and delay the next publish. Stops after the first error, which is returned by `next`
+pub(super) struct FramePrefetcher {
+    receiver: mpsc::Receiver<anyhow::Result<PrefetchedFrame>>,
+    task: JoinHandle<()>,
+}
+
+impl FramePrefetcher {
+    /// At most `capacity` frames are decoded ahead
+    pub fn spawn(
+        file_service: FileService<()>,
+        file_ending: String,
+        pool: WorkerPool,
+        capacity: usize,
+    ) -> Self {
+        let (sender, receiver) = mpsc::channel(capacity.max(1));
+        let task = tokio::spawn(prefetch(file_service, file_ending, pool, sender));
+        Self { receiver, task }
+    }
+
+    pub async fn next(&mut self) -> anyhow::Result<PrefetchedFrame> {
+        self.receiver
+            .recv()
+            .await
+            .unwrap_or_else(|| Err(anyhow::anyhow!("Prefetching stopped")))
+    }
+}
+
+impl Drop for FramePrefetcher {
+    fn drop(&mut self) {
+        self.task.abort();
+    }
+}
+
+async fn prefetch(
+    file_service: FileService<()>,
+    file_ending: String,
+    pool: WorkerPool,
+    sender: mpsc::Sender<anyhow::Result<PrefetchedFrame>>,
+) {
+    loop {
+        // Listed each round, so added or removed files show up after the current round
+        let files = list_files(&file_service, &file_ending).await;
+        if files.is_empty() {
+            let _ignore_dropped_receiver = sender
+                .send(Err(anyhow::anyhow!("Stop streaming, there is no file")))
+                .await;
+            return;
+        }
+        for (index, path) in files.into_iter().enumerate() {
+            let frame = decode(&file_service, &path, &pool)
+                .await
+                .map(|image| PrefetchedFrame { index, image });
+            let is_err = frame.is_err();
+            if sender.send(frame).await.is_err() || is_err {
+                return;
+            }
+        }
+    }
+}
+
+async fn list_files(file_service: &FileService<()>, file_ending: &str) -> Vec<RelativeFilePath> {
+    let mut files = file_service
+        .stream_files(RelativeDirectoryPath::root())
+        .filter_map(|x| async {
+            let entry = x.ok()?;
+            entry.file_name().ends_with(file_ending).then_some(entry)
+        })
+        .collect::<Vec<_>>()
+        .await;
+    files.sort_by(|a, b| a.file_name().cmp(b.file_name()));
+    files
+}
+
+async fn decode(
+    file_service: &FileService<()>,
+    path: &RelativeFilePath,
+    pool: &WorkerPool,
+) -> anyhow::Result<DynamicImage> {
+    let data = file_service.get_file(path).await?;
+    let image = pool
+        .execute_blocking(move || image::load_from_memory(&data))
+        .await
+        .map_err(|e| anyhow::anyhow!("Cannot decode {}: {e}", path.file_name()))?;
+    Ok(image.try_into()?)
+}
+
+#[cfg(test)]
+mod tests {
+    use pilatus::{device::DeviceId, WorkerPoolKind};
+    use pilatus_rt::TokioFileService;
+
+    use super::*;
+
+    /// The width identifies the frame
+    fn png(width: u32) -> Vec<u8> {
+        let mut data = Vec::new();
+        image::DynamicImage::ImageLuma8(image::ImageBuffer::from_pixel(
+            width,
+            1,
+            image::Luma([42u8]),
+        ))
+        .write_to(
+            &mut std::io::Cursor::new(&mut data),
+            image::ImageFormat::Png,
+        )
+        .unwrap();
+        data
+    }
+
+    async fn file_service(dir: &std::path::Path, files: &[(&str, Vec<u8>)]) -> FileService<()> {
+        let mut file_service = TokioFileService::builder(dir).build(DeviceId::new_v4());
+        for (name, data) in files {
+            file_service
+                .add_file_unchecked(&RelativeFilePath::new(*name).unwrap(), data)
+                .await
+                .unwrap();
+        }
+        file_service
+    }
+
+    fn spawn(file_service: FileService<()>) -> FramePrefetcher {
+        FramePrefetcher::spawn(
+            file_service,
+            ".png".into(),
+            WorkerPool::new(WorkerPoolKind::Io, 1),
+            2,
+        )
+    }
+
+    async fn next_frame(prefetcher: &mut FramePrefetcher) -> (usize, u32) {
+        let frame = prefetcher.next().await.unwrap();
+        (frame.index, frame.image.dimensions().0.get())
+    }
+
+    #[tokio::test]
+    async fn prefetch_sorted_files_and_restart_index_each_round() {
+        let dir = tempfile::tempdir().unwrap();
+        let file_service = file_service(
+            dir.path(),
+            &[
+                ("b.png", png(2)),
+                ("a.png", png(1)),
+                ("ignored.txt", b"no image".to_vec()),
+                ("c.png", png(3)),
+            ],
+        )
+        .await;
+        let mut prefetcher = spawn(file_service);
+        let mut frames = Vec::new();
+        for _ in 0..4 {
+            frames.push(next_frame(&mut prefetcher).await);
+        }
+        assert_eq!(vec![(0, 1), (1, 2), (2, 3), (0, 1)], frames);
+    }
+
+    #[tokio::test]
+    async fn stop_after_first_error() {
+        let dir = tempfile::tempdir().unwrap();
+        let file_service = file_service(
+            dir.path(),
+            &[
+                ("a.png", png(1)),
+                ("b.png", b"broken".to_vec()),
+                ("c.png", png(3)),
+            ],
+        )
+        .await;
+        let mut prefetcher = spawn(file_service);
+        assert_eq!((0, 1), next_frame(&mut prefetcher).await);
+        let error = prefetcher.next().await.err().unwrap().to_string();
+        assert!(error.contains("b.png"), "{error}");
+        let stopped = prefetcher.next().await.err().unwrap().to_string();
+        assert_eq!("Prefetching stopped", stopped);
+    }
+
+    #[tokio::test]
+    async fn report_missing_files() {
+        let dir = tempfile::tempdir().unwrap();
+        let mut prefetcher = spawn(file_service(dir.path(), &[]).await);
+        assert!(prefetcher.next().await.is_err());
+        let stopped = prefetcher.next().await.err().unwrap().to_string();
+        assert_eq!("Prefetching stopped", stopped);
+    }
+}
@@ -1,14 +1,23 @@
-use std::{collections::BinaryHeap, sync::Weak, time::Duration};
+use std::{
+    sync::{Arc, Weak},
+    time::Duration,
+};
 
-use futures::StreamExt;
 use pilatus::{
     device::{ActorMessage, HandlerResult, Step2, WeakUntypedActorMessageSender},
-    RelativeDirectoryPath, RelativeFilePath,
+    WorkerPoolKind,
+};
+use pilatus_engineering::image::{
+    DynamicImage as PilatusDynamicImage, ImageWithMeta, StreamImageError,
 };
-use pilatus_engineering::image::{DynamicImage as PilatusDynamicImage, ImageWithMeta};
-use tracing::warn;
+use tokio::time::Instant;
+use tracing::{debug, warn};
 
-use super::{DeviceState, Params};
+use super::{
+    fault::Fault,
+    prefetch::{FramePrefetcher, PrefetchedFrame},
+    DeviceState, EmulationMode, Params,
+};
 
 pub(super) struct PublishImageMessage(pub Weak<PublisherState>);
 
@@ -22,16 +31,41 @@ impl DeviceState {
         &mut self,
         msg: PublishImageMessage,
     ) -> impl HandlerResult<PublishImageMessage> {
-        let re_schedule = if let Some(strong) = msg.0.upgrade() {
+        let re_schedule = if self.paused {
+            // Resuming schedules the next frame
+            None
+        } else if let Some(strong) = msg.0.upgrade() {
             match strong.next_image(self).await {
                 Ok(image) => {
                     self.counter += 1;
-                    self.stream
-                        .send(Ok(ImageWithMeta::with_hash(image, None)))
-                        .ok()
-                        .map(|_| msg.0)
+                    self.statistics.increment("frames");
+                    self.last_image = Some(image.clone());
+                    let faults = &strong.params.faults;
+                    let is_subscribed = match faults.fault_for(self.counter) {
+                        None => self
+                            .stream
+                            .send(Ok(ImageWithMeta::with_hash(image, None)))
+                            .is_ok(),
+                        Some(Fault::Drop) => true,
+                        Some(Fault::Error) => self
+                            .stream
+                            .send(Err(StreamImageError::ProcessingError {
+                                image,
+                                error: Arc::new(anyhow::anyhow!("Injected processing error")),
+                            }))
+                            .is_ok(),
+                        Some(Fault::Disappear(duration)) => {
+                            debug!("Disappear for {duration:?} due to fault injection");
+                            // Dropping the sender ends all subscriptions
+                            self.stream = tokio::sync::broadcast::channel(1).0;
+                            self.unavailable_until = Some(Instant::now() + duration);
+                            false
+                        }
+                    };
+                    is_subscribed.then(|| (msg.0, faults.delay_for(self.counter + 1)))
                 }
                 Err(e) => {
+                    self.statistics.increment("errors");
                     warn!("Stop due to acquisition error: {e:?}");
                     None
                 }
@@ -41,8 +75,8 @@ impl DeviceState {
         };
 
         Step2(async move {
-            if let Some(weak) = re_schedule {
-                PublisherState::send_delayed(weak).await;
+            if let Some((weak, extra_delay)) = re_schedule {
+                PublisherState::send_delayed(weak, extra_delay).await;
             }
             Ok(())
         })
@@ -56,9 +90,9 @@ pub(super) struct PublisherState {
 }
 
 impl PublisherState {
-    pub async fn send_delayed(weak: Weak<Self>) {
+    pub async fn send_delayed(weak: Weak<Self>, extra_delay: Duration) {
         if let Some(state) = weak.upgrade() {
-            tokio::time::sleep(Duration::from_millis(state.params.interval)).await;
+            tokio::time::sleep(Duration::from_millis(state.params.interval) + extra_delay).await;
             state
                 .self_sender
                 .clone()
@@ -66,63 +100,45 @@ impl PublisherState {
                 .ok();
         }
     }
-    async fn next_image(
+    pub async fn next_image(
         &self,
         state: &mut super::DeviceState,
     ) -> anyhow::Result<PilatusDynamicImage> {
-        let files = state
-            .file_service
-            .stream_files(RelativeDirectoryPath::root())
-            .filter_map(|x| async {
-                let entry = x.ok()?;
-
-                (entry.file_name().ends_with(&self.params.file_ending))
-                    .then_some(ExistingDirEntry(entry))
-            })
-            .collect::<BinaryHeap<_>>()
-            .await;
-        let mut iter = files.iter();
-        let first = iter.next();
-        let current = match (
-            first,
-            files.iter().nth(state.counter.saturating_sub(1) as usize),
-        ) {
-            (_, Some(x)) => x,
-            (Some(x), _) => {
-                state.counter = 0;
-                x
+        let image = match &self.params.mode {
+            EmulationMode::Files => self.next_file_image(state).await?,
+            EmulationMode::Synthetic(synthetic) => {
+                let synthetic = synthetic.clone();
+                let frame = state.counter;
+                tokio::task::spawn_blocking(move || synthetic.generate(frame)).await?
             }
-            _ => return Err(anyhow::anyhow!("Stop streaming, there is no file")),
         };
-
-        let image_data = state
-            .file_service
-            .get_file(&RelativeFilePath::new(current.0.file_name())?)
-            .await?;
-        let img =
-            tokio::task::spawn_blocking(move || image::load_from_memory(&image_data)).await??;
-
-        Ok(img.try_into()?)
+        let exposure = state.exposure;
+        Ok(tokio::task::spawn_blocking(move || super::exposure::apply(exposure, image)).await?)
     }
-}
-
-struct ExistingDirEntry(RelativeFilePath);
 
-impl PartialEq for ExistingDirEntry {
-    fn eq(&self, other: &Self) -> bool {
-        self.0.file_name() == other.0.file_name()
-    }
-}
-impl Eq for ExistingDirEntry {}
-
-impl PartialOrd for ExistingDirEntry {
-    fn partial_cmp(&self, other: &Self) -> Option<std::cmp::Ordering> {
-        Some(self.cmp(other))
-    }
-}
-
-impl Ord for ExistingDirEntry {
-    fn cmp(&self, other: &Self) -> std::cmp::Ordering {
-        self.0.file_name().cmp(other.0.file_name())
+    async fn next_file_image(
+        &self,
+        state: &mut super::DeviceState,
+    ) -> anyhow::Result<PilatusDynamicImage> {
+        let prefetcher = state.prefetcher.get_or_insert_with(|| {
+            FramePrefetcher::spawn(
+                state.file_service_builder.clone().build(state.id),
+                self.params.file_ending.clone(),
+                state.pools.get(WorkerPoolKind::Compute).clone(),
+                self.params.prefetch,
+            )
+        });
+        match prefetcher.next().await {
+            Ok(PrefetchedFrame { index, image }) => {
+                // Faults are counted per round through the files
+                state.counter = index as u32;
+                Ok(image)
+            }
+            Err(e) => {
+                // Retry from the first file on the next publish
+                state.prefetcher = None;
+                Err(e)
+            }
+        }
     }
 }
@@ -5,21 +5,28 @@ use chrono::{DateTime, Utc};
 use futures::StreamExt;
 use minfac::ServiceCollection;
 use pilatus::{
-    device::{ActorError, ActorErrorResultExtensions, ActorResult, ActorSystem, DeviceId},
-    Name, RelativeFilePath,
+    device::{
+        ActorError, ActorErrorResultExtensions, ActorProgress, ActorResult, DeviceId,
+        ProgressReporter,
+    },
+    Name, RelativeFilePath, ResourceAction,
 };
 use pilatus_axum::{
-    extract::{InjectRegistered, Json, Path},
+    extract::{ws::WebSocketUpgrade, Abort, Json, Path, Query, WebActorSystem},
     http::StatusCode,
-    ServiceCollectionExtensions,
+    IntoResponse, ServiceCollectionExtensions,
 };
 use pilatus_engineering::image::{StreamImageError, SubscribeDynamicImageMessage};
 use pilatus_engineering_camera::RecordMessage;
 use serde::Deserialize;
+use tracing::trace;
 
 pub(super) fn register_services(c: &mut ServiceCollection) {
     c.register_web("engineering/emulation-camera", |r| {
         r.http("/:device_id/record/:collection_name", |f| f.put(record_web))
+            .http("/:device_id/record/:collection_name/progress", |f| {
+                f.get(record_with_progress_web)
+            })
     })
 }
 
@@ -27,6 +34,7 @@ impl DeviceState {
     pub(super) async fn record(
         &mut self,
         msg: RecordMessage,
+        progress: ProgressReporter,
         reg: futures::stream::AbortRegistration,
     ) -> ActorResult<RecordMessage> {
         let images = self
@@ -51,14 +59,18 @@ impl DeviceState {
             .buffer_unordered(8);
         let mut abortable_stream = futures::stream::Abortable::new(encoded_stream, reg);
 
-        let mut size_budget =
-            msg.max_size_mb.map(NonZeroU32::get).unwrap_or(100) as u64 * 1_000_000;
+        let total_budget = msg.max_size_mb.map(NonZeroU32::get).unwrap_or(100) as u64 * 1_000_000;
+        let mut size_budget = total_budget;
 
         let collection_dir = std::path::Path::new(msg.collection_name.as_str());
         while let Some(x) =
             tokio::time::timeout(Duration::from_secs(5), abortable_stream.next()).await?
         {
             let (time, encoded) = x?;
+            if self.health.is_action_active(ResourceAction::PauseRecording) {
+                trace!("Skip frame, recording is paused due to high resource usage");
+                continue;
+            }
             let Some(remainer) = size_budget.checked_sub(encoded.len() as u64) else {
                 break;
             };
@@ -82,6 +94,10 @@ impl DeviceState {
             self.file_service
                 .add_file_unchecked(&path, &encoded)
                 .await?;
+            progress.report(ActorProgress::new(
+                1. - size_budget as f32 / total_budget as f32,
+                format!("Recorded {}", path.file_name()),
+            ));
         }
 
         Ok(())
@@ -93,6 +109,12 @@ struct RecordBody {
     source_id: DeviceId,
     max_size_mb: Option<NonZeroU32>,
 }
+#[derive(Deserialize)]
+struct RecordQuery {
+    source_id: DeviceId,
+    max_size_mb: Option<NonZeroU32>,
+}
+
 #[derive(Deserialize)]
 struct RecordPath {
     collection_name: Name,
@@ -100,7 +122,8 @@ struct RecordPath {
 }
 
 async fn record_web(
-    InjectRegistered(actor_system): InjectRegistered<ActorSystem>,
+    abort: Abort,
+    WebActorSystem(actor_system): WebActorSystem,
     Path(RecordPath {
         device_id,
         collection_name,
@@ -113,10 +136,32 @@ async fn record_web(
     let msg = RecordMessage::with_option_max_size(source_id, collection_name, max_size_mb)
         .map_err(|e| (StatusCode::UNPROCESSABLE_ENTITY, e.to_string()))?;
 
-    actor_system
-        .ask(device_id, msg)
+    abort
+        .make_cancellable(actor_system.ask(device_id, msg))
         .await
         .map_err(|e| (StatusCode::BAD_REQUEST, e.to_string()))?;
 
     Ok(())
 }
+
+async fn record_with_progress_web(
+    upgrade: WebSocketUpgrade,
+    WebActorSystem(actor_system): WebActorSystem,
+    Path(RecordPath {
+        device_id,
+        collection_name,
+    }): Path<RecordPath>,
+    Query(RecordQuery {
+        source_id,
+        max_size_mb,
+    }): Query<RecordQuery>,
+) -> Result<impl IntoResponse, (StatusCode, String)> {
+    let msg = RecordMessage::with_option_max_size(source_id, collection_name, max_size_mb)
+        .map_err(|e| (StatusCode::UNPROCESSABLE_ENTITY, e.to_string()))?;
+
+    let stream = actor_system
+        .ask_with_progress(device_id, msg)
+        .map_err(|e| (StatusCode::NOT_FOUND, e.to_string()))?;
+
+    Ok(pilatus_axum::stream_actor_progress(upgrade, stream))
+}
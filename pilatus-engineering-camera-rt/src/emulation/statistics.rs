@@ -0,0 +1,22 @@
+use pilatus::device::ActorResult;
+use pilatus_engineering::image::{GetImageStatisticsMessage, ImageStatistics};
+
+use super::DeviceState;
+
+impl DeviceState {
+    /// Uses the last published frame. If nothing was published yet, the next frame is acquired without publishing it
+    pub(super) async fn get_statistics(
+        &mut self,
+        GetImageStatisticsMessage { bins, .. }: GetImageStatisticsMessage,
+    ) -> ActorResult<GetImageStatisticsMessage> {
+        let image = match &self.last_image {
+            Some(x) => x.clone(),
+            None => self.publisher.clone().next_image(self).await?,
+        };
+        Ok(
+            tokio::task::spawn_blocking(move || ImageStatistics::compute(&image, bins))
+                .await
+                .map_err(anyhow::Error::from)?,
+        )
+    }
+}
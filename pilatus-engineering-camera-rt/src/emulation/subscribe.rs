@@ -1,8 +1,14 @@
 use std::sync::Arc;
 
 use futures::StreamExt;
-use pilatus::{device::ActorResult, MissedItemsError};
-use pilatus_engineering::image::{StreamImageError, SubscribeDynamicImageMessage};
+use pilatus::{
+    device::{ActorErrorUnknownDevice, ActorResult},
+    MissedItemsError,
+};
+use pilatus_engineering::image::{
+    ListImageKeysMessage, StreamImageError, SubscribeDynamicImageMessage,
+};
+use tokio::time::Instant;
 use tokio_stream::wrappers::errors::BroadcastStreamRecvError;
 
 use super::{publish_frame::PublishImageMessage, DeviceState};
@@ -12,6 +18,17 @@ impl DeviceState {
         &mut self,
         _msg: SubscribeDynamicImageMessage,
     ) -> ActorResult<SubscribeDynamicImageMessage> {
+        if let Some(until) = self.unavailable_until {
+            if Instant::now() < until {
+                return Err(ActorErrorUnknownDevice::unknown_id(
+                    self.id,
+                    "Disappeared due to fault injection",
+                )
+                .with_message_type::<SubscribeDynamicImageMessage>()
+                .into());
+            }
+            self.unavailable_until = None;
+        }
         if Arc::weak_count(&self.publisher) == 0 {
             self.publisher
                 .self_sender
@@ -31,4 +48,12 @@ impl DeviceState {
                 .boxed(),
         )
     }
+
+    /// Recorded collections only contain main images
+    pub(super) async fn list_image_keys(
+        &mut self,
+        _msg: ListImageKeysMessage,
+    ) -> ActorResult<ListImageKeysMessage> {
+        Ok(Vec::new())
+    }
 }
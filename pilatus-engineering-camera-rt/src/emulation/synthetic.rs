@@ -0,0 +1,235 @@
+//! Generated test patterns, so image pipelines can run without any recorded files
+//!
+//! The content only depends on the params and the frame number, which makes it suitable for golden-image tests
+
+use std::num::NonZeroU32;
+
+use pilatus::UpdateParamsMessageError;
+use pilatus_engineering::image::{DynamicImage, LumaImage};
+use serde::{Deserialize, Serialize};
+
+/// Larger images are rejected, as they would only be useful to run out of memory
+const MAX_SIDE_LEN: u32 = 16384;
+
+#[derive(Debug, Deserialize, Serialize, Clone, PartialEq)]
+#[serde(deny_unknown_fields, default)]
+pub struct SyntheticParams {
+    pattern: SyntheticPattern,
+    width: NonZeroU32,
+    height: NonZeroU32,
+    /// Maximum deviation of each pixel in grey values. 0 disables noise
+    noise: u8,
+    /// Different seeds produce different noise for the same frame
+    seed: u64,
+}
+
+impl Default for SyntheticParams {
+    fn default() -> Self {
+        Self {
+            pattern: Default::default(),
+            width: NonZeroU32::new(640).unwrap(),
+            height: NonZeroU32::new(480).unwrap(),
+            noise: 0,
+            seed: 0,
+        }
+    }
+}
+
+#[derive(Debug, Deserialize, Serialize, Clone, PartialEq, Default)]
+#[serde(deny_unknown_fields, rename_all = "snake_case", tag = "type")]
+pub enum SyntheticPattern {
+    /// Horizontal gradient, which moves one pixel per frame
+    #[default]
+    Gradient,
+    /// Moves one pixel per frame in both directions
+    Checkerboard { cell_size: NonZeroU32 },
+    /// Bright circles on a dark background, each moving on its own path
+    MovingBlobs { count: u32, radius: u32 },
+}
+
+impl SyntheticParams {
+    pub(super) fn validate(&self) -> Result<(), UpdateParamsMessageError> {
+        for (path, len) in [
+            ("mode.width", self.width.get()),
+            ("mode.height", self.height.get()),
+        ] {
+            if len > MAX_SIDE_LEN {
+                return Err(UpdateParamsMessageError::InvalidField {
+                    path,
+                    message: format!("{len} > {MAX_SIDE_LEN}"),
+                });
+            }
+        }
+        Ok(())
+    }
+
+    pub(super) fn generate(&self, frame: u32) -> DynamicImage {
+        let (width, height) = (self.width.get(), self.height.get());
+        let render = self.pattern.renderer(width, height, frame);
+        let mut data = vec![0u8; width as usize * height as usize];
+        for (y, row) in data.chunks_exact_mut(width as usize).enumerate() {
+            for (x, pixel) in row.iter_mut().enumerate() {
+                *pixel = render(x as u32, y as u32);
+            }
+        }
+        if self.noise > 0 {
+            let mut rng =
+                SplitMix64(self.seed ^ (frame as u64).wrapping_mul(0x9E37_79B9_7F4A_7C15));
+            let range = 2 * self.noise as u64 + 1;
+            for pixel in data.iter_mut() {
+                let delta = (rng.next() % range) as i16 - self.noise as i16;
+                *pixel = (*pixel as i16 + delta).clamp(0, 255) as u8;
+            }
+        }
+        DynamicImage::Luma8(LumaImage::new_vec(data, self.width, self.height))
+    }
+}
+
+impl SyntheticPattern {
+    /// Grey value for each pixel of the given frame
+    fn renderer(&self, width: u32, height: u32, frame: u32) -> Box<dyn Fn(u32, u32) -> u8 + '_> {
+        match self {
+            SyntheticPattern::Gradient => Box::new(move |x, _| {
+                let pos = x.wrapping_add(frame) % width;
+                (pos as u64 * 255 / (width.max(2) - 1) as u64) as u8
+            }),
+            SyntheticPattern::Checkerboard { cell_size } => Box::new(move |x, y| {
+                let cell_x = x.wrapping_add(frame) / cell_size.get();
+                let cell_y = y.wrapping_add(frame) / cell_size.get();
+                if (cell_x + cell_y) % 2 == 0 {
+                    255
+                } else {
+                    0
+                }
+            }),
+            SyntheticPattern::MovingBlobs { count, radius } => {
+                let r = *radius as i64;
+                let centers = (0..*count)
+                    .map(|i| blob_center(i, width, height, frame))
+                    .collect::<Vec<_>>();
+                Box::new(move |x, y| {
+                    let hit = centers.iter().any(|(cx, cy)| {
+                        let (dx, dy) = (x as i64 - cx, y as i64 - cy);
+                        dx * dx + dy * dy <= r * r
+                    });
+                    if hit {
+                        230
+                    } else {
+                        25
+                    }
+                })
+            }
+        }
+    }
+}
+
+/// Blobs bounce between the image borders with a speed derived from their index
+fn blob_center(index: u32, width: u32, height: u32, frame: u32) -> (i64, i64) {
+    let mut rng = SplitMix64(index as u64);
+    let start_x = rng.next() % width as u64;
+    let start_y = rng.next() % height as u64;
+    let speed_x = 1 + rng.next() % 5;
+    let speed_y = 1 + rng.next() % 5;
+    (
+        bounce(start_x + speed_x * frame as u64, width),
+        bounce(start_y + speed_y * frame as u64, height),
+    )
+}
+
+fn bounce(pos: u64, len: u32) -> i64 {
+    let len = len as u64;
+    if len < 2 {
+        return 0;
+    }
+    let period = 2 * (len - 1);
+    let pos = pos % period;
+    (if pos < len { pos } else { period - pos }) as i64
+}
+
+/// Small deterministic generator, so the output is identical on all platforms and versions
+pub(super) struct SplitMix64(pub(super) u64);
+
+impl SplitMix64 {
+    pub(super) fn next(&mut self) -> u64 {
+        self.0 = self.0.wrapping_add(0x9E37_79B9_7F4A_7C15);
+        let mut z = self.0;
+        z = (z ^ (z >> 30)).wrapping_mul(0xBF58_476D_1CE4_E5B9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94D0_49BB_1331_11EB);
+        z ^ (z >> 31)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn pixels(image: &DynamicImage) -> Vec<u8> {
+        match image {
+            DynamicImage::Luma8(x) => x.buffer().to_vec(),
+            _ => panic!("Expected Luma8"),
+        }
+    }
+
+    #[test]
+    fn same_frame_produces_same_image() {
+        let params = SyntheticParams {
+            pattern: SyntheticPattern::MovingBlobs {
+                count: 3,
+                radius: 5,
+            },
+            width: NonZeroU32::new(64).unwrap(),
+            height: NonZeroU32::new(32).unwrap(),
+            noise: 10,
+            seed: 42,
+        };
+        let first = params.generate(7);
+        assert_eq!(
+            (NonZeroU32::new(64).unwrap(), NonZeroU32::new(32).unwrap()),
+            first.dimensions()
+        );
+        assert_eq!(pixels(&first), pixels(&params.generate(7)));
+        assert_ne!(pixels(&first), pixels(&params.generate(8)));
+    }
+
+    #[test]
+    fn checkerboard_moves_with_frames() {
+        let params = SyntheticParams {
+            pattern: SyntheticPattern::Checkerboard {
+                cell_size: NonZeroU32::new(2).unwrap(),
+            },
+            width: NonZeroU32::new(4).unwrap(),
+            height: NonZeroU32::new(1).unwrap(),
+            ..Default::default()
+        };
+        assert_eq!(vec![255, 255, 0, 0], pixels(&params.generate(0)));
+        assert_eq!(vec![255, 0, 0, 255], pixels(&params.generate(1)));
+    }
+
+    #[test]
+    fn deserialize_with_defaults() {
+        let params: SyntheticParams = serde_json::from_value(serde_json::json!({
+            "pattern": { "type": "checkerboard", "cell_size": 8 },
+            "noise": 3
+        }))
+        .unwrap();
+        assert_eq!(
+            SyntheticParams {
+                pattern: SyntheticPattern::Checkerboard {
+                    cell_size: NonZeroU32::new(8).unwrap()
+                },
+                noise: 3,
+                ..Default::default()
+            },
+            params
+        );
+    }
+
+    #[test]
+    fn reject_huge_images() {
+        let params = SyntheticParams {
+            width: NonZeroU32::new(MAX_SIDE_LEN + 1).unwrap(),
+            ..Default::default()
+        };
+        assert!(params.validate().is_err());
+    }
+}
@@ -0,0 +1,397 @@
+//! Compares the frames of a source with golden images, e.g. for regression tests on hardware-in-the-loop rigs
+//!
+//! Golden images are PNGs in a subdirectory (the golden set) of the device folder: `_main.png` for the main image and
+//! `<key>.png` for other images. They can be managed with the usual file routes or captured with [`CaptureGoldenMessage`]
+
+use std::{borrow::Cow, pin::pin, sync::Arc, time::Duration};
+
+use futures::{future::Either, StreamExt};
+use minfac::{Registered, ServiceCollection};
+use pilatus::{
+    device::{
+        ActorResult, ActorSystem, DeviceContext, DeviceId, DeviceResult, DeviceValidationContext,
+    },
+    prelude::*,
+    FileService, FileServiceBuilder, Name, RelativeDirectoryPath, RelativeFilePath,
+    UpdateParamsMessage, UpdateParamsMessageError,
+};
+use pilatus_engineering::image::{
+    CompareMetric, Comparison, DynamicImage, ImageKey, ImageWithMeta, SpecificImageKey,
+    StreamImageError, SubscribeDynamicImageMessage, SubscribeImageQuery,
+};
+use pilatus_engineering_camera::{
+    CaptureGoldenMessage, GetLastGoldenResultMessage, GoldenImageResult, GoldenResult,
+    SubscribeGoldenResultsMessage,
+};
+use serde::{Deserialize, Serialize};
+use tokio::sync::watch;
+use tokio_stream::wrappers::WatchStream;
+use tracing::{debug, warn};
+
+mod web;
+
+pub const DEVICE_TYPE: &str = "engineering-golden-frame";
+
+/// File stem of the main image. Image keys can't start with '_', so it never collides with other images
+const MAIN_IMAGE_STEM: &str = "_main";
+/// Wait time before subscribing again, if the source failed or ended its stream
+const RETRY_DELAY: Duration = Duration::from_secs(1);
+const CAPTURE_TIMEOUT: Duration = Duration::from_secs(5);
+
+pub(super) fn register_services(c: &mut ServiceCollection) {
+    web::register_services(c);
+    c.with::<(Registered<ActorSystem>, Registered<FileServiceBuilder>)>()
+        .register_device(DEVICE_TYPE, validator, device);
+}
+
+struct DeviceState {
+    params: watch::Sender<Params>,
+    results: watch::Receiver<Option<GoldenResult>>,
+    file_service: FileService<()>,
+    actor_system: ActorSystem,
+}
+
+#[derive(Debug, Deserialize, Serialize, Clone, PartialEq)]
+#[serde(deny_unknown_fields, default)]
+pub struct Params {
+    /// Device whose frames are compared. Nothing is compared without source
+    source: Option<DeviceId>,
+    /// Subdirectory with the golden images
+    golden_set: Name,
+    metric: CompareMetric,
+}
+
+impl Default for Params {
+    fn default() -> Self {
+        Self {
+            source: None,
+            golden_set: Name::new("default").expect("Valid name"),
+            metric: Default::default(),
+        }
+    }
+}
+
+async fn validator(ctx: DeviceValidationContext<'_>) -> Result<Params, UpdateParamsMessageError> {
+    ctx.params_as::<Params>()
+}
+
+async fn device(
+    ctx: DeviceContext,
+    params: Params,
+    (actor_system, file_service_builder): (ActorSystem, FileServiceBuilder),
+) -> DeviceResult {
+    let (params, params_receiver) = watch::channel(params);
+    let (results_sender, results) = watch::channel(None);
+    let actor = actor_system
+        .register(ctx.id)
+        .add_handler(DeviceState::update_params)
+        .add_handler(DeviceState::subscribe_results)
+        .add_handler(DeviceState::last_result)
+        .add_handler(DeviceState::capture)
+        .execute(DeviceState {
+            params,
+            results,
+            file_service: file_service_builder.clone().build(ctx.id),
+            actor_system: actor_system.clone(),
+        });
+    let comparison = compare_loop(
+        actor_system.clone(),
+        file_service_builder.build(ctx.id),
+        params_receiver,
+        results_sender,
+    );
+
+    // The comparison has no purpose anymore, once the device is stopped
+    futures::future::select(pin!(actor), pin!(comparison)).await;
+    Ok(())
+}
+
+impl DeviceState {
+    async fn update_params(
+        &mut self,
+        UpdateParamsMessage { params }: UpdateParamsMessage<Params>,
+    ) -> ActorResult<UpdateParamsMessage<Params>> {
+        self.params.send_replace(params);
+        Ok(())
+    }
+
+    async fn subscribe_results(
+        &mut self,
+        _msg: SubscribeGoldenResultsMessage,
+    ) -> ActorResult<SubscribeGoldenResultsMessage> {
+        Ok(WatchStream::new(self.results.clone())
+            .filter_map(std::future::ready)
+            .boxed())
+    }
+
+    async fn last_result(
+        &mut self,
+        _msg: GetLastGoldenResultMessage,
+    ) -> ActorResult<GetLastGoldenResultMessage> {
+        Ok(self.results.borrow().clone())
+    }
+
+    async fn capture(
+        &mut self,
+        CaptureGoldenMessage { set, keys, .. }: CaptureGoldenMessage,
+    ) -> ActorResult<CaptureGoldenMessage> {
+        let source = self
+            .params
+            .borrow()
+            .source
+            .ok_or_else(|| anyhow::anyhow!("No source configured"))?;
+        let query = SubscribeImageQuery::with_keys(keys.iter().cloned().map(ImageKey::from));
+        let mut images = self
+            .actor_system
+            .ask(source, SubscribeDynamicImageMessage::from(query))
+            .await
+            .map_err(anyhow::Error::from)?;
+        let frame = tokio::time::timeout(CAPTURE_TIMEOUT, async {
+            while let Some(item) = images.next().await {
+                match item {
+                    Ok(x) => return Ok(x),
+                    Err(StreamImageError::MissedItems(_)) => continue,
+                    Err(e) => return Err(anyhow::Error::from(e)),
+                }
+            }
+            Err(anyhow::anyhow!("Source ended the stream"))
+        })
+        .await??;
+
+        let encoded = tokio::task::spawn_blocking(move || {
+            std::iter::once(None)
+                .chain(keys.into_iter().map(Some))
+                .map(|key| {
+                    let image_key = key
+                        .clone()
+                        .map(ImageKey::from)
+                        .unwrap_or(ImageKey::unspecified());
+                    let image = frame
+                        .by_name(&image_key)
+                        .ok_or_else(|| anyhow::anyhow!("Source didn't provide image {key:?}"))?;
+                    anyhow::Ok((key, image.encode_png()?))
+                })
+                .collect::<Result<Vec<_>, _>>()
+        })
+        .await
+        .map_err(anyhow::Error::from)??;
+
+        let dir = RelativeDirectoryPath::new(set.as_str()).map_err(anyhow::Error::from)?;
+        for existing in self.file_service.list_files(dir).await.unwrap_or_default() {
+            if existing.file_name().ends_with(".png") {
+                self.file_service
+                    .remove_file(&existing)
+                    .await
+                    .map_err(anyhow::Error::from)?;
+            }
+        }
+        for (key, data) in encoded {
+            self.file_service
+                .add_file_unchecked(&golden_path(&set, key.as_ref())?, &data)
+                .await?;
+        }
+        Ok(())
+    }
+}
+
+fn golden_path(set: &Name, key: Option<&SpecificImageKey>) -> anyhow::Result<RelativeFilePath> {
+    let stem = key.map(SpecificImageKey::as_str).unwrap_or(MAIN_IMAGE_STEM);
+    Ok(RelativeFilePath::new(format!("{set}/{stem}.png"))?)
+}
+
+/// Restarts the comparison whenever the params or the golden images change
+async fn compare_loop(
+    actor_system: ActorSystem,
+    file_service: FileService<()>,
+    mut params: watch::Receiver<Params>,
+    results: watch::Sender<Option<GoldenResult>>,
+) {
+    loop {
+        let current = params.borrow_and_update().clone();
+        results.send_replace(None);
+        let Some(source) = current.source else {
+            if params.changed().await.is_err() {
+                return;
+            }
+            continue;
+        };
+        let mut golden_changes = match RelativeDirectoryPath::new(current.golden_set.as_str()) {
+            Ok(dir) => file_service.watch(dir),
+            Err(e) => {
+                warn!("Invalid golden set: {e:?}");
+                futures::stream::pending().boxed()
+            }
+        };
+        let comparison = pin!(compare(
+            &actor_system,
+            &file_service,
+            source,
+            &current,
+            &results
+        ));
+        match futures::future::select(
+            comparison,
+            futures::future::select(pin!(params.changed()), golden_changes.next()),
+        )
+        .await
+        {
+            Either::Left((Ok(()), _)) => debug!("Source {source} restarted, subscribe again"),
+            Either::Left((Err(e), _)) => {
+                warn!("Golden frame comparison for {source} failed: {e:?}");
+                tokio::time::sleep(RETRY_DELAY).await;
+            }
+            Either::Right((Either::Left((Err(_), _)), _)) => return,
+            Either::Right((Either::Left((Ok(()), _)), _)) => {
+                debug!("Restart golden frame comparison with new params")
+            }
+            Either::Right((Either::Right((Some(_), _)), _)) => debug!("Reload golden images"),
+            Either::Right((Either::Right((None, _)), _)) => {
+                tokio::time::sleep(RETRY_DELAY).await;
+            }
+        }
+    }
+}
+
+/// Returns Ok, if the source restarted and can be subscribed again right away
+async fn compare(
+    actor_system: &ActorSystem,
+    file_service: &FileService<()>,
+    source: DeviceId,
+    params: &Params,
+    results: &watch::Sender<Option<GoldenResult>>,
+) -> anyhow::Result<()> {
+    let golden = Arc::new(load_golden_set(file_service, &params.golden_set).await?);
+    if golden.is_empty() {
+        debug!("Golden set {} is empty", params.golden_set);
+        return std::future::pending().await;
+    }
+    let query = SubscribeImageQuery::with_keys(
+        golden
+            .iter()
+            .filter_map(|(key, _)| key.clone().map(ImageKey::from)),
+    );
+    let mut images = actor_system
+        .ask(source, SubscribeDynamicImageMessage::from(query))
+        .await?;
+    let mut frame = 0;
+    while let Some(item) = images.next().await {
+        let image = match item {
+            Ok(x) => x,
+            Err(StreamImageError::MissedItems(_)) => continue,
+            Err(StreamImageError::ProducerRestarted { .. }) => return Ok(()),
+            Err(e) => return Err(e.into()),
+        };
+        frame += 1;
+        let golden = golden.clone();
+        let metric = params.metric;
+        let images =
+            tokio::task::spawn_blocking(move || compare_frame(&golden, &image, metric)).await?;
+        let passed = images
+            .iter()
+            .all(|x| matches!(x.comparison, Ok(Comparison { passed: true, .. })));
+        results.send_replace(Some(GoldenResult {
+            frame,
+            passed,
+            images,
+        }));
+    }
+    Err(anyhow::anyhow!("Source ended the stream"))
+}
+
+async fn load_golden_set(
+    file_service: &FileService<()>,
+    set: &Name,
+) -> anyhow::Result<Vec<(Option<SpecificImageKey>, DynamicImage)>> {
+    let mut golden = Vec::new();
+    for file in file_service
+        .list_files(RelativeDirectoryPath::new(set.as_str())?)
+        .await?
+    {
+        let Some(stem) = file.file_name().strip_suffix(".png") else {
+            continue;
+        };
+        let key = match stem {
+            MAIN_IMAGE_STEM => None,
+            x => Some(SpecificImageKey::try_from(Cow::Owned(x.to_string()))?),
+        };
+        let data = file_service.get_file(&file).await?;
+        let image: DynamicImage =
+            tokio::task::spawn_blocking(move || image::load_from_memory(&data))
+                .await??
+                .try_into()?;
+        golden.push((key, image));
+    }
+    Ok(golden)
+}
+
+fn compare_frame(
+    golden: &[(Option<SpecificImageKey>, DynamicImage)],
+    frame: &ImageWithMeta<DynamicImage>,
+    metric: CompareMetric,
+) -> Vec<GoldenImageResult> {
+    golden
+        .iter()
+        .map(|(key, reference)| {
+            let image_key = key
+                .clone()
+                .map(ImageKey::from)
+                .unwrap_or(ImageKey::unspecified());
+            let comparison = match frame.by_name(&image_key) {
+                Some(actual) => metric.compare(reference, actual).map_err(|e| e.to_string()),
+                None => Err("Image is missing in the frame".into()),
+            };
+            GoldenImageResult {
+                key: key.clone(),
+                comparison,
+            }
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use std::num::NonZeroU32;
+
+    use pilatus_engineering::image::LumaImage;
+
+    use super::*;
+
+    #[test]
+    fn report_missing_images() {
+        let image = DynamicImage::Luma8(LumaImage::new_vec(
+            vec![1, 2],
+            NonZeroU32::new(2).unwrap(),
+            NonZeroU32::MIN,
+        ));
+        let other_key = SpecificImageKey::try_from("other").unwrap();
+        let golden = vec![
+            (None, image.clone()),
+            (Some(other_key.clone()), image.clone()),
+        ];
+        let results = compare_frame(
+            &golden,
+            &ImageWithMeta::with_hash(image, None),
+            CompareMetric::default(),
+        );
+        assert!(matches!(
+            results[0].comparison,
+            Ok(Comparison { passed: true, .. })
+        ));
+        assert_eq!(Some(other_key), results[1].key);
+        assert!(results[1].comparison.is_err());
+    }
+
+    #[test]
+    fn main_image_is_stored_with_reserved_name() {
+        let set = Name::new("set").unwrap();
+        assert_eq!(
+            "set/_main.png",
+            golden_path(&set, None).unwrap().to_string()
+        );
+        let key = SpecificImageKey::try_from("overlay").unwrap();
+        assert_eq!(
+            "set/overlay.png",
+            golden_path(&set, Some(&key)).unwrap().to_string()
+        );
+    }
+}
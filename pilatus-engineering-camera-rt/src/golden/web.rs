@@ -0,0 +1,72 @@
+use futures::{Stream, StreamExt};
+use minfac::ServiceCollection;
+use pilatus::{device::DeviceId, Name};
+use pilatus_axum::{
+    extract::{Json, Path, WebActorSystem},
+    http::StatusCode,
+    sse::{Event, Sse},
+    IntoResponse, ServiceCollectionExtensions,
+};
+use pilatus_engineering::image::SpecificImageKey;
+use pilatus_engineering_camera::{
+    CaptureGoldenMessage, GetLastGoldenResultMessage, SubscribeGoldenResultsMessage,
+};
+use serde::Deserialize;
+
+pub(super) fn register_services(c: &mut ServiceCollection) {
+    #[rustfmt::skip]
+    c.register_web("engineering/golden", |r| r
+        .http("/:device_id/capture/:set", |m| m.put(capture_web))
+        .http("/:device_id/last", |m| m.get(last_result_web))
+        .http("/:device_id/results", |m| m.get(results_web))
+    );
+}
+
+#[derive(Deserialize)]
+struct CapturePath {
+    device_id: DeviceId,
+    set: Name,
+}
+
+/// `{}` captures the main image only
+#[derive(Deserialize, Default)]
+#[serde(default, deny_unknown_fields)]
+struct CaptureBody {
+    keys: Vec<SpecificImageKey>,
+}
+
+async fn capture_web(
+    WebActorSystem(actor_system): WebActorSystem,
+    Path(CapturePath { device_id, set }): Path<CapturePath>,
+    Json(CaptureBody { keys }): Json<CaptureBody>,
+) -> Result<(), (StatusCode, String)> {
+    actor_system
+        .ask(device_id, CaptureGoldenMessage::new(set, keys))
+        .await
+        .map_err(|e| (StatusCode::BAD_REQUEST, format!("Bummer, it failed: {e:?}")))
+}
+
+async fn last_result_web(
+    WebActorSystem(actor_system): WebActorSystem,
+    Path(device_id): Path<DeviceId>,
+) -> Result<impl IntoResponse, (StatusCode, String)> {
+    actor_system
+        .ask(device_id, GetLastGoldenResultMessage::default())
+        .await
+        .map(Json)
+        .map_err(|e| (StatusCode::BAD_REQUEST, format!("Bummer, it failed: {e:?}")))
+}
+
+/// Server-sent events with one JSON encoded result per compared frame
+async fn results_web(
+    WebActorSystem(actor_system): WebActorSystem,
+    Path(device_id): Path<DeviceId>,
+) -> Result<Sse<impl Stream<Item = Result<Event, anyhow::Error>>>, (StatusCode, String)> {
+    let results = actor_system
+        .ask(device_id, SubscribeGoldenResultsMessage::default())
+        .await
+        .map_err(|e| (StatusCode::NOT_FOUND, e.to_string()))?;
+    Ok(Sse::new(
+        results.map(|result| Ok(Event::default().json_data(result)?)),
+    ))
+}
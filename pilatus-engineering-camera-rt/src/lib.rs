@@ -1,9 +1,15 @@
 use minfac::ServiceCollection;
 
+mod auto_exposure;
+mod bridge;
 mod emulation;
+mod golden;
 
 pub extern "C" fn register(c: &mut ServiceCollection) {
     emulation::register_services(c);
+    auto_exposure::register_services(c);
+    golden::register_services(c);
+    bridge::register_services(c);
 }
 
 pub use emulation::create_default_device_config as create_default_emulation_device_config;
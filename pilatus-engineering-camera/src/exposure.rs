@@ -0,0 +1,44 @@
+use std::time::Duration;
+
+use pilatus::device::ActorMessage;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Exposure {
+    pub time: Duration,
+    /// Linear amplification, 1.0 means no amplification
+    pub gain: f64,
+}
+
+impl Exposure {
+    pub fn new(time: Duration, gain: f64) -> Self {
+        Self { time, gain }
+    }
+}
+
+#[derive(Debug, Default)]
+#[non_exhaustive]
+pub struct GetExposureMessage {}
+
+impl ActorMessage for GetExposureMessage {
+    type Output = Exposure;
+    type Error = anyhow::Error;
+}
+
+/// Cameras clamp the values to their supported range and respond with the exposure which is actually used.
+/// Changes are transient and not persisted in the recipe
+#[derive(Debug)]
+#[non_exhaustive]
+pub struct SetExposureMessage {
+    pub exposure: Exposure,
+}
+
+impl SetExposureMessage {
+    pub fn new(exposure: Exposure) -> Self {
+        Self { exposure }
+    }
+}
+
+impl ActorMessage for SetExposureMessage {
+    type Output = Exposure;
+    type Error = anyhow::Error;
+}
@@ -0,0 +1,60 @@
+use futures::stream::BoxStream;
+use pilatus::{device::ActorMessage, Name};
+use pilatus_engineering::image::{Comparison, SpecificImageKey};
+use serde::{Deserialize, Serialize};
+
+/// Result of comparing one frame with all golden images of the active set
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct GoldenResult {
+    /// Number of the compared frame since the comparison was (re)started
+    pub frame: u64,
+    pub passed: bool,
+    pub images: Vec<GoldenImageResult>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct GoldenImageResult {
+    /// None for the main image
+    pub key: Option<SpecificImageKey>,
+    /// Err if the images couldn't be compared at all (e.g. missing image or different size)
+    pub comparison: Result<Comparison, String>,
+}
+
+/// The latest result is sent immediately if available. Slow subscribers skip results instead of lagging behind
+#[derive(Debug, Default)]
+#[non_exhaustive]
+pub struct SubscribeGoldenResultsMessage {}
+
+impl ActorMessage for SubscribeGoldenResultsMessage {
+    type Output = BoxStream<'static, GoldenResult>;
+    type Error = anyhow::Error;
+}
+
+#[derive(Debug, Default)]
+#[non_exhaustive]
+pub struct GetLastGoldenResultMessage {}
+
+impl ActorMessage for GetLastGoldenResultMessage {
+    type Output = Option<GoldenResult>;
+    type Error = anyhow::Error;
+}
+
+/// Stores the next frame of the source as golden images of `set`. Existing images of the set are replaced
+#[derive(Debug)]
+#[non_exhaustive]
+pub struct CaptureGoldenMessage {
+    pub set: Name,
+    /// Images captured in addition to the main image
+    pub keys: Vec<SpecificImageKey>,
+}
+
+impl CaptureGoldenMessage {
+    pub fn new(set: Name, keys: Vec<SpecificImageKey>) -> Self {
+        Self { set, keys }
+    }
+}
+
+impl ActorMessage for CaptureGoldenMessage {
+    type Output = ();
+    type Error = anyhow::Error;
+}
@@ -1,3 +1,7 @@
+mod exposure;
+mod golden;
 mod record;
 
+pub use exposure::*;
+pub use golden::*;
 pub use record::*;
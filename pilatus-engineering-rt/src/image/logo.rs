@@ -13,6 +13,7 @@ pub(super) fn register_services(c: &mut ServiceCollection) {
     c.with::<Registered<LogoService>>()
         .register_shared(|s| Arc::new(ImageLogoServiceImpl::new(s)))
         .alias(|s| ImageLogoService::new(s));
+    c.register_instance(pilatus::ServiceInfo::shared::<ImageLogoService>());
 }
 
 type Age = u64;
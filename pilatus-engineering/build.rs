@@ -0,0 +1,31 @@
+fn main() {
+    #[cfg(feature = "cbindgen")]
+    generate_c_header();
+}
+
+/// Writes `pilatus_image.h` to OUT_DIR and to `PILATUS_C_HEADER_DIR`, if set
+#[cfg(feature = "cbindgen")]
+fn generate_c_header() {
+    use std::path::PathBuf;
+
+    println!("cargo:rerun-if-changed=src/image/ffi.rs");
+    println!("cargo:rerun-if-env-changed=PILATUS_C_HEADER_DIR");
+
+    let crate_dir = PathBuf::from(std::env::var("CARGO_MANIFEST_DIR").unwrap());
+    let out = PathBuf::from(std::env::var("OUT_DIR").unwrap()).join("pilatus_image.h");
+    cbindgen::Builder::new()
+        .with_src(crate_dir.join("src/image/ffi.rs"))
+        .with_language(cbindgen::Language::C)
+        .with_include_guard("PILATUS_IMAGE_H")
+        .with_header(
+            "/* Generated from pilatus-engineering/src/image/ffi.rs. Do not edit manually */",
+        )
+        .generate()
+        .expect("Unable to generate pilatus_image.h")
+        .write_to_file(&out);
+
+    if let Ok(dir) = std::env::var("PILATUS_C_HEADER_DIR") {
+        std::fs::copy(&out, PathBuf::from(dir).join("pilatus_image.h"))
+            .expect("Cannot copy pilatus_image.h to PILATUS_C_HEADER_DIR");
+    }
+}
@@ -0,0 +1,290 @@
+//! Color space conversions and white balance for packed RGB images (RGBRGBRGB)
+//!
+//! All conversions back to 8 bit round to the nearest value and saturate at 0 and 255,
+//! so results are identical, no matter which device converts them
+
+use super::GenericImage;
+
+/// `h` in degrees (0..360), `s` and `v` in 0..=1
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Hsv {
+    pub h: f32,
+    pub s: f32,
+    pub v: f32,
+}
+
+/// CIE L*a*b* with D65 white point, assuming sRGB input. `l` in 0..=100
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Lab {
+    pub l: f32,
+    pub a: f32,
+    pub b: f32,
+}
+
+const D65_WHITE: [f32; 3] = [0.950_47, 1.0, 1.088_83];
+
+fn to_u8(value: f32) -> u8 {
+    value.round().clamp(0., 255.) as u8
+}
+
+impl Hsv {
+    pub fn from_rgb([r, g, b]: [u8; 3]) -> Self {
+        let [r, g, b] = [r, g, b].map(|x| x as f32 / 255.);
+        let max = r.max(g).max(b);
+        let delta = max - r.min(g).min(b);
+        let h = if delta == 0. {
+            0.
+        } else if max == r {
+            60. * ((g - b) / delta).rem_euclid(6.)
+        } else if max == g {
+            60. * ((b - r) / delta + 2.)
+        } else {
+            60. * ((r - g) / delta + 4.)
+        };
+        let s = if max == 0. { 0. } else { delta / max };
+        Self { h, s, v: max }
+    }
+
+    pub fn to_rgb(self) -> [u8; 3] {
+        let h = self.h.rem_euclid(360.) / 60.;
+        let (s, v) = (self.s.clamp(0., 1.), self.v.clamp(0., 1.));
+        let c = v * s;
+        let x = c * (1. - (h.rem_euclid(2.) - 1.).abs());
+        let (r, g, b) = match h as u32 {
+            0 => (c, x, 0.),
+            1 => (x, c, 0.),
+            2 => (0., c, x),
+            3 => (0., x, c),
+            4 => (x, 0., c),
+            _ => (c, 0., x),
+        };
+        let m = v - c;
+        [r, g, b].map(|x| to_u8((x + m) * 255.))
+    }
+}
+
+impl Lab {
+    pub fn from_rgb(rgb: [u8; 3]) -> Self {
+        let [r, g, b] = rgb.map(|x| srgb_to_linear(x as f32 / 255.));
+        let xyz = [
+            0.412_456_4 * r + 0.357_576_1 * g + 0.180_437_5 * b,
+            0.212_672_9 * r + 0.715_152_2 * g + 0.072_175 * b,
+            0.019_333_9 * r + 0.119_192 * g + 0.950_304_1 * b,
+        ];
+        let [fx, fy, fz] = [0, 1, 2].map(|i| lab_f(xyz[i] / D65_WHITE[i]));
+        Self {
+            l: 116. * fy - 16.,
+            a: 500. * (fx - fy),
+            b: 200. * (fy - fz),
+        }
+    }
+
+    pub fn to_rgb(self) -> [u8; 3] {
+        let fy = (self.l + 16.) / 116.;
+        let f = [fy + self.a / 500., fy, fy - self.b / 200.];
+        let [x, y, z] = [0, 1, 2].map(|i| lab_f_inv(f[i]) * D65_WHITE[i]);
+        [
+            3.240_454_2 * x - 1.537_138_5 * y - 0.498_531_4 * z,
+            -0.969_266 * x + 1.876_010_8 * y + 0.041_556 * z,
+            0.055_643_4 * x - 0.204_025_9 * y + 1.057_225_2 * z,
+        ]
+        .map(|x| to_u8(linear_to_srgb(x.clamp(0., 1.)) * 255.))
+    }
+}
+
+fn srgb_to_linear(x: f32) -> f32 {
+    if x <= 0.040_45 {
+        x / 12.92
+    } else {
+        ((x + 0.055) / 1.055).powf(2.4)
+    }
+}
+
+fn linear_to_srgb(x: f32) -> f32 {
+    if x <= 0.003_130_8 {
+        x * 12.92
+    } else {
+        1.055 * x.powf(1. / 2.4) - 0.055
+    }
+}
+
+const LAB_EPSILON: f32 = 216. / 24389.;
+const LAB_KAPPA: f32 = 24389. / 27.;
+
+fn lab_f(t: f32) -> f32 {
+    if t > LAB_EPSILON {
+        t.cbrt()
+    } else {
+        (LAB_KAPPA * t + 16.) / 116.
+    }
+}
+
+fn lab_f_inv(f: f32) -> f32 {
+    let cubed = f * f * f;
+    if cubed > LAB_EPSILON {
+        cubed
+    } else {
+        (116. * f - 16.) / LAB_KAPPA
+    }
+}
+
+/// `value * gain + offset` per channel
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct ChannelGains {
+    pub gain: [f32; 3],
+    pub offset: [f32; 3],
+}
+
+impl Default for ChannelGains {
+    fn default() -> Self {
+        Self {
+            gain: [1.; 3],
+            offset: [0.; 3],
+        }
+    }
+}
+
+impl ChannelGains {
+    pub fn with_gain(gain: [f32; 3]) -> Self {
+        Self {
+            gain,
+            ..Default::default()
+        }
+    }
+
+    /// Gray world assumption: The average of a scene is neutral gray.
+    /// Red and blue are scaled to the mean of the green channel, which keeps the brightness
+    pub fn gray_world(image: &GenericImage<u8, 3>) -> Self {
+        let mut sums = [0u64; 3];
+        for pixel in image.buffer().chunks_exact(3) {
+            for (sum, value) in sums.iter_mut().zip(pixel) {
+                *sum += *value as u64;
+            }
+        }
+        let green = sums[1] as f32;
+        Self::with_gain(sums.map(|sum| if sum == 0 { 1. } else { green / sum as f32 }))
+    }
+
+    pub fn apply(&self, [r, g, b]: [u8; 3]) -> [u8; 3] {
+        let mut result = [r, g, b];
+        for (i, value) in result.iter_mut().enumerate() {
+            *value = to_u8(*value as f32 * self.gain[i] + self.offset[i]);
+        }
+        result
+    }
+}
+
+impl GenericImage<u8, 3> {
+    /// Applies `f` to every pixel
+    pub fn map_rgb(mut self, f: impl Fn([u8; 3]) -> [u8; 3]) -> Self {
+        for pixel in self.make_mut().chunks_exact_mut(3) {
+            pixel.copy_from_slice(&f([pixel[0], pixel[1], pixel[2]]));
+        }
+        self
+    }
+
+    pub fn with_gains(self, gains: &ChannelGains) -> Self {
+        self.map_rgb(|x| gains.apply(x))
+    }
+
+    /// See [`ChannelGains::gray_world`]
+    pub fn white_balance_gray_world(self) -> Self {
+        let gains = ChannelGains::gray_world(&self);
+        self.with_gains(&gains)
+    }
+
+    /// Channels are `[h, s, v]` per pixel
+    pub fn to_hsv(&self) -> GenericImage<f32, 3> {
+        self.map_to_f32(|x| {
+            let Hsv { h, s, v } = Hsv::from_rgb(x);
+            [h, s, v]
+        })
+    }
+
+    pub fn from_hsv(image: &GenericImage<f32, 3>) -> Self {
+        Self::map_from_f32(image, |[h, s, v]| Hsv { h, s, v }.to_rgb())
+    }
+
+    /// Channels are `[l, a, b]` per pixel
+    pub fn to_lab(&self) -> GenericImage<f32, 3> {
+        self.map_to_f32(|x| {
+            let Lab { l, a, b } = Lab::from_rgb(x);
+            [l, a, b]
+        })
+    }
+
+    pub fn from_lab(image: &GenericImage<f32, 3>) -> Self {
+        Self::map_from_f32(image, |[l, a, b]| Lab { l, a, b }.to_rgb())
+    }
+
+    fn map_to_f32(&self, f: impl Fn([u8; 3]) -> [f32; 3]) -> GenericImage<f32, 3> {
+        let (width, height) = self.dimensions();
+        let data = self
+            .buffer()
+            .chunks_exact(3)
+            .flat_map(|x| f([x[0], x[1], x[2]]))
+            .collect();
+        GenericImage::new_vec(data, width, height)
+    }
+
+    fn map_from_f32(image: &GenericImage<f32, 3>, f: impl Fn([f32; 3]) -> [u8; 3]) -> Self {
+        let (width, height) = image.dimensions();
+        let data = image
+            .buffer()
+            .chunks_exact(3)
+            .flat_map(|x| f([x[0], x[1], x[2]]))
+            .collect();
+        Self::new_vec(data, width, height)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::num::NonZeroU32;
+
+    use super::*;
+
+    fn rgb_image(pixels: &[[u8; 3]]) -> GenericImage<u8, 3> {
+        GenericImage::new_vec(
+            pixels.concat(),
+            NonZeroU32::new(pixels.len() as u32).unwrap(),
+            NonZeroU32::MIN,
+        )
+    }
+
+    #[test]
+    fn roundtrip_color_spaces() {
+        let pixels = [[0, 0, 0], [255, 255, 255], [255, 0, 0], [12, 200, 77]];
+        let image = rgb_image(&pixels);
+        assert_eq!(
+            image.buffer(),
+            GenericImage::from_hsv(&image.to_hsv()).buffer()
+        );
+        assert_eq!(
+            image.buffer(),
+            GenericImage::from_lab(&image.to_lab()).buffer()
+        );
+
+        let red = Hsv::from_rgb([255, 0, 0]);
+        assert_eq!((0., 1., 1.), (red.h, red.s, red.v));
+        let white = Lab::from_rgb([255, 255, 255]);
+        approx::assert_abs_diff_eq!(100., white.l, epsilon = 0.01);
+        approx::assert_abs_diff_eq!(0., white.a, epsilon = 0.01);
+    }
+
+    #[test]
+    fn gray_world_saturates() {
+        let image = rgb_image(&[[100, 50, 25], [200, 50, 25]]);
+        let gains = ChannelGains::gray_world(&image);
+        assert_eq!([1. / 3., 1., 2.], gains.gain);
+        assert_eq!(
+            &[33u8, 50, 50, 67, 50, 50],
+            image.white_balance_gray_world().buffer()
+        );
+        let offset = ChannelGains {
+            gain: [1.; 3],
+            offset: [-10., 0., 10.],
+        };
+        assert_eq!([0, 5, 255], offset.apply([5, 5, 250]));
+    }
+}
@@ -0,0 +1,192 @@
+//! Similarity metrics to compare images against references (e.g. golden images in regression tests)
+
+use serde::{Deserialize, Serialize};
+
+use super::{DynamicImage, GenericImage};
+
+/// Window size for SSIM. Windows at the right and bottom border might be smaller
+const SSIM_WINDOW: usize = 8;
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields, rename_all = "snake_case", tag = "type")]
+pub enum CompareMetric {
+    /// Score is the percentage of pixels which differ more than `tolerance` (relative to the value range, 0..=1)
+    PixelDiff {
+        tolerance: f64,
+        max_differing_percent: f64,
+    },
+    /// Score is the mean structural similarity (SSIM) of 8x8 windows. Identical images have a score of 1
+    Ssim { min_similarity: f64 },
+}
+
+impl Default for CompareMetric {
+    fn default() -> Self {
+        CompareMetric::PixelDiff {
+            tolerance: 0.02,
+            max_differing_percent: 0.1,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct Comparison {
+    pub score: f64,
+    pub passed: bool,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, thiserror::Error)]
+#[non_exhaustive]
+pub enum CompareError {
+    #[error("Image size {actual:?} differs from reference size {expected:?}")]
+    SizeMismatch {
+        expected: (u32, u32),
+        actual: (u32, u32),
+    },
+}
+
+impl CompareMetric {
+    /// Images with different formats can be compared, as values are relative to the value range of their format
+    pub fn compare(
+        &self,
+        reference: &DynamicImage,
+        actual: &DynamicImage,
+    ) -> Result<Comparison, CompareError> {
+        let (expected_size, actual_size) = (size(reference), size(actual));
+        if expected_size != actual_size {
+            return Err(CompareError::SizeMismatch {
+                expected: expected_size,
+                actual: actual_size,
+            });
+        }
+        let (reference, actual) = (normalized(reference), normalized(actual));
+        Ok(match *self {
+            CompareMetric::PixelDiff {
+                tolerance,
+                max_differing_percent,
+            } => {
+                let differing = reference
+                    .iter()
+                    .zip(&actual)
+                    .filter(|(a, b)| (*a - *b).abs() > tolerance as f32)
+                    .count();
+                let score = differing as f64 * 100. / reference.len() as f64;
+                Comparison {
+                    score,
+                    passed: score <= max_differing_percent,
+                }
+            }
+            CompareMetric::Ssim { min_similarity } => {
+                let score = ssim(&reference, &actual, expected_size.0 as usize);
+                Comparison {
+                    score,
+                    passed: score >= min_similarity,
+                }
+            }
+        })
+    }
+}
+
+fn size(image: &DynamicImage) -> (u32, u32) {
+    let (width, height) = image.dimensions();
+    (width.get(), height.get())
+}
+
+fn normalized(image: &DynamicImage) -> Vec<f32> {
+    fn scale<T: Copy + Into<f32>>(image: &GenericImage<T, 1>, max: f32) -> Vec<f32> {
+        image.buffer().iter().map(|&v| v.into() / max).collect()
+    }
+    match image {
+        DynamicImage::Luma8(x) => scale(x, u8::MAX as f32),
+        DynamicImage::Luma16(x) => scale(x, u16::MAX as f32),
+    }
+}
+
+fn ssim(a: &[f32], b: &[f32], width: usize) -> f64 {
+    // Stabilizers for a value range of 1 (K1 = 0.01, K2 = 0.03)
+    const C1: f64 = 0.0001;
+    const C2: f64 = 0.0009;
+    let height = a.len() / width;
+    let mut sum = 0.;
+    let mut windows = 0;
+    for top in (0..height).step_by(SSIM_WINDOW) {
+        for left in (0..width).step_by(SSIM_WINDOW) {
+            let pixels = (top..(top + SSIM_WINDOW).min(height)).flat_map(|y| {
+                let start = y * width;
+                (start + left..start + (left + SSIM_WINDOW).min(width))
+                    .map(|i| (a[i] as f64, b[i] as f64))
+            });
+            let (mut n, mut sa, mut sb, mut saa, mut sbb, mut sab) = (0., 0., 0., 0., 0., 0.);
+            for (va, vb) in pixels {
+                n += 1.;
+                sa += va;
+                sb += vb;
+                saa += va * va;
+                sbb += vb * vb;
+                sab += va * vb;
+            }
+            let (mean_a, mean_b) = (sa / n, sb / n);
+            let var_a = saa / n - mean_a * mean_a;
+            let var_b = sbb / n - mean_b * mean_b;
+            let cov = sab / n - mean_a * mean_b;
+            sum += ((2. * mean_a * mean_b + C1) * (2. * cov + C2))
+                / ((mean_a * mean_a + mean_b * mean_b + C1) * (var_a + var_b + C2));
+            windows += 1;
+        }
+    }
+    sum / windows as f64
+}
+
+#[cfg(test)]
+mod tests {
+    use std::num::NonZeroU32;
+
+    use super::*;
+    use crate::image::LumaImage;
+
+    fn luma8(data: Vec<u8>, width: u32) -> DynamicImage {
+        let height = data.len() as u32 / width;
+        DynamicImage::Luma8(LumaImage::new_vec(
+            data,
+            NonZeroU32::new(width).unwrap(),
+            NonZeroU32::new(height).unwrap(),
+        ))
+    }
+
+    #[test]
+    fn pixel_diff_counts_pixels_above_tolerance() {
+        let metric = CompareMetric::PixelDiff {
+            tolerance: 0.05,
+            max_differing_percent: 30.,
+        };
+        let result = metric
+            .compare(&luma8(vec![0, 0, 0, 0], 2), &luma8(vec![0, 10, 100, 0], 2))
+            .unwrap();
+        assert_eq!(25., result.score);
+        assert!(result.passed);
+    }
+
+    #[test]
+    fn ssim_of_identical_images_is_one() {
+        let image = luma8((0..=255).collect(), 16);
+        let metric = CompareMetric::Ssim {
+            min_similarity: 0.99,
+        };
+        let same = metric.compare(&image, &image).unwrap();
+        assert!((same.score - 1.).abs() < 1e-6);
+        assert!(same.passed);
+
+        let inverted = luma8((0..=255).rev().collect(), 16);
+        assert!(!metric.compare(&image, &inverted).unwrap().passed);
+    }
+
+    #[test]
+    fn reject_different_sizes() {
+        assert_eq!(
+            Err(CompareError::SizeMismatch {
+                expected: (2, 2),
+                actual: (4, 1)
+            }),
+            CompareMetric::default().compare(&luma8(vec![0; 4], 2), &luma8(vec![0; 4], 4))
+        );
+    }
+}
@@ -0,0 +1,268 @@
+//! Conversion of [`DynamicImage`] into the pixel formats a consumer can handle
+//!
+//! Consumers declare the formats they accept instead of matching on [`DynamicImage`] themselves,
+//! so the policy how 16 bit images are reduced to 8 bit is the same everywhere
+
+use std::sync::Arc;
+
+use futures::{stream::BoxStream, Stream, StreamExt};
+
+use super::{
+    DynamicImage, GenericImage, ImageWithMeta, LumaImage, PackedGenericImage, PackedRgbImage,
+    StreamImageError,
+};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[non_exhaustive]
+pub enum PixelFormat {
+    Luma8,
+    Luma16,
+    /// RGBRGBRGB, grey images are replicated into all channels
+    PackedRgb8,
+}
+
+/// How 16 bit images are reduced to 8 bit
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum Luma16Scaling {
+    /// Keeps the most significant byte. Brightness is comparable between frames
+    #[default]
+    MostSignificantByte,
+    /// Keeps the 8 most significant of `n` used bits, e.g. 12 for 12 bit sensors. Higher values are saturated
+    SignificantBits(u8),
+    /// Stretches the range between the darkest and brightest pixel of each frame to 0..=255.
+    /// Best contrast, but brightness isn't comparable between frames
+    MinMax,
+}
+
+#[derive(Debug, Clone)]
+#[non_exhaustive]
+pub enum ConvertedImage {
+    Luma8(LumaImage),
+    Luma16(GenericImage<u16, 1>),
+    PackedRgb8(Arc<dyn PackedRgbImage + Send + Sync>),
+}
+
+impl ConvertedImage {
+    pub fn format(&self) -> PixelFormat {
+        match self {
+            ConvertedImage::Luma8(_) => PixelFormat::Luma8,
+            ConvertedImage::Luma16(_) => PixelFormat::Luma16,
+            ConvertedImage::PackedRgb8(_) => PixelFormat::PackedRgb8,
+        }
+    }
+}
+
+impl DynamicImage {
+    pub fn format(&self) -> PixelFormat {
+        match self {
+            DynamicImage::Luma8(_) => PixelFormat::Luma8,
+            DynamicImage::Luma16(_) => PixelFormat::Luma16,
+        }
+    }
+}
+
+#[derive(Debug, Clone, thiserror::Error)]
+#[error(
+    "Image with format {actual:?} cannot be converted, as the consumer doesn't accept any format"
+)]
+pub struct ImageFormatError {
+    pub actual: PixelFormat,
+}
+
+#[derive(Debug, Clone, thiserror::Error)]
+pub enum ConvertedStreamError {
+    #[error("{0}")]
+    Stream(StreamImageError<ConvertedImage>),
+    #[error("{0}")]
+    Format(#[from] ImageFormatError),
+}
+
+#[derive(Debug, Clone)]
+pub struct ImageConverter {
+    accepted: Vec<PixelFormat>,
+    luma16_scaling: Luma16Scaling,
+}
+
+impl ImageConverter {
+    /// Images are passed without conversion, if their format is accepted.
+    /// Otherwise, they are converted into the first format in `accepted`
+    pub fn new(accepted: impl IntoIterator<Item = PixelFormat>) -> Self {
+        Self {
+            accepted: accepted.into_iter().collect(),
+            luma16_scaling: Default::default(),
+        }
+    }
+
+    pub fn with_luma16_scaling(mut self, scaling: Luma16Scaling) -> Self {
+        self.luma16_scaling = scaling;
+        self
+    }
+
+    pub fn convert(&self, image: DynamicImage) -> Result<ConvertedImage, ImageFormatError> {
+        let actual = image.format();
+        let target = if self.accepted.contains(&actual) {
+            actual
+        } else {
+            *self.accepted.first().ok_or(ImageFormatError { actual })?
+        };
+        Ok(match (image, target) {
+            (DynamicImage::Luma8(x), PixelFormat::Luma8) => ConvertedImage::Luma8(x),
+            (DynamicImage::Luma16(x), PixelFormat::Luma16) => ConvertedImage::Luma16(x),
+            (DynamicImage::Luma8(x), PixelFormat::Luma16) => {
+                let (width, height) = x.dimensions();
+                let data = x.buffer().iter().map(|&v| v as u16 * 257).collect();
+                ConvertedImage::Luma16(GenericImage::new_vec(data, width, height))
+            }
+            (DynamicImage::Luma16(x), PixelFormat::Luma8) => {
+                ConvertedImage::Luma8(self.luma16_scaling.apply(&x))
+            }
+            (DynamicImage::Luma8(x), PixelFormat::PackedRgb8) => {
+                ConvertedImage::PackedRgb8(Arc::new(PackedGenericImage::from(&x)))
+            }
+            (DynamicImage::Luma16(x), PixelFormat::PackedRgb8) => ConvertedImage::PackedRgb8(
+                Arc::new(PackedGenericImage::from(&self.luma16_scaling.apply(&x))),
+            ),
+        })
+    }
+
+    /// Converts the main image and all other images
+    pub fn convert_with_meta(
+        &self,
+        image: ImageWithMeta<DynamicImage>,
+    ) -> Result<ImageWithMeta<ConvertedImage>, ImageFormatError> {
+        let ImageWithMeta { image, meta, other } = image;
+        Ok(ImageWithMeta::with_meta_and_others(
+            self.convert(image)?,
+            meta,
+            other
+                .into_iter()
+                .map(|(key, image)| Ok((key, self.convert(image)?)))
+                .collect::<Result<_, ImageFormatError>>()?,
+        ))
+    }
+
+    /// Adapter for streams of e.g. `SubscribeDynamicImageMessage`. Conversion errors are reported per frame
+    pub fn adapt(
+        self,
+        stream: impl Stream<Item = Result<ImageWithMeta<DynamicImage>, StreamImageError<DynamicImage>>>
+            + Send
+            + 'static,
+    ) -> BoxStream<'static, Result<ImageWithMeta<ConvertedImage>, ConvertedStreamError>> {
+        stream
+            .map(move |item| match item {
+                Ok(image) => Ok(self.convert_with_meta(image)?),
+                Err(e) => Err(self.convert_error(e)),
+            })
+            .boxed()
+    }
+
+    fn convert_error(&self, error: StreamImageError<DynamicImage>) -> ConvertedStreamError {
+        ConvertedStreamError::Stream(match error {
+            StreamImageError::MissedItems(x) => StreamImageError::MissedItems(x),
+            StreamImageError::ProcessingError { image, error } => match self.convert(image) {
+                Ok(image) => StreamImageError::ProcessingError { image, error },
+                Err(e) => return e.into(),
+            },
+            StreamImageError::ActorError(x) => StreamImageError::ActorError(x),
+            StreamImageError::ProducerRestarted { new_hash } => {
+                StreamImageError::ProducerRestarted { new_hash }
+            }
+        })
+    }
+}
+
+impl Luma16Scaling {
+    fn apply(self, image: &GenericImage<u16, 1>) -> LumaImage {
+        let (width, height) = image.dimensions();
+        let buffer = image.buffer();
+        let data = match self {
+            Luma16Scaling::MostSignificantByte => buffer.iter().map(|&v| (v >> 8) as u8).collect(),
+            Luma16Scaling::SignificantBits(bits) => {
+                let shift = bits.saturating_sub(8).min(8);
+                buffer
+                    .iter()
+                    .map(|&v| (v >> shift).min(u8::MAX as u16) as u8)
+                    .collect()
+            }
+            Luma16Scaling::MinMax => {
+                let min = buffer.iter().copied().min().unwrap_or_default() as u32;
+                let max = buffer.iter().copied().max().unwrap_or_default() as u32;
+                let range = (max - min).max(1);
+                buffer
+                    .iter()
+                    .map(|&v| ((v as u32 - min) * 255 / range) as u8)
+                    .collect()
+            }
+        };
+        LumaImage::new_vec(data, width, height)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::num::NonZeroU32;
+
+    use super::*;
+
+    fn luma16(data: Vec<u16>) -> DynamicImage {
+        let width = NonZeroU32::new(data.len() as u32).unwrap();
+        DynamicImage::Luma16(GenericImage::new_vec(data, width, NonZeroU32::MIN))
+    }
+
+    fn luma8_buffer(image: ConvertedImage) -> Vec<u8> {
+        match image {
+            ConvertedImage::Luma8(x) => x.buffer().to_vec(),
+            x => panic!("Expected Luma8, got {x:?}"),
+        }
+    }
+
+    #[test]
+    fn keep_accepted_format() {
+        let converter = ImageConverter::new([PixelFormat::Luma8, PixelFormat::Luma16]);
+        let converted = converter.convert(luma16(vec![1, 2])).unwrap();
+        assert_eq!(PixelFormat::Luma16, converted.format());
+    }
+
+    #[test]
+    fn scale_luma16_to_luma8() {
+        let data = vec![0x0100, 0x0800, 0x0FFF];
+        let converter = ImageConverter::new([PixelFormat::Luma8]);
+        assert_eq!(
+            vec![1, 8, 15],
+            luma8_buffer(converter.convert(luma16(data.clone())).unwrap())
+        );
+        let converter = converter.with_luma16_scaling(Luma16Scaling::SignificantBits(12));
+        assert_eq!(
+            vec![16, 128, 255],
+            luma8_buffer(converter.convert(luma16(data.clone())).unwrap())
+        );
+        let converter = converter.with_luma16_scaling(Luma16Scaling::MinMax);
+        assert_eq!(
+            vec![0, 119, 255],
+            luma8_buffer(converter.convert(luma16(data)).unwrap())
+        );
+    }
+
+    #[test]
+    fn fail_without_accepted_formats() {
+        assert!(ImageConverter::new([]).convert(luma16(vec![1])).is_err());
+    }
+
+    #[test]
+    fn adapt_stream() {
+        let converter = ImageConverter::new([PixelFormat::PackedRgb8]);
+        let mut stream = converter.adapt(futures::stream::iter([Ok(ImageWithMeta::with_hash(
+            luma16(vec![0xFF00]),
+            None,
+        ))]));
+        match futures::executor::block_on(stream.next())
+            .unwrap()
+            .unwrap()
+            .image
+        {
+            ConvertedImage::PackedRgb8(x) => assert_eq!(&[255, 255, 255], x.buffer()),
+            x => panic!("Expected PackedRgb8, got {x:?}"),
+        }
+    }
+}
@@ -0,0 +1,221 @@
+//! Stable C interface for exchanging images with non-Rust plugins (e.g. C++/Halcon).
+//!
+//! `GenericImage` is repr(C), but its layout is generic and might change. `PilatusImage` is the versioned,
+//! non-generic representation which is published in `pilatus_image.h` (build with feature `cbindgen`).
+//! Buffers are never copied during conversion. Ownership is transferred together with the `release` callback,
+//! which is called exactly once by the receiver.
+
+use std::{ffi::c_void, num::NonZeroU32};
+
+use super::{clone_slice, Factory, GenericImage, ImageVtable};
+
+/// Must be increased whenever the layout of `PilatusImage` changes
+pub const PILATUS_IMAGE_ABI_VERSION: u32 = 1;
+
+pub const PILATUS_PIXEL_TYPE_U8: u32 = 0;
+pub const PILATUS_PIXEL_TYPE_U16: u32 = 1;
+
+/// Image with a buffer of `width * height * channels` pixels in y:x:channel order
+#[repr(C)]
+#[derive(Debug)]
+pub struct PilatusImage {
+    /// Must be `PILATUS_IMAGE_ABI_VERSION`
+    pub abi_version: u32,
+    /// One of `PILATUS_PIXEL_TYPE_*`
+    pub pixel_type: u32,
+    pub channels: u32,
+    pub width: u32,
+    pub height: u32,
+    pub buffer: *const c_void,
+    /// Opaque pointer passed to `release`
+    pub owner: *mut c_void,
+    /// Frees the buffer. Might be called from any thread
+    pub release: Option<unsafe extern "C" fn(owner: *mut c_void)>,
+}
+
+unsafe impl Send for PilatusImage {}
+
+impl Drop for PilatusImage {
+    fn drop(&mut self) {
+        if let Some(release) = self.release.take() {
+            unsafe { release(self.owner) };
+        }
+    }
+}
+
+#[derive(Debug, thiserror::Error, PartialEq, Eq)]
+pub enum PilatusImageError {
+    #[error("Image uses ABI version {0}, but {PILATUS_IMAGE_ABI_VERSION} is required")]
+    AbiVersion(u32),
+    #[error("Expected pixel type {expected} with {expected_channels} channels, got {actual} with {actual_channels}")]
+    Format {
+        expected: u32,
+        expected_channels: u32,
+        actual: u32,
+        actual_channels: u32,
+    },
+    #[error("Image must have a buffer and a size of at least 1x1")]
+    Empty,
+}
+
+/// Pixel types which can cross the C interface
+pub trait PilatusPixel: Clone + 'static {
+    const PIXEL_TYPE: u32;
+}
+
+impl PilatusPixel for u8 {
+    const PIXEL_TYPE: u32 = PILATUS_PIXEL_TYPE_U8;
+}
+
+impl PilatusPixel for u16 {
+    const PIXEL_TYPE: u32 = PILATUS_PIXEL_TYPE_U16;
+}
+
+#[no_mangle]
+pub extern "C" fn pilatus_image_abi_version() -> u32 {
+    PILATUS_IMAGE_ABI_VERSION
+}
+
+/// Releases an image which was received from pilatus. The image must not be used afterwards
+///
+/// # Safety
+/// `image` must be null or point to a valid `PilatusImage`
+#[no_mangle]
+pub unsafe extern "C" fn pilatus_image_release(image: *mut PilatusImage) {
+    if let Some(image) = image.as_mut() {
+        if let Some(release) = image.release.take() {
+            release(image.owner);
+        }
+    }
+}
+
+unsafe extern "C" fn release_generic<T, const CHANNELS: usize>(owner: *mut c_void) {
+    drop(Box::from_raw(owner as *mut GenericImage<T, CHANNELS>));
+}
+
+impl<T: PilatusPixel, const CHANNELS: usize> From<GenericImage<T, CHANNELS>> for PilatusImage {
+    fn from(image: GenericImage<T, CHANNELS>) -> Self {
+        let (width, height) = image.dimensions();
+        let buffer = image.ptr as *const c_void;
+        PilatusImage {
+            abi_version: PILATUS_IMAGE_ABI_VERSION,
+            pixel_type: T::PIXEL_TYPE,
+            channels: CHANNELS as u32,
+            width: width.get(),
+            height: height.get(),
+            buffer,
+            owner: Box::into_raw(Box::new(image)) as *mut c_void,
+            release: Some(release_generic::<T, CHANNELS>),
+        }
+    }
+}
+
+struct ForeignOwner {
+    owner: *mut c_void,
+    release: Option<unsafe extern "C" fn(owner: *mut c_void)>,
+}
+
+struct ForeignFactory;
+
+impl<T: PilatusPixel, const CHANNELS: usize> Factory<T, CHANNELS> for ForeignFactory {
+    const VTABLE: &'static ImageVtable<T, CHANNELS> = {
+        /// Foreign buffers are never mutated. The image is copied into a Vec instead
+        unsafe extern "C" fn make_mut<T: Clone + 'static, const CHANNELS: usize>(
+            image: &mut GenericImage<T, CHANNELS>,
+            out_len: &mut usize,
+        ) -> *mut T {
+            let (width, height) = image.dimensions();
+            *image = GenericImage::new_vec(image.buffer().to_vec(), width, height);
+            (image.vtable.make_mut)(image, out_len)
+        }
+
+        extern "C" fn release_foreign<T, const CHANNELS: usize>(
+            image: &mut GenericImage<T, CHANNELS>,
+        ) {
+            let owner = unsafe { Box::from_raw(image.data as *mut ForeignOwner) };
+            if let Some(release) = owner.release {
+                unsafe { release(owner.owner) };
+            }
+        }
+
+        &ImageVtable {
+            make_mut,
+            drop: release_foreign,
+            clone: clone_slice,
+        }
+    };
+}
+
+impl<T: PilatusPixel, const CHANNELS: usize> TryFrom<PilatusImage> for GenericImage<T, CHANNELS> {
+    type Error = PilatusImageError;
+
+    fn try_from(mut image: PilatusImage) -> Result<Self, Self::Error> {
+        if image.abi_version != PILATUS_IMAGE_ABI_VERSION {
+            return Err(PilatusImageError::AbiVersion(image.abi_version));
+        }
+        if image.pixel_type != T::PIXEL_TYPE || image.channels != CHANNELS as u32 {
+            return Err(PilatusImageError::Format {
+                expected: T::PIXEL_TYPE,
+                expected_channels: CHANNELS as u32,
+                actual: image.pixel_type,
+                actual_channels: image.channels,
+            });
+        }
+        let (Some(width), Some(height)) =
+            (NonZeroU32::new(image.width), NonZeroU32::new(image.height))
+        else {
+            return Err(PilatusImageError::Empty);
+        };
+        if image.buffer.is_null() {
+            return Err(PilatusImageError::Empty);
+        }
+
+        let owner = Box::new(ForeignOwner {
+            owner: image.owner,
+            release: image.release.take(),
+        });
+        let vtable = <ForeignFactory as Factory<T, CHANNELS>>::VTABLE;
+        Ok(unsafe {
+            GenericImage::new_with_vtable(
+                image.buffer as *const T,
+                width,
+                height,
+                vtable,
+                Box::into_raw(owner) as usize,
+            )
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::image::LumaImage;
+
+    use super::*;
+
+    #[test]
+    fn miri_roundtrip_doesnt_copy() {
+        let size = 2.try_into().unwrap();
+        let image = LumaImage::new_vec(vec![0u8, 64u8, 128u8, 192u8], size, size);
+        let pointer = image.buffer().as_ptr();
+        let ffi = PilatusImage::from(image);
+        assert_eq!(PILATUS_PIXEL_TYPE_U8, ffi.pixel_type);
+
+        let mut back = LumaImage::try_from(ffi).unwrap();
+        assert_eq!(pointer, back.buffer().as_ptr());
+        assert_eq!(&[0u8, 64, 128, 192], back.buffer());
+
+        back.make_mut()[0] = 1;
+        assert_eq!(&[1u8, 64, 128, 192], back.buffer());
+    }
+
+    #[test]
+    fn miri_reject_wrong_format() {
+        let size = 1.try_into().unwrap();
+        let ffi = PilatusImage::from(LumaImage::new_vec(vec![0u8], size, size));
+        assert!(matches!(
+            GenericImage::<u16, 1>::try_from(ffi),
+            Err(PilatusImageError::Format { .. })
+        ));
+    }
+}
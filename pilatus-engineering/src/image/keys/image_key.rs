@@ -23,10 +23,21 @@ impl TryFrom<Cow<'static, str>> for ImageKey {
     }
 }
 
+impl From<SpecificImageKey> for ImageKey {
+    fn from(value: SpecificImageKey) -> Self {
+        Self(Some(value))
+    }
+}
+
 impl ImageKey {
     pub const fn unspecified() -> Self {
         Self(None)
     }
+
+    /// See [`SpecificImageKey::overlay`]
+    pub const fn overlay() -> Self {
+        Self(Some(SpecificImageKey::overlay()))
+    }
     pub(in super::super) fn by_name_or<'a, T>(
         &self,
         collection: &'a HashMap<SpecificImageKey, T>,
@@ -64,4 +75,11 @@ mod tests {
         let key: ImageKey = serde_json::from_str("null").unwrap();
         assert_eq!(ImageKey::unspecified(), key);
     }
+
+    #[test]
+    fn specific_keys_are_specified() {
+        let key = ImageKey::from(SpecificImageKey::try_from("overlay").unwrap());
+        assert_ne!(ImageKey::unspecified(), key);
+        assert_eq!(ImageKey::try_from("overlay").unwrap(), key);
+    }
 }
@@ -17,6 +17,17 @@ impl<'de> Deserialize<'de> for SpecificImageKey {
     }
 }
 
+impl SpecificImageKey {
+    /// Annotated debug images, e.g. drawn with [`Overlay`](crate::image::Overlay)
+    pub const fn overlay() -> Self {
+        Self(Cow::Borrowed("overlay"))
+    }
+
+    pub fn as_str(&self) -> &str {
+        &self.0
+    }
+}
+
 impl TryFrom<&'static str> for SpecificImageKey {
     type Error = IntoSpecificImageKeyError;
 
@@ -1,4 +1,12 @@
-use std::{collections::HashMap, convert::Infallible, fmt::Debug, sync::Arc};
+use std::{
+    collections::HashMap,
+    convert::Infallible,
+    fmt::Debug,
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Arc,
+    },
+};
 
 use futures::stream::BoxStream;
 use pilatus::{
@@ -27,6 +35,37 @@ pub enum StreamImageError<TImage> {
     },
     #[error("ActorError: {0:?}")]
     ActorError(Arc<ActorError<Infallible>>),
+    /// Last item of a subscription, because the producer restarted with new parameters.
+    /// Consumers should subscribe again right away. `new_hash` identifies the new parameters, if the producer knows them
+    ///
+    /// Only producers which restart themselves on param changes send this, e.g. the emulation camera or the bridge.
+    /// If the runtime restarts a device, e.g. after disabling it or changing variables, its senders are dropped and
+    /// subscriptions just end without this item. Consumers which resubscribe should therefore treat the end of a stream alike
+    #[error("Producer restarted")]
+    ProducerRestarted { new_hash: Option<StableHash> },
+}
+
+impl<T> StreamImageError<T> {
+    pub fn is_producer_restarted(&self) -> bool {
+        matches!(self, Self::ProducerRestarted { .. })
+    }
+}
+
+/// Ends a subscription after [`StreamImageError::ProducerRestarted`], for producers which keep their sender across restarts
+pub fn end_after_producer_restart<T: Send + 'static>(
+    stream: impl futures::Stream<Item = Result<ImageWithMeta<T>, StreamImageError<T>>> + Send + 'static,
+) -> BoxStream<'static, Result<ImageWithMeta<T>, StreamImageError<T>>> {
+    use futures::StreamExt;
+
+    stream
+        .scan(false, |restarted, item| {
+            let next = (!*restarted).then(|| {
+                *restarted = matches!(&item, Err(e) if e.is_producer_restarted());
+                item
+            });
+            futures::future::ready(next)
+        })
+        .boxed()
 }
 
 impl<T: Debug> From<ActorError<(T, anyhow::Error)>> for StreamImageError<T> {
@@ -64,8 +103,25 @@ impl<T> std::ops::DerefMut for ImageWithMeta<T> {
 }
 
 #[derive(Clone, Serialize, Deserialize, Debug)]
+#[cfg_attr(feature = "ts", derive(ts_rs::TS), ts(export))]
 pub struct ImageMeta {
     pub hash: Option<StableHash>,
+    /// Frames received from other processes get a new id, because ids are only unique within a process
+    #[serde(skip_deserializing, default = "FrameId::next")]
+    pub frame_id: FrameId,
+}
+
+/// Identifies a single frame within the process. Unlike the hash, which identifies the producer chain,
+/// two frames never share an id, but all clones of a frame do
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[cfg_attr(feature = "ts", derive(ts_rs::TS))]
+pub struct FrameId(u64);
+
+impl FrameId {
+    pub fn next() -> Self {
+        static NEXT: AtomicU64 = AtomicU64::new(0);
+        Self(NEXT.fetch_add(1, Ordering::Relaxed))
+    }
 }
 
 pub type GetImageOk = ImageWithMeta<LumaImage>;
@@ -82,7 +138,10 @@ impl<T> ImageWithMeta<T> {
     pub fn with_hash(image: T, hash: Option<StableHash>) -> Self {
         Self {
             image,
-            meta: ImageMeta { hash },
+            meta: ImageMeta {
+                hash,
+                frame_id: FrameId::next(),
+            },
             other: Default::default(),
         }
     }
@@ -130,11 +189,34 @@ impl ActorMessage for GetImageMessage {
     type Error = anyhow::Error;
 }
 
+/// Keys of the images a producer emits in addition to the main image, so clients can offer them without hardcoding.
+/// Producers without this handler don't declare their keys, which is different from declaring none
+#[derive(Default, Debug)]
+#[non_exhaustive]
+pub struct ListImageKeysMessage {}
+
+impl ActorMessage for ListImageKeysMessage {
+    type Output = Vec<SpecificImageKey>;
+    type Error = Infallible;
+}
+
 pub type SubscribeImageOk = BoxStream<'static, BroadcastImage>;
 
 #[derive(Default, Debug, Clone)]
 #[non_exhaustive]
-pub struct SubscribeImageQuery {}
+pub struct SubscribeImageQuery {
+    /// Images which are requested in addition to the main image. Producers are free to skip
+    /// generating images which nobody requested
+    pub keys: Vec<ImageKey>,
+}
+
+impl SubscribeImageQuery {
+    pub fn with_keys(keys: impl IntoIterator<Item = ImageKey>) -> Self {
+        Self {
+            keys: keys.into_iter().collect(),
+        }
+    }
+}
 
 #[derive(Default)]
 #[non_exhaustive]
@@ -151,6 +233,15 @@ impl ActorMessage for SubscribeImageMessage {
     type Error = anyhow::Error;
 }
 
+pilatus::actor_client! {
+    /// Typed facade for devices producing images (e.g. cameras)
+    pub struct ImageClient {
+        pub fn get_image() -> GetImageMessage;
+        pub fn subscribe() -> SubscribeImageMessage;
+        pub fn get_localizable_image() -> GetLocalizableImageMessage;
+    }
+}
+
 /// Contains hash to be able to immediately detect changes in the producer chain
 /// The consumer is free to continue the stream or reconnect
 #[non_exhaustive]
@@ -194,6 +285,7 @@ impl From<LocalizableBroadcastImage> for BroadcastImage {
 pub struct LocalizableBroadcastImage {
     pub image: Arc<LumaImage>,
     pub hash: Option<StableHash>,
+    pub frame_id: FrameId,
     pub projector: Option<DynamicPointProjector>,
 }
 
@@ -206,6 +298,7 @@ impl LocalizableBroadcastImage {
         Self {
             image: image.into(),
             hash,
+            frame_id: FrameId::next(),
             projector,
         }
     }
@@ -216,6 +309,7 @@ impl std::fmt::Debug for LocalizableBroadcastImage {
         f.debug_struct("LocalizableBroadcastImage")
             .field("image", &self.image)
             .field("hash", &self.hash)
+            .field("frame_id", &self.frame_id)
             .finish()
     }
 }
@@ -300,3 +394,36 @@ impl From<SubscribeLocalizableImageMessage> for SubscribeImageMessage {
         Self {}
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use futures::StreamExt;
+
+    use super::*;
+
+    #[test]
+    fn clones_share_frame_id() {
+        let image = ImageWithMeta::with_hash(1, None);
+        let other = ImageWithMeta::with_hash(1, None);
+        assert_eq!(image.frame_id, image.clone().frame_id);
+        assert_ne!(image.frame_id, other.frame_id);
+
+        let json = serde_json::to_string(&image.meta).unwrap();
+        let received: ImageMeta = serde_json::from_str(&json).unwrap();
+        assert_ne!(image.frame_id, received.frame_id);
+    }
+
+    #[test]
+    fn stream_ends_after_producer_restart() {
+        let items = futures::executor::block_on(
+            end_after_producer_restart(futures::stream::iter([
+                Ok(ImageWithMeta::with_hash(1, None)),
+                Err(StreamImageError::ProducerRestarted { new_hash: None }),
+                Ok(ImageWithMeta::with_hash(2, None)),
+            ]))
+            .collect::<Vec<_>>(),
+        );
+        assert_eq!(2, items.len());
+        assert!(items[1].as_ref().unwrap_err().is_producer_restarted());
+    }
+}
@@ -30,25 +30,39 @@ use crate::{InvertibleTransform, InvertibleTransform3d};
 
 #[cfg(feature = "tokio")]
 mod broadcaster;
+mod color;
+mod compare;
+mod convert;
+mod ffi;
 mod keys;
 #[cfg(feature = "image-algorithm")]
 mod logo;
 mod message;
+mod overlay;
 #[cfg(feature = "image-algorithm")]
 mod png;
+mod pyramid;
 mod stable_hash;
+mod statistics;
 
 #[cfg(feature = "tokio")]
 pub use broadcaster::*;
+pub use color::*;
+pub use compare::*;
+pub use convert::*;
+pub use ffi::*;
 use image::GenericImageView;
 pub use keys::*;
 #[cfg(feature = "image-algorithm")]
 pub use logo::*;
 
 pub use message::*;
+pub use overlay::*;
 #[cfg(feature = "image-algorithm")]
 pub use png::*;
+pub use pyramid::*;
 pub use stable_hash::*;
+pub use statistics::*;
 
 pub trait PointProjector {
     fn project_to_world_plane(
@@ -0,0 +1,337 @@
+//! Draws annotations like regions, found features or measured values onto debug images.
+//! Devices publish the result with [`ImageKey::overlay`](super::ImageKey::overlay), so no device needs its own drawing dependencies:
+//! ```ignore
+//! let mut debug = image.clone();
+//! Overlay::new(&mut debug)
+//!     .rectangle((10, 10), (100, 40), Color::GREEN)
+//!     .text((12, 12), "OK 12.3mm", 2, Color::GREEN);
+//! ```
+
+mod font;
+
+use super::GenericImage;
+
+/// Luma images receive the luminance of the color
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Color {
+    pub r: u8,
+    pub g: u8,
+    pub b: u8,
+}
+
+impl Color {
+    pub const BLACK: Self = Self::gray(0);
+    pub const WHITE: Self = Self::gray(255);
+    pub const RED: Self = Self::rgb(255, 0, 0);
+    pub const GREEN: Self = Self::rgb(0, 255, 0);
+    pub const BLUE: Self = Self::rgb(0, 0, 255);
+    pub const YELLOW: Self = Self::rgb(255, 255, 0);
+
+    pub const fn rgb(r: u8, g: u8, b: u8) -> Self {
+        Self { r, g, b }
+    }
+
+    pub const fn gray(value: u8) -> Self {
+        Self::rgb(value, value, value)
+    }
+
+    /// ITU-R BT.601 weights
+    pub const fn luma(self) -> u8 {
+        ((self.r as u32 * 299 + self.g as u32 * 587 + self.b as u32 * 114 + 500) / 1000) as u8
+    }
+}
+
+/// Coordinates are (x=col, y=row) in pixels. Everything outside of the image is clipped
+pub struct Overlay<'a> {
+    buffer: &'a mut [u8],
+    width: i64,
+    height: i64,
+    channels: usize,
+    thickness: u32,
+}
+
+impl<'a> Overlay<'a> {
+    /// Images with 2 channels are luma with alpha, images with 3 or 4 channels must be packed (RGBRGB or RGBARGBA).
+    /// The alpha channel is left untouched
+    pub fn new<const CHANNELS: usize>(image: &'a mut GenericImage<u8, CHANNELS>) -> Self {
+        let (width, height) = image.dimensions();
+        Self {
+            buffer: image.make_mut(),
+            width: width.get() as i64,
+            height: height.get() as i64,
+            channels: CHANNELS,
+            thickness: 1,
+        }
+    }
+
+    /// Line width of shapes. Text is scaled separately
+    pub fn with_thickness(mut self, thickness: u32) -> Self {
+        self.thickness = thickness.max(1);
+        self
+    }
+
+    pub fn line(&mut self, from: (i32, i32), to: (i32, i32), color: Color) -> &mut Self {
+        // Points within the thickness outside of the image still touch it
+        let margin = self.thickness as i64;
+        let (min, max) = (
+            (-margin, -margin),
+            (self.width + margin, self.height + margin),
+        );
+        let inside = |(x, y): (i32, i32)| {
+            (min.0..=max.0).contains(&(x as i64)) && (min.1..=max.1).contains(&(y as i64))
+        };
+        let ((mut x, mut y), (x1, y1)) = if inside(from) && inside(to) {
+            ((from.0 as i64, from.1 as i64), (to.0 as i64, to.1 as i64))
+        } else {
+            match clip_line(from, to, min, max) {
+                Some(clipped) => clipped,
+                None => return self,
+            }
+        };
+        let dx = (x1 - x).abs();
+        let dy = -(y1 - y).abs();
+        let (sx, sy) = ((x1 - x).signum(), (y1 - y).signum());
+        let mut err = dx + dy;
+        loop {
+            self.dot(x, y, color);
+            if x == x1 && y == y1 {
+                return self;
+            }
+            let e2 = 2 * err;
+            if e2 >= dy {
+                err += dy;
+                x += sx;
+            }
+            if e2 <= dx {
+                err += dx;
+                y += sy;
+            }
+        }
+    }
+
+    /// The last point is connected with the first one
+    pub fn polygon(&mut self, points: &[(i32, i32)], color: Color) -> &mut Self {
+        for (i, from) in points.iter().enumerate() {
+            self.line(*from, points[(i + 1) % points.len()], color);
+        }
+        self
+    }
+
+    pub fn rectangle(&mut self, top_left: (i32, i32), size: (u32, u32), color: Color) -> &mut Self {
+        let (x, y) = top_left;
+        let right = x.saturating_add(size.0.saturating_sub(1) as i32);
+        let bottom = y.saturating_add(size.1.saturating_sub(1) as i32);
+        self.polygon(&[(x, y), (right, y), (right, bottom), (x, bottom)], color)
+    }
+
+    pub fn fill_rectangle(
+        &mut self,
+        top_left: (i32, i32),
+        size: (u32, u32),
+        color: Color,
+    ) -> &mut Self {
+        self.fill_clipped(
+            (top_left.0 as i64, top_left.1 as i64),
+            (size.0 as i64, size.1 as i64),
+            color,
+        );
+        self
+    }
+
+    /// `size` is the length of each arm from the center
+    pub fn crosshair(&mut self, center: (i32, i32), size: u32, color: Color) -> &mut Self {
+        let (x, y) = center;
+        let size = size.min(i32::MAX as u32) as i32;
+        self.line(
+            (x.saturating_sub(size), y),
+            (x.saturating_add(size), y),
+            color,
+        );
+        self.line(
+            (x, y.saturating_sub(size)),
+            (x, y.saturating_add(size)),
+            color,
+        )
+    }
+
+    /// Renders with an embedded 5x7 font, where each font pixel covers `scale`x`scale` image pixels.
+    /// `top_left` is the corner of the first glyph. '\n' starts a new line
+    pub fn text(
+        &mut self,
+        top_left: (i32, i32),
+        text: &str,
+        scale: u32,
+        color: Color,
+    ) -> &mut Self {
+        let scale = scale.max(1) as i64;
+        let (mut x, mut y) = (top_left.0 as i64, top_left.1 as i64);
+        for c in text.chars() {
+            if c == '\n' {
+                x = top_left.0 as i64;
+                y += (font::GLYPH_HEIGHT + 1) as i64 * scale;
+                continue;
+            }
+            if x >= self.width || y >= self.height {
+                continue;
+            }
+            for (row, bits) in font::glyph(c).into_iter().enumerate() {
+                for col in 0..font::GLYPH_WIDTH {
+                    if bits & (1 << (font::GLYPH_WIDTH - 1 - col)) == 0 {
+                        continue;
+                    }
+                    let (px, py) = (x + col as i64 * scale, y + row as i64 * scale);
+                    self.fill_clipped((px, py), (scale, scale), color);
+                }
+            }
+            x += (font::GLYPH_WIDTH + 1) as i64 * scale;
+        }
+        self
+    }
+
+    /// Size in pixels which [`Overlay::text`] covers, e.g. to draw a background first
+    pub fn text_size(text: &str, scale: u32) -> (u32, u32) {
+        let scale = scale.max(1);
+        let (lines, max_chars) = text.split('\n').fold((0, 0), |(lines, max), line| {
+            (lines + 1, max.max(line.chars().count() as u32))
+        });
+        let width = (max_chars * (font::GLYPH_WIDTH + 1)).saturating_sub(1);
+        let height = (lines * (font::GLYPH_HEIGHT + 1)).saturating_sub(1);
+        (width * scale, height * scale)
+    }
+
+    fn dot(&mut self, x: i64, y: i64, color: Color) {
+        let thickness = self.thickness as i64;
+        let start = -(thickness - 1) / 2;
+        self.fill_clipped((x + start, y + start), (thickness, thickness), color);
+    }
+
+    /// Only iterates over the part within the image, so huge shapes don't take forever
+    fn fill_clipped(&mut self, top_left: (i64, i64), size: (i64, i64), color: Color) {
+        let (x, y) = top_left;
+        let cols = x.max(0)..(x + size.0).min(self.width);
+        for row in y.max(0)..(y + size.1).min(self.height) {
+            for col in cols.clone() {
+                self.set_pixel(col, row, color);
+            }
+        }
+    }
+
+    fn set_pixel(&mut self, x: i64, y: i64, color: Color) {
+        if x < 0 || y < 0 || x >= self.width || y >= self.height {
+            return;
+        }
+        let offset = (y * self.width + x) as usize * self.channels;
+        match self.channels {
+            1 | 2 => self.buffer[offset] = color.luma(),
+            _ => self.buffer[offset..offset + 3].copy_from_slice(&[color.r, color.g, color.b]),
+        }
+    }
+}
+
+/// Liang-Barsky: The part of the line within `min..=max`, None if it doesn't cross this area
+fn clip_line(
+    from: (i32, i32),
+    to: (i32, i32),
+    min: (i64, i64),
+    max: (i64, i64),
+) -> Option<((i64, i64), (i64, i64))> {
+    let (x0, y0) = (from.0 as f64, from.1 as f64);
+    let (dx, dy) = (to.0 as f64 - x0, to.1 as f64 - y0);
+    let (mut t0, mut t1) = (0f64, 1f64);
+    for (p, q) in [
+        (-dx, x0 - min.0 as f64),
+        (dx, max.0 as f64 - x0),
+        (-dy, y0 - min.1 as f64),
+        (dy, max.1 as f64 - y0),
+    ] {
+        if p == 0. {
+            if q < 0. {
+                return None;
+            }
+            continue;
+        }
+        let r = q / p;
+        if p < 0. {
+            t0 = t0.max(r);
+        } else {
+            t1 = t1.min(r);
+        }
+        if t0 > t1 {
+            return None;
+        }
+    }
+    let at = |t: f64| ((x0 + t * dx).round() as i64, (y0 + t * dy).round() as i64);
+    Some((at(t0), at(t1)))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::num::NonZeroU32;
+
+    use super::*;
+    use crate::image::LumaImage;
+
+    fn dim(x: u32) -> NonZeroU32 {
+        NonZeroU32::new(x).unwrap()
+    }
+
+    #[test]
+    fn draw_rectangle_outline_and_clip() {
+        let mut image = LumaImage::new_vec(vec![0; 25], dim(5), dim(5));
+        Overlay::new(&mut image)
+            .rectangle((1, 1), (3, 3), Color::WHITE)
+            .crosshair((-10, -10), 3, Color::WHITE);
+
+        #[rustfmt::skip]
+        let expected: [u8; 25] = [
+            0,   0,   0,   0, 0,
+            0, 255, 255, 255, 0,
+            0, 255,   0, 255, 0,
+            0, 255, 255, 255, 0,
+            0,   0,   0,   0, 0,
+        ];
+        assert_eq!(&expected, image.buffer());
+    }
+
+    #[test]
+    fn clip_huge_shapes_to_the_image() {
+        let mut image = LumaImage::new_vec(vec![0; 25], dim(5), dim(5));
+        Overlay::new(&mut image)
+            .crosshair((2, 2), u32::MAX, Color::WHITE)
+            .line((i32::MIN, i32::MIN), (i32::MAX, i32::MAX), Color::WHITE)
+            .fill_rectangle((4, 4), (u32::MAX, u32::MAX), Color::WHITE)
+            // Only font pixels far outside of the image
+            .text((0, 0), "11", u32::MAX, Color::BLACK);
+
+        #[rustfmt::skip]
+        let expected: [u8; 25] = [
+            255,   0, 255,   0,   0,
+              0, 255, 255,   0,   0,
+            255, 255, 255, 255, 255,
+              0,   0, 255, 255,   0,
+              0,   0, 255,   0, 255,
+        ];
+        assert_eq!(&expected, image.buffer());
+    }
+
+    #[test]
+    fn keep_alpha_of_luma_alpha_images() {
+        let mut image = GenericImage::<u8, 2>::new_vec(vec![7; 2 * 2 * 2], dim(2), dim(2));
+        Overlay::new(&mut image).fill_rectangle((0, 0), (2, 2), Color::WHITE);
+        assert_eq!(&[255, 7, 255, 7, 255, 7, 255, 7], image.buffer());
+    }
+
+    #[test]
+    fn render_text_in_color() {
+        let (width, height) = Overlay::text_size("1", 1);
+        assert_eq!((5, 7), (width, height));
+        let mut image = GenericImage::<u8, 3>::new_vec(vec![0; 5 * 7 * 3], dim(5), dim(7));
+        Overlay::new(&mut image).text((0, 0), "1", 1, Color::RED);
+
+        // Top row of '1' is a single pixel in the center
+        let top_row: Vec<_> = image.buffer()[..15].chunks(3).collect();
+        assert_eq!(&[255u8, 0, 0], top_row[2]);
+        assert_eq!(&[0u8, 0, 0], top_row[0]);
+        assert_eq!(Color::RED.luma(), 76);
+    }
+}
@@ -0,0 +1,242 @@
+//! Downscaling for previews and multi-scale processing
+//!
+//! Unlike nearest neighbour sampling, every source pixel contributes to the result, so thin structures
+//! and noise don't produce aliasing. Throughput on typical frames is measured in `pilatus-bench`
+
+use std::num::NonZeroU32;
+
+use super::{DynamicImage, GenericImage};
+
+/// Pixel types, which can be averaged. Results are rounded to the nearest value
+pub trait ScalablePixel: Copy + 'static {
+    fn to_f32(self) -> f32;
+    fn from_f32(value: f32) -> Self;
+}
+
+impl ScalablePixel for u8 {
+    fn to_f32(self) -> f32 {
+        self as f32
+    }
+    fn from_f32(value: f32) -> Self {
+        value.round().clamp(0., u8::MAX as f32) as u8
+    }
+}
+
+impl ScalablePixel for u16 {
+    fn to_f32(self) -> f32 {
+        self as f32
+    }
+    fn from_f32(value: f32) -> Self {
+        value.round().clamp(0., u16::MAX as f32) as u16
+    }
+}
+
+impl ScalablePixel for f32 {
+    fn to_f32(self) -> f32 {
+        self
+    }
+    fn from_f32(value: f32) -> Self {
+        value
+    }
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum PyramidFilter {
+    /// Average of 2x2 pixels. Fastest
+    #[default]
+    Area,
+    /// 5x5 binomial kernel before subsampling. Smoother levels, e.g. for coarse-to-fine matching
+    Gaussian,
+}
+
+/// 1D binomial approximation of a gaussian
+const GAUSSIAN_KERNEL: [f32; 5] = [1. / 16., 4. / 16., 6. / 16., 4. / 16., 1. / 16.];
+
+impl<T: ScalablePixel, const CHANNELS: usize> GenericImage<T, CHANNELS> {
+    /// Each target pixel is the average of the source area it covers, also for non-integer factors.
+    /// Target dimensions larger than the source are clamped, as upscaling isn't supported
+    pub fn downscale(&self, width: NonZeroU32, height: NonZeroU32) -> Self {
+        let (src_width, src_height) = self.dimensions();
+        let (width, height) = (width.min(src_width), height.min(src_height));
+        if (width, height) == (src_width, src_height) {
+            return self.clone();
+        }
+        let columns = area_weights(src_width.get() as usize, width.get() as usize);
+        let rows = area_weights(src_height.get() as usize, height.get() as usize);
+        self.resample(width, height, &columns, &rows)
+    }
+
+    /// Each level halves the size of the previous one (rounded up), until `levels` are created or the image has 1x1 pixels.
+    /// The original isn't part of the result
+    pub fn pyramid(&self, levels: usize, filter: PyramidFilter) -> Vec<Self> {
+        let mut result: Vec<Self> = Vec::with_capacity(levels);
+        for _ in 0..levels {
+            let previous = result.last().unwrap_or(self);
+            let (width, height) = previous.dimensions();
+            if width.get() == 1 && height.get() == 1 {
+                break;
+            }
+            let half = |x: NonZeroU32| -> NonZeroU32 {
+                x.get().div_ceil(2).try_into().expect("Input is > 0")
+            };
+            let (half_width, half_height) = (half(width), half(height));
+            let next = match filter {
+                PyramidFilter::Area => previous.downscale(half_width, half_height),
+                PyramidFilter::Gaussian => previous.resample(
+                    half_width,
+                    half_height,
+                    &gaussian_weights(width.get() as usize, half_width.get() as usize),
+                    &gaussian_weights(height.get() as usize, half_height.get() as usize),
+                ),
+            };
+            result.push(next);
+        }
+        result
+    }
+
+    /// Separable filter: Columns are reduced first, so the vertical pass runs on fewer pixels
+    fn resample(
+        &self,
+        width: NonZeroU32,
+        height: NonZeroU32,
+        columns: &[Weights],
+        rows: &[Weights],
+    ) -> Self {
+        let src_width = self.dimensions().0.get() as usize;
+        let row_len = width.get() as usize * CHANNELS;
+        let mut horizontal = Vec::with_capacity(row_len * self.dimensions().1.get() as usize);
+        for src_row in self.buffer().chunks_exact(src_width * CHANNELS) {
+            for weights in columns {
+                for channel in 0..CHANNELS {
+                    horizontal.push(weights.apply(|i| src_row[i * CHANNELS + channel].to_f32()));
+                }
+            }
+        }
+
+        let mut result = Vec::with_capacity(row_len * height.get() as usize);
+        let mut accumulator = vec![0f32; row_len];
+        for weights in rows {
+            accumulator.fill(0.);
+            for (index, weight) in weights.indices.iter().zip(&weights.values) {
+                let start = index * row_len;
+                let src_row = &horizontal[start..start + row_len];
+                for (acc, value) in accumulator.iter_mut().zip(src_row) {
+                    *acc += value * weight;
+                }
+            }
+            result.extend(accumulator.iter().map(|x| T::from_f32(*x)));
+        }
+        Self::new_vec(result, width, height)
+    }
+}
+
+impl DynamicImage {
+    /// See [`GenericImage::downscale`]
+    pub fn downscale(&self, width: NonZeroU32, height: NonZeroU32) -> Self {
+        match self {
+            DynamicImage::Luma8(x) => DynamicImage::Luma8(x.downscale(width, height)),
+            DynamicImage::Luma16(x) => DynamicImage::Luma16(x.downscale(width, height)),
+        }
+    }
+
+    /// See [`GenericImage::pyramid`]
+    pub fn pyramid(&self, levels: usize, filter: PyramidFilter) -> Vec<Self> {
+        match self {
+            DynamicImage::Luma8(x) => x
+                .pyramid(levels, filter)
+                .into_iter()
+                .map(DynamicImage::Luma8)
+                .collect(),
+            DynamicImage::Luma16(x) => x
+                .pyramid(levels, filter)
+                .into_iter()
+                .map(DynamicImage::Luma16)
+                .collect(),
+        }
+    }
+}
+
+/// Source indices and their weight for a single target pixel along one axis
+struct Weights {
+    indices: Vec<usize>,
+    values: Vec<f32>,
+}
+
+impl Weights {
+    fn apply(&self, get: impl Fn(usize) -> f32) -> f32 {
+        self.indices
+            .iter()
+            .zip(&self.values)
+            .map(|(i, weight)| get(*i) * weight)
+            .sum()
+    }
+}
+
+fn area_weights(src: usize, target: usize) -> Vec<Weights> {
+    let scale = src as f64 / target as f64;
+    (0..target)
+        .map(|i| {
+            let begin = i as f64 * scale;
+            let end = begin + scale;
+            let indices: Vec<_> =
+                (begin.floor() as usize..(end.ceil() as usize).min(src)).collect();
+            let values = indices
+                .iter()
+                .map(|&s| ((end.min(s as f64 + 1.) - begin.max(s as f64)) / scale) as f32)
+                .collect();
+            Weights { indices, values }
+        })
+        .collect()
+}
+
+/// Kernel centered on every second source pixel. Borders are replicated
+fn gaussian_weights(src: usize, target: usize) -> Vec<Weights> {
+    (0..target)
+        .map(|i| {
+            let center = 2 * i as isize;
+            Weights {
+                indices: (-2..=2)
+                    .map(|offset| (center + offset).clamp(0, src as isize - 1) as usize)
+                    .collect(),
+                values: GAUSSIAN_KERNEL.to_vec(),
+            }
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::image::LumaImage;
+
+    fn dim(x: u32) -> NonZeroU32 {
+        NonZeroU32::new(x).unwrap()
+    }
+
+    #[test]
+    fn average_covered_area() {
+        let image = LumaImage::new_vec(vec![0, 10, 20, 30], dim(4), dim(1));
+        assert_eq!(&[5u8, 25], image.downscale(dim(2), dim(1)).buffer());
+
+        // Factor 1.5: The center pixel contributes to both targets
+        let image = LumaImage::new_vec(vec![0, 30, 60], dim(3), dim(1));
+        assert_eq!(&[10u8, 50], image.downscale(dim(2), dim(5)).buffer());
+
+        let rgb = GenericImage::<u8, 3>::new_vec(vec![0, 100, 200, 10, 110, 210], dim(2), dim(1));
+        assert_eq!(&[5u8, 105, 205], rgb.downscale(dim(1), dim(1)).buffer());
+    }
+
+    #[test]
+    fn pyramid_levels_round_up_and_stop_at_single_pixel() {
+        let image = GenericImage::<u16, 1>::new_vec(vec![1000; 15], dim(5), dim(3));
+        for filter in [PyramidFilter::Area, PyramidFilter::Gaussian] {
+            let levels = image.pyramid(10, filter);
+            let dimensions: Vec<_> = levels
+                .iter()
+                .map(|x| (x.dimensions().0.get(), x.dimensions().1.get()))
+                .collect();
+            assert_eq!(vec![(3, 2), (2, 1), (1, 1)], dimensions);
+            assert!(levels.iter().all(|x| x.buffer().iter().all(|p| *p == 1000)));
+        }
+    }
+}
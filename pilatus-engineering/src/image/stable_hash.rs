@@ -8,8 +8,9 @@ use std::{
 use serde::{Deserialize, Deserializer, Serialize, Serializer};
 
 #[derive(Hash, PartialEq, Eq, Clone, Copy)]
+#[cfg_attr(feature = "ts", derive(ts_rs::TS))]
 #[repr(transparent)]
-pub struct StableHash(NonZeroU64);
+pub struct StableHash(#[cfg_attr(feature = "ts", ts(type = "string"))] NonZeroU64);
 
 impl Debug for StableHash {
     fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
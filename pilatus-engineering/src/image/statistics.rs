@@ -0,0 +1,136 @@
+//! Statistics of a single frame, so e.g. exposure tuning doesn't need to transfer the full image
+
+use std::num::NonZeroU32;
+
+use pilatus::device::ActorMessage;
+use serde::{Deserialize, Serialize};
+
+use super::{DynamicImage, GenericImage};
+
+/// Bins cover the full value range of the image format, e.g. 0..=255 for Luma8
+#[derive(Debug, Clone, Copy, Deserialize, Serialize)]
+#[serde(default, deny_unknown_fields)]
+#[non_exhaustive]
+pub struct GetImageStatisticsMessage {
+    pub bins: NonZeroU32,
+}
+
+impl GetImageStatisticsMessage {
+    pub fn with_bins(bins: NonZeroU32) -> Self {
+        Self { bins }
+    }
+}
+
+impl Default for GetImageStatisticsMessage {
+    fn default() -> Self {
+        Self {
+            bins: NonZeroU32::new(256).unwrap(),
+        }
+    }
+}
+
+impl ActorMessage for GetImageStatisticsMessage {
+    type Output = ImageStatistics;
+    type Error = anyhow::Error;
+}
+
+#[derive(Debug, Clone, PartialEq, Deserialize, Serialize)]
+#[cfg_attr(feature = "ts", derive(ts_rs::TS), ts(export))]
+#[non_exhaustive]
+pub struct ImageStatistics {
+    pub mean: f64,
+    pub min: u16,
+    pub max: u16,
+    /// Number of pixels per bin
+    pub histogram: Vec<u64>,
+    /// Percentage (0..=100) of pixels with the highest value of the image format
+    pub saturation: f64,
+}
+
+impl ImageStatistics {
+    /// Bins are capped to the number of distinct values of the image format
+    pub fn compute(image: &DynamicImage, bins: NonZeroU32) -> Self {
+        match image {
+            DynamicImage::Luma8(x) => Self::compute_generic(x, u8::MAX as u16, bins),
+            DynamicImage::Luma16(x) => Self::compute_generic(x, u16::MAX, bins),
+        }
+    }
+
+    fn compute_generic<T: Copy + Into<u16>>(
+        image: &GenericImage<T, 1>,
+        saturated: u16,
+        bins: NonZeroU32,
+    ) -> Self {
+        let values = saturated as u64 + 1;
+        let bins = (bins.get() as u64).min(values);
+        let mut histogram = vec![0u64; bins as usize];
+        let (mut sum, mut min, mut max, mut saturated_count) = (0u64, u16::MAX, 0u16, 0u64);
+        let buffer = image.buffer();
+        for &v in buffer {
+            let v: u16 = v.into();
+            sum += v as u64;
+            min = min.min(v);
+            max = max.max(v);
+            saturated_count += (v == saturated) as u64;
+            histogram[(v as u64 * bins / values) as usize] += 1;
+        }
+        let len = buffer.len().max(1) as f64;
+        Self {
+            mean: sum as f64 / len,
+            min,
+            max,
+            histogram,
+            saturation: saturated_count as f64 * 100. / len,
+        }
+    }
+}
+
+impl DynamicImage {
+    pub fn statistics(&self, bins: NonZeroU32) -> ImageStatistics {
+        ImageStatistics::compute(self, bins)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::image::LumaImage;
+
+    #[test]
+    fn luma8_statistics() {
+        let image = DynamicImage::Luma8(LumaImage::new_vec(
+            vec![0, 100, 200, 255],
+            NonZeroU32::new(2).unwrap(),
+            NonZeroU32::new(2).unwrap(),
+        ));
+        let stats = image.statistics(NonZeroU32::new(2).unwrap());
+        assert_eq!(138.75, stats.mean);
+        assert_eq!((0, 255), (stats.min, stats.max));
+        assert_eq!(vec![2, 2], stats.histogram);
+        assert_eq!(25., stats.saturation);
+    }
+
+    #[test]
+    fn bins_are_capped_to_value_range() {
+        let image = DynamicImage::Luma8(LumaImage::new_vec(
+            vec![7],
+            NonZeroU32::MIN,
+            NonZeroU32::MIN,
+        ));
+        let stats = image.statistics(NonZeroU32::new(1000).unwrap());
+        assert_eq!(256, stats.histogram.len());
+        assert_eq!(1, stats.histogram[7]);
+    }
+
+    #[test]
+    fn luma16_uses_full_range() {
+        let image = DynamicImage::Luma16(GenericImage::new_vec(
+            vec![0x7FFF, 0x8000, u16::MAX],
+            NonZeroU32::new(3).unwrap(),
+            NonZeroU32::MIN,
+        ));
+        let stats = image.statistics(NonZeroU32::new(4).unwrap());
+        assert_eq!(vec![0, 1, 1, 1], stats.histogram);
+        assert!((stats.saturation - 100. / 3.).abs() < 1e-9);
+    }
+}
@@ -0,0 +1,325 @@
+//! Lens distortion in pixel coordinates (x=col, y=row), compatible with the OpenCV calibration models
+//!
+//! Measurements are located on the distorted image. [`LensDistortion::undistort`] maps them into the ideal pinhole image,
+//! where [`PointProjector`]s are valid. [`UndistortingProjector`] does this for every projected transform.
+
+use std::sync::Arc;
+
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    image::{DynamicPointProjector, GenericImage, PointProjector, ScalablePixel},
+    InvertibleTransform, InvertibleTransform3d, InvertibleTransformRaw,
+};
+
+/// Iterations for inverting the models. Both converge within a few iterations for realistic lenses
+const MAX_ITERATIONS: usize = 20;
+const CONVERGENCE_EPSILON: f64 = 1e-12;
+
+/// Pinhole parameters in pixels
+#[derive(Serialize, Deserialize, Debug, PartialEq, Clone, Copy)]
+#[serde(deny_unknown_fields)]
+pub struct CameraIntrinsics {
+    pub fx: f64,
+    pub fy: f64,
+    pub cx: f64,
+    pub cy: f64,
+}
+
+#[derive(Serialize, Deserialize, Debug, PartialEq, Clone, Copy)]
+#[serde(tag = "type", rename_all = "snake_case", deny_unknown_fields)]
+pub enum DistortionModel {
+    /// Radial (k1..k3) and tangential (p1, p2) distortion of standard lenses
+    BrownConrady {
+        k1: f64,
+        k2: f64,
+        k3: f64,
+        p1: f64,
+        p2: f64,
+    },
+    /// Equidistant model for wide angle lenses
+    Fisheye { k1: f64, k2: f64, k3: f64, k4: f64 },
+}
+
+#[derive(Serialize, Deserialize, Debug, PartialEq, Clone, Copy)]
+#[serde(deny_unknown_fields)]
+pub struct LensDistortion {
+    pub intrinsics: CameraIntrinsics,
+    pub model: DistortionModel,
+}
+
+impl DistortionModel {
+    /// Normalized coordinates of the ideal pinhole camera to normalized distorted coordinates
+    fn distort(&self, (x, y): (f64, f64)) -> (f64, f64) {
+        match *self {
+            DistortionModel::BrownConrady { k1, k2, k3, p1, p2 } => {
+                let r2 = x * x + y * y;
+                let radial = 1. + r2 * (k1 + r2 * (k2 + r2 * k3));
+                (
+                    x * radial + 2. * p1 * x * y + p2 * (r2 + 2. * x * x),
+                    y * radial + p1 * (r2 + 2. * y * y) + 2. * p2 * x * y,
+                )
+            }
+            DistortionModel::Fisheye { k1, k2, k3, k4 } => {
+                let r = (x * x + y * y).sqrt();
+                if r < CONVERGENCE_EPSILON {
+                    return (x, y);
+                }
+                let theta = r.atan();
+                let theta2 = theta * theta;
+                let theta_d =
+                    theta * (1. + theta2 * (k1 + theta2 * (k2 + theta2 * (k3 + theta2 * k4))));
+                (x * theta_d / r, y * theta_d / r)
+            }
+        }
+    }
+
+    fn undistort(&self, (xd, yd): (f64, f64)) -> (f64, f64) {
+        match *self {
+            DistortionModel::BrownConrady { k1, k2, k3, p1, p2 } => {
+                // Fixed-point iteration, which is also used by OpenCV
+                let (mut x, mut y) = (xd, yd);
+                for _ in 0..MAX_ITERATIONS {
+                    let r2 = x * x + y * y;
+                    let radial = 1. + r2 * (k1 + r2 * (k2 + r2 * k3));
+                    let next = (
+                        (xd - 2. * p1 * x * y - p2 * (r2 + 2. * x * x)) / radial,
+                        (yd - p1 * (r2 + 2. * y * y) - 2. * p2 * x * y) / radial,
+                    );
+                    let converged =
+                        (next.0 - x).abs().max((next.1 - y).abs()) < CONVERGENCE_EPSILON;
+                    (x, y) = next;
+                    if converged {
+                        break;
+                    }
+                }
+                (x, y)
+            }
+            DistortionModel::Fisheye { k1, k2, k3, k4 } => {
+                let theta_d = (xd * xd + yd * yd).sqrt();
+                if theta_d < CONVERGENCE_EPSILON {
+                    return (xd, yd);
+                }
+                // Newton's method on theta_d = theta * (1 + k1*theta^2 + ...)
+                let mut theta = theta_d;
+                for _ in 0..MAX_ITERATIONS {
+                    let t2 = theta * theta;
+                    let f = theta * (1. + t2 * (k1 + t2 * (k2 + t2 * (k3 + t2 * k4)))) - theta_d;
+                    let df = 1. + t2 * (3. * k1 + t2 * (5. * k2 + t2 * (7. * k3 + t2 * 9. * k4)));
+                    let step = f / df;
+                    theta -= step;
+                    if step.abs() < CONVERGENCE_EPSILON {
+                        break;
+                    }
+                }
+                let scale = theta.tan() / theta_d;
+                (xd * scale, yd * scale)
+            }
+        }
+    }
+}
+
+impl LensDistortion {
+    /// Pixel of the ideal pinhole image to the pixel, where it appears in the acquired image
+    pub fn distort(&self, point: (f64, f64)) -> (f64, f64) {
+        self.denormalize(self.model.distort(self.normalize(point)))
+    }
+
+    /// Pixel of the acquired image to the pixel of the ideal pinhole image. Inverse of [`LensDistortion::distort`]
+    pub fn undistort(&self, point: (f64, f64)) -> (f64, f64) {
+        self.denormalize(self.model.undistort(self.normalize(point)))
+    }
+
+    /// Precomputes the source pixel for each pixel of the rectified image, so rectifying frames only interpolates
+    pub fn rectification_map(&self, width: u32, height: u32) -> RectificationMap {
+        let sources = (0..height)
+            .flat_map(|y| (0..width).map(move |x| (x as f64, y as f64)))
+            .map(|p| {
+                let (x, y) = self.distort(p);
+                [x as f32, y as f32]
+            })
+            .collect();
+        RectificationMap {
+            width,
+            height,
+            sources,
+        }
+    }
+
+    /// Corrects all transforms before `inner` projects them into the world
+    pub fn wrap_projector(self, inner: DynamicPointProjector) -> DynamicPointProjector {
+        Arc::new(UndistortingProjector {
+            distortion: self,
+            inner,
+        })
+    }
+
+    fn normalize(&self, (x, y): (f64, f64)) -> (f64, f64) {
+        let i = &self.intrinsics;
+        ((x - i.cx) / i.fx, (y - i.cy) / i.fy)
+    }
+
+    fn denormalize(&self, (x, y): (f64, f64)) -> (f64, f64) {
+        let i = &self.intrinsics;
+        (x * i.fx + i.cx, y * i.fy + i.cy)
+    }
+}
+
+/// Lookup table created by [`LensDistortion::rectification_map`]
+#[derive(Debug, Clone)]
+pub struct RectificationMap {
+    width: u32,
+    height: u32,
+    /// Position in the distorted image for each target pixel (row-major)
+    sources: Vec<[f32; 2]>,
+}
+
+impl RectificationMap {
+    pub fn dimensions(&self) -> (u32, u32) {
+        (self.width, self.height)
+    }
+
+    /// Bilinear interpolation. Pixels without source, e.g. in the corners of barrel distortion, are 0.
+    /// Returns None, if the dimensions don't match the map
+    pub fn apply<T: ScalablePixel, const CHANNELS: usize>(
+        &self,
+        image: &GenericImage<T, CHANNELS>,
+    ) -> Option<GenericImage<T, CHANNELS>> {
+        let (width, height) = image.dimensions();
+        if (width.get(), height.get()) != (self.width, self.height) {
+            return None;
+        }
+        let (w, h) = (self.width as usize, self.height as usize);
+        let buffer = image.buffer();
+        let pixel = |x: usize, y: usize, c: usize| buffer[(y * w + x) * CHANNELS + c].to_f32();
+        let mut result = Vec::with_capacity(buffer.len());
+        for &[sx, sy] in &self.sources {
+            let (x0, y0) = (sx.floor(), sy.floor());
+            if x0 < 0. || y0 < 0. || x0 as usize + 1 >= w || y0 as usize + 1 >= h {
+                // Border pixels are sampled without interpolation
+                let (rx, ry) = (sx.round(), sy.round());
+                let inside = rx >= 0. && ry >= 0. && (rx as usize) < w && (ry as usize) < h;
+                for c in 0..CHANNELS {
+                    result.push(if inside {
+                        buffer[(ry as usize * w + rx as usize) * CHANNELS + c]
+                    } else {
+                        T::from_f32(0.)
+                    });
+                }
+                continue;
+            }
+            let (fx, fy) = (sx - x0, sy - y0);
+            let (x, y) = (x0 as usize, y0 as usize);
+            for c in 0..CHANNELS {
+                let top = pixel(x, y, c) * (1. - fx) + pixel(x + 1, y, c) * fx;
+                let bottom = pixel(x, y + 1, c) * (1. - fx) + pixel(x + 1, y + 1, c) * fx;
+                result.push(T::from_f32(top * (1. - fy) + bottom * fy));
+            }
+        }
+        Some(GenericImage::new_vec(result, width, height))
+    }
+}
+
+/// Undistorts transforms of the acquired image before they are passed to a projector of the ideal pinhole camera
+pub struct UndistortingProjector {
+    pub distortion: LensDistortion,
+    pub inner: DynamicPointProjector,
+}
+
+impl PointProjector for UndistortingProjector {
+    fn project_to_world_plane(
+        &self,
+        transform: &InvertibleTransform,
+    ) -> Result<InvertibleTransform3d, anyhow::Error> {
+        // Distortion is nonlinear, so the axes are corrected by undistorting their endpoints
+        let origin = (transform.m31, transform.m32);
+        let (ox, oy) = self.distortion.undistort(origin);
+        let (ax, ay) = self
+            .distortion
+            .undistort((origin.0 + transform.m11, origin.1 + transform.m12));
+        let (bx, by) = self
+            .distortion
+            .undistort((origin.0 + transform.m21, origin.1 + transform.m22));
+        let corrected = InvertibleTransformRaw {
+            m11: ax - ox,
+            m12: ay - oy,
+            m21: bx - ox,
+            m22: by - oy,
+            m31: ox,
+            m32: oy,
+        }
+        .seal()
+        .map_err(|e| anyhow::anyhow!("Undistorted transform is invalid: {e:?}"))?;
+        self.inner.project_to_world_plane(&corrected)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::num::NonZeroU32;
+
+    use super::*;
+    use crate::image::LumaImage;
+
+    const INTRINSICS: CameraIntrinsics = CameraIntrinsics {
+        fx: 1000.,
+        fy: 1000.,
+        cx: 640.,
+        cy: 512.,
+    };
+
+    #[test]
+    fn undistort_inverts_distort() {
+        for model in [
+            DistortionModel::BrownConrady {
+                k1: -0.2,
+                k2: 0.05,
+                k3: 0.,
+                p1: 0.001,
+                p2: -0.0005,
+            },
+            DistortionModel::Fisheye {
+                k1: -0.01,
+                k2: 0.002,
+                k3: 0.,
+                k4: 0.,
+            },
+        ] {
+            let distortion = LensDistortion {
+                intrinsics: INTRINSICS,
+                model,
+            };
+            for point in [(640., 512.), (0., 0.), (1200., 900.)] {
+                let (x, y) = distortion.undistort(distortion.distort(point));
+                approx::assert_abs_diff_eq!(point.0, x, epsilon = 1e-6);
+                approx::assert_abs_diff_eq!(point.1, y, epsilon = 1e-6);
+            }
+        }
+    }
+
+    #[test]
+    fn identity_rectification_keeps_pixels() {
+        let distortion = LensDistortion {
+            intrinsics: CameraIntrinsics {
+                fx: 10.,
+                fy: 10.,
+                cx: 1.5,
+                cy: 1.,
+            },
+            model: DistortionModel::BrownConrady {
+                k1: 0.,
+                k2: 0.,
+                k3: 0.,
+                p1: 0.,
+                p2: 0.,
+            },
+        };
+        let dim = |x| NonZeroU32::new(x).unwrap();
+        let image = LumaImage::new_vec((0..12).collect(), dim(4), dim(3));
+        let map = distortion.rectification_map(4, 3);
+        assert_eq!(image.buffer(), map.apply(&image).unwrap().buffer());
+        assert!(map
+            .apply(&LumaImage::new_vec(vec![0; 4], dim(2), dim(2)))
+            .is_none());
+    }
+}
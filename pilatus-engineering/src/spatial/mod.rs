@@ -1,6 +1,7 @@
 use serde::{Deserialize, Serialize};
 
 mod angle;
+mod distortion;
 mod frame;
 mod invertibletransform;
 mod invertibletransform3d;
@@ -11,6 +12,7 @@ mod relative_polygon;
 mod relative_rectangle;
 
 pub use angle::*;
+pub use distortion::*;
 pub use frame::*;
 pub use invertibletransform::*;
 pub use invertibletransform3d::*;
@@ -0,0 +1,138 @@
+//! Derive macros for pilatus. Use them through the reexports in `pilatus`
+//!
+//! ```ignore
+//! #[derive(pilatus::device::ActorMessage)]
+//! #[actor_message(output = Vec<u8>, error = anyhow::Error, name = "get_file")]
+//! struct GetFileMessage { /* ... */ }
+//! ```
+
+use proc_macro::TokenStream;
+use quote::quote;
+use syn::{parse_macro_input, DeriveInput, LitStr, Path, Type};
+
+/// Implements `ActorMessage` and `WireActorMessage`
+///
+/// Attributes in `#[actor_message(...)]`:
+/// - `output = Type` (required)
+/// - `error = Type` (required)
+/// - `name = "..."`: Stable name for RPC and introspection. Defaults to the name of the type
+/// - `crate = path`: Path to pilatus, if it is not available as `::pilatus`
+#[proc_macro_derive(ActorMessage, attributes(actor_message))]
+pub fn derive_actor_message(input: TokenStream) -> TokenStream {
+    let input = parse_macro_input!(input as DeriveInput);
+    match expand_actor_message(input) {
+        Ok(x) => x.into(),
+        Err(e) => e.to_compile_error().into(),
+    }
+}
+
+fn expand_actor_message(input: DeriveInput) -> syn::Result<proc_macro2::TokenStream> {
+    let mut output: Option<Type> = None;
+    let mut error: Option<Type> = None;
+    let mut name: Option<LitStr> = None;
+    let mut krate: Option<Path> = None;
+
+    for attr in input
+        .attrs
+        .iter()
+        .filter(|a| a.path().is_ident("actor_message"))
+    {
+        attr.parse_nested_meta(|meta| {
+            if meta.path.is_ident("output") {
+                output = Some(meta.value()?.parse()?);
+            } else if meta.path.is_ident("error") {
+                error = Some(meta.value()?.parse()?);
+            } else if meta.path.is_ident("name") {
+                name = Some(meta.value()?.parse()?);
+            } else if meta.path.is_ident("crate") {
+                krate = Some(meta.value()?.parse()?);
+            } else {
+                return Err(meta.error("expected one of `output`, `error`, `name` or `crate`"));
+            }
+            Ok(())
+        })?;
+    }
+
+    let ident = &input.ident;
+    let missing = |field: &str| {
+        syn::Error::new_spanned(
+            ident,
+            format!("missing `#[actor_message({field} = ...)]` attribute"),
+        )
+    };
+    let output = output.ok_or_else(|| missing("output"))?;
+    let error = error.ok_or_else(|| missing("error"))?;
+    let name = name.unwrap_or_else(|| LitStr::new(&ident.to_string(), ident.span()));
+    let krate = krate.unwrap_or_else(|| syn::parse_quote!(::pilatus));
+    let (impl_generics, ty_generics, where_clause) = input.generics.split_for_impl();
+
+    Ok(quote! {
+        impl #impl_generics #krate::device::ActorMessage for #ident #ty_generics #where_clause {
+            type Output = #output;
+            type Error = #error;
+        }
+
+        impl #impl_generics #krate::device::WireActorMessage for #ident #ty_generics #where_clause {
+            const WIRE_NAME: &'static str = #name;
+        }
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn expand_error(input: DeriveInput) -> String {
+        expand_actor_message(input)
+            .expect_err("Expected an error")
+            .to_string()
+    }
+
+    #[test]
+    fn default_to_type_name_and_pilatus_crate() {
+        let expanded = expand_actor_message(syn::parse_quote! {
+            #[actor_message(output = (), error = ())]
+            struct Ping;
+        })
+        .unwrap()
+        .to_string();
+        assert!(expanded.contains(":: pilatus :: device :: WireActorMessage"));
+        assert!(expanded.contains("\"Ping\""), "{expanded}");
+    }
+
+    #[test]
+    fn use_configured_name_and_crate() {
+        let expanded = expand_actor_message(syn::parse_quote! {
+            #[actor_message(crate = crate, output = (), error = (), name = "ping")]
+            struct Ping;
+        })
+        .unwrap()
+        .to_string();
+        assert!(expanded.contains("crate :: device :: WireActorMessage"));
+        assert!(expanded.contains("\"ping\""), "{expanded}");
+        assert!(!expanded.contains("\"Ping\""), "{expanded}");
+    }
+
+    #[test]
+    fn reject_missing_output_and_error() {
+        assert!(expand_error(syn::parse_quote! {
+            #[actor_message(error = ())]
+            struct Ping;
+        })
+        .contains("output"));
+        assert!(expand_error(syn::parse_quote! {
+            #[actor_message(output = ())]
+            struct Ping;
+        })
+        .contains("error"));
+    }
+
+    #[test]
+    fn reject_unknown_attributes() {
+        assert!(expand_error(syn::parse_quote! {
+            #[actor_message(output = (), error = (), timeout = 5)]
+            struct Ping;
+        })
+        .contains("expected one of"));
+    }
+}
@@ -0,0 +1,245 @@
+use std::{path::PathBuf, sync::Arc, thread::JoinHandle};
+
+use anyhow::anyhow;
+use futures::{stream::BoxStream, StreamExt};
+use pilatus::{
+    device::{
+        ActiveState, ActorSystem, DeviceId, RemoteActorError, RemoteMessages, RemoteRequest,
+        RemoteTransport,
+    },
+    ParameterUpdate, RecipeId, RecipeService,
+};
+use pilatus_engineering::image::{BroadcastImage, ImageClient, LumaImage};
+use pilatus_rt::Runtime;
+use tokio::{runtime::Handle, sync::oneshot};
+
+/// Runs pilatus on a background thread. All methods block until the operation is finished.
+/// Pilatus is shut down when this is dropped
+pub struct InProcessPilatus {
+    handle: Handle,
+    actor_system: ActorSystem,
+    recipe_service: RecipeService,
+    remote_messages: RemoteMessages,
+    stop: Option<oneshot::Sender<()>>,
+    thread: Option<JoinHandle<()>>,
+}
+
+impl InProcessPilatus {
+    /// Messages can only be sent with `ask_json`, if they are registered as `RemoteMessage`
+    pub fn start(root: PathBuf, plugin_dir: Option<PathBuf>) -> anyhow::Result<Self> {
+        let (ready_tx, ready_rx) = std::sync::mpsc::channel();
+        let thread = std::thread::Builder::new()
+            .name("pilatus".into())
+            .spawn(move || {
+                let mut runtime = Runtime::with_root(root);
+                if let Some(dir) = plugin_dir {
+                    runtime = runtime.load_plugins(dir);
+                }
+                let runtime = runtime.configure();
+                let services = (
+                    runtime.provider.get::<ActorSystem>(),
+                    runtime.provider.get::<RecipeService>(),
+                    runtime.provider.get::<RemoteMessages>(),
+                );
+                let (stop_tx, stop_rx) = oneshot::channel::<()>();
+                runtime.run_until_finished(async move {
+                    let _ignore_dropped_caller =
+                        ready_tx.send((Handle::current(), services, stop_tx));
+                    let _ = stop_rx.await;
+                });
+            })?;
+
+        let (handle, services, stop) = ready_rx
+            .recv()
+            .map_err(|_| anyhow!("Pilatus stopped during startup"))?;
+        let (Some(actor_system), Some(recipe_service), Some(remote_messages)) = services else {
+            return Err(anyhow!("Runtime is missing core services"));
+        };
+        Ok(Self {
+            handle,
+            actor_system,
+            recipe_service,
+            remote_messages,
+            stop: Some(stop),
+            thread: Some(thread),
+        })
+    }
+
+    pub fn state(&self) -> ActiveState {
+        self.handle.block_on(self.recipe_service.state())
+    }
+
+    pub fn add_default_recipe(&self) -> anyhow::Result<RecipeId> {
+        let (id, _) = self.handle.block_on(
+            self.recipe_service
+                .add_new_default_recipe_with(Default::default()),
+        )?;
+        Ok(id)
+    }
+
+    pub fn activate_recipe(&self, id: RecipeId) -> anyhow::Result<()> {
+        Ok(self
+            .handle
+            .block_on(self.recipe_service.activate_recipe(id))?)
+    }
+
+    pub fn duplicate_recipe(&self, id: RecipeId) -> anyhow::Result<RecipeId> {
+        let (id, _) = self
+            .handle
+            .block_on(self.recipe_service.duplicate_recipe(id))?;
+        Ok(id)
+    }
+
+    pub fn delete_recipe(&self, id: RecipeId) -> anyhow::Result<()> {
+        Ok(self
+            .handle
+            .block_on(self.recipe_service.delete_recipe(id))?)
+    }
+
+    pub fn update_device_params(
+        &self,
+        recipe_id: RecipeId,
+        device_id: DeviceId,
+        update: ParameterUpdate,
+    ) -> anyhow::Result<()> {
+        Ok(self.handle.block_on(
+            self.recipe_service
+                .update_device_params(recipe_id, device_id, update),
+        )?)
+    }
+
+    pub fn commit_active(&self) -> anyhow::Result<()> {
+        Ok(self.handle.block_on(self.recipe_service.commit_active())?)
+    }
+
+    pub fn restore_active(&self) -> anyhow::Result<()> {
+        Ok(self.handle.block_on(self.recipe_service.restore_active())?)
+    }
+
+    pub fn ask_json(
+        &self,
+        device_id: DeviceId,
+        message: &str,
+        payload: serde_json::Value,
+    ) -> Result<serde_json::Value, RemoteActorError> {
+        self.handle.block_on(self.remote_messages.dispatch(
+            self.actor_system.clone(),
+            RemoteRequest {
+                device_id,
+                message: message.to_string().into(),
+                payload,
+            },
+        ))
+    }
+
+    pub fn subscribe_images(&self, device_id: DeviceId) -> anyhow::Result<ImageSubscription> {
+        let client = ImageClient::new(self.actor_system.clone(), device_id);
+        let stream = self.handle.block_on(client.subscribe())?;
+        Ok(ImageSubscription {
+            handle: self.handle.clone(),
+            stream,
+        })
+    }
+}
+
+impl Drop for InProcessPilatus {
+    fn drop(&mut self) {
+        if let Some(stop) = self.stop.take() {
+            let _ignore_stopped = stop.send(());
+        }
+        if let Some(thread) = self.thread.take() {
+            let _ignore_panic = thread.join();
+        }
+    }
+}
+
+pub struct ImageSubscription {
+    handle: Handle,
+    stream: BoxStream<'static, BroadcastImage>,
+}
+
+impl ImageSubscription {
+    /// Blocks until the next image is available. Returns None, if the producer stopped
+    pub fn next_image(&mut self) -> Option<Arc<LumaImage>> {
+        self.handle.block_on(self.stream.next()).map(|x| x.image)
+    }
+}
+
+/// Client for a pilatus process, which listens for remote nodes (config `remote_actors.listen` and `remote_actors.secret`)
+pub struct RemotePilatus {
+    runtime: tokio::runtime::Runtime,
+    transport: Arc<dyn RemoteTransport>,
+}
+
+impl RemotePilatus {
+    pub fn connect(address: impl Into<String>, secret: Option<String>) -> anyhow::Result<Self> {
+        let runtime = tokio::runtime::Builder::new_multi_thread()
+            .worker_threads(1)
+            .enable_all()
+            .build()?;
+        Ok(Self {
+            runtime,
+            transport: pilatus_rt::connect_remote_node(address, secret),
+        })
+    }
+
+    pub fn ask_json(
+        &self,
+        device_id: DeviceId,
+        message: &str,
+        payload: serde_json::Value,
+    ) -> Result<serde_json::Value, RemoteActorError> {
+        self.runtime.block_on(self.transport.request(RemoteRequest {
+            device_id,
+            message: message.to_string().into(),
+            payload,
+        }))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn control_recipes_in_process() -> anyhow::Result<()> {
+        let dir = tempfile::tempdir()?;
+        let pilatus = InProcessPilatus::start(dir.path().into(), None)?;
+        let initial = pilatus.state().recipes.active().0;
+
+        let added = pilatus.add_default_recipe()?;
+        pilatus.activate_recipe(added.clone())?;
+        assert_eq!(added, pilatus.state().recipes.active().0);
+
+        let duplicate = pilatus.duplicate_recipe(added.clone())?;
+        assert!(pilatus.state().recipes.get_with_id(&duplicate).is_some());
+        pilatus.delete_recipe(duplicate.clone())?;
+        pilatus.delete_recipe(initial.clone())?;
+        let state = pilatus.state();
+        assert!(state.recipes.get_with_id(&duplicate).is_none());
+        assert!(state.recipes.get_with_id(&initial).is_none());
+        assert!(pilatus.delete_recipe(added).is_err(), "Active recipe");
+
+        drop(pilatus);
+        dir.close()?;
+        Ok(())
+    }
+
+    #[test]
+    fn reject_unregistered_messages_and_unknown_image_producers() -> anyhow::Result<()> {
+        let dir = tempfile::tempdir()?;
+        let pilatus = InProcessPilatus::start(dir.path().into(), None)?;
+        let device_id = DeviceId::new_v4();
+
+        let result = pilatus.ask_json(device_id, "unknown", serde_json::Value::Null);
+        assert!(
+            matches!(&result, Err(RemoteActorError::UnknownMessageType(x)) if x == "unknown"),
+            "{result:?}"
+        );
+        assert!(pilatus.subscribe_images(device_id).is_err());
+
+        drop(pilatus);
+        dir.close()?;
+        Ok(())
+    }
+}
@@ -0,0 +1,17 @@
+//! Allows scripting pilatus without touching Rust (e.g. for acceptance tests).
+//! The python module `pilatus` is only built with feature `python`:
+//!
+//! ```python
+//! import pilatus
+//! p = pilatus.Pilatus("data", plugin_dir="plugins")
+//! recipe_id = p.add_default_recipe()
+//! p.activate_recipe(recipe_id)
+//! for frame in p.subscribe_images(camera_id):
+//!     print(frame.shape)
+//! ```
+
+mod in_process;
+#[cfg(feature = "python")]
+mod python;
+
+pub use in_process::*;
@@ -0,0 +1,187 @@
+use std::{path::PathBuf, str::FromStr, sync::Mutex};
+
+use numpy::{PyArray1, PyArrayMethods};
+use pilatus::{device::DeviceId, RecipeId};
+use pyo3::{
+    exceptions::{PyRuntimeError, PyValueError},
+    prelude::*,
+};
+
+use crate::{ImageSubscription, InProcessPilatus, RemotePilatus};
+
+#[pymodule]
+fn pilatus(m: &Bound<'_, PyModule>) -> PyResult<()> {
+    m.add_class::<PyPilatus>()?;
+    m.add_class::<PyRemotePilatus>()?;
+    m.add_class::<PyImageSubscription>()?;
+    Ok(())
+}
+
+fn runtime_error(e: impl std::fmt::Display) -> PyErr {
+    PyRuntimeError::new_err(e.to_string())
+}
+
+fn parse_id<T: FromStr>(id: &str) -> PyResult<T>
+where
+    T::Err: std::fmt::Display,
+{
+    id.parse()
+        .map_err(|e| PyValueError::new_err(format!("Invalid id '{id}': {e}")))
+}
+
+fn to_json(value: &Bound<'_, PyAny>) -> PyResult<serde_json::Value> {
+    let text: String = value
+        .py()
+        .import("json")?
+        .call_method1("dumps", (value,))?
+        .extract()?;
+    serde_json::from_str(&text).map_err(|e| PyValueError::new_err(e.to_string()))
+}
+
+fn from_json<'py>(py: Python<'py>, value: &impl serde::Serialize) -> PyResult<Bound<'py, PyAny>> {
+    let text = serde_json::to_string(value).map_err(runtime_error)?;
+    py.import("json")?.call_method1("loads", (text,))
+}
+
+/// Pilatus running within the python process
+#[pyclass(name = "Pilatus")]
+struct PyPilatus(InProcessPilatus);
+
+#[pymethods]
+impl PyPilatus {
+    #[new]
+    #[pyo3(signature = (root, plugin_dir=None))]
+    fn new(py: Python<'_>, root: PathBuf, plugin_dir: Option<PathBuf>) -> PyResult<Self> {
+        py.detach(|| InProcessPilatus::start(root, plugin_dir))
+            .map(Self)
+            .map_err(runtime_error)
+    }
+
+    fn state<'py>(&self, py: Python<'py>) -> PyResult<Bound<'py, PyAny>> {
+        let state = py.detach(|| self.0.state());
+        from_json(py, &state)
+    }
+
+    fn add_default_recipe(&self, py: Python<'_>) -> PyResult<String> {
+        py.detach(|| self.0.add_default_recipe())
+            .map(|id| id.to_string())
+            .map_err(runtime_error)
+    }
+
+    fn activate_recipe(&self, py: Python<'_>, recipe_id: &str) -> PyResult<()> {
+        let id: RecipeId = parse_id(recipe_id)?;
+        py.detach(|| self.0.activate_recipe(id))
+            .map_err(runtime_error)
+    }
+
+    fn duplicate_recipe(&self, py: Python<'_>, recipe_id: &str) -> PyResult<String> {
+        let id: RecipeId = parse_id(recipe_id)?;
+        py.detach(|| self.0.duplicate_recipe(id))
+            .map(|id| id.to_string())
+            .map_err(runtime_error)
+    }
+
+    fn delete_recipe(&self, py: Python<'_>, recipe_id: &str) -> PyResult<()> {
+        let id: RecipeId = parse_id(recipe_id)?;
+        py.detach(|| self.0.delete_recipe(id))
+            .map_err(runtime_error)
+    }
+
+    /// `update` has the same format as the http-api: `{"parameters": ..., "variables": ...}`
+    fn update_device_params(
+        &self,
+        py: Python<'_>,
+        recipe_id: &str,
+        device_id: &str,
+        update: &Bound<'_, PyAny>,
+    ) -> PyResult<()> {
+        let recipe_id: RecipeId = parse_id(recipe_id)?;
+        let device_id: DeviceId = parse_id(device_id)?;
+        let update = serde_json::from_value(to_json(update)?)
+            .map_err(|e| PyValueError::new_err(e.to_string()))?;
+        py.detach(|| self.0.update_device_params(recipe_id, device_id, update))
+            .map_err(runtime_error)
+    }
+
+    fn commit_active(&self, py: Python<'_>) -> PyResult<()> {
+        py.detach(|| self.0.commit_active()).map_err(runtime_error)
+    }
+
+    fn restore_active(&self, py: Python<'_>) -> PyResult<()> {
+        py.detach(|| self.0.restore_active()).map_err(runtime_error)
+    }
+
+    /// Sends a message which was registered as `RemoteMessage` with its wire name
+    fn ask<'py>(
+        &self,
+        py: Python<'py>,
+        device_id: &str,
+        message: &str,
+        payload: &Bound<'py, PyAny>,
+    ) -> PyResult<Bound<'py, PyAny>> {
+        let device_id: DeviceId = parse_id(device_id)?;
+        let payload = to_json(payload)?;
+        let response = py
+            .detach(|| self.0.ask_json(device_id, message, payload))
+            .map_err(runtime_error)?;
+        from_json(py, &response)
+    }
+
+    fn subscribe_images(&self, py: Python<'_>, device_id: &str) -> PyResult<PyImageSubscription> {
+        let device_id: DeviceId = parse_id(device_id)?;
+        py.detach(|| self.0.subscribe_images(device_id))
+            .map(|x| PyImageSubscription(Mutex::new(x)))
+            .map_err(runtime_error)
+    }
+}
+
+/// Iterator over images of a device as numpy arrays with shape (height, width)
+#[pyclass(name = "ImageSubscription")]
+struct PyImageSubscription(Mutex<ImageSubscription>);
+
+#[pymethods]
+impl PyImageSubscription {
+    fn __iter__(slf: PyRef<'_, Self>) -> PyRef<'_, Self> {
+        slf
+    }
+
+    fn __next__<'py>(&self, py: Python<'py>) -> PyResult<Option<Bound<'py, PyAny>>> {
+        let Some(image) = py.detach(|| self.0.lock().expect("Never poisoned").next_image()) else {
+            return Ok(None);
+        };
+        let (width, height) = image.dimensions();
+        let array = PyArray1::from_slice(py, image.buffer())
+            .reshape([height.get() as usize, width.get() as usize])?;
+        Ok(Some(array.into_any()))
+    }
+}
+
+/// Client for the remote actor bridge of a running pilatus
+#[pyclass(name = "RemotePilatus")]
+struct PyRemotePilatus(RemotePilatus);
+
+#[pymethods]
+impl PyRemotePilatus {
+    #[new]
+    #[pyo3(signature = (address, secret=None))]
+    fn new(address: String, secret: Option<String>) -> PyResult<Self> {
+        RemotePilatus::connect(address, secret)
+            .map(Self)
+            .map_err(runtime_error)
+    }
+
+    fn ask<'py>(
+        &self,
+        py: Python<'py>,
+        device_id: &str,
+        message: &str,
+        payload: &Bound<'py, PyAny>,
+    ) -> PyResult<Bound<'py, PyAny>> {
+        let device_id: DeviceId = parse_id(device_id)?;
+        let payload = to_json(payload)?;
+        let response = py
+            .detach(|| self.0.ask_json(device_id, message, payload))
+            .map_err(runtime_error)?;
+        from_json(py, &response)
+    }
+}
@@ -0,0 +1,306 @@
+use std::time::Duration;
+
+use pilatus::{
+    device::{
+        ActivationCompleteMessage, ActorMessage, ActorSystem, DeviceId, PrepareActivationMessage,
+    },
+    EventBus, SystemEventKind,
+};
+use serde::Deserialize;
+use tracing::{info, warn};
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub(crate) struct ActivationHooksConfig {
+    /// Time for spawned devices to register their handlers
+    startup_timeout_ms: u64,
+    prepare_timeout_ms: u64,
+    complete_timeout_ms: u64,
+}
+
+impl Default for ActivationHooksConfig {
+    fn default() -> Self {
+        Self {
+            startup_timeout_ms: 5000,
+            // Homing axes can take a while
+            prepare_timeout_ms: 60000,
+            complete_timeout_ms: 10000,
+        }
+    }
+}
+
+/// Sends `PrepareActivationMessage` to `devices`, once they are running. The activation fails, if any of them
+/// fails or doesn't respond in time. Failures are published as `SystemEventKind::Error`
+pub(crate) async fn prepare_activation(
+    actor_system: &ActorSystem,
+    events: &EventBus,
+    devices: &[DeviceId],
+    config: &ActivationHooksConfig,
+) -> anyhow::Result<()> {
+    wait_for_registration(
+        actor_system,
+        devices,
+        Duration::from_millis(config.startup_timeout_ms),
+    )
+    .await;
+    let failed = ask_all(
+        actor_system,
+        devices,
+        PrepareActivationMessage,
+        Duration::from_millis(config.prepare_timeout_ms),
+    )
+    .await;
+    if failed.is_empty() {
+        return Ok(());
+    }
+    let count = failed.len();
+    publish_failures(events, "prepare", failed);
+    Err(anyhow::anyhow!("Preparation of {count} devices failed"))
+}
+
+/// Sends `ActivationCompleteMessage` to all devices, once `others` are running.
+/// Failures are published as `SystemEventKind::Error`
+pub(crate) async fn complete_activation(
+    actor_system: ActorSystem,
+    events: EventBus,
+    prepared: Vec<DeviceId>,
+    others: Vec<DeviceId>,
+    config: ActivationHooksConfig,
+) {
+    wait_for_registration(
+        &actor_system,
+        &others,
+        Duration::from_millis(config.startup_timeout_ms),
+    )
+    .await;
+
+    let unannounced = actor_system.list_devices_for_message_type::<PrepareActivationMessage>();
+    let unannounced = others
+        .iter()
+        .filter(|id| unannounced.contains(id))
+        .collect::<Vec<_>>();
+    if !unannounced.is_empty() {
+        warn!("Devices {unannounced:?} handle PrepareActivationMessage, but their type isn't registered as PreparesActivation. They are prepared after all other devices started");
+        let failed = ask_all(
+            &actor_system,
+            &others,
+            PrepareActivationMessage,
+            Duration::from_millis(config.prepare_timeout_ms),
+        )
+        .await;
+        if !failed.is_empty() {
+            publish_failures(&events, "prepare", failed);
+            warn!("Devices are not notified about the completed activation, because preparation failed");
+            return;
+        }
+    }
+
+    let devices = prepared.into_iter().chain(others).collect::<Vec<_>>();
+    let failed = ask_all(
+        &actor_system,
+        &devices,
+        ActivationCompleteMessage,
+        Duration::from_millis(config.complete_timeout_ms),
+    )
+    .await;
+    if failed.is_empty() {
+        info!("Activation hooks of {} devices finished", devices.len());
+    } else {
+        publish_failures(&events, "complete", failed);
+    }
+}
+
+async fn wait_for_registration(
+    actor_system: &ActorSystem,
+    devices: &[DeviceId],
+    timeout: Duration,
+) {
+    let all_executing = futures::future::join_all(
+        devices
+            .iter()
+            .map(|id| actor_system.wait_until_executing(*id)),
+    );
+    if tokio::time::timeout(timeout, all_executing).await.is_err() {
+        warn!("Not all devices registered within {timeout:?}. Run activation hooks anyway");
+    }
+}
+
+async fn ask_all<TMsg: ActorMessage<Output = (), Error = anyhow::Error> + Clone>(
+    actor_system: &ActorSystem,
+    devices: &[DeviceId],
+    msg: TMsg,
+    timeout: Duration,
+) -> Vec<(DeviceId, String)> {
+    let handling = actor_system.list_devices_for_message_type::<TMsg>();
+    let results = futures::future::join_all(devices.iter().filter(|id| handling.contains(id)).map(
+        |&id| {
+            let msg = msg.clone();
+            async move {
+                match tokio::time::timeout(timeout, actor_system.ask(id, msg)).await {
+                    Ok(Ok(())) => None,
+                    Ok(Err(e)) => Some((id, format!("{e:?}"))),
+                    Err(_) => Some((id, format!("No response within {timeout:?}"))),
+                }
+            }
+        },
+    ))
+    .await;
+    results.into_iter().flatten().collect()
+}
+
+fn publish_failures(events: &EventBus, phase: &str, failed: Vec<(DeviceId, String)>) {
+    for (id, message) in failed {
+        warn!("Activation hook '{phase}' of device {id} failed: {message}");
+        events.publish(SystemEventKind::Error {
+            source: format!("Activation hook '{phase}' of device {id}"),
+            message,
+        });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::{Arc, Mutex};
+
+    use futures::StreamExt;
+    use pilatus::device::ActorResult;
+
+    use super::*;
+
+    type Calls = Arc<Mutex<Vec<&'static str>>>;
+
+    async fn prepare_fails(
+        _state: &mut Calls,
+        _msg: PrepareActivationMessage,
+    ) -> ActorResult<PrepareActivationMessage> {
+        Err(pilatus::device::ActorError::custom(anyhow::anyhow!(
+            "Axis not referenced"
+        )))
+    }
+
+    async fn complete(
+        state: &mut Calls,
+        _msg: ActivationCompleteMessage,
+    ) -> ActorResult<ActivationCompleteMessage> {
+        state.lock().unwrap().push("complete");
+        Ok(())
+    }
+
+    async fn prepare(
+        state: &mut Calls,
+        _msg: PrepareActivationMessage,
+    ) -> ActorResult<PrepareActivationMessage> {
+        state.lock().unwrap().push("prepare");
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn fail_activation_after_failed_preparation() {
+        let actor_system = ActorSystem::new();
+        let events = EventBus::default();
+        let mut errors = events.subscribe();
+        let axis = DeviceId::new_v4();
+        let axis_device = actor_system
+            .register(axis)
+            .add_handler(prepare_fails)
+            .execute(Calls::default());
+        let hooks = async {
+            let result = prepare_activation(
+                &actor_system,
+                &events,
+                &[axis],
+                &ActivationHooksConfig::default(),
+            )
+            .await;
+            actor_system.forget_senders();
+            result
+        };
+        let (_, result) = futures::future::join(axis_device, hooks).await;
+
+        assert!(result.is_err());
+        assert!(matches!(
+            errors.next().await.map(|x| x.kind),
+            Some(SystemEventKind::Error { .. })
+        ));
+    }
+
+    #[tokio::test]
+    async fn complete_activation_of_prepared_and_other_devices() {
+        let actor_system = ActorSystem::new();
+        let events = EventBus::default();
+        let (axis, output) = (DeviceId::new_v4(), DeviceId::new_v4());
+        let calls = Calls::default();
+        let axis_device = actor_system
+            .register(axis)
+            .add_handler(prepare)
+            .add_handler(complete)
+            .execute(calls.clone());
+        let hooks = async {
+            prepare_activation(
+                &actor_system,
+                &events,
+                &[axis],
+                &ActivationHooksConfig::default(),
+            )
+            .await
+            .unwrap();
+            let output_device = actor_system
+                .register(output)
+                .add_handler(complete)
+                .execute(calls.clone());
+            let complete = async {
+                complete_activation(
+                    actor_system.clone(),
+                    events.clone(),
+                    vec![axis],
+                    vec![output],
+                    ActivationHooksConfig::default(),
+                )
+                .await;
+                actor_system.forget_senders();
+            };
+            futures::future::join(output_device, complete).await;
+        };
+        futures::future::join(axis_device, hooks).await;
+
+        assert_eq!(
+            vec!["prepare", "complete", "complete"],
+            *calls.lock().unwrap()
+        );
+    }
+
+    #[tokio::test]
+    async fn prepare_unannounced_devices_before_completion() {
+        let actor_system = ActorSystem::new();
+        let events = EventBus::default();
+        let mut errors = events.subscribe();
+        let (axis, output) = (DeviceId::new_v4(), DeviceId::new_v4());
+        let calls = Calls::default();
+        let axis_device = actor_system
+            .register(axis)
+            .add_handler(prepare_fails)
+            .execute(calls.clone());
+        let output_device = actor_system
+            .register(output)
+            .add_handler(complete)
+            .execute(calls.clone());
+        let hooks = async {
+            complete_activation(
+                actor_system.clone(),
+                events.clone(),
+                Vec::new(),
+                vec![axis, output],
+                ActivationHooksConfig::default(),
+            )
+            .await;
+            actor_system.forget_senders();
+        };
+        futures::future::join3(axis_device, output_device, hooks).await;
+
+        assert!(calls.lock().unwrap().is_empty());
+        assert!(matches!(
+            errors.next().await.map(|x| x.kind),
+            Some(SystemEventKind::Error { .. })
+        ));
+    }
+}
@@ -0,0 +1,223 @@
+use std::{
+    collections::{HashMap, HashSet},
+    time::{Duration, Instant},
+};
+
+use minfac::{Registered, ServiceCollection};
+use pilatus::{
+    device::{ActorSystem, DeviceId, MessageMetadata},
+    prelude::*,
+    CycleDefinition, CycleTimeStats, CycleTimes, EventBus, GenericConfig, HealthState,
+    SystemEventKind, SystemShutdown,
+};
+use serde::Deserialize;
+use tracing::{info, warn};
+
+pub(super) fn register_services(c: &mut ServiceCollection) {
+    c.register_shared(|| std::sync::Arc::new(CycleTimes::default()))
+        .alias(|x| CycleTimes::clone(&x));
+    c.register_instance(pilatus::ServiceInfo::shared::<CycleTimes>());
+    c.with::<(
+        Registered<GenericConfig>,
+        Registered<CycleTimes>,
+        Registered<ActorSystem>,
+        Registered<HealthState>,
+        Registered<EventBus>,
+        Registered<SystemShutdown>,
+    )>()
+    .register_hosted_service("Cycle Time Monitor", monitor_cycle_times);
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+struct CycleTimeConfig {
+    /// Number of cycles for the rolling statistics
+    window: usize,
+    /// Interval to publish the statistics to the health report
+    update_interval_secs: u64,
+    history_interval_secs: u64,
+    /// Number of samples per device. The default keeps a day
+    history_len: usize,
+    devices: HashMap<DeviceId, CycleDefinition>,
+}
+
+impl Default for CycleTimeConfig {
+    fn default() -> Self {
+        Self {
+            window: 500,
+            update_interval_secs: 5,
+            history_interval_secs: 60,
+            history_len: 1440,
+            devices: HashMap::new(),
+        }
+    }
+}
+
+async fn monitor_cycle_times(
+    (config, cycle_times, actor_system, health, events, shutdown): (
+        GenericConfig,
+        CycleTimes,
+        ActorSystem,
+        HealthState,
+        EventBus,
+        SystemShutdown,
+    ),
+) -> anyhow::Result<()> {
+    let cycle_config = config
+        .get::<CycleTimeConfig>("cycle_times")
+        .unwrap_or_default();
+    if cycle_config.devices.is_empty() {
+        return Ok(());
+    }
+    info!(
+        "Monitor cycle times of {} devices",
+        cycle_config.devices.len()
+    );
+    cycle_times.configure(
+        cycle_config.window,
+        cycle_config.history_len,
+        cycle_config.devices.clone(),
+    );
+    observe_messages(&actor_system, &cycle_times);
+    let run = std::pin::pin!(async {
+        let mut interval = tokio::time::interval(Duration::from_secs(
+            cycle_config.update_interval_secs.max(1),
+        ));
+        let history_interval = Duration::from_secs(cycle_config.history_interval_secs.max(1));
+        let mut last_sample = Instant::now();
+        let mut exceeded = HashSet::new();
+        loop {
+            interval.tick().await;
+            if last_sample.elapsed() >= history_interval {
+                cycle_times.sample();
+                last_sample = Instant::now();
+            }
+            let stats = cycle_times.stats();
+            exceeded = report_takt_changes(&events, &stats, &exceeded);
+            health.update(|r| r.cycle_times = stats);
+        }
+    });
+    futures::future::select(run, shutdown).await;
+    Ok(())
+}
+
+/// Marks every message which is sent to a device. Untracked devices and messages are ignored by [`CycleTimes`]
+fn observe_messages(actor_system: &ActorSystem, cycle_times: &CycleTimes) {
+    let cycle_times = cycle_times.clone();
+    actor_system.add_interceptor(move |meta: &MessageMetadata| {
+        cycle_times.mark_message(meta);
+        Ok(())
+    });
+}
+
+/// Publishes an error once per device, when its takt gets exceeded. Returns the devices which exceed it now
+fn report_takt_changes(
+    events: &EventBus,
+    stats: &HashMap<DeviceId, CycleTimeStats>,
+    previous: &HashSet<DeviceId>,
+) -> HashSet<DeviceId> {
+    let exceeded: HashSet<_> = stats
+        .iter()
+        .filter(|(_, s)| s.takt_exceeded)
+        .map(|(id, _)| *id)
+        .collect();
+    for id in exceeded.difference(previous) {
+        let s = &stats[id];
+        let message = format!(
+            "p95 cycle time of device '{id}' is {:.1}ms, which exceeds the takt of {:.1}ms",
+            s.p95_ms,
+            s.takt_ms.unwrap_or_default()
+        );
+        warn!("{message}");
+        events.publish(SystemEventKind::Error {
+            source: "cycle_time".into(),
+            message,
+        });
+    }
+    for id in previous.difference(&exceeded) {
+        info!("Cycle time of device '{id}' is within the takt again");
+    }
+    exceeded
+}
+
+#[cfg(test)]
+mod tests {
+    use pilatus::device::{ActivationCompleteMessage, ActorResult, PrepareActivationMessage};
+
+    use super::*;
+
+    #[tokio::test]
+    async fn mark_messages_sent_to_devices() {
+        async fn prepare(
+            _: &mut (),
+            _msg: PrepareActivationMessage,
+        ) -> ActorResult<PrepareActivationMessage> {
+            Ok(())
+        }
+        async fn complete(
+            _: &mut (),
+            _msg: ActivationCompleteMessage,
+        ) -> ActorResult<ActivationCompleteMessage> {
+            Ok(())
+        }
+
+        let actor_system = ActorSystem::new();
+        let cycle_times = CycleTimes::default();
+        let device_id = DeviceId::new_v4();
+        cycle_times.configure(
+            10,
+            10,
+            [(
+                device_id,
+                CycleDefinition {
+                    trigger: "PrepareActivationMessage".into(),
+                    result: "ActivationCompleteMessage".into(),
+                    takt_ms: None,
+                },
+            )],
+        );
+        observe_messages(&actor_system, &cycle_times);
+        let device = actor_system
+            .register(device_id)
+            .add_handler(prepare)
+            .add_handler(complete)
+            .execute(());
+        let asks = async {
+            for _ in 0..2 {
+                actor_system
+                    .ask(device_id, PrepareActivationMessage)
+                    .await
+                    .unwrap();
+            }
+            actor_system
+                .ask(device_id, ActivationCompleteMessage)
+                .await
+                .unwrap();
+            actor_system.forget_senders();
+        };
+        futures::future::join(device, asks).await;
+
+        let stats = cycle_times.stats().remove(&device_id).unwrap();
+        assert_eq!((1, 1), (stats.cycles, stats.open_triggers));
+    }
+
+    #[test]
+    fn report_exceeded_takt_once() {
+        let events = EventBus::default();
+        let id = DeviceId::new_v4();
+        let stats = HashMap::from([(
+            id,
+            CycleTimeStats {
+                cycles: 10,
+                p95_ms: 120.,
+                takt_ms: Some(100.),
+                takt_exceeded: true,
+                ..Default::default()
+            },
+        )]);
+        let exceeded = report_takt_changes(&events, &stats, &HashSet::new());
+        let exceeded = report_takt_changes(&events, &stats, &exceeded);
+        assert_eq!(HashSet::from([id]), exceeded);
+        assert_eq!(1, events.query(&Default::default()).len());
+    }
+}
@@ -1,33 +1,46 @@
+use std::collections::{HashMap, HashSet};
 use std::future::Future;
 use std::sync::Arc;
 use std::sync::Mutex;
+use std::time::{Duration, Instant};
 
 use anyhow::anyhow;
 use async_trait::async_trait;
 use futures::future::BoxFuture;
 use futures::{
     channel::oneshot::{self, Sender},
-    future::select_all,
+    future::{select, select_all, Either},
     FutureExt, TryFutureExt,
 };
 use minfac::{AllRegistered, Registered, ServiceCollection, WeakServiceProvider};
 use pilatus::device::DeviceContext;
+use pilatus::device::DeviceLifecycle;
+use pilatus::device::DeviceResult;
+use pilatus::device::DeviceStateStore;
+use pilatus::device::DeviceStatusRegistry;
 use pilatus::device::InfallibleParamApplier;
 use pilatus::device::RecipeServiceParamApplier;
 use pilatus::device::WithInfallibleParamUpdate;
 use pilatus::Variables;
 use pilatus::{
-    device::{ActorSystem, DeviceId, FinalizeRecipeExecution, RecipeRunner, RecipeRunnerTrait},
+    device::{
+        ActorSystem, DeviceId, FinalizeRecipeExecution, PreparesActivation, RecipeRunner,
+        RecipeRunnerTrait, ScratchRecipe,
+    },
     prelude::*,
-    DeviceConfig, RecipeId, RecipeServiceTrait, SystemShutdown,
+    DeviceConfig, EventBus, GenericConfig, MaintenanceMode, RecipeId, RecipeServiceTrait,
+    SystemEventKind, SystemShutdown,
 };
+use serde::Deserialize;
 use tokio::task::JoinHandle;
-use tracing::{error, info};
+use tracing::{error, info, warn};
 
+use crate::activation_hooks::{complete_activation, prepare_activation, ActivationHooksConfig};
 use crate::metadata_future::MetadataFuture;
 use crate::recipe::DeviceSpawnerService;
 use crate::recipe::RecipeServiceFassade;
 use crate::recipe::StartDeviceError;
+use crate::shutdown::ShutdownSequence;
 
 pub(super) fn register_services(c: &mut ServiceCollection) {
     c.with::<(
@@ -35,45 +48,129 @@ pub(super) fn register_services(c: &mut ServiceCollection) {
         Registered<Arc<RecipeServiceFassade>>,
         Registered<ActorSystem>,
         Registered<SystemShutdown>,
+        Registered<ShutdownSequence>,
+        Registered<GenericConfig>,
     )>()
     .register_hosted_service("Device Runner", run_devices_from_service);
 
     c.register_shared(|| Arc::new(RecipeRunnerState::default()));
+    c.register_instance(pilatus::ServiceInfo::shared::<Arc<RecipeRunnerState>>());
+    c.register_shared(|| Arc::new(DeviceStatusRegistry::default()))
+        .alias(|x| DeviceStatusRegistry::clone(&x));
+    c.register_instance(pilatus::ServiceInfo::shared::<DeviceStatusRegistry>());
 
     c.with::<(
         WeakServiceProvider,
         Registered<Arc<RecipeRunnerState>>,
         Registered<DeviceSpawnerService>,
+        Registered<ActorSystem>,
+        Registered<EventBus>,
+        Registered<DeviceStateStore>,
+        Registered<DeviceStatusRegistry>,
+        Registered<GenericConfig>,
         AllRegistered<Arc<dyn FinalizeRecipeExecution>>,
+        AllRegistered<PreparesActivation>,
     )>()
-    .register(|(provider, state, spawner, finalizer)| {
-        RecipeRunnerImpl::new(provider, state, spawner, finalizer.collect())
-    });
-    c.with::<(Registered<RecipeRunnerImpl>, Registered<ActorSystem>)>()
-        .register(|(recipe_runner, actor_system)| {
-            RecipeRunner::new(Arc::new(RecipeRunnerService {
-                recipe_runner,
+    .register(
+        |(
+            provider,
+            state,
+            spawner,
+            actor_system,
+            events,
+            state_store,
+            status,
+            config,
+            finalizer,
+            preparing,
+        )| {
+            RecipeRunnerImpl::new(
+                provider,
+                state,
+                spawner,
                 actor_system,
-            }))
-        });
+                events,
+                state_store,
+                status,
+                finalizer.collect(),
+            )
+            .with_activation_hooks(
+                config
+                    .get::<ActivationHooksConfig>("activation_hooks")
+                    .unwrap_or_default(),
+                preparing.map(|x| x.0).collect(),
+            )
+        },
+    );
+    c.register_instance(pilatus::ServiceInfo::transient::<RecipeRunnerImpl>());
+    c.with::<(
+        Registered<RecipeRunnerImpl>,
+        Registered<ActorSystem>,
+        Registered<MaintenanceMode>,
+    )>()
+    .register(|(recipe_runner, actor_system, maintenance)| {
+        RecipeRunner::new(Arc::new(RecipeRunnerService {
+            recipe_runner,
+            actor_system,
+            maintenance,
+        }))
+    });
+    c.register_instance(pilatus::ServiceInfo::transient::<RecipeRunner>());
 }
 
-type RunJob = Sender<(RecipeId, Sender<anyhow::Result<()>>)>;
+type RunJob = Sender<(RunRequest, Sender<anyhow::Result<()>>)>;
+
+enum RunRequest {
+    Recipe(RecipeId),
+    Scratch(ScratchRecipe),
+    StopScratch,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+struct ParamRollbackConfig {
+    /// Opt-in, because a rollback also resets devices which were live-updated within the window
+    enabled: bool,
+    /// Devices failing within this time after starting with uncommitted parameters are rolled back,
+    /// together with the devices live-updated within this time
+    window_ms: u64,
+}
+
+impl Default for ParamRollbackConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            window_ms: 5000,
+        }
+    }
+}
 
 async fn run_devices_from_service(
-    (runner, recipe_service, actor_system, shutdown): (
+    (runner, recipe_service, actor_system, shutdown, shutdown_sequence, config): (
         RecipeRunnerImpl,
         Arc<RecipeServiceFassade>,
         ActorSystem,
         SystemShutdown,
+        ShutdownSequence,
+        GenericConfig,
     ),
 ) -> Result<(), anyhow::Error> {
-    let (r1, r2) = tokio::join!(runner.run_active_recipe(recipe_service), async {
-        shutdown.await;
-        runner.set_next(None)?;
-        actor_system.forget_senders();
-        anyhow::Result::<()>::Ok(())
-    });
+    let rollback_config = config
+        .get::<ParamRollbackConfig>("param_rollback")
+        .unwrap_or_default();
+    let rollback_window = rollback_config
+        .enabled
+        .then(|| Duration::from_millis(rollback_config.window_ms));
+    let (r1, r2) = tokio::join!(
+        runner.run_active_recipe(recipe_service, rollback_window),
+        async {
+            shutdown.await;
+            shutdown_sequence.run().await;
+            runner.set_next(None)?;
+            actor_system.forget_senders();
+            anyhow::Result::<()>::Ok(())
+        }
+    );
 
     r1.and(r2)
 }
@@ -83,33 +180,85 @@ pub struct RecipeRunnerImpl {
     provider: WeakServiceProvider,
     state: Arc<RecipeRunnerState>,
     spawner: DeviceSpawnerService,
+    actor_system: ActorSystem,
+    events: EventBus,
+    state_store: DeviceStateStore,
+    status: DeviceStatusRegistry,
     finalizer: Vec<Arc<dyn FinalizeRecipeExecution>>,
+    activation_hooks: ActivationHooksConfig,
+    /// Device types which are started and prepared before all others
+    preparing_types: Arc<HashSet<&'static str>>,
 }
 
 struct RecipeRunnerService {
     recipe_runner: RecipeRunnerImpl,
     actor_system: ActorSystem,
+    /// Devices mustn't be started or stopped during maintenance. Recipe activation is checked by the RecipeService
+    maintenance: MaintenanceMode,
 }
 
 #[async_trait]
 impl RecipeRunnerTrait for RecipeRunnerService {
     async fn select_recipe(&self, recipe_id: RecipeId) -> anyhow::Result<()> {
-        let result = self.recipe_runner.select_recipe(recipe_id)?;
+        self.request(RunRequest::Recipe(recipe_id)).await
+    }
+
+    async fn run_scratch_recipe(&self, recipe: ScratchRecipe) -> anyhow::Result<()> {
+        self.maintenance.ensure_inactive()?;
+        self.request(RunRequest::Scratch(recipe)).await
+    }
+
+    async fn stop_scratch_recipe(&self) -> anyhow::Result<()> {
+        self.maintenance.ensure_inactive()?;
+        self.request(RunRequest::StopScratch).await
+    }
+
+    async fn restart_devices(&self, devices: HashSet<DeviceId>) -> anyhow::Result<()> {
+        self.maintenance.ensure_inactive()?;
+        self.recipe_runner.restart(devices);
+        Ok(())
+    }
+}
+
+impl RecipeRunnerService {
+    async fn request(&self, request: RunRequest) -> anyhow::Result<()> {
+        let result = self.recipe_runner.request(request)?;
         self.actor_system.forget_senders();
         result.await
     }
 }
 
-type ChangeApplier<'a> = &'a mut (dyn FnMut(
-    DeviceId,
-    WithInfallibleParamUpdate<JoinHandle<Result<(), anyhow::Error>>>,
-) -> BoxFuture<'a, JoinHandle<Result<(), anyhow::Error>>>
-             + Send);
+type ChangeApplierFn<'a> = dyn FnMut(
+        DeviceId,
+        WithInfallibleParamUpdate<JoinHandle<Result<(), anyhow::Error>>>,
+    ) -> BoxFuture<'a, JoinHandle<Result<(), anyhow::Error>>>
+    + Send
+    + 'a;
+type ChangeApplier<'a> = &'a mut ChangeApplierFn<'a>;
+
+/// Provides the current configuration of a device which is restarted
+type ConfigLoader<'a> =
+    &'a (dyn Fn(DeviceId) -> BoxFuture<'a, Option<(DeviceConfig, Variables)>> + Send + Sync);
+
+/// Restores the committed parameters of a device and returns its config to restart it.
+/// Devices which were live-updated within the window get their committed parameters back as well
+type ParamRestorer<'a> = &'a (dyn Fn(DeviceId, String, Duration) -> BoxFuture<'a, Option<(DeviceConfig, Variables)>>
+         + Send
+         + Sync);
+
+/// Devices which fail within `window` after they were started with uncommitted parameters
+/// are restarted with their committed parameters
+struct ParamRollback<'a> {
+    window: Duration,
+    restore: ParamRestorer<'a>,
+}
+
+type DeviceFuture = MetadataFuture<(DeviceId, String), JoinHandle<Result<(), anyhow::Error>>>;
 
 impl RecipeRunnerImpl {
-    fn select_recipe(
+    fn request(
         &self,
-        recipe_id: RecipeId,
+        request: RunRequest,
     ) -> anyhow::Result<impl Future<Output = anyhow::Result<()>>> {
         let sender = {
             self.state
@@ -122,8 +271,8 @@ impl RecipeRunnerImpl {
 
         let (tx, rx) = oneshot::channel();
         sender
-            .send((recipe_id, tx))
-            .map_err(|_| anyhow::anyhow!("Couldn't send RecipeId to channel"))?;
+            .send((request, tx))
+            .map_err(|_| anyhow::anyhow!("Couldn't send RunRequest to channel"))?;
         Ok(rx
             .map_err(Into::into)
             .and_then(|x| async move { x })
@@ -134,13 +283,44 @@ impl RecipeRunnerImpl {
         provider: WeakServiceProvider,
         state: Arc<RecipeRunnerState>,
         spawner: DeviceSpawnerService,
+        actor_system: ActorSystem,
+        events: EventBus,
+        state_store: DeviceStateStore,
+        status: DeviceStatusRegistry,
         finalizer: Vec<Arc<dyn FinalizeRecipeExecution>>,
     ) -> Self {
         Self {
             provider,
             state,
             spawner,
+            actor_system,
+            events,
+            state_store,
+            status,
             finalizer,
+            activation_hooks: Default::default(),
+            preparing_types: Default::default(),
+        }
+    }
+
+    fn with_activation_hooks(
+        self,
+        activation_hooks: ActivationHooksConfig,
+        preparing_types: HashSet<&'static str>,
+    ) -> Self {
+        Self {
+            activation_hooks,
+            preparing_types: Arc::new(preparing_types),
+            ..self
+        }
+    }
+
+    /// The devices stop when their senders are dropped and are respawned by `run_devices`
+    fn restart(&self, devices: HashSet<DeviceId>) {
+        let mut requests = self.state.restart_requests.lock().expect("Not poisoned");
+        for id in devices {
+            requests.insert(id);
+            self.actor_system.forget_sender(id);
         }
     }
 
@@ -154,7 +334,12 @@ impl RecipeRunnerImpl {
         Ok(())
     }
 
-    async fn run_active_recipe(&self, rs: Arc<RecipeServiceFassade>) -> Result<(), anyhow::Error> {
+    async fn run_active_recipe(
+        &self,
+        rs: Arc<RecipeServiceFassade>,
+        rollback_window: Option<Duration>,
+    ) -> Result<(), anyhow::Error> {
+        let mut scratch = None;
         loop {
             let (recipe_id, active_devices, variables) = rs
                 .recipe_service_read()
@@ -162,80 +347,237 @@ impl RecipeRunnerImpl {
                 .get_owned_devices_from_active()
                 .await;
             let (tx, rx) = oneshot::channel();
-            // Allow new recipe via self.select_recipe()
+            // Allow new recipe via self.request()
             *self.state.next_recipe_id.lock().expect("Not poisoned") = Some(tx);
-            self.run_devices(
-                active_devices,
-                variables,
-                &mut |device_id, update| {
-                    RecipeServiceParamApplier {
-                        device_id,
-                        recipe_id: recipe_id.clone(),
-                        service: rs.as_ref() as &(dyn RecipeServiceTrait + Send + Sync),
-                    }
-                    .apply(update)
-                },
-                |info| info!(info),
-                |error| error!(error),
-            )
-            .await?;
+            match scratch.take() {
+                Some(scratch) => self.run_scratch_devices(scratch, variables).await?,
+                None => {
+                    let load_config = |device_id| {
+                        let rs = rs.clone();
+                        async move {
+                            let (_, devices, variables) = rs
+                                .recipe_service_read()
+                                .await
+                                .get_owned_devices_from_active()
+                                .await;
+                            devices
+                                .into_iter()
+                                .find(|(id, _)| *id == device_id)
+                                .map(|(_, config)| (config, variables))
+                        }
+                        .boxed()
+                    };
+                    let restore = |device_id, reason, window| {
+                        let rs = rs.clone();
+                        let recipe_id = recipe_id.clone();
+                        async move {
+                            rs.rollback_failed_device(recipe_id, device_id, reason, window)
+                                .await
+                                .map_err(|e| {
+                                    error!("Couldn't restore committed params of {device_id}: {e}")
+                                })
+                                .ok()
+                        }
+                        .boxed()
+                    };
+                    self.run_devices(
+                        active_devices,
+                        variables,
+                        &load_config,
+                        rollback_window.map(|window| ParamRollback {
+                            window,
+                            restore: &restore,
+                        }),
+                        &mut |device_id, update| {
+                            RecipeServiceParamApplier {
+                                device_id,
+                                recipe_id: recipe_id.clone(),
+                                service: rs.as_ref() as &(dyn RecipeServiceTrait + Send + Sync),
+                            }
+                            .apply(update)
+                        },
+                        |info| info!(info),
+                        |error| error!(error),
+                    )
+                    .await?
+                }
+            }
 
             futures::future::join_all(self.finalizer.iter().map(|x| x.finalize_recipe_execution()))
                 .await;
 
             match rx.await {
-                Ok((next_id, select_recipe_response)) => {
-                    let _ignore_absent_receiver = select_recipe_response
-                        .send(rs.activate_recipe(next_id).await.map_err(Into::into));
+                Ok((RunRequest::Recipe(next_id), response)) => {
+                    let result = rs.activate_recipe(next_id.clone()).await;
+                    if result.is_ok() {
+                        self.events
+                            .publish(SystemEventKind::RecipeActivated { recipe_id: next_id });
+                    }
+                    let _ignore_absent_receiver = response.send(result.map_err(Into::into));
+                }
+                Ok((RunRequest::Scratch(next_scratch), response)) => {
+                    scratch = Some(next_scratch);
+                    let _ignore_absent_receiver = response.send(Ok(()));
+                }
+                Ok((RunRequest::StopScratch, response)) => {
+                    let _ignore_absent_receiver = response.send(Ok(()));
                 }
                 Err(_) => break,
             }
         }
         Ok(())
     }
+
+    /// Runs the devices without persisting anything. Unless another RunRequest arrives earlier,
+    /// the devices are stopped after ScratchRecipe::timeout
+    async fn run_scratch_devices(
+        &self,
+        scratch: ScratchRecipe,
+        variables: Variables,
+    ) -> anyhow::Result<()> {
+        info!(
+            "Start scratch recipe with {} devices for {:?}",
+            scratch.devices.len(),
+            scratch.timeout
+        );
+        let configs: &HashMap<DeviceId, DeviceConfig> = &scratch.devices;
+        let mut discard_changes =
+            |device_id: DeviceId, update: WithInfallibleParamUpdate<JoinHandle<DeviceResult>>| {
+                let mut discarded = configs
+                    .get(&device_id)
+                    .cloned()
+                    .expect("Only devices of the scratch recipe are spawned");
+                async move { discarded.apply(update).await }.boxed()
+            };
+        let load_config = |device_id| {
+            let config = configs.get(&device_id).cloned();
+            let variables = variables.clone();
+            async move { config.map(|c| (c, variables)) }.boxed()
+        };
+        let run = self.run_devices(
+            configs.iter().map(|(id, config)| (*id, config.clone())),
+            variables.clone(),
+            &load_config,
+            None,
+            &mut discard_changes,
+            |info| info!(info),
+            |error| error!(error),
+        );
+        let timeout = async {
+            tokio::time::sleep(scratch.timeout).await;
+            // Fails, if another RunRequest was received in the meantime
+            if let Ok(result) = self.request(RunRequest::StopScratch) {
+                info!("Scratch recipe timed out");
+                self.actor_system.forget_senders();
+                drop(result);
+            }
+            futures::future::pending::<()>().await
+        };
+        futures::pin_mut!(run, timeout);
+        match select(run, timeout).await {
+            Either::Left((result, _)) => result,
+            Either::Right(((), _)) => unreachable!("Timeout never finishes"),
+        }
+    }
     async fn run_devices<'a>(
         &'a self,
         active_devices: impl IntoIterator<Item = (DeviceId, DeviceConfig)>,
         variables: Variables,
+        load_config: ConfigLoader<'a>,
+        rollback: Option<ParamRollback<'a>>,
         change_applier: ChangeApplier<'a>,
         mut info_logger: impl FnMut(String),
         mut error_logger: impl FnMut(String),
     ) -> Result<(), anyhow::Error> {
         let mut device_futures = Vec::new();
+        self.status.clear();
+        // Devices started with uncommitted parameters, which are rolled back if they fail early
+        let mut uncommitted_starts = HashMap::new();
+        let (preparing, mut others): (Vec<_>, Vec<_>) = active_devices
+            .into_iter()
+            .partition(|(_, device)| self.preparing_types.contains(device.get_device_type()));
 
-        for (id, device) in active_devices {
-            let device_type = device.get_device_type().to_string();
+        for (id, device) in preparing {
+            if device.has_uncommitted_params() {
+                uncommitted_starts.insert(id, Instant::now());
+            }
+            device_futures.extend(
+                self.spawn_device(id, device, variables.clone(), &mut *change_applier)
+                    .await,
+            );
+        }
+        let prepared = device_futures
+            .iter()
+            .map(|x| x.get_meta().0)
+            .collect::<Vec<_>>();
+        let activation = prepare_activation(
+            &self.actor_system,
+            &self.events,
+            &prepared,
+            &self.activation_hooks,
+        )
+        .await;
+        if let Err(e) = &activation {
+            (error_logger)(format!(
+                "Activation failed, the remaining devices are not started: {e:#}"
+            ));
+            for (id, device) in others.drain(..) {
+                self.status.set(
+                    id,
+                    device.get_device_type(),
+                    DeviceLifecycle::Failed {
+                        error: format!("Not started, because the activation failed: {e:#}"),
+                    },
+                );
+            }
+            for id in prepared.iter() {
+                self.actor_system.forget_sender(*id);
+            }
+        }
 
-            match self
-                .spawner
-                .spawn(
-                    &device_type,
-                    DeviceContext::new(id, variables.clone(), device.params.clone()),
-                    self.provider.clone(),
-                )
-                .await
-            {
-                Ok(x) => {
-                    let extracted = (change_applier)(id, x).await;
-                    info!("Starting Device '{device_type}' with id '{id}'");
-                    device_futures.push(MetadataFuture::new((id, device_type), extracted));
-                }
-                Err(StartDeviceError::UnknownDeviceType) => {
-                    error!(device = device.get_device_type(), "Unknown DeviceType");
-                }
-                Err(StartDeviceError::Validation(e)) => {
-                    error!(message = %e, "Invalid Params for Device '{device_type}' with id '{id}'");
-                }
-                Err(StartDeviceError::Io(e)) => {
-                    error!(message = %e, "Couldn't spawn Device '{device_type}' with id '{id}'");
-                }
+        let first_other = device_futures.len();
+        for (id, device) in others {
+            if device.has_uncommitted_params() {
+                uncommitted_starts.insert(id, Instant::now());
             }
+            device_futures.extend(
+                self.spawn_device(id, device, variables.clone(), &mut *change_applier)
+                    .await,
+            );
         }
+        // Devices which are restarted later on don't run the hooks again
+        let activation_hooks = activation.is_ok().then(|| {
+            tokio::spawn(complete_activation(
+                self.actor_system.clone(),
+                self.events.clone(),
+                prepared,
+                device_futures[first_other..]
+                    .iter()
+                    .map(|x| x.get_meta().0)
+                    .collect(),
+                self.activation_hooks.clone(),
+            ))
+        });
 
         while !device_futures.is_empty() {
             let (((id, devicetype), finished), _, rest) = select_all(device_futures).await;
             device_futures = rest;
             let flattened = finished.map_err(anyhow::Error::from).and_then(|e| e);
+            let started = uncommitted_starts.remove(&id);
+            let error = flattened.as_ref().err().map(|e| format!("{e:#}"));
+            self.status.set(
+                id,
+                &devicetype,
+                match error.clone() {
+                    Some(error) => DeviceLifecycle::Failed { error },
+                    None => DeviceLifecycle::Stopped,
+                },
+            );
+            self.events.publish(SystemEventKind::DeviceStopped {
+                device_id: id,
+                device_type: devicetype.clone(),
+                error,
+            });
             if let Err(e) = flattened {
                 for cause in e.chain() {
                     (error_logger)(format!(
@@ -243,25 +585,151 @@ impl RecipeRunnerImpl {
                         id, devicetype, cause
                     ));
                 }
-            } else {
-                (info_logger)(format!(
-                    "Device {id} of Type '{devicetype}' stopped, {}",
-                    if device_futures.len() == 1 {
-                        format!("1 remaining ({:?})", device_futures[0].get_meta())
-                    } else {
-                        format!("{} remaining", device_futures.len())
+                if let (Some(rollback), Some(started)) = (rollback.as_ref(), started) {
+                    if started.elapsed() < rollback.window {
+                        let reason = format!(
+                            "Restored committed parameters, because device failed {:?} after starting with uncommitted ones: {e:#}",
+                            started.elapsed()
+                        );
+                        if let Some((device, variables)) =
+                            (rollback.restore)(id, reason, rollback.window).await
+                        {
+                            if let Some(restarted) = self
+                                .spawn_device(id, device, variables, change_applier)
+                                .await
+                            {
+                                (error_logger)(format!(
+                                    "Device {id} of Type '{devicetype}' failed to start with uncommitted parameters and was restarted with the committed ones"
+                                ));
+                                device_futures.push(restarted);
+                                continue;
+                            }
+                        }
                     }
-                ));
+                }
             }
+
+            let restart_requested = self
+                .state
+                .restart_requests
+                .lock()
+                .expect("Not poisoned")
+                .remove(&id);
+            if restart_requested {
+                if let Some((device, variables)) = (load_config)(id).await {
+                    if device.has_uncommitted_params() {
+                        uncommitted_starts.insert(id, Instant::now());
+                    }
+                    if let Some(restarted) = self
+                        .spawn_device(id, device, variables, change_applier)
+                        .await
+                    {
+                        (info_logger)(format!("Device {id} of Type '{devicetype}' restarted"));
+                        device_futures.push(restarted);
+                        continue;
+                    }
+                }
+            }
+
+            (info_logger)(format!(
+                "Device {id} of Type '{devicetype}' stopped, {}",
+                if device_futures.len() == 1 {
+                    format!("1 remaining ({:?})", device_futures[0].get_meta())
+                } else {
+                    format!("{} remaining", device_futures.len())
+                }
+            ));
+        }
+        if let Some(activation_hooks) = activation_hooks {
+            activation_hooks.abort();
         }
+        self.state
+            .restart_requests
+            .lock()
+            .expect("Not poisoned")
+            .clear();
 
         Ok(())
     }
+
+    async fn spawn_device<'a>(
+        &'a self,
+        id: DeviceId,
+        device: DeviceConfig,
+        variables: Variables,
+        change_applier: &mut ChangeApplierFn<'a>,
+    ) -> Option<DeviceFuture> {
+        let device_type = device.get_device_type().to_string();
+        if !device.enabled {
+            info!("Device '{device_type}' with id '{id}' is disabled");
+            self.status.set(id, &device_type, DeviceLifecycle::Stopped);
+            return None;
+        }
+        if let Some(field) = device.unfilled_placeholders().next() {
+            warn!("Device '{device_type}' with id '{id}' is not started, because '{field}' isn't set yet");
+            self.status.set(id, &device_type, DeviceLifecycle::Stopped);
+            return None;
+        }
+        self.status
+            .set(id, &device_type, DeviceLifecycle::Validating);
+        self.actor_system
+            .set_device_name(id, device.device_name.clone());
+
+        match self
+            .spawner
+            .spawn(
+                &device_type,
+                DeviceContext::new(id, variables, device.params.clone())
+                    .with_simulated(device.simulated)
+                    .with_state_store(&self.state_store),
+                self.provider.clone(),
+            )
+            .await
+        {
+            Ok(x) => {
+                self.status.set(id, &device_type, DeviceLifecycle::Starting);
+                let extracted = (change_applier)(id, x).await;
+                info!("Starting Device '{device_type}' with id '{id}'");
+                self.status.set(id, &device_type, DeviceLifecycle::Running);
+                self.events.publish(SystemEventKind::DeviceStarted {
+                    device_id: id,
+                    device_type: device_type.clone(),
+                });
+                Some(MetadataFuture::new((id, device_type), extracted))
+            }
+            Err(e) => {
+                match &e {
+                    StartDeviceError::UnknownDeviceType => {
+                        error!(device = device.get_device_type(), "Unknown DeviceType");
+                    }
+                    StartDeviceError::Validation(e) => {
+                        error!(message = %e, "Invalid Params for Device '{device_type}' with id '{id}'");
+                    }
+                    StartDeviceError::Io(e) => {
+                        error!(message = %e, "Couldn't spawn Device '{device_type}' with id '{id}'");
+                    }
+                }
+                self.status.set(
+                    id,
+                    &device_type,
+                    DeviceLifecycle::Failed {
+                        error: e.to_string(),
+                    },
+                );
+                self.events.publish(SystemEventKind::Error {
+                    source: format!("Device '{device_type}' with id '{id}'"),
+                    message: e.to_string(),
+                });
+                None
+            }
+        }
+    }
 }
 
 #[derive(Default)]
 struct RecipeRunnerState {
     next_recipe_id: Mutex<Option<RunJob>>,
+    restart_requests: Mutex<HashSet<DeviceId>>,
 }
 
 #[cfg(test)]
@@ -309,6 +777,10 @@ mod tests {
             weak_provider,
             Arc::new(state),
             DeviceSpawnerService::new(provider.get_all(), ActorSystem::new()),
+            ActorSystem::new(),
+            EventBus::default(),
+            DeviceStateStore::in_memory(),
+            DeviceStatusRegistry::default(),
             Vec::new(),
         );
         runner
@@ -330,6 +802,8 @@ mod tests {
                 .into_iter()
                 .collect::<Vec<_>>(),
                 Variables::default(),
+                &|_| async { None }.boxed(),
+                None,
                 &mut |_, changes| {
                     #[allow(clippy::async_yields_async)]
                     async {
@@ -367,4 +841,256 @@ mod tests {
             "'{baz_msg}' doesn't contain 'baz'"
         );
     }
+
+    #[tokio::test]
+    async fn skip_disabled_devices() {
+        let mut collection = minfac::ServiceCollection::new();
+        collection
+            .with::<()>()
+            .register_device("foo", validate_ok, |_, _, _| async {
+                panic!("Disabled device must not be started")
+            });
+        let provider = collection.build().unwrap();
+        let runner = RecipeRunnerImpl::new(
+            (&provider).into(),
+            Default::default(),
+            DeviceSpawnerService::new(provider.get_all(), ActorSystem::new()),
+            ActorSystem::new(),
+            EventBus::default(),
+            DeviceStateStore::in_memory(),
+            DeviceStatusRegistry::default(),
+            Vec::new(),
+        );
+        let mut messages = Vec::new();
+        runner
+            .run_devices(
+                [(
+                    DeviceId::new_v4(),
+                    DeviceConfig::new_unchecked("foo", "MyFoo", "{}").with_enabled(false),
+                )],
+                Variables::default(),
+                &|_| async { None }.boxed(),
+                None,
+                &mut |_, changes| {
+                    #[allow(clippy::async_yields_async)]
+                    async {
+                        changes
+                            .into_data_if_no_changes()
+                            .expect("Should have no changes")
+                    }
+                    .boxed()
+                },
+                |x| messages.push(x),
+                |x| panic!("Unexpected error: {x}"),
+            )
+            .await
+            .unwrap();
+        assert!(messages.is_empty());
+    }
+
+    #[tokio::test]
+    async fn rollback_device_failing_with_uncommitted_params() {
+        async fn validate_number(
+            ctx: DeviceValidationContext<'_>,
+        ) -> Result<i32, UpdateParamsMessageError> {
+            ctx.params_as::<i32>()
+        }
+        let mut collection = minfac::ServiceCollection::new();
+        collection.with::<()>().register_device(
+            "foo",
+            validate_number,
+            |_, params, _| async move {
+                anyhow::ensure!(params != 2, "Camera rejected exposure");
+                Ok(())
+            },
+        );
+        let provider = collection.build().unwrap();
+        let runner = RecipeRunnerImpl::new(
+            (&provider).into(),
+            Default::default(),
+            DeviceSpawnerService::new(provider.get_all(), ActorSystem::new()),
+            ActorSystem::new(),
+            EventBus::default(),
+            DeviceStateStore::in_memory(),
+            DeviceStatusRegistry::default(),
+            Vec::new(),
+        );
+        let mut device = DeviceConfig::new_unchecked("foo", "MyFoo", 1);
+        device.update_params_uncommitted(
+            pilatus::UntypedDeviceParamsWithVariables::from_serializable(2).unwrap(),
+        );
+        let reasons = Mutex::new(Vec::new());
+        let restore = |_, reason, _| {
+            reasons.lock().unwrap().push(reason);
+            async {
+                Some((
+                    DeviceConfig::new_unchecked("foo", "MyFoo", 1),
+                    Variables::default(),
+                ))
+            }
+            .boxed()
+        };
+        let mut errors = Vec::new();
+        runner
+            .run_devices(
+                [(DeviceId::new_v4(), device)],
+                Variables::default(),
+                &|_| async { None }.boxed(),
+                Some(ParamRollback {
+                    window: Duration::from_secs(10),
+                    restore: &restore,
+                }),
+                &mut |_, changes| {
+                    #[allow(clippy::async_yields_async)]
+                    async {
+                        changes
+                            .into_data_if_no_changes()
+                            .expect("Should have no changes")
+                    }
+                    .boxed()
+                },
+                |_| {},
+                |x| errors.push(x),
+            )
+            .await
+            .unwrap();
+        let reasons = reasons.lock().unwrap();
+        assert_eq!(1, reasons.len());
+        assert!(reasons[0].contains("Camera rejected exposure"));
+        assert!(errors
+            .iter()
+            .any(|x| x.contains("restarted with the committed")));
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn stop_scratch_devices_after_timeout() {
+        let actor_system = ActorSystem::new();
+        let mut collection = minfac::ServiceCollection::new();
+        collection.register_instance(actor_system.clone());
+        collection
+            .with::<Registered<ActorSystem>>()
+            .register_device("foo", validate_ok, |ctx, _, actor_system| async move {
+                actor_system.register(ctx.id).execute(()).await;
+                Ok(())
+            });
+        let provider = collection.build().unwrap();
+        let state = Arc::new(RecipeRunnerState::default());
+        let status = DeviceStatusRegistry::default();
+        let runner = RecipeRunnerImpl::new(
+            (&provider).into(),
+            state.clone(),
+            DeviceSpawnerService::new(provider.get_all(), actor_system.clone()),
+            actor_system,
+            EventBus::default(),
+            DeviceStateStore::in_memory(),
+            status.clone(),
+            Vec::new(),
+        );
+        let (tx, rx) = oneshot::channel();
+        *state.next_recipe_id.lock().unwrap() = Some(tx);
+        let device_id = DeviceId::new_v4();
+        let scratch = ScratchRecipe::new(
+            HashMap::from([(device_id, DeviceConfig::new_unchecked("foo", "MyFoo", "{}"))]),
+            Duration::from_secs(30),
+        );
+
+        let start = tokio::time::Instant::now();
+        runner
+            .run_scratch_devices(scratch, Variables::default())
+            .await
+            .unwrap();
+        assert_eq!(Duration::from_secs(30), start.elapsed());
+        assert!(matches!(rx.await, Ok((RunRequest::StopScratch, _))));
+        assert_eq!(
+            vec![device_id],
+            status
+                .all()
+                .into_iter()
+                .map(|x| x.device_id)
+                .collect::<Vec<_>>()
+        );
+    }
+
+    #[tokio::test]
+    async fn reject_scratch_recipes_and_restarts_during_maintenance() {
+        let provider = minfac::ServiceCollection::new().build().unwrap();
+        let actor_system = ActorSystem::new();
+        let state = Arc::new(RecipeRunnerState::default());
+        let maintenance = MaintenanceMode::default();
+        let runner = RecipeRunnerService {
+            recipe_runner: RecipeRunnerImpl::new(
+                (&provider).into(),
+                state.clone(),
+                DeviceSpawnerService::new(provider.get_all(), actor_system.clone()),
+                actor_system.clone(),
+                EventBus::default(),
+                DeviceStateStore::in_memory(),
+                DeviceStatusRegistry::default(),
+                Vec::new(),
+            ),
+            actor_system,
+            maintenance: maintenance.clone(),
+        };
+        let (tx, mut rx) = oneshot::channel();
+        *state.next_recipe_id.lock().unwrap() = Some(tx);
+        maintenance.enable(Some("Replacing the conveyor belt".into()), None);
+
+        let scratch = ScratchRecipe::new(HashMap::new(), Duration::from_secs(30));
+        for result in [
+            runner.run_scratch_recipe(scratch).await,
+            runner.stop_scratch_recipe().await,
+            runner
+                .restart_devices(HashSet::from([DeviceId::new_v4()]))
+                .await,
+        ] {
+            let error = result.unwrap_err();
+            assert!(error.is::<pilatus::MaintenanceError>(), "{error}");
+        }
+        assert!(matches!(rx.try_recv(), Ok(None)), "No request was sent");
+        assert!(state.restart_requests.lock().unwrap().is_empty());
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn keep_scratch_devices_if_another_request_arrived() {
+        let actor_system = ActorSystem::new();
+        let mut collection = minfac::ServiceCollection::new();
+        collection.register_instance(actor_system.clone());
+        collection
+            .with::<Registered<ActorSystem>>()
+            .register_device("foo", validate_ok, |ctx, _, actor_system| async move {
+                actor_system.register(ctx.id).execute(()).await;
+                Ok(())
+            });
+        let provider = collection.build().unwrap();
+        let runner = RecipeRunnerImpl::new(
+            (&provider).into(),
+            Default::default(),
+            DeviceSpawnerService::new(provider.get_all(), actor_system.clone()),
+            actor_system.clone(),
+            EventBus::default(),
+            DeviceStateStore::in_memory(),
+            DeviceStatusRegistry::default(),
+            Vec::new(),
+        );
+        // No RunJob is pending, as if another RunRequest was received before the timeout
+        let scratch = ScratchRecipe::new(
+            HashMap::from([(
+                DeviceId::new_v4(),
+                DeviceConfig::new_unchecked("foo", "MyFoo", "{}"),
+            )]),
+            Duration::from_secs(30),
+        );
+        let stop_later = async {
+            tokio::time::sleep(Duration::from_secs(60)).await;
+            actor_system.forget_senders();
+        };
+        let start = tokio::time::Instant::now();
+        let (result, ()) = futures::future::join(
+            runner.run_scratch_devices(scratch, Variables::default()),
+            stop_later,
+        )
+        .await;
+        result.unwrap();
+        assert_eq!(Duration::from_secs(60), start.elapsed());
+    }
 }
@@ -0,0 +1,103 @@
+use std::{collections::BTreeMap, sync::Arc};
+
+use minfac::{Registered, ServiceCollection};
+use pilatus::{
+    device::{DeviceId, DeviceStateBackend, DeviceStateStore},
+    GenericConfig,
+};
+use tracing::error;
+
+const DEVICE_STATE_DIR: &str = "device_state";
+
+pub(super) fn register_services(c: &mut ServiceCollection) {
+    c.with::<Registered<GenericConfig>>()
+        .register_shared(|config| {
+            let path = config.root.join(DEVICE_STATE_DIR);
+            Arc::new(match SledDeviceState::open(&path) {
+                Ok(x) => DeviceStateStore::new(Arc::new(x)),
+                Err(e) => {
+                    error!("Cannot open device state in {path:?}, state is lost on restart: {e}");
+                    DeviceStateStore::in_memory()
+                }
+            })
+        })
+        .alias(|x| DeviceStateStore::clone(&x));
+    c.register_instance(pilatus::ServiceInfo::shared::<DeviceStateStore>());
+}
+
+/// Keys are prefixed with the DeviceId, so the state of a device can be listed with a prefix scan.
+/// Sled flushes to disk in the background every few hundred milliseconds, unless devices flush explicitly
+struct SledDeviceState(sled::Db);
+
+impl SledDeviceState {
+    fn open(path: &std::path::Path) -> sled::Result<Self> {
+        sled::open(path).map(Self)
+    }
+}
+
+fn prefix(device_id: DeviceId) -> String {
+    format!("{device_id}/")
+}
+
+impl DeviceStateBackend for SledDeviceState {
+    fn get(&self, device_id: DeviceId, key: &str) -> anyhow::Result<Option<serde_json::Value>> {
+        self.0
+            .get(format!("{}{key}", prefix(device_id)))?
+            .map(|raw| serde_json::from_slice(&raw))
+            .transpose()
+            .map_err(Into::into)
+    }
+
+    fn set(&self, device_id: DeviceId, key: &str, value: serde_json::Value) -> anyhow::Result<()> {
+        self.0.insert(
+            format!("{}{key}", prefix(device_id)),
+            serde_json::to_vec(&value)?,
+        )?;
+        Ok(())
+    }
+
+    fn remove(&self, device_id: DeviceId, key: &str) -> anyhow::Result<()> {
+        self.0.remove(format!("{}{key}", prefix(device_id)))?;
+        Ok(())
+    }
+
+    fn entries(&self, device_id: DeviceId) -> anyhow::Result<BTreeMap<String, serde_json::Value>> {
+        let prefix = prefix(device_id);
+        self.0
+            .scan_prefix(&prefix)
+            .map(|entry| {
+                let (key, value) = entry?;
+                let key = std::str::from_utf8(&key)?[prefix.len()..].to_string();
+                Ok((key, serde_json::from_slice(&value)?))
+            })
+            .collect()
+    }
+
+    fn flush(&self) -> anyhow::Result<()> {
+        self.0.flush()?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn state_survives_reopening() -> anyhow::Result<()> {
+        let dir = tempfile::tempdir()?;
+        let device_id = DeviceId::new_v4();
+        {
+            let store = DeviceStateStore::new(Arc::new(SledDeviceState::open(dir.path())?));
+            let state = store.scope(device_id);
+            state.set("counter", &3)?;
+            state.set("last_maintenance", &"2024-01-01")?;
+            store.scope(DeviceId::new_v4()).set("counter", &7)?;
+        }
+        let store = DeviceStateStore::new(Arc::new(SledDeviceState::open(dir.path())?));
+        let state = store.scope(device_id);
+        assert_eq!(Some(3), state.get::<i32>("counter")?);
+        assert_eq!(2, state.entries()?.len());
+        Ok(())
+    }
+}
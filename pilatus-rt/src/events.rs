@@ -0,0 +1,124 @@
+use std::{path::PathBuf, sync::Arc, time::Duration};
+
+use minfac::{Registered, ServiceCollection};
+use pilatus::{EventBus, GenericConfig, SystemEvent, SystemShutdown};
+use serde::Deserialize;
+use tracing::warn;
+
+const EVENTS_FILE: &str = "events.json";
+
+pub(super) fn register_services(c: &mut ServiceCollection) {
+    c.with::<Registered<GenericConfig>>()
+        .register_shared(|config| {
+            let events_config = config.get::<EventsConfig>("events").unwrap_or_default();
+            Arc::new(EventBus::with_events(
+                events_config.capacity,
+                load_events(&config.root.join(EVENTS_FILE)),
+            ))
+        })
+        .alias(|x| EventBus::clone(&x));
+    c.register_instance(pilatus::ServiceInfo::shared::<EventBus>());
+    c.with::<(
+        Registered<GenericConfig>,
+        Registered<EventBus>,
+        Registered<SystemShutdown>,
+    )>()
+    .register_hosted_service("Event Persistence", persist_events);
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+struct EventsConfig {
+    capacity: usize,
+    persist_interval_secs: u64,
+}
+
+impl Default for EventsConfig {
+    fn default() -> Self {
+        Self {
+            capacity: 1000,
+            persist_interval_secs: 60,
+        }
+    }
+}
+
+fn load_events(path: &std::path::Path) -> Vec<SystemEvent> {
+    match std::fs::read(path) {
+        Ok(data) => serde_json::from_slice(&data).unwrap_or_else(|e| {
+            warn!("Ignore corrupt event history {path:?}: {e}");
+            Vec::new()
+        }),
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => Vec::new(),
+        Err(e) => {
+            warn!("Cannot read event history {path:?}: {e}");
+            Vec::new()
+        }
+    }
+}
+
+async fn persist_events(
+    (config, bus, shutdown): (GenericConfig, EventBus, SystemShutdown),
+) -> anyhow::Result<()> {
+    let events_config = config.get::<EventsConfig>("events").unwrap_or_default();
+    let path = config.root.join(EVENTS_FILE);
+    let mut persisted_revision = 0;
+    {
+        let persist_loop = std::pin::pin!(async {
+            let mut interval = tokio::time::interval(Duration::from_secs(
+                events_config.persist_interval_secs.max(1),
+            ));
+            loop {
+                interval.tick().await;
+                persist_if_changed(&bus, &path, &mut persisted_revision).await;
+            }
+        });
+        futures::future::select(persist_loop, shutdown).await;
+    }
+    // Events published during shutdown (e.g. stopped devices) are written as well
+    persist_if_changed(&bus, &path, &mut persisted_revision).await;
+    Ok(())
+}
+
+async fn persist_if_changed(bus: &EventBus, path: &PathBuf, persisted_revision: &mut u64) {
+    let (revision, events) = bus.snapshot();
+    if revision == *persisted_revision {
+        return;
+    }
+    let result = async {
+        let data = serde_json::to_vec(&events)?;
+        let tmp = path.with_extension("json.tmp");
+        tokio::fs::write(&tmp, data).await?;
+        tokio::fs::rename(&tmp, path).await?;
+        anyhow::Ok(())
+    }
+    .await;
+    match result {
+        Ok(()) => *persisted_revision = revision,
+        Err(e) => warn!("Cannot persist event history to {path:?}: {e:?}"),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use pilatus::SystemEventKind;
+
+    use super::*;
+
+    #[tokio::test]
+    async fn persisted_events_are_restored() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join(EVENTS_FILE);
+        let bus = EventBus::default();
+        bus.publish(SystemEventKind::UserAction {
+            action: "acknowledge".into(),
+            details: None,
+            user: None,
+        });
+        let mut revision = 0;
+        persist_if_changed(&bus, &path, &mut revision).await;
+        assert_eq!(1, revision);
+
+        let restored = load_events(&path);
+        assert_eq!(bus.snapshot().1, restored);
+    }
+}
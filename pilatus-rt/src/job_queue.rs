@@ -0,0 +1,571 @@
+//! Device which persists jobs of other devices and dispatches them to a worker (see [`pilatus::device::EnqueueJobMessage`])
+//!
+//! Jobs survive restarts, as each of them is stored under its own key in the device state.
+//! Failed jobs are retried with exponential backoff and become dead letters after `max_attempts`. The queue doesn't dispatch while it is paused, e.g. during maintenance.
+
+use std::{
+    collections::{HashMap, HashSet, VecDeque},
+    pin::pin,
+    sync::Arc,
+    time::Duration,
+};
+
+use anyhow::Context;
+use chrono::Utc;
+use futures::future::Either;
+use minfac::{Registered, ServiceCollection};
+use pilatus::{
+    device::{
+        ActorResult, ActorSystem, DeviceContext, DeviceId, DeviceResult, DeviceValidationContext,
+        EnqueueJobMessage, Job, JobQueueSnapshot, ListJobsMessage, PauseProducerMessage,
+        ProcessJobMessage, RetryDeadLettersMessage,
+    },
+    prelude::*,
+    EventBus, SystemEventKind, UpdateParamsMessage, UpdateParamsMessageError,
+};
+use serde::{Deserialize, Serialize};
+use tokio::sync::watch;
+use tracing::{debug, warn};
+use uuid::Uuid;
+
+pub const DEVICE_TYPE: &str = "job-queue";
+const PENDING_PREFIX: &str = "pending/";
+const DEAD_LETTER_PREFIX: &str = "dead_letter/";
+
+pub(super) fn register_services(c: &mut ServiceCollection) {
+    c.with::<(Registered<ActorSystem>, Registered<EventBus>)>()
+        .register_device(DEVICE_TYPE, validator, device);
+}
+
+#[derive(Debug, Deserialize, Serialize, Clone, PartialEq)]
+#[serde(deny_unknown_fields, default)]
+pub struct Params {
+    /// Device handling `ProcessJobMessage`. If missing, the only device handling it is used
+    worker: Option<DeviceId>,
+    /// Jobs become dead letters after this many failed attempts
+    max_attempts: u32,
+    /// Wait time after the first failure. It doubles with each further failure
+    initial_backoff_ms: u64,
+    max_backoff_ms: u64,
+    /// Time the worker may take for a single job, before the attempt counts as failed
+    timeout_ms: u64,
+    /// The oldest dead letters are dropped, once there are more
+    max_dead_letters: usize,
+}
+
+impl Default for Params {
+    fn default() -> Self {
+        Self {
+            worker: None,
+            max_attempts: 5,
+            initial_backoff_ms: 1000,
+            max_backoff_ms: 300_000,
+            timeout_ms: 60_000,
+            max_dead_letters: 1000,
+        }
+    }
+}
+
+impl Params {
+    fn backoff(&self, attempts: u32) -> chrono::Duration {
+        let factor = 1u64 << attempts.saturating_sub(1).min(32);
+        let ms = self
+            .initial_backoff_ms
+            .saturating_mul(factor)
+            .min(self.max_backoff_ms);
+        chrono::Duration::milliseconds(ms.min(i64::MAX as u64) as i64)
+    }
+}
+
+async fn validator(ctx: DeviceValidationContext<'_>) -> Result<Params, UpdateParamsMessageError> {
+    let params = ctx.params_as::<Params>()?;
+    let invalid = |path, message: &str| {
+        Err(UpdateParamsMessageError::InvalidField {
+            path,
+            message: message.to_string(),
+        })
+    };
+    if params.max_attempts == 0 {
+        return invalid("max_attempts", "must be > 0");
+    }
+    if params.initial_backoff_ms > params.max_backoff_ms {
+        return invalid("initial_backoff_ms", "must be <= max_backoff_ms");
+    }
+    if params.timeout_ms == 0 {
+        return invalid("timeout_ms", "must be > 0");
+    }
+    Ok(params)
+}
+
+#[derive(Debug, Default, Clone)]
+struct PersistedJobs {
+    pending: VecDeque<Job>,
+    dead_letters: VecDeque<Job>,
+}
+
+#[derive(Debug, Default, Clone)]
+struct Queue {
+    jobs: PersistedJobs,
+    paused: bool,
+}
+
+/// Shared by the actor and the dispatcher. Both run in the same task, so modifications don't interleave
+struct SharedQueue {
+    queue: watch::Sender<Queue>,
+    store: pilatus::device::DeviceState,
+}
+
+impl SharedQueue {
+    /// Fails if any stored job is unreadable, as the queue would otherwise drop it with the next change
+    fn load(store: pilatus::device::DeviceState) -> anyhow::Result<Self> {
+        let mut jobs = PersistedJobs::default();
+        for (key, value) in store.entries()? {
+            let target = if key.starts_with(PENDING_PREFIX) {
+                &mut jobs.pending
+            } else if key.starts_with(DEAD_LETTER_PREFIX) {
+                &mut jobs.dead_letters
+            } else {
+                continue;
+            };
+            let job = serde_json::from_value::<Job>(value)
+                .with_context(|| format!("Stored job '{key}' is unreadable"))?;
+            target.push_back(job);
+        }
+        // Keys are ordered by id. The enqueue time restores the order of the queue
+        jobs.pending.make_contiguous().sort_by_key(|x| x.enqueued);
+        jobs.dead_letters
+            .make_contiguous()
+            .sort_by_key(|x| x.enqueued);
+        Ok(Self {
+            queue: watch::channel(Queue {
+                jobs,
+                paused: false,
+            })
+            .0,
+            store,
+        })
+    }
+
+    /// Changes are only applied, if they could be persisted. They are flushed before returning,
+    /// so acknowledged jobs survive a power cut
+    fn modify<R>(&self, f: impl FnOnce(&mut Queue) -> R) -> anyhow::Result<R> {
+        let mut queue = self.queue.borrow().clone();
+        let result = f(&mut queue);
+        persist_changes(&self.store, &self.queue.borrow().jobs, &queue.jobs)?;
+        self.store.flush()?;
+        self.queue.send_replace(queue);
+        Ok(result)
+    }
+}
+
+/// Only writes changed jobs. New keys are written before old ones are removed,
+/// so a job which moves to the dead letters is never lost in between
+fn persist_changes(
+    store: &pilatus::device::DeviceState,
+    previous: &PersistedJobs,
+    current: &PersistedJobs,
+) -> anyhow::Result<()> {
+    let lists = [
+        (PENDING_PREFIX, &previous.pending, &current.pending),
+        (
+            DEAD_LETTER_PREFIX,
+            &previous.dead_letters,
+            &current.dead_letters,
+        ),
+    ];
+    for (prefix, previous, current) in lists {
+        let before: HashMap<_, _> = previous.iter().map(|job| (job.id, job)).collect();
+        for job in current {
+            if before.get(&job.id) != Some(&job) {
+                store.set(&format!("{prefix}{}", job.id), job)?;
+            }
+        }
+    }
+    for (prefix, previous, current) in lists {
+        let after: HashSet<_> = current.iter().map(|job| job.id).collect();
+        for job in previous.iter().filter(|job| !after.contains(&job.id)) {
+            store.remove(&format!("{prefix}{}", job.id))?;
+        }
+    }
+    Ok(())
+}
+
+struct DeviceState {
+    params: watch::Sender<Params>,
+    queue: Arc<SharedQueue>,
+}
+
+async fn device(
+    ctx: DeviceContext,
+    params: Params,
+    (actor_system, events): (ActorSystem, EventBus),
+) -> DeviceResult {
+    let (params, params_receiver) = watch::channel(params);
+    let queue = Arc::new(SharedQueue::load(ctx.state().clone())?);
+    let actor = actor_system
+        .register(ctx.id)
+        .add_handler(DeviceState::update_params)
+        .add_handler(DeviceState::enqueue)
+        .add_handler(DeviceState::list)
+        .add_handler(DeviceState::retry_dead_letters)
+        .add_handler(DeviceState::pause)
+        .execute(DeviceState {
+            params,
+            queue: queue.clone(),
+        });
+
+    // Jobs remain persisted for the next start
+    futures::future::select(
+        pin!(actor),
+        pin!(dispatch_loop(
+            ctx.id,
+            actor_system.clone(),
+            events,
+            queue,
+            params_receiver
+        )),
+    )
+    .await;
+    Ok(())
+}
+
+impl DeviceState {
+    async fn update_params(
+        &mut self,
+        UpdateParamsMessage { params }: UpdateParamsMessage<Params>,
+    ) -> ActorResult<UpdateParamsMessage<Params>> {
+        self.params.send_replace(params);
+        Ok(())
+    }
+
+    async fn enqueue(
+        &mut self,
+        EnqueueJobMessage { kind, payload }: EnqueueJobMessage,
+    ) -> ActorResult<EnqueueJobMessage> {
+        let job = Job {
+            id: Uuid::new_v4(),
+            kind,
+            payload,
+            enqueued: Utc::now(),
+            attempts: 0,
+            next_attempt: None,
+            last_error: None,
+        };
+        let id = job.id;
+        self.queue.modify(|q| q.jobs.pending.push_back(job))?;
+        Ok(id)
+    }
+
+    async fn list(&mut self, _msg: ListJobsMessage) -> ActorResult<ListJobsMessage> {
+        let queue = self.queue.queue.borrow();
+        Ok(JobQueueSnapshot {
+            pending: queue.jobs.pending.iter().cloned().collect(),
+            dead_letters: queue.jobs.dead_letters.iter().cloned().collect(),
+            paused: queue.paused,
+        })
+    }
+
+    async fn retry_dead_letters(
+        &mut self,
+        _msg: RetryDeadLettersMessage,
+    ) -> ActorResult<RetryDeadLettersMessage> {
+        Ok(self.queue.modify(|q| {
+            let count = q.jobs.dead_letters.len();
+            for mut job in q.jobs.dead_letters.drain(..) {
+                job.attempts = 0;
+                job.next_attempt = None;
+                q.jobs.pending.push_back(job);
+            }
+            count
+        })?)
+    }
+
+    async fn pause(
+        &mut self,
+        PauseProducerMessage { paused }: PauseProducerMessage,
+    ) -> ActorResult<PauseProducerMessage> {
+        self.queue.modify(|q| q.paused = paused)?;
+        Ok(())
+    }
+}
+
+/// Dispatches one job at a time. Jobs which are ready are processed in the order they were enqueued
+async fn dispatch_loop(
+    device_id: DeviceId,
+    actor_system: ActorSystem,
+    events: EventBus,
+    queue: Arc<SharedQueue>,
+    params: watch::Receiver<Params>,
+) {
+    let mut changes = queue.queue.subscribe();
+    loop {
+        let next = {
+            let queue = changes.borrow_and_update();
+            if queue.paused {
+                None
+            } else {
+                // None sorts before Some, so jobs without backoff come first
+                queue
+                    .jobs
+                    .pending
+                    .iter()
+                    .min_by_key(|job| job.next_attempt)
+                    .cloned()
+            }
+        };
+        let Some(job) = next else {
+            if changes.changed().await.is_err() {
+                return;
+            }
+            continue;
+        };
+        if let Some(wait) = job
+            .next_attempt
+            .and_then(|at| (at - Utc::now()).to_std().ok())
+        {
+            // Newly enqueued jobs don't have to wait for the backoff
+            let sleep = pin!(tokio::time::sleep(wait));
+            if let Either::Right((Err(_), _)) =
+                futures::future::select(sleep, pin!(changes.changed())).await
+            {
+                return;
+            }
+            continue;
+        }
+
+        let current = params.borrow().clone();
+        let result = process(&actor_system, &current, job.clone()).await;
+        match queue.modify(|q| apply_result(q, job.id, result, &current)) {
+            Ok(Some(dead)) => {
+                let message = format!(
+                    "Job {} ({}) failed {} times: {}",
+                    dead.id,
+                    dead.kind,
+                    dead.attempts,
+                    dead.last_error.as_deref().unwrap_or_default()
+                );
+                warn!("{message}");
+                events.publish(SystemEventKind::Error {
+                    source: format!("Job queue {device_id}"),
+                    message,
+                });
+            }
+            Ok(None) => {}
+            Err(e) => {
+                warn!("Cannot persist job queue: {e}");
+                // Avoids a busy loop, if the storage is unavailable
+                tokio::time::sleep(Duration::from_millis(current.initial_backoff_ms)).await;
+            }
+        }
+    }
+}
+
+async fn process(actor_system: &ActorSystem, params: &Params, job: Job) -> Result<(), String> {
+    let worker = match params.worker {
+        Some(x) => x,
+        None => {
+            let workers = actor_system.list_devices_for_message_type::<ProcessJobMessage>();
+            let mut iter = workers.into_iter();
+            match (iter.next(), iter.next()) {
+                (Some(x), None) => x,
+                (None, _) => return Err("No device handles jobs".into()),
+                (Some(_), Some(_)) => {
+                    return Err("Multiple devices handle jobs. Configure the worker".into())
+                }
+            }
+        }
+    };
+    debug!("Dispatch job {} ({}) to {worker}", job.id, job.kind);
+    let timeout = Duration::from_millis(params.timeout_ms);
+    match tokio::time::timeout(timeout, actor_system.ask(worker, ProcessJobMessage { job })).await {
+        Ok(Ok(())) => Ok(()),
+        Ok(Err(e)) => Err(format!("{e:?}")),
+        Err(_) => Err(format!("No response within {timeout:?}")),
+    }
+}
+
+/// Returns the job, if it became a dead letter
+fn apply_result(
+    queue: &mut Queue,
+    id: Uuid,
+    result: Result<(), String>,
+    params: &Params,
+) -> Option<Job> {
+    let pending = &mut queue.jobs.pending;
+    let index = pending.iter().position(|job| job.id == id)?;
+    let Err(error) = result else {
+        pending.remove(index);
+        return None;
+    };
+    let job = &mut pending[index];
+    job.attempts += 1;
+    job.last_error = Some(error);
+    if job.attempts < params.max_attempts {
+        job.next_attempt = Some(Utc::now() + params.backoff(job.attempts));
+        return None;
+    }
+    let mut job = pending.remove(index)?;
+    job.next_attempt = None;
+    let dead_letters = &mut queue.jobs.dead_letters;
+    dead_letters.push_back(job.clone());
+    while dead_letters.len() > params.max_dead_letters {
+        dead_letters.pop_front();
+    }
+    Some(job)
+}
+
+#[cfg(test)]
+mod tests {
+    use pilatus::device::DeviceStateStore;
+
+    use super::*;
+
+    async fn fail_first_attempt(
+        _state: &mut (),
+        ProcessJobMessage { job }: ProcessJobMessage,
+    ) -> ActorResult<ProcessJobMessage> {
+        if job.attempts == 0 {
+            return Err(pilatus::device::ActorError::custom(anyhow::anyhow!(
+                "Line is busy"
+            )));
+        }
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn retry_after_backoff_and_keep_jobs_after_restart() {
+        let store = DeviceStateStore::in_memory();
+        let queue_id = DeviceId::new_v4();
+        let actor_system = ActorSystem::new();
+        let worker = actor_system
+            .register(DeviceId::new_v4())
+            .add_handler(fail_first_attempt)
+            .execute(());
+        tokio::spawn(worker);
+
+        let queue = Arc::new(SharedQueue::load(store.scope(queue_id)).unwrap());
+        queue
+            .modify(|q| {
+                q.paused = true;
+                q.jobs.pending.push_back(Job {
+                    id: Uuid::new_v4(),
+                    kind: "archive".into(),
+                    payload: serde_json::json!({ "frame": 1 }),
+                    enqueued: Utc::now(),
+                    attempts: 0,
+                    next_attempt: None,
+                    last_error: None,
+                })
+            })
+            .unwrap();
+        let restarted = SharedQueue::load(store.scope(queue_id)).unwrap();
+        assert_eq!(1, restarted.queue.borrow().jobs.pending.len());
+
+        let params = Params {
+            initial_backoff_ms: 1,
+            ..Default::default()
+        };
+        let mut changes = queue.queue.subscribe();
+        let dispatcher = tokio::spawn(dispatch_loop(
+            queue_id,
+            actor_system.clone(),
+            EventBus::default(),
+            queue.clone(),
+            watch::channel(params).1,
+        ));
+        queue.modify(|q| q.paused = false).unwrap();
+        tokio::time::timeout(
+            Duration::from_secs(5),
+            changes.wait_for(|q| q.jobs.pending.is_empty()),
+        )
+        .await
+        .expect("Job must be processed")
+        .unwrap();
+        dispatcher.abort();
+
+        let persisted = SharedQueue::load(store.scope(queue_id)).unwrap();
+        let jobs = &persisted.queue.borrow().jobs;
+        assert!(jobs.pending.is_empty());
+        assert!(jobs.dead_letters.is_empty());
+        assert!(store.entries(queue_id).unwrap().is_empty());
+    }
+
+    #[test]
+    fn store_one_key_per_job_and_refuse_unreadable_jobs() {
+        let store = DeviceStateStore::in_memory();
+        let state = store.scope(DeviceId::new_v4());
+        let queue = SharedQueue::load(state.clone()).unwrap();
+        let job = |kind: &str| Job {
+            id: Uuid::new_v4(),
+            kind: kind.into(),
+            payload: serde_json::Value::Null,
+            enqueued: Utc::now(),
+            attempts: 0,
+            next_attempt: None,
+            last_error: None,
+        };
+        let (first, second) = (job("first"), job("second"));
+        queue
+            .modify(|q| {
+                q.jobs.pending.push_back(first.clone());
+                q.jobs.pending.push_back(second.clone());
+            })
+            .unwrap();
+        queue
+            .modify(|q| {
+                let dead = q.jobs.pending.pop_front().unwrap();
+                q.jobs.dead_letters.push_back(dead);
+            })
+            .unwrap();
+        let keys: HashSet<_> = state.entries().unwrap().into_keys().collect();
+        assert_eq!(
+            HashSet::from([
+                format!("{PENDING_PREFIX}{}", second.id),
+                format!("{DEAD_LETTER_PREFIX}{}", first.id)
+            ]),
+            keys
+        );
+
+        state
+            .set(&format!("{PENDING_PREFIX}{}", Uuid::new_v4()), &"garbage")
+            .unwrap();
+        assert!(SharedQueue::load(state).is_err());
+    }
+
+    #[test]
+    fn move_to_dead_letters_after_max_attempts() {
+        let params = Params {
+            max_attempts: 2,
+            max_dead_letters: 1,
+            ..Default::default()
+        };
+        assert_eq!(chrono::Duration::seconds(2), params.backoff(2));
+        let mut queue = Queue::default();
+        let ids = [Uuid::new_v4(), Uuid::new_v4()];
+        for id in ids {
+            queue.jobs.pending.push_back(Job {
+                id,
+                kind: "report".into(),
+                payload: serde_json::Value::Null,
+                enqueued: Utc::now(),
+                attempts: 0,
+                next_attempt: None,
+                last_error: None,
+            });
+            let failure = || Err("Offline".to_string());
+            assert!(apply_result(&mut queue, id, failure(), &params).is_none());
+            let dead = apply_result(&mut queue, id, failure(), &params).unwrap();
+            assert_eq!((2, None), (dead.attempts, dead.next_attempt));
+        }
+        assert!(queue.jobs.pending.is_empty());
+        assert_eq!(
+            vec![ids[1]],
+            queue
+                .jobs
+                .dead_letters
+                .iter()
+                .map(|x| x.id)
+                .collect::<Vec<_>>()
+        );
+    }
+}
@@ -1,24 +1,66 @@
+mod activation_hooks;
+mod cycle_time;
 mod device;
+mod device_state;
+mod events;
+mod job_queue;
 mod logo;
+mod maintenance;
 mod metadata_future;
+mod notifier;
+#[cfg(feature = "plugins")]
+mod plugin;
 mod recipe;
+mod remote;
+mod resource_watchdog;
 mod runtime;
+#[cfg(feature = "scripting")]
+mod scripting;
+mod self_test;
 mod shutdown;
+mod system_info;
+mod temp_dir;
+#[cfg(any(test, feature = "unstable"))]
+mod test_runtime;
+mod time_sync;
 mod tracing;
+mod user;
+mod worker_pools;
 
 pub use device::*;
 #[cfg(feature = "unstable")]
 pub use logo::create_default_logo_service;
+#[cfg(feature = "plugins")]
+pub use plugin::PluginError;
 pub use recipe::TokioFileService;
+pub use remote::connect_remote_node;
 #[cfg(feature = "unstable")]
 pub use recipe::*;
 pub use tracing::TracingState;
 
-pub use runtime::Runtime;
+pub use runtime::{Runtime, RuntimeBuilder};
+#[cfg(feature = "unstable")]
+pub use test_runtime::{TestHandles, TestRuntime};
 
 pub extern "C" fn register(collection: &mut minfac::ServiceCollection) {
+    cycle_time::register_services(collection);
     device::register_services(collection);
+    device_state::register_services(collection);
+    events::register_services(collection);
+    job_queue::register_services(collection);
     recipe::register_services(collection);
     shutdown::register_services(collection);
     logo::register_services(collection);
+    maintenance::register_services(collection);
+    resource_watchdog::register_services(collection);
+    notifier::register_services(collection);
+    self_test::register_services(collection);
+    system_info::register_services(collection);
+    temp_dir::register_services(collection);
+    time_sync::register_services(collection);
+    user::register_services(collection);
+    remote::register_services(collection);
+    worker_pools::register_services(collection);
+    #[cfg(feature = "scripting")]
+    scripting::register_services(collection);
 }
@@ -7,6 +7,7 @@ use pilatus::{
 
 pub(super) fn register_services(c: &mut ServiceCollection) {
     c.register(create_fallback_logo);
+    c.register_instance(pilatus::ServiceInfo::transient::<FallbackLogo>());
     c.with::<(Registered<FallbackLogo>, Registered<GenericConfig>)>()
         .register_shared(|(fallback, generic)| {
             let (main, themes) =
@@ -14,6 +15,7 @@ pub(super) fn register_services(c: &mut ServiceCollection) {
             Arc::new(LogoServiceImpl::new(main, themes))
         })
         .alias(|s| LogoService::new(s));
+    c.register_instance(pilatus::ServiceInfo::shared::<LogoService>());
 }
 
 fn read_logo_from_path(path: &Path) -> Option<(EncodedImage, HashMap<Name, EncodedImage>)> {
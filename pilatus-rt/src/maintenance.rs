@@ -0,0 +1,173 @@
+use std::{
+    path::{Path, PathBuf},
+    sync::Arc,
+};
+
+use futures::StreamExt;
+use minfac::{Registered, ServiceCollection};
+use pilatus::{
+    device::{ActorSystem, DeviceId, PauseProducerMessage},
+    prelude::*,
+    EventBus, GenericConfig, HealthState, MaintenanceMode, MaintenanceStatus, SystemEventKind,
+    SystemShutdown,
+};
+use tokio_stream::wrappers::WatchStream;
+use tracing::{info, warn};
+
+const STATE_FILE_NAME: &str = "maintenance.json";
+
+pub(super) fn register_services(c: &mut ServiceCollection) {
+    // Loaded before devices are started, so a restart during maintenance doesn't start the producers
+    c.with::<Registered<GenericConfig>>()
+        .register_shared(|config| Arc::new(load(&config.root)))
+        .alias(|x| MaintenanceMode::clone(&x));
+    c.register_instance(pilatus::ServiceInfo::shared::<MaintenanceMode>());
+    c.with::<(
+        Registered<GenericConfig>,
+        Registered<MaintenanceMode>,
+        Registered<ActorSystem>,
+        Registered<HealthState>,
+        Registered<EventBus>,
+        Registered<SystemShutdown>,
+    )>()
+    .register_hosted_service("Maintenance Mode", apply_maintenance_mode);
+}
+
+fn load(root: &Path) -> MaintenanceMode {
+    let status = match std::fs::read(root.join(STATE_FILE_NAME)) {
+        Ok(data) => match serde_json::from_slice::<MaintenanceStatus>(&data) {
+            Ok(status) => {
+                warn!("System is in maintenance mode since {}", status.since);
+                Some(status)
+            }
+            Err(e) => {
+                warn!("Ignore invalid {STATE_FILE_NAME}: {e}");
+                None
+            }
+        },
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => None,
+        Err(e) => {
+            warn!("Cannot read {STATE_FILE_NAME}: {e}");
+            None
+        }
+    };
+    MaintenanceMode::new(status)
+}
+
+async fn persist(path: &Path, status: Option<&MaintenanceStatus>) -> std::io::Result<()> {
+    match status {
+        Some(status) => tokio::fs::write(path, serde_json::to_vec_pretty(status)?).await,
+        None => match tokio::fs::remove_file(path).await {
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(()),
+            x => x,
+        },
+    }
+}
+
+enum Trigger {
+    Changed(Option<MaintenanceStatus>),
+    DeviceStarted(DeviceId),
+}
+
+/// Pauses producers whenever the maintenance mode is enabled or a device is started during maintenance
+async fn apply_maintenance_mode(
+    (config, maintenance, actor_system, health, events, shutdown): (
+        GenericConfig,
+        MaintenanceMode,
+        ActorSystem,
+        HealthState,
+        EventBus,
+        SystemShutdown,
+    ),
+) -> anyhow::Result<()> {
+    let path: PathBuf = config.root.join(STATE_FILE_NAME);
+    // Yields the current status first
+    let changes = WatchStream::new(maintenance.subscribe()).map(Trigger::Changed);
+    let started_devices = events.subscribe().filter_map(|e| async move {
+        match e.kind {
+            SystemEventKind::DeviceStarted { device_id, .. } => {
+                Some(Trigger::DeviceStarted(device_id))
+            }
+            _ => None,
+        }
+    });
+    let run = std::pin::pin!(async {
+        let mut triggers = std::pin::pin!(futures::stream::select(changes, started_devices));
+        let mut was_enabled = false;
+        while let Some(trigger) = triggers.next().await {
+            match trigger {
+                Trigger::Changed(status) => {
+                    let enabled = status.is_some();
+                    health.update(|r| r.maintenance = status.clone());
+                    if let Err(e) = persist(&path, status.as_ref()).await {
+                        warn!("Cannot persist maintenance mode: {e}");
+                    }
+                    if enabled != was_enabled {
+                        info!(
+                            "Maintenance mode is {}",
+                            if enabled { "enabled" } else { "disabled" }
+                        );
+                        pause_producers(&actor_system, &events, None, enabled).await;
+                        was_enabled = enabled;
+                    }
+                }
+                Trigger::DeviceStarted(device_id) if maintenance.status().is_some() => {
+                    pause_producers(&actor_system, &events, Some(device_id), true).await;
+                }
+                Trigger::DeviceStarted(_) => {}
+            }
+        }
+    });
+    futures::future::select(run, shutdown).await;
+    Ok(())
+}
+
+/// Sends [`PauseProducerMessage`] to `device` or all devices handling it. Failures are published, as the operator must not rely on a paused machine
+async fn pause_producers(
+    actor_system: &ActorSystem,
+    events: &EventBus,
+    device: Option<DeviceId>,
+    paused: bool,
+) {
+    let producers = actor_system.list_devices_for_message_type::<PauseProducerMessage>();
+    let targets = producers
+        .into_iter()
+        .filter(|id| device.map_or(true, |d| d == *id));
+    let results = futures::future::join_all(targets.map(|id| async move {
+        (
+            id,
+            actor_system.ask(id, PauseProducerMessage { paused }).await,
+        )
+    }))
+    .await;
+    for (id, result) in results {
+        if let Err(e) = result {
+            let action = if paused { "pause" } else { "resume" };
+            warn!("Cannot {action} device {id}: {e}");
+            events.publish(SystemEventKind::Error {
+                source: "maintenance".into(),
+                message: format!("Cannot {action} device {id}: {e}"),
+            });
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn keep_maintenance_after_restart() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join(STATE_FILE_NAME);
+        let mode = MaintenanceMode::default();
+        let status = mode.enable(Some("Replace belt".into()), Some("admin".into()));
+        persist(&path, Some(&status)).await.unwrap();
+
+        assert_eq!(Some(status), load(dir.path()).status());
+
+        persist(&path, None).await.unwrap();
+        persist(&path, None).await.unwrap();
+        assert_eq!(None, load(dir.path()).status());
+    }
+}
@@ -0,0 +1,310 @@
+//! Forwards system events to external channels, so operators get notified about critical events
+//!
+//! Example for the config key "notifier":
+//! ```json
+//! {
+//!   "sinks": {
+//!     "ops": { "type": "webhook", "url": "https://example.com/hook" }
+//!   },
+//!   "rules": [
+//!     { "types": ["device_stopped", "resource_exceeded"], "errors_only": true, "sinks": ["ops"] }
+//!   ]
+//! }
+//! ```
+
+use std::{
+    collections::{HashMap, HashSet},
+    sync::Arc,
+    time::{Duration, Instant},
+};
+
+use async_trait::async_trait;
+use futures::StreamExt;
+use minfac::{Registered, ServiceCollection};
+use pilatus::{device::DeviceId, EventBus, GenericConfig, SystemEvent, SystemShutdown};
+use serde::Deserialize;
+use tracing::{debug, warn};
+
+#[cfg(feature = "notify-mqtt")]
+mod mqtt;
+#[cfg(feature = "notify-smtp")]
+mod smtp;
+#[cfg(feature = "notify-webhook")]
+mod webhook;
+
+pub(super) fn register_services(c: &mut ServiceCollection) {
+    c.with::<(
+        Registered<GenericConfig>,
+        Registered<EventBus>,
+        Registered<SystemShutdown>,
+    )>()
+    .register_hosted_service("Notifier", run_notifier);
+}
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+struct NotifierConfig {
+    sinks: HashMap<String, SinkConfig>,
+    rules: Vec<RoutingRule>,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case", deny_unknown_fields)]
+#[cfg_attr(
+    not(all(
+        feature = "notify-smtp",
+        feature = "notify-webhook",
+        feature = "notify-mqtt"
+    )),
+    allow(dead_code)
+)]
+enum SinkConfig {
+    Smtp {
+        host: String,
+        port: Option<u16>,
+        username: Option<String>,
+        password: Option<String>,
+        from: String,
+        to: Vec<String>,
+    },
+    /// The event is posted as JSON
+    Webhook {
+        url: String,
+        #[serde(default)]
+        headers: HashMap<String, String>,
+    },
+    /// The event is published as JSON with QoS "at least once"
+    Mqtt {
+        host: String,
+        #[serde(default = "default_mqtt_port")]
+        port: u16,
+        topic: String,
+        client_id: Option<String>,
+    },
+}
+
+fn default_mqtt_port() -> u16 {
+    1883
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct RoutingRule {
+    /// Event types (e.g. `device_stopped`). All types match if empty
+    #[serde(default)]
+    types: Vec<String>,
+    #[serde(default)]
+    device_id: Option<DeviceId>,
+    /// Ignore events which don't indicate an error, e.g. regularly stopped devices
+    #[serde(default)]
+    errors_only: bool,
+    /// Events of the same type are not forwarded again within this duration
+    #[serde(default)]
+    cooldown_secs: u64,
+    sinks: Vec<String>,
+}
+
+impl RoutingRule {
+    fn matches(&self, event: &SystemEvent) -> bool {
+        (self.types.is_empty() || self.types.iter().any(|t| t == event.kind.name()))
+            && self
+                .device_id
+                .map_or(true, |id| event.kind.device_id() == Some(id))
+            && (!self.errors_only || event.kind.is_error())
+    }
+}
+
+#[async_trait]
+trait Sink: Send + Sync {
+    async fn send(&self, event: &SystemEvent) -> anyhow::Result<()>;
+}
+
+impl SinkConfig {
+    fn create(self) -> anyhow::Result<Arc<dyn Sink>> {
+        match self {
+            #[cfg(feature = "notify-smtp")]
+            SinkConfig::Smtp {
+                host,
+                port,
+                username,
+                password,
+                from,
+                to,
+            } => Ok(Arc::new(smtp::SmtpSink::new(
+                &host, port, username, password, &from, &to,
+            )?)),
+            #[cfg(feature = "notify-webhook")]
+            SinkConfig::Webhook { url, headers } => {
+                Ok(Arc::new(webhook::WebhookSink::new(url, headers)?))
+            }
+            #[cfg(feature = "notify-mqtt")]
+            SinkConfig::Mqtt {
+                host,
+                port,
+                topic,
+                client_id,
+            } => Ok(Arc::new(mqtt::MqttSink::new(host, port, topic, client_id))),
+            #[allow(unreachable_patterns)]
+            other => Err(anyhow::anyhow!(
+                "Sink {other:?} is not supported by this build. Enable the corresponding 'notify-*' feature of pilatus-rt"
+            )),
+        }
+    }
+}
+
+struct Router {
+    rules: Vec<RoutingRule>,
+    sinks: HashMap<String, Arc<dyn Sink>>,
+    /// Last notification per (rule index, event type)
+    last_sent: HashMap<(usize, &'static str), Instant>,
+}
+
+impl Router {
+    fn new(config: NotifierConfig) -> Self {
+        let sinks = config
+            .sinks
+            .into_iter()
+            .filter_map(|(name, sink)| match sink.create() {
+                Ok(sink) => Some((name, sink)),
+                Err(e) => {
+                    warn!("Notification sink '{name}' is disabled: {e:#}");
+                    None
+                }
+            })
+            .collect::<HashMap<_, _>>();
+        for name in config.rules.iter().flat_map(|r| &r.sinks) {
+            if !sinks.contains_key(name) {
+                warn!("Notification rule refers to unavailable sink '{name}'");
+            }
+        }
+        Self {
+            rules: config.rules,
+            sinks,
+            last_sent: HashMap::new(),
+        }
+    }
+
+    /// Names of the sinks, which should receive the event. Each sink is returned once
+    fn route(&mut self, event: &SystemEvent, now: Instant) -> HashSet<String> {
+        let mut result = HashSet::new();
+        for (i, rule) in self.rules.iter().enumerate() {
+            if !rule.matches(event) {
+                continue;
+            }
+            let key = (i, event.kind.name());
+            let cooldown = Duration::from_secs(rule.cooldown_secs);
+            if let Some(last) = self.last_sent.get(&key) {
+                if now.duration_since(*last) < cooldown {
+                    debug!("Skip notification of event {} during cooldown", event.id);
+                    continue;
+                }
+            }
+            self.last_sent.insert(key, now);
+            result.extend(rule.sinks.iter().cloned());
+        }
+        result
+    }
+}
+
+async fn run_notifier(
+    (config, bus, shutdown): (GenericConfig, EventBus, SystemShutdown),
+) -> anyhow::Result<()> {
+    let notifier_config = config.get::<NotifierConfig>("notifier").unwrap_or_default();
+    if notifier_config.rules.is_empty() {
+        return Ok(());
+    }
+    let mut router = Router::new(notifier_config);
+    let mut events = bus.subscribe().take_until(shutdown);
+
+    while let Some(event) = events.next().await {
+        let event = Arc::new(event);
+        for name in router.route(&event, Instant::now()) {
+            let Some(sink) = router.sinks.get(&name).cloned() else {
+                continue;
+            };
+            let event = event.clone();
+            // Slow sinks must not delay notifications of other sinks
+            tokio::spawn(async move {
+                if let Err(e) = sink.send(&event).await {
+                    warn!("Couldn't send event {} to sink '{name}': {e:#}", event.id);
+                }
+            });
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::Utc;
+    use pilatus::SystemEventKind;
+
+    use super::*;
+
+    fn stopped(error: Option<&str>) -> SystemEvent {
+        SystemEvent {
+            id: 0,
+            time: Utc::now(),
+            kind: SystemEventKind::DeviceStopped {
+                device_id: DeviceId::new_v4(),
+                device_type: "camera".into(),
+                error: error.map(Into::into),
+            },
+        }
+    }
+
+    fn router(rules: serde_json::Value) -> Router {
+        Router::new(serde_json::from_value(serde_json::json!({ "rules": rules })).unwrap())
+    }
+
+    #[test]
+    fn route_errors_only() {
+        let mut router = router(serde_json::json!([
+            { "types": ["device_stopped"], "errors_only": true, "sinks": ["ops"] },
+            { "sinks": ["log"] }
+        ]));
+        let now = Instant::now();
+        assert_eq!(
+            HashSet::from(["log".to_string()]),
+            router.route(&stopped(None), now)
+        );
+        assert_eq!(
+            HashSet::from(["log".to_string(), "ops".to_string()]),
+            router.route(&stopped(Some("Connection lost")), now)
+        );
+    }
+
+    #[test]
+    fn skip_during_cooldown() {
+        let mut router = router(serde_json::json!([
+            { "cooldown_secs": 60, "sinks": ["ops"] }
+        ]));
+        let now = Instant::now();
+        assert_eq!(1, router.route(&stopped(Some("1")), now).len());
+        assert!(router
+            .route(&stopped(Some("2")), now + Duration::from_secs(30))
+            .is_empty());
+        assert_eq!(
+            1,
+            router
+                .route(&stopped(Some("3")), now + Duration::from_secs(61))
+                .len()
+        );
+    }
+
+    #[test]
+    fn parse_sinks() {
+        let config: NotifierConfig = serde_json::from_value(serde_json::json!({
+            "sinks": {
+                "mail": { "type": "smtp", "host": "smtp.local", "from": "a@b.ch", "to": ["c@d.ch"] },
+                "hook": { "type": "webhook", "url": "http://localhost/hook" },
+                "mqtt": { "type": "mqtt", "host": "localhost", "topic": "pilatus/events" }
+            }
+        }))
+        .unwrap();
+        assert!(matches!(
+            config.sinks["mqtt"],
+            SinkConfig::Mqtt { port: 1883, .. }
+        ));
+    }
+}
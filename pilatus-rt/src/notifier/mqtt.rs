@@ -0,0 +1,47 @@
+use std::time::Duration;
+
+use async_trait::async_trait;
+use pilatus::SystemEvent;
+use rumqttc::{AsyncClient, MqttOptions, QoS};
+use tracing::debug;
+
+use super::Sink;
+
+pub(super) struct MqttSink {
+    client: AsyncClient,
+    topic: String,
+}
+
+impl MqttSink {
+    /// The connection is established in the background and reestablished, if it is lost
+    pub fn new(host: String, port: u16, topic: String, client_id: Option<String>) -> Self {
+        let client_id = client_id.unwrap_or_else(|| format!("pilatus-{}", uuid::Uuid::new_v4()));
+        let mut options = MqttOptions::new(client_id, host, port);
+        options.set_keep_alive(Duration::from_secs(30));
+        let (client, mut event_loop) = AsyncClient::new(options, 16);
+        tokio::spawn(async move {
+            loop {
+                if let Err(e) = event_loop.poll().await {
+                    debug!("MQTT connection failed: {e}");
+                    tokio::time::sleep(Duration::from_secs(5)).await;
+                }
+            }
+        });
+        Self { client, topic }
+    }
+}
+
+#[async_trait]
+impl Sink for MqttSink {
+    async fn send(&self, event: &SystemEvent) -> anyhow::Result<()> {
+        self.client
+            .publish(
+                &self.topic,
+                QoS::AtLeastOnce,
+                false,
+                serde_json::to_vec(event)?,
+            )
+            .await?;
+        Ok(())
+    }
+}
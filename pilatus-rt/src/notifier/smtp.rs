@@ -0,0 +1,56 @@
+use async_trait::async_trait;
+use lettre::{
+    message::Mailbox, transport::smtp::authentication::Credentials, AsyncSmtpTransport,
+    AsyncTransport, Message, Tokio1Executor,
+};
+use pilatus::SystemEvent;
+
+use super::Sink;
+
+pub(super) struct SmtpSink {
+    transport: AsyncSmtpTransport<Tokio1Executor>,
+    from: Mailbox,
+    to: Vec<Mailbox>,
+}
+
+impl SmtpSink {
+    pub fn new(
+        host: &str,
+        port: Option<u16>,
+        username: Option<String>,
+        password: Option<String>,
+        from: &str,
+        to: &[String],
+    ) -> anyhow::Result<Self> {
+        let mut builder = AsyncSmtpTransport::<Tokio1Executor>::relay(host)?;
+        if let Some(port) = port {
+            builder = builder.port(port);
+        }
+        if let Some(username) = username {
+            builder = builder.credentials(Credentials::new(username, password.unwrap_or_default()));
+        }
+        Ok(Self {
+            transport: builder.build(),
+            from: from.parse()?,
+            to: to.iter().map(|x| x.parse()).collect::<Result<_, _>>()?,
+        })
+    }
+}
+
+#[async_trait]
+impl Sink for SmtpSink {
+    async fn send(&self, event: &SystemEvent) -> anyhow::Result<()> {
+        let mut builder = Message::builder()
+            .from(self.from.clone())
+            .subject(format!("[pilatus] {}", event.kind.name()));
+        for to in &self.to {
+            builder = builder.to(to.clone());
+        }
+        let message = builder.body(format!(
+            "{}\n\nTime: {}\nEvent: {}",
+            event.kind, event.time, event.id
+        ))?;
+        self.transport.send(message).await?;
+        Ok(())
+    }
+}
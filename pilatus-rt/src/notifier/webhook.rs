@@ -0,0 +1,39 @@
+use std::{collections::HashMap, str::FromStr};
+
+use async_trait::async_trait;
+use pilatus::SystemEvent;
+use reqwest::header::{HeaderMap, HeaderName, HeaderValue};
+
+use super::Sink;
+
+pub(super) struct WebhookSink {
+    client: reqwest::Client,
+    url: String,
+}
+
+impl WebhookSink {
+    pub fn new(url: String, headers: HashMap<String, String>) -> anyhow::Result<Self> {
+        let mut header_map = HeaderMap::new();
+        for (name, value) in headers {
+            header_map.insert(HeaderName::from_str(&name)?, HeaderValue::from_str(&value)?);
+        }
+        let client = reqwest::Client::builder()
+            .default_headers(header_map)
+            .timeout(std::time::Duration::from_secs(10))
+            .build()?;
+        Ok(Self { client, url })
+    }
+}
+
+#[async_trait]
+impl Sink for WebhookSink {
+    async fn send(&self, event: &SystemEvent) -> anyhow::Result<()> {
+        self.client
+            .post(&self.url)
+            .json(event)
+            .send()
+            .await?
+            .error_for_status()?;
+        Ok(())
+    }
+}
@@ -0,0 +1,181 @@
+use std::{
+    ffi::{c_char, CStr},
+    path::Path,
+};
+
+use libloading::{Library, Symbol};
+use minfac::ServiceCollection;
+use pilatus::{
+    plugin::{
+        LoadedPlugin, ABI_VERSION_SYMBOL, PILATUS_VERSION, PILATUS_VERSION_SYMBOL,
+        PLUGIN_ABI_VERSION, REGISTER_SYMBOL, RUSTC_VERSION, RUSTC_VERSION_SYMBOL,
+    },
+    ServiceInfo,
+};
+use tracing::{error, info};
+
+#[derive(Debug, thiserror::Error)]
+pub enum PluginError {
+    #[error("Cannot load library: {0}")]
+    Load(#[from] libloading::Error),
+    #[error("Plugin uses ABI version {actual}, but {PLUGIN_ABI_VERSION} is required")]
+    AbiMismatch { actual: u32 },
+    #[error("Plugin was built against pilatus {actual}, but host uses {PILATUS_VERSION:?}")]
+    VersionMismatch { actual: String },
+    #[error("Plugin was built with {actual}, but host was built with {RUSTC_VERSION:?}")]
+    CompilerMismatch { actual: String },
+    #[error("Plugin panicked during registration")]
+    Panicked,
+}
+
+/// Loads all dynamic libraries in `dir` in alphabetical order.
+/// Invalid plugins are logged and skipped. Returned libraries must outlive the ServiceProvider
+pub(crate) fn load_plugin_dir(dir: &Path, services: &mut ServiceCollection) -> Vec<Library> {
+    let mut paths = match std::fs::read_dir(dir) {
+        Ok(x) => x
+            .filter_map(|e| e.ok().map(|e| e.path()))
+            .filter(|p| p.extension() == Some(std::env::consts::DLL_EXTENSION.as_ref()))
+            .collect::<Vec<_>>(),
+        Err(e) => {
+            error!("Cannot read plugin directory {dir:?}: {e}");
+            return Vec::new();
+        }
+    };
+    paths.sort_unstable();
+
+    paths
+        .into_iter()
+        .filter_map(|path| {
+            // Safety: Plugins are trusted code, which is checked for compatibility by the handshake
+            let library = match unsafe { Library::new(&path) } {
+                Ok(x) => x,
+                Err(e) => {
+                    error!("Skip plugin {path:?}: {e}");
+                    return None;
+                }
+            };
+            match unsafe { register_plugin(&library, &path, services) } {
+                Ok(()) => {
+                    info!("Loaded plugin {path:?}");
+                    services.register_instance(LoadedPlugin {
+                        name: path
+                            .file_stem()
+                            .map(|x| x.to_string_lossy().into_owned())
+                            .unwrap_or_default(),
+                        path,
+                    });
+                    Some(library)
+                }
+                // Registrations before the panic might reference code of the library, so it's never unloaded
+                Err(PluginError::Panicked) => {
+                    error!("Plugin {path:?} panicked during registration");
+                    Some(library)
+                }
+                Err(e) => {
+                    error!("Skip plugin {path:?}: {e}");
+                    None
+                }
+            }
+        })
+        .collect()
+}
+
+unsafe fn register_plugin(
+    library: &Library,
+    path: &Path,
+    services: &mut ServiceCollection,
+) -> Result<(), PluginError> {
+    let abi_version: Symbol<extern "C" fn() -> u32> = library.get(ABI_VERSION_SYMBOL)?;
+    check_abi_version(abi_version())?;
+
+    let pilatus_version: Symbol<extern "C" fn() -> *const c_char> =
+        library.get(PILATUS_VERSION_SYMBOL)?;
+    let rustc_version: Symbol<extern "C" fn() -> *const c_char> =
+        library.get(RUSTC_VERSION_SYMBOL)?;
+    check_versions(
+        CStr::from_ptr(pilatus_version()),
+        CStr::from_ptr(rustc_version()),
+    )?;
+
+    let register: Symbol<extern "C" fn(&mut ServiceCollection) -> bool> =
+        library.get(REGISTER_SYMBOL)?;
+    services.register_instance(ServiceInfo::registrar(format!("plugin {path:?}")));
+    if register(services) {
+        Ok(())
+    } else {
+        Err(PluginError::Panicked)
+    }
+}
+
+fn check_abi_version(actual: u32) -> Result<(), PluginError> {
+    if actual == PLUGIN_ABI_VERSION {
+        Ok(())
+    } else {
+        Err(PluginError::AbiMismatch { actual })
+    }
+}
+
+/// Rust has no stable ABI, so both the pilatus and the compiler version have to match exactly
+fn check_versions(pilatus: &CStr, rustc: &CStr) -> Result<(), PluginError> {
+    if pilatus != PILATUS_VERSION {
+        return Err(PluginError::VersionMismatch {
+            actual: pilatus.to_string_lossy().into_owned(),
+        });
+    }
+    if rustc != RUSTC_VERSION {
+        return Err(PluginError::CompilerMismatch {
+            actual: rustc.to_string_lossy().into_owned(),
+        });
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn accept_matching_versions() {
+        check_abi_version(PLUGIN_ABI_VERSION).unwrap();
+        check_versions(PILATUS_VERSION, RUSTC_VERSION).unwrap();
+    }
+
+    #[test]
+    fn reject_other_abi_version() {
+        assert!(matches!(
+            check_abi_version(PLUGIN_ABI_VERSION + 1),
+            Err(PluginError::AbiMismatch { .. })
+        ));
+    }
+
+    #[test]
+    fn reject_other_pilatus_version() {
+        assert!(matches!(
+            check_versions(c"0.0.0-other", RUSTC_VERSION),
+            Err(PluginError::VersionMismatch { actual }) if actual == "0.0.0-other"
+        ));
+    }
+
+    #[test]
+    fn reject_other_compiler_version() {
+        assert!(matches!(
+            check_versions(PILATUS_VERSION, c"rustc 1.0.0 (a59de37e9 2015-05-13)"),
+            Err(PluginError::CompilerMismatch { actual }) if actual.starts_with("rustc 1.0.0")
+        ));
+    }
+
+    #[test]
+    fn skip_files_which_are_no_libraries() {
+        let dir = tempfile::tempdir().unwrap();
+        std::fs::write(
+            dir.path()
+                .join("broken")
+                .with_extension(std::env::consts::DLL_EXTENSION),
+            b"no library",
+        )
+        .unwrap();
+        std::fs::write(dir.path().join("readme.txt"), b"ignored").unwrap();
+        let mut services = ServiceCollection::new();
+        assert!(load_plugin_dir(dir.path(), &mut services).is_empty());
+    }
+}
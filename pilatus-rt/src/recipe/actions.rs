@@ -1,9 +1,10 @@
 use std::fmt::Debug;
+use std::time::Duration;
 
 use futures::future::BoxFuture;
 
 use pilatus::{
-    device::{DeviceContext, SpawnError, WithInfallibleParamUpdate},
+    device::{DeviceContext, DeviceId, SpawnError, WithInfallibleParamUpdate},
     TransactionError, UpdateParamsMessageError,
 };
 
@@ -18,6 +19,23 @@ pub trait DeviceActions: Debug + Send + Sync {
         device_type: &str,
         ctx: DeviceContext,
     ) -> BoxFuture<Result<(), TransactionError>>;
+    /// True if the running device follows changes of the variable at `pointer` without a restart
+    fn is_live_bound(&self, device_type: &str, pointer: &str) -> bool;
+    /// Asks the running devices among `device_ids` to flush and pause or to resume file writes.
+    /// Fails if any of them didn't respond in time, as a copy could contain half written files otherwise
+    fn pause_file_writes(
+        &self,
+        _device_ids: &[DeviceId],
+        _paused: bool,
+    ) -> BoxFuture<anyhow::Result<()>> {
+        Box::pin(futures::future::ready(Ok(())))
+    }
+    /// Devices which accepted parameters from `try_apply` within the last `window`
+    fn live_updated_within(&self, _window: Duration) -> Vec<DeviceId> {
+        Vec::new()
+    }
+    /// Stops running devices, which are no longer part of the active recipe. They are not restarted
+    fn stop_devices(&self, _device_ids: &[DeviceId]) {}
 }
 
 #[derive(Debug, thiserror::Error)]
@@ -0,0 +1,208 @@
+//! Incremental synchronization of the backup folder which is used to detect and discard uncommitted file changes.
+//! Only files which differ from the backup are copied, so activation time is proportional to the changes instead of
+//! the size of all device directories.
+//!
+//! Files are copied instead of hard-linked: FileServices overwrite files in place, which would change the backup too.
+
+use std::{
+    collections::{HashMap, HashSet},
+    io::{self, ErrorKind},
+    path::{Path, PathBuf},
+    time::SystemTime,
+};
+
+use futures::TryStreamExt;
+use pilatus::{device::DeviceId, visit_directory_files};
+use tokio::fs;
+
+#[derive(Debug, Default, PartialEq, Eq)]
+pub(super) struct SyncStats {
+    pub copied: usize,
+    pub removed: usize,
+    pub unchanged: usize,
+}
+
+struct FileInfo {
+    len: u64,
+    modified: Option<SystemTime>,
+}
+
+impl FileInfo {
+    /// The backup is written after the source, so a backup which is newer and has the same size is up to date
+    fn is_backup_of(&self, source: &FileInfo) -> bool {
+        match (self.modified, source.modified) {
+            (Some(backup), Some(source_modified)) => {
+                self.len == source.len && backup > source_modified
+            }
+            _ => false,
+        }
+    }
+}
+
+/// Brings `backup_root/<device_id>` in sync with `recipe_root/<device_id>` for each device.
+/// Device directories in the backup which don't belong to any of `device_ids` are removed.
+pub(super) async fn sync_backup(
+    recipe_root: &Path,
+    backup_root: &Path,
+    device_ids: impl IntoIterator<Item = DeviceId>,
+) -> io::Result<SyncStats> {
+    let device_dirs = device_ids
+        .into_iter()
+        .map(|id| id.to_string())
+        .collect::<HashSet<_>>();
+    let mut stats = SyncStats::default();
+
+    match fs::read_dir(backup_root).await {
+        Ok(mut dir) => {
+            while let Some(entry) = dir.next_entry().await? {
+                let is_known = entry
+                    .file_name()
+                    .to_str()
+                    .map(|name| device_dirs.contains(name))
+                    .unwrap_or(false);
+                if !is_known {
+                    if entry.file_type().await?.is_dir() {
+                        fs::remove_dir_all(entry.path()).await?;
+                    } else {
+                        fs::remove_file(entry.path()).await?;
+                    }
+                    stats.removed += 1;
+                }
+            }
+        }
+        Err(e) if e.kind() == ErrorKind::NotFound => {}
+        Err(e) => return Err(e),
+    }
+
+    for device_dir in device_dirs {
+        sync_directory(
+            &recipe_root.join(&device_dir),
+            &backup_root.join(&device_dir),
+            &mut stats,
+        )
+        .await?;
+    }
+    Ok(stats)
+}
+
+async fn sync_directory(source: &Path, target: &Path, stats: &mut SyncStats) -> io::Result<()> {
+    let source_files = list_files(source).await?;
+    let mut target_files = list_files(target).await?;
+
+    if source_files.is_empty() {
+        if !target_files.is_empty() {
+            stats.removed += target_files.len();
+            fs::remove_dir_all(target).await?;
+        }
+        return Ok(());
+    }
+
+    for (relative, source_info) in source_files {
+        let target_path = target.join(&relative);
+        match target_files.remove(&relative) {
+            Some(target_info) if target_info.is_backup_of(&source_info) => {
+                stats.unchanged += 1;
+                continue;
+            }
+            Some(_) => {}
+            None => {
+                fs::create_dir_all(target_path.parent().expect("File always has a parent")).await?;
+            }
+        }
+        fs::copy(source.join(&relative), target_path).await?;
+        stats.copied += 1;
+    }
+
+    for relative in target_files.into_keys() {
+        fs::remove_file(target.join(relative)).await?;
+        stats.removed += 1;
+    }
+    Ok(())
+}
+
+/// Relative paths of all files below root. A missing root is treated as empty directory
+async fn list_files(root: &Path) -> io::Result<HashMap<PathBuf, FileInfo>> {
+    let mut result = HashMap::new();
+    let mut files = std::pin::pin!(visit_directory_files(root));
+    loop {
+        let entry = match files.try_next().await {
+            Ok(Some(entry)) => entry,
+            Ok(None) => break,
+            Err(e) if e.kind() == ErrorKind::NotFound && result.is_empty() => break,
+            Err(e) => return Err(e),
+        };
+        let path = entry.path();
+        let meta = entry.metadata().await?;
+        let relative = path
+            .strip_prefix(root)
+            .map_err(|e| io::Error::new(ErrorKind::Other, e))?
+            .to_path_buf();
+        result.insert(
+            relative,
+            FileInfo {
+                len: meta.len(),
+                modified: meta.modified().ok(),
+            },
+        );
+    }
+    Ok(result)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn copies_only_changed_files() -> anyhow::Result<()> {
+        let dir = tempfile::tempdir()?;
+        let backup = dir.path().join("backup");
+        let device_id = DeviceId::new_v4();
+        let removed_device_id = DeviceId::new_v4();
+        let device_dir = dir.path().join(device_id.to_string());
+        fs::create_dir_all(device_dir.join("sub")).await?;
+        fs::write(device_dir.join("a.txt"), "a").await?;
+        fs::write(device_dir.join("sub/b.txt"), "b").await?;
+        fs::create_dir_all(backup.join(removed_device_id.to_string())).await?;
+        fs::write(backup.join(removed_device_id.to_string()).join("x"), "x").await?;
+
+        let stats = sync_backup(dir.path(), &backup, [device_id, DeviceId::new_v4()]).await?;
+        assert_eq!(
+            SyncStats {
+                copied: 2,
+                removed: 1,
+                unchanged: 0
+            },
+            stats
+        );
+        assert!(!backup.join(removed_device_id.to_string()).exists());
+
+        fs::write(device_dir.join("a.txt"), "changed").await?;
+        fs::remove_file(device_dir.join("sub/b.txt")).await?;
+        let stats = sync_backup(dir.path(), &backup, [device_id]).await?;
+        assert_eq!(
+            SyncStats {
+                copied: 1,
+                removed: 1,
+                unchanged: 0
+            },
+            stats
+        );
+        let backup_dir = backup.join(device_id.to_string());
+        assert_eq!(
+            "changed",
+            fs::read_to_string(backup_dir.join("a.txt")).await?
+        );
+        assert!(!backup_dir.join("sub/b.txt").exists());
+
+        let stats = sync_backup(dir.path(), &backup, [device_id]).await?;
+        assert_eq!(
+            SyncStats {
+                copied: 0,
+                removed: 0,
+                unchanged: 1
+            },
+            stats
+        );
+        Ok(())
+    }
+}
@@ -6,7 +6,7 @@ use std::{
 use anyhow::anyhow;
 use async_trait::async_trait;
 use futures::{io::Cursor, pin_mut, StreamExt};
-use pilatus::{EntryWriter, RecipeExporterTrait, RecipeId};
+use pilatus::{EntryWriter, ManifestEntryWriter, RecipeExporterTrait, RecipeId};
 use tokio::fs;
 
 use super::RecipeServiceFassade;
@@ -18,8 +18,9 @@ impl RecipeExporterTrait for RecipeServiceFassade {
     async fn export<'a>(
         &self,
         recipe_id: RecipeId,
-        mut writer: Box<dyn EntryWriter>,
+        writer: Box<dyn EntryWriter>,
     ) -> anyhow::Result<()> {
+        let mut writer = ManifestEntryWriter::new_boxed(writer);
         let recipes_service = self.recipe_service_read().await;
         let recipes = &recipes_service.recipes;
         let recipe = recipes.get_with_id_or_error(&recipe_id)?;
@@ -29,40 +30,47 @@ impl RecipeExporterTrait for RecipeServiceFassade {
         //write json data
         let filename = format!("{recipe_id}/recipe.json");
 
-        writer
-            .insert(filename, &mut Cursor::new(recipe_string.as_bytes()))
-            .await?;
-
         let recipe_dir_path = self.recipe_dir_path();
         let recipe_id_str = recipe_id.to_string();
         let output_path_base = Path::new(&recipe_id_str);
         let mut used_variable_names = HashSet::new();
+        // Collected in advance, so the number of entries is known for progress reporting
+        let mut files = Vec::new();
         for (&device_id, config) in recipe.devices.iter_unordered() {
             used_variable_names.extend(config.params.variables_names());
             let path = recipe_dir_path.join(device_id.to_string());
             if let Ok(meta) = fs::metadata(&path).await {
                 if meta.is_dir() {
-                    let files = super::visit_directory_files(path.clone());
-                    pin_mut!(files);
-                    while let Some(file) = files.next().await {
+                    let device_files = super::visit_directory_files(path.clone());
+                    pin_mut!(device_files);
+                    while let Some(file) = device_files.next().await {
                         let filename_full_path = file?.path();
                         let entry_path = output_path_base
                             .join(filename_full_path.strip_prefix(recipe_dir_path)?)
                             .to_str()
                             .ok_or_else(|| anyhow!("invalid UTF-8"))?
                             .to_owned();
-                        writer
-                            .insert(
-                                entry_path,
-                                &mut tokio_util::compat::TokioAsyncReadCompatExt::compat(
-                                    fs::File::open(filename_full_path).await?,
-                                ),
-                            )
-                            .await?;
+                        files.push((filename_full_path, entry_path));
                     }
                 }
             }
         }
+        // recipe.json, files and variables.json
+        writer.announce_entries(files.len() + 2);
+        writer
+            .insert(filename, &mut Cursor::new(recipe_string.as_bytes()))
+            .await?;
+        for (filename_full_path, entry_path) in files {
+            let file = fs::File::open(filename_full_path).await?;
+            let size = file.metadata().await?.len();
+            writer
+                .insert_sized(
+                    entry_path,
+                    size,
+                    &mut tokio_util::compat::TokioAsyncReadCompatExt::compat(file),
+                )
+                .await?;
+        }
         let variables = recipes.as_ref();
         let variable_map = used_variable_names
             .into_iter()
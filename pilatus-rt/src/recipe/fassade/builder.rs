@@ -18,6 +18,32 @@ impl RecipeServiceFassadeBuilder {
         self
     }
 
+    pub fn with_unlock_token(mut self, token: impl Into<String>) -> RecipeServiceFassadeBuilder {
+        self.recipe_builder = self.recipe_builder.with_unlock_token(Some(token.into()));
+        self
+    }
+
+    pub fn with_production(mut self, production: bool) -> RecipeServiceFassadeBuilder {
+        self.recipe_builder = self.recipe_builder.with_production(production);
+        self
+    }
+
+    pub fn with_machine_capacity(
+        mut self,
+        capacity: pilatus::MachineCapacity,
+    ) -> RecipeServiceFassadeBuilder {
+        self.recipe_builder = self.recipe_builder.with_machine_capacity(capacity);
+        self
+    }
+
+    pub fn with_maintenance(
+        mut self,
+        maintenance: pilatus::MaintenanceMode,
+    ) -> RecipeServiceFassadeBuilder {
+        self.recipe_builder = self.recipe_builder.with_maintenance(maintenance);
+        self
+    }
+
     pub fn replace_permissioner(
         mut self,
         s: Arc<dyn DeviceActions>,
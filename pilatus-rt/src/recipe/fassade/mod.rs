@@ -1,15 +1,19 @@
 use std::path::Path;
 use std::sync::Arc;
+use std::time::Duration;
 
 use async_trait::async_trait;
 use futures::stream::BoxStream;
 use minfac::{Registered, ServiceCollection};
 use pilatus::device::ActiveState;
 use pilatus::{
-    device::DeviceId, Name, ParameterUpdate, Recipe, RecipeId, RecipeMetadata, RecipeService,
-    RecipeServiceTrait, TransactionError, TransactionOptions,
+    device::DeviceId, ApprovalState, DeviceConfig, DeviceGroupId, Name, ParameterUpdate,
+    ParamsPreview, Recipe, RecipeId, RecipeMetadata, RecipeService, RecipeServiceTrait,
+    RecipeStats, TransactionError, TransactionOptions, Variables, VariablesPatch,
+};
+use pilatus::{
+    FileServiceBuilder, MaintenanceError, MaintenanceMode, RecipeExporter, RecipeImporter,
 };
-use pilatus::{FileServiceBuilder, RecipeExporter, RecipeImporter};
 use tokio::sync::{RwLockReadGuard, RwLockWriteGuard};
 use uuid::Uuid;
 
@@ -25,11 +29,14 @@ pub(super) fn register_services(c: &mut ServiceCollection) {
     c.with::<Registered<Arc<RecipeServiceAccessor>>>()
         .register(|recipe_service| Arc::new(RecipeServiceFassade { recipe_service }))
         .alias(|x| x as RecipeService);
+    c.register_instance(pilatus::ServiceInfo::transient::<RecipeService>());
     c.with::<Registered<Arc<RecipeServiceFassade>>>()
         .register(|x| x as RecipeExporter);
+    c.register_instance(pilatus::ServiceInfo::transient::<RecipeExporter>());
 
     c.with::<Registered<Arc<RecipeServiceFassade>>>()
         .register(|x| Box::new(RecipeImporterImpl(x)) as RecipeImporter);
+    c.register_instance(pilatus::ServiceInfo::transient::<RecipeImporter>());
 }
 
 #[derive(Clone, Debug)]
@@ -52,11 +59,53 @@ impl RecipeServiceFassade {
     ) -> RecipeDataService<RwLockWriteGuard<pilatus::Recipes>> {
         self.recipe_service.write().await
     }
+    /// Like `recipe_service_write`, but fails while the system is in maintenance mode.
+    /// The mode is checked with the lock held, so it can't be enabled while waiting for the lock
+    pub async fn recipe_service_mutation(
+        &self,
+    ) -> Result<RecipeDataService<RwLockWriteGuard<pilatus::Recipes>>, MaintenanceError> {
+        let s = self.recipe_service.write().await;
+        self.maintenance().ensure_inactive()?;
+        Ok(s)
+    }
+
+    pub fn maintenance(&self) -> &MaintenanceMode {
+        &self.recipe_service.maintenance
+    }
+
     pub fn recipe_dir_path(&self) -> &Path {
         &self.recipe_service.path
     }
+
+    pub(super) fn temp_dir_path(&self) -> &Path {
+        &self.recipe_service.temp_dir
+    }
     pub(super) fn build_file_service(&self) -> FileServiceBuilder {
-        TokioFileService::builder(self.recipe_dir_path())
+        TokioFileService::versioned_builder(
+            self.recipe_dir_path(),
+            self.recipe_service.file_versions,
+        )
+    }
+
+    /// Restores the committed parameters of a device which failed to start with uncommitted ones.
+    /// The reason is recorded in the change history of the recipe
+    pub async fn rollback_failed_device(
+        &self,
+        recipe_id: RecipeId,
+        device_id: DeviceId,
+        reason: String,
+        window: Duration,
+    ) -> Result<(DeviceConfig, Variables), TransactionError> {
+        let options = TransactionOptions::default()
+            .with_author("pilatus")
+            .with_message(reason);
+        let mut s = self.recipe_service_mutation().await?;
+        let restored = s
+            .rollback_failed_device(&recipe_id, device_id, window)
+            .await?;
+        s.annotate(&recipe_id, &options)?;
+        s.commit(options.key).await?;
+        Ok(restored)
     }
 }
 
@@ -66,8 +115,9 @@ impl RecipeServiceTrait for RecipeServiceFassade {
         &self,
         options: TransactionOptions,
     ) -> Result<(RecipeId, Recipe), TransactionError> {
-        let mut s = self.recipe_service_write().await;
+        let mut s = self.recipe_service_mutation().await?;
         let r = s.add_new_default_recipe().await?;
+        s.annotate(&r.0, &options)?;
         s.commit(options.key).await?;
         Ok(r)
     }
@@ -78,8 +128,11 @@ impl RecipeServiceTrait for RecipeServiceFassade {
         data: RecipeMetadata,
         options: TransactionOptions,
     ) -> Result<(), TransactionError> {
-        let mut s = self.recipe_service_write().await;
+        let mut s = self.recipe_service_mutation().await?;
+        let new_id = data.new_id.clone();
+        s.ensure_editable(&id, &options)?;
         s.update_recipe_metadata(id, data).await?;
+        s.annotate_edit(&new_id, &options)?;
         s.commit(options.key).await?;
         Ok(())
     }
@@ -89,7 +142,8 @@ impl RecipeServiceTrait for RecipeServiceFassade {
         recipe_id: RecipeId,
         options: TransactionOptions,
     ) -> Result<(), TransactionError> {
-        let mut s = self.recipe_service_write().await;
+        let mut s = self.recipe_service_mutation().await?;
+        s.ensure_editable(&recipe_id, &options)?;
         s.delete_recipe(recipe_id).await?;
         s.commit(options.key).await?;
         Ok(())
@@ -100,8 +154,9 @@ impl RecipeServiceTrait for RecipeServiceFassade {
         recipe_id: RecipeId,
         options: TransactionOptions,
     ) -> Result<(RecipeId, Recipe), TransactionError> {
-        let mut s = self.recipe_service_write().await;
+        let mut s = self.recipe_service_mutation().await?;
         let r = s.duplicate_recipe(recipe_id).await?;
+        s.annotate(&r.0, &options)?;
         s.commit(options.key).await?;
         Ok(r)
     }
@@ -115,8 +170,22 @@ impl RecipeServiceTrait for RecipeServiceFassade {
         id: RecipeId,
         options: TransactionOptions,
     ) -> Result<(), TransactionError> {
-        let mut s = self.recipe_service_write().await;
-        s.activate_recipe(id).await?;
+        let mut s = self.recipe_service_mutation().await?;
+        s.activate_recipe(id.clone()).await?;
+        s.annotate(&id, &options)?;
+        s.commit(options.key).await?;
+        Ok(())
+    }
+
+    async fn update_recipe_approval_with(
+        &self,
+        recipe_id: RecipeId,
+        state: ApprovalState,
+        options: TransactionOptions,
+    ) -> Result<(), TransactionError> {
+        let mut s = self.recipe_service_mutation().await?;
+        s.update_recipe_approval(&recipe_id, state, &options)?;
+        s.annotate(&recipe_id, &options)?;
         s.commit(options.key).await?;
         Ok(())
     }
@@ -128,24 +197,65 @@ impl RecipeServiceTrait for RecipeServiceFassade {
         values: ParameterUpdate,
         options: TransactionOptions,
     ) -> Result<(), TransactionError> {
-        let mut s = self.recipe_service_write().await;
-        s.update_device_params(recipe_id, device_id, values, &options)
+        let mut s = self.recipe_service_mutation().await?;
+        s.ensure_editable(&recipe_id, &options)?;
+        s.update_device_params(recipe_id.clone(), device_id, values, &options)
             .await?;
+        s.annotate_edit(&recipe_id, &options)?;
+        s.commit(options.key).await?;
+        Ok(())
+    }
+
+    async fn update_variables_with(
+        &self,
+        variables: VariablesPatch,
+        options: TransactionOptions,
+    ) -> Result<(), TransactionError> {
+        let mut s = self.recipe_service_mutation().await?;
+        let affected = s.ensure_variables_editable(&variables, &options)?;
+        s.update_variables(variables, options.origin()).await?;
+        for recipe_id in affected {
+            s.annotate_edit(&recipe_id, &options)?;
+        }
         s.commit(options.key).await?;
         Ok(())
     }
 
-    async fn restore_active_with(&self, transaction_key: Uuid) -> Result<(), TransactionError> {
-        let mut s = self.recipe_service_write().await;
+    async fn preview_device_params(
+        &self,
+        recipe_id: RecipeId,
+        device_id: DeviceId,
+        values: ParameterUpdate,
+    ) -> Result<ParamsPreview, TransactionError> {
+        self.recipe_service_read()
+            .await
+            .preview_device_params(&recipe_id, device_id, values)
+            .await
+    }
+
+    async fn restore_active_with(
+        &self,
+        options: TransactionOptions,
+    ) -> Result<(), TransactionError> {
+        let mut s = self.recipe_service_mutation().await?;
+        let recipe_id = s.recipes.active().0;
+        s.ensure_editable(&recipe_id, &options)?;
         s.restore_active().await?;
-        s.commit(transaction_key).await?;
+        s.annotate_edit(&recipe_id, &options)?;
+        s.commit(options.key).await?;
         Ok(())
     }
 
-    async fn commit_active_with(&self, transaction_key: Uuid) -> Result<(), TransactionError> {
-        let mut s = self.recipe_service_write().await;
+    async fn commit_active_with(
+        &self,
+        options: TransactionOptions,
+    ) -> Result<(), TransactionError> {
+        let mut s = self.recipe_service_mutation().await?;
+        let recipe_id = s.recipes.active().0;
+        s.ensure_editable(&recipe_id, &options)?;
         s.commit_active().await?;
-        s.commit(transaction_key).await?;
+        s.annotate_edit(&recipe_id, &options)?;
+        s.commit(options.key).await?;
         Ok(())
     }
 
@@ -155,21 +265,29 @@ impl RecipeServiceTrait for RecipeServiceFassade {
         device_id: DeviceId,
         options: TransactionOptions,
     ) -> Result<(), TransactionError> {
-        let mut s = self.recipe_service.write().await;
-        s.delete_device(recipe_id, device_id).await?;
+        let mut s = self.recipe_service_mutation().await?;
+        s.ensure_editable(&recipe_id, &options)?;
+        s.ensure_device_unlocked(&recipe_id, device_id, &options)?;
+        s.delete_device(recipe_id.clone(), device_id).await?;
+        s.annotate_edit(&recipe_id, &options)?;
         s.commit(options.key).await?;
+        self.recipe_service.disk_sizes.invalidate(device_id);
         Ok(())
     }
 
-    async fn restore_committed(
+    async fn restore_committed_with(
         &self,
         recipe_id: RecipeId,
         device_id: DeviceId,
-        transaction_key: Uuid,
+        options: TransactionOptions,
     ) -> Result<(), TransactionError> {
-        let mut s = self.recipe_service_write().await;
-        s.restore_committed(recipe_id, device_id).await?;
-        s.commit(transaction_key).await?;
+        let mut s = self.recipe_service_mutation().await?;
+        s.ensure_editable(&recipe_id, &options)?;
+        s.ensure_device_unlocked(&recipe_id, device_id, &options)?;
+        s.restore_committed(recipe_id.clone(), device_id, options.origin())
+            .await?;
+        s.annotate_edit(&recipe_id, &options)?;
+        s.commit(options.key).await?;
         Ok(())
     }
 
@@ -180,8 +298,138 @@ impl RecipeServiceTrait for RecipeServiceFassade {
         name: Name,
         options: TransactionOptions,
     ) -> Result<(), TransactionError> {
-        let mut s = self.recipe_service_write().await;
-        s.update_device_name(recipe_id, device_id, name).await?;
+        let mut s = self.recipe_service_mutation().await?;
+        s.ensure_editable(&recipe_id, &options)?;
+        s.ensure_device_unlocked(&recipe_id, device_id, &options)?;
+        s.update_device_name(recipe_id.clone(), device_id, name)
+            .await?;
+        s.annotate_edit(&recipe_id, &options)?;
+        s.commit(options.key).await?;
+        Ok(())
+    }
+
+    async fn update_device_simulated_with(
+        &self,
+        recipe_id: RecipeId,
+        device_id: DeviceId,
+        simulated: bool,
+        options: TransactionOptions,
+    ) -> Result<(), TransactionError> {
+        let mut s = self.recipe_service_mutation().await?;
+        s.ensure_editable(&recipe_id, &options)?;
+        s.ensure_device_unlocked(&recipe_id, device_id, &options)?;
+        s.update_device_simulated(recipe_id.clone(), device_id, simulated)
+            .await?;
+        s.annotate_edit(&recipe_id, &options)?;
+        s.commit(options.key).await?;
+        Ok(())
+    }
+
+    async fn update_device_enabled_with(
+        &self,
+        recipe_id: RecipeId,
+        device_id: DeviceId,
+        enabled: bool,
+        options: TransactionOptions,
+    ) -> Result<(), TransactionError> {
+        let mut s = self.recipe_service_mutation().await?;
+        s.ensure_editable(&recipe_id, &options)?;
+        s.ensure_device_unlocked(&recipe_id, device_id, &options)?;
+        s.update_device_enabled(recipe_id.clone(), device_id, enabled)
+            .await?;
+        s.annotate_edit(&recipe_id, &options)?;
+        s.commit(options.key).await?;
+        Ok(())
+    }
+
+    async fn update_device_locked_with(
+        &self,
+        recipe_id: RecipeId,
+        device_id: DeviceId,
+        locked: bool,
+        options: TransactionOptions,
+    ) -> Result<(), TransactionError> {
+        let mut s = self.recipe_service_mutation().await?;
+        s.ensure_editable(&recipe_id, &options)?;
+        s.update_device_locked(recipe_id.clone(), device_id, locked, &options)
+            .await?;
+        s.annotate_edit(&recipe_id, &options)?;
+        s.commit(options.key).await?;
+        Ok(())
+    }
+
+    async fn update_device_notes_with(
+        &self,
+        recipe_id: RecipeId,
+        device_id: DeviceId,
+        notes: String,
+        options: TransactionOptions,
+    ) -> Result<(), TransactionError> {
+        let mut s = self.recipe_service_mutation().await?;
+        s.ensure_editable(&recipe_id, &options)?;
+        s.ensure_device_unlocked(&recipe_id, device_id, &options)?;
+        s.update_device_notes(recipe_id.clone(), device_id, notes)
+            .await?;
+        s.annotate_edit(&recipe_id, &options)?;
+        s.commit(options.key).await?;
+        Ok(())
+    }
+
+    async fn add_device_group_with(
+        &self,
+        recipe_id: RecipeId,
+        name: Name,
+        options: TransactionOptions,
+    ) -> Result<DeviceGroupId, TransactionError> {
+        let mut s = self.recipe_service_mutation().await?;
+        s.ensure_editable(&recipe_id, &options)?;
+        let id = s.add_device_group(&recipe_id, name)?;
+        s.annotate_edit(&recipe_id, &options)?;
+        s.commit(options.key).await?;
+        Ok(id)
+    }
+
+    async fn rename_device_group_with(
+        &self,
+        recipe_id: RecipeId,
+        group_id: DeviceGroupId,
+        name: Name,
+        options: TransactionOptions,
+    ) -> Result<(), TransactionError> {
+        let mut s = self.recipe_service_mutation().await?;
+        s.ensure_editable(&recipe_id, &options)?;
+        s.rename_device_group(&recipe_id, group_id, name)?;
+        s.annotate_edit(&recipe_id, &options)?;
+        s.commit(options.key).await?;
+        Ok(())
+    }
+
+    async fn delete_device_group_with(
+        &self,
+        recipe_id: RecipeId,
+        group_id: DeviceGroupId,
+        options: TransactionOptions,
+    ) -> Result<(), TransactionError> {
+        let mut s = self.recipe_service_mutation().await?;
+        s.ensure_editable(&recipe_id, &options)?;
+        s.delete_device_group(&recipe_id, group_id)?;
+        s.annotate_edit(&recipe_id, &options)?;
+        s.commit(options.key).await?;
+        Ok(())
+    }
+
+    async fn update_device_group_with(
+        &self,
+        recipe_id: RecipeId,
+        device_id: DeviceId,
+        group_id: Option<DeviceGroupId>,
+        options: TransactionOptions,
+    ) -> Result<(), TransactionError> {
+        let mut s = self.recipe_service_mutation().await?;
+        s.ensure_editable(&recipe_id, &options)?;
+        s.ensure_device_unlocked(&recipe_id, device_id, &options)?;
+        s.update_device_group(&recipe_id, device_id, group_id)?;
+        s.annotate_edit(&recipe_id, &options)?;
         s.commit(options.key).await?;
         Ok(())
     }
@@ -189,6 +437,34 @@ impl RecipeServiceTrait for RecipeServiceFassade {
     fn get_update_receiver(&self) -> BoxStream<'static, Uuid> {
         self.recipe_service.get_update_receiver()
     }
+
+    async fn stats(&self) -> Result<Vec<RecipeStats>, TransactionError> {
+        let mut stats = {
+            let s = self.recipe_service_read().await;
+            let active_id = s.recipes.active().0;
+            s.recipes
+                .iter_without_backup()
+                .map(|(id, recipe)| {
+                    let devices = recipe.devices.keys().copied().collect::<Vec<_>>();
+                    let stats = RecipeStats::from_recipe(id.clone(), recipe, id == &active_id);
+                    (stats, devices)
+                })
+                .collect::<Vec<_>>()
+        };
+        // Sizes are calculated without holding the lock, so slow disks don't block recipe changes
+        for (stats, devices) in stats.iter_mut() {
+            for device_id in devices {
+                stats.disk_size += self
+                    .recipe_service
+                    .disk_sizes
+                    .device_size(self.recipe_dir_path(), *device_id)
+                    .await?;
+            }
+        }
+        let mut stats = stats.into_iter().map(|(x, _)| x).collect::<Vec<_>>();
+        stats.sort_by(|a, b| a.id.cmp(&b.id));
+        Ok(stats)
+    }
 }
 
 #[cfg(any(test, feature = "unstable"))]
@@ -4,23 +4,40 @@ use std::{
     sync::Arc,
 };
 
+use bytes::Bytes;
+use chrono::{TimeZone, Utc};
 use futures::{
     stream::{self, BoxStream},
     Stream, StreamExt, TryStreamExt,
 };
 use minfac::{Registered, ServiceCollection};
+use notify::{
+    event::{CreateKind, ModifyKind, RemoveKind, RenameMode},
+    EventKind, RecursiveMode, Watcher,
+};
 use pilatus::{
-    FileServiceBuilder, FileServiceTrait, RelativeDirectoryPath, RelativeDirectoryPathBuf,
-    RelativeFilePath, TransactionError,
+    FileEvent, FileListEntry, FileListQuery, FilePage, FileServiceBuilder, FileServiceTrait,
+    FileVersion, RelativeDirectoryPath, RelativeDirectoryPathBuf, RelativeFilePath,
+    TransactionError,
+};
+use tokio::{
+    fs,
+    io::{AsyncReadExt, AsyncWriteExt},
 };
-use tokio::{fs, io::AsyncReadExt};
 use tracing::trace;
+use uuid::Uuid;
+
+use super::{path_lock, RecipeServiceFassade};
 
-use super::RecipeServiceFassade;
+/// Sibling of the device directories
+pub(super) const VERSIONS_DIR: &str = "versions";
+/// Content is written into a temporary file next to the target first, which is then renamed atomically
+const TMP_PREFIX: &str = ".pilatus-tmp-";
 
 pub(super) fn register_services(c: &mut ServiceCollection) {
     c.with::<Registered<Arc<RecipeServiceFassade>>>()
         .register(|r| r.build_file_service());
+    c.register_instance(pilatus::ServiceInfo::transient::<pilatus::FileServiceBuilder>());
 }
 
 #[async_trait::async_trait]
@@ -49,9 +66,39 @@ impl FileServiceTrait for TokioFileService {
         data: &[u8],
     ) -> Result<(), anyhow::Error> {
         trace!(filename = ?file_path, "Create file unchecked");
-        self.get_or_create_directory(file_path.relative_dir())
+        self.write_stream(
+            file_path,
+            stream::once(async { Ok(Bytes::copy_from_slice(data)) }).boxed(),
+        )
+        .await
+    }
+
+    async fn write_stream(
+        &mut self,
+        file_path: &RelativeFilePath,
+        mut data: BoxStream<'_, std::io::Result<Bytes>>,
+    ) -> Result<(), anyhow::Error> {
+        let target = self.get_filepath(file_path);
+        let _lock = path_lock::lock_path(&target).await;
+        self.keep_version(file_path).await?;
+        let dir = self
+            .get_or_create_directory(file_path.relative_dir())
             .await?;
-        fs::write(self.get_filepath(file_path), data).await?;
+        let tmp = dir.join(format!("{TMP_PREFIX}{}", Uuid::new_v4()));
+        let written = async {
+            let mut file = fs::File::create(&tmp).await?;
+            while let Some(chunk) = data.try_next().await? {
+                file.write_all(&chunk).await?;
+            }
+            file.sync_all().await?;
+            drop(file);
+            fs::rename(&tmp, &target).await
+        }
+        .await;
+        if let Err(e) = written {
+            fs::remove_file(&tmp).await.ok();
+            return Err(e.into());
+        }
         Ok(())
     }
 
@@ -66,6 +113,8 @@ impl FileServiceTrait for TokioFileService {
 
     async fn remove_file(&self, filename: &RelativeFilePath) -> Result<(), TransactionError> {
         let p = self.get_filepath(filename);
+        let _lock = path_lock::lock_path(&p).await;
+        self.keep_version(filename).await?;
 
         //remove file from folder
         fs::remove_file(&p).await.map_err(|e| match e.kind() {
@@ -100,6 +149,20 @@ impl FileServiceTrait for TokioFileService {
         }
     }
 
+    async fn read_stream(
+        &self,
+        filename: &RelativeFilePath,
+    ) -> Result<BoxStream<'static, std::io::Result<Bytes>>, TransactionError> {
+        let p = self.get_filepath(filename);
+        match fs::File::open(&p).await {
+            Ok(f) => Ok(tokio_util::io::ReaderStream::new(f).boxed()),
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => {
+                Err(TransactionError::UnknownFilePath(p))
+            }
+            Err(e) => Err(e.into()),
+        }
+    }
+
     fn stream_files(
         &self,
         path: &RelativeDirectoryPath,
@@ -152,23 +215,231 @@ impl FileServiceTrait for TokioFileService {
     fn get_root(&self) -> &Path {
         &self.root
     }
+
+    async fn list_versions(
+        &self,
+        filename: &RelativeFilePath,
+    ) -> Result<Vec<FileVersion>, TransactionError> {
+        let Some(versions) = &self.versions else {
+            return Ok(Vec::new());
+        };
+        let dir = versions.root.join(filename.get_path());
+        let mut entries = match fs::read_dir(&dir).await {
+            Ok(x) => x,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(Vec::new()),
+            Err(e) => return Err(e.into()),
+        };
+        let mut result = Vec::new();
+        while let Some(entry) = entries.next_entry().await? {
+            let Some(id) = entry
+                .file_name()
+                .to_str()
+                .and_then(|x| x.parse::<u64>().ok())
+            else {
+                continue;
+            };
+            let Some(replaced) = Utc.timestamp_millis_opt(id as i64).single() else {
+                continue;
+            };
+            result.push(FileVersion {
+                id,
+                replaced,
+                size: entry.metadata().await?.len(),
+            });
+        }
+        result.sort_by(|a, b| b.id.cmp(&a.id));
+        Ok(result)
+    }
+
+    async fn restore_version(
+        &mut self,
+        filename: &RelativeFilePath,
+        version: u64,
+    ) -> Result<(), TransactionError> {
+        let Some(versions) = &self.versions else {
+            return Err(TransactionError::UnknownFilePath(
+                self.get_filepath(filename),
+            ));
+        };
+        let p = versions
+            .root
+            .join(filename.get_path())
+            .join(version.to_string());
+        // Read first, as keeping the current content could prune the restored version
+        let data = fs::read(&p)
+            .await
+            .map_err(TransactionError::from_io_producer(&p))?;
+        self.add_file_unchecked(filename, &data).await?;
+        Ok(())
+    }
+
+    fn watch(
+        &self,
+        path: &RelativeDirectoryPath,
+    ) -> BoxStream<'static, Result<FileEvent, TransactionError>> {
+        let dir = self.get_directory_path(path);
+        let (watcher, rx) = match start_watcher(&dir) {
+            Ok(x) => x,
+            Err(e) => return stream::iter([Err(e)]).boxed(),
+        };
+        // Depending on the platform, notify reports canonical paths (e.g. resolved symlinks in tempdirs)
+        let roots = [
+            self.root.clone(),
+            std::fs::canonicalize(&self.root).unwrap_or_else(|_| self.root.clone()),
+        ];
+        stream::unfold((watcher, rx), |(watcher, mut rx)| async move {
+            let event = rx.recv().await?;
+            Some((event, (watcher, rx)))
+        })
+        .flat_map(move |event| {
+            let events = match event {
+                Ok(event) => to_file_events(&roots, event).into_iter().map(Ok).collect(),
+                Err(e) => vec![Err(anyhow::Error::from(e).into())],
+            };
+            stream::iter(events)
+        })
+        .boxed()
+    }
+
+    async fn list_files_paged(
+        &self,
+        path: &RelativeDirectoryPath,
+        query: &FileListQuery,
+    ) -> Result<FilePage, TransactionError> {
+        let filter = query.filter_for(path)?;
+        let mut files = Vec::new();
+        let mut pending = vec![path.to_owned()];
+        while let Some(dir) = pending.pop() {
+            if query.recursive {
+                pending.extend(
+                    self.stream_directories(&dir)
+                        .try_collect::<Vec<_>>()
+                        .await?,
+                );
+            }
+            for file in self.list_files(&dir).await? {
+                if !filter(&file) {
+                    continue;
+                }
+                let p = self.get_filepath(&file);
+                let meta = fs::metadata(&p)
+                    .await
+                    .map_err(TransactionError::from_io_producer(&p))?;
+                files.push(FileListEntry {
+                    path: file,
+                    size: meta.len(),
+                    modified: meta.modified().map(Into::into).unwrap_or_default(),
+                });
+            }
+        }
+        query.paginate(files)
+    }
+}
+
+type WatchReceiver = tokio::sync::mpsc::UnboundedReceiver<notify::Result<notify::Event>>;
+
+fn start_watcher(
+    dir: &Path,
+) -> Result<(notify::RecommendedWatcher, WatchReceiver), TransactionError> {
+    std::fs::create_dir_all(dir)?;
+    let (tx, rx) = tokio::sync::mpsc::unbounded_channel();
+    let mut watcher = notify::recommended_watcher(move |e| {
+        let _ignore_closed_receiver = tx.send(e);
+    })
+    .map_err(anyhow::Error::from)?;
+    watcher
+        .watch(dir, RecursiveMode::Recursive)
+        .map_err(anyhow::Error::from)?;
+    Ok((watcher, rx))
+}
+
+fn to_file_events(roots: &[PathBuf], event: notify::Event) -> Vec<FileEvent> {
+    let create: fn(RelativeFilePath) -> FileEvent = match event.kind {
+        EventKind::Create(CreateKind::Folder) | EventKind::Remove(RemoveKind::Folder) => {
+            return Vec::new()
+        }
+        EventKind::Create(_)
+        | EventKind::Modify(ModifyKind::Any | ModifyKind::Data(_))
+        | EventKind::Modify(ModifyKind::Name(RenameMode::To)) => FileEvent::Changed,
+        EventKind::Remove(_) | EventKind::Modify(ModifyKind::Name(RenameMode::From)) => {
+            FileEvent::Removed
+        }
+        _ => return Vec::new(),
+    };
+    event
+        .paths
+        .iter()
+        .filter(|p| {
+            p.file_name()
+                .map(|name| !name.to_string_lossy().starts_with(TMP_PREFIX))
+                .unwrap_or(false)
+        })
+        .filter_map(|p| {
+            let relative = roots.iter().find_map(|root| p.strip_prefix(root).ok())?;
+            RelativeFilePath::new(relative).ok()
+        })
+        .map(create)
+        .collect()
 }
 
 pub struct TokioFileService {
     root: PathBuf,
+    versions: Option<FileVersions>,
 }
+
+struct FileVersions {
+    root: PathBuf,
+    keep: usize,
+}
+
 impl TokioFileService {
     pub fn builder(root: impl Into<PathBuf>) -> FileServiceBuilder {
+        Self::versioned_builder(root, 0)
+    }
+
+    /// Overwritten and removed files are kept in `{root}/versions/{device_id}`, up to `keep` versions per file
+    pub fn versioned_builder(root: impl Into<PathBuf>, keep: usize) -> FileServiceBuilder {
         let root = root.into();
         FileServiceBuilder {
             inner_factory: Arc::new(move |device_id| {
+                let device_id = device_id.to_string();
                 Box::new(TokioFileService {
-                    root: root.join(device_id.to_string()),
+                    root: root.join(&device_id),
+                    versions: (keep > 0).then(|| FileVersions {
+                        root: root.join(VERSIONS_DIR).join(&device_id),
+                        keep,
+                    }),
                 })
             }),
         }
     }
 
+    async fn keep_version(&self, file_path: &RelativeFilePath) -> std::io::Result<()> {
+        let Some(versions) = &self.versions else {
+            return Ok(());
+        };
+        let current = self.get_filepath(file_path);
+        if !fs::try_exists(&current).await? {
+            return Ok(());
+        }
+        let dir = versions.root.join(file_path.get_path());
+        fs::create_dir_all(&dir).await?;
+        let mut id = Utc::now().timestamp_millis() as u64;
+        while fs::try_exists(dir.join(id.to_string())).await? {
+            id += 1;
+        }
+        fs::copy(&current, dir.join(id.to_string())).await?;
+
+        let existing = self.list_versions(file_path).await.map_err(|e| match e {
+            TransactionError::FileSystemError(e) => e,
+            e => std::io::Error::new(std::io::ErrorKind::Other, e),
+        })?;
+        for outdated in existing.into_iter().skip(versions.keep) {
+            fs::remove_file(dir.join(outdated.id.to_string())).await?;
+        }
+        Ok(())
+    }
+
     fn stream_files_internal<T: Send + 'static>(
         &self,
         path: &RelativeDirectoryPath,
@@ -204,6 +475,9 @@ impl TokioFileService {
                             Ok(x) => x,
                             Err(e) => return Some(Err((io_err_converter)(e))),
                         };
+                        if entry.file_name().to_string_lossy().starts_with(TMP_PREFIX) {
+                            return None;
+                        }
                         let p = entry.path();
                         let p = p
                             .strip_prefix(device_dir)
@@ -220,7 +494,11 @@ impl TokioFileService {
 #[cfg(test)]
 mod tests {
     use futures::{future::BoxFuture, FutureExt};
-    use pilatus::{device::DeviceId, FileServiceExt, Validator};
+    use pilatus::{
+        device::{ActorSystem, DeviceId},
+        FileServiceExt, MoveFileMessage, RegisterFileHandlersExtension, StreamFileMessage,
+        Validator,
+    };
 
     use super::*;
     use pilatus::{FileService, RelativeDirectoryPathBuf, RelativeFilePath};
@@ -320,4 +598,308 @@ mod tests {
         }
         Ok(())
     }
+
+    #[tokio::test]
+    async fn list_files_paged_recursive_with_filter() -> anyhow::Result<()> {
+        let dir = tempfile::tempdir()?;
+        let mut svc = TokioFileService::builder(dir.path()).build(DeviceId::new_v4());
+        for file in ["a.png", "b.jpg", "sub/c.png", "sub/deep/d.png"] {
+            svc.add_file_unchecked(&RelativeFilePath::new(file)?, b"Text")
+                .await?;
+        }
+        let mut query = FileListQuery {
+            filter: Some("**/*.png".into()),
+            recursive: true,
+            limit: Some(2),
+            ..Default::default()
+        };
+        let root = RelativeDirectoryPathBuf::root();
+        let first = svc.list_files_paged(&root, &query).await?;
+        query.continuation = first.continuation;
+        let second = svc.list_files_paged(&root, &query).await?;
+        assert_eq!(None, second.continuation);
+        assert_eq!(
+            vec!["a.png", "sub/c.png", "sub/deep/d.png"],
+            first
+                .files
+                .iter()
+                .chain(&second.files)
+                .map(|f| f.path.to_string())
+                .collect::<Vec<_>>()
+        );
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn keep_limited_versions_and_restore() -> anyhow::Result<()> {
+        let dir = tempfile::tempdir()?;
+        let device_id = DeviceId::new_v4();
+        let mut svc = TokioFileService::versioned_builder(dir.path(), 2).build(device_id);
+        let file = RelativeFilePath::new("sub/calibration.json")?;
+
+        for content in ["1", "2", "3", "4"] {
+            svc.add_file_unchecked(&file, content.as_bytes()).await?;
+        }
+        let versions = svc.list_versions(&file).await?;
+        assert_eq!(2, versions.len());
+        assert_eq!(b"4".to_vec(), svc.get_file(&file).await?);
+
+        svc.restore_version(&file, versions[0].id).await?;
+        assert_eq!(b"3".to_vec(), svc.get_file(&file).await?);
+        let versions = svc.list_versions(&file).await?;
+        assert_eq!(
+            b"4".to_vec(),
+            fs::read(
+                dir.path()
+                    .join(VERSIONS_DIR)
+                    .join(device_id.to_string())
+                    .join("sub/calibration.json")
+                    .join(versions[0].id.to_string())
+            )
+            .await?
+        );
+
+        svc.remove_file(&file).await?;
+        assert_eq!(2, svc.list_versions(&file).await?.len());
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn concurrent_stream_writes_are_not_interleaved() -> anyhow::Result<()> {
+        let dir = tempfile::tempdir()?;
+        let device_id = DeviceId::new_v4();
+        let builder = TokioFileService::builder(dir.path());
+        let file = RelativeFilePath::new("large.bin")?;
+
+        let writes = (0..4u8).map(|i| {
+            let mut svc = builder.clone().build(device_id);
+            let file = file.clone();
+            async move {
+                let chunks = (0..20).map(move |_| Ok(Bytes::from(vec![i; 1000])));
+                svc.write_stream(&file, stream::iter(chunks).boxed()).await
+            }
+        });
+        futures::future::try_join_all(writes).await?;
+
+        let svc = builder.build(device_id);
+        let content = svc.get_file(&file).await?;
+        assert_eq!(20_000, content.len());
+        assert!(content.iter().all(|x| *x == content[0]));
+        assert_eq!(
+            vec![file],
+            svc.list_files(&RelativeDirectoryPathBuf::root()).await?
+        );
+        assert_eq!(1, svc.list_recursive().await?.len());
+        Ok(())
+    }
+
+    async fn next_event(
+        events: &mut BoxStream<'static, Result<FileEvent, TransactionError>>,
+    ) -> anyhow::Result<Option<FileEvent>> {
+        let event =
+            tokio::time::timeout(std::time::Duration::from_secs(5), events.try_next()).await??;
+        Ok(event)
+    }
+
+    #[tokio::test]
+    async fn watch_reports_changed_and_removed_files() -> anyhow::Result<()> {
+        let dir = tempfile::tempdir()?;
+        let mut svc = TokioFileService::builder(dir.path()).build(DeviceId::new_v4());
+        let file = RelativeFilePath::new("models/model.onnx")?;
+        let mut events = svc.watch(&RelativeDirectoryPathBuf::root());
+
+        svc.add_file_unchecked(&file, b"model").await?;
+        assert_eq!(
+            Some(FileEvent::Changed(file.clone())),
+            next_event(&mut events).await?
+        );
+        svc.remove_file(&file).await?;
+        loop {
+            match next_event(&mut events).await? {
+                Some(FileEvent::Changed(_)) => continue,
+                x => {
+                    assert_eq!(Some(FileEvent::Removed(file)), x);
+                    break;
+                }
+            }
+        }
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn without_versioning_nothing_is_kept() -> anyhow::Result<()> {
+        let dir = tempfile::tempdir()?;
+        let mut svc = TokioFileService::builder(dir.path()).build(DeviceId::new_v4());
+        let file = RelativeFilePath::new("model.onnx")?;
+        svc.add_file_unchecked(&file, b"1").await?;
+        svc.add_file_unchecked(&file, b"2").await?;
+        assert!(svc.list_versions(&file).await?.is_empty());
+        assert!(svc.restore_version(&file, 0).await.is_err());
+        Ok(())
+    }
+
+    struct CopyCtx {
+        file_service: FileService<CopyCtx>,
+    }
+
+    impl AsRef<FileService<CopyCtx>> for CopyCtx {
+        fn as_ref(&self) -> &FileService<CopyCtx> {
+            &self.file_service
+        }
+    }
+
+    impl AsMut<FileService<CopyCtx>> for CopyCtx {
+        fn as_mut(&mut self) -> &mut FileService<CopyCtx> {
+            &mut self.file_service
+        }
+    }
+
+    /// Accepts files with the given extension, if their content is not empty
+    struct NotEmpty(&'static str);
+
+    impl Validator for NotEmpty {
+        type State = CopyCtx;
+        fn is_responsible(&self, path: &RelativeFilePath) -> bool {
+            path.get_path().extension() == Some(self.0.as_ref())
+        }
+
+        fn validate<'a>(
+            &self,
+            data: &'a [u8],
+            _: &'a mut CopyCtx,
+        ) -> BoxFuture<'a, Result<(), anyhow::Error>> {
+            async move {
+                anyhow::ensure!(!data.is_empty(), "Must not be empty");
+                Ok(())
+            }
+            .boxed()
+        }
+    }
+
+    fn copy_service(dir: &Path) -> CopyCtx {
+        CopyCtx {
+            file_service: TokioFileService::builder(dir)
+                .with_validator(NotEmpty("onnx"))
+                .with_validator(NotEmpty("json"))
+                .build(DeviceId::new_v4()),
+        }
+    }
+
+    #[tokio::test]
+    async fn copy_streams_if_same_validator_is_responsible() -> anyhow::Result<()> {
+        let dir = tempfile::tempdir()?;
+        let mut svc = copy_service(dir.path());
+        let from = RelativeFilePath::new("a.onnx")?;
+        let to = RelativeFilePath::new("sub/b.onnx")?;
+        // Invalid, but the responsible validator is assumed to have accepted the source already
+        svc.as_mut().add_file_unchecked(&from, b"").await?;
+        svc.copy_file_validated(&from, &to).await?;
+        assert!(svc.file_service.get_file(&to).await?.is_empty());
+
+        let content = vec![42u8; 200_000];
+        svc.as_mut().add_file_unchecked(&from, &content).await?;
+        svc.copy_file_validated(&from, &to).await?;
+        assert_eq!(content, svc.file_service.get_file(&to).await?);
+        assert_eq!(content, svc.file_service.get_file(&from).await?);
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn copy_validates_for_other_validators() -> anyhow::Result<()> {
+        let dir = tempfile::tempdir()?;
+        let mut svc = copy_service(dir.path());
+        let empty = RelativeFilePath::new("empty.onnx")?;
+        let filled = RelativeFilePath::new("filled.onnx")?;
+        let target = RelativeFilePath::new("target.json")?;
+        svc.as_mut().add_file_unchecked(&empty, b"").await?;
+        svc.as_mut().add_file_unchecked(&filled, b"{}").await?;
+
+        svc.copy_file_validated(&empty, &target)
+            .await
+            .expect_err("Empty content is invalid for json");
+        assert!(!svc.file_service.has_file(&target).await?);
+        svc.copy_file_validated(&filled, &target).await?;
+        assert_eq!(b"{}".to_vec(), svc.file_service.get_file(&target).await?);
+        svc.copy_file_validated(&filled, &RelativeFilePath::new("other.txt")?)
+            .await
+            .expect_err("No validator is responsible for txt");
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn read_stream_in_chunks() -> anyhow::Result<()> {
+        let dir = tempfile::tempdir()?;
+        let mut svc = TokioFileService::builder(dir.path()).build(DeviceId::new_v4());
+        let file = RelativeFilePath::new("large.bin")?;
+        let content = (0..100_000u32).map(|x| x as u8).collect::<Vec<_>>();
+        svc.add_file_unchecked(&file, &content).await?;
+
+        let chunks = svc
+            .read_stream(&file)
+            .await?
+            .try_collect::<Vec<_>>()
+            .await?;
+        assert!(chunks.len() > 1);
+        assert_eq!(content, chunks.concat());
+        assert!(matches!(
+            svc.read_stream(&RelativeFilePath::new("missing.bin")?)
+                .await,
+            Err(TransactionError::UnknownFilePath(_))
+        ));
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn move_and_stream_files_of_device() -> anyhow::Result<()> {
+        let dir = tempfile::tempdir()?;
+        let actor_system = ActorSystem::new();
+        let device_id = DeviceId::new_v4();
+        let from = RelativeFilePath::new("a.onnx")?;
+        let to = RelativeFilePath::new("moved/b.onnx")?;
+        let mut state = copy_service(dir.path());
+        state.as_mut().add_file_unchecked(&from, b"model").await?;
+
+        let device = actor_system
+            .register(device_id)
+            .add_file_handlers()
+            .execute(state);
+        let asks = async {
+            actor_system
+                .ask(
+                    device_id,
+                    MoveFileMessage {
+                        from: from.clone(),
+                        to: to.clone(),
+                    },
+                )
+                .await?;
+            let moved = actor_system
+                .ask(device_id, StreamFileMessage { path: to.clone() })
+                .await?
+                .try_collect::<Vec<_>>()
+                .await?;
+            assert_eq!(b"model", &moved.concat()[..]);
+            assert!(
+                actor_system
+                    .ask(device_id, StreamFileMessage { path: from.clone() })
+                    .await
+                    .is_err(),
+                "Source is removed"
+            );
+            actor_system
+                .ask(
+                    device_id,
+                    MoveFileMessage {
+                        from: to.clone(),
+                        to: RelativeFilePath::new("other.txt")?,
+                    },
+                )
+                .await
+                .expect_err("No validator is responsible for the target");
+            actor_system.forget_senders();
+            anyhow::Ok(())
+        };
+        let (_, asks) = futures::future::join(device, asks).await;
+        asks
+    }
 }
@@ -1,5 +1,5 @@
 use std::{
-    collections::{hash_map::Entry, HashMap, HashSet},
+    collections::{hash_map::Entry, BTreeMap, HashMap, HashSet},
     ffi::OsStr,
     io,
     path::PathBuf,
@@ -14,7 +14,7 @@ use futures::{
     AsyncReadExt,
 };
 use pilatus::{
-    EntryReader,
+    EntryReader, ExportManifest, HashingReader,
     ImportRecipeError::{self, InvalidFormat},
     ImportRecipesOptions, ImporterTrait, IntoMergeStrategy, IrreversibleError, Recipe, RecipeId,
     RecipeImporterTrait, Recipes, RelativeFilePath, Variables,
@@ -69,7 +69,7 @@ impl Importer {
         mut strategy: impl MergeStrategy,
     ) -> Result<(), ImportRecipeError> {
         let service = self.service.clone();
-        let mut recipes_lock = service.recipe_service_write().await;
+        let mut recipes_lock = service.recipe_service_mutation().await?;
 
         let (active_id, _) = recipes_lock.recipes.get_active();
         let mut recipes_copy = recipes_lock.recipes.clone();
@@ -140,11 +140,22 @@ impl RecipeImporterTrait for RecipeImporterImpl {
         reader: &mut dyn EntryReader,
         options: ImportRecipesOptions,
     ) -> Result<(), ImportRecipeError> {
-        let tmp = spawn_blocking(tempfile::tempdir)
-            .await
-            .map_err(|e| ImportRecipeError::Io(e.into()))??;
+        // Fail before the archive is transferred
+        self.0.maintenance().ensure_inactive()?;
+        let temp_dir = self.0.temp_dir_path().to_path_buf();
+        let tmp = spawn_blocking(move || {
+            std::fs::create_dir_all(&temp_dir)?;
+            tempfile::Builder::new()
+                .prefix("pilatus-import-")
+                .tempdir_in(temp_dir)
+        })
+        .await
+        .map_err(|e| ImportRecipeError::Io(e.into()))??;
         let path = tmp.path().into();
-        let recipes = self.0.import_into_path(reader, path).await;
+        let recipes = self
+            .0
+            .import_into_path(reader, path, options.allow_without_manifest)
+            .await;
 
         match recipes {
             Ok((recipes, variables)) => {
@@ -167,24 +178,45 @@ impl RecipeImporterTrait for RecipeImporterImpl {
     }
 }
 impl RecipeServiceFassade {
-    async fn import_into_path(&self, r: &mut dyn EntryReader, root: PathBuf) -> ImportResult {
+    async fn import_into_path(
+        &self,
+        r: &mut dyn EntryReader,
+        root: PathBuf,
+        allow_without_manifest: bool,
+    ) -> ImportResult {
         let mut data = Vec::new();
         let mut recipes = HashMap::new();
         let mut variables: Result<Variables, _> =
             Err(InvalidFormat(anyhow!("Variables.json not found")));
+        let mut manifest = None;
+        let mut checksums = BTreeMap::new();
         const MAX_JSON_FILE_SIZE_LIMIT: usize = 100 * 1024 * 1024;
         trace!("Import into path {root:?}");
         debug_assert!(root.exists(), "Expected {root:?} to exist");
 
         while let Some(entry) = r.next().await {
-            let mut entry = entry.map_err(|e| ImportRecipeError::InvalidFormat(e.into()))?;
-            if entry.filename == "variables.json" {
+            let entry = entry.map_err(|e| ImportRecipeError::InvalidFormat(e.into()))?;
+            if entry.filename == pilatus::MANIFEST_FILENAME {
                 data.clear();
-                let consumed_bytes = entry
+                entry
                     .reader
                     .take(MAX_JSON_FILE_SIZE_LIMIT as u64)
                     .read_to_end(&mut data)
                     .await?;
+                manifest = Some(
+                    serde_json::from_slice::<ExportManifest>(&data)
+                        .map_err(|e| InvalidFormat(e.into()))?,
+                );
+                continue;
+            }
+            let entry_name = entry.filename;
+            let mut reader = HashingReader::new(entry.reader);
+            if entry_name == "variables.json" {
+                data.clear();
+                let consumed_bytes = (&mut reader)
+                    .take(MAX_JSON_FILE_SIZE_LIMIT as u64)
+                    .read_to_end(&mut data)
+                    .await?;
                 if consumed_bytes == MAX_JSON_FILE_SIZE_LIMIT {
                     warn!("Variables are too big. Max is: {MAX_JSON_FILE_SIZE_LIMIT}");
                     return Err(InvalidFormat(anyhow!(
@@ -193,9 +225,10 @@ impl RecipeServiceFassade {
                 }
 
                 variables = serde_json::from_slice(&data).map_err(|e| InvalidFormat(e.into()));
+                checksums.insert(entry_name, reader.finalize());
                 continue;
             }
-            let filename = PathBuf::from(entry.filename);
+            let filename = PathBuf::from(&entry_name);
             let mut filename_iter = filename.iter().filter_map(OsStr::to_str);
             let recipe_id = filename_iter.next().ok_or_else(|| {
                 InvalidFormat(anyhow!(
@@ -211,7 +244,7 @@ impl RecipeServiceFassade {
                 Some("recipe.json") if filename_iter.next().is_none() => {
                     let mut cursor = Cursor::new(&mut data);
                     copy(
-                        &mut entry.reader.take(MAX_JSON_FILE_SIZE_LIMIT as _),
+                        &mut (&mut reader).take(MAX_JSON_FILE_SIZE_LIMIT as _),
                         &mut cursor,
                     )
                     .await?;
@@ -237,7 +270,7 @@ impl RecipeServiceFassade {
                     trace!("Create dir all {path:?}");
                     create_dir_all(&path.parent().expect("Must exist")).await?;
                     copy(
-                        &mut entry.reader,
+                        &mut reader,
                         &mut tokio_util::compat::TokioAsyncWriteCompatExt::compat_write(
                             File::create(&path).await?,
                         ),
@@ -251,6 +284,21 @@ impl RecipeServiceFassade {
                     )));
                 }
             };
+            checksums.insert(entry_name, reader.finalize());
+        }
+
+        match manifest {
+            Some(manifest) => manifest
+                .verify(&checksums)
+                .map_err(|e| InvalidFormat(anyhow!("Integrity check failed: {e}")))?,
+            None if allow_without_manifest => {
+                warn!("Import doesn't contain a manifest. Integrity cannot be verified")
+            }
+            None => {
+                return Err(InvalidFormat(anyhow!(
+                    "Archive doesn't contain a manifest. Archives of older versions have to be imported with 'allow_without_manifest'"
+                )))
+            }
         }
 
         Ok((recipes, variables?))
@@ -1,21 +1,22 @@
 use std::any::{Any, TypeId};
-use std::collections::HashMap;
+use std::collections::{HashMap, HashSet};
 use std::fmt::{self, Debug, Formatter};
 use std::io::{self, ErrorKind};
 use std::ops::{Deref, DerefMut};
 use std::path::{Path, PathBuf};
 use std::sync::Arc;
+use std::time::Duration;
 
-use anyhow::anyhow;
 use futures::stream::BoxStream;
 use futures::{StreamExt, TryStreamExt};
 use minfac::{AllRegistered, Registered, ServiceCollection};
-use pilatus::device::{ActiveState, DeviceContext};
+use pilatus::device::{ActiveState, DeviceContext, InfallibleParamApplier, MessageOrigin};
 use pilatus::{
-    clone_directory_deep, device::DeviceId, visit_directory_files, DeviceConfig, GenericConfig,
-    InitRecipeListener, Name, ParameterUpdate, Recipe, RecipeId, RecipeMetadata, Recipes,
-    TransactionError, TransactionOptions, UntypedDeviceParamsWithVariables, VariableError,
-    Variables, VariablesPatch,
+    clone_directory_deep, device::DeviceId, visit_directory_files, ApprovalError, ApprovalState,
+    DeviceConfig, DeviceGroupId, GenericConfig, InitRecipeListener, MachineCapacity,
+    MaintenanceMode, Name, ParamChange, ParameterUpdate, ParamsPreview, Recipe, RecipeId,
+    RecipeMetadata, Recipes, TransactionError, TransactionOptions,
+    UntypedDeviceParamsWithVariables, VariableError, Variables, VariablesPatch,
 };
 use pilatus::{UncommittedChangesError, UnknownDeviceError};
 use tokio::fs::File;
@@ -26,19 +27,23 @@ use tokio::{
     io::AsyncRead,
     sync::{broadcast, RwLock},
 };
-use tracing::{debug, error, trace};
+use tracing::{debug, error, info, trace, warn};
 use uuid::Uuid;
 
 use self::recipes::RecipesExt;
+use self::service_builder::InitRecipeListeners;
 
 mod actions;
+mod backup;
 mod export;
 mod fassade;
 mod file;
 mod import;
 mod parameters;
+mod path_lock;
 mod recipes;
 mod service_builder;
+mod stats;
 
 pub use actions::*;
 pub use fassade::*;
@@ -53,16 +58,26 @@ pub(super) fn register_services(c: &mut ServiceCollection) {
         AllRegistered<InitRecipeListener>,
         Registered<Arc<dyn DeviceActions>>,
         AllRegistered<parameters::ChangeParamsStrategy>,
+        Registered<MaintenanceMode>,
     )>()
     .register_shared(
-        |(conf, initializers, device_actions, change_params_strategies)| {
-            let mut builder = RecipeServiceBuilder::new(conf.root, device_actions);
+        |(conf, initializers, device_actions, change_params_strategies, maintenance)| {
+            let mut builder = RecipeServiceBuilder::new(conf.root.clone(), device_actions)
+                .with_config(conf.clone());
             builder = initializers.fold(builder, |acc, x| acc.with_initializer(x));
             builder = change_params_strategies.fold(builder, |acc, x| acc.with_change_strategy(x));
+            builder = builder.with_unlock_token(conf.get("unlock_token").ok());
+            builder = builder.with_file_versions(conf.get("file_versions").unwrap_or_default());
+            builder = builder.with_production(conf.is_production());
+            builder =
+                builder.with_machine_capacity(conf.get("machine_capacity").unwrap_or_default());
+            builder = builder.with_maintenance(maintenance);
+            builder = builder.with_temp_dir(conf.temp_dir());
 
             Arc::new(builder.build())
         },
     );
+    c.register_instance(pilatus::ServiceInfo::shared::<Arc<RecipeServiceAccessor>>());
 
     fassade::register_services(c);
     parameters::register_services(c);
@@ -84,13 +99,23 @@ impl<X: Into<TransactionError>> From<X> for ChangeDeviceParamsTransactionError {
 }
 
 const RECIPES_FILE_NAME: &str = "recipes.json";
+/// Committed files are copied here before they replace the files of the active recipe
+const RESTORE_STAGING_DIR: &str = "restore";
 
 pub struct RecipeServiceAccessor {
     path: PathBuf,
     recipes: Arc<RwLock<Recipes>>,
     device_actions: Arc<dyn DeviceActions>,
-    listeners: Vec<InitRecipeListener>,
+    listeners: InitRecipeListeners,
+    unlock_token: Option<String>,
+    file_versions: usize,
+    production: bool,
+    capacity: MachineCapacity,
+    maintenance: MaintenanceMode,
+    /// Working directory for imports
+    temp_dir: PathBuf,
     update_sender: broadcast::Sender<Uuid>,
+    disk_sizes: stats::DiskSizeCache,
     // Can be used to update a Device with change_device_params_on_active_recipe
     // DeviceType -> fn(serde_json::Value, T) -> Result<serde_json::Value, TransactionError>>
     change_strategies: HashMap<(&'static str, TypeId), Box<dyn Any + Send + Sync>>,
@@ -99,13 +124,54 @@ pub struct RecipeServiceAccessor {
 pub struct RecipeDataService<'a, T: 'a> {
     path: &'a Path,
     recipes: T,
-    device_actions: &'a dyn DeviceActions,
-    listeners: &'a [InitRecipeListener],
+    device_actions: &'a Arc<dyn DeviceActions>,
+    listeners: &'a InitRecipeListeners,
+    unlock_token: Option<&'a str>,
+    production: bool,
+    capacity: &'a MachineCapacity,
     update_sender: &'a broadcast::Sender<Uuid>,
     change_strategies: &'a HashMap<(&'static str, TypeId), Box<dyn Any + Send + Sync>>,
 }
 
 impl<'a, T: Deref<Target = Recipes>> RecipeDataService<'a, T> {
+    /// Validates like `update_device_params` with autorepair, so the preview contains migrations as well
+    pub async fn preview_device_params(
+        &self,
+        recipe_id: &RecipeId,
+        device_id: DeviceId,
+        values: ParameterUpdate,
+    ) -> Result<ParamsPreview, TransactionError> {
+        let device = self
+            .recipes
+            .get_with_id_or_error(recipe_id)?
+            .device_by_id(device_id)?;
+        let variables: &Variables = self.recipes.as_ref();
+        let patched_vars = variables.patch(values.variables);
+        let changes = self
+            .device_actions
+            .validate(
+                &device.device_type,
+                DeviceContext::new(device_id, patched_vars.clone(), values.parameters.clone()),
+            )
+            .await?;
+        let mut preview = device.clone();
+        preview.params = values.parameters;
+        preview.apply(changes).await;
+
+        let old = variables
+            .resolve(&device.params)?
+            .params_as::<serde_json::Value>()
+            .map_err(TransactionError::other)?;
+        let new = patched_vars
+            .resolve(&preview.params)?
+            .params_as::<serde_json::Value>()
+            .map_err(TransactionError::other)?;
+        Ok(ParamsPreview {
+            changes: ParamChange::diff(&old, &new),
+            parameters: preview.params,
+        })
+    }
+
     async fn state(&self) -> ActiveState {
         let has_uncommitted_changes =
             self.check_active_files().await.is_err() || self.recipes.has_active_changes();
@@ -188,9 +254,158 @@ impl<'a, T: Deref<Target = Recipes>> RecipeDataService<'a, T> {
     fn device_dir(&self, device_id: &DeviceId) -> PathBuf {
         self.path.join(device_id.to_string())
     }
+
+    /// Devices which are added to or enabled in the running recipe must fit into the machine capacity as well
+    fn check_capacity_if_active(&self, recipe_id: &RecipeId) -> Result<(), TransactionError> {
+        let (active_id, active) = self.recipes.active();
+        if &active_id == recipe_id {
+            self.capacity.check(active)?;
+        }
+        Ok(())
+    }
 }
 
 impl<'a, T: DerefMut<Target = Recipes>> RecipeDataService<'a, T> {
+    fn add_device_group(
+        &mut self,
+        recipe_id: &RecipeId,
+        name: Name,
+    ) -> Result<DeviceGroupId, TransactionError> {
+        Ok(self
+            .recipes
+            .get_with_id_or_error_mut(recipe_id)?
+            .add_group(name)?)
+    }
+
+    fn rename_device_group(
+        &mut self,
+        recipe_id: &RecipeId,
+        group_id: DeviceGroupId,
+        name: Name,
+    ) -> Result<(), TransactionError> {
+        self.recipes
+            .get_with_id_or_error_mut(recipe_id)?
+            .rename_group(group_id, name)?;
+        Ok(())
+    }
+
+    fn delete_device_group(
+        &mut self,
+        recipe_id: &RecipeId,
+        group_id: DeviceGroupId,
+    ) -> Result<(), TransactionError> {
+        self.recipes
+            .get_with_id_or_error_mut(recipe_id)?
+            .remove_group(group_id)?;
+        Ok(())
+    }
+
+    fn update_device_group(
+        &mut self,
+        recipe_id: &RecipeId,
+        device_id: DeviceId,
+        group_id: Option<DeviceGroupId>,
+    ) -> Result<(), TransactionError> {
+        self.recipes
+            .get_with_id_or_error_mut(recipe_id)?
+            .move_device_to_group(device_id, group_id)?;
+        Ok(())
+    }
+
+    /// Fails for locked recipes. Must be called before the recipe is changed
+    fn ensure_editable(
+        &self,
+        recipe_id: &RecipeId,
+        options: &TransactionOptions,
+    ) -> Result<(), TransactionError> {
+        let recipe = self.recipes.get_with_id_or_error(recipe_id)?;
+        options.approval_after_change(recipe_id, recipe.approval)?;
+        Ok(())
+    }
+
+    /// Fails for locked devices without the unlock token. Must be called before the device is changed
+    fn ensure_device_unlocked(
+        &self,
+        recipe_id: &RecipeId,
+        device_id: DeviceId,
+        options: &TransactionOptions,
+    ) -> Result<(), TransactionError> {
+        options.check_unlocked(
+            device_id,
+            self.recipes
+                .get_with_id_or_error(recipe_id)?
+                .device_by_id(device_id)?,
+            self.unlock_token,
+        )?;
+        Ok(())
+    }
+
+    /// Variables are shared, so changing them edits every recipe which references them.
+    /// Returns these recipes, so they can be annotated after the change
+    fn ensure_variables_editable(
+        &self,
+        variables: &VariablesPatch,
+        options: &TransactionOptions,
+    ) -> Result<HashSet<RecipeId>, TransactionError> {
+        let mut affected = HashSet::new();
+        for (recipe_id, _, device_id, _) in
+            self.recipes.find_variable_usage_in_all_recipes(variables)
+        {
+            // Devices which only exist in the backup of the active recipe are not edited
+            if let Ok(device) = self
+                .recipes
+                .get_with_id_or_error(&recipe_id)?
+                .device_by_id(device_id)
+            {
+                options.check_unlocked(device_id, device, self.unlock_token)?;
+            }
+            affected.insert(recipe_id);
+        }
+        for recipe_id in affected.iter() {
+            self.ensure_editable(recipe_id, options)?;
+        }
+        Ok(affected)
+    }
+
+    /// Like `annotate`, but reviewed recipes fall back to draft, as released recipes must not differ from what was reviewed
+    fn annotate_edit(
+        &mut self,
+        recipe_id: &RecipeId,
+        options: &TransactionOptions,
+    ) -> Result<(), TransactionError> {
+        let recipe = self.recipes.get_with_id_or_error_mut(recipe_id)?;
+        recipe.approval = options.approval_after_change(recipe_id, recipe.approval)?;
+        self.annotate(recipe_id, options)
+    }
+
+    fn update_recipe_approval(
+        &mut self,
+        recipe_id: &RecipeId,
+        state: ApprovalState,
+        options: &TransactionOptions,
+    ) -> Result<(), TransactionError> {
+        let recipe = self.recipes.get_with_id_or_error_mut(recipe_id)?;
+        recipe
+            .approval
+            .check_transition(recipe_id, state, options.role())?;
+        recipe.approval = state;
+        Ok(())
+    }
+
+    /// Records message and author of the transaction in the recipe's change history
+    fn annotate(
+        &mut self,
+        recipe_id: &RecipeId,
+        options: &TransactionOptions,
+    ) -> Result<(), TransactionError> {
+        if let Some(annotation) = options.annotation()? {
+            self.recipes
+                .get_with_id_or_error_mut(recipe_id)?
+                .annotate(annotation);
+        }
+        Ok(())
+    }
+
     async fn delete_device(
         &mut self,
         recipe_id: RecipeId,
@@ -211,9 +426,7 @@ impl<'a, T: DerefMut<Target = Recipes>> RecipeDataService<'a, T> {
         let mut recipe = Recipe::default();
 
         // add all default devices
-        for listener in self.listeners.iter() {
-            listener.call(&mut recipe);
-        }
+        self.listeners.apply(&mut recipe);
 
         let new_id = self.recipes.add_new(recipe.clone());
 
@@ -233,6 +446,9 @@ impl<'a, T: DerefMut<Target = Recipes>> RecipeDataService<'a, T> {
 
         let r = self.recipes.get_with_id_or_error_mut(&raw.new_id)?;
         r.tags = raw.tags;
+        if let Some(description) = raw.description {
+            r.description = description;
+        }
         Ok(())
     }
 
@@ -245,6 +461,15 @@ impl<'a, T: DerefMut<Target = Recipes>> RecipeDataService<'a, T> {
                     return Err(e.into());
                 }
             }
+            let versions = self
+                .path
+                .join(file::VERSIONS_DIR)
+                .join(device_id.to_string());
+            if let Err(e) = tokio::fs::remove_dir_all(versions).await {
+                if e.kind() != ErrorKind::NotFound {
+                    return Err(e.into());
+                }
+            }
         }
         Ok(())
     }
@@ -271,8 +496,13 @@ impl<'a, T: DerefMut<Target = Recipes>> RecipeDataService<'a, T> {
         values: ParameterUpdate,
         options: &TransactionOptions,
     ) -> Result<(), TransactionError> {
+        self.ensure_device_unlocked(&recipe_id, device_id, options)?;
         let variables = self
-            .apply_params(device_id, &values.parameters, values.variables)
+            .apply_params(
+                Some((device_id, &values.parameters)),
+                values.variables,
+                options.origin(),
+            )
             .await?;
         let recipe = self.recipes.get_with_id_or_error_mut(&recipe_id)?;
 
@@ -281,11 +511,22 @@ impl<'a, T: DerefMut<Target = Recipes>> RecipeDataService<'a, T> {
         Ok(())
     }
 
+    async fn update_variables(
+        &mut self,
+        variables: VariablesPatch,
+        origin: &MessageOrigin,
+    ) -> Result<(), TransactionError> {
+        let variables = self.apply_params(None, variables, origin).await?;
+        *self.recipes.as_mut() = variables;
+        Ok(())
+    }
+
+    /// `edited` is None, if only variables change
     async fn apply_params(
         &self,
-        device_id: DeviceId,
-        params: &UntypedDeviceParamsWithVariables,
+        edited: Option<(DeviceId, &UntypedDeviceParamsWithVariables)>,
         variables: VariablesPatch,
+        origin: &MessageOrigin,
     ) -> Result<Variables, TransactionError> {
         let usages = if !variables.is_empty() {
             self.recipes
@@ -294,14 +535,19 @@ impl<'a, T: DerefMut<Target = Recipes>> RecipeDataService<'a, T> {
         } else {
             Vec::new()
         };
+        let changed_vars = variables.keys().cloned().collect::<HashSet<_>>();
         let patched_vars = self.recipes.as_ref().patch(variables);
 
         let mut has_var_changes_on_active = false;
-        let (active_id, _) = self.recipes.active();
+        let mut live_updates = HashSet::new();
+        let (active_id, active) = self.recipes.active();
 
-        for (recipe_id, device_type, device_id, params) in usages {
+        for (recipe_id, device_type, used_by, params) in usages {
             if recipe_id == active_id {
                 has_var_changes_on_active = true;
+                if edited.map(|(id, _)| id) != Some(used_by) && active.has_device(&used_by) {
+                    live_updates.insert(used_by);
+                }
                 continue;
             }
 
@@ -309,7 +555,7 @@ impl<'a, T: DerefMut<Target = Recipes>> RecipeDataService<'a, T> {
                 .device_actions
                 .validate(
                     &device_type,
-                    DeviceContext::new(device_id, patched_vars.clone(), params.clone()),
+                    DeviceContext::new(used_by, patched_vars.clone(), params.clone()),
                 )
                 .await
                 .map_err(|e| VariableError::from((recipe_id, e)))?;
@@ -322,22 +568,67 @@ impl<'a, T: DerefMut<Target = Recipes>> RecipeDataService<'a, T> {
             }
         }
 
-        if has_var_changes_on_active || self.recipes.has_device_on_running(device_id) {
+        if let Some((device_id, params)) = edited.filter(|(device_id, _)| {
+            has_var_changes_on_active || self.recipes.has_device_on_running(device_id)
+        }) {
             let edit_device_type = &self.recipes.get_device_or_error(device_id)?.device_type;
+            let ctx = DeviceContext::new(device_id, patched_vars.clone(), params.clone())
+                .with_origin(origin.clone());
+            if self.is_waiting_for_placeholders(device_id) {
+                // The device was never started. It starts with the next activation of the recipe
+                let _ = self.device_actions.validate(edit_device_type, ctx).await?;
+            } else {
+                self.device_actions.try_apply(edit_device_type, ctx).await?;
+            }
+        }
+
+        // Usages contain the backup of the active recipe as well. Only the running params are relevant
+        for used_by in live_updates {
+            let device = active.device_by_id(used_by)?;
+            if !self.is_live_bound(&device.device_type, &device.params, &changed_vars)
+                || self.is_waiting_for_placeholders(used_by)
+            {
+                continue;
+            }
             self.device_actions
                 .try_apply(
-                    edit_device_type,
-                    DeviceContext::new(device_id, patched_vars.clone(), params.clone()),
+                    &device.device_type,
+                    DeviceContext::new(used_by, patched_vars.clone(), device.params.clone())
+                        .with_origin(origin.clone()),
                 )
                 .await?;
         }
         Ok(patched_vars)
     }
 
+    /// Devices of the active recipe with unfilled placeholders are not started
+    fn is_waiting_for_placeholders(&self, device_id: DeviceId) -> bool {
+        self.recipes
+            .active()
+            .1
+            .device_by_id(device_id)
+            .is_ok_and(|d| d.unfilled_placeholders().next().is_some())
+    }
+
+    /// Devices are only updated without a restart, if all references to changed variables are live-bound
+    fn is_live_bound(
+        &self,
+        device_type: &str,
+        params: &UntypedDeviceParamsWithVariables,
+        changed_vars: &HashSet<String>,
+    ) -> bool {
+        params
+            .variable_paths()
+            .iter()
+            .filter(|(_, var)| changed_vars.contains(var))
+            .all(|(pointer, _)| self.device_actions.is_live_bound(device_type, pointer))
+    }
+
     async fn restore_committed(
         &mut self,
         recipe_id: RecipeId,
         device_id: DeviceId,
+        origin: &MessageOrigin,
     ) -> Result<(), TransactionError> {
         let restored = self
             .recipes
@@ -347,18 +638,148 @@ impl<'a, T: DerefMut<Target = Recipes>> RecipeDataService<'a, T> {
             // Even if we get an immutable ref in restore_committed(), recipes is still borrowed mut (Current compiler 'bug')
             .clone();
         let variables = self
-            .apply_params(device_id, &restored, Default::default())
+            .apply_params(Some((device_id, &restored)), Default::default(), origin)
             .await?;
         *self.recipes.as_mut() = variables;
 
         Ok(())
     }
 
+    /// Unlike `restore_committed`, the failed device isn't notified, because it isn't running anymore.
+    /// Running devices which were live-updated with uncommitted params within `window` get their committed params back
+    async fn rollback_failed_device(
+        &mut self,
+        recipe_id: &RecipeId,
+        device_id: DeviceId,
+        window: Duration,
+    ) -> Result<(DeviceConfig, Variables), TransactionError> {
+        let device = self
+            .recipes
+            .get_with_id_or_error_mut(recipe_id)?
+            .device_by_id_mut(device_id)?;
+        device.restore_committed()?;
+        let restored = (device.clone(), self.recipes.as_ref().clone());
+
+        for live_id in self.device_actions.live_updated_within(window) {
+            let has_uncommitted = self
+                .recipes
+                .get_with_id_or_error(recipe_id)?
+                .device_by_id(live_id)
+                .is_ok_and(|d| d.has_uncommitted_params());
+            if live_id == device_id || !has_uncommitted {
+                continue;
+            }
+            match self
+                .restore_committed(recipe_id.clone(), live_id, &MessageOrigin::Internal)
+                .await
+            {
+                Ok(()) => info!("Sent the committed params back to live-updated device {live_id}"),
+                Err(e) => warn!("Couldn't send the committed params back to device {live_id}: {e}"),
+            }
+        }
+        Ok(restored)
+    }
+
+    /// Discards all uncommitted changes of the active recipe including its files and the variables.
+    /// Devices which were added since the last commit are stopped.
+    /// Devices which were removed since are only started with the next recipe activation
     async fn restore_active(&mut self) -> Result<(), TransactionError> {
-        Err(TransactionError::Other(anyhow!("Not yet implemented")))
+        let variables = self.recipes.committed_variables().clone();
+        let variables_changed = &variables != self.recipes.as_ref();
+        // Running devices have to accept the committed params before anything is changed
+        let changed_params = self
+            .recipes
+            .iter_running_join_backup()
+            .filter_map(Result::ok)
+            .filter(|x| variables_changed || x.running.params != x.backup.params)
+            .map(|x| (x.id, x.backup.device_type.clone(), x.backup.params.clone()))
+            .collect::<Vec<_>>();
+        for (device_id, device_type, params) in changed_params {
+            self.device_actions
+                .try_apply(
+                    &device_type,
+                    DeviceContext::new(device_id, variables.clone(), params),
+                )
+                .await?;
+        }
+
+        // The current files are only replaced, once all backups were copied
+        let staging = self.recipe_dir_path().join(RESTORE_STAGING_DIR);
+        let restored = self
+            .recipes
+            .committed_active()
+            .devices
+            .keys()
+            .copied()
+            .collect::<Vec<_>>();
+        if let Err(e) = self.stage_backup_files(&staging, &restored).await {
+            fs::remove_dir_all(&staging).await.ok();
+            return Err(e);
+        }
+
+        let added = self.recipes.restore_active();
+        self.device_actions.stop_devices(&added);
+        for device_id in added {
+            self.remove_device_dir(&device_id).await?;
+        }
+        for device_id in restored {
+            self.remove_device_dir(&device_id).await?;
+            let staged = staging.join(device_id.to_string());
+            if fs::metadata(&staged).await.is_ok() {
+                fs::rename(&staged, self.device_dir(&device_id))
+                    .await
+                    .map_err(TransactionError::from_io_producer(&staged))?;
+            }
+        }
+        fs::remove_dir_all(&staging).await.ok();
+        Ok(())
+    }
+
+    /// Copies the committed files of `devices` into `staging`. Devices without backup get no directory
+    async fn stage_backup_files(
+        &self,
+        staging: &Path,
+        devices: &[DeviceId],
+    ) -> Result<(), TransactionError> {
+        self.remove_dir(staging).await?;
+        let backup_root = self.recipe_dir_path().join("backup");
+        for device_id in devices {
+            let backup_dir = backup_root.join(device_id.to_string());
+            if let Ok(meta) = fs::metadata(&backup_dir).await {
+                if meta.is_dir() {
+                    clone_directory_deep(&backup_dir, staging.join(device_id.to_string()))
+                        .await
+                        .map_err(TransactionError::from_io_producer(&backup_dir))?;
+                }
+            }
+        }
+        Ok(())
+    }
+
+    async fn remove_device_dir(&self, device_id: &DeviceId) -> Result<(), TransactionError> {
+        self.remove_dir(&self.device_dir(device_id)).await
+    }
+
+    async fn remove_dir(&self, path: &Path) -> Result<(), TransactionError> {
+        match fs::remove_dir_all(path).await {
+            Err(e) if e.kind() != ErrorKind::NotFound => Err(e.into()),
+            _ => Ok(()),
+        }
     }
 
     pub(super) async fn activate_recipe(&mut self, id: RecipeId) -> Result<(), TransactionError> {
+        if self.production
+            && !self
+                .recipes
+                .get_with_id_or_error(&id)?
+                .approval
+                .is_released()
+        {
+            return Err(ApprovalError::NotReleased(id).into());
+        }
+        let recipe = self.recipes.get_with_id_or_error(&id)?;
+        self.capacity.check(recipe)?;
+        recipe.check_placeholders()?;
         self.check_active_files().await?;
 
         let active_devices = self.recipes.set_active(&id)?;
@@ -370,21 +791,27 @@ impl<'a, T: DerefMut<Target = Recipes>> RecipeDataService<'a, T> {
         device_ids: impl IntoIterator<Item = DeviceId>,
     ) -> Result<(), TransactionError> {
         let path = self.recipe_dir_path();
-        let dst_folder = path.join("backup");
-        tokio::fs::remove_dir_all(&dst_folder).await.ok();
-
-        for device_id in device_ids {
-            let device_id_str = device_id.to_string();
-            let src_path = path.join(&device_id_str);
-            let dst_path = dst_folder.join(device_id_str);
-            if let Ok(meta) = fs::metadata(&src_path).await {
-                if meta.is_dir() {
-                    clone_directory_deep(&src_path, dst_path)
-                        .await
-                        .map_err(TransactionError::from_io_producer(&src_path))?;
-                }
-            }
+        let backup_root = path.join("backup");
+        let device_ids = device_ids.into_iter().collect::<Vec<_>>();
+        // Running devices could otherwise write while their folder is copied
+        let resume = ResumeFileWrites {
+            actions: Some(self.device_actions.clone()),
+            device_ids: device_ids.clone(),
+        };
+        if let Err(e) = self
+            .device_actions
+            .pause_file_writes(&device_ids, true)
+            .await
+        {
+            resume.run().await;
+            return Err(TransactionError::other(e.context(
+                "Backup is not synchronized, because not all devices paused their file writes",
+            )));
         }
+        let result = backup::sync_backup(path, &backup_root, device_ids.iter().copied()).await;
+        resume.run().await;
+        let stats = result.map_err(TransactionError::from_io_producer(&backup_root))?;
+        debug!("Synchronized backup: {stats:?}");
         Ok(())
     }
 
@@ -409,6 +836,7 @@ impl<'a, T: DerefMut<Target = Recipes>> RecipeDataService<'a, T> {
         }
         let mut duplicate = duplicate.into_inner();
         duplicate.recipe.created = chrono::Utc::now();
+        duplicate.recipe.approval = ApprovalState::Draft;
         self.recipes
             .add_inexistent(new_recipe_id.clone(), duplicate.recipe.clone());
 
@@ -429,6 +857,68 @@ impl<'a, T: DerefMut<Target = Recipes>> RecipeDataService<'a, T> {
         Ok(())
     }
 
+    async fn update_device_simulated(
+        &mut self,
+        recipe_id: RecipeId,
+        device_id: DeviceId,
+        simulated: bool,
+    ) -> Result<(), TransactionError> {
+        self.recipes
+            .get_with_id_or_error_mut(&recipe_id)?
+            .device_by_id_mut(device_id)?
+            .simulated = simulated;
+
+        Ok(())
+    }
+
+    async fn update_device_enabled(
+        &mut self,
+        recipe_id: RecipeId,
+        device_id: DeviceId,
+        enabled: bool,
+    ) -> Result<(), TransactionError> {
+        self.recipes
+            .get_with_id_or_error_mut(&recipe_id)?
+            .device_by_id_mut(device_id)?
+            .enabled = enabled;
+        if enabled {
+            self.check_capacity_if_active(&recipe_id)?;
+        }
+
+        Ok(())
+    }
+
+    async fn update_device_locked(
+        &mut self,
+        recipe_id: RecipeId,
+        device_id: DeviceId,
+        locked: bool,
+        options: &TransactionOptions,
+    ) -> Result<(), TransactionError> {
+        options.check_may_lock(device_id, self.unlock_token)?;
+        self.recipes
+            .get_with_id_or_error_mut(&recipe_id)?
+            .device_by_id_mut(device_id)?
+            .locked = locked;
+
+        Ok(())
+    }
+
+    async fn update_device_notes(
+        &mut self,
+        recipe_id: RecipeId,
+        device_id: DeviceId,
+        notes: String,
+    ) -> Result<(), TransactionError> {
+        pilatus::check_description(&notes).map_err(TransactionError::InvalidDeviceConfig)?;
+        self.recipes
+            .get_with_id_or_error_mut(&recipe_id)?
+            .device_by_id_mut(device_id)?
+            .notes = notes;
+
+        Ok(())
+    }
+
     async fn commit(&self, transaction_key: Uuid) -> io::Result<()> {
         let p = self.get_recipe_file_path();
         trace!(path = ?p, "storing json (async)");
@@ -459,8 +949,11 @@ impl RecipeServiceAccessor {
         RecipeDataService {
             path: &self.path,
             recipes: self.recipes.write().await,
-            device_actions: self.device_actions.deref(),
+            device_actions: &self.device_actions,
             listeners: &self.listeners,
+            unlock_token: self.unlock_token.as_deref(),
+            production: self.production,
+            capacity: &self.capacity,
             update_sender: &self.update_sender,
             change_strategies: &self.change_strategies,
         }
@@ -469,8 +962,11 @@ impl RecipeServiceAccessor {
         RecipeDataService {
             path: &self.path,
             recipes: self.recipes.read().await,
-            device_actions: self.device_actions.deref(),
+            device_actions: &self.device_actions,
             listeners: &self.listeners,
+            unlock_token: self.unlock_token.as_deref(),
+            production: self.production,
+            capacity: &self.capacity,
             update_sender: &self.update_sender,
             change_strategies: &self.change_strategies,
         }
@@ -507,11 +1003,14 @@ pub(crate) mod unstable {
             id: RecipeId,
             recipe: Recipe,
         ) -> Result<(), TransactionError> {
-            recipes_try_add_new_with_id(&mut self.recipes, id.clone(), recipe, self.device_actions)
-                .await
-                .map_err(|_| {
-                    TransactionError::Other(anyhow::anyhow!("Recipe {id} already exists "))
-                })?;
+            recipes_try_add_new_with_id(
+                &mut self.recipes,
+                id.clone(),
+                recipe,
+                self.device_actions.as_ref(),
+            )
+            .await
+            .map_err(|_| TransactionError::Other(anyhow::anyhow!("Recipe {id} already exists ")))?;
             Ok(())
         }
 
@@ -530,6 +1029,7 @@ pub(crate) mod unstable {
                 .recipes
                 .get_with_id_or_error_mut(&recipe_id)?
                 .add_device(device);
+            self.check_capacity_if_active(&recipe_id)?;
             Ok(id)
         }
 
@@ -543,6 +1043,7 @@ pub(crate) mod unstable {
             recipe
                 .add_device_with_id(id, device)
                 .map_err(|x| TransactionError::Other(x.into()))?;
+            self.check_capacity_if_active(&recipe_id)?;
             Ok(())
         }
 
@@ -550,12 +1051,43 @@ pub(crate) mod unstable {
             &mut self,
             device: DeviceConfig,
         ) -> Result<DeviceId, TransactionError> {
-            let id = self.recipes.get_active().1.add_device(device);
+            let (active_id, active) = self.recipes.get_active();
+            let id = active.add_device(device);
+            self.check_capacity_if_active(&active_id)?;
             Ok(id)
         }
     }
 }
 
+/// Resumes paused file writes. If the backup is cancelled before `run`, resuming continues in a separate task,
+/// so devices never stay paused
+struct ResumeFileWrites {
+    actions: Option<Arc<dyn DeviceActions>>,
+    device_ids: Vec<DeviceId>,
+}
+
+impl ResumeFileWrites {
+    async fn run(mut self) {
+        if let Some(actions) = self.actions.take() {
+            Self::resume(actions, std::mem::take(&mut self.device_ids)).await;
+        }
+    }
+
+    async fn resume(actions: Arc<dyn DeviceActions>, device_ids: Vec<DeviceId>) {
+        if let Err(e) = actions.pause_file_writes(&device_ids, false).await {
+            warn!("{e:#}");
+        }
+    }
+}
+
+impl Drop for ResumeFileWrites {
+    fn drop(&mut self) {
+        if let Some(actions) = self.actions.take() {
+            tokio::spawn(Self::resume(actions, std::mem::take(&mut self.device_ids)));
+        }
+    }
+}
+
 async fn is_content_equal(a: impl AsyncRead, b: impl AsyncRead) -> std::io::Result<bool> {
     let mut a = std::pin::pin!(a);
     let mut b = std::pin::pin!(b);
@@ -710,26 +1242,309 @@ mod tests {
     }
 
     #[tokio::test]
-    async fn test_path_property_assignment() -> anyhow::Result<()> {
+    async fn push_variable_changes_to_live_bound_devices() -> anyhow::Result<()> {
+        #[derive(Debug, Default)]
+        struct ApplyRecorder(std::sync::Mutex<Vec<DeviceId>>);
+
+        impl DeviceActions for ApplyRecorder {
+            fn validate(
+                &self,
+                _device_type: &str,
+                _ctx: DeviceContext,
+            ) -> futures::future::BoxFuture<
+                Result<pilatus::device::WithInfallibleParamUpdate<()>, TransactionError>,
+            > {
+                Box::pin(futures::future::ready(Ok(
+                    pilatus::device::IntoParamValidatorOk::into_ok(()),
+                )))
+            }
+            fn try_apply(
+                &self,
+                _device_type: &str,
+                ctx: DeviceContext,
+            ) -> futures::future::BoxFuture<Result<(), TransactionError>> {
+                self.0.lock().unwrap().push(ctx.id);
+                Box::pin(futures::future::ready(Ok(())))
+            }
+            fn is_live_bound(&self, _device_type: &str, pointer: &str) -> bool {
+                pointer == "/speed"
+            }
+        }
+
+        let recorder = Arc::new(ApplyRecorder::default());
         let (dir, rsb) = RecipeServiceFassade::create_temp_builder();
-        let rs = rsb.build();
+        let rs = rsb.replace_permissioner(recorder.clone()).build();
+        let active_id = rs.get_active_id().await;
+        let update = |field: &str, value: &str| -> anyhow::Result<ParameterUpdate> {
+            Ok(ParameterUpdate {
+                parameters: serde_json::from_value(json!({ field: {"__var": "var1"}}))?,
+                variables: std::iter::once(("var1".to_string(), serde_json::from_str(value)?))
+                    .collect(),
+            })
+        };
+
+        let mut ids = Vec::new();
+        for (name, field) in [
+            ("Live", "speed"),
+            ("Restarted", "exposure"),
+            ("Edited", "speed"),
+        ] {
+            let id = rs
+                .add_device_to_active_recipe(DeviceConfig::new_unchecked(
+                    "my_type",
+                    name,
+                    json!({ field: 1 }),
+                ))
+                .await?;
+            rs.update_device_params(active_id.clone(), id, update(field, "1")?)
+                .await?;
+            ids.push(id);
+        }
+        let [live, _restarted, edited] = ids[..] else {
+            unreachable!()
+        };
+        recorder.0.lock().unwrap().clear();
+
+        rs.update_device_params(active_id, edited, update("speed", "2")?)
+            .await?;
+        assert_eq!(vec![edited, live], *recorder.0.lock().unwrap());
 
-        assert_eq!(dir.path().join("recipes"), rs.recipe_dir_path());
         dir.close()?;
         Ok(())
     }
 
     #[tokio::test]
-    async fn delete_device() -> anyhow::Result<()> {
-        let (_dir, rsb) = RecipeServiceFassade::create_temp_builder();
-        let rs = rsb.build();
-        let active_id = rs.get_active_id().await;
-        let device_id = rs
-            .add_device_to_active_recipe(DeviceConfig::mock("params"))
-            .await?;
-        let device_id_with_file = rs
-            .add_device_to_active_recipe(DeviceConfig::mock("params"))
-            .await?;
+    async fn pause_file_writes_while_copying_backup() -> anyhow::Result<()> {
+        #[derive(Debug, Default)]
+        struct PauseRecorder(
+            std::sync::Mutex<Vec<(Vec<DeviceId>, bool)>>,
+            std::sync::atomic::AtomicBool,
+        );
+
+        impl DeviceActions for PauseRecorder {
+            fn validate(
+                &self,
+                _device_type: &str,
+                _ctx: DeviceContext,
+            ) -> futures::future::BoxFuture<
+                Result<pilatus::device::WithInfallibleParamUpdate<()>, TransactionError>,
+            > {
+                Box::pin(futures::future::ready(Ok(
+                    pilatus::device::IntoParamValidatorOk::into_ok(()),
+                )))
+            }
+            fn try_apply(
+                &self,
+                _device_type: &str,
+                _ctx: DeviceContext,
+            ) -> futures::future::BoxFuture<Result<(), TransactionError>> {
+                Box::pin(futures::future::ready(Ok(())))
+            }
+            fn is_live_bound(&self, _device_type: &str, _pointer: &str) -> bool {
+                false
+            }
+            fn pause_file_writes(
+                &self,
+                device_ids: &[DeviceId],
+                paused: bool,
+            ) -> futures::future::BoxFuture<anyhow::Result<()>> {
+                self.0.lock().unwrap().push((device_ids.to_vec(), paused));
+                let fail = paused && self.1.load(std::sync::atomic::Ordering::Relaxed);
+                Box::pin(futures::future::ready(match fail {
+                    true => Err(anyhow::anyhow!("Pause timed out")),
+                    false => Ok(()),
+                }))
+            }
+        }
+
+        let recorder = Arc::new(PauseRecorder::default());
+        let (dir, rsb) = RecipeServiceFassade::create_temp_builder();
+        let rs = rsb.replace_permissioner(recorder.clone()).build();
+        let device_id = rs
+            .add_device_to_active_recipe(DeviceConfig::mock(1))
+            .await?;
+        recorder.0.lock().unwrap().clear();
+
+        rs.commit_active().await?;
+        assert_eq!(
+            vec![(vec![device_id], true), (vec![device_id], false)],
+            *recorder.0.lock().unwrap()
+        );
+
+        recorder.0.lock().unwrap().clear();
+        recorder.1.store(true, std::sync::atomic::Ordering::Relaxed);
+        assert!(rs.commit_active().await.is_err());
+        assert_eq!(
+            vec![(vec![device_id], true), (vec![device_id], false)],
+            *recorder.0.lock().unwrap()
+        );
+
+        dir.close()?;
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn rollback_sends_committed_params_to_live_updated_devices() -> anyhow::Result<()> {
+        #[derive(Debug, Default)]
+        struct LiveRecorder(std::sync::Mutex<Vec<DeviceId>>);
+
+        impl DeviceActions for LiveRecorder {
+            fn validate(
+                &self,
+                _device_type: &str,
+                _ctx: DeviceContext,
+            ) -> futures::future::BoxFuture<
+                Result<pilatus::device::WithInfallibleParamUpdate<()>, TransactionError>,
+            > {
+                Box::pin(futures::future::ready(Ok(
+                    pilatus::device::IntoParamValidatorOk::into_ok(()),
+                )))
+            }
+            fn try_apply(
+                &self,
+                _device_type: &str,
+                ctx: DeviceContext,
+            ) -> futures::future::BoxFuture<Result<(), TransactionError>> {
+                self.0.lock().unwrap().push(ctx.id);
+                Box::pin(futures::future::ready(Ok(())))
+            }
+            fn is_live_bound(&self, _device_type: &str, _pointer: &str) -> bool {
+                false
+            }
+            fn live_updated_within(&self, _window: Duration) -> Vec<DeviceId> {
+                self.0.lock().unwrap().clone()
+            }
+        }
+
+        let recorder = Arc::new(LiveRecorder::default());
+        let (dir, rsb) = RecipeServiceFassade::create_temp_builder();
+        let rs = rsb.replace_permissioner(recorder.clone()).build();
+        let active_id = rs.get_active_id().await;
+        let committed = UntypedDeviceParamsWithVariables::from_serializable(1)?;
+        let mut ids = Vec::new();
+        for params in [2, 3] {
+            let id = rs
+                .add_device_to_active_recipe(DeviceConfig::mock(1))
+                .await?;
+            rs.update_device_params_with(
+                active_id.clone(),
+                id,
+                ParameterUpdate {
+                    parameters: UntypedDeviceParamsWithVariables::from_serializable(params)?,
+                    variables: Default::default(),
+                },
+                serde_json::from_value::<TransactionOptions>(json!({ "committed": false }))?,
+            )
+            .await?;
+            ids.push(id);
+        }
+        let [failed, live] = ids[..] else {
+            unreachable!()
+        };
+        recorder.0.lock().unwrap().clear();
+        recorder.0.lock().unwrap().push(live);
+
+        let (restored, _) = rs
+            .rollback_failed_device(
+                active_id,
+                failed,
+                "Camera rejected exposure".into(),
+                Duration::from_secs(10),
+            )
+            .await?;
+        assert_eq!(committed, restored.params);
+        assert_eq!(vec![live, live], *recorder.0.lock().unwrap());
+        let service = rs.recipe_service_read().await;
+        let live_device = service.recipes.get_device_or_error(live)?;
+        assert_eq!(committed, live_device.params);
+        assert!(!live_device.has_uncommitted_params());
+
+        dir.close()?;
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn pass_the_transaction_origin_to_running_devices() -> anyhow::Result<()> {
+        #[derive(Debug, Default)]
+        struct OriginRecorder(std::sync::Mutex<Vec<MessageOrigin>>);
+
+        impl DeviceActions for OriginRecorder {
+            fn validate(
+                &self,
+                _device_type: &str,
+                _ctx: DeviceContext,
+            ) -> futures::future::BoxFuture<
+                Result<pilatus::device::WithInfallibleParamUpdate<()>, TransactionError>,
+            > {
+                Box::pin(futures::future::ready(Ok(
+                    pilatus::device::IntoParamValidatorOk::into_ok(()),
+                )))
+            }
+            fn try_apply(
+                &self,
+                _device_type: &str,
+                ctx: DeviceContext,
+            ) -> futures::future::BoxFuture<Result<(), TransactionError>> {
+                self.0.lock().unwrap().push(ctx.origin().clone());
+                Box::pin(futures::future::ready(Ok(())))
+            }
+            fn is_live_bound(&self, _device_type: &str, _pointer: &str) -> bool {
+                false
+            }
+        }
+
+        let recorder = Arc::new(OriginRecorder::default());
+        let (dir, rsb) = RecipeServiceFassade::create_temp_builder();
+        let rs = rsb.replace_permissioner(recorder.clone()).build();
+        let active_id = rs.get_active_id().await;
+        let device_id = rs
+            .add_device_to_active_recipe(DeviceConfig::mock(1))
+            .await?;
+        recorder.0.lock().unwrap().clear();
+
+        let users = crate::user::FileUserStore::open(dir.path().join("users.json"));
+        let web = pilatus::device::ActorSystem::new().with_web_origin(&users, None);
+        rs.update_device_params_with(
+            active_id,
+            device_id,
+            ParameterUpdate {
+                parameters: UntypedDeviceParamsWithVariables::from_serializable(2)?,
+                variables: Default::default(),
+            },
+            TransactionOptions::default().with_origin_of(&web),
+        )
+        .await?;
+        let origins = recorder.0.lock().unwrap().clone();
+        assert!(
+            matches!(&origins[..], [MessageOrigin::Web { user: None, .. }]),
+            "{origins:?}"
+        );
+
+        dir.close()?;
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_path_property_assignment() -> anyhow::Result<()> {
+        let (dir, rsb) = RecipeServiceFassade::create_temp_builder();
+        let rs = rsb.build();
+
+        assert_eq!(dir.path().join("recipes"), rs.recipe_dir_path());
+        dir.close()?;
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn delete_device() -> anyhow::Result<()> {
+        let (_dir, rsb) = RecipeServiceFassade::create_temp_builder();
+        let rs = rsb.build();
+        let active_id = rs.get_active_id().await;
+        let device_id = rs
+            .add_device_to_active_recipe(DeviceConfig::mock("params"))
+            .await?;
+        let device_id_with_file = rs
+            .add_device_to_active_recipe(DeviceConfig::mock("params"))
+            .await?;
         let mut file_service = rs.build_device_file_service(device_id);
         file_service
             .add_file_unchecked(&"bar/test.txt".try_into()?, b"content")
@@ -742,6 +1557,31 @@ mod tests {
         Ok(())
     }
 
+    #[tokio::test]
+    async fn stats_contain_devices_and_disk_size() -> anyhow::Result<()> {
+        let (_dir, rsb) = RecipeServiceFassade::create_temp_builder();
+        let rs = rsb.build();
+        let active_id = rs.get_active_id().await;
+        let device_id = rs
+            .add_device_to_active_recipe(DeviceConfig::mock("params"))
+            .await?;
+        rs.create_device_file(device_id, "bar/test.txt", b"content")
+            .await;
+        let other_id = rs.add_recipe(Recipe::default()).await?;
+
+        let stats = rs.stats().await?;
+        assert_eq!(2, stats.len());
+        let active = stats.iter().find(|s| s.id == active_id).unwrap();
+        assert!(active.is_active);
+        assert_eq!(1, active.device_count);
+        assert_eq!(1, active.devices_per_type.values().sum::<usize>());
+        assert_eq!(7, active.disk_size);
+        let other = stats.iter().find(|s| s.id == other_id).unwrap();
+        assert!(!other.is_active);
+        assert_eq!(0, other.disk_size);
+        Ok(())
+    }
+
     #[tokio::test]
     async fn set_active_without_changes() -> anyhow::Result<()> {
         let (_dir, rsb) = RecipeServiceFassade::create_temp_builder();
@@ -763,7 +1603,6 @@ mod tests {
     }
 
     #[tokio::test]
-    #[ignore = "Not yet implemented"]
     async fn discard_active_with_new_device_with_files_removes_folder() -> anyhow::Result<()> {
         let (_dir, rsb) = RecipeServiceFassade::create_temp_builder();
         let rs = rsb.build();
@@ -777,10 +1616,126 @@ mod tests {
         assert_eq!(1, fs.list_recursive().await?.len());
         rs.restore_active().await?;
         assert_eq!(0, fs.list_recursive().await?.len());
+        assert!(!rs.state().await.recipes().has_device_on_running(did));
 
         Ok(())
     }
 
+    #[tokio::test]
+    async fn discard_active_restores_params_and_files() -> anyhow::Result<()> {
+        let (_dir, rsb) = RecipeServiceFassade::create_temp_builder();
+        let rs = rsb.build();
+        let recipe_id = rs.get_active_id().await;
+        let did = rs
+            .add_device_to_active_recipe(DeviceConfig::mock(1))
+            .await?;
+        let mut fs = rs.build_device_file_service(did);
+        let kept = RelativeFilePath::new("kept.txt").unwrap();
+        let added = RelativeFilePath::new("added.txt").unwrap();
+        fs.add_file_unchecked(&kept, b"kept").await?;
+        rs.commit_active().await?;
+
+        fs.remove_file(&kept).await?;
+        fs.add_file_unchecked(&added, b"added").await?;
+        rs.update_device_params(
+            recipe_id.clone(),
+            did,
+            ParameterUpdate {
+                parameters: UntypedDeviceParamsWithVariables::from_serializable(2)?,
+                variables: Default::default(),
+            },
+        )
+        .await?;
+        assert!(rs.state().await.has_uncommitted_changes());
+
+        rs.restore_active().await?;
+        let state = rs.state().await;
+        assert!(!state.has_uncommitted_changes());
+        assert_eq!(
+            Some(1),
+            state
+                .recipes()
+                .active()
+                .1
+                .device_by_id(did)?
+                .params
+                .as_i64()
+        );
+        assert!(fs.has_file(&kept).await?);
+        assert!(!fs.has_file(&added).await?);
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn discard_active_restores_variables_and_stops_added_devices() -> anyhow::Result<()> {
+        #[derive(Debug, Default)]
+        struct StopRecorder(std::sync::Mutex<Vec<DeviceId>>);
+
+        impl DeviceActions for StopRecorder {
+            fn validate(
+                &self,
+                _device_type: &str,
+                _ctx: DeviceContext,
+            ) -> futures::future::BoxFuture<
+                Result<pilatus::device::WithInfallibleParamUpdate<()>, TransactionError>,
+            > {
+                Box::pin(futures::future::ready(Ok(
+                    pilatus::device::IntoParamValidatorOk::into_ok(()),
+                )))
+            }
+            fn try_apply(
+                &self,
+                _device_type: &str,
+                _ctx: DeviceContext,
+            ) -> futures::future::BoxFuture<Result<(), TransactionError>> {
+                Box::pin(futures::future::ready(Ok(())))
+            }
+            fn is_live_bound(&self, _device_type: &str, _pointer: &str) -> bool {
+                false
+            }
+            fn stop_devices(&self, device_ids: &[DeviceId]) {
+                self.0.lock().unwrap().extend_from_slice(device_ids);
+            }
+        }
+
+        let recorder = Arc::new(StopRecorder::default());
+        let (_dir, rsb) = RecipeServiceFassade::create_temp_builder();
+        let rs = rsb.replace_permissioner(recorder.clone()).build();
+        let recipe_id = rs.get_active_id().await;
+        let device_id = rs
+            .add_device_to_active_recipe(DeviceConfig::mock(json!({ "test": 1 })))
+            .await?;
+        let var = |value: &str| -> anyhow::Result<VariablesPatch> {
+            Ok([("var1".to_string(), serde_json::from_str(value)?)].into())
+        };
+        rs.update_device_params(
+            recipe_id.clone(),
+            device_id,
+            ParameterUpdate {
+                parameters: serde_json::from_value(json!({ "test": {"__var": "var1"}}))?,
+                variables: var("1")?,
+            },
+        )
+        .await?;
+        rs.commit_active().await?;
+
+        rs.update_variables_with(var("2")?, Default::default())
+            .await?;
+        let added = rs
+            .add_device_to_active_recipe(DeviceConfig::mock(1))
+            .await?;
+        rs.restore_active().await?;
+
+        let state = rs.state().await;
+        assert!(!state.has_uncommitted_changes());
+        assert_eq!(
+            var("1")?.get("var1"),
+            state.recipes().as_ref().resolve_key("var1")
+        );
+        assert_eq!(vec![added], *recorder.0.lock().unwrap());
+        Ok(())
+    }
+
     #[tokio::test]
     async fn set_active_forbidden_with_new_device() -> anyhow::Result<()> {
         let (_dir, rsb) = RecipeServiceFassade::create_temp_builder();
@@ -956,6 +1911,41 @@ mod tests {
         Ok(())
     }
 
+    #[tokio::test]
+    async fn preview_device_params_doesnt_change_recipe() -> anyhow::Result<()> {
+        let (_dir, rsb) = RecipeServiceFassade::create_temp_builder();
+        let rs = rsb.build();
+        let recipe_id = rs.get_active_id().await;
+        let device = DeviceConfig::mock(serde_json::json!({ "foo": 3, "bar": "x" }));
+        let device_id = rs.add_device_to_active_recipe(device.clone()).await?;
+
+        let preview = rs
+            .preview_device_params(
+                recipe_id.clone(),
+                device_id,
+                ParameterUpdate {
+                    parameters: UntypedDeviceParamsWithVariables::from_serializable(
+                        serde_json::json!({ "foo": 5, "bar": "x" }),
+                    )?,
+                    variables: Default::default(),
+                },
+            )
+            .await?;
+        assert_eq!(
+            vec![ParamChange {
+                path: "/foo".into(),
+                old: Some(3.into()),
+                new: Some(5.into()),
+            }],
+            preview.changes
+        );
+        assert_eq!(
+            device.params,
+            rs.device_config(recipe_id, device_id).await?.params
+        );
+        Ok(())
+    }
+
     #[tokio::test]
     #[rustfmt::skip]
     async fn test_update_device_params() -> anyhow::Result<()> {
@@ -988,4 +1978,296 @@ mod tests {
        dir.close()?;
         Ok(())
     }
+
+    #[tokio::test]
+    async fn locked_devices_require_unlock_token() -> anyhow::Result<()> {
+        let (_dir, rsb) = RecipeServiceFassade::create_temp_builder();
+        let rs = rsb.with_unlock_token("secret").build();
+        let recipe_id = rs.get_active_id().await;
+        let device_id = rs
+            .add_device_to_active_recipe(DeviceConfig::mock(1).with_locked(true))
+            .await?;
+        assert!(rs.state().await.locked_devices.contains(&device_id));
+
+        let update = || ParameterUpdate {
+            parameters: UntypedDeviceParamsWithVariables::from_serializable(2).unwrap(),
+            variables: Default::default(),
+        };
+        let Err(TransactionError::Other(e)) = rs
+            .update_device_params(recipe_id.clone(), device_id, update())
+            .await
+        else {
+            panic!("Locked device must not be changed without token");
+        };
+        assert!(e.is::<pilatus::DeviceLockedError>());
+        assert!(rs
+            .update_device_params_with(
+                recipe_id.clone(),
+                device_id,
+                update(),
+                TransactionOptions::default().with_unlock_token("wrong"),
+            )
+            .await
+            .is_err());
+        assert!(rs
+            .update_device_locked_with(recipe_id.clone(), device_id, false, Default::default())
+            .await
+            .is_err());
+        let Err(TransactionError::Other(e)) = rs.delete_device(recipe_id.clone(), device_id).await
+        else {
+            panic!("Locked device must not be deleted without token");
+        };
+        assert!(e.is::<pilatus::DeviceLockedError>());
+        rs.update_device_params_with(
+            recipe_id.clone(),
+            device_id,
+            update(),
+            TransactionOptions::default().with_unlock_token("secret"),
+        )
+        .await?;
+        rs.update_device_params_with(
+            recipe_id,
+            device_id,
+            update(),
+            TransactionOptions::default().bypassing_lock(),
+        )
+        .await?;
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn variable_changes_are_edits_of_referencing_recipes() -> anyhow::Result<()> {
+        let (_dir, rsb) = RecipeServiceFassade::create_temp_builder();
+        let rs = rsb.with_unlock_token("secret").build();
+        let recipe_id = rs.get_active_id().await;
+        let device_id = rs
+            .add_device_to_active_recipe(DeviceConfig::mock(json!({ "test": 1 })))
+            .await?;
+        rs.update_device_params(
+            recipe_id.clone(),
+            device_id,
+            ParameterUpdate {
+                parameters: serde_json::from_value(json!({ "test": {"__var": "var1"}}))?,
+                variables: [("var1".to_string(), serde_json::from_str("1")?)].into(),
+            },
+        )
+        .await?;
+        let with_token = || TransactionOptions::default().with_unlock_token("secret");
+        rs.update_device_locked_with(recipe_id.clone(), device_id, true, with_token())
+            .await?;
+        let patch =
+            || VariablesPatch::from([("var1".to_string(), serde_json::from_str("2").unwrap())]);
+
+        let Err(TransactionError::Other(e)) =
+            rs.update_variables_with(patch(), Default::default()).await
+        else {
+            panic!("Variables of locked devices must not change without token");
+        };
+        assert!(e.is::<pilatus::DeviceLockedError>());
+
+        rs.update_variables_with(patch(), with_token().with_author("script"))
+            .await?;
+        let s = rs.recipe_service_read().await;
+        let last_change = s
+            .recipes
+            .get_with_id_or_error(&recipe_id)?
+            .last_change()
+            .and_then(|x| x.author.clone());
+        assert_eq!(Some("script".to_string()), last_change);
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn activation_checks_machine_capacity() -> anyhow::Result<()> {
+        let (_dir, rsb) = RecipeServiceFassade::create_temp_builder();
+        let rs = rsb
+            .with_machine_capacity(MachineCapacity {
+                memory_mb: Some(100),
+                gpus: None,
+            })
+            .build();
+        let heavy = DeviceConfig::mock(1).with_resources(pilatus::DeviceResources {
+            memory_mb: 200,
+            ..Default::default()
+        });
+        let mut recipe = Recipe::default();
+        recipe.add_device(heavy.clone());
+        let recipe_id = rs.add_recipe(recipe).await?;
+        let Err(TransactionError::Other(e)) = rs.activate_recipe(recipe_id).await else {
+            panic!("Recipe exceeds the machine capacity");
+        };
+        assert!(e.is::<pilatus::AdmissionError>());
+
+        let Err(TransactionError::Other(e)) = rs.add_device_to_active_recipe(heavy.clone()).await
+        else {
+            panic!("Running recipe would exceed the machine capacity");
+        };
+        assert!(e.is::<pilatus::AdmissionError>());
+
+        let disabled_id = rs
+            .add_device_to_active_recipe(heavy.with_enabled(false))
+            .await?;
+        let Err(TransactionError::Other(e)) = rs
+            .update_device_enabled_with(
+                rs.get_active_id().await,
+                disabled_id,
+                true,
+                TransactionOptions::default(),
+            )
+            .await
+        else {
+            panic!("Enabling the device would exceed the machine capacity");
+        };
+        assert!(e.is::<pilatus::AdmissionError>());
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn activation_requires_filled_placeholders() -> anyhow::Result<()> {
+        let (_dir, rsb) = RecipeServiceFassade::create_temp_builder();
+        let rs = rsb.build();
+        let active_id = rs.get_active_id().await;
+        let device_id = rs
+            .add_device_to_active_recipe(
+                DeviceConfig::mock(json!({ "ip": "" })).with_placeholder("/ip"),
+            )
+            .await?;
+        let Err(TransactionError::Other(e)) = rs.activate_recipe(active_id.clone()).await else {
+            panic!("Placeholders must be set before activation");
+        };
+        assert!(e.is::<pilatus::UnfilledPlaceholdersError>());
+        assert_eq!(
+            Some(&vec!["/ip".to_string()]),
+            rs.state().await.unfilled_placeholders.get(&device_id)
+        );
+
+        rs.update_device_params(
+            active_id.clone(),
+            device_id,
+            ParameterUpdate {
+                parameters: UntypedDeviceParamsWithVariables::from_serializable(
+                    json!({ "ip": "192.168.1.10" }),
+                )?,
+                variables: Default::default(),
+            },
+        )
+        .await?;
+        rs.activate_recipe(active_id).await?;
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn reject_mutations_in_maintenance_mode() -> anyhow::Result<()> {
+        let (_dir, rsb) = RecipeServiceFassade::create_temp_builder();
+        let maintenance = pilatus::MaintenanceMode::default();
+        let rs = rsb.with_maintenance(maintenance.clone()).build();
+        maintenance.enable(Some("Replace belt".into()), None);
+        let Err(TransactionError::Other(e)) =
+            rs.add_new_default_recipe_with(Default::default()).await
+        else {
+            panic!("Recipes must not change during maintenance");
+        };
+        assert!(e.is::<pilatus::MaintenanceError>());
+        let active_id = rs.recipe_service_read().await.recipes.active().0;
+        let Err(TransactionError::Other(e)) = rs
+            .delete_device_with(active_id, DeviceId::new_v4(), Default::default())
+            .await
+        else {
+            panic!("Devices must not be deleted during maintenance");
+        };
+        assert!(e.is::<pilatus::MaintenanceError>());
+        assert_eq!(
+            1,
+            rs.recipe_service_read()
+                .await
+                .recipes
+                .iter_without_backup()
+                .count()
+        );
+
+        maintenance.disable();
+        rs.add_new_default_recipe_with(Default::default()).await?;
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn production_only_activates_released_recipes() -> anyhow::Result<()> {
+        let (_dir, rsb) = RecipeServiceFassade::create_temp_builder();
+        let rs = rsb.with_production(true).build();
+        let (recipe_id, _) = rs.add_new_default_recipe_with(Default::default()).await?;
+        let Err(TransactionError::Other(e)) = rs.activate_recipe(recipe_id.clone()).await else {
+            panic!("Drafts must not be activated on production installations");
+        };
+        assert!(e.is::<pilatus::ApprovalError>());
+
+        let as_role = |role| TransactionOptions::default().with_role(role);
+        rs.update_recipe_approval_with(
+            recipe_id.clone(),
+            pilatus::ApprovalState::Review,
+            as_role(pilatus::Role::Operator),
+        )
+        .await?;
+        assert!(rs
+            .update_recipe_approval_with(
+                recipe_id.clone(),
+                pilatus::ApprovalState::Released,
+                as_role(pilatus::Role::Operator),
+            )
+            .await
+            .is_err());
+        rs.update_recipe_approval_with(
+            recipe_id.clone(),
+            pilatus::ApprovalState::Released,
+            as_role(pilatus::Role::Admin),
+        )
+        .await?;
+        rs.activate_recipe(recipe_id.clone()).await?;
+        assert_eq!(pilatus::ApprovalState::Released, rs.state().await.approval);
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn changes_reset_approval_and_are_rejected_when_released() -> anyhow::Result<()> {
+        let (_dir, rsb) = RecipeServiceFassade::create_temp_builder();
+        let rs = rsb.build();
+        let recipe_id = rs.get_active_id().await;
+        let device_id = rs
+            .add_device_to_active_recipe(DeviceConfig::mock(1))
+            .await?;
+        let admin = || TransactionOptions::default().with_role(pilatus::Role::Admin);
+        rs.update_recipe_approval_with(recipe_id.clone(), pilatus::ApprovalState::Review, admin())
+            .await?;
+        rs.update_device_notes_with(recipe_id.clone(), device_id, "Checked".into(), admin())
+            .await?;
+        assert_eq!(pilatus::ApprovalState::Draft, rs.state().await.approval);
+
+        for state in [
+            pilatus::ApprovalState::Review,
+            pilatus::ApprovalState::Released,
+        ] {
+            rs.update_recipe_approval_with(recipe_id.clone(), state, admin())
+                .await?;
+        }
+        let is_rejected = |result: Result<(), TransactionError>| matches!(result, Err(TransactionError::Other(e)) if e.is::<pilatus::ApprovalError>());
+        assert!(is_rejected(
+            rs.update_device_notes_with(recipe_id.clone(), device_id, "Changed".into(), admin())
+                .await
+        ));
+        assert!(is_rejected(rs.commit_active_with(admin()).await));
+        assert!(is_rejected(rs.restore_active_with(admin()).await));
+        assert!(is_rejected(
+            rs.restore_committed_with(recipe_id.clone(), device_id, admin())
+                .await
+        ));
+        assert_eq!(pilatus::ApprovalState::Released, rs.state().await.approval);
+
+        rs.update_recipe_approval_with(recipe_id.clone(), pilatus::ApprovalState::Locked, admin())
+            .await?;
+        assert!(is_rejected(
+            rs.update_device_notes_with(recipe_id.clone(), device_id, "Changed".into(), admin())
+                .await
+        ));
+        assert_eq!(pilatus::ApprovalState::Locked, rs.state().await.approval);
+        Ok(())
+    }
 }
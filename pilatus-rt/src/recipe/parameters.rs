@@ -2,7 +2,8 @@ use std::any::{Any, TypeId};
 use std::collections::HashMap;
 use std::fmt::Debug;
 use std::ops::DerefMut;
-use std::sync::Arc;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
 
 use anyhow::anyhow;
 use futures::future::BoxFuture;
@@ -11,22 +12,43 @@ use minfac::{AllRegistered, Registered, ServiceCollection, WeakServiceProvider};
 use tokio::task::JoinHandle;
 
 use pilatus::device::{
-    ActorSystem, DeviceContext, DeviceHandler, DeviceId, DeviceResult, UpdateDeviceError,
-    WithInfallibleParamUpdate,
+    ActorSystem, DeviceContext, DeviceHandler, DeviceId, DeviceResult, FieldRenames, LiveBindings,
+    MessageRecorder, PauseFileWritesMessage, UpdateDeviceError, WithInfallibleParamUpdate,
 };
-use pilatus::{Recipes, TransactionError, TransactionOptions, UntypedDeviceParamsWithVariables};
+use pilatus::{
+    GenericConfig, Recipes, TransactionError, TransactionOptions, UntypedDeviceParamsWithVariables,
+};
+use serde::Deserialize;
+use tracing::{info, warn};
 
 use super::{ChangeDeviceParamsTransactionError, RecipeDataService, RecipeServiceBuilder};
 
+/// Flushing might take a while, but a hanging device must not block the recipe service
+const PAUSE_FILE_WRITES_TIMEOUT: Duration = Duration::from_secs(10);
+
 pub(super) fn register_services(c: &mut ServiceCollection) {
     c.with::<(
         AllRegistered<Box<dyn DeviceHandler>>,
         Registered<ActorSystem>,
+        Registered<GenericConfig>,
+        AllRegistered<FieldRenames>,
+        AllRegistered<LiveBindings>,
     )>()
-    .register(|(handlers, system)| DeviceSpawnerService::new(handlers, system));
+    .register(|(handlers, system, config, renames, bindings)| {
+        let config = config
+            .get::<ActorSystemConfig>("actor_system")
+            .unwrap_or_default();
+        DeviceSpawnerService::new(handlers, system)
+            .with_mailbox_capacities(config.mailbox_capacity)
+            .with_recorded_messages(config.record_messages)
+            .with_field_renames(renames)
+            .with_live_bindings(bindings)
+    });
+    c.register_instance(pilatus::ServiceInfo::transient::<DeviceSpawnerService>());
 
     c.with::<Registered<DeviceSpawnerService>>()
         .register(|s| Arc::new(s) as Arc<dyn DeviceActions>);
+    c.register_instance(pilatus::ServiceInfo::transient::<Arc<dyn DeviceActions>>());
 }
 
 impl DeviceActions for DeviceSpawnerService {
@@ -36,7 +58,15 @@ impl DeviceActions for DeviceSpawnerService {
         ctx: DeviceContext,
     ) -> BoxFuture<Result<WithInfallibleParamUpdate<()>, TransactionError>> {
         let spawner = self.get_spawner(device_type);
-        async move { spawner?.validate(ctx).await.map_err(Into::into) }.boxed()
+        let (ctx, migrated) = self.migrate_fields(device_type, ctx);
+        async move {
+            spawner?
+                .validate(ctx)
+                .await
+                .map(|x| x.or_update(migrated))
+                .map_err(Into::into)
+        }
+        .boxed()
     }
     fn try_apply(
         &self,
@@ -44,6 +74,7 @@ impl DeviceActions for DeviceSpawnerService {
         ctx: DeviceContext,
     ) -> BoxFuture<Result<(), TransactionError>> {
         let spawner = self.get_spawner(device_type);
+        let id = ctx.id;
         async move {
             spawner?
                 .update(ctx, self.actor_system.clone())
@@ -52,22 +83,103 @@ impl DeviceActions for DeviceSpawnerService {
                     UpdateDeviceError::Validate(x) => x.into(),
                     UpdateDeviceError::UnknownDevice(d) => d.into(),
                     UpdateDeviceError::Other(x) => x.into(),
-                })
+                })?;
+            self.live_updates
+                .lock()
+                .expect("Never poisoned")
+                .insert(id, Instant::now());
+            Ok(())
+        }
+        .boxed()
+    }
+    fn is_live_bound(&self, device_type: &str, pointer: &str) -> bool {
+        self.live_bindings
+            .get(device_type)
+            .is_some_and(|b| b.is_bound(pointer))
+    }
+    fn live_updated_within(&self, window: Duration) -> Vec<DeviceId> {
+        let mut live_updates = self.live_updates.lock().expect("Never poisoned");
+        live_updates.retain(|_, updated| updated.elapsed() < window);
+        live_updates.keys().copied().collect()
+    }
+    fn stop_devices(&self, device_ids: &[DeviceId]) {
+        for id in device_ids {
+            self.actor_system.forget_sender(*id);
+        }
+    }
+    fn pause_file_writes(
+        &self,
+        device_ids: &[DeviceId],
+        paused: bool,
+    ) -> BoxFuture<anyhow::Result<()>> {
+        let writers = self
+            .actor_system
+            .list_devices_for_message_type::<PauseFileWritesMessage>();
+        let targets: Vec<_> = device_ids
+            .iter()
+            .filter(|id| writers.contains(id))
+            .copied()
+            .collect();
+        async move {
+            let failures = futures::future::join_all(targets.into_iter().map(|id| async move {
+                let action = if paused { "pause" } else { "resume" };
+                let msg = PauseFileWritesMessage { paused };
+                match tokio::time::timeout(
+                    PAUSE_FILE_WRITES_TIMEOUT,
+                    self.actor_system.ask(id, msg),
+                )
+                .await
+                {
+                    Ok(Ok(())) => None,
+                    Ok(Err(e)) => Some(format!("Cannot {action} file writes of device {id}: {e}")),
+                    Err(_) => Some(format!(
+                        "Device {id} didn't {action} file writes within {PAUSE_FILE_WRITES_TIMEOUT:?}"
+                    )),
+                }
+            }))
+            .await
+            .into_iter()
+            .flatten()
+            .collect::<Vec<_>>();
+            if failures.is_empty() {
+                Ok(())
+            } else {
+                Err(anyhow::anyhow!(failures.join(", ")))
+            }
         }
         .boxed()
     }
 }
 
+#[derive(Debug, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+struct ActorSystemConfig {
+    /// Mailbox capacity per device_type. Overrides the capacity chosen by the device itself
+    mailbox_capacity: HashMap<String, usize>,
+    /// Number of received messages per device_type, which are kept to reproduce field bugs
+    record_messages: HashMap<String, usize>,
+}
+
 #[derive(Clone)]
 pub struct DeviceSpawnerService {
     actor_system: ActorSystem,
     map: HashMap<&'static str, Box<dyn DeviceHandler>>,
+    mailbox_capacities: Arc<HashMap<String, usize>>,
+    recorded_messages: Arc<HashMap<String, usize>>,
+    field_renames: Arc<HashMap<&'static str, FieldRenames>>,
+    live_bindings: Arc<HashMap<&'static str, LiveBindings>>,
+    /// When running devices last accepted an `UpdateParamsMessage`, so they can be rolled back
+    live_updates: Arc<Mutex<HashMap<DeviceId, Instant>>>,
 }
 
 impl Debug for DeviceSpawnerService {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         f.debug_struct("ActorSystemRecipePermissioner")
             .field("map", &self.map.keys())
+            .field("mailbox_capacities", &self.mailbox_capacities)
+            .field("recorded_messages", &self.recorded_messages)
+            .field("field_renames", &self.field_renames.keys())
+            .field("live_bindings", &self.live_bindings.keys())
             .finish()
     }
 }
@@ -80,14 +192,99 @@ impl DeviceSpawnerService {
         Self {
             actor_system,
             map: devices.map(|d| (d.get_device_type(), d)).collect(),
+            mailbox_capacities: Default::default(),
+            recorded_messages: Default::default(),
+            field_renames: Default::default(),
+            live_bindings: Default::default(),
+            live_updates: Default::default(),
+        }
+    }
+
+    pub fn with_mailbox_capacities(self, mailbox_capacities: HashMap<String, usize>) -> Self {
+        Self {
+            mailbox_capacities: Arc::new(mailbox_capacities),
+            ..self
+        }
+    }
+
+    /// Devices of these types record the given number of received messages, unless a plugin set a recorder already
+    pub fn with_recorded_messages(self, recorded_messages: HashMap<String, usize>) -> Self {
+        Self {
+            recorded_messages: Arc::new(recorded_messages),
+            ..self
+        }
+    }
+
+    pub fn with_field_renames(self, renames: impl Iterator<Item = FieldRenames>) -> Self {
+        let mut field_renames = HashMap::<_, FieldRenames>::new();
+        for r in renames {
+            let merged = match field_renames.remove(r.device_type()) {
+                Some(existing) => existing.merge(r),
+                None => r,
+            };
+            field_renames.insert(merged.device_type(), merged);
+        }
+        Self {
+            field_renames: Arc::new(field_renames),
+            ..self
+        }
+    }
+
+    pub fn with_live_bindings(self, bindings: impl Iterator<Item = LiveBindings>) -> Self {
+        let mut live_bindings = HashMap::<_, LiveBindings>::new();
+        for b in bindings {
+            let merged = match live_bindings.remove(b.device_type()) {
+                Some(existing) => existing.merge(b),
+                None => b,
+            };
+            live_bindings.insert(merged.device_type(), merged);
+        }
+        Self {
+            live_bindings: Arc::new(live_bindings),
+            ..self
         }
     }
+
+    /// Renamed fields are migrated before validation, so devices with `deny_unknown_fields` can start with old recipes
+    fn migrate_fields(
+        &self,
+        device_type: &str,
+        mut ctx: DeviceContext,
+    ) -> (DeviceContext, Option<UntypedDeviceParamsWithVariables>) {
+        let migrated = self
+            .field_renames
+            .get(device_type)
+            .and_then(|r| ctx.migrate_fields(r));
+        if migrated.is_some() {
+            info!(
+                "Migrated renamed fields of device {} ({device_type})",
+                ctx.id
+            );
+        }
+        (ctx, migrated)
+    }
+
     fn get_spawner(&self, device_type: &str) -> anyhow::Result<&dyn DeviceHandler> {
         self.map
             .get(device_type)
             .map(|f| f.as_ref())
             .ok_or_else(|| anyhow!("Unknown DeviceType {device_type}"))
     }
+    fn override_configured_mailbox_capacity(&self, device_type: &str, device_id: DeviceId) {
+        self.actor_system.override_mailbox_capacity(
+            device_id,
+            self.mailbox_capacities.get(device_type).copied(),
+        );
+    }
+    fn record_configured_messages(&self, device_type: &str, device_id: DeviceId) {
+        if let Some(capacity) = self.recorded_messages.get(device_type) {
+            if self.actor_system.message_recorder(device_id).is_none() {
+                self.actor_system
+                    .record_messages(device_id, Some(MessageRecorder::new(*capacity)));
+            }
+        }
+    }
+
     pub fn spawn(
         &self,
         device_type: &str,
@@ -98,7 +295,10 @@ impl DeviceSpawnerService {
         let x = self
             .get_spawner(device_type)
             .map_err(|_| StartDeviceError::UnknownDeviceType);
-        async move { Ok(x?.spawn(ctx, provider).await?) }.boxed()
+        self.override_configured_mailbox_capacity(device_type, ctx.id);
+        self.record_configured_messages(device_type, ctx.id);
+        let (ctx, migrated) = self.migrate_fields(device_type, ctx);
+        async move { Ok(x?.spawn(ctx, provider).await?.or_update(migrated)) }.boxed()
     }
 }
 pub struct ChangeParamsStrategy {
@@ -146,7 +346,11 @@ impl<'a, TRecipe: DerefMut<Target = Recipes>> RecipeDataService<'a, TRecipe> {
         let new_params = (modifier)(&device.params, msg)?;
 
         let variables = self
-            .apply_params(device_id, &new_params, Default::default())
+            .apply_params(
+                Some((device_id, &new_params)),
+                Default::default(),
+                options.origin(),
+            )
             .await?;
 
         options.update_device_params(self.recipes.get_active().1, device_id, new_params)?;
@@ -224,6 +428,9 @@ mod testutil {
         ) -> BoxFuture<Result<(), TransactionError>> {
             futures::future::ready(Ok(())).boxed()
         }
+        fn is_live_bound(&self, _device_type: &str, _pointer: &str) -> bool {
+            false
+        }
     }
 }
 #[cfg(any(test, feature = "unstable"))]
@@ -280,4 +487,60 @@ mod tests {
         dir.close()?;
         Ok(())
     }
+
+    #[test]
+    fn record_messages_of_configured_device_types() {
+        let system = ActorSystem::new();
+        let spawner = DeviceSpawnerService::new(std::iter::empty(), system.clone())
+            .with_recorded_messages(HashMap::from([("camera".to_string(), 10)]));
+        let (camera, other, with_payload) =
+            (DeviceId::new_v4(), DeviceId::new_v4(), DeviceId::new_v4());
+        let plugin_recorder = MessageRecorder::new(1);
+        system.record_messages(with_payload, Some(plugin_recorder.clone()));
+
+        spawner.record_configured_messages("camera", camera);
+        spawner.record_configured_messages("other", other);
+        spawner.record_configured_messages("camera", with_payload);
+
+        assert!(system.message_recorder(camera).is_some());
+        assert!(system.message_recorder(other).is_none());
+        assert_eq!(
+            format!("{plugin_recorder:?}"),
+            format!("{:?}", system.message_recorder(with_payload).unwrap())
+        );
+    }
+
+    #[test]
+    fn override_mailbox_capacity_of_configured_device_types() {
+        struct Ping;
+        impl pilatus::device::ActorMessage for Ping {
+            type Output = ();
+            type Error = ();
+        }
+        fn accepted_messages(system: &ActorSystem, id: DeviceId) -> usize {
+            let _device = system.register::<()>(id);
+            let mut sender = system.get_sender::<Ping>(id).unwrap();
+            (0..).take_while(|_| sender.tell(Ping).is_ok()).count()
+        }
+
+        let system = ActorSystem::new();
+        let spawner = DeviceSpawnerService::new(std::iter::empty(), system.clone())
+            .with_mailbox_capacities(HashMap::from([("camera".to_string(), 20)]));
+        let (camera, other) = (DeviceId::new_v4(), DeviceId::new_v4());
+
+        spawner.override_configured_mailbox_capacity("camera", camera);
+        spawner.override_configured_mailbox_capacity("other", other);
+        // Each sender has one guaranteed slot in addition to the mailbox capacity
+        assert_eq!(21, accepted_messages(&system, camera));
+        assert_eq!(
+            ActorSystem::DEFAULT_MAILBOX_CAPACITY + 1,
+            accepted_messages(&system, other)
+        );
+
+        spawner.override_configured_mailbox_capacity("other", camera);
+        assert_eq!(
+            ActorSystem::DEFAULT_MAILBOX_CAPACITY + 1,
+            accepted_messages(&system, camera)
+        );
+    }
 }
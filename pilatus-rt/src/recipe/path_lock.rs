@@ -0,0 +1,55 @@
+//! Advisory locks for files accessed through TokioFileService
+//! FileServices are cheap to build and many instances might exist for the same device, so locks are registered globally by path.
+
+use std::{
+    collections::HashMap,
+    path::{Path, PathBuf},
+    sync::{Arc, Mutex, OnceLock, Weak},
+};
+
+use tokio::sync::{Mutex as AsyncMutex, OwnedMutexGuard};
+
+type Registry = Mutex<HashMap<PathBuf, Weak<AsyncMutex<()>>>>;
+
+fn registry() -> &'static Registry {
+    static LOCKS: OnceLock<Registry> = OnceLock::new();
+    LOCKS.get_or_init(Default::default)
+}
+
+/// Waits until no other writer holds the lock for `path`. The lock is released when the guard is dropped
+pub(super) async fn lock_path(path: &Path) -> OwnedMutexGuard<()> {
+    let lock = {
+        let mut locks = registry().lock().expect("Never poisoned");
+        match locks.get(path).and_then(Weak::upgrade) {
+            Some(x) => x,
+            None => {
+                locks.retain(|_, l| l.strong_count() > 0);
+                let lock = Arc::new(AsyncMutex::new(()));
+                locks.insert(path.to_path_buf(), Arc::downgrade(&lock));
+                lock
+            }
+        }
+    };
+    lock.lock_owned().await
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use super::*;
+
+    #[tokio::test]
+    async fn same_path_is_exclusive() {
+        let path = Path::new("/tmp/pilatus_path_lock_test");
+        let guard = lock_path(path).await;
+        assert!(
+            tokio::time::timeout(Duration::from_millis(10), lock_path(path))
+                .await
+                .is_err()
+        );
+        let _other = lock_path(Path::new("/tmp/pilatus_path_lock_test_other")).await;
+        drop(guard);
+        let _guard = lock_path(path).await;
+    }
+}
@@ -18,7 +18,8 @@ pub(super) async fn recipes_try_add_new_with_id(
         match device_actions
             .validate(
                 &device.device_type,
-                DeviceContext::new(id, vars.clone(), device.params.clone()),
+                DeviceContext::new(id, vars.clone(), device.params.clone())
+                    .with_simulated(device.simulated),
             )
             .await
         {
@@ -11,7 +11,9 @@ use tracing::debug;
 
 use super::InitRecipeListener;
 use crate::recipe::RecipeServiceAccessor;
-use pilatus::{Recipe, Recipes};
+use pilatus::{
+    GenericConfig, InitRecipeContext, MachineCapacity, MaintenanceMode, Recipe, Recipes,
+};
 
 use super::actions::DeviceActions;
 
@@ -20,6 +22,13 @@ pub struct RecipeServiceBuilder {
     // Responsible for changes in the running configuration
     device_actions: Arc<dyn DeviceActions>,
     listeners: Vec<InitRecipeListener>,
+    config: GenericConfig,
+    unlock_token: Option<String>,
+    file_versions: usize,
+    production: bool,
+    capacity: MachineCapacity,
+    maintenance: MaintenanceMode,
+    temp_dir: Option<PathBuf>,
     pub(super) change_strategies:
         HashMap<(&'static str, std::any::TypeId), Box<dyn Any + Send + Sync>>,
 }
@@ -32,6 +41,13 @@ impl RecipeServiceBuilder {
             path: path.into(),
             device_actions,
             listeners: Default::default(),
+            config: Default::default(),
+            unlock_token: None,
+            file_versions: 0,
+            production: false,
+            capacity: Default::default(),
+            maintenance: Default::default(),
+            temp_dir: None,
             change_strategies: Default::default(),
         }
     }
@@ -46,18 +62,75 @@ impl RecipeServiceBuilder {
         self
     }
 
-    pub fn build(self) -> RecipeServiceAccessor {
+    /// Passed to InitRecipeListeners
+    pub fn with_config(mut self, config: GenericConfig) -> Self {
+        self.config = config;
+        self
+    }
+
+    /// Without token, locked devices can't be changed until they are unlocked
+    pub fn with_unlock_token(mut self, token: Option<String>) -> Self {
+        self.unlock_token = token;
+        self
+    }
+
+    /// Number of previous versions kept per device file. 0 disables versioning
+    pub fn with_file_versions(mut self, keep: usize) -> Self {
+        self.file_versions = keep;
+        self
+    }
+
+    /// Production installations only activate released recipes
+    pub fn with_production(mut self, production: bool) -> Self {
+        self.production = production;
+        self
+    }
+
+    /// Recipes which exceed the capacity or claim the same exclusive interface twice can't be activated
+    pub fn with_machine_capacity(mut self, capacity: MachineCapacity) -> Self {
+        self.capacity = capacity;
+        self
+    }
+
+    /// Imports are extracted there. Defaults to `tmp` in the root path
+    pub fn with_temp_dir(mut self, temp_dir: impl Into<PathBuf>) -> Self {
+        self.temp_dir = Some(temp_dir.into());
+        self
+    }
+
+    /// Recipes can't be changed while the maintenance mode is enabled
+    pub fn with_maintenance(mut self, maintenance: MaintenanceMode) -> Self {
+        self.maintenance = maintenance;
+        self
+    }
+
+    pub fn build(mut self) -> RecipeServiceAccessor {
+        // Stable, so listeners with equal priority keep their registration order
+        self.listeners
+            .sort_by_key(|l| std::cmp::Reverse(l.priority()));
+        let listeners = InitRecipeListeners {
+            listeners: self.listeners,
+            config: self.config,
+        };
+        let temp_dir = self.temp_dir.unwrap_or_else(|| self.path.join("tmp"));
         let mut path = self.path.join("recipes"); // /root/recipes
         for c in 1..100 {
-            match Self::try_from_file_or_new(&path, self.listeners.as_ref()) {
+            match Self::try_from_file_or_new(&path, &listeners) {
                 Ok(recipes) => {
                     let (update_sender, _) = tokio::sync::broadcast::channel(10);
                     return RecipeServiceAccessor {
                         device_actions: self.device_actions,
                         path,
                         recipes: Arc::new(RwLock::new(recipes)),
-                        listeners: self.listeners,
+                        listeners,
+                        unlock_token: self.unlock_token,
+                        file_versions: self.file_versions,
+                        production: self.production,
+                        capacity: self.capacity,
+                        maintenance: self.maintenance,
+                        temp_dir,
                         update_sender,
+                        disk_sizes: Default::default(),
                         change_strategies: self.change_strategies,
                     };
                 }
@@ -68,7 +141,7 @@ impl RecipeServiceBuilder {
         }
         panic!("RecipeService cannot be started");
     }
-    fn try_from_file_or_new(path: &Path, listeners: &[InitRecipeListener]) -> io::Result<Recipes> {
+    fn try_from_file_or_new(path: &Path, listeners: &InitRecipeListeners) -> io::Result<Recipes> {
         let recipes: Recipes;
         let path = path.to_path_buf();
         std::fs::create_dir_all(&path)?; //create directory and all of its parent components if they are missing.
@@ -84,9 +157,7 @@ impl RecipeServiceBuilder {
             let mut r = Recipe::default();
 
             //add all default devices
-            for listener in listeners {
-                listener.call(&mut r);
-            }
+            listeners.apply(&mut r);
 
             recipes = Recipes::new_with_recipe(r);
             recipes.store_sync(jpath.clone())?;
@@ -96,3 +167,71 @@ impl RecipeServiceBuilder {
         Ok(recipes)
     }
 }
+
+/// Ordered by priority
+pub(super) struct InitRecipeListeners {
+    listeners: Vec<InitRecipeListener>,
+    config: GenericConfig,
+}
+
+impl InitRecipeListeners {
+    pub(super) fn apply(&self, recipe: &mut Recipe) {
+        let ctx = InitRecipeContext::new(&self.config);
+        for listener in self.listeners.iter() {
+            listener.call(recipe, &ctx);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use pilatus::{DeviceConfig, Name};
+
+    use super::*;
+    use crate::recipe::parameters::LambdaRecipePermissioner;
+
+    #[tokio::test]
+    async fn listeners_run_by_priority_with_config() {
+        let dir = tempfile::tempdir().unwrap();
+        let builder =
+            RecipeServiceBuilder::new(dir.path(), Arc::new(LambdaRecipePermissioner::always_ok()))
+                .with_config(GenericConfig::mock(serde_json::json!({ "camera": "real" })))
+                .with_initializer(InitRecipeListener::new(|r| {
+                    if r.devices.is_empty() {
+                        r.add_device(
+                            DeviceConfig::new(
+                                "emulation",
+                                Name::new("Emulation").unwrap(),
+                                serde_json::json!({}),
+                            )
+                            .unwrap(),
+                        );
+                    }
+                }))
+                .with_initializer(
+                    InitRecipeListener::with_context(|r, ctx| {
+                        if ctx.config.get::<String>("camera").is_ok() {
+                            r.add_device(
+                                DeviceConfig::new(
+                                    "camera",
+                                    Name::new("Camera").unwrap(),
+                                    serde_json::json!({}),
+                                )
+                                .unwrap(),
+                            );
+                        }
+                    })
+                    .with_priority(10),
+                );
+
+        let accessor = builder.build();
+        let recipes = accessor.recipes.read().await;
+        let (_, active) = recipes.active();
+        let types = active
+            .devices
+            .iter_unordered()
+            .map(|(_, d)| d.get_device_type().to_string())
+            .collect::<Vec<_>>();
+        assert_eq!(vec!["camera".to_string()], types);
+    }
+}
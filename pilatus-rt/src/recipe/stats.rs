@@ -0,0 +1,82 @@
+//! Device folder sizes for RecipeStats
+//!
+//! Walking all device folders can take a while for recipes with large collections (e.g. recorded frames),
+//! so sizes are calculated on the first request and reused for `CACHE_DURATION`
+
+use std::{
+    collections::HashMap,
+    io::{self, ErrorKind},
+    path::Path,
+    sync::Mutex,
+    time::{Duration, Instant},
+};
+
+use futures::TryStreamExt;
+use pilatus::{device::DeviceId, visit_directory_files};
+
+const CACHE_DURATION: Duration = Duration::from_secs(30);
+
+#[derive(Debug, Default)]
+pub(super) struct DiskSizeCache(Mutex<HashMap<DeviceId, (Instant, u64)>>);
+
+impl DiskSizeCache {
+    pub(super) async fn device_size(&self, recipe_root: &Path, id: DeviceId) -> io::Result<u64> {
+        if let Some(size) = self.get_valid(id) {
+            return Ok(size);
+        }
+        let size = directory_size(&recipe_root.join(id.to_string())).await?;
+        self.0
+            .lock()
+            .expect("Never poisoned")
+            .insert(id, (Instant::now(), size));
+        Ok(size)
+    }
+
+    /// Forces recalculation, e.g. after files of the device were imported
+    pub(super) fn invalidate(&self, id: DeviceId) {
+        self.0.lock().expect("Never poisoned").remove(&id);
+    }
+
+    fn get_valid(&self, id: DeviceId) -> Option<u64> {
+        let mut cache = self.0.lock().expect("Never poisoned");
+        cache.retain(|_, (created, _)| created.elapsed() < CACHE_DURATION);
+        cache.get(&id).map(|(_, size)| *size)
+    }
+}
+
+/// A missing directory has size 0
+async fn directory_size(dir: &Path) -> io::Result<u64> {
+    let mut size = 0;
+    let mut files = std::pin::pin!(visit_directory_files(dir));
+    loop {
+        match files.try_next().await {
+            Ok(Some(entry)) => size += entry.metadata().await?.len(),
+            Ok(None) => break,
+            Err(e) if e.kind() == ErrorKind::NotFound && size == 0 => break,
+            Err(e) => return Err(e),
+        }
+    }
+    Ok(size)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn cache_size_until_invalidated() -> anyhow::Result<()> {
+        let dir = tempfile::tempdir()?;
+        let id = DeviceId::new_v4();
+        let cache = DiskSizeCache::default();
+        assert_eq!(0, cache.device_size(dir.path(), id).await?);
+
+        let device_dir = dir.path().join(id.to_string()).join("sub");
+        tokio::fs::create_dir_all(&device_dir).await?;
+        tokio::fs::write(device_dir.join("a.txt"), "abc").await?;
+        assert_eq!(0, cache.device_size(dir.path(), id).await?);
+
+        cache.invalidate(id);
+        assert_eq!(3, cache.device_size(dir.path(), id).await?);
+        Ok(())
+    }
+}
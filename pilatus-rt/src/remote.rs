@@ -0,0 +1,581 @@
+//! TCP-Transport for `pilatus::device::RemoteMessages`.
+//! Frames are newline-delimited JSON. Each request carries an id, which is used to match the response,
+//! so many requests can be in flight on a single connection.
+//! The first frame of every connection is a [`HelloFrame`]. Listeners with a secret close connections which don't present it.
+
+use std::{
+    collections::HashMap,
+    net::SocketAddr,
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Arc, Mutex,
+    },
+    time::Duration,
+};
+
+use futures::{
+    future::{BoxFuture, Either},
+    FutureExt, StreamExt,
+};
+use minfac::{AllRegistered, Registered, ServiceCollection};
+use pilatus::{
+    device::{
+        ActorSystem, DeviceId, RemoteActorError, RemoteMessage, RemoteMessages, RemoteRequest,
+        RemoteResponse, RemoteTransport,
+    },
+    prelude::*,
+    GenericConfig, SystemShutdown,
+};
+use serde::{Deserialize, Serialize};
+use tokio::{
+    io::AsyncWriteExt,
+    net::{
+        tcp::{OwnedReadHalf, OwnedWriteHalf},
+        TcpListener, TcpStream,
+    },
+    sync::{mpsc, oneshot, Semaphore},
+};
+use tokio_util::codec::{FramedRead, LinesCodec};
+use tracing::{debug, info, warn};
+
+/// Connections sending longer frames are closed
+const MAX_FRAME_LEN: usize = 16 * 1024 * 1024;
+/// Connections which don't send a [`HelloFrame`] in time are closed
+const HELLO_TIMEOUT: Duration = Duration::from_secs(10);
+/// Per connection and direction. Further requests wait until a response arrives
+const MAX_REQUESTS_IN_FLIGHT: usize = 64;
+
+pub(super) fn register_services(c: &mut ServiceCollection) {
+    c.with::<AllRegistered<RemoteMessage>>()
+        .register(RemoteMessages::new);
+    c.register_instance(pilatus::ServiceInfo::transient::<RemoteMessages>());
+    c.with::<(
+        Registered<GenericConfig>,
+        Registered<ActorSystem>,
+        Registered<RemoteMessages>,
+        Registered<SystemShutdown>,
+    )>()
+    .register_hosted_service("Remote Actor Bridge", run_bridge);
+}
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+struct RemoteConfig {
+    /// Accept requests from other nodes. Addresses other than loopback require a `secret`
+    listen: Option<SocketAddr>,
+    /// Shared by all nodes. It is presented to `nodes` and required from everyone connecting to `listen`
+    secret: Option<String>,
+    nodes: Vec<RemoteNodeConfig>,
+}
+
+/// Requests to `devices` are forwarded to the node at `address`
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct RemoteNodeConfig {
+    address: String,
+    devices: Vec<DeviceId>,
+}
+
+#[derive(Serialize, Deserialize)]
+struct HelloFrame {
+    secret: Option<String>,
+}
+
+#[derive(Serialize, Deserialize)]
+struct RequestFrame {
+    id: u64,
+    request: RemoteRequest,
+}
+
+#[derive(Serialize, Deserialize)]
+struct ResponseFrame {
+    id: u64,
+    response: RemoteResponse,
+}
+
+async fn run_bridge(
+    (config, system, messages, shutdown): (
+        GenericConfig,
+        ActorSystem,
+        RemoteMessages,
+        SystemShutdown,
+    ),
+) -> anyhow::Result<()> {
+    let config = config
+        .get::<RemoteConfig>("remote_actors")
+        .unwrap_or_default();
+    if config.listen.is_none() && config.nodes.is_empty() {
+        return Ok(());
+    }
+    if let Some(address) = config.listen {
+        if config.secret.is_none() && !address.ip().is_loopback() {
+            anyhow::bail!(
+                "remote_actors.listen on {address} requires remote_actors.secret, only loopback addresses may omit it"
+            );
+        }
+    }
+    let secret: Option<Arc<str>> = config.secret.as_deref().map(Into::into);
+
+    let server = async {
+        match config.listen {
+            Some(address) => {
+                let listener = TcpListener::bind(address).await?;
+                info!("Listening for remote nodes on {address}");
+                serve(listener, system.clone(), messages.clone(), secret.clone()).await
+            }
+            None => futures::future::pending().await,
+        }
+    };
+    let proxies = futures::future::join_all(config.nodes.iter().flat_map(|node| {
+        let transport: Arc<dyn RemoteTransport> = Arc::new(TcpTransport::new(
+            node.address.clone(),
+            config.secret.clone(),
+        ));
+        let (system, messages) = (&system, &messages);
+        node.devices
+            .iter()
+            .map(move |id| keep_proxy_alive(system, messages, *id, transport.clone()))
+    }));
+    futures::pin_mut!(server, proxies);
+    let work = futures::future::select(server, proxies).map(|x| match x {
+        Either::Left((r, _)) => r,
+        Either::Right(_) => Ok(()),
+    });
+
+    match futures::future::select(work, shutdown).await {
+        Either::Left((r, _)) => r,
+        Either::Right(_) => Ok(()),
+    }
+}
+
+/// Proxies stop whenever the senders of the ActorSystem are forgotten (e.g. on recipe change), so they are registered again
+async fn keep_proxy_alive(
+    system: &ActorSystem,
+    messages: &RemoteMessages,
+    device_id: DeviceId,
+    transport: Arc<dyn RemoteTransport>,
+) {
+    loop {
+        messages
+            .spawn_proxy(system, device_id, transport.clone())
+            .await;
+        debug!("Proxy for remote device {device_id} stopped. Register it again");
+        tokio::time::sleep(Duration::from_millis(10)).await;
+    }
+}
+
+async fn serve(
+    listener: TcpListener,
+    system: ActorSystem,
+    messages: RemoteMessages,
+    secret: Option<Arc<str>>,
+) -> anyhow::Result<()> {
+    loop {
+        let (stream, address) = listener.accept().await?;
+        info!("Remote node connected from {address}");
+        tokio::spawn(serve_connection(
+            stream,
+            address,
+            system.clone(),
+            messages.clone(),
+            secret.clone(),
+        ));
+    }
+}
+
+async fn serve_connection(
+    stream: TcpStream,
+    address: SocketAddr,
+    system: ActorSystem,
+    messages: RemoteMessages,
+    secret: Option<Arc<str>>,
+) {
+    let (read, write) = stream.into_split();
+    let mut lines = read_frames(read);
+    let hello = match tokio::time::timeout(HELLO_TIMEOUT, next_frame(&mut lines)).await {
+        Ok(Some(line)) => serde_json::from_str::<HelloFrame>(&line).ok(),
+        _ => None,
+    };
+    if !hello.is_some_and(|h| is_authorized(secret.as_deref(), h.secret.as_deref())) {
+        warn!(
+            "Closed connection of remote node {address}, which didn't present the secret in time"
+        );
+        return;
+    }
+    let (tx, rx) = mpsc::channel::<String>(MAX_REQUESTS_IN_FLIGHT);
+    let in_flight = Arc::new(Semaphore::new(MAX_REQUESTS_IN_FLIGHT));
+    let read_task = async move {
+        while let Some(line) = next_frame(&mut lines).await {
+            let frame = match serde_json::from_str::<RequestFrame>(&line) {
+                Ok(x) => x,
+                Err(e) => {
+                    warn!("Invalid request from remote node: {e}");
+                    continue;
+                }
+            };
+            // No more frames are read until a response is written, so the remote node is slowed down instead of piling up tasks
+            let permit = in_flight
+                .clone()
+                .acquire_owned()
+                .await
+                .expect("Semaphore is never closed");
+            let response = messages.dispatch(system.clone(), frame.request);
+            let tx = tx.clone();
+            tokio::spawn(async move {
+                let frame = ResponseFrame {
+                    id: frame.id,
+                    response: response.await,
+                };
+                match serde_json::to_string(&frame) {
+                    Ok(x) => {
+                        let _ignore_disconnected = tx.send(x).await;
+                    }
+                    Err(e) => warn!("Couldn't serialize response for remote node: {e}"),
+                }
+                drop(permit);
+            });
+        }
+    };
+    futures::future::join(read_task, write_frames(write, rx)).await;
+}
+
+/// Compares in constant time, so the secret can't be guessed byte by byte
+fn is_authorized(expected: Option<&str>, provided: Option<&str>) -> bool {
+    let Some(expected) = expected else {
+        return true;
+    };
+    let Some(provided) = provided else {
+        return false;
+    };
+    expected.len() == provided.len()
+        && expected
+            .bytes()
+            .zip(provided.bytes())
+            .fold(0, |acc, (a, b)| acc | (a ^ b))
+            == 0
+}
+
+type Frames = FramedRead<OwnedReadHalf, LinesCodec>;
+
+fn read_frames(read: OwnedReadHalf) -> Frames {
+    FramedRead::new(read, LinesCodec::new_with_max_length(MAX_FRAME_LEN))
+}
+
+/// None if the connection is closed or the frame exceeds [`MAX_FRAME_LEN`]
+async fn next_frame(frames: &mut Frames) -> Option<String> {
+    match frames.next().await? {
+        Ok(line) => Some(line),
+        Err(e) => {
+            warn!("Closing remote connection: {e}");
+            None
+        }
+    }
+}
+
+async fn write_frames(mut write: OwnedWriteHalf, mut rx: mpsc::Receiver<String>) {
+    while let Some(mut frame) = rx.recv().await {
+        frame.push('\n');
+        if let Err(e) = write.write_all(frame.as_bytes()).await {
+            debug!("Remote connection closed: {e}");
+            break;
+        }
+    }
+}
+
+type PendingResponses = Arc<Mutex<HashMap<u64, oneshot::Sender<RemoteResponse>>>>;
+
+#[derive(Clone)]
+struct Connection {
+    frames: mpsc::Sender<String>,
+    pending: PendingResponses,
+    in_flight: Arc<Semaphore>,
+}
+
+impl Connection {
+    fn spawn(stream: TcpStream, hello: String) -> Self {
+        let (read, write) = stream.into_split();
+        let (frames, rx) = mpsc::channel(MAX_REQUESTS_IN_FLIGHT);
+        frames
+            .try_send(hello)
+            .expect("Channel is empty and its receiver is alive until the end of this function");
+        let pending = PendingResponses::default();
+        let reader_pending = pending.clone();
+        tokio::spawn(async move {
+            let write_task = write_frames(write, rx);
+            let read_task = read_responses(read, &reader_pending);
+            futures::pin_mut!(write_task, read_task);
+            futures::future::select(write_task, read_task).await;
+            // Dropping the senders fails all pending requests
+            reader_pending.lock().expect("Never poisoned").clear();
+        });
+        Self {
+            frames,
+            pending,
+            in_flight: Arc::new(Semaphore::new(MAX_REQUESTS_IN_FLIGHT)),
+        }
+    }
+}
+
+async fn read_responses(read: OwnedReadHalf, pending: &PendingResponses) {
+    let mut lines = read_frames(read);
+    while let Some(line) = next_frame(&mut lines).await {
+        match serde_json::from_str::<ResponseFrame>(&line) {
+            Ok(frame) => {
+                if let Some(tx) = pending.lock().expect("Never poisoned").remove(&frame.id) {
+                    let _ignore_dropped_request = tx.send(frame.response);
+                }
+            }
+            Err(e) => warn!("Invalid response from remote node: {e}"),
+        }
+    }
+}
+
+/// Transport to the bridge of a running pilatus process, which is configured with `remote_actors.listen`.
+/// `secret` must match `remote_actors.secret` of that process
+pub fn connect_remote_node(
+    address: impl Into<String>,
+    secret: Option<String>,
+) -> Arc<dyn RemoteTransport> {
+    Arc::new(TcpTransport::new(address.into(), secret))
+}
+
+#[derive(Clone)]
+struct TcpTransport(Arc<TcpTransportInner>);
+
+struct TcpTransportInner {
+    address: String,
+    secret: Option<String>,
+    next_id: AtomicU64,
+    connection: tokio::sync::Mutex<Option<Connection>>,
+}
+
+impl TcpTransport {
+    fn new(address: String, secret: Option<String>) -> Self {
+        Self(Arc::new(TcpTransportInner {
+            address,
+            secret,
+            next_id: AtomicU64::new(0),
+            connection: Default::default(),
+        }))
+    }
+
+    /// Connects lazily and reconnects, if the previous connection was lost
+    async fn connection(&self) -> Result<Connection, RemoteActorError> {
+        let mut lock = self.0.connection.lock().await;
+        if let Some(c) = lock.as_ref().filter(|c| !c.frames.is_closed()) {
+            return Ok(c.clone());
+        }
+        let stream = TcpStream::connect(&self.0.address).await.map_err(|e| {
+            RemoteActorError::Other(format!("Cannot connect to {}: {e}", self.0.address))
+        })?;
+        let hello = serde_json::to_string(&HelloFrame {
+            secret: self.0.secret.clone(),
+        })
+        .map_err(|e| RemoteActorError::Other(e.to_string()))?;
+        let connection = Connection::spawn(stream, hello);
+        *lock = Some(connection.clone());
+        Ok(connection)
+    }
+
+    async fn send(self, request: RemoteRequest) -> RemoteResponse {
+        let connection_lost =
+            || RemoteActorError::Other(format!("Connection to {} lost", self.0.address));
+        let id = self.0.next_id.fetch_add(1, Ordering::Relaxed);
+        let frame = serde_json::to_string(&RequestFrame { id, request })
+            .map_err(|e| RemoteActorError::Other(e.to_string()))?;
+        let connection = self.connection().await?;
+        let _permit = connection
+            .in_flight
+            .acquire()
+            .await
+            .expect("Semaphore is never closed");
+        let (tx, rx) = oneshot::channel();
+        connection
+            .pending
+            .lock()
+            .expect("Never poisoned")
+            .insert(id, tx);
+        if connection.frames.send(frame).await.is_err() {
+            connection
+                .pending
+                .lock()
+                .expect("Never poisoned")
+                .remove(&id);
+            return Err(connection_lost());
+        }
+        rx.await.unwrap_or_else(|_| Err(connection_lost()))
+    }
+}
+
+impl RemoteTransport for TcpTransport {
+    fn request(&self, request: RemoteRequest) -> BoxFuture<'static, RemoteResponse> {
+        self.clone().send(request).boxed()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use pilatus::device::{ActorError, ActorMessage, ActorResult};
+    use tokio::io::AsyncReadExt;
+
+    use super::*;
+
+    #[derive(Serialize, Deserialize, ActorMessage)]
+    #[actor_message(output = String, error = (), name = "echo")]
+    struct EchoMessage(String);
+
+    #[tokio::test]
+    async fn forward_over_tcp() -> anyhow::Result<()> {
+        let local = ActorSystem::new();
+        let remote = ActorSystem::new();
+        let messages = RemoteMessages::new([RemoteMessage::new::<EchoMessage>()]);
+        let id = DeviceId::new_v4();
+        async fn echo(_: &mut (), msg: EchoMessage) -> ActorResult<EchoMessage> {
+            Ok(msg.0)
+        }
+        let remote_device = remote.register(id).add_handler(echo).execute(());
+
+        let listener = TcpListener::bind("127.0.0.1:0").await?;
+        let address = listener.local_addr()?;
+        tokio::spawn(serve(
+            listener,
+            remote.clone(),
+            messages.clone(),
+            Some("secret".into()),
+        ));
+        let proxy = messages.spawn_proxy(
+            &local,
+            id,
+            Arc::new(TcpTransport::new(
+                address.to_string(),
+                Some("secret".into()),
+            )),
+        );
+
+        let test = async {
+            assert_eq!(
+                Ok("hello".to_string()),
+                local.ask(id, EchoMessage("hello".into())).await
+            );
+            assert!(matches!(
+                local
+                    .ask(DeviceId::new_v4(), EchoMessage("unknown".into()))
+                    .await,
+                Err(ActorError::UnknownDevice(_))
+            ));
+            local.forget_senders();
+            remote.forget_senders();
+        };
+        tokio::time::timeout(
+            Duration::from_secs(5),
+            futures::future::join3(remote_device, proxy, test),
+        )
+        .await?;
+        Ok(())
+    }
+    #[tokio::test]
+    async fn close_connections_without_secret() -> anyhow::Result<()> {
+        let remote = ActorSystem::new();
+        let messages = RemoteMessages::new([RemoteMessage::new::<EchoMessage>()]);
+        let listener = TcpListener::bind("127.0.0.1:0").await?;
+        let address = listener.local_addr()?.to_string();
+        tokio::spawn(serve(
+            listener,
+            remote.clone(),
+            messages.clone(),
+            Some("secret".into()),
+        ));
+        let request = || RemoteRequest {
+            device_id: DeviceId::new_v4(),
+            message: "echo".into(),
+            payload: serde_json::Value::Null,
+        };
+        for secret in [None, Some("wrong".to_string())] {
+            let response = tokio::time::timeout(
+                Duration::from_secs(5),
+                TcpTransport::new(address.clone(), secret).request(request()),
+            )
+            .await?;
+            assert!(
+                matches!(response, Err(RemoteActorError::Other(ref e)) if e.contains("lost")),
+                "{response:?}"
+            );
+        }
+        Ok(())
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn close_connections_without_hello_in_time() -> anyhow::Result<()> {
+        let listener = TcpListener::bind("127.0.0.1:0").await?;
+        let address = listener.local_addr()?;
+        tokio::spawn(serve(
+            listener,
+            ActorSystem::new(),
+            RemoteMessages::new([]),
+            Some("secret".into()),
+        ));
+        let mut stream = TcpStream::connect(address).await?;
+        let start = tokio::time::Instant::now();
+        assert_eq!(0, stream.read(&mut [0; 1]).await?);
+        assert!(start.elapsed() >= HELLO_TIMEOUT);
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn close_connections_with_oversized_frames() -> anyhow::Result<()> {
+        let listener = TcpListener::bind("127.0.0.1:0").await?;
+        let address = listener.local_addr()?;
+        tokio::spawn(serve(
+            listener,
+            ActorSystem::new(),
+            RemoteMessages::new([]),
+            None,
+        ));
+        let mut stream = TcpStream::connect(address).await?;
+        let closed = async {
+            // Writing fails, if the connection is closed before everything is sent
+            let _ignore_closed = stream.write_all(&vec![b'a'; MAX_FRAME_LEN + 1]).await;
+            let mut buf = [0; 1];
+            matches!(stream.read(&mut buf).await, Ok(0) | Err(_))
+        };
+        assert!(tokio::time::timeout(Duration::from_secs(5), closed).await?);
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn answer_more_requests_than_allowed_in_flight() -> anyhow::Result<()> {
+        let remote = ActorSystem::new();
+        let messages = RemoteMessages::new([RemoteMessage::new::<EchoMessage>()]);
+        let id = DeviceId::new_v4();
+        async fn echo(_: &mut (), msg: EchoMessage) -> ActorResult<EchoMessage> {
+            Ok(msg.0)
+        }
+        let remote_device = remote.register(id).add_handler(echo).execute(());
+        let listener = TcpListener::bind("127.0.0.1:0").await?;
+        let address = listener.local_addr()?.to_string();
+        tokio::spawn(serve(listener, remote.clone(), messages, None));
+        let transport = TcpTransport::new(address, None);
+
+        let test = async {
+            let responses = futures::future::join_all((0..MAX_REQUESTS_IN_FLIGHT * 3).map(|i| {
+                transport.request(RemoteRequest {
+                    device_id: id,
+                    message: "echo".into(),
+                    payload: serde_json::json!(i.to_string()),
+                })
+            }))
+            .await;
+            remote.forget_senders();
+            responses
+        };
+        let (_, responses) = tokio::time::timeout(
+            Duration::from_secs(5),
+            futures::future::join(remote_device, test),
+        )
+        .await?;
+        for (i, response) in responses.into_iter().enumerate() {
+            assert_eq!(serde_json::json!(i.to_string()), response?);
+        }
+        Ok(())
+    }
+}
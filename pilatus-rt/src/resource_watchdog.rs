@@ -0,0 +1,208 @@
+use std::{collections::HashSet, path::PathBuf, time::Duration};
+
+use minfac::{Registered, ServiceCollection};
+use pilatus::{
+    prelude::*, EventBus, GenericConfig, HealthState, ResourceAction, ResourceKind, ResourceUsage,
+    SystemEventKind, SystemShutdown,
+};
+use serde::Deserialize;
+use sysinfo::{Disks, ProcessRefreshKind, ProcessesToUpdate, System};
+use tracing::{info, warn};
+
+pub(super) fn register_services(c: &mut ServiceCollection) {
+    c.register_shared(|| std::sync::Arc::new(HealthState::default()))
+        .alias(|x| HealthState::clone(&x));
+    c.register_instance(pilatus::ServiceInfo::shared::<HealthState>());
+    c.with::<(
+        Registered<GenericConfig>,
+        Registered<HealthState>,
+        Registered<EventBus>,
+        Registered<SystemShutdown>,
+    )>()
+    .register_hosted_service("Resource Watchdog", watch_resources);
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+struct WatchdogConfig {
+    interval_secs: u64,
+    max_cpu_percent: Option<f32>,
+    max_memory_mb: Option<u64>,
+    min_free_disk_mb: Option<u64>,
+    max_file_descriptors: Option<u64>,
+    actions: HashSet<ResourceAction>,
+}
+
+impl Default for WatchdogConfig {
+    fn default() -> Self {
+        Self {
+            interval_secs: 30,
+            max_cpu_percent: None,
+            max_memory_mb: None,
+            min_free_disk_mb: Some(500),
+            max_file_descriptors: None,
+            actions: HashSet::new(),
+        }
+    }
+}
+
+impl WatchdogConfig {
+    fn exceeded(&self, usage: &ResourceUsage) -> HashSet<ResourceKind> {
+        let mut result = HashSet::new();
+        if let (Some(max), Some(x)) = (self.max_cpu_percent, usage.cpu_percent) {
+            if x > max {
+                result.insert(ResourceKind::Cpu);
+            }
+        }
+        if let (Some(max), Some(x)) = (self.max_memory_mb, usage.memory_bytes) {
+            if x > max * 1_000_000 {
+                result.insert(ResourceKind::Memory);
+            }
+        }
+        if let (Some(min), Some(x)) = (self.min_free_disk_mb, usage.disk_available_bytes) {
+            if x < min * 1_000_000 {
+                result.insert(ResourceKind::Disk);
+            }
+        }
+        if let (Some(max), Some(x)) = (self.max_file_descriptors, usage.open_file_descriptors) {
+            if x > max {
+                result.insert(ResourceKind::FileDescriptors);
+            }
+        }
+        result
+    }
+}
+
+async fn watch_resources(
+    (config, health, events, shutdown): (GenericConfig, HealthState, EventBus, SystemShutdown),
+) -> anyhow::Result<()> {
+    let watchdog_config = config
+        .get::<WatchdogConfig>("resource_watchdog")
+        .unwrap_or_default();
+    let sampler = Sampler::new(config.root.clone());
+    let watch = std::pin::pin!(watch_loop(watchdog_config, sampler, health, events));
+
+    match futures::future::select(watch, shutdown).await {
+        futures::future::Either::Left((r, _)) => r,
+        futures::future::Either::Right(_) => Ok(()),
+    }
+}
+
+async fn watch_loop(
+    config: WatchdogConfig,
+    mut sampler: Sampler,
+    health: HealthState,
+    events: EventBus,
+) -> anyhow::Result<()> {
+    let mut interval = tokio::time::interval(Duration::from_secs(config.interval_secs.max(1)));
+    loop {
+        interval.tick().await;
+        let (returned, usage) = tokio::task::spawn_blocking(move || {
+            let usage = sampler.sample();
+            (sampler, usage)
+        })
+        .await?;
+        sampler = returned;
+
+        let previous = health.report();
+        let exceeded = config.exceeded(&usage);
+        for kind in exceeded.difference(&previous.exceeded) {
+            warn!("Resource threshold exceeded for {kind:?}: {usage:?}");
+            events.publish(SystemEventKind::ResourceExceeded {
+                resource: *kind,
+                message: format!("{usage:?}"),
+            });
+        }
+        for kind in previous.exceeded.difference(&exceeded) {
+            info!("Resource usage for {kind:?} is back to normal");
+        }
+        let active_actions = if exceeded.is_empty() {
+            HashSet::new()
+        } else {
+            config.actions.clone()
+        };
+        health.update(|report| {
+            report.usage = usage;
+            report.exceeded = exceeded;
+            report.active_actions = active_actions;
+        });
+    }
+}
+
+struct Sampler {
+    system: System,
+    disks: Disks,
+    root: PathBuf,
+}
+
+impl Sampler {
+    fn new(root: PathBuf) -> Self {
+        Self {
+            system: System::new(),
+            disks: Disks::new_with_refreshed_list(),
+            root: root.canonicalize().unwrap_or(root),
+        }
+    }
+
+    fn sample(&mut self) -> ResourceUsage {
+        let process = sysinfo::get_current_pid().ok().and_then(|pid| {
+            self.system.refresh_processes_specifics(
+                ProcessesToUpdate::Some(&[pid]),
+                ProcessRefreshKind::new().with_cpu().with_memory(),
+            );
+            self.system.process(pid)
+        });
+
+        self.disks.refresh();
+        // The disk with the longest mount_point containing the root
+        let disk = self
+            .disks
+            .iter()
+            .filter(|d| self.root.starts_with(d.mount_point()))
+            .max_by_key(|d| d.mount_point().as_os_str().len());
+
+        ResourceUsage {
+            cpu_percent: process.map(|p| p.cpu_usage()),
+            memory_bytes: process.map(|p| p.memory()),
+            disk_available_bytes: disk.map(|d| d.available_space()),
+            disk_total_bytes: disk.map(|d| d.total_space()),
+            open_file_descriptors: count_file_descriptors(),
+        }
+    }
+}
+
+#[cfg(target_os = "linux")]
+fn count_file_descriptors() -> Option<u64> {
+    std::fs::read_dir("/proc/self/fd")
+        .ok()
+        .map(|x| x.count() as u64)
+}
+
+#[cfg(not(target_os = "linux"))]
+fn count_file_descriptors() -> Option<u64> {
+    None
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn detect_exceeded_thresholds() {
+        let config = WatchdogConfig {
+            max_memory_mb: Some(100),
+            min_free_disk_mb: Some(10),
+            ..Default::default()
+        };
+        let usage = ResourceUsage {
+            memory_bytes: Some(200_000_000),
+            disk_available_bytes: Some(20_000_000),
+            open_file_descriptors: Some(100_000),
+            ..Default::default()
+        };
+        assert_eq!(
+            HashSet::from([ResourceKind::Memory]),
+            config.exceeded(&usage)
+        );
+    }
+}
@@ -1,6 +1,7 @@
 use futures::{stream::FuturesUnordered, StreamExt};
 use minfac::{ServiceCollection, ServiceProvider};
-use std::{any::Any, path::PathBuf, sync::Arc};
+use serde::Serialize;
+use std::{any::Any, net::SocketAddr, path::PathBuf, sync::Arc};
 use tokio::runtime::Builder;
 use tracing::{error, info};
 
@@ -12,21 +13,85 @@ pub struct Runtime {
     services: ServiceCollection,
     #[cfg(feature = "tracing")]
     tracing: bool,
+    #[cfg(feature = "plugins")]
+    plugins: Vec<libloading::Library>,
 }
 
 impl Default for Runtime {
     fn default() -> Self {
-        Self::with_root(std::env::var("PILATUSROOT").unwrap_or_else(|_| "data".into()))
+        Self::builder().build()
     }
 }
 
-impl Runtime {
-    pub fn with_root(root: impl Into<PathBuf>) -> Self {
-        let root = root.into();
+/// Configures the Runtime from code. Overrides take precedence over the JSON-Files in the data directory
+pub struct RuntimeBuilder {
+    root: PathBuf,
+    overrides: serde_json::Map<String, serde_json::Value>,
+}
+
+impl Default for RuntimeBuilder {
+    fn default() -> Self {
+        Self {
+            root: std::env::var("PILATUSROOT")
+                .unwrap_or_else(|_| "data".into())
+                .into(),
+            overrides: Default::default(),
+        }
+    }
+}
+
+impl RuntimeBuilder {
+    pub fn data_dir(self, root: impl Into<PathBuf>) -> Self {
+        Self {
+            root: root.into(),
+            ..self
+        }
+    }
+
+    /// Use port 0 to bind an ephemeral port. The bound address is available via `pilatus_axum::Stats`
+    pub fn web_address(self, socket: SocketAddr) -> Self {
+        self.config_override("web.socket", socket)
+    }
+
+    /// Doesn't start the webserver, even if it's registered
+    pub fn headless(self) -> Self {
+        self.config_override("web.enabled", false)
+    }
+
+    /// `key` uses dots to address nested values (e.g. "web.body_limit")
+    pub fn config_override(mut self, key: &str, value: impl Serialize) -> Self {
+        let value = serde_json::to_value(value).expect("Overrides must be serializable");
+        let mut segments = key.split('.').peekable();
+        let mut current = &mut self.overrides;
+        while let Some(segment) = segments.next() {
+            if segments.peek().is_none() {
+                current.insert(segment.to_string(), value);
+                break;
+            }
+            let entry = current
+                .entry(segment.to_string())
+                .or_insert_with(|| serde_json::Value::Object(Default::default()));
+            if !entry.is_object() {
+                *entry = serde_json::Value::Object(Default::default());
+            }
+            current = entry.as_object_mut().expect("Was set to object above");
+        }
+        self
+    }
+
+    pub fn build(self) -> Runtime {
+        let root = self.root;
         std::fs::create_dir_all(&root).unwrap_or_else(|_| panic!("Can't create root dir {root:?}"));
         let mut services = ServiceCollection::new();
         let settings = root.join("settings.json");
-        let config = GenericConfig::new(root).expect("Invalid config");
+        let config = GenericConfig::new(root)
+            .and_then(|c| {
+                c.with_overrides(&serde_json::Value::Object(self.overrides))
+                    .map_err(|e| std::io::Error::new(std::io::ErrorKind::InvalidInput, e))
+            })
+            .expect("Invalid config");
+        // Has to be set before any recipe is loaded, as it affects the validation of names
+        pilatus::NamePolicy::set_global(config.get("name_policy").unwrap_or_default());
 
         #[cfg(feature = "tracing")]
         let tracing = crate::tracing::pre_init(&config, &mut services);
@@ -37,34 +102,66 @@ impl Runtime {
         services.register_instance(
             pilatus::Settings::new(settings).expect("Found invalid data in settings.json"),
         );
+        services.register_instance(pilatus::ServiceInfo::registrar("pilatus"));
         pilatus::register(&mut services);
+        services.register_instance(pilatus::ServiceInfo::registrar("pilatus-rt"));
         crate::register(&mut services);
 
-        Self {
+        Runtime {
             services,
             #[cfg(feature = "tracing")]
             tracing,
+            #[cfg(feature = "plugins")]
+            plugins: Vec::new(),
         }
     }
+}
+
+impl Runtime {
+    pub fn builder() -> RuntimeBuilder {
+        RuntimeBuilder::default()
+    }
+
+    pub fn with_root(root: impl Into<PathBuf>) -> Self {
+        Self::builder().data_dir(root).build()
+    }
 
+    /// Services are listed with the caller location as origin (see `pilatus::ServiceInfo`)
+    #[track_caller]
     pub fn register(mut self, registrar: extern "C" fn(&mut ServiceCollection)) -> Self {
+        let origin = std::panic::Location::caller().to_string();
+        self.services
+            .register_instance(pilatus::ServiceInfo::registrar(origin));
         (registrar)(&mut self.services);
         self
     }
 
+    /// Loads all dynamic libraries within `dir` which were exported with `pilatus::export_plugin!`.
+    /// Plugins with a different ABI or pilatus version are skipped
+    #[cfg(feature = "plugins")]
+    pub fn load_plugins(mut self, dir: impl AsRef<std::path::Path>) -> Self {
+        let loaded = crate::plugin::load_plugin_dir(dir.as_ref(), &mut self.services);
+        self.plugins.extend(loaded);
+        self
+    }
+
     pub fn register_instance(mut self, instance: impl Clone + Send + Sync + Any) -> Self {
         self.services.register_instance(instance);
         self
     }
 
     /// As long as there is no Dynamic Plugin System, this method is allowed to panic, as it's the outermost layer
-    pub fn configure(mut self) -> ConfiguredRuntime {
+    pub fn configure(self) -> ConfiguredRuntime {
         // Should help to detect blocking threads/deadlocks
         #[cfg(debug_assertions)]
-        let mut tokio_builder = Builder::new_current_thread();
+        let tokio_builder = Builder::new_current_thread();
         #[cfg(not(debug_assertions))]
-        let mut tokio_builder = Builder::new_multi_thread();
+        let tokio_builder = Builder::new_multi_thread();
+        self.configure_with(tokio_builder)
+    }
 
+    /// Settings which pilatus relies on (e.g. enabled drivers) are applied to `tokio_builder`
+    pub(crate) fn configure_with(mut self, mut tokio_builder: Builder) -> ConfiguredRuntime {
         let tokio = Arc::new(
             tokio_builder
                 .thread_name("pilatus")
@@ -83,7 +180,12 @@ impl Runtime {
         #[cfg(feature = "tracing")]
         crate::tracing::init(&provider, self.tracing).expect("Error during tracing setup");
 
-        ConfiguredRuntime { tokio, provider }
+        ConfiguredRuntime {
+            tokio,
+            provider,
+            #[cfg(feature = "plugins")]
+            _plugins: self.plugins,
+        }
     }
     pub fn run(self) {
         self.configure().run(async {})
@@ -93,6 +195,9 @@ impl Runtime {
 pub struct ConfiguredRuntime {
     tokio: Arc<tokio::runtime::Runtime>,
     pub provider: ServiceProvider,
+    // Must be dropped after the provider, as services might contain code of the plugins
+    #[cfg(feature = "plugins")]
+    _plugins: Vec<libloading::Library>,
 }
 
 impl ConfiguredRuntime {
@@ -155,3 +260,22 @@ impl ConfiguredRuntime {
         r
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn nested_overrides() {
+        let builder = Runtime::builder()
+            .config_override("web.body_limit", 10)
+            .web_address(SocketAddr::from(([127, 0, 0, 1], 0)))
+            .headless();
+        assert_eq!(
+            serde_json::json!({
+                "web": { "body_limit": 10, "socket": "127.0.0.1:0", "enabled": false }
+            }),
+            serde_json::Value::Object(builder.overrides)
+        );
+    }
+}
@@ -0,0 +1,410 @@
+//! Site-specific glue without building a custom device crate.
+//!
+//! Every `*.rhai` file in the scripts directory (default: `<data>/scripts`) is loaded at startup.
+//! Scripts react to system events by defining functions with a single parameter, which receives the event as map:
+//! ```rhai
+//! fn on_recipe_activated(event) { set_variable("line_speed", 12); }
+//! fn on_device_failed(event) { log(`${event.device_type} failed: ${event.error}`); }
+//! fn on_measurement_received(event) { if !event.values.ok { send(event.device_id, "reject", #{}); } }
+//! fn on_event(event) { }  // Called for every event
+//! ```
+//! Besides `on_event`, functions are named `on_<type>` after the event type (see `SystemEventKind::name`).
+//! `on_device_failed` is called for stopped devices with an error.
+//! `on_measurement_received` is called for the results, which devices publish as `SystemEventKind::MeasurementReceived`.
+//!
+//! Scripts have a restricted API and can't access files or modules:
+//! - `log(message)`, `warn(message)`
+//! - `send(device_id, message, payload)`: Sends a message registered as `RemoteMessage` by its wire name
+//! - `set_variable(name, value)`: Numbers and strings only
+//!
+//! Messages and variables are applied after the function returned, so scripts never wait for devices
+
+use std::{
+    path::{Path, PathBuf},
+    sync::{Arc, Mutex},
+};
+
+use futures::StreamExt;
+use minfac::{Registered, ServiceCollection};
+use pilatus::{
+    device::{ActorSystem, DeviceId, RemoteMessages, RemoteRequest},
+    EventBus, GenericConfig, RecipeService, SystemEvent, SystemEventKind, SystemShutdown,
+    TransactionOptions, Variable,
+};
+use rhai::{Dynamic, Engine, EvalAltResult, Scope, AST};
+use serde::Deserialize;
+use tracing::{info, warn};
+
+pub(super) fn register_services(c: &mut ServiceCollection) {
+    c.with::<(
+        Registered<GenericConfig>,
+        Registered<EventBus>,
+        Registered<ActorSystem>,
+        Registered<RemoteMessages>,
+        Registered<RecipeService>,
+        Registered<SystemShutdown>,
+    )>()
+    .register_hosted_service("Scripting", run_scripts);
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+struct ScriptingConfig {
+    /// Relative paths are resolved against the data directory
+    dir: PathBuf,
+    /// Protects against endless loops. Each event handler is aborted after this many operations
+    max_operations: u64,
+}
+
+impl Default for ScriptingConfig {
+    fn default() -> Self {
+        Self {
+            dir: "scripts".into(),
+            max_operations: 1_000_000,
+        }
+    }
+}
+
+#[derive(Debug, PartialEq)]
+enum ScriptAction {
+    Send {
+        device_id: DeviceId,
+        message: String,
+        payload: serde_json::Value,
+    },
+    SetVariable {
+        name: String,
+        value: Variable,
+    },
+}
+
+struct Script {
+    name: String,
+    ast: AST,
+}
+
+struct ScriptHost {
+    engine: Engine,
+    scripts: Vec<Script>,
+    actions: Arc<Mutex<Vec<ScriptAction>>>,
+}
+
+impl ScriptHost {
+    /// Scripts which don't compile are skipped
+    fn new(sources: impl IntoIterator<Item = (String, String)>, max_operations: u64) -> Self {
+        let actions = Arc::new(Mutex::new(Vec::new()));
+        let engine = create_engine(actions.clone(), max_operations);
+        let scripts = sources
+            .into_iter()
+            .filter_map(|(name, source)| match engine.compile(&source) {
+                Ok(ast) => Some(Script { name, ast }),
+                Err(e) => {
+                    warn!("Script '{name}' is disabled: {e}");
+                    None
+                }
+            })
+            .collect();
+        Self {
+            engine,
+            scripts,
+            actions,
+        }
+    }
+
+    /// Calls all handlers of the event and returns the collected actions per script
+    fn dispatch(&self, event: &SystemEvent) -> Vec<(String, Vec<ScriptAction>)> {
+        let mut handlers = vec!["on_event".to_string(), format!("on_{}", event.kind.name())];
+        if event.kind.is_error() && matches!(event.kind, SystemEventKind::DeviceStopped { .. }) {
+            handlers.push("on_device_failed".into());
+        }
+        let arg = match rhai::serde::to_dynamic(event) {
+            Ok(x) => x,
+            Err(e) => {
+                warn!("Event {} can't be passed to scripts: {e}", event.id);
+                return Vec::new();
+            }
+        };
+
+        let mut result = Vec::new();
+        for script in &self.scripts {
+            for handler in &handlers {
+                let is_defined = script
+                    .ast
+                    .iter_functions()
+                    .any(|f| f.name == handler && f.params.len() == 1);
+                if !is_defined {
+                    continue;
+                }
+                if let Err(e) = self.engine.call_fn::<Dynamic>(
+                    &mut Scope::new(),
+                    &script.ast,
+                    handler,
+                    (arg.clone(),),
+                ) {
+                    warn!("Script '{}' failed in '{handler}': {e}", script.name);
+                }
+            }
+            let actions = std::mem::take(&mut *self.actions.lock().expect("Never poisoned"));
+            if !actions.is_empty() {
+                result.push((script.name.clone(), actions));
+            }
+        }
+        result
+    }
+}
+
+fn create_engine(actions: Arc<Mutex<Vec<ScriptAction>>>, max_operations: u64) -> Engine {
+    let mut engine = Engine::new();
+    engine.set_module_resolver(rhai::module_resolvers::DummyModuleResolver::new());
+    engine.set_max_operations(max_operations);
+    engine.on_print(|x| info!("Script: {x}"));
+    engine.on_debug(|x, _, _| info!("Script: {x}"));
+    engine.register_fn("log", |x: &str| info!("Script: {x}"));
+    engine.register_fn("warn", |x: &str| warn!("Script: {x}"));
+
+    let send_actions = actions.clone();
+    engine.register_fn(
+        "send",
+        move |device_id: &str, message: &str, payload: Dynamic| -> Result<(), Box<EvalAltResult>> {
+            let device_id = device_id
+                .parse::<DeviceId>()
+                .map_err(|e| format!("Invalid device_id '{device_id}': {e}"))?;
+            let payload = rhai::serde::from_dynamic::<serde_json::Value>(&payload)?;
+            send_actions
+                .lock()
+                .expect("Never poisoned")
+                .push(ScriptAction::Send {
+                    device_id,
+                    message: message.to_string(),
+                    payload,
+                });
+            Ok(())
+        },
+    );
+    engine.register_fn(
+        "set_variable",
+        move |name: &str, value: Dynamic| -> Result<(), Box<EvalAltResult>> {
+            let value = rhai::serde::from_dynamic::<serde_json::Value>(&value)?;
+            let value = Variable::try_from(value).map_err(|e| e.to_string())?;
+            actions
+                .lock()
+                .expect("Never poisoned")
+                .push(ScriptAction::SetVariable {
+                    name: name.to_string(),
+                    value,
+                });
+            Ok(())
+        },
+    );
+    engine
+}
+
+fn load_sources(dir: &Path) -> Vec<(String, String)> {
+    let entries = match std::fs::read_dir(dir) {
+        Ok(x) => x,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Vec::new(),
+        Err(e) => {
+            warn!("Cannot read scripts from {dir:?}: {e}");
+            return Vec::new();
+        }
+    };
+    let mut sources = entries
+        .filter_map(Result::ok)
+        .map(|e| e.path())
+        .filter(|p| p.extension().is_some_and(|x| x == "rhai"))
+        .filter_map(|path| {
+            let name = path.file_name()?.to_string_lossy().into_owned();
+            match std::fs::read_to_string(&path) {
+                Ok(source) => Some((name, source)),
+                Err(e) => {
+                    warn!("Cannot read script {path:?}: {e}");
+                    None
+                }
+            }
+        })
+        .collect::<Vec<_>>();
+    // Scripts run in a predictable order
+    sources.sort_by(|(a, _), (b, _)| a.cmp(b));
+    sources
+}
+
+async fn run_scripts(
+    (config, bus, actor_system, messages, recipe_service, shutdown): (
+        GenericConfig,
+        EventBus,
+        ActorSystem,
+        RemoteMessages,
+        RecipeService,
+        SystemShutdown,
+    ),
+) -> anyhow::Result<()> {
+    let scripting_config = config
+        .get::<ScriptingConfig>("scripting")
+        .unwrap_or_default();
+    let sources = load_sources(&config.root.join(&scripting_config.dir));
+    if sources.is_empty() {
+        return Ok(());
+    }
+    let host = Arc::new(ScriptHost::new(sources, scripting_config.max_operations));
+    info!("Loaded {} scripts", host.scripts.len());
+    let mut events = bus.subscribe().take_until(shutdown);
+
+    while let Some(event) = events.next().await {
+        let host = host.clone();
+        // Scripts are synchronous and may run up to max_operations
+        let results = match tokio::task::spawn_blocking(move || host.dispatch(&event)).await {
+            Ok(x) => x,
+            Err(e) => {
+                warn!("Script host panicked: {e}");
+                continue;
+            }
+        };
+        for (script, actions) in results {
+            for action in actions {
+                if let Err(e) =
+                    apply(action, &script, &actor_system, &messages, &recipe_service).await
+                {
+                    warn!("Action of script '{script}' failed: {e:#}");
+                }
+            }
+        }
+    }
+    Ok(())
+}
+
+async fn apply(
+    action: ScriptAction,
+    script: &str,
+    actor_system: &ActorSystem,
+    messages: &RemoteMessages,
+    recipe_service: &RecipeService,
+) -> anyhow::Result<()> {
+    match action {
+        ScriptAction::Send {
+            device_id,
+            message,
+            payload,
+        } => {
+            messages
+                .dispatch(
+                    actor_system.clone(),
+                    RemoteRequest {
+                        device_id,
+                        message: message.into(),
+                        payload,
+                    },
+                )
+                .await?;
+        }
+        ScriptAction::SetVariable { name, value } => {
+            recipe_service
+                .update_variables_with(
+                    [(name, value)].into(),
+                    TransactionOptions::default().with_author(format!("script {script}")),
+                )
+                .await?;
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::Utc;
+
+    use super::*;
+
+    #[test]
+    fn collect_actions_of_matching_handlers() {
+        let device_id = DeviceId::new_v4();
+        let host = ScriptHost::new(
+            [
+                (
+                    "a.rhai".to_string(),
+                    format!(
+                        r#"
+                        fn on_device_failed(event) {{ send("{device_id}", "restart", #{{ reason: event.error }}); }}
+                        fn on_recipe_activated(event) {{ set_variable("speed", 1); }}
+                        "#
+                    ),
+                ),
+                ("broken.rhai".to_string(), "fn on_event(".to_string()),
+            ],
+            1000,
+        );
+        assert_eq!(1, host.scripts.len());
+
+        let event = SystemEvent {
+            id: 0,
+            time: Utc::now(),
+            kind: SystemEventKind::DeviceStopped {
+                device_id,
+                device_type: "camera".into(),
+                error: Some("Connection lost".into()),
+            },
+        };
+        assert_eq!(
+            vec![(
+                "a.rhai".to_string(),
+                vec![ScriptAction::Send {
+                    device_id,
+                    message: "restart".into(),
+                    payload: serde_json::json!({ "reason": "Connection lost" }),
+                }]
+            )],
+            host.dispatch(&event)
+        );
+    }
+
+    #[test]
+    fn pass_measurements_to_scripts() {
+        let device_id = DeviceId::new_v4();
+        let host = ScriptHost::new(
+            [(
+                "reject.rhai".to_string(),
+                r#"fn on_measurement_received(event) { if !event.values.ok { send(event.device_id, "reject", #{ width: event.values.width_mm }); } }"#.to_string(),
+            )],
+            1000,
+        );
+        let event = |ok| SystemEvent {
+            id: 0,
+            time: Utc::now(),
+            kind: SystemEventKind::MeasurementReceived {
+                device_id,
+                values: serde_json::json!({ "ok": ok, "width_mm": 12.5 }),
+            },
+        };
+        assert_eq!(
+            vec![(
+                "reject.rhai".to_string(),
+                vec![ScriptAction::Send {
+                    device_id,
+                    message: "reject".into(),
+                    payload: serde_json::json!({ "width": 12.5 }),
+                }]
+            )],
+            host.dispatch(&event(false))
+        );
+        assert!(host.dispatch(&event(true)).is_empty());
+    }
+
+    #[test]
+    fn abort_endless_loops() {
+        let host = ScriptHost::new(
+            [(
+                "loop.rhai".to_string(),
+                "fn on_event(event) { loop { } }".to_string(),
+            )],
+            1000,
+        );
+        let event = SystemEvent {
+            id: 0,
+            time: Utc::now(),
+            kind: SystemEventKind::Error {
+                source: "test".into(),
+                message: "boom".into(),
+            },
+        };
+        assert!(host.dispatch(&event).is_empty());
+    }
+}
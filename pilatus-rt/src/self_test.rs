@@ -0,0 +1,210 @@
+use std::time::Duration;
+
+use chrono::Utc;
+use futures::StreamExt;
+use minfac::{Registered, ServiceCollection};
+use pilatus::{
+    device::{ActorSystem, DeviceId, SelfTestMessage},
+    prelude::*,
+    DeviceSelfTestResult, EventBus, GenericConfig, HealthState, RecipeId, RecipeService,
+    SelfTestReport, SelfTestStatus, SystemEventKind, SystemShutdown,
+};
+use serde::Deserialize;
+use tracing::{info, warn};
+
+pub(super) fn register_services(c: &mut ServiceCollection) {
+    c.with::<(
+        Registered<GenericConfig>,
+        Registered<HealthState>,
+        Registered<ActorSystem>,
+        Registered<RecipeService>,
+        Registered<EventBus>,
+        Registered<SystemShutdown>,
+    )>()
+    .register_hosted_service("Device Self Test", run_self_tests);
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+struct SelfTestConfig {
+    enabled: bool,
+    /// Devices of the active recipe which didn't start within this time are reported as failed
+    startup_timeout_ms: u64,
+    timeout_ms: u64,
+}
+
+impl Default for SelfTestConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            startup_timeout_ms: 10000,
+            timeout_ms: 10000,
+        }
+    }
+}
+
+async fn run_self_tests(
+    (config, health, actor_system, recipe_service, events, shutdown): (
+        GenericConfig,
+        HealthState,
+        ActorSystem,
+        RecipeService,
+        EventBus,
+        SystemShutdown,
+    ),
+) -> anyhow::Result<()> {
+    let self_test_config = config
+        .get::<SelfTestConfig>("self_test")
+        .unwrap_or_default();
+    if !self_test_config.enabled {
+        return Ok(());
+    }
+    let run = std::pin::pin!(self_test_loop(
+        self_test_config,
+        health,
+        actor_system,
+        recipe_service,
+        events
+    ));
+    futures::future::select(run, shutdown).await;
+    Ok(())
+}
+
+/// Runs the self-test on startup and after each activation. The system isn't ready while it runs
+async fn self_test_loop(
+    config: SelfTestConfig,
+    health: HealthState,
+    actor_system: ActorSystem,
+    recipe_service: RecipeService,
+    events: EventBus,
+) {
+    // Subscribed before the first run, so activations during the run aren't missed
+    let mut activations = events
+        .subscribe()
+        .filter(|e| std::future::ready(matches!(e.kind, SystemEventKind::RecipeActivated { .. })));
+    loop {
+        health.update(|r| r.self_test = SelfTestStatus::Running);
+        let (recipe_id, devices) = {
+            let state = recipe_service.state().await;
+            let (id, recipe) = state.recipes().active();
+            let devices = recipe
+                .devices
+                .iter_unordered()
+                .filter(|(_, device)| device.enabled)
+                .map(|(id, _)| *id)
+                .collect::<Vec<_>>();
+            (id, devices)
+        };
+        let report = run_self_test(
+            &actor_system,
+            recipe_id,
+            &devices,
+            Duration::from_millis(config.startup_timeout_ms),
+            Duration::from_millis(config.timeout_ms),
+        )
+        .await;
+        if report.is_passed() {
+            info!("Self-test of {} devices passed", report.devices.len());
+        } else {
+            warn!("Self-test failed: {report:?}");
+        }
+        health.update(|r| r.self_test = SelfTestStatus::Finished(report));
+        if activations.next().await.is_none() {
+            break;
+        }
+    }
+}
+
+/// Waits for the devices to start instead of a fixed delay, so devices which start late are tested as well
+async fn run_self_test(
+    actor_system: &ActorSystem,
+    recipe_id: RecipeId,
+    devices: &[DeviceId],
+    startup_timeout: Duration,
+    timeout: Duration,
+) -> SelfTestReport {
+    let results = futures::future::join_all(devices.iter().map(|&id| async move {
+        let started = tokio::time::timeout(startup_timeout, actor_system.wait_until_executing(id));
+        if started.await.is_err() {
+            let error = format!("Didn't start within {startup_timeout:?}");
+            return Some((
+                id,
+                DeviceSelfTestResult {
+                    checks: Vec::new(),
+                    error: Some(error),
+                },
+            ));
+        }
+        if !actor_system
+            .list_devices_for_message_type::<SelfTestMessage>()
+            .contains(&id)
+        {
+            return None;
+        }
+        let result =
+            match tokio::time::timeout(timeout, actor_system.ask(id, SelfTestMessage)).await {
+                Ok(Ok(checks)) => DeviceSelfTestResult {
+                    checks,
+                    error: None,
+                },
+                Ok(Err(e)) => DeviceSelfTestResult {
+                    checks: Vec::new(),
+                    error: Some(format!("{e:?}")),
+                },
+                Err(_) => DeviceSelfTestResult {
+                    checks: Vec::new(),
+                    error: Some(format!("No response within {timeout:?}")),
+                },
+            };
+        Some((id, result))
+    }))
+    .await;
+
+    SelfTestReport {
+        recipe_id,
+        finished: Utc::now(),
+        devices: results.into_iter().flatten().collect(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use pilatus::device::{ActorResult, SelfTestCheck};
+
+    use super::*;
+
+    async fn self_test(_: &mut (), _msg: SelfTestMessage) -> ActorResult<SelfTestMessage> {
+        Ok(vec![SelfTestCheck::failed("camera", "disconnected")])
+    }
+
+    #[tokio::test]
+    async fn test_devices_which_start_late() {
+        let actor_system = ActorSystem::new();
+        let (late, missing) = (DeviceId::new_v4(), DeviceId::new_v4());
+        let device = async {
+            tokio::time::sleep(Duration::from_millis(50)).await;
+            actor_system
+                .register(late)
+                .add_handler(self_test)
+                .execute(())
+                .await;
+        };
+        let test = async {
+            let report = run_self_test(
+                &actor_system,
+                RecipeId::default(),
+                &[late, missing],
+                Duration::from_millis(500),
+                Duration::from_secs(1),
+            )
+            .await;
+            actor_system.forget_senders();
+            report
+        };
+        let ((), report) = futures::future::join(device, test).await;
+
+        assert!(!report.is_passed());
+        assert_eq!(1, report.devices[&late].checks.len());
+        assert!(report.devices[&missing].error.is_some());
+    }
+}
@@ -1,4 +1,4 @@
-use std::{pin::Pin, sync::Arc};
+use std::{collections::HashMap, pin::Pin, sync::Arc, time::Duration};
 
 use futures::{
     future::Shared,
@@ -6,7 +6,8 @@ use futures::{
     Future, FutureExt,
 };
 use minfac::{Registered, ServiceCollection};
-use pilatus::{SystemShutdown, SystemTerminator};
+use pilatus::{GenericConfig, ShutdownHooks, ShutdownPhase, SystemShutdown, SystemTerminator};
+use serde::Deserialize;
 
 type InnerPrivateState =
     Shared<Pin<std::boxed::Box<(dyn futures::Future<Output = ()> + 'static + Send + Sync)>>>;
@@ -25,9 +26,51 @@ pub fn register_services(c: &mut ServiceCollection) {
 
     c.with::<Registered<Arc<PrivateState>>>()
         .register(|s| SystemTerminator::new(s.0.clone()));
+    c.register_instance(pilatus::ServiceInfo::transient::<SystemTerminator>());
 
     c.with::<Registered<Arc<PrivateState>>>()
         .register(|x| SystemShutdown::new(x.1.clone()));
+    c.register_instance(pilatus::ServiceInfo::transient::<SystemShutdown>());
+
+    c.register_shared(|| Arc::new(ShutdownHooks::default()))
+        .alias(|x| ShutdownHooks::clone(&x));
+    c.register_instance(pilatus::ServiceInfo::shared::<ShutdownHooks>());
+
+    c.with::<(Registered<ShutdownHooks>, Registered<GenericConfig>)>()
+        .register(|(hooks, config)| ShutdownSequence {
+            hooks,
+            config: config.get("shutdown").unwrap_or_default(),
+        });
+    c.register_instance(pilatus::ServiceInfo::transient::<ShutdownSequence>());
+}
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+struct ShutdownConfig {
+    phase_timeout_secs: HashMap<ShutdownPhase, u64>,
+}
+
+impl ShutdownConfig {
+    const DEFAULT_PHASE_TIMEOUT: Duration = Duration::from_secs(5);
+
+    fn phase_timeout(&self, phase: ShutdownPhase) -> Duration {
+        self.phase_timeout_secs
+            .get(&phase)
+            .map(|x| Duration::from_secs(*x))
+            .unwrap_or(Self::DEFAULT_PHASE_TIMEOUT)
+    }
+}
+
+/// Runs the registered ShutdownHooks with the timeouts from the config key "shutdown"
+pub(crate) struct ShutdownSequence {
+    hooks: ShutdownHooks,
+    config: ShutdownConfig,
+}
+
+impl ShutdownSequence {
+    pub(crate) async fn run(&self) {
+        self.hooks.run(|p| self.config.phase_timeout(p)).await
+    }
 }
 
 struct PrivateState(AbortHandle, InnerPrivateState);
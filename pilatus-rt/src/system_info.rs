@@ -0,0 +1,245 @@
+use std::{path::Path, sync::Arc};
+
+use ed25519_dalek::{Signature, Verifier, VerifyingKey};
+use minfac::{AllRegistered, Registered, ServiceCollection};
+use pilatus::{
+    plugin::LoadedPlugin, GenericConfig, License, LicenseState, LicenseStatus, MachineId,
+    SystemInfo,
+};
+use serde::Deserialize;
+use sha2::{Digest, Sha256};
+use tracing::{info, warn};
+
+const LICENSE_FILE: &str = "license.json";
+/// Hex encoded ed25519 key of the vendor, which signs license files. It is compiled into the binary,
+/// so operators can't replace it with a key of their own. Builds without it don't accept any license
+const VENDOR_PUBLIC_KEY: Option<&str> = option_env!("PILATUS_LICENSE_PUBLIC_KEY");
+
+pub(super) fn register_services(c: &mut ServiceCollection) {
+    c.register_shared(|| Arc::new(machine_id_from_system()))
+        .alias(|x| *x);
+    c.register_instance(pilatus::ServiceInfo::shared::<MachineId>());
+    c.with::<(Registered<GenericConfig>, Registered<MachineId>)>()
+        .register_shared(|(config, machine_id)| {
+            if config.get::<serde_json::Value>("license").is_ok() {
+                warn!(
+                    "'license' in the config is ignored. Licenses are verified with the vendor key"
+                );
+            }
+            Arc::new(LicenseState::new(load_license(
+                &config.root.join(LICENSE_FILE),
+                vendor_key(),
+                machine_id,
+            )))
+        })
+        .alias(|x| LicenseState::clone(&x));
+    c.register_instance(pilatus::ServiceInfo::shared::<LicenseState>());
+    c.with::<(
+        Registered<MachineId>,
+        Registered<LicenseState>,
+        AllRegistered<LoadedPlugin>,
+    )>()
+    .register(|(machine_id, license, plugins)| {
+        SystemInfo::new(machine_id, plugins.map(|p| p.name).collect(), &license)
+    });
+    c.register_instance(pilatus::ServiceInfo::transient::<SystemInfo>());
+}
+
+/// Content of the license file. The signature is calculated over the bytes of `payload`, which contains the License as JSON
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct SignedLicense {
+    payload: String,
+    /// Hex encoded ed25519 signature
+    signature: String,
+}
+
+fn vendor_key() -> anyhow::Result<VerifyingKey> {
+    let public_key = VENDOR_PUBLIC_KEY.ok_or_else(|| {
+        anyhow::anyhow!(
+            "This build contains no key to verify licenses (PILATUS_LICENSE_PUBLIC_KEY)"
+        )
+    })?;
+    let key_bytes: [u8; 32] = hex::decode(public_key.trim())?
+        .try_into()
+        .map_err(|_| anyhow::anyhow!("Public key must have 32 bytes"))?;
+    Ok(VerifyingKey::from_bytes(&key_bytes)?)
+}
+
+/// Identifiers which are provided by the OS or the hardware. Copying the data directory to another machine doesn't copy them
+#[cfg(target_os = "linux")]
+fn system_identifiers() -> Vec<String> {
+    [
+        "/etc/machine-id",
+        "/var/lib/dbus/machine-id",
+        "/sys/class/dmi/id/product_uuid",
+    ]
+    .into_iter()
+    .filter_map(|path| std::fs::read_to_string(path).ok())
+    .map(|x| x.trim().to_lowercase())
+    .filter(|x| !x.is_empty())
+    .collect()
+}
+
+#[cfg(windows)]
+fn system_identifiers() -> Vec<String> {
+    let output = std::process::Command::new("reg")
+        .args([
+            "query",
+            r"HKLM\SOFTWARE\Microsoft\Cryptography",
+            "/v",
+            "MachineGuid",
+        ])
+        .output();
+    output
+        .ok()
+        .and_then(|x| {
+            String::from_utf8_lossy(&x.stdout)
+                .split_whitespace()
+                .last()
+                .map(|x| x.to_lowercase())
+        })
+        .into_iter()
+        .collect()
+}
+
+#[cfg(not(any(target_os = "linux", windows)))]
+fn system_identifiers() -> Vec<String> {
+    Vec::new()
+}
+
+/// The nil id is used, if the system provides no identifiers. Licenses for a specific machine are invalid then
+fn machine_id_from_system() -> MachineId {
+    let identifiers = system_identifiers();
+    let id = machine_id_from_identifiers(&identifiers);
+    if identifiers.is_empty() {
+        warn!("The system provides no machine identifiers. Machine bound licenses can't be used");
+    } else {
+        info!("Machine id is {id}");
+    }
+    id
+}
+
+fn machine_id_from_identifiers(identifiers: &[String]) -> MachineId {
+    // Deduplicated, as /etc/machine-id and the dbus machine-id are often the same
+    let mut identifiers = identifiers.to_vec();
+    identifiers.sort();
+    identifiers.dedup();
+    if identifiers.is_empty() {
+        return MachineId(uuid::Uuid::nil());
+    }
+    let mut hasher = Sha256::new();
+    for identifier in identifiers {
+        hasher.update(identifier.as_bytes());
+        hasher.update([0]);
+    }
+    let hash = hasher.finalize();
+    let bytes: [u8; 16] = hash[..16].try_into().expect("Sha256 has 32 bytes");
+    MachineId(uuid::Uuid::from_bytes(bytes))
+}
+
+fn load_license(
+    path: &Path,
+    key: anyhow::Result<VerifyingKey>,
+    machine_id: MachineId,
+) -> LicenseStatus {
+    let raw = match std::fs::read(path) {
+        Ok(x) => x,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => return LicenseStatus::Missing,
+        Err(e) => {
+            return LicenseStatus::Invalid {
+                reason: format!("Cannot read {path:?}: {e}"),
+            }
+        }
+    };
+    match key.and_then(|key| verify_license(&raw, &key, machine_id)) {
+        Ok(license) => {
+            info!("Licensed to '{}'", license.licensee);
+            LicenseStatus::Valid { license }
+        }
+        Err(e) => {
+            warn!("Ignore license {path:?}: {e:#}");
+            LicenseStatus::Invalid {
+                reason: format!("{e:#}"),
+            }
+        }
+    }
+}
+
+fn verify_license(
+    raw: &[u8],
+    key: &VerifyingKey,
+    machine_id: MachineId,
+) -> anyhow::Result<License> {
+    let signed: SignedLicense = serde_json::from_slice(raw)?;
+    let signature = Signature::from_slice(&hex::decode(&signed.signature)?)?;
+    key.verify(signed.payload.as_bytes(), &signature)
+        .map_err(|_| anyhow::anyhow!("Invalid signature"))?;
+
+    let license: License = serde_json::from_str(&signed.payload)?;
+    if let Some(licensed_machine) = license.machine_id {
+        if machine_id.0.is_nil() || licensed_machine != machine_id {
+            anyhow::bail!("License is issued for machine {licensed_machine}");
+        }
+    }
+    Ok(license)
+}
+
+#[cfg(test)]
+mod tests {
+    use ed25519_dalek::{Signer, SigningKey};
+
+    use super::*;
+
+    fn signed(key: &SigningKey, payload: &str) -> Vec<u8> {
+        serde_json::to_vec(&serde_json::json!({
+            "payload": payload,
+            "signature": hex::encode(key.sign(payload.as_bytes()).to_bytes()),
+        }))
+        .unwrap()
+    }
+
+    #[test]
+    fn verify_signed_license_for_machine() {
+        let key = SigningKey::from_bytes(&[7; 32]);
+        let public_key = key.verifying_key();
+        let machine_id = MachineId(uuid::Uuid::new_v4());
+        let payload = serde_json::json!({
+            "licensee": "ACME",
+            "machine_id": machine_id,
+            "features": ["ocr"]
+        })
+        .to_string();
+
+        let license = verify_license(&signed(&key, &payload), &public_key, machine_id).unwrap();
+        assert!(license.features.contains("ocr"));
+
+        let other_machine = MachineId(uuid::Uuid::new_v4());
+        assert!(verify_license(&signed(&key, &payload), &public_key, other_machine).is_err());
+
+        let forged = SigningKey::from_bytes(&[8; 32]);
+        assert!(verify_license(&signed(&forged, &payload), &public_key, machine_id).is_err());
+    }
+
+    #[test]
+    fn derive_machine_id_from_identifiers() {
+        let ids = |x: &[&str]| {
+            machine_id_from_identifiers(&x.iter().map(|x| x.to_string()).collect::<Vec<_>>())
+        };
+        assert_eq!(ids(&["a", "b"]), ids(&["b", "a", "a"]));
+        assert_ne!(ids(&["a"]), ids(&["b"]));
+        assert!(ids(&[]).0.is_nil());
+    }
+
+    #[test]
+    fn reject_machine_bound_license_without_machine_id() {
+        let key = SigningKey::from_bytes(&[7; 32]);
+        let payload = serde_json::json!({
+            "licensee": "ACME",
+            "machine_id": uuid::Uuid::nil(),
+        })
+        .to_string();
+        let nil = MachineId(uuid::Uuid::nil());
+        assert!(verify_license(&signed(&key, &payload), &key.verifying_key(), nil).is_err());
+    }
+}
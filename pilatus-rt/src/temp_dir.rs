@@ -0,0 +1,77 @@
+//! Removes leftovers in [`GenericConfig::temp_dir`], e.g. of imports which were interrupted by a restart.
+//! Only entries starting with `pilatus-` are touched, as the directory might be shared with other applications
+
+use std::{io, path::Path, time::SystemTime};
+
+use minfac::{Registered, ServiceCollection};
+use pilatus::{prelude::*, GenericConfig};
+use tracing::{debug, warn};
+
+const PREFIX: &str = "pilatus-";
+
+pub(super) fn register_services(c: &mut ServiceCollection) {
+    c.with::<Registered<GenericConfig>>()
+        .register_hosted_service("Temp Dir Cleanup", cleanup_temp_dir);
+}
+
+async fn cleanup_temp_dir(config: GenericConfig) -> anyhow::Result<()> {
+    let dir = config.temp_dir();
+    // Entries of imports or downloads which already started are newer
+    let started = SystemTime::now();
+    let removed = tokio::task::spawn_blocking(move || remove_leftovers(&dir, started)).await??;
+    debug!("Removed {removed} leftovers from the temp dir");
+    Ok(())
+}
+
+fn remove_leftovers(dir: &Path, before: SystemTime) -> io::Result<usize> {
+    std::fs::create_dir_all(dir)?;
+    let mut removed = 0;
+    for entry in std::fs::read_dir(dir)? {
+        let entry = entry?;
+        if !entry.file_name().to_string_lossy().starts_with(PREFIX) {
+            continue;
+        }
+        let meta = entry.metadata()?;
+        if meta.modified()? >= before {
+            continue;
+        }
+        let result = if meta.is_dir() {
+            std::fs::remove_dir_all(entry.path())
+        } else {
+            std::fs::remove_file(entry.path())
+        };
+        match result {
+            Ok(()) => removed += 1,
+            Err(e) => warn!("Cannot remove {:?}: {e}", entry.path()),
+        }
+    }
+    Ok(removed)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use super::*;
+
+    #[test]
+    fn keep_foreign_entries() {
+        let dir = tempfile::tempdir().unwrap();
+        let temp_dir = dir.path().join("tmp");
+        std::fs::create_dir_all(temp_dir.join("pilatus-import-abc/recipe")).unwrap();
+        std::fs::write(temp_dir.join("pilatus-spool-abc"), b"data").unwrap();
+        std::fs::write(temp_dir.join("other.txt"), b"data").unwrap();
+
+        let later = SystemTime::now() + Duration::from_secs(60);
+        assert_eq!(
+            0,
+            remove_leftovers(&temp_dir, SystemTime::UNIX_EPOCH).unwrap()
+        );
+        assert_eq!(2, remove_leftovers(&temp_dir, later).unwrap());
+        let remaining: Vec<_> = std::fs::read_dir(&temp_dir)
+            .unwrap()
+            .map(|x| x.unwrap().file_name())
+            .collect();
+        assert_eq!(vec![std::ffi::OsString::from("other.txt")], remaining);
+    }
+}
@@ -0,0 +1,138 @@
+//! Runs the whole runtime in-process, so device crates can write end-to-end tests in a few lines
+//!
+//! ```ignore
+//! Runtime::test()
+//!     .register(my_device::register)
+//!     .run(|h| async move {
+//!         let active = h.recipe_service.get_active_id().await;
+//!     });
+//! ```
+
+use std::{net::SocketAddr, path::Path, sync::Arc};
+
+use futures::Future;
+use minfac::{ServiceCollection, WeakServiceProvider};
+use pilatus::device::ActorSystem;
+use tokio::runtime::Builder;
+
+use crate::{recipe::RecipeServiceFassade, Runtime};
+
+/// Uses an ephemeral data directory which is removed when the test finishes.
+/// The webserver (if registered) binds an ephemeral port on localhost, which is available via `pilatus_axum::Stats`.
+/// Time is paused (see `tokio::time::pause`), so timers are deterministic and fire as soon as the runtime is idle.
+/// Use `real_time` for tests which wait for external processes or network peers
+pub struct TestRuntime {
+    runtime: Runtime,
+    dir: tempfile::TempDir,
+    paused_time: bool,
+}
+
+/// Handles which are commonly needed for assertions
+pub struct TestHandles {
+    pub provider: WeakServiceProvider,
+    pub actor_system: ActorSystem,
+    pub recipe_service: Arc<RecipeServiceFassade>,
+}
+
+impl Runtime {
+    pub fn test() -> TestRuntime {
+        let dir = tempfile::tempdir().expect("Can't create temporary data directory");
+        let runtime = Runtime::builder()
+            .data_dir(dir.path())
+            .web_address(SocketAddr::from(([127, 0, 0, 1], 0)))
+            .build();
+        TestRuntime {
+            runtime,
+            dir,
+            paused_time: true,
+        }
+    }
+}
+
+impl TestRuntime {
+    pub fn register(self, registrar: extern "C" fn(&mut ServiceCollection)) -> Self {
+        Self {
+            runtime: self.runtime.register(registrar),
+            ..self
+        }
+    }
+
+    pub fn register_instance(self, instance: impl Clone + Send + Sync + std::any::Any) -> Self {
+        Self {
+            runtime: self.runtime.register_instance(instance),
+            ..self
+        }
+    }
+
+    /// Timers use the wall clock. Their order is not deterministic anymore
+    pub fn real_time(self) -> Self {
+        Self {
+            paused_time: false,
+            ..self
+        }
+    }
+
+    pub fn data_dir(&self) -> &Path {
+        self.dir.path()
+    }
+
+    /// Starts all HostedServices and shuts them down when the future returned by `test` completes
+    pub fn run<TFut: Future>(self, test: impl FnOnce(TestHandles) -> TFut) -> TFut::Output {
+        let configured = if self.paused_time {
+            let mut tokio_builder = Builder::new_current_thread();
+            tokio_builder.start_paused(true);
+            self.runtime.configure_with(tokio_builder)
+        } else {
+            self.runtime.configure()
+        };
+        let handles = TestHandles {
+            provider: (&configured.provider).into(),
+            actor_system: configured
+                .provider
+                .get()
+                .expect("ActorSystem is registered by pilatus"),
+            recipe_service: configured
+                .provider
+                .get()
+                .expect("RecipeServiceFassade is registered by pilatus-rt"),
+        };
+        let result = configured.run_until_finished(test(handles));
+        drop(self.dir);
+        result
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use pilatus::RecipeServiceTrait;
+
+    use super::*;
+
+    #[test]
+    fn run_with_default_recipe() {
+        let rt = Runtime::test();
+        let recipes_dir = rt.data_dir().join("recipes");
+        let devices = rt.run(|h| async move {
+            h.recipe_service
+                .state()
+                .await
+                .recipes()
+                .active()
+                .1
+                .devices
+                .len()
+        });
+        assert_eq!(0, devices);
+        assert!(!recipes_dir.exists(), "Data directory is removed");
+    }
+
+    #[test]
+    fn time_is_paused() {
+        let elapsed = Runtime::test().run(|_| async {
+            let start = tokio::time::Instant::now();
+            tokio::time::sleep(std::time::Duration::from_secs(3600)).await;
+            start.elapsed()
+        });
+        assert_eq!(std::time::Duration::from_secs(3600), elapsed);
+    }
+}
@@ -0,0 +1,296 @@
+use std::time::Duration;
+
+use chrono::{DateTime, TimeZone, Utc};
+use minfac::{Registered, ServiceCollection};
+use pilatus::{
+    prelude::*, EventBus, GenericConfig, HealthState, SystemEventKind, SystemShutdown,
+    TimeSyncStatus,
+};
+use serde::Deserialize;
+use tokio::{net::UdpSocket, process::Command};
+use tracing::{info, warn};
+
+pub(super) fn register_services(c: &mut ServiceCollection) {
+    c.with::<(
+        Registered<GenericConfig>,
+        Registered<HealthState>,
+        Registered<EventBus>,
+        Registered<SystemShutdown>,
+    )>()
+    .register_hosted_service("Time Sync Monitor", monitor_time_sync);
+}
+
+#[derive(Debug, Default, Clone, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "snake_case", tag = "type", deny_unknown_fields)]
+enum TimeSource {
+    /// Uses chrony if it's installed and falls back to timedatectl
+    #[default]
+    Auto,
+    Chrony,
+    Timedatectl,
+    /// Estimates the drift against an NTP server directly, e.g. if the OS doesn't synchronize the clock
+    Ntp {
+        server: String,
+    },
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+struct TimeSyncConfig {
+    enabled: bool,
+    source: TimeSource,
+    interval_secs: u64,
+    max_drift_ms: f64,
+    timeout_ms: u64,
+}
+
+impl Default for TimeSyncConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            source: TimeSource::Auto,
+            interval_secs: 300,
+            max_drift_ms: 500.,
+            timeout_ms: 5000,
+        }
+    }
+}
+
+/// What a single source reports
+#[derive(Debug, Default, PartialEq)]
+struct Measurement {
+    synchronized: Option<bool>,
+    offset_ms: Option<f64>,
+}
+
+async fn monitor_time_sync(
+    (config, health, events, shutdown): (GenericConfig, HealthState, EventBus, SystemShutdown),
+) -> anyhow::Result<()> {
+    let sync_config = config
+        .get::<TimeSyncConfig>("time_sync")
+        .unwrap_or_default();
+    if !sync_config.enabled {
+        return Ok(());
+    }
+    info!("Monitor time synchronization with {:?}", sync_config.source);
+    let run = std::pin::pin!(async {
+        let mut was_exceeded = false;
+        loop {
+            let status = check(&sync_config).await;
+            if status.drift_exceeded && !was_exceeded {
+                let message = describe_drift(&status);
+                warn!("{message}");
+                events.publish(SystemEventKind::Error {
+                    source: "time_sync".into(),
+                    message,
+                });
+            } else if !status.drift_exceeded && was_exceeded {
+                info!("System clock is synchronized again");
+            }
+            was_exceeded = status.drift_exceeded;
+            health.update(|r| r.time_sync = Some(status));
+            tokio::time::sleep(Duration::from_secs(sync_config.interval_secs)).await;
+        }
+    });
+    futures::future::select(run, shutdown).await;
+    Ok(())
+}
+
+async fn check(config: &TimeSyncConfig) -> TimeSyncStatus {
+    let timeout = Duration::from_millis(config.timeout_ms);
+    let (source, result) = match &config.source {
+        TimeSource::Auto => match query_chrony(timeout).await {
+            Ok(x) => ("chrony".to_string(), Ok(x)),
+            Err(_) => ("timedatectl".to_string(), query_timedatectl(timeout).await),
+        },
+        TimeSource::Chrony => ("chrony".into(), query_chrony(timeout).await),
+        TimeSource::Timedatectl => ("timedatectl".into(), query_timedatectl(timeout).await),
+        TimeSource::Ntp { server } => (format!("ntp:{server}"), query_ntp(server, timeout).await),
+    };
+    match result {
+        Ok(m) => TimeSyncStatus {
+            source,
+            checked: Utc::now(),
+            drift_exceeded: m.synchronized == Some(false)
+                || m.offset_ms.is_some_and(|x| x.abs() > config.max_drift_ms),
+            synchronized: m.synchronized,
+            offset_ms: m.offset_ms,
+            error: None,
+        },
+        Err(e) => TimeSyncStatus {
+            source,
+            checked: Utc::now(),
+            synchronized: None,
+            offset_ms: None,
+            drift_exceeded: false,
+            error: Some(format!("{e:#}")),
+        },
+    }
+}
+
+fn describe_drift(status: &TimeSyncStatus) -> String {
+    match status.offset_ms {
+        Some(offset) => format!(
+            "System clock deviates {offset:.1}ms from {} (synchronized: {:?})",
+            status.source, status.synchronized
+        ),
+        None => format!("System clock is not synchronized ({})", status.source),
+    }
+}
+
+async fn run_command(program: &str, args: &[&str], timeout: Duration) -> anyhow::Result<String> {
+    let output = tokio::time::timeout(timeout, Command::new(program).args(args).output()).await??;
+    anyhow::ensure!(
+        output.status.success(),
+        "{program} failed: {}",
+        String::from_utf8_lossy(&output.stderr).trim()
+    );
+    Ok(String::from_utf8(output.stdout)?)
+}
+
+async fn query_chrony(timeout: Duration) -> anyhow::Result<Measurement> {
+    parse_chrony_tracking(&run_command("chronyc", &["tracking"], timeout).await?)
+}
+
+async fn query_timedatectl(timeout: Duration) -> anyhow::Result<Measurement> {
+    let output = run_command(
+        "timedatectl",
+        &["show", "--property=NTPSynchronized", "--value"],
+        timeout,
+    )
+    .await?;
+    Ok(Measurement {
+        synchronized: Some(output.trim() == "yes"),
+        offset_ms: None,
+    })
+}
+
+/// Parses lines like `System time     : 0.000012 seconds slow of NTP time`
+fn parse_chrony_tracking(output: &str) -> anyhow::Result<Measurement> {
+    let mut result = Measurement::default();
+    for line in output.lines() {
+        let Some((key, value)) = line.split_once(':') else {
+            continue;
+        };
+        match key.trim() {
+            "System time" => {
+                let mut parts = value.split_whitespace();
+                let seconds: f64 = parts
+                    .next()
+                    .ok_or_else(|| anyhow::anyhow!("Missing offset in '{line}'"))?
+                    .parse()?;
+                let sign = match parts.nth(1) {
+                    Some("fast") => 1.,
+                    Some("slow") => -1.,
+                    _ => anyhow::bail!("Unknown direction in '{line}'"),
+                };
+                result.offset_ms = Some(sign * seconds * 1000.);
+            }
+            "Leap status" => result.synchronized = Some(value.trim() != "Not synchronised"),
+            _ => {}
+        }
+    }
+    anyhow::ensure!(
+        result.offset_ms.is_some(),
+        "chronyc tracking reported no offset"
+    );
+    Ok(result)
+}
+
+/// Seconds between 1900 (NTP era) and 1970 (unix epoch)
+const NTP_UNIX_OFFSET: i64 = 2_208_988_800;
+
+/// Single SNTP request (RFC 4330). The server's clock is the reference
+async fn query_ntp(server: &str, timeout: Duration) -> anyhow::Result<Measurement> {
+    let socket = UdpSocket::bind("0.0.0.0:0").await?;
+    socket.connect(server).await?;
+    let mut request = [0u8; 48];
+    // Leap indicator 0, version 4, mode 3 (client)
+    request[0] = 0x23;
+    let sent = Utc::now();
+    socket.send(&request).await?;
+    let mut response = [0u8; 48];
+    let len = tokio::time::timeout(timeout, socket.recv(&mut response)).await??;
+    let received = Utc::now();
+    anyhow::ensure!(len == 48, "Invalid NTP response with {len} bytes");
+    anyhow::ensure!(response[1] != 0, "NTP server is not synchronized");
+
+    let server_received = parse_ntp_timestamp(&response[32..40])?;
+    let server_sent = parse_ntp_timestamp(&response[40..48])?;
+    Ok(Measurement {
+        synchronized: None,
+        offset_ms: Some(local_offset_ms(
+            sent,
+            server_received,
+            server_sent,
+            received,
+        )),
+    })
+}
+
+fn parse_ntp_timestamp(raw: &[u8]) -> anyhow::Result<DateTime<Utc>> {
+    let seconds = u32::from_be_bytes(raw[0..4].try_into()?) as i64;
+    let fraction = u32::from_be_bytes(raw[4..8].try_into()?) as u64;
+    let nanos = ((fraction * 1_000_000_000) >> 32) as u32;
+    Utc.timestamp_opt(seconds - NTP_UNIX_OFFSET, nanos)
+        .single()
+        .ok_or_else(|| anyhow::anyhow!("Invalid NTP timestamp"))
+}
+
+/// Offset of the local clock with the network delay cancelled out. Positive, if the local clock is ahead
+fn local_offset_ms(
+    sent: DateTime<Utc>,
+    server_received: DateTime<Utc>,
+    server_sent: DateTime<Utc>,
+    received: DateTime<Utc>,
+) -> f64 {
+    let server_ahead = ((server_received - sent) + (server_sent - received)) / 2;
+    -(server_ahead.num_microseconds().unwrap_or(i64::MAX) as f64) / 1000.
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_chrony_output() {
+        let output = "Reference ID    : C0A80001 (router)
+Stratum         : 3
+System time     : 0.250000000 seconds slow of NTP time
+Last offset     : -0.000012 seconds
+Leap status     : Normal
+";
+        assert_eq!(
+            Measurement {
+                synchronized: Some(true),
+                offset_ms: Some(-250.)
+            },
+            parse_chrony_tracking(output).unwrap()
+        );
+        assert!(parse_chrony_tracking("Leap status     : Normal").is_err());
+    }
+
+    #[test]
+    fn offset_cancels_network_delay() {
+        let sent = Utc.timestamp_opt(1000, 0).unwrap();
+        // Local clock is 2s behind, request and response take 100ms each
+        let server_received = sent + chrono::Duration::milliseconds(2100);
+        let server_sent = server_received + chrono::Duration::milliseconds(10);
+        let received = sent + chrono::Duration::milliseconds(210);
+        assert_eq!(
+            -2000.,
+            local_offset_ms(sent, server_received, server_sent, received)
+        );
+    }
+
+    #[test]
+    fn parse_ntp_era_timestamp() {
+        let mut raw = [0u8; 8];
+        raw[0..4].copy_from_slice(&((NTP_UNIX_OFFSET + 60) as u32).to_be_bytes());
+        raw[4..8].copy_from_slice(&(1u32 << 31).to_be_bytes());
+        assert_eq!(
+            Utc.timestamp_opt(60, 500_000_000).unwrap(),
+            parse_ntp_timestamp(&raw).unwrap()
+        );
+    }
+}
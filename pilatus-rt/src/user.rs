@@ -0,0 +1,418 @@
+use std::{
+    collections::BTreeMap,
+    path::{Path, PathBuf},
+    sync::{Arc, Mutex},
+};
+
+use argon2::{
+    password_hash::{rand_core::OsRng, PasswordHash, PasswordHasher, PasswordVerifier, SaltString},
+    Argon2,
+};
+use async_trait::async_trait;
+use chrono::{DateTime, Utc};
+use minfac::{Registered, ServiceCollection};
+use pilatus::{
+    GenericConfig, IssuedToken, TokenInfo, User, UserError, UserService, UserServiceTrait,
+};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use tracing::warn;
+use uuid::Uuid;
+
+const USERS_FILE: &str = "users.json";
+
+pub(super) fn register_services(c: &mut ServiceCollection) {
+    c.with::<Registered<GenericConfig>>()
+        .register_shared(|config| Arc::new(FileUserStore::open(config.root.join(USERS_FILE))))
+        .alias(|x| x as UserService);
+    c.register_instance(pilatus::ServiceInfo::shared::<UserService>());
+}
+
+/// Users and tokens are persisted in a single JSON file. Only hashes of passwords and tokens are stored
+pub(crate) struct FileUserStore {
+    path: PathBuf,
+    state: Mutex<StoredUsers>,
+    /// Serializes writes of the file
+    write_lock: tokio::sync::Mutex<()>,
+    /// Verified for unknown users, so logins take the same time whether the user exists or not
+    dummy_hash: String,
+}
+
+#[derive(Debug, Default, Clone, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+struct StoredUsers {
+    users: BTreeMap<String, StoredUser>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct StoredUser {
+    role: pilatus::Role,
+    password_hash: String,
+    #[serde(default)]
+    tokens: Vec<StoredToken>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct StoredToken {
+    #[serde(flatten)]
+    info: TokenInfo,
+    /// Hex encoded sha256 of the token. Tokens have enough entropy, so a salt is not required
+    hash: String,
+}
+
+impl FileUserStore {
+    pub(crate) fn open(path: PathBuf) -> Self {
+        let state = match std::fs::read(&path) {
+            Ok(raw) => serde_json::from_slice(&raw).unwrap_or_else(|e| {
+                warn!("Cannot parse users from {path:?}, starting without users: {e}");
+                StoredUsers::default()
+            }),
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => StoredUsers::default(),
+            Err(e) => {
+                warn!("Cannot read users from {path:?}, starting without users: {e}");
+                StoredUsers::default()
+            }
+        };
+        let dummy_hash = Argon2::default()
+            .hash_password(
+                Uuid::new_v4().to_string().as_bytes(),
+                &SaltString::generate(&mut OsRng),
+            )
+            .map(|h| h.to_string())
+            .unwrap_or_default();
+        Self {
+            path,
+            state: Mutex::new(state),
+            write_lock: Default::default(),
+            dummy_hash,
+        }
+    }
+
+    /// Applies `f` to a copy of the state, which replaces the current state after it was persisted
+    async fn modify<T>(
+        &self,
+        f: impl FnOnce(&mut StoredUsers) -> Result<T, UserError>,
+    ) -> Result<T, UserError> {
+        let _write_guard = self.write_lock.lock().await;
+        let mut copy = self.state.lock().expect("Never poisoned").clone();
+        let result = f(&mut copy)?;
+        persist(&self.path, &copy).await?;
+        *self.state.lock().expect("Never poisoned") = copy;
+        Ok(result)
+    }
+}
+
+async fn persist(path: &Path, users: &StoredUsers) -> anyhow::Result<()> {
+    let tmp = path.with_extension("json.tmp");
+    tokio::fs::write(&tmp, serde_json::to_vec_pretty(users)?).await?;
+    tokio::fs::rename(&tmp, path).await?;
+    Ok(())
+}
+
+fn hash_token(token: &str) -> String {
+    hex::encode(Sha256::digest(token.as_bytes()))
+}
+
+async fn hash_password(password: String) -> Result<String, UserError> {
+    tokio::task::spawn_blocking(move || {
+        let salt = SaltString::generate(&mut OsRng);
+        Argon2::default()
+            .hash_password(password.as_bytes(), &salt)
+            .map(|h| h.to_string())
+            .map_err(|e| anyhow::anyhow!("Cannot hash password: {e}"))
+    })
+    .await
+    .map_err(anyhow::Error::from)?
+    .map_err(Into::into)
+}
+
+async fn verify_password(password: String, hash: String) -> bool {
+    tokio::task::spawn_blocking(move || {
+        PasswordHash::new(&hash)
+            .map(|parsed| {
+                Argon2::default()
+                    .verify_password(password.as_bytes(), &parsed)
+                    .is_ok()
+            })
+            .unwrap_or(false)
+    })
+    .await
+    .unwrap_or(false)
+}
+
+async fn validate_and_hash(user: &User, password: String) -> Result<String, UserError> {
+    if user.name.trim().is_empty() {
+        return Err(UserError::Invalid("Name must not be empty".into()));
+    }
+    if password.len() < 8 {
+        return Err(UserError::Invalid(
+            "Password must have at least 8 characters".into(),
+        ));
+    }
+    hash_password(password).await
+}
+
+/// Tokens of existing users are revoked, as they were issued with the old password
+fn insert_user(state: &mut StoredUsers, user: User, password_hash: String) {
+    let entry = state.users.entry(user.name).or_insert_with(|| StoredUser {
+        role: user.role,
+        password_hash: String::new(),
+        tokens: Vec::new(),
+    });
+    entry.role = user.role;
+    entry.password_hash = password_hash;
+    entry.tokens.clear();
+}
+
+#[async_trait]
+impl UserServiceTrait for FileUserStore {
+    fn authenticate(&self, token: &str) -> Option<User> {
+        let hash = hash_token(token);
+        let now = Utc::now();
+        let state = self.state.lock().expect("Never poisoned");
+        state.users.iter().find_map(|(name, user)| {
+            user.tokens
+                .iter()
+                .any(|t| t.hash == hash && t.info.expires.map_or(true, |e| e > now))
+                .then(|| User {
+                    name: name.clone(),
+                    role: user.role,
+                })
+        })
+    }
+
+    fn has_users(&self) -> bool {
+        !self.state.lock().expect("Never poisoned").users.is_empty()
+    }
+
+    async fn users(&self) -> Vec<User> {
+        let state = self.state.lock().expect("Never poisoned");
+        state
+            .users
+            .iter()
+            .map(|(name, user)| User {
+                name: name.clone(),
+                role: user.role,
+            })
+            .collect()
+    }
+
+    async fn set_user(&self, user: User, password: String) -> Result<(), UserError> {
+        let password_hash = validate_and_hash(&user, password).await?;
+        self.modify(|state| {
+            insert_user(state, user, password_hash);
+            Ok(())
+        })
+        .await
+    }
+
+    async fn create_first_user(&self, user: User, password: String) -> Result<(), UserError> {
+        let password_hash = validate_and_hash(&user, password).await?;
+        // The check runs under the write lock of modify, so it can't race with another insert
+        self.modify(|state| {
+            if !state.users.is_empty() {
+                return Err(UserError::AlreadyInitialized);
+            }
+            insert_user(state, user, password_hash);
+            Ok(())
+        })
+        .await
+    }
+
+    async fn delete_user(&self, name: &str) -> Result<(), UserError> {
+        self.modify(|state| {
+            state
+                .users
+                .remove(name)
+                .map(drop)
+                .ok_or_else(|| UserError::UnknownUser(name.into()))
+        })
+        .await
+    }
+
+    async fn login(
+        &self,
+        name: &str,
+        password: &str,
+        label: String,
+        expires: Option<DateTime<Utc>>,
+    ) -> Result<IssuedToken, UserError> {
+        let hash = {
+            let state = self.state.lock().expect("Never poisoned");
+            state.users.get(name).map(|u| u.password_hash.clone())
+        };
+        let known = hash.is_some();
+        let hash = hash.unwrap_or_else(|| self.dummy_hash.clone());
+        if !verify_password(password.into(), hash).await || !known {
+            return Err(UserError::InvalidCredentials);
+        }
+        // Two v4 uuids provide 244 random bits from the OS
+        let token = format!("{}{}", Uuid::new_v4().simple(), Uuid::new_v4().simple());
+        let info = TokenInfo {
+            id: Uuid::new_v4(),
+            label,
+            created: Utc::now(),
+            expires,
+        };
+        let stored = StoredToken {
+            info: info.clone(),
+            hash: hash_token(&token),
+        };
+        self.modify(|state| {
+            let user = state
+                .users
+                .get_mut(name)
+                .ok_or_else(|| UserError::UnknownUser(name.into()))?;
+            let now = Utc::now();
+            user.tokens
+                .retain(|t| t.info.expires.map_or(true, |e| e > now));
+            user.tokens.push(stored);
+            Ok(())
+        })
+        .await?;
+        Ok(IssuedToken { token, info })
+    }
+
+    async fn tokens(&self, name: &str) -> Vec<TokenInfo> {
+        let state = self.state.lock().expect("Never poisoned");
+        state
+            .users
+            .get(name)
+            .map(|u| u.tokens.iter().map(|t| t.info.clone()).collect())
+            .unwrap_or_default()
+    }
+
+    async fn revoke_token(&self, name: &str, id: Uuid) -> Result<(), UserError> {
+        self.modify(|state| {
+            let user = state
+                .users
+                .get_mut(name)
+                .ok_or_else(|| UserError::UnknownUser(name.into()))?;
+            let before = user.tokens.len();
+            user.tokens.retain(|t| t.info.id != id);
+            if user.tokens.len() == before {
+                Err(UserError::UnknownToken(id))
+            } else {
+                Ok(())
+            }
+        })
+        .await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use pilatus::device::{ActorSystem, MessageOrigin};
+    use pilatus::Role;
+
+    use super::*;
+
+    #[tokio::test]
+    async fn login_and_authenticate_with_token() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join(USERS_FILE);
+        let store = FileUserStore::open(path.clone());
+        let alice = User {
+            name: "alice".into(),
+            role: Role::Admin,
+        };
+        store
+            .set_user(alice.clone(), "correct horse".into())
+            .await
+            .unwrap();
+        assert!(matches!(
+            store.login("alice", "wrong", "test".into(), None).await,
+            Err(UserError::InvalidCredentials)
+        ));
+
+        let issued = store
+            .login("alice", "correct horse", "test".into(), None)
+            .await
+            .unwrap();
+        assert_eq!(Some(alice.clone()), store.authenticate(&issued.token));
+
+        let reopened = FileUserStore::open(path);
+        assert_eq!(Some(alice), reopened.authenticate(&issued.token));
+        reopened
+            .revoke_token("alice", issued.info.id)
+            .await
+            .unwrap();
+        assert_eq!(None, reopened.authenticate(&issued.token));
+    }
+
+    #[tokio::test]
+    async fn password_change_revokes_tokens() {
+        let dir = tempfile::tempdir().unwrap();
+        let store = FileUserStore::open(dir.path().join(USERS_FILE));
+        let carol = User {
+            name: "carol".into(),
+            role: Role::Admin,
+        };
+        store
+            .create_first_user(carol.clone(), "12345678".into())
+            .await
+            .unwrap();
+        assert!(matches!(
+            store
+                .create_first_user(carol.clone(), "87654321".into())
+                .await,
+            Err(UserError::AlreadyInitialized)
+        ));
+        let issued = store
+            .login("carol", "12345678", "test".into(), None)
+            .await
+            .unwrap();
+        store.set_user(carol, "87654321".into()).await.unwrap();
+        assert_eq!(None, store.authenticate(&issued.token));
+        assert!(matches!(
+            store.login("nobody", "12345678", "test".into(), None).await,
+            Err(UserError::InvalidCredentials)
+        ));
+    }
+
+    #[tokio::test]
+    async fn expired_tokens_are_rejected() {
+        let dir = tempfile::tempdir().unwrap();
+        let store = FileUserStore::open(dir.path().join(USERS_FILE));
+        let bob = User {
+            name: "bob".into(),
+            role: Role::Viewer,
+        };
+        store.set_user(bob, "12345678".into()).await.unwrap();
+        let expired = Utc::now() - chrono::Duration::seconds(1);
+        let issued = store
+            .login("bob", "12345678", "old".into(), Some(expired))
+            .await
+            .unwrap();
+        assert_eq!(None, store.authenticate(&issued.token));
+    }
+
+    #[tokio::test]
+    async fn web_origin_only_carries_authenticated_users() {
+        let dir = tempfile::tempdir().unwrap();
+        let store = FileUserStore::open(dir.path().join(USERS_FILE));
+        let dave = User {
+            name: "dave".into(),
+            role: Role::Operator,
+        };
+        store
+            .set_user(dave.clone(), "12345678".into())
+            .await
+            .unwrap();
+        let issued = store
+            .login("dave", "12345678", "test".into(), None)
+            .await
+            .unwrap();
+
+        let system = ActorSystem::new();
+        let web_user = |token: Option<&str>| match system.with_web_origin(&store, token).origin() {
+            MessageOrigin::Web { user, .. } => user.clone(),
+            origin => panic!("Unexpected origin {origin:?}"),
+        };
+        assert_eq!(Some(dave), web_user(Some(issued.token.as_str())));
+        assert_eq!(None, web_user(Some("forged")));
+        assert_eq!(None, web_user(None));
+        assert_eq!(&MessageOrigin::Internal, system.origin());
+    }
+}
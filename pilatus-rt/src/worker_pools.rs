@@ -0,0 +1,16 @@
+use std::sync::Arc;
+
+use minfac::{Registered, ServiceCollection};
+use pilatus::{GenericConfig, WorkerPools, WorkerPoolsConfig};
+
+pub(super) fn register_services(c: &mut ServiceCollection) {
+    c.with::<Registered<GenericConfig>>()
+        .register_shared(|config| {
+            let pools_config = config
+                .get::<WorkerPoolsConfig>("worker_pools")
+                .unwrap_or_default();
+            Arc::new(WorkerPools::new(&pools_config))
+        })
+        .alias(|x| WorkerPools::clone(&x));
+    c.register_instance(pilatus::ServiceInfo::shared::<WorkerPools>());
+}
@@ -0,0 +1,11 @@
+/// Exposes the compiler version as `PILATUS_RUSTC_VERSION`, which plugins have to match (see `plugin::RUSTC_VERSION`)
+fn main() {
+    println!("cargo:rerun-if-env-changed=RUSTC");
+    let rustc = std::env::var("RUSTC").unwrap_or_else(|_| "rustc".into());
+    let output = std::process::Command::new(rustc)
+        .arg("--version")
+        .output()
+        .expect("Cannot run rustc --version");
+    let version = String::from_utf8(output.stdout).expect("rustc version is not utf8");
+    println!("cargo:rustc-env=PILATUS_RUSTC_VERSION={}", version.trim());
+}
@@ -64,6 +64,18 @@ impl GenericConfig {
         Ok(Self { config, root })
     }
 
+    /// Values in `overrides` take precedence over the ones from the JSON-Files. Nested objects are merged
+    pub fn with_overrides(self, overrides: &serde_json::Value) -> anyhow::Result<Self> {
+        let config = config::Config::builder()
+            .add_source(self.config)
+            .add_source(config::Config::try_from(overrides)?)
+            .build()?;
+        Ok(Self {
+            config,
+            root: self.root,
+        })
+    }
+
     pub fn instrument_relative(&self, path: impl Into<PathBuf> + AsRef<Path>) -> PathBuf {
         if path.as_ref().is_relative() {
             self.root.join(path)
@@ -72,6 +84,21 @@ impl GenericConfig {
         }
     }
 
+    /// Working directory for large temporary files like imports and downloads, as system temp dirs are often a small tmpfs.
+    /// Configured with `"temp_dir": "/mnt/data/tmp"`. Relative paths are relative to the root, which also contains the default `tmp`.
+    /// Entries starting with `pilatus-` are leftovers after a restart, which the runtime removes on startup
+    pub fn temp_dir(&self) -> PathBuf {
+        self.instrument_relative(
+            self.get::<PathBuf>("temp_dir")
+                .unwrap_or_else(|_| PathBuf::from("tmp")),
+        )
+    }
+
+    /// Production installations only activate released recipes. Configured with `"production": true`
+    pub fn is_production(&self) -> bool {
+        self.get("production").unwrap_or(false)
+    }
+
     pub fn get<T: DeserializeOwned>(&self, key: &str) -> anyhow::Result<T> {
         Ok(self.config.get::<T>(key)?)
     }
@@ -93,6 +120,16 @@ mod tests {
         )
     }
 
+    #[test]
+    fn temp_dir_is_relative_to_root() -> Result<()> {
+        let tmp = tempfile::tempdir()?;
+        let c = GenericConfig::new(tmp.path())?;
+        assert_eq!(tmp.path().join("tmp"), c.temp_dir());
+        let c = c.with_overrides(&serde_json::json!({ "temp_dir": "work" }))?;
+        assert_eq!(tmp.path().join("work"), c.temp_dir());
+        Ok(())
+    }
+
     #[derive(Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
     struct Foo {
         bar: String,
@@ -108,6 +145,25 @@ mod tests {
         Ok(())
     }
 
+    #[test]
+    fn overrides_are_merged() -> Result<()> {
+        let tmp = tempfile::tempdir()?;
+        std::fs::write(
+            tmp.path().join("develop.json"),
+            r#"{ "foo":  { "bar": "Test", "baz": 1 } }"#,
+        )?;
+        let c = GenericConfig::new(tmp.path())?
+            .with_overrides(&serde_json::json!({ "foo": { "baz": 2 } }))?;
+        assert_eq!(
+            c.get::<Foo>("foo")?,
+            Foo {
+                bar: "Test".into(),
+                baz: 2
+            }
+        );
+        Ok(())
+    }
+
     #[test]
     fn get_partial_default() -> Result<()> {
         let tmp = tempfile::tempdir()?;
@@ -0,0 +1,291 @@
+//! Cycle times between a trigger and its result, e.g. from the camera trigger to the inspection result
+//!
+//! The runtime marks each message sent to a tracked device with its short type name, e.g. `TriggerMessage`,
+//! using [`CycleTimes::mark_message`]. Devices can report additional events with [`CycleTimes::mark`].
+//! Which events start and finish a cycle is configured per device,
+//! so the same device can be used on lines with different timing requirements. The runtime samples the statistics
+//! into a history, which makes slow drifts visible long before the takt is missed.
+
+use std::{
+    collections::{HashMap, VecDeque},
+    sync::{Arc, Mutex},
+    time::{Duration, Instant},
+};
+
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+
+use crate::device::{DeviceId, MessageMetadata};
+
+/// Events which start and finish a cycle of a single device.
+/// Messages are referred to by their type name without path and generics, e.g. `TriggerMessage`
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct CycleDefinition {
+    pub trigger: String,
+    /// Finishes the oldest open trigger. If it equals the trigger, the time between consecutive events is measured
+    pub result: String,
+    /// Expected maximum cycle time. The takt is exceeded, if the p95 is above
+    #[serde(default)]
+    pub takt_ms: Option<f64>,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct CycleTimeStats {
+    /// Cycles within the rolling window
+    pub cycles: usize,
+    /// Finished cycles per minute. None for less than two cycles
+    pub rate_per_minute: Option<f64>,
+    pub mean_ms: f64,
+    pub p95_ms: f64,
+    pub max_ms: f64,
+    /// Standard deviation of the cycle time
+    pub jitter_ms: f64,
+    /// Triggers without result yet
+    pub open_triggers: usize,
+    /// Change of the mean cycle time, estimated from the history. None until there are two samples
+    pub drift_ms_per_hour: Option<f64>,
+    pub takt_ms: Option<f64>,
+    pub takt_exceeded: bool,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct CycleTimeSample {
+    pub time: DateTime<Utc>,
+    pub stats: CycleTimeStats,
+}
+
+/// Cheap to clone, all clones share the same measurements
+#[derive(Debug, Clone, Default)]
+pub struct CycleTimes(Arc<Mutex<CycleTimesState>>);
+
+#[derive(Debug, Default)]
+struct CycleTimesState {
+    window: usize,
+    history_len: usize,
+    trackers: HashMap<DeviceId, Tracker>,
+}
+
+#[derive(Debug)]
+struct Tracker {
+    definition: CycleDefinition,
+    open: VecDeque<Instant>,
+    cycles: VecDeque<(Instant, Duration)>,
+    history: VecDeque<CycleTimeSample>,
+}
+
+impl CycleTimes {
+    /// Replaces the tracked devices. Measurements of devices with an unchanged definition are kept
+    pub fn configure(
+        &self,
+        window: usize,
+        history_len: usize,
+        devices: impl IntoIterator<Item = (DeviceId, CycleDefinition)>,
+    ) {
+        let mut lock = self.0.lock().expect("Never poisoned");
+        let mut previous = std::mem::take(&mut lock.trackers);
+        lock.window = window.max(1);
+        lock.history_len = history_len;
+        lock.trackers = devices
+            .into_iter()
+            .map(|(id, definition)| {
+                let tracker = match previous.remove(&id) {
+                    Some(x) if x.definition == definition => x,
+                    _ => Tracker {
+                        definition,
+                        open: VecDeque::new(),
+                        cycles: VecDeque::new(),
+                        history: VecDeque::new(),
+                    },
+                };
+                (id, tracker)
+            })
+            .collect();
+    }
+
+    /// Events of devices or names which are not configured are ignored, so devices can mark unconditionally
+    pub fn mark(&self, device_id: DeviceId, event: &str) {
+        self.mark_at(device_id, event, Instant::now());
+    }
+
+    /// Marks a message which is sent to a device. Meant to be called by an [`crate::device::ActorInterceptor`]
+    pub fn mark_message(&self, meta: &MessageMetadata) {
+        self.mark(meta.device_id, short_message_name(meta.msg_type_name));
+    }
+
+    fn mark_at(&self, device_id: DeviceId, event: &str, now: Instant) {
+        let mut lock = self.0.lock().expect("Never poisoned");
+        let window = lock.window;
+        let Some(tracker) = lock.trackers.get_mut(&device_id) else {
+            return;
+        };
+        if event == tracker.definition.result {
+            if let Some(start) = tracker.open.pop_front() {
+                if tracker.cycles.len() == window {
+                    tracker.cycles.pop_front();
+                }
+                tracker.cycles.push_back((now, now - start));
+            }
+        }
+        if event == tracker.definition.trigger {
+            // Triggers without result, e.g. due to a lost frame, must not accumulate
+            if tracker.open.len() == window {
+                tracker.open.pop_front();
+            }
+            tracker.open.push_back(now);
+        }
+    }
+
+    pub fn stats(&self) -> HashMap<DeviceId, CycleTimeStats> {
+        let lock = self.0.lock().expect("Never poisoned");
+        lock.trackers
+            .iter()
+            .map(|(id, tracker)| (*id, tracker.stats()))
+            .collect()
+    }
+
+    /// Oldest sample first. Empty for devices which are not tracked
+    pub fn history(&self, device_id: DeviceId) -> Vec<CycleTimeSample> {
+        let lock = self.0.lock().expect("Never poisoned");
+        lock.trackers
+            .get(&device_id)
+            .map(|x| x.history.iter().cloned().collect())
+            .unwrap_or_default()
+    }
+
+    /// Appends the current statistics of all devices to their history
+    pub fn sample(&self) {
+        let time = Utc::now();
+        let mut lock = self.0.lock().expect("Never poisoned");
+        let history_len = lock.history_len;
+        for tracker in lock.trackers.values_mut() {
+            let stats = tracker.stats();
+            if stats.cycles == 0 {
+                continue;
+            }
+            if tracker.history.len() >= history_len {
+                tracker.history.pop_front();
+            }
+            if history_len > 0 {
+                tracker.history.push_back(CycleTimeSample { time, stats });
+            }
+        }
+    }
+}
+
+/// `my_crate::module::TriggerMessage<u8>` becomes `TriggerMessage`
+fn short_message_name(type_name: &str) -> &str {
+    let without_generics = type_name.split('<').next().unwrap_or(type_name);
+    without_generics
+        .rsplit("::")
+        .next()
+        .unwrap_or(without_generics)
+}
+
+impl Tracker {
+    fn stats(&self) -> CycleTimeStats {
+        let mut durations: Vec<f64> = self
+            .cycles
+            .iter()
+            .map(|(_, d)| d.as_secs_f64() * 1000.)
+            .collect();
+        let takt_ms = self.definition.takt_ms;
+        let mut stats = CycleTimeStats {
+            cycles: durations.len(),
+            open_triggers: self.open.len(),
+            drift_ms_per_hour: self.drift_ms_per_hour(),
+            takt_ms,
+            ..Default::default()
+        };
+        if durations.is_empty() {
+            return stats;
+        }
+        durations.sort_by(f64::total_cmp);
+        let n = durations.len() as f64;
+        let mean = durations.iter().sum::<f64>() / n;
+        stats.mean_ms = mean;
+        stats.p95_ms = durations[((n * 0.95).ceil() as usize).clamp(1, durations.len()) - 1];
+        stats.max_ms = durations[durations.len() - 1];
+        stats.jitter_ms = (durations.iter().map(|x| (x - mean).powi(2)).sum::<f64>() / n).sqrt();
+        if let (Some((first, _)), Some((last, _))) = (self.cycles.front(), self.cycles.back()) {
+            let span = last.duration_since(*first).as_secs_f64();
+            if self.cycles.len() > 1 && span > 0. {
+                stats.rate_per_minute = Some((self.cycles.len() - 1) as f64 * 60. / span);
+            }
+        }
+        stats.takt_exceeded = takt_ms.is_some_and(|takt| stats.p95_ms > takt);
+        stats
+    }
+
+    /// Slope of the least squares line through the mean of all history samples
+    fn drift_ms_per_hour(&self) -> Option<f64> {
+        let first = self.history.front()?.time;
+        let points: Vec<(f64, f64)> = self
+            .history
+            .iter()
+            .map(|x| {
+                let hours = (x.time - first).num_milliseconds() as f64 / 3_600_000.;
+                (hours, x.stats.mean_ms)
+            })
+            .collect();
+        let n = points.len() as f64;
+        let (mean_x, mean_y) = points
+            .iter()
+            .fold((0., 0.), |(sx, sy), (x, y)| (sx + x / n, sy + y / n));
+        let (covariance, variance) = points.iter().fold((0., 0.), |(c, v), (x, y)| {
+            (c + (x - mean_x) * (y - mean_y), v + (x - mean_x).powi(2))
+        });
+        (variance > 0.).then(|| covariance / variance)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn strip_path_and_generics_from_message_names() {
+        assert_eq!("Trigger", short_message_name("Trigger"));
+        assert_eq!("Trigger", short_message_name("a::b::Trigger"));
+        assert_eq!("Trigger", short_message_name("a::Trigger<b::C<u8>>"));
+    }
+
+    #[test]
+    fn pair_results_with_oldest_trigger() {
+        let times = CycleTimes::default();
+        let device_id = DeviceId::new_v4();
+        times.configure(
+            100,
+            10,
+            [(
+                device_id,
+                CycleDefinition {
+                    trigger: "trigger".into(),
+                    result: "result".into(),
+                    takt_ms: Some(150.),
+                },
+            )],
+        );
+        let start = Instant::now();
+        let at = |ms| start + Duration::from_millis(ms);
+        // Two overlapping cycles of 100ms and 200ms, one cycle of 100ms
+        times.mark_at(device_id, "trigger", at(0));
+        times.mark_at(device_id, "trigger", at(50));
+        times.mark_at(device_id, "result", at(100));
+        times.mark_at(device_id, "result", at(250));
+        times.mark_at(device_id, "trigger", at(500));
+        times.mark_at(device_id, "result", at(600));
+        times.mark_at(device_id, "trigger", at(700));
+        times.mark_at(DeviceId::new_v4(), "result", at(800));
+
+        let stats = times.stats().remove(&device_id).unwrap();
+        assert_eq!((3, 1), (stats.cycles, stats.open_triggers));
+        assert!((stats.mean_ms - 400. / 3.).abs() < 1e-6, "{stats:?}");
+        assert!((stats.p95_ms - 200.).abs() < 1e-6, "{stats:?}");
+        assert!((stats.rate_per_minute.unwrap() - 2. * 60. / 0.5).abs() < 1e-6);
+        assert!(stats.takt_exceeded);
+
+        times.sample();
+        assert_eq!(1, times.history(device_id).len());
+    }
+}
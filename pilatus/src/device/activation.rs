@@ -0,0 +1,20 @@
+use super::ActorMessage;
+
+/// Optional message for work which must be done before the recipe is considered active, e.g. homing an axis.
+/// The runtime starts the devices of types registered with [`PreparesActivation`] first and sends them this message.
+/// All other devices are only started, once every preparation succeeded
+#[derive(Debug, Clone, Default, ActorMessage)]
+#[actor_message(crate = crate, output = (), error = anyhow::Error, name = "prepare_activation")]
+pub struct PrepareActivationMessage;
+
+/// Optional message for work which requires all devices to be up, e.g. enabling outputs.
+/// It's only sent if all devices handling [`PrepareActivationMessage`] succeeded in time
+#[derive(Debug, Clone, Default, ActorMessage)]
+#[actor_message(crate = crate, output = (), error = anyhow::Error, name = "activation_complete")]
+pub struct ActivationCompleteMessage;
+
+/// Registered next to device types which handle [`PrepareActivationMessage`], e.g.
+/// `c.register_instance(PreparesActivation("axis"))`. The runtime has to know them before the devices are spawned,
+/// as the handlers are only known once a device runs
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct PreparesActivation(pub &'static str);
@@ -1,18 +1,62 @@
-use crate::Recipes;
+use std::collections::{HashMap, HashSet};
+
+use crate::{device::DeviceId, ApprovalState, Recipes};
 
 #[derive(serde::Serialize, serde::Deserialize)]
+#[cfg_attr(feature = "ts", derive(ts_rs::TS), ts(export))]
 pub struct ActiveState {
     /// Should contain information like DeviceState (Device will be mapped)
     #[serde(flatten)]
     pub recipes: Recipes,
     pub has_uncommitted_changes: bool,
+    /// Devices of the active recipe which don't talk to real hardware
+    #[serde(default)]
+    pub simulated_devices: HashSet<DeviceId>,
+    /// Devices of the active recipe which require an unlock token to change parameters
+    #[serde(default)]
+    pub locked_devices: HashSet<DeviceId>,
+    /// Approval state of the active recipe
+    #[serde(default)]
+    pub approval: ApprovalState,
+    /// JSON pointers of fields which must be set before these devices are started
+    #[serde(default)]
+    pub unfilled_placeholders: HashMap<DeviceId, Vec<String>>,
 }
 
 impl ActiveState {
     pub fn new(recipes: Recipes, has_uncommitted_changes: bool) -> Self {
+        let (_, active) = recipes.active();
+        let simulated_devices = active
+            .devices
+            .iter_unordered()
+            .filter(|(_, d)| d.simulated)
+            .map(|(id, _)| *id)
+            .collect();
+        let locked_devices = active
+            .devices
+            .iter_unordered()
+            .filter(|(_, d)| d.locked)
+            .map(|(id, _)| *id)
+            .collect();
+        let unfilled_placeholders = active
+            .devices
+            .iter_unordered()
+            .filter_map(|(id, d)| {
+                let fields = d
+                    .unfilled_placeholders()
+                    .map(str::to_string)
+                    .collect::<Vec<_>>();
+                (!fields.is_empty()).then_some((*id, fields))
+            })
+            .collect();
+        let approval = active.approval;
         Self {
             recipes,
             has_uncommitted_changes,
+            simulated_devices,
+            locked_devices,
+            approval,
+            unfilled_placeholders,
         }
     }
 
@@ -0,0 +1,81 @@
+use std::{any::TypeId, collections::BTreeSet};
+
+use super::{ActorMessage, ActorSystem, DeviceId};
+
+/// Named message type, so clients can discover what a device supports without knowing its device_type.
+/// Register instances with `ServiceCollection::register_instance`. They are served by `GET /api/device/:device_id/capabilities`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct DeviceCapability {
+    pub name: &'static str,
+    pub message_type: TypeId,
+}
+
+impl DeviceCapability {
+    pub fn of<TMsg: ActorMessage>(name: &'static str) -> Self {
+        Self {
+            name,
+            message_type: TypeId::of::<TMsg>(),
+        }
+    }
+}
+
+impl ActorSystem {
+    /// Names of the `capabilities` whose message is handled by the device
+    pub fn device_capabilities(
+        &self,
+        device_id: DeviceId,
+        capabilities: impl IntoIterator<Item = DeviceCapability>,
+    ) -> BTreeSet<&'static str> {
+        let capabilities = capabilities.into_iter().collect::<Vec<_>>();
+        let grouped = self
+            .list_grouped_devices_for_message_types(capabilities.iter().map(|x| x.message_type));
+        capabilities
+            .into_iter()
+            .zip(grouped)
+            .filter(|(_, (_, devices))| devices.contains(&device_id))
+            .map(|(capability, _)| capability.name)
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::device::ActorResult;
+
+    #[derive(Debug)]
+    struct Supported;
+    impl ActorMessage for Supported {
+        type Output = ();
+        type Error = ();
+    }
+
+    #[derive(Debug)]
+    struct Unsupported;
+    impl ActorMessage for Unsupported {
+        type Output = ();
+        type Error = ();
+    }
+
+    #[test]
+    fn list_capabilities_of_handled_messages() {
+        async fn handle(_: &mut (), _: Supported) -> ActorResult<Supported> {
+            Ok(())
+        }
+        let system = ActorSystem::new();
+        let device_id = DeviceId::new_v4();
+        let _device = system.register(device_id).add_handler(handle);
+        let capabilities = [
+            DeviceCapability::of::<Supported>("supported"),
+            DeviceCapability::of::<Unsupported>("unsupported"),
+        ];
+
+        assert_eq!(
+            BTreeSet::from(["supported"]),
+            system.device_capabilities(device_id, capabilities)
+        );
+        assert!(system
+            .device_capabilities(DeviceId::new_v4(), capabilities)
+            .is_empty());
+    }
+}
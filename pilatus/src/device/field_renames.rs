@@ -0,0 +1,114 @@
+use serde_json::Value;
+
+use super::{DeviceContext, WithInfallibleParamUpdate};
+use crate::UntypedDeviceParamsWithVariables;
+
+/// Fields which were renamed in the params of a device type. Register one per device type, e.g.
+/// `c.register(|| FieldRenames::new(DEVICE_TYPE).with_rename("/exposure_ms", "exposure"))`.
+/// Devices are migrated when they are spawned or imported, so old recipes keep working with `deny_unknown_fields`
+#[derive(Debug, Clone)]
+pub struct FieldRenames {
+    device_type: &'static str,
+    renames: Vec<(String, String)>,
+}
+
+impl FieldRenames {
+    pub fn new(device_type: &'static str) -> Self {
+        Self {
+            device_type,
+            renames: Vec::new(),
+        }
+    }
+
+    /// `old` is the JSON pointer of the field, e.g. `/roi/width_px`. `new` is the name within the same object
+    pub fn with_rename(mut self, old: impl Into<String>, new: impl Into<String>) -> Self {
+        self.renames.push((old.into(), new.into()));
+        self
+    }
+
+    pub fn device_type(&self) -> &'static str {
+        self.device_type
+    }
+
+    /// Renames are applied in registration order. Fields which exist with the new name already are never overwritten
+    pub fn merge(mut self, other: Self) -> Self {
+        self.renames.extend(other.renames);
+        self
+    }
+
+    /// Returns true, if any field was renamed
+    pub fn apply(&self, params: &mut Value) -> bool {
+        let mut changed = false;
+        for (old, new) in &self.renames {
+            let Some((parent, name)) = old.rsplit_once('/') else {
+                continue;
+            };
+            let name = name.replace("~1", "/").replace("~0", "~");
+            let Some(Value::Object(parent)) = params.pointer_mut(parent) else {
+                continue;
+            };
+            if parent.contains_key(new) {
+                continue;
+            }
+            if let Some(value) = parent.remove(&name) {
+                parent.insert(new.clone(), value);
+                changed = true;
+            }
+        }
+        changed
+    }
+}
+
+impl DeviceContext {
+    /// Returns the migrated params, which have to be persisted, if any field was renamed
+    pub fn migrate_fields(
+        &mut self,
+        renames: &FieldRenames,
+    ) -> Option<UntypedDeviceParamsWithVariables> {
+        renames
+            .apply(&mut self.params_with_vars)
+            .then(|| self.params_with_vars.clone())
+    }
+}
+
+impl<T> WithInfallibleParamUpdate<T> {
+    /// Used for migrations before validation. Updates of the validator are based on the migrated params and therefore win
+    pub fn or_update(self, update: Option<UntypedDeviceParamsWithVariables>) -> Self {
+        Self {
+            data: self.data,
+            update: self.update.or(update),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use super::*;
+
+    #[test]
+    fn rename_nested_fields_without_overwriting() {
+        let renames = FieldRenames::new("camera")
+            .with_rename("/exposure_ms", "exposure")
+            .with_rename("/roi/width_px", "width")
+            .with_rename("/gain_db", "gain");
+        let mut params = json!({
+            "exposure_ms": { "__var": "exposure" },
+            "roi": { "width_px": 100 },
+            "gain_db": 1,
+            "gain": 2
+        });
+        assert!(renames.apply(&mut params));
+        assert_eq!(
+            json!({
+                "exposure": { "__var": "exposure" },
+                "roi": { "width": 100 },
+                "gain_db": 1,
+                "gain": 2
+            }),
+            params
+        );
+        assert!(!renames.apply(&mut params));
+    }
+}
@@ -0,0 +1,20 @@
+use super::ActorMessage;
+
+/// Sent by the recipe service while it copies device folders, e.g. into the backup of the active recipe.
+/// Devices which write into their folder flush pending writes before they respond to a pause and don't write
+/// until they are resumed. Devices are always resumed, even if copying failed
+#[derive(Debug, Clone, ActorMessage)]
+#[actor_message(crate = crate, output = (), error = anyhow::Error, name = "pause_file_writes")]
+pub struct PauseFileWritesMessage {
+    pub paused: bool,
+}
+
+impl PauseFileWritesMessage {
+    pub fn pause() -> Self {
+        Self { paused: true }
+    }
+
+    pub fn resume() -> Self {
+        Self { paused: false }
+    }
+}
@@ -0,0 +1,73 @@
+//! Messages of the job queue device, which persists jobs and processes them later, e.g. when the line is idle
+//!
+//! Producers enqueue payloads with [`EnqueueJobMessage`]. The queue sends them to a worker device with [`ProcessJobMessage`]
+//! and retries failed jobs with backoff. Jobs which fail too often become dead letters, which can be retried manually.
+
+use chrono::{DateTime, Utc};
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
+use uuid::Uuid;
+
+use super::ActorMessage;
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Job {
+    pub id: Uuid,
+    /// Workers can handle multiple kinds of jobs, e.g. "archive_image" or "report_result"
+    pub kind: String,
+    pub payload: serde_json::Value,
+    pub enqueued: DateTime<Utc>,
+    /// Failed attempts so far
+    pub attempts: u32,
+    /// Not dispatched before. None if the job can be dispatched right away
+    pub next_attempt: Option<DateTime<Utc>>,
+    pub last_error: Option<String>,
+}
+
+impl Job {
+    pub fn payload_as<T: DeserializeOwned>(&self) -> serde_json::Result<T> {
+        T::deserialize(&self.payload)
+    }
+}
+
+/// Returns the id of the new job, once it is persisted
+#[derive(Debug, Clone, ActorMessage)]
+#[actor_message(crate = crate, output = Uuid, error = anyhow::Error, name = "enqueue_job")]
+pub struct EnqueueJobMessage {
+    pub kind: String,
+    pub payload: serde_json::Value,
+}
+
+impl EnqueueJobMessage {
+    pub fn new(kind: impl Into<String>, payload: &impl Serialize) -> serde_json::Result<Self> {
+        Ok(Self {
+            kind: kind.into(),
+            payload: serde_json::to_value(payload)?,
+        })
+    }
+}
+
+/// Sent by the queue to its worker. The job is removed from the queue, if the worker returns Ok
+#[derive(Debug, Clone, ActorMessage)]
+#[actor_message(crate = crate, output = (), error = anyhow::Error, name = "process_job")]
+pub struct ProcessJobMessage {
+    pub job: Job,
+}
+
+#[derive(Debug, Clone, Default, ActorMessage)]
+#[actor_message(crate = crate, output = JobQueueSnapshot, error = anyhow::Error, name = "list_jobs")]
+#[non_exhaustive]
+pub struct ListJobsMessage {}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct JobQueueSnapshot {
+    pub pending: Vec<Job>,
+    pub dead_letters: Vec<Job>,
+    /// Paused queues don't dispatch, e.g. during maintenance
+    pub paused: bool,
+}
+
+/// Moves all dead letters back into the queue with reset attempts. Returns the number of requeued jobs
+#[derive(Debug, Clone, Default, ActorMessage)]
+#[actor_message(crate = crate, output = usize, error = anyhow::Error, name = "retry_dead_letters")]
+#[non_exhaustive]
+pub struct RetryDeadLettersMessage {}
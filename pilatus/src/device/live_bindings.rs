@@ -0,0 +1,55 @@
+/// Params of a device type which follow their variables while the device is running. Register one per device type, e.g.
+/// `c.register(|| LiveBindings::new(DEVICE_TYPE).with_param("/line_speed"))`.
+/// If a variable changes, running devices which reference it in live-bound params only receive an `UpdateParamsMessage`.
+/// Devices with other references pick up the change with the next recipe activation
+#[derive(Debug, Clone)]
+pub struct LiveBindings {
+    device_type: &'static str,
+    params: Vec<String>,
+}
+
+impl LiveBindings {
+    pub fn new(device_type: &'static str) -> Self {
+        Self {
+            device_type,
+            params: Vec::new(),
+        }
+    }
+
+    /// `pointer` is the JSON pointer of the param, e.g. `/roi`. Nested fields like `/roi/width_px` are bound too
+    pub fn with_param(mut self, pointer: impl Into<String>) -> Self {
+        self.params.push(pointer.into());
+        self
+    }
+
+    pub fn device_type(&self) -> &'static str {
+        self.device_type
+    }
+
+    pub fn merge(mut self, other: Self) -> Self {
+        self.params.extend(other.params);
+        self
+    }
+
+    pub fn is_bound(&self, pointer: &str) -> bool {
+        self.params.iter().any(|p| {
+            pointer
+                .strip_prefix(p.as_str())
+                .is_some_and(|rest| rest.is_empty() || rest.starts_with('/'))
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn nested_fields_are_bound() {
+        let bindings = LiveBindings::new("test").with_param("/roi");
+        assert!(bindings.is_bound("/roi"));
+        assert!(bindings.is_bound("/roi/width_px"));
+        assert!(!bindings.is_bound("/roi_offset"));
+        assert!(!bindings.is_bound("/exposure"));
+    }
+}
@@ -40,6 +40,8 @@ where
     ) where
         TFut: Future<Output = DeviceResult> + Send + 'static,
     {
+        self.0
+            .register_instance(crate::ServiceInfo::device::<TDep>(device_type));
         self.0.register_instance(
             Box::new(super::DepDeviceHandler::<TDep, TFut, TParams>::new(
                 device_type,
@@ -1,26 +1,59 @@
-use std::{fmt::Debug, sync::Arc};
+use std::{
+    collections::{HashMap, HashSet},
+    fmt::Debug,
+    sync::Arc,
+    time::Duration,
+};
 
 use anyhow::Result;
 use async_trait::async_trait;
 use futures::{channel::oneshot, future::BoxFuture};
 
-use crate::{RecipeId, UntypedDeviceParamsWithVariables, Variables};
+use crate::{DeviceConfig, RecipeId, UntypedDeviceParamsWithVariables, Variables};
 
+mod activation;
 mod active_state;
+mod capability;
+mod field_renames;
+mod file_writes;
+mod job_queue;
+mod live_bindings;
 #[cfg(all(feature = "tokio", feature = "minfac"))]
 mod minfac_ext;
+mod pause;
+mod remote;
+#[cfg(feature = "tokio")]
+mod runtime_status;
+mod self_test;
 #[cfg(all(feature = "tokio", feature = "minfac"))]
 mod spawner;
+mod state_store;
+#[cfg(feature = "tokio")]
+mod statistics;
 mod system;
 #[cfg(feature = "tokio")]
 mod validation;
 
+pub use activation::*;
 pub use active_state::*;
+pub use capability::*;
+pub use field_renames::*;
+pub use file_writes::*;
+pub use job_queue::*;
+pub use live_bindings::*;
 pub type DeviceResult = Result<()>;
 #[cfg(all(feature = "tokio", feature = "minfac"))]
 pub use minfac_ext::*;
+pub use pause::*;
+pub use remote::*;
+#[cfg(feature = "tokio")]
+pub use runtime_status::*;
+pub use self_test::*;
 #[cfg(all(feature = "tokio", feature = "minfac"))]
 pub use spawner::*;
+pub use state_store::*;
+#[cfg(feature = "tokio")]
+pub use statistics::*;
 pub use system::*;
 #[cfg(feature = "tokio")]
 pub use validation::*;
@@ -48,11 +81,45 @@ impl RecipeRunner {
     pub fn select_recipe(&self, recipe_id: RecipeId) -> BoxFuture<anyhow::Result<()>> {
         self.0.select_recipe(recipe_id)
     }
+
+    /// Stops the active recipe and runs the devices of `recipe` instead.
+    /// The active recipe is restarted after `ScratchRecipe::timeout` or when stopped explicitly
+    pub fn run_scratch_recipe(&self, recipe: ScratchRecipe) -> BoxFuture<anyhow::Result<()>> {
+        self.0.run_scratch_recipe(recipe)
+    }
+
+    pub fn stop_scratch_recipe(&self) -> BoxFuture<anyhow::Result<()>> {
+        self.0.stop_scratch_recipe()
+    }
+
+    /// Stops the devices and starts them again with their current configuration.
+    /// Returns before the devices are restarted. Devices which aren't running are ignored
+    pub fn restart_devices(&self, devices: HashSet<DeviceId>) -> BoxFuture<anyhow::Result<()>> {
+        self.0.restart_devices(devices)
+    }
 }
 
 #[async_trait]
 pub trait RecipeRunnerTrait: Send + Sync {
     async fn select_recipe(&self, recipe_id: RecipeId) -> anyhow::Result<()>;
+    async fn run_scratch_recipe(&self, recipe: ScratchRecipe) -> anyhow::Result<()>;
+    async fn stop_scratch_recipe(&self) -> anyhow::Result<()>;
+    async fn restart_devices(&self, devices: HashSet<DeviceId>) -> anyhow::Result<()>;
+}
+
+/// Devices which are run temporarily without being persisted in the recipes.
+/// Allows engineers to trial a device configuration without polluting the saved recipe list.
+/// Parameter-Migrations of these devices are discarded.
+#[derive(Debug, Clone)]
+pub struct ScratchRecipe {
+    pub devices: HashMap<DeviceId, DeviceConfig>,
+    pub timeout: Duration,
+}
+
+impl ScratchRecipe {
+    pub fn new(devices: HashMap<DeviceId, DeviceConfig>, timeout: Duration) -> Self {
+        Self { devices, timeout }
+    }
 }
 
 impl<T> IgnoreNotSendableOneShotChannel<T>
@@ -71,6 +138,9 @@ pub struct DeviceContext {
     // Must stay private to forbid access to variables in device
     variables: Variables,
     params_with_vars: UntypedDeviceParamsWithVariables,
+    simulated: bool,
+    state: DeviceState,
+    origin: MessageOrigin,
 }
 
 impl DeviceContext {
@@ -83,8 +153,42 @@ impl DeviceContext {
             id,
             variables,
             params_with_vars,
+            simulated: false,
+            state: DeviceStateStore::in_memory().scope(id),
+            origin: MessageOrigin::Internal,
         }
     }
+
+    /// Parameter updates of running devices are sent with this origin, e.g. the one of [`crate::TransactionOptions::origin`]
+    pub fn with_origin(self, origin: MessageOrigin) -> Self {
+        Self { origin, ..self }
+    }
+
+    pub fn origin(&self) -> &MessageOrigin {
+        &self.origin
+    }
+
+    pub fn with_simulated(self, simulated: bool) -> Self {
+        Self { simulated, ..self }
+    }
+
+    /// Set by the device spawner. Without a store, the state is only kept in memory of this context
+    pub fn with_state_store(self, store: &DeviceStateStore) -> Self {
+        Self {
+            state: store.scope(self.id),
+            ..self
+        }
+    }
+
+    /// Runtime state like counters, which survives restarts but isn't part of the recipe
+    pub fn state(&self) -> &DeviceState {
+        &self.state
+    }
+
+    /// Devices for real hardware should use their emulation instead of connecting to the hardware
+    pub fn is_simulated(&self) -> bool {
+        self.simulated
+    }
     #[cfg(feature = "unstable")]
     pub fn with_random_id(device: impl serde::Serialize) -> Self {
         Self::new(
@@ -0,0 +1,19 @@
+use super::ActorMessage;
+
+/// Optional message for devices which produce data or move something, e.g. cameras or axes.
+/// The runtime pauses them while the system is in maintenance mode and resumes them afterwards
+#[derive(Debug, Clone, ActorMessage)]
+#[actor_message(crate = crate, output = (), error = anyhow::Error, name = "pause_producer")]
+pub struct PauseProducerMessage {
+    pub paused: bool,
+}
+
+impl PauseProducerMessage {
+    pub fn pause() -> Self {
+        Self { paused: true }
+    }
+
+    pub fn resume() -> Self {
+        Self { paused: false }
+    }
+}
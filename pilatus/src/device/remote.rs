@@ -0,0 +1,261 @@
+//! Forwarding of messages to devices which live in another pilatus process.
+//! Only message types registered as `RemoteMessage` can cross process boundaries.
+//! The transport itself is provided by the runtime.
+
+use std::{borrow::Cow, collections::HashMap, sync::Arc};
+
+use futures::{future::BoxFuture, FutureExt};
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
+
+use super::{
+    ActorDevice, ActorError, ActorErrorUnknownDevice, ActorResult, ActorSystem, DeviceId,
+    MessageOrigin, Step2, WireActorMessage,
+};
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct RemoteRequest {
+    pub device_id: DeviceId,
+    /// `WireActorMessage::WIRE_NAME` of the message
+    pub message: Cow<'static, str>,
+    pub payload: serde_json::Value,
+}
+
+pub type RemoteResponse = Result<serde_json::Value, RemoteActorError>;
+
+#[derive(Debug, Clone, thiserror::Error, Serialize, Deserialize)]
+pub enum RemoteActorError {
+    #[error("Unknown device '{0}' on remote node")]
+    UnknownDevice(DeviceId),
+    #[error("Message '{0}' is not registered for remote calls")]
+    UnknownMessageType(String),
+    #[error("Error occured within the remote device: {0}")]
+    Custom(serde_json::Value),
+    #[error("{0}")]
+    Other(String),
+}
+
+/// Sends requests to another pilatus process
+pub trait RemoteTransport: Send + Sync {
+    fn request(&self, request: RemoteRequest) -> BoxFuture<'static, RemoteResponse>;
+}
+
+type AddProxyHandler = fn(ActorDevice<RemoteProxy>) -> ActorDevice<RemoteProxy>;
+type Dispatch = fn(ActorSystem, DeviceId, serde_json::Value) -> BoxFuture<'static, RemoteResponse>;
+
+/// Register it with `register_instance` to make a message type available for remote calls in both directions
+#[derive(Clone, Copy)]
+pub struct RemoteMessage {
+    wire_name: &'static str,
+    add_proxy_handler: AddProxyHandler,
+    dispatch: Dispatch,
+}
+
+impl RemoteMessage {
+    pub fn new<TMsg>() -> Self
+    where
+        TMsg: WireActorMessage + Serialize + DeserializeOwned,
+        TMsg::Output: Serialize + DeserializeOwned,
+        TMsg::Error: Serialize + DeserializeOwned,
+    {
+        Self {
+            wire_name: TMsg::WIRE_NAME,
+            add_proxy_handler: |device| device.add_handler(forward::<TMsg>),
+            dispatch: dispatch::<TMsg>,
+        }
+    }
+
+    pub fn wire_name(&self) -> &'static str {
+        self.wire_name
+    }
+}
+
+/// Lookup for incoming requests from other processes
+#[derive(Clone, Default)]
+pub struct RemoteMessages(Arc<HashMap<&'static str, RemoteMessage>>);
+
+impl RemoteMessages {
+    pub fn new(messages: impl IntoIterator<Item = RemoteMessage>) -> Self {
+        Self(Arc::new(
+            messages.into_iter().map(|m| (m.wire_name, m)).collect(),
+        ))
+    }
+
+    pub fn dispatch(
+        &self,
+        system: ActorSystem,
+        request: RemoteRequest,
+    ) -> BoxFuture<'static, RemoteResponse> {
+        match self.0.get(request.message.as_ref()) {
+            Some(m) => (m.dispatch)(system, request.device_id, request.payload),
+            None => futures::future::ready(Err(RemoteActorError::UnknownMessageType(
+                request.message.into_owned(),
+            )))
+            .boxed(),
+        }
+    }
+
+    /// Registers a local device, which forwards all known messages to the device with the same id on the remote node.
+    /// The proxy stops when the senders of the ActorSystem are forgotten
+    pub fn spawn_proxy(
+        &self,
+        system: &ActorSystem,
+        device_id: DeviceId,
+        transport: Arc<dyn RemoteTransport>,
+    ) -> BoxFuture<'static, ()> {
+        let device = self
+            .0
+            .values()
+            .fold(system.register(device_id), |device, m| {
+                (m.add_proxy_handler)(device)
+            });
+        device
+            .execute(RemoteProxy {
+                device_id,
+                transport,
+            })
+            .map(|_| ())
+            .boxed()
+    }
+}
+
+pub struct RemoteProxy {
+    device_id: DeviceId,
+    transport: Arc<dyn RemoteTransport>,
+}
+
+async fn forward<TMsg>(
+    state: &mut RemoteProxy,
+    msg: TMsg,
+) -> Step2<BoxFuture<'static, ActorResult<TMsg>>>
+where
+    TMsg: WireActorMessage + Serialize + DeserializeOwned,
+    TMsg::Output: Serialize + DeserializeOwned,
+    TMsg::Error: Serialize + DeserializeOwned,
+{
+    let device_id = state.device_id;
+    let payload = serde_json::to_value(msg);
+    let transport = state.transport.clone();
+    Step2(
+        async move {
+            let payload = payload.map_err(|e| ActorError::Remote(e.to_string()))?;
+            let response = transport
+                .request(RemoteRequest {
+                    device_id,
+                    message: TMsg::WIRE_NAME.into(),
+                    payload,
+                })
+                .await;
+            match response {
+                Ok(x) => serde_json::from_value(x).map_err(|e| ActorError::Remote(e.to_string())),
+                Err(RemoteActorError::UnknownDevice(device_id)) => Err(
+                    ActorErrorUnknownDevice::unknown_id(device_id, "Unknown on remote node")
+                        .with_message_type::<TMsg>()
+                        .into(),
+                ),
+                Err(RemoteActorError::UnknownMessageType(_)) => {
+                    Err(ActorError::UnknownMessageType(TMsg::WIRE_NAME))
+                }
+                Err(RemoteActorError::Custom(x)) => Err(serde_json::from_value(x)
+                    .map(ActorError::Custom)
+                    .unwrap_or_else(|e| ActorError::Remote(e.to_string()))),
+                Err(RemoteActorError::Other(x)) => Err(ActorError::Remote(x)),
+            }
+        }
+        .boxed(),
+    )
+}
+
+fn dispatch<TMsg>(
+    system: ActorSystem,
+    device_id: DeviceId,
+    payload: serde_json::Value,
+) -> BoxFuture<'static, RemoteResponse>
+where
+    TMsg: WireActorMessage + Serialize + DeserializeOwned,
+    TMsg::Output: Serialize + DeserializeOwned,
+    TMsg::Error: Serialize + DeserializeOwned,
+{
+    async move {
+        let msg = serde_json::from_value::<TMsg>(payload)
+            .map_err(|e| RemoteActorError::Other(format!("Invalid payload: {e}")))?;
+        let serialize_error = |e: serde_json::Error| RemoteActorError::Other(e.to_string());
+        let system = system.with_origin(MessageOrigin::Remote);
+        match system.ask(device_id, msg).await {
+            Ok(x) => serde_json::to_value(x).map_err(serialize_error),
+            Err(ActorError::Custom(x)) => Err(RemoteActorError::Custom(
+                serde_json::to_value(x).map_err(serialize_error)?,
+            )),
+            Err(ActorError::UnknownDevice(_)) => Err(RemoteActorError::UnknownDevice(device_id)),
+            Err(ActorError::UnknownMessageType(x)) => {
+                Err(RemoteActorError::UnknownMessageType(x.into()))
+            }
+            Err(e) => Err(RemoteActorError::Other(e.to_string())),
+        }
+    }
+    .boxed()
+}
+
+#[cfg(all(test, feature = "tokio"))]
+mod tests {
+    use std::time::Duration;
+
+    use super::*;
+    use crate::device::ActorMessage;
+
+    #[derive(Serialize, Deserialize, ActorMessage)]
+    #[actor_message(crate = crate, output = i32, error = String, name = "add")]
+    struct AddMessage(i32);
+
+    struct LoopbackTransport {
+        system: ActorSystem,
+        messages: RemoteMessages,
+    }
+
+    impl RemoteTransport for LoopbackTransport {
+        fn request(&self, request: RemoteRequest) -> BoxFuture<'static, RemoteResponse> {
+            let request = serde_json::from_str(&serde_json::to_string(&request).unwrap()).unwrap();
+            self.messages.dispatch(self.system.clone(), request)
+        }
+    }
+
+    #[tokio::test]
+    async fn forward_to_remote_system() {
+        let local = ActorSystem::new();
+        let remote = ActorSystem::new();
+        let messages = RemoteMessages::new([RemoteMessage::new::<AddMessage>()]);
+        let id = DeviceId::new_v4();
+
+        async fn add(state: &mut i32, msg: AddMessage) -> ActorResult<AddMessage> {
+            if msg.0 < 0 {
+                return Err(ActorError::Custom("negative".into()));
+            }
+            Ok(*state + msg.0)
+        }
+        let remote_device = remote.register(id).add_handler(add).execute(40);
+        let proxy = messages.spawn_proxy(
+            &local,
+            id,
+            Arc::new(LoopbackTransport {
+                system: remote.clone(),
+                messages: messages.clone(),
+            }),
+        );
+
+        let test = async {
+            tokio::time::sleep(Duration::from_millis(1)).await;
+            assert_eq!(Ok(42), local.ask(id, AddMessage(2)).await);
+            assert_eq!(
+                Err(ActorError::Custom("negative".into())),
+                local.ask(id, AddMessage(-1)).await
+            );
+            local.forget_senders();
+            remote.forget_senders();
+        };
+        tokio::time::timeout(
+            Duration::from_secs(5),
+            futures::future::join3(remote_device, proxy, test),
+        )
+        .await
+        .unwrap();
+    }
+}
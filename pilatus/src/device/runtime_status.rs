@@ -0,0 +1,149 @@
+use std::{
+    collections::HashMap,
+    sync::{Arc, Mutex},
+};
+
+use chrono::{DateTime, Utc};
+use futures::{stream::BoxStream, StreamExt};
+use serde::Serialize;
+use tokio::sync::broadcast::{self, error::RecvError};
+
+use super::DeviceId;
+
+/// Lifecycle of a device in the running recipe, as seen by the spawner
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+#[cfg_attr(feature = "ts", derive(ts_rs::TS))]
+#[serde(rename_all = "snake_case", tag = "state")]
+pub enum DeviceLifecycle {
+    Validating,
+    Starting,
+    Running,
+    Stopped,
+    Failed { error: String },
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+#[cfg_attr(feature = "ts", derive(ts_rs::TS))]
+pub struct DeviceRuntimeStatus {
+    pub device_id: DeviceId,
+    pub device_type: String,
+    #[serde(flatten)]
+    pub lifecycle: DeviceLifecycle,
+    pub since: DateTime<Utc>,
+}
+
+/// Runtime status of all devices of the running recipe. Unlike [`super::ActiveState`], which contains the configuration,
+/// this reflects what actually happens to the devices
+#[derive(Clone)]
+pub struct DeviceStatusRegistry(Arc<DeviceStatusRegistryInner>);
+
+struct DeviceStatusRegistryInner {
+    devices: Mutex<HashMap<DeviceId, DeviceRuntimeStatus>>,
+    live: broadcast::Sender<DeviceRuntimeStatus>,
+}
+
+impl Default for DeviceStatusRegistry {
+    fn default() -> Self {
+        Self(Arc::new(DeviceStatusRegistryInner {
+            devices: Default::default(),
+            live: broadcast::channel(64).0,
+        }))
+    }
+}
+
+impl std::fmt::Debug for DeviceStatusRegistry {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("DeviceStatusRegistry")
+            .finish_non_exhaustive()
+    }
+}
+
+impl DeviceStatusRegistry {
+    pub fn set(&self, device_id: DeviceId, device_type: &str, lifecycle: DeviceLifecycle) {
+        let status = DeviceRuntimeStatus {
+            device_id,
+            device_type: device_type.to_string(),
+            lifecycle,
+            since: Utc::now(),
+        };
+        self.0
+            .devices
+            .lock()
+            .expect("Never poisoned")
+            .insert(device_id, status.clone());
+        let _ignore_without_subscribers = self.0.live.send(status);
+    }
+
+    pub fn get(&self, device_id: DeviceId) -> Option<DeviceRuntimeStatus> {
+        self.0
+            .devices
+            .lock()
+            .expect("Never poisoned")
+            .get(&device_id)
+            .cloned()
+    }
+
+    pub fn all(&self) -> Vec<DeviceRuntimeStatus> {
+        let mut all: Vec<_> = self
+            .0
+            .devices
+            .lock()
+            .expect("Never poisoned")
+            .values()
+            .cloned()
+            .collect();
+        all.sort_by_key(|x| x.since);
+        all
+    }
+
+    /// Forgets devices of the previous recipe
+    pub fn clear(&self) {
+        self.0.devices.lock().expect("Never poisoned").clear();
+    }
+
+    /// Changes from now on. Slow subscribers skip changes, so they should fetch [`Self::all`] afterwards
+    pub fn subscribe(&self) -> BoxStream<'static, DeviceRuntimeStatus> {
+        futures::stream::unfold(self.0.live.subscribe(), |mut receiver| async move {
+            loop {
+                match receiver.recv().await {
+                    Ok(status) => return Some((status, receiver)),
+                    Err(RecvError::Lagged(_)) => continue,
+                    Err(RecvError::Closed) => return None,
+                }
+            }
+        })
+        .boxed()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn subscribers_see_lifecycle_changes() {
+        let registry = DeviceStatusRegistry::default();
+        let mut changes = registry.subscribe();
+        let id = DeviceId::new_v4();
+        registry.set(id, "camera", DeviceLifecycle::Starting);
+        registry.set(
+            id,
+            "camera",
+            DeviceLifecycle::Failed {
+                error: "Not connected".into(),
+            },
+        );
+        assert_eq!(
+            DeviceLifecycle::Starting,
+            changes.next().await.unwrap().lifecycle
+        );
+        assert_eq!(
+            Some(DeviceLifecycle::Failed {
+                error: "Not connected".into()
+            }),
+            registry.get(id).map(|x| x.lifecycle)
+        );
+        registry.clear();
+        assert!(registry.all().is_empty());
+    }
+}
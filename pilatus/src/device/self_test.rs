@@ -0,0 +1,54 @@
+use serde::{Deserialize, Serialize};
+
+use super::ActorMessage;
+
+/// Optional message which devices can handle to verify their hardware or configuration after startup.
+/// The runtime sends it to all devices handling it after a recipe was activated, if enabled in the config (`self_test`)
+#[derive(Debug, Clone, Default, ActorMessage)]
+#[actor_message(crate = crate, output = Vec<SelfTestCheck>, error = anyhow::Error, name = "self_test")]
+pub struct SelfTestMessage;
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct SelfTestCheck {
+    pub name: String,
+    /// The system is not reported as ready, until all mandatory checks passed
+    pub mandatory: bool,
+    pub passed: bool,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub message: Option<String>,
+}
+
+impl SelfTestCheck {
+    pub fn passed(name: impl Into<String>) -> Self {
+        Self {
+            name: name.into(),
+            mandatory: true,
+            passed: true,
+            message: None,
+        }
+    }
+
+    pub fn failed(name: impl Into<String>, message: impl Into<String>) -> Self {
+        Self {
+            name: name.into(),
+            mandatory: true,
+            passed: false,
+            message: Some(message.into()),
+        }
+    }
+
+    /// Failing optional checks are reported, but don't prevent readiness
+    pub fn optional(self) -> Self {
+        Self {
+            mandatory: false,
+            ..self
+        }
+    }
+
+    pub fn with_message(self, message: impl Into<String>) -> Self {
+        Self {
+            message: Some(message.into()),
+            ..self
+        }
+    }
+}
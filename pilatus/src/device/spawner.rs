@@ -12,7 +12,8 @@ use super::{
 };
 use crate::{
     DeviceConfig, NotAppliedError, ParameterUpdate, RecipeId, RecipeServiceTrait,
-    UnknownDeviceError, UntypedDeviceParamsWithVariables, UpdateParamsMessageError,
+    TransactionOptions, UnknownDeviceError, UntypedDeviceParamsWithVariables,
+    UpdateParamsMessageError,
 };
 
 #[derive(thiserror::Error, Debug)]
@@ -173,13 +174,14 @@ impl<'a, T: Send + 'a> InfallibleParamApplier<T> for RecipeServiceParamApplier<'
         if let Some(parameters) = changes.update {
             if let Err(e) = self
                 .service
-                .update_device_params(
+                .update_device_params_with(
                     self.recipe_id,
                     self.device_id,
                     ParameterUpdate {
                         parameters,
                         variables: Default::default(),
                     },
+                    TransactionOptions::default().bypassing_lock(),
                 )
                 .await
             {
@@ -323,6 +325,7 @@ where
             }
 
             actor_system
+                .with_origin(ctx.origin().clone())
                 .ask(
                     ctx.id,
                     crate::UpdateParamsMessage::<TParam>::new(typed_params.data),
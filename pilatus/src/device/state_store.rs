@@ -0,0 +1,143 @@
+use std::{
+    collections::BTreeMap,
+    sync::{Arc, Mutex},
+};
+
+use serde::{de::DeserializeOwned, Serialize};
+
+use super::DeviceId;
+
+/// Persists small runtime state of devices like counters or the date of the last maintenance.
+/// Unlike params, it isn't part of recipes and therefore neither versioned nor exported.
+pub trait DeviceStateBackend: Send + Sync {
+    fn get(&self, device_id: DeviceId, key: &str) -> anyhow::Result<Option<serde_json::Value>>;
+    fn set(&self, device_id: DeviceId, key: &str, value: serde_json::Value) -> anyhow::Result<()>;
+    fn remove(&self, device_id: DeviceId, key: &str) -> anyhow::Result<()>;
+    fn entries(&self, device_id: DeviceId) -> anyhow::Result<BTreeMap<String, serde_json::Value>>;
+    /// Returns once all previous changes are durable. Backends without background writes don't have to implement it
+    fn flush(&self) -> anyhow::Result<()> {
+        Ok(())
+    }
+}
+
+/// Shared by all devices. Devices access their own part via [`super::DeviceContext::state`]
+#[derive(Clone)]
+pub struct DeviceStateStore(Arc<dyn DeviceStateBackend>);
+
+impl DeviceStateStore {
+    pub fn new(backend: Arc<dyn DeviceStateBackend>) -> Self {
+        Self(backend)
+    }
+
+    /// State is lost when the last clone is dropped. Used if no persistent store is available, e.g. in tests
+    pub fn in_memory() -> Self {
+        Self(Arc::new(InMemoryDeviceState::default()))
+    }
+
+    pub fn scope(&self, device_id: DeviceId) -> DeviceState {
+        DeviceState {
+            device_id,
+            store: self.clone(),
+        }
+    }
+
+    pub fn entries(
+        &self,
+        device_id: DeviceId,
+    ) -> anyhow::Result<BTreeMap<String, serde_json::Value>> {
+        self.0.entries(device_id)
+    }
+}
+
+impl std::fmt::Debug for DeviceStateStore {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("DeviceStateStore").finish_non_exhaustive()
+    }
+}
+
+/// Key-value state of a single device. Values are stored as JSON
+#[derive(Clone, Debug)]
+pub struct DeviceState {
+    device_id: DeviceId,
+    store: DeviceStateStore,
+}
+
+impl DeviceState {
+    pub fn get<T: DeserializeOwned>(&self, key: &str) -> anyhow::Result<Option<T>> {
+        self.store
+            .0
+            .get(self.device_id, key)?
+            .map(serde_json::from_value)
+            .transpose()
+            .map_err(Into::into)
+    }
+
+    pub fn set<T: Serialize>(&self, key: &str, value: &T) -> anyhow::Result<()> {
+        self.store
+            .0
+            .set(self.device_id, key, serde_json::to_value(value)?)
+    }
+
+    pub fn remove(&self, key: &str) -> anyhow::Result<()> {
+        self.store.0.remove(self.device_id, key)
+    }
+
+    pub fn entries(&self) -> anyhow::Result<BTreeMap<String, serde_json::Value>> {
+        self.store.entries(self.device_id)
+    }
+
+    /// Changes may be written in the background. Call this before reporting them as persisted
+    pub fn flush(&self) -> anyhow::Result<()> {
+        self.store.0.flush()
+    }
+}
+
+#[derive(Default)]
+struct InMemoryDeviceState(Mutex<BTreeMap<DeviceId, BTreeMap<String, serde_json::Value>>>);
+
+impl DeviceStateBackend for InMemoryDeviceState {
+    fn get(&self, device_id: DeviceId, key: &str) -> anyhow::Result<Option<serde_json::Value>> {
+        let state = self.0.lock().expect("Never poisoned");
+        Ok(state.get(&device_id).and_then(|x| x.get(key)).cloned())
+    }
+
+    fn set(&self, device_id: DeviceId, key: &str, value: serde_json::Value) -> anyhow::Result<()> {
+        let mut state = self.0.lock().expect("Never poisoned");
+        state
+            .entry(device_id)
+            .or_default()
+            .insert(key.to_string(), value);
+        Ok(())
+    }
+
+    fn remove(&self, device_id: DeviceId, key: &str) -> anyhow::Result<()> {
+        let mut state = self.0.lock().expect("Never poisoned");
+        if let Some(x) = state.get_mut(&device_id) {
+            x.remove(key);
+        }
+        Ok(())
+    }
+
+    fn entries(&self, device_id: DeviceId) -> anyhow::Result<BTreeMap<String, serde_json::Value>> {
+        let state = self.0.lock().expect("Never poisoned");
+        Ok(state.get(&device_id).cloned().unwrap_or_default())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn devices_dont_see_state_of_others() -> anyhow::Result<()> {
+        let store = DeviceStateStore::in_memory();
+        let a = store.scope(DeviceId::new_v4());
+        let b = store.scope(DeviceId::new_v4());
+        a.set("counter", &42)?;
+        assert_eq!(Some(42), a.get::<i32>("counter")?);
+        assert_eq!(None, b.get::<i32>("counter")?);
+        a.remove("counter")?;
+        assert!(a.entries()?.is_empty());
+        Ok(())
+    }
+}
@@ -0,0 +1,167 @@
+use std::{
+    collections::BTreeMap,
+    sync::{Arc, Mutex, Weak},
+    time::{Duration, Instant},
+};
+
+use tracing::warn;
+
+use super::DeviceState;
+
+/// Cumulative counters like acquired frames or errors, which survive restarts and recipe switches.
+/// Values are kept in memory and written to the [`DeviceState`] periodically and when the last clone is dropped:
+/// ```ignore
+/// let stats = DeviceStatistics::load(ctx.state().clone(), DeviceStatistics::DEFAULT_FLUSH_INTERVAL);
+/// stats.increment("frames");
+/// ```
+#[derive(Clone, Debug)]
+pub struct DeviceStatistics(Arc<StatisticsInner>);
+
+#[derive(Debug)]
+struct StatisticsInner {
+    state: DeviceState,
+    values: Mutex<StatisticsValues>,
+}
+
+#[derive(Debug)]
+struct StatisticsValues {
+    counters: BTreeMap<String, u64>,
+    dirty: bool,
+    /// Time since the last flush is added to `RUNTIME_SECONDS`
+    last_flush: Instant,
+}
+
+impl DeviceStatistics {
+    pub const STATE_KEY: &'static str = "statistics";
+    /// Seconds the device was running with statistics enabled
+    pub const RUNTIME_SECONDS: &'static str = "runtime_seconds";
+    pub const DEFAULT_FLUSH_INTERVAL: Duration = Duration::from_secs(30);
+
+    /// Must be called within a tokio runtime, which flushes the values every `flush_interval`
+    pub fn load(state: DeviceState, flush_interval: Duration) -> Self {
+        let counters = Self::read(&state).unwrap_or_else(|e| {
+            warn!("Reset unreadable device statistics: {e}");
+            Default::default()
+        });
+        let this = Self(Arc::new(StatisticsInner {
+            state,
+            values: Mutex::new(StatisticsValues {
+                counters,
+                dirty: false,
+                last_flush: Instant::now(),
+            }),
+        }));
+        tokio::spawn(flush_periodically(Arc::downgrade(&this.0), flush_interval));
+        this
+    }
+
+    /// Persisted values, e.g. for devices which are not running
+    pub fn read(state: &DeviceState) -> anyhow::Result<BTreeMap<String, u64>> {
+        Ok(state.get(Self::STATE_KEY)?.unwrap_or_default())
+    }
+
+    pub fn increment(&self, key: &str) {
+        self.add(key, 1);
+    }
+
+    pub fn add(&self, key: &str, value: u64) {
+        let mut lock = self.0.values.lock().expect("Never poisoned");
+        let counter = lock.counters.entry(key.to_string()).or_default();
+        *counter = counter.saturating_add(value);
+        lock.dirty = true;
+    }
+
+    pub fn get(&self, key: &str) -> u64 {
+        let lock = self.0.values.lock().expect("Never poisoned");
+        lock.counters.get(key).copied().unwrap_or_default()
+    }
+
+    /// Includes changes which are not flushed yet
+    pub fn values(&self) -> BTreeMap<String, u64> {
+        self.0
+            .values
+            .lock()
+            .expect("Never poisoned")
+            .counters
+            .clone()
+    }
+
+    pub fn flush(&self) -> anyhow::Result<()> {
+        self.0.flush()
+    }
+}
+
+impl StatisticsInner {
+    fn flush(&self) -> anyhow::Result<()> {
+        let counters = {
+            let mut lock = self.values.lock().expect("Never poisoned");
+            let elapsed = lock.last_flush.elapsed().as_secs();
+            if elapsed > 0 {
+                // Remaining fractions are counted with the next flush
+                lock.last_flush += Duration::from_secs(elapsed);
+                let runtime = lock
+                    .counters
+                    .entry(DeviceStatistics::RUNTIME_SECONDS.to_string())
+                    .or_default();
+                *runtime = runtime.saturating_add(elapsed);
+                lock.dirty = true;
+            }
+            if !std::mem::take(&mut lock.dirty) {
+                return Ok(());
+            }
+            lock.counters.clone()
+        };
+        self.state.set(DeviceStatistics::STATE_KEY, &counters)
+    }
+}
+
+impl Drop for StatisticsInner {
+    fn drop(&mut self) {
+        if let Err(e) = self.flush() {
+            warn!("Cannot persist device statistics: {e}");
+        }
+    }
+}
+
+async fn flush_periodically(inner: Weak<StatisticsInner>, interval: Duration) {
+    loop {
+        tokio::time::sleep(interval).await;
+        let Some(inner) = inner.upgrade() else {
+            return;
+        };
+        if let Err(e) = inner.flush() {
+            warn!("Cannot persist device statistics: {e}");
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::device::{DeviceId, DeviceStateStore};
+
+    #[tokio::test]
+    async fn keep_counters_after_restart() {
+        let store = DeviceStateStore::in_memory();
+        let id = DeviceId::new_v4();
+        let stats = DeviceStatistics::load(store.scope(id), Duration::from_secs(3600));
+        stats.increment("frames");
+        stats.add("frames", 2);
+        assert_eq!(
+            None,
+            store
+                .scope(id)
+                .get::<BTreeMap<String, u64>>("statistics")
+                .unwrap()
+        );
+        drop(stats);
+
+        let restarted = DeviceStatistics::load(store.scope(id), Duration::from_secs(3600));
+        assert_eq!(3, restarted.get("frames"));
+        restarted.increment("errors");
+        restarted.flush().unwrap();
+        let persisted = DeviceStatistics::read(&store.scope(id)).unwrap();
+        assert_eq!(Some(&1), persisted.get("errors"));
+        assert_eq!(Some(&3), persisted.get("frames"));
+    }
+}
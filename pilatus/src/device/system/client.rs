@@ -0,0 +1,76 @@
+/// Generates a typed facade for a device, so callers don't have to know which messages a device handles.
+/// `handles_all_messages()` allows detecting a mismatch before the first call fails with `UnknownMessageType`.
+///
+/// Methods without argument construct the message with `Default::default()`
+///
+/// ```ignore
+/// pilatus::actor_client! {
+///     /// Facade for devices producing images
+///     pub struct CameraClient {
+///         pub fn get_image() -> GetImageMessage;
+///         pub fn subscribe() -> SubscribeImageMessage;
+///         pub fn get_file(msg) -> GetFileMessage;
+///     }
+/// }
+///
+/// let image = CameraClient::new(actor_system, device_id).get_image().await?;
+/// ```
+#[macro_export]
+macro_rules! actor_client {
+    (
+        $(#[$meta:meta])*
+        $vis:vis struct $name:ident {
+            $($body:tt)*
+        }
+    ) => {
+        $(#[$meta])*
+        #[derive(Debug, Clone)]
+        $vis struct $name {
+            system: $crate::device::ActorSystem,
+            id: $crate::device::DeviceId,
+        }
+
+        impl $name {
+            pub fn new(system: $crate::device::ActorSystem, id: $crate::device::DeviceId) -> Self {
+                Self { system, id }
+            }
+
+            pub fn device_id(&self) -> $crate::device::DeviceId {
+                self.id
+            }
+
+            pub fn handles_all_messages(&self) -> bool {
+                self.system
+                    .list_devices_for_message_types($crate::actor_client!(@types [] $($body)*))
+                    .contains(&self.id)
+            }
+
+            $crate::actor_client!(@methods $($body)*);
+        }
+    };
+
+    (@types [$($acc:ty),*]) => {
+        [$(std::any::TypeId::of::<$acc>()),*]
+    };
+    (@types [$($acc:ty),*] $(#[$m:meta])* $fvis:vis fn $f:ident($($arg:ident)?) -> $msg:ty; $($rest:tt)*) => {
+        $crate::actor_client!(@types [$($acc,)* $msg] $($rest)*)
+    };
+
+    (@methods) => {};
+    (@methods $(#[$m:meta])* $fvis:vis fn $f:ident() -> $msg:ty; $($rest:tt)*) => {
+        $(#[$m])*
+        $fvis async fn $f(&self) -> $crate::device::ActorResult<$msg> {
+            self.system
+                .ask(self.id, <$msg as ::std::default::Default>::default())
+                .await
+        }
+        $crate::actor_client!(@methods $($rest)*);
+    };
+    (@methods $(#[$m:meta])* $fvis:vis fn $f:ident($arg:ident) -> $msg:ty; $($rest:tt)*) => {
+        $(#[$m])*
+        $fvis async fn $f(&self, $arg: $msg) -> $crate::device::ActorResult<$msg> {
+            self.system.ask(self.id, $arg).await
+        }
+        $crate::actor_client!(@methods $($rest)*);
+    };
+}
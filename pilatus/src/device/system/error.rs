@@ -1,4 +1,8 @@
-use std::{borrow::Cow, collections::HashSet, fmt::Debug};
+use std::{
+    borrow::Cow,
+    collections::HashSet,
+    fmt::{Debug, Display},
+};
 
 use futures::{channel::oneshot, stream::Aborted};
 
@@ -26,6 +30,12 @@ pub enum ActorError<TCustom: Debug> {
 
     #[error("Running into timeout when handling request")]
     Timeout,
+
+    #[error("Remote node failed to handle the request: {0}")]
+    Remote(String),
+
+    #[error("Message was rejected by an interceptor: {0}")]
+    Forbidden(String),
 }
 
 impl<T: Debug> From<Aborted> for ActorError<T> {
@@ -81,6 +91,8 @@ impl<TCustom: Debug> ActorError<TCustom> {
             ActorError::Busy(x) => ActorError::Busy(x),
             ActorError::Aborted => ActorError::Aborted,
             ActorError::Timeout => ActorError::Timeout,
+            ActorError::Remote(x) => ActorError::Remote(x),
+            ActorError::Forbidden(x) => ActorError::Forbidden(x),
         }
     }
     pub fn custom(custom: impl Into<TCustom>) -> Self {
@@ -88,6 +100,17 @@ impl<TCustom: Debug> ActorError<TCustom> {
     }
 }
 
+impl ActorError<anyhow::Error> {
+    /// Wraps custom errors, so the chain of causes is preserved when the error is reported.
+    /// Other variants already describe what went wrong and are returned unchanged
+    pub fn context<C: Display + Send + Sync + 'static>(self, context: C) -> Self {
+        match self {
+            ActorError::Custom(e) => ActorError::Custom(e.context(context)),
+            e => e,
+        }
+    }
+}
+
 impl<T: Debug> From<ActorWeakTellError> for ActorError<T> {
     fn from(value: ActorWeakTellError) -> Self {
         match value {
@@ -123,25 +146,102 @@ impl<T, TErr: Debug> ActorErrorResultExtensions<T, TErr> for Result<T, ActorErro
     }
 }
 
+/// Like `anyhow::Context`, but for results of devices with `anyhow::Error` as custom error
+/// The methods are named differently, so both traits can be imported at the same time
+pub trait ActorErrorContextExtensions<T> {
+    fn actor_context<C: Display + Send + Sync + 'static>(
+        self,
+        context: C,
+    ) -> Result<T, ActorError<anyhow::Error>>;
+
+    fn with_actor_context<C: Display + Send + Sync + 'static>(
+        self,
+        f: impl FnOnce() -> C,
+    ) -> Result<T, ActorError<anyhow::Error>>;
+}
+
+impl<T> ActorErrorContextExtensions<T> for Result<T, ActorError<anyhow::Error>> {
+    fn actor_context<C: Display + Send + Sync + 'static>(
+        self,
+        context: C,
+    ) -> Result<T, ActorError<anyhow::Error>> {
+        self.map_err(|e| e.context(context))
+    }
+
+    fn with_actor_context<C: Display + Send + Sync + 'static>(
+        self,
+        f: impl FnOnce() -> C,
+    ) -> Result<T, ActorError<anyhow::Error>> {
+        self.map_err(|e| e.context(f()))
+    }
+}
+
 #[derive(Debug, thiserror::Error, PartialEq, Eq)]
 pub enum ActorErrorUnknownDevice {
-    #[error("Unknown device with id '{device_id}': {details}")]
+    #[error("Unknown device with id '{device_id}'{}: {details}", describe_request(.name.as_ref(), *.msg_type))]
     UnknownDeviceId {
         device_id: DeviceId,
         details: Cow<'static, str>,
+        /// Name of the device in the recipe, if the ActorSystem knew the device at some point
+        name: Option<Name>,
+        /// Type of the message which couldn't be delivered
+        msg_type: Option<&'static str>,
     },
     #[error("Couldn't find unique handler for '{msg_type}': {possibilities:?}")]
     AmbiguousHandler {
         msg_type: &'static str,
         possibilities: HashSet<DeviceId>,
     },
-    #[error("Unknown device with name '{name}': {details}")]
+    #[error("Unknown device with name '{name}'{}: {details}", describe_request(None, *.msg_type))]
     UnknownDeviceName {
         name: Name,
         details: Cow<'static, str>,
+        msg_type: Option<&'static str>,
     },
 }
 
+impl ActorErrorUnknownDevice {
+    pub fn unknown_id(device_id: DeviceId, details: impl Into<Cow<'static, str>>) -> Self {
+        Self::UnknownDeviceId {
+            device_id,
+            details: details.into(),
+            name: None,
+            msg_type: None,
+        }
+    }
+
+    /// Records which message couldn't be delivered, unless it's known already
+    pub fn with_message_type<TMsg: 'static>(mut self) -> Self {
+        match &mut self {
+            Self::UnknownDeviceId { msg_type, .. } | Self::UnknownDeviceName { msg_type, .. } => {
+                msg_type.get_or_insert(std::any::type_name::<TMsg>());
+            }
+            Self::AmbiguousHandler { .. } => {}
+        }
+        self
+    }
+
+    pub fn with_name(mut self, device_name: Option<Name>) -> Self {
+        if let Self::UnknownDeviceId { name, .. } = &mut self {
+            if name.is_none() {
+                *name = device_name;
+            }
+        }
+        self
+    }
+}
+
+fn describe_request(name: Option<&Name>, msg_type: Option<&'static str>) -> String {
+    let mut result = String::new();
+    if let Some(name) = name {
+        result.push_str(&format!(" named '{name}'"));
+    }
+    if let Some(msg_type) = msg_type {
+        result.push_str(&format!(" for message '{msg_type}'"));
+    }
+    result
+}
+
 #[derive(Debug, thiserror::Error, PartialEq, Eq)]
 pub enum ActorErrorBusy {
     #[error("Queue for device {0} has no more space")]
@@ -150,3 +250,39 @@ pub enum ActorErrorBusy {
     #[error("spawn_blocking failed due to system overload")]
     SpawnBlocking,
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn unknown_device_describes_request() {
+        let error = ActorErrorUnknownDevice::unknown_id(DeviceId::nil(), "Not running")
+            .with_name(Some(Name::new("Camera").unwrap()))
+            .with_message_type::<u32>();
+        assert_eq!(
+            format!(
+                "Unknown device with id '{}' named 'Camera' for message 'u32': Not running",
+                DeviceId::nil()
+            ),
+            error.to_string()
+        );
+    }
+
+    #[test]
+    fn context_preserves_cause() {
+        // Must not be ambiguous with anyhow::Context
+        #[allow(unused_imports)]
+        use anyhow::Context;
+
+        let result: Result<(), _> = Err(ActorError::Custom(anyhow::anyhow!("Socket closed")));
+        let ActorError::Custom(e) = result.actor_context("Couldn't acquire image").unwrap_err()
+        else {
+            panic!("Custom errors stay custom");
+        };
+        assert_eq!(
+            vec!["Couldn't acquire image", "Socket closed"],
+            e.chain().map(|x| x.to_string()).collect::<Vec<_>>()
+        );
+    }
+}
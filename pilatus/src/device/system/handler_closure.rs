@@ -7,7 +7,10 @@ use futures::{
     Future, FutureExt,
 };
 
-use super::{ActorMessage, ActorResult, HandlerClosureResponse, HandlerResult, Task};
+use super::{
+    ActorMessage, ActorProgress, ActorResult, HandlerClosureResponse, HandlerResult,
+    ProgressReporter, Task,
+};
 
 pub trait AsyncHandlerClosure<'a, TState, TMsg: ActorMessage> {
     type Fut: Future<Output = Self::Result> + 'a + Send;
@@ -26,6 +29,7 @@ pub trait AsyncHandlerClosure<'a, TState, TMsg: ActorMessage> {
 
 pub struct HandlerClosureContext<TMsg: ActorMessage> {
     pub(super) response_channel: oneshot::Sender<ActorResult<TMsg>>,
+    pub(super) progress: Option<futures::channel::mpsc::UnboundedSender<ActorProgress>>,
 }
 
 impl<'a, TState, TMsg, THandlerResult, TFut, TFn> AsyncHandlerClosure<'a, TState, TMsg> for TFn
@@ -55,6 +59,8 @@ where
     }
 }
 
+/// Passes an AbortRegistration to the handler, which is triggered as soon as the asker drops the response-future.
+/// This happens on recipe shutdown, but also if e.g. the http-client which issued the request disconnects.
 #[derive(Clone)]
 pub struct WithAbort<TFn>(TFn);
 impl<TFn> WithAbort<TFn> {
@@ -103,3 +109,52 @@ where
         .boxed()
     }
 }
+
+/// Like `WithAbort`, but additionally passes a `ProgressReporter` to the handler.
+/// The AbortRegistration is triggered as soon as the asker drops the response (e.g. the stream of `ask_with_progress`)
+#[derive(Clone)]
+pub struct WithProgress<TFn>(TFn);
+impl<TFn> WithProgress<TFn> {
+    pub fn new(t: TFn) -> Self {
+        Self(t)
+    }
+}
+
+impl<'a, TState, TMsg, THandlerResult, TFut, TFn> AsyncHandlerClosure<'a, TState, TMsg>
+    for WithProgress<TFn>
+where
+    TState: 'static,
+    TMsg: ActorMessage,
+    THandlerResult: HandlerResult<TMsg>,
+    TFut: Future<Output = THandlerResult> + 'a + Send,
+    TFn: Fn(&'a mut TState, TMsg, ProgressReporter, AbortRegistration) -> TFut,
+{
+    type Fut = TFut;
+    type Result = THandlerResult;
+    type FinalFut = BoxFuture<'a, HandlerClosureResponse>;
+
+    fn call(
+        &self,
+        state: &'a mut TState,
+        msg: TMsg,
+        mut ctx: HandlerClosureContext<TMsg>,
+    ) -> BoxFuture<'a, HandlerClosureResponse> {
+        let (abort_handle, abort_registration) = AbortHandle::new_pair();
+        let reporter = ProgressReporter::new(ctx.progress.take());
+        let future = self.0(state, msg, reporter, abort_registration).fuse();
+
+        async move {
+            futures::future::select(std::pin::pin!(future), ctx.response_channel.cancellation())
+                .then(move |r| match r {
+                    Either::Left((x, _)) => std::future::ready(x).left_future(),
+                    Either::Right((_, other)) => {
+                        abort_handle.abort();
+                        other.right_future()
+                    }
+                })
+                .await
+                .handle_as_result(ctx)
+        }
+        .boxed()
+    }
+}
@@ -22,6 +22,7 @@ pub trait ActorSystemIdentifier: Sized {
     ) -> Result<ActorMessageSender<TMsg>, ActorErrorUnknownDevice> {
         self.get_untyped_sender(actor_system)
             .map(ActorMessageSender::new)
+            .map_err(ActorErrorUnknownDevice::with_message_type::<TMsg>)
     }
 }
 
@@ -35,11 +36,12 @@ impl ActorSystemIdentifier for DeviceId {
             .devices
             .get(&self)
             .map(|x| mpsc::Sender::clone(x))
-            .ok_or(ActorErrorUnknownDevice::UnknownDeviceId {
-                device_id: self,
-                details: "No message queue for this device".into(),
+            .ok_or_else(|| {
+                ActorErrorUnknownDevice::unknown_id(self, "No message queue for this device")
+                    .with_name(state.0.names.get(&self).cloned())
             })?;
-        Ok(UntypedActorMessageSender::new(self, mpsc_sender))
+        Ok(UntypedActorMessageSender::new(self, mpsc_sender)
+            .with_interceptors(state.0.interceptors.clone()))
     }
 }
 
@@ -58,7 +60,9 @@ impl ActorSystemIdentifier for DynamicIdentifier {
         actor_system: SealedActorSystemState,
     ) -> Result<ActorMessageSender<TMsg>, ActorErrorUnknownDevice> {
         match self {
-            DynamicIdentifier::DeviceId(device_id) => device_id.get_typed_sender(actor_system),
+            DynamicIdentifier::DeviceId(device_id) => {
+                device_id.get_typed_sender::<TMsg>(actor_system)
+            }
             DynamicIdentifier::None => {
                 let ids = actor_system.0.messages.get(&TypeId::of::<TMsg>());
                 let mut ids_iter = ids.iter().flat_map(|x| x.iter());
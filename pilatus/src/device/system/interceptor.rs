@@ -0,0 +1,66 @@
+use std::{any::TypeId, sync::Arc};
+
+use crate::{device::DeviceId, User};
+
+use super::ActorMessage;
+
+/// Who sent a message. Senders are `Internal`, unless they were acquired from an [`super::ActorSystem`]
+/// which was tagged by the webserver with [`super::ActorSystem::with_web_origin`] or by the remote bridge.
+/// Only this crate can construct the other variants, so plugins can't pretend to act for a user
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub enum MessageOrigin {
+    #[default]
+    Internal,
+    #[non_exhaustive]
+    Web { user: Option<User> },
+    #[non_exhaustive]
+    Remote,
+}
+
+/// Describes a message before it's sent to a device
+#[derive(Debug)]
+pub struct MessageMetadata<'a> {
+    pub device_id: DeviceId,
+    pub msg_type: TypeId,
+    pub msg_type_name: &'static str,
+    pub origin: &'a MessageOrigin,
+}
+
+impl MessageMetadata<'_> {
+    pub fn is<TMsg: ActorMessage>(&self) -> bool {
+        self.msg_type == TypeId::of::<TMsg>()
+    }
+}
+
+/// Policy check, which runs for every `tell` and `ask` before the message is queued.
+/// Rejected asks fail with `ActorError::Forbidden`, rejected tells are dropped.
+/// Interceptors run on the senders thread and must therefore be fast
+pub trait ActorInterceptor: Send + Sync + 'static {
+    fn intercept(&self, meta: &MessageMetadata) -> Result<(), String>;
+}
+
+impl<T: Fn(&MessageMetadata) -> Result<(), String> + Send + Sync + 'static> ActorInterceptor for T {
+    fn intercept(&self, meta: &MessageMetadata) -> Result<(), String> {
+        (self)(meta)
+    }
+}
+
+/// Cheap to clone, so each sender can keep a snapshot
+#[derive(Clone, Default)]
+pub(super) struct Interceptors(Arc<[Arc<dyn ActorInterceptor>]>);
+
+impl Interceptors {
+    pub(super) fn with(&self, interceptor: Arc<dyn ActorInterceptor>) -> Self {
+        Self(self.0.iter().cloned().chain([interceptor]).collect())
+    }
+
+    pub(super) fn check(&self, meta: &MessageMetadata) -> Result<(), String> {
+        self.0.iter().try_for_each(|x| x.intercept(meta))
+    }
+}
+
+impl std::fmt::Debug for Interceptors {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_tuple("Interceptors").field(&self.0.len()).finish()
+    }
+}
@@ -19,24 +19,39 @@ use tracing::{trace, warn};
 use self::identifier::ActorSystemIdentifier;
 
 use super::DeviceId;
+use crate::UserServiceTrait;
 
+mod client;
 mod error;
 mod handler_closure;
 mod handler_result;
 mod identifier;
+mod interceptor;
+mod progress;
+mod recording;
 mod sender;
 
 pub use error::*;
 pub use handler_closure::*;
 pub use handler_result::*;
 pub use identifier::DynamicIdentifier;
+pub use interceptor::{ActorInterceptor, MessageMetadata, MessageOrigin};
+pub use pilatus_macros::ActorMessage;
+pub use progress::{ActorProgress, ActorProgressEvent, ActorProgressStream, ProgressReporter};
+pub use recording::{
+    read_recording, MessageRecorder, MessageReplay, RecordedMessage, ReplayOutcome,
+};
 pub use sender::*;
 
 #[cfg(feature = "minfac")]
 pub(super) fn register_services(c: &mut minfac::ServiceCollection) {
     c.register_shared::<RwLock<ActorSystemState>>(Default::default);
     c.with::<minfac::Registered<Arc<RwLock<ActorSystemState>>>>()
-        .register(|state| ActorSystem { state });
+        .register(|state| ActorSystem {
+            state,
+            origin: Default::default(),
+        });
+    c.register_instance(crate::ServiceInfo::transient::<ActorSystem>());
 }
 
 pub trait ActorMessage: Any + Send {
@@ -44,20 +59,58 @@ pub trait ActorMessage: Any + Send {
     type Error: Debug + 'static + Send;
 }
 
+/// Derive it with `#[derive(ActorMessage)]`.
+/// The name must remain stable, as it identifies the message outside of the process (e.g. for RPC or introspection)
+pub trait WireActorMessage: ActorMessage {
+    const WIRE_NAME: &'static str;
+}
+
 pub struct BoxMessage(Box<dyn Any + Send>);
 
 #[derive(Debug, Clone)]
 pub struct ActorSystem {
     state: SharedActorSystemState,
+    origin: MessageOrigin,
 }
 
 impl ActorSystem {
+    pub const DEFAULT_MAILBOX_CAPACITY: usize = 10;
+
     pub fn new() -> Self {
         Self {
             state: Default::default(),
+            origin: Default::default(),
         }
     }
 
+    /// Senders acquired from the returned system pass the origin to interceptors
+    pub(crate) fn with_origin(&self, origin: MessageOrigin) -> Self {
+        Self {
+            state: self.state.clone(),
+            origin,
+        }
+    }
+
+    /// Senders acquired from the returned system are marked as `MessageOrigin::Web`.
+    /// The user is only attached if `token` authenticates, so anonymous requests can't claim one
+    pub fn with_web_origin(&self, users: &dyn UserServiceTrait, token: Option<&str>) -> Self {
+        self.with_origin(MessageOrigin::Web {
+            user: token.and_then(|t| users.authenticate(t)),
+        })
+    }
+
+    /// Origin of all senders acquired from this system
+    pub fn origin(&self) -> &MessageOrigin {
+        &self.origin
+    }
+
+    /// Interceptors apply to all senders acquired afterwards, including the ones of running devices
+    /// which acquire their senders on each request
+    pub fn add_interceptor(&self, interceptor: impl ActorInterceptor) {
+        let mut lock = self.state.write().expect("Shouldnt be poisoned");
+        lock.interceptors = lock.interceptors.with(Arc::new(interceptor));
+    }
+
     // After forgetting the senders, the system should finish pending tasks and shutdown eventually.
     // It is therefore essential that Actors dont have persistent cyclic senders.
     // If so, consider using a Weak-Sender or request the sender for each new request to avoid unstoppable recipes.
@@ -69,6 +122,15 @@ impl ActorSystem {
             .clear();
     }
 
+    /// Stops a single device the same way `forget_senders` stops all of them
+    pub fn forget_sender(&self, device_id: DeviceId) {
+        self.state
+            .write()
+            .expect("Shouldnt be poisoned")
+            .devices
+            .remove(&device_id);
+    }
+
     #[cfg(all(feature = "unstable", feature = "tokio"))]
     pub async fn run_and_shutdown<F: std::future::Future<Output = ()> + 'static>(
         &self,
@@ -81,17 +143,97 @@ impl ActorSystem {
     }
 
     pub fn register<TState>(&self, device_id: DeviceId) -> ActorDevice<TState> {
-        let (sender, receiver) = mpsc::channel(10);
-        {
+        self.register_with_mailbox_capacity(device_id, Self::DEFAULT_MAILBOX_CAPACITY)
+    }
+
+    /// High-throughput producers might need deeper queues, while control-only devices are fine with a few messages.
+    /// An override set by `override_mailbox_capacity` (e.g. from the config) wins over the capacity passed here
+    pub fn register_with_mailbox_capacity<TState>(
+        &self,
+        device_id: DeviceId,
+        capacity: usize,
+    ) -> ActorDevice<TState> {
+        let (receiver, recorder) = {
             let mut lock = self.state.write().expect("Shouldnt be poisoned");
+            let capacity = lock
+                .mailbox_capacities
+                .get(&device_id)
+                .copied()
+                .unwrap_or(capacity);
+            let (sender, receiver) = mpsc::channel(capacity);
             lock.devices.insert(device_id, Arc::new(sender));
-        }
+            (receiver, lock.recorders.get(&device_id).cloned())
+        };
         ActorDevice::new(
             receiver,
             releaser::DeviceReleaser::new(device_id, self.state.clone()),
+            recorder,
         )
     }
 
+    /// Resolves once the device added all its handlers and started to process messages
+    pub fn wait_until_executing(
+        &self,
+        device_id: DeviceId,
+    ) -> impl std::future::Future<Output = ()> + Send + 'static {
+        let receiver = {
+            let mut lock = self.state.write().expect("Shouldnt be poisoned");
+            (!lock.executing.contains(&device_id)).then(|| {
+                let (tx, rx) = oneshot::channel();
+                let waiters = lock.executing_waiters.entry(device_id).or_default();
+                waiters.retain(|x| !x.is_canceled());
+                waiters.push(tx);
+                rx
+            })
+        };
+        async move {
+            if let Some(receiver) = receiver {
+                let _ignore_cleared_waiters = receiver.await;
+            }
+        }
+    }
+
+    /// Records all messages received by the device, starting with its next registration.
+    /// `None` stops recording for devices registered afterwards
+    pub fn record_messages(&self, device_id: DeviceId, recorder: Option<MessageRecorder>) {
+        let mut lock = self.state.write().expect("Shouldnt be poisoned");
+        match recorder {
+            Some(x) => lock.recorders.insert(device_id, x),
+            None => lock.recorders.remove(&device_id),
+        };
+    }
+
+    /// Recorder which was set with [`ActorSystem::record_messages`], e.g. to download its recording
+    pub fn message_recorder(&self, device_id: DeviceId) -> Option<MessageRecorder> {
+        self.state
+            .read()
+            .expect("Shouldnt be poisoned")
+            .recorders
+            .get(&device_id)
+            .cloned()
+    }
+
+    /// Used by the runtime, so errors about unavailable devices can name the device
+    /// Names of stopped devices are forgotten, once too many of them accumulated
+    pub fn set_device_name(&self, device_id: DeviceId, name: crate::Name) {
+        let mut lock = self.state.write().expect("Shouldnt be poisoned");
+        if lock.names.len() >= lock.devices.len() + MAX_STOPPED_DEVICE_NAMES {
+            let ActorSystemState { devices, names, .. } = &mut *lock;
+            names.retain(|id, _| devices.contains_key(id));
+        }
+        lock.names.insert(device_id, name);
+    }
+
+    /// Used by the runtime to apply per device_type capacities from the config before a device is spawned.
+    /// `None` removes the override
+    pub fn override_mailbox_capacity(&self, device_id: DeviceId, capacity: Option<usize>) {
+        let mut lock = self.state.write().expect("Shouldnt be poisoned");
+        match capacity {
+            Some(x) => lock.mailbox_capacities.insert(device_id, x),
+            None => lock.mailbox_capacities.remove(&device_id),
+        };
+    }
+
     pub fn list_devices_for_message_type<TMsg: Any>(&self) -> HashSet<DeviceId> {
         let lock = self.state.read().expect("Not poisoned");
         match lock.messages.get(&TypeId::of::<TMsg>()) {
@@ -145,14 +287,19 @@ impl ActorSystem {
         let mpsc_sender = {
             let lock = self.state.read().expect("Should never be poisoned");
 
-            Arc::downgrade(lock.devices.get(&device_id).ok_or(
-                ActorErrorUnknownDevice::UnknownDeviceId {
-                    device_id,
-                    details: "Unknown Id".into(),
-                },
-            )?)
+            Arc::downgrade(lock.devices.get(&device_id).ok_or_else(|| {
+                ActorErrorUnknownDevice::unknown_id(device_id, "Unknown Id")
+                    .with_name(lock.names.get(&device_id).cloned())
+            })?)
         };
-        Ok(WeakUntypedActorMessageSender::new(device_id, mpsc_sender))
+        let interceptors = self
+            .state
+            .read()
+            .expect("Should never be poisoned")
+            .interceptors
+            .clone();
+        Ok(WeakUntypedActorMessageSender::new(device_id, mpsc_sender)
+            .with_interceptors(interceptors, self.origin.clone()))
     }
 
     pub fn get_untyped_sender(
@@ -160,7 +307,9 @@ impl ActorSystem {
         identifier: impl ActorSystemIdentifier,
     ) -> Result<UntypedActorMessageSender, ActorErrorUnknownDevice> {
         let lock = self.state.read().expect("Should never be poisoned");
-        identifier.get_untyped_sender(identifier::SealedActorSystemState(&lock))
+        identifier
+            .get_untyped_sender(identifier::SealedActorSystemState(&lock))
+            .map(|x| x.with_origin(self.origin.clone()))
     }
 
     pub fn get_sender<T: ActorMessage>(
@@ -168,7 +317,9 @@ impl ActorSystem {
         identifier: impl identifier::ActorSystemIdentifier,
     ) -> Result<ActorMessageSender<T>, ActorErrorUnknownDevice> {
         let lock = self.state.read().expect("Should never be poisoned");
-        identifier.get_typed_sender(identifier::SealedActorSystemState(&lock))
+        identifier
+            .get_typed_sender(identifier::SealedActorSystemState(&lock))
+            .map(|x| x.with_origin(self.origin.clone()))
     }
 
     pub fn get_senders<TMsg: ActorMessage>(
@@ -208,6 +359,16 @@ impl ActorSystem {
     ) -> ActorResult<TMsg> {
         self.get_sender(device_id)?.ask(msg).await
     }
+
+    /// Like `ask`, but yields the progress reported by handlers registered with `WithProgress`.
+    /// The last item is always `ActorProgressEvent::Finished`
+    pub fn ask_with_progress<TMsg: ActorMessage>(
+        &self,
+        device_id: impl ActorSystemIdentifier,
+        msg: TMsg,
+    ) -> Result<ActorProgressStream<TMsg>, ActorError<TMsg::Error>> {
+        Ok(self.get_sender(device_id)?.ask_with_progress(msg)?)
+    }
 }
 
 impl Default for ActorSystem {
@@ -216,6 +377,9 @@ impl Default for ActorSystem {
     }
 }
 
+/// Prevents the names of devices, which were spawned and stopped repeatedly, from growing without bound
+const MAX_STOPPED_DEVICE_NAMES: usize = 256;
+
 type SharedActorSystemState = Arc<RwLock<ActorSystemState>>;
 type InternalSender = mpsc::Sender<(TypeId, BoxMessage)>;
 
@@ -225,18 +389,32 @@ struct ActorSystemState {
     devices: HashMap<DeviceId, Arc<InternalSender>>,
     /// Map from a MessageType to Uuid of Actors which are able to handle the message
     messages: HashMap<TypeId, HashSet<DeviceId>>,
+    mailbox_capacities: HashMap<DeviceId, usize>,
+    /// Names are kept after devices stopped, so errors can tell which device was meant
+    names: HashMap<DeviceId, crate::Name>,
+    interceptors: interceptor::Interceptors,
+    recorders: HashMap<DeviceId, MessageRecorder>,
+    /// Devices which added all their handlers and process messages
+    executing: HashSet<DeviceId>,
+    executing_waiters: HashMap<DeviceId, Vec<oneshot::Sender<()>>>,
 }
 
 struct MessageWithResponse<TMsg: ActorMessage> {
     msg: TMsg,
     response_channel: oneshot::Sender<ActorResult<TMsg>>,
+    progress: Option<mpsc::UnboundedSender<ActorProgress>>,
 }
 
 impl<TMsg: ActorMessage> MessageWithResponse<TMsg> {
-    fn new(msg: TMsg, response_channel: oneshot::Sender<ActorResult<TMsg>>) -> Self {
+    fn new(
+        msg: TMsg,
+        response_channel: oneshot::Sender<ActorResult<TMsg>>,
+        progress: Option<mpsc::UnboundedSender<ActorProgress>>,
+    ) -> Self {
         Self {
             msg,
             response_channel,
+            progress,
         }
     }
 }
@@ -253,6 +431,10 @@ pub trait MessageHandler<TState>: Send {
         boxed_msg: BoxMessage,
         detail: Cow<'static, str>,
     );
+    /// Used to record messages before they are handled. Returns the type name and the message itself
+    fn message_ref<'a>(&self, _boxed_msg: &'a BoxMessage) -> Option<(&'static str, &'a dyn Any)> {
+        None
+    }
 }
 
 #[cfg(any(feature = "tokio", feature = "rayon", test))]
@@ -275,6 +457,7 @@ where
         let MessageWithResponse {
             msg,
             response_channel,
+            ..
         } = *boxed_msg
             .0
             .downcast::<MessageWithResponse<TMsg>>()
@@ -306,6 +489,10 @@ where
     ) {
         respond_with_unknown_device::<TMsg>(boxed_msg, detail)
     }
+
+    fn message_ref<'a>(&self, boxed_msg: &'a BoxMessage) -> Option<(&'static str, &'a dyn Any)> {
+        peek_message::<TMsg>(boxed_msg)
+    }
 }
 
 struct AsyncMessageHandler<THandlerClosure: Send, TState, TMsg> {
@@ -330,6 +517,7 @@ where
         let MessageWithResponse {
             msg,
             response_channel,
+            progress,
         } = *boxed_msg
             .0
             .downcast::<MessageWithResponse<TMsg>>()
@@ -341,7 +529,14 @@ where
         );
         async move {
             let r = h_cloned
-                .call(&mut state, msg, HandlerClosureContext { response_channel })
+                .call(
+                    &mut state,
+                    msg,
+                    HandlerClosureContext {
+                        response_channel,
+                        progress,
+                    },
+                )
                 .await;
             (state, r)
         }
@@ -356,6 +551,17 @@ where
     ) {
         respond_with_unknown_device::<TMsg>(boxed_msg, detail)
     }
+
+    fn message_ref<'a>(&self, boxed_msg: &'a BoxMessage) -> Option<(&'static str, &'a dyn Any)> {
+        peek_message::<TMsg>(boxed_msg)
+    }
+}
+
+fn peek_message<TMsg: ActorMessage>(boxed_msg: &BoxMessage) -> Option<(&'static str, &dyn Any)> {
+    boxed_msg
+        .0
+        .downcast_ref::<MessageWithResponse<TMsg>>()
+        .map(|x| (std::any::type_name::<TMsg>(), &x.msg as &dyn Any))
 }
 
 fn respond_with_unknown_device<TMsg: ActorMessage>(
@@ -368,12 +574,12 @@ fn respond_with_unknown_device<TMsg: ActorMessage>(
         .0
         .downcast::<MessageWithResponse<TMsg>>()
         .expect("Must be castable. This is most likely an internal bug of the ActorSystem");
-    let _ignore_not_consumed =
-        response_channel.send(Err(ActorErrorUnknownDevice::UnknownDeviceId {
-            device_id: DeviceId::nil(),
-            details,
-        }
-        .into()));
+    let _ignore_not_consumed = response_channel.send(Err(ActorErrorUnknownDevice::unknown_id(
+        DeviceId::nil(),
+        details,
+    )
+    .with_message_type::<TMsg>()
+    .into()));
 }
 
 mod releaser {
@@ -397,6 +603,14 @@ mod releaser {
             lock.messages.entry(typeid).or_default().insert(self.id);
         }
 
+        pub fn announce_executing(&self) {
+            let mut lock = self.state.write().expect("Not poisoned");
+            lock.executing.insert(self.id);
+            for waiter in lock.executing_waiters.remove(&self.id).unwrap_or_default() {
+                let _ignore_stopped_waiter = waiter.send(());
+            }
+        }
+
         pub fn revoke_message_responsibility(&self, typeids: impl IntoIterator<Item = TypeId>) {
             let lock = &mut self.state.write().expect("Not poisoned").messages;
             for typeid in typeids {
@@ -411,6 +625,7 @@ mod releaser {
         fn drop(&mut self) {
             let mut lock = self.state.write().expect("Not poisoned");
             lock.devices.remove(&self.id);
+            lock.executing.remove(&self.id);
         }
     }
 }
@@ -449,6 +664,7 @@ pub struct ActorDevice<TState> {
     receiver: mpsc::Receiver<(TypeId, BoxMessage)>, // Contains MessageWithResponse<TMsg>
     post: ActorDevicePostExecute<TState>,
     pending_tasks: FuturesUnordered<Task>,
+    recorder: Option<MessageRecorder>,
 }
 
 pub struct ActorDevicePostExecute<TState> {
@@ -468,6 +684,7 @@ impl<TState> ActorDevice<TState> {
     fn new(
         receiver: mpsc::Receiver<(TypeId, BoxMessage)>,
         manager: releaser::DeviceReleaser,
+        recorder: Option<MessageRecorder>,
     ) -> Self {
         ActorDevice {
             receiver,
@@ -476,6 +693,7 @@ impl<TState> ActorDevice<TState> {
                 manager,
             },
             pending_tasks: Default::default(),
+            recorder,
         }
     }
 }
@@ -546,8 +764,15 @@ impl<TState: 'static + Send> ActorDevice<TState> {
         mut state: TState,
         strategy: impl ActorExecutionStrategy<TState>,
     ) -> TState {
+        self.post.manager.announce_executing();
         while let Some((typeid, untyped_message)) = self.receiver.next().await {
             if let Some(available_handler) = self.post.handlers.get(&typeid) {
+                if let Some(recorder) = &self.recorder {
+                    if let Some((type_name, msg)) = available_handler.message_ref(&untyped_message)
+                    {
+                        recorder.record(type_name, msg);
+                    }
+                }
                 let fut = strategy.execute(available_handler.as_ref(), state, untyped_message);
                 pin_mut!(fut);
 
@@ -695,6 +920,22 @@ mod tests {
         );
     }
 
+    #[tokio::test]
+    async fn wait_until_device_executes() {
+        let system = ActorSystem::new();
+        let id = DeviceId::new_v4();
+        async fn handler(state: &mut i32, _msg: I32Message) -> Result<i64, ActorError<String>> {
+            Ok(*state as i64)
+        }
+        let waiting = system.wait_until_executing(id);
+        let runner = system.register(id).add_handler(handler).execute(1);
+        pin_mut!(waiting, runner);
+        assert!((&mut waiting).now_or_never().is_none());
+        assert!((&mut runner).now_or_never().is_none());
+        assert!(waiting.now_or_never().is_some());
+        assert!(system.wait_until_executing(id).now_or_never().is_some());
+    }
+
     #[tokio::test]
     async fn remove_device_after_drop() {
         let system = ActorSystem::new();
@@ -706,10 +947,7 @@ mod tests {
 
         assert_eq!(
             system.get_untyped_sender(device_id).unwrap_err(),
-            ActorErrorUnknownDevice::UnknownDeviceId {
-                device_id,
-                details: "No message queue for this device".into()
-            }
+            ActorErrorUnknownDevice::unknown_id(device_id, "No message queue for this device")
         );
     }
 
@@ -807,14 +1045,48 @@ mod tests {
         assert_eq!(
             system.ask(device_id, I32Message(42)).await,
             Err(ActorError::UnknownDevice(
-                ActorErrorUnknownDevice::UnknownDeviceId {
-                    device_id,
-                    details: "No message queue for this device".into()
-                }
+                ActorErrorUnknownDevice::unknown_id(device_id, "No message queue for this device")
+                    .with_message_type::<I32Message>()
             ))
         );
     }
 
+    #[test]
+    fn forget_names_of_stopped_devices() {
+        let system = ActorSystem::new();
+        let name = crate::Name::new("Camera").unwrap();
+        let last = DeviceId::new_v4();
+        for _ in 0..MAX_STOPPED_DEVICE_NAMES * 2 {
+            system.set_device_name(DeviceId::new_v4(), name.clone());
+        }
+        system.set_device_name(last, name.clone());
+
+        let lock = system.state.read().unwrap();
+        assert!(lock.names.len() <= MAX_STOPPED_DEVICE_NAMES);
+        assert_eq!(Some(&name), lock.names.get(&last));
+    }
+
+    #[tokio::test]
+    async fn interceptor_rejects_web_messages() {
+        let system = ActorSystem::new();
+        let device_id = DeviceId::new_v4();
+        async fn handler(state: &mut i32, _msg: I32Message) -> Result<i64, ActorError<String>> {
+            Ok(*state as i64)
+        }
+        tokio::spawn(system.register(device_id).add_handler(handler).execute(1));
+        system.add_interceptor(|meta: &MessageMetadata| match meta.origin {
+            MessageOrigin::Web { .. } if meta.is::<I32Message>() => Err("Recipe is locked".into()),
+            _ => Ok(()),
+        });
+
+        let web = system.with_origin(MessageOrigin::Web { user: None });
+        assert_eq!(
+            web.ask(device_id, I32Message(1)).await,
+            Err(ActorError::Forbidden("Recipe is locked".into()))
+        );
+        assert_eq!(system.ask(device_id, I32Message(1)).await, Ok(1));
+    }
+
     #[tokio::test]
     async fn get_devices_for_messages() {
         async fn handler<TMsg>(_state: &mut i32, _msg: TMsg) -> Result<(), ActorError<()>> {
@@ -878,6 +1150,52 @@ mod tests {
         );
     }
 
+    #[tokio::test]
+    async fn ask_with_progress_yields_progress_before_result() {
+        let system = ActorSystem::new();
+        let id = DeviceId::new_v4();
+
+        async fn handler(
+            state: &mut i32,
+            msg: I32Message,
+            progress: ProgressReporter,
+            _reg: AbortRegistration,
+        ) -> Result<i64, ActorError<String>> {
+            for i in 0..msg.0 {
+                progress.report(ActorProgress::new(i as f32 / msg.0 as f32, "working"));
+            }
+            Ok(*state as i64)
+        }
+
+        let (_, events) = futures::future::join(
+            system
+                .register(id)
+                .add_handler(WithProgress::new(handler))
+                .execute(42),
+            async {
+                tokio::time::sleep(Duration::from_micros(10)).await;
+                let events = system
+                    .ask_with_progress(id, I32Message(2))
+                    .unwrap()
+                    .collect::<Vec<_>>()
+                    .await;
+                system.forget_senders();
+                events
+            },
+        )
+        .await;
+
+        assert_eq!(3, events.len());
+        assert!(matches!(
+            &events[1],
+            ActorProgressEvent::Progress(ActorProgress {
+                fraction: Some(x),
+                ..
+            }) if *x == 0.5
+        ));
+        assert!(matches!(events[2], ActorProgressEvent::Finished(Ok(42))));
+    }
+
     #[tokio::test]
     async fn handle_unknown_message() {
         let system = ActorSystem::new();
@@ -893,4 +1211,96 @@ mod tests {
             }
         } => {}};
     }
+
+    crate::actor_client! {
+        struct I32Client {
+            fn double(msg) -> I32Message;
+        }
+    }
+
+    #[tokio::test]
+    async fn typed_client() {
+        let system = ActorSystem::new();
+        let id = DeviceId::new_v4();
+        async fn handler(state: &mut i32, msg: I32Message) -> Result<i64, ActorError<String>> {
+            Ok((*state * msg.0) as i64)
+        }
+        let client = I32Client::new(system.clone(), id);
+        assert!(!client.handles_all_messages());
+        tokio::select! {
+        _ = system.register(id).add_handler(handler).execute(2) => { panic!("Should not terminate"); },
+        _ = async {
+            assert!(client.handles_all_messages());
+            assert_eq!(Ok(42), client.double(I32Message(21)).await);
+        } => {}};
+    }
+
+    fn accepted_messages(system: &ActorSystem, id: DeviceId) -> usize {
+        let mut sender = system.get_sender::<I32Message>(id).unwrap();
+        (0..)
+            .take_while(|_| sender.tell(I32Message(1)).is_ok())
+            .count()
+    }
+
+    #[test]
+    fn mailbox_capacity_limits_pending_messages() {
+        let system = ActorSystem::new();
+        let (default_id, small_id) = (DeviceId::new_v4(), DeviceId::new_v4());
+        let _default = system.register::<i32>(default_id);
+        let _small = system.register_with_mailbox_capacity::<i32>(small_id, 2);
+
+        // Each sender has one guaranteed slot in addition to the mailbox capacity
+        assert_eq!(
+            ActorSystem::DEFAULT_MAILBOX_CAPACITY + 1,
+            accepted_messages(&system, default_id)
+        );
+        assert_eq!(3, accepted_messages(&system, small_id));
+    }
+
+    #[test]
+    fn overridden_mailbox_capacity_wins_until_removed() {
+        let system = ActorSystem::new();
+        let id = DeviceId::new_v4();
+        system.override_mailbox_capacity(id, Some(20));
+        let device = system.register_with_mailbox_capacity::<i32>(id, 2);
+        assert_eq!(21, accepted_messages(&system, id));
+        drop(device);
+
+        system.override_mailbox_capacity(id, None);
+        let _device = system.register_with_mailbox_capacity::<i32>(id, 2);
+        assert_eq!(3, accepted_messages(&system, id));
+    }
+
+    #[derive(ActorMessage)]
+    #[actor_message(crate = crate, output = i64, error = String, name = "double")]
+    struct DerivedMessage(i32);
+
+    #[derive(ActorMessage)]
+    #[actor_message(crate = crate, output = T, error = ())]
+    struct GenericMessage<T: Send + 'static>(T);
+
+    #[test]
+    fn derived_messages_have_stable_wire_names() {
+        assert_eq!("double", DerivedMessage::WIRE_NAME);
+        assert_eq!("GenericMessage", GenericMessage::<u8>::WIRE_NAME);
+    }
+
+    #[tokio::test]
+    async fn handle_derived_messages() {
+        let system = ActorSystem::new();
+        let id = DeviceId::new_v4();
+        async fn double(state: &mut i32, msg: DerivedMessage) -> Result<i64, ActorError<String>> {
+            Ok((*state * msg.0) as i64)
+        }
+        async fn echo(_state: &mut i32, msg: GenericMessage<u8>) -> Result<u8, ActorError<()>> {
+            Ok(msg.0)
+        }
+        tokio::select! {
+        _ = system.register(id).add_handler(double).add_handler(echo).execute(2) => { panic!("Should not terminate"); },
+        _ = async {
+            system.wait_until_executing(id).await;
+            assert_eq!(Ok(42), system.ask(id, DerivedMessage(21)).await);
+            assert_eq!(Ok(7), system.ask(id, GenericMessage(7u8)).await);
+        } => {}};
+    }
 }
@@ -0,0 +1,89 @@
+use std::borrow::Cow;
+
+use futures::{
+    channel::{mpsc, oneshot},
+    future::Either,
+    stream::BoxStream,
+    StreamExt,
+};
+
+use super::{ActorError, ActorMessage, ActorResult};
+
+/// Intermediate state of a long running handler
+#[derive(Debug, Clone, PartialEq, serde::Serialize, serde::Deserialize)]
+pub struct ActorProgress {
+    /// Value between 0 and 1, if the handler is able to estimate it
+    pub fraction: Option<f32>,
+    pub message: Cow<'static, str>,
+}
+
+impl ActorProgress {
+    pub fn new(fraction: impl Into<Option<f32>>, message: impl Into<Cow<'static, str>>) -> Self {
+        Self {
+            fraction: fraction.into().map(|x| x.clamp(0., 1.)),
+            message: message.into(),
+        }
+    }
+}
+
+/// Handed to handlers registered with `WithProgress`.
+/// Reporting is a noop, if the message was sent with `ask` instead of `ask_with_progress`
+#[derive(Debug, Clone, Default)]
+pub struct ProgressReporter(Option<mpsc::UnboundedSender<ActorProgress>>);
+
+impl ProgressReporter {
+    pub(super) fn new(sender: Option<mpsc::UnboundedSender<ActorProgress>>) -> Self {
+        Self(sender)
+    }
+
+    pub fn report(&self, progress: ActorProgress) {
+        if let Some(sender) = &self.0 {
+            let _ignore_closed_receiver = sender.unbounded_send(progress);
+        }
+    }
+
+    /// Allows skipping expensive progress calculations if nobody listens
+    pub fn is_observed(&self) -> bool {
+        self.0.as_ref().is_some_and(|x| !x.is_closed())
+    }
+}
+
+#[derive(Debug)]
+pub enum ActorProgressEvent<T> {
+    Progress(ActorProgress),
+    Finished(T),
+}
+
+pub type ActorProgressStream<TMsg> = BoxStream<'static, ActorProgressEvent<ActorResult<TMsg>>>;
+
+/// Emits all progress until the response is available. Dropping the stream cancels handlers registered with `WithProgress`
+pub(super) fn progress_stream<TMsg: ActorMessage>(
+    progress: mpsc::UnboundedReceiver<ActorProgress>,
+    response: oneshot::Receiver<ActorResult<TMsg>>,
+) -> ActorProgressStream<TMsg> {
+    fn flatten<TMsg: ActorMessage>(
+        x: Result<ActorResult<TMsg>, oneshot::Canceled>,
+    ) -> ActorResult<TMsg> {
+        x.unwrap_or_else(|_| Err(ActorError::UnknownMessageType(std::any::type_name::<TMsg>())))
+    }
+
+    futures::stream::unfold(
+        (progress, Some(response)),
+        |(mut progress, response)| async move {
+            let mut response = response?;
+            let next = match futures::future::select(progress.next(), &mut response).await {
+                Either::Left((p, _)) => Either::Left(p),
+                Either::Right((r, _)) => Either::Right(r),
+            };
+            let event = match next {
+                Either::Left(Some(p)) => {
+                    return Some((ActorProgressEvent::Progress(p), (progress, Some(response))))
+                }
+                Either::Left(None) => ActorProgressEvent::Finished(flatten::<TMsg>(response.await)),
+                Either::Right(r) => ActorProgressEvent::Finished(flatten::<TMsg>(r)),
+            };
+            Some((event, (progress, None)))
+        },
+    )
+    .boxed()
+}
@@ -0,0 +1,315 @@
+//! Records the messages a device received, so field bugs can be reproduced in unit tests.
+//!
+//! The runtime enables recording for the device types configured in `actor_system.record_messages`, e.g.
+//! `{ "actor_system": { "record_messages": { "camera": 1000 } } }`. Such recordings contain message types only.
+//! Plugins which need payloads call [`ActorSystem::record_messages`] with [`MessageRecorder::with_payload`] before
+//! the device is spawned. The recording is downloaded from `/api/device/{device_id}/recording` or written with
+//! [`MessageRecorder::dump`]. A test feeds it into a freshly constructed device:
+//! ```ignore
+//! let records = read_recording("camera.jsonl")?;
+//! let system = ActorSystem::new();
+//! let device = system.register(id).add_handler(handle_trigger);
+//! let (state, outcomes) = MessageReplay::new()
+//!     .with_message::<TriggerMessage>()
+//!     .run(&system, id, device, State::default(), records)
+//!     .await;
+//! ```
+
+use std::{
+    any::{Any, TypeId},
+    collections::{HashMap, VecDeque},
+    io::{BufRead, Write},
+    path::Path,
+    sync::{Arc, Mutex},
+};
+
+use chrono::{DateTime, Utc};
+use futures::{future::BoxFuture, FutureExt};
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
+
+use super::{ActorDevice, ActorSystem, WireActorMessage};
+use crate::device::DeviceId;
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct RecordedMessage {
+    pub time: DateTime<Utc>,
+    /// `WireActorMessage::WIRE_NAME` for messages with payload, the rust type name otherwise
+    pub msg_type: String,
+    /// `None` if the message type wasn't registered with [`MessageRecorder::with_payload`]
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub payload: Option<serde_json::Value>,
+}
+
+type Serializer = fn(&dyn Any) -> Result<serde_json::Value, serde_json::Error>;
+
+/// Keeps the last `capacity` messages in memory. Clones share the same log. A capacity of 0 records nothing
+#[derive(Clone)]
+pub struct MessageRecorder {
+    capacity: usize,
+    serializers: HashMap<TypeId, (&'static str, Serializer)>,
+    log: Arc<Mutex<VecDeque<RecordedMessage>>>,
+}
+
+impl std::fmt::Debug for MessageRecorder {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("MessageRecorder")
+            .field("capacity", &self.capacity)
+            .field("payloads", &self.serializers.len())
+            .finish()
+    }
+}
+
+impl MessageRecorder {
+    pub fn new(capacity: usize) -> Self {
+        Self {
+            capacity,
+            serializers: Default::default(),
+            log: Default::default(),
+        }
+    }
+
+    /// Payloads of this message type are recorded, so they can be replayed
+    pub fn with_payload<TMsg: WireActorMessage + Serialize>(mut self) -> Self {
+        self.serializers.insert(
+            TypeId::of::<TMsg>(),
+            (TMsg::WIRE_NAME, |msg| {
+                serde_json::to_value(
+                    msg.downcast_ref::<TMsg>()
+                        .expect("Registered with the TypeId of TMsg"),
+                )
+            }),
+        );
+        self
+    }
+
+    pub(super) fn record(&self, type_name: &'static str, msg: &dyn Any) {
+        if self.capacity == 0 {
+            return;
+        }
+        let (msg_type, payload) = match self.serializers.get(&msg.type_id()) {
+            Some((wire_name, serialize)) => match serialize(msg) {
+                Ok(x) => (*wire_name, Some(x)),
+                Err(e) => {
+                    tracing::warn!("Cannot record payload of '{wire_name}': {e}");
+                    (*wire_name, None)
+                }
+            },
+            None => (type_name, None),
+        };
+        let mut lock = self.log.lock().expect("Never poisoned");
+        if lock.len() >= self.capacity {
+            lock.pop_front();
+        }
+        lock.push_back(RecordedMessage {
+            time: Utc::now(),
+            msg_type: msg_type.to_string(),
+            payload,
+        });
+    }
+
+    pub fn messages(&self) -> Vec<RecordedMessage> {
+        self.log
+            .lock()
+            .expect("Never poisoned")
+            .iter()
+            .cloned()
+            .collect()
+    }
+
+    /// Writes one JSON object per line, oldest first
+    pub fn dump(&self, path: impl AsRef<Path>) -> std::io::Result<()> {
+        let mut writer = std::io::BufWriter::new(std::fs::File::create(path)?);
+        self.write_to(&mut writer)?;
+        writer.flush()
+    }
+
+    /// Same format as [`MessageRecorder::dump`]
+    pub fn write_to(&self, mut writer: impl Write) -> std::io::Result<()> {
+        for message in self.messages() {
+            serde_json::to_writer(&mut writer, &message)?;
+            writer.write_all(b"\n")?;
+        }
+        Ok(())
+    }
+}
+
+/// Reads a file written by [`MessageRecorder::dump`]
+pub fn read_recording(path: impl AsRef<Path>) -> std::io::Result<Vec<RecordedMessage>> {
+    let reader = std::io::BufReader::new(std::fs::File::open(path)?);
+    reader
+        .lines()
+        .filter(|line| !matches!(line, Ok(x) if x.trim().is_empty()))
+        .map(|line| Ok(serde_json::from_str(&line?)?))
+        .collect()
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ReplayOutcome {
+    Handled,
+    Failed(String),
+    /// The message was recorded without payload or its type isn't registered with [`MessageReplay::with_message`]
+    Skipped,
+}
+
+type Replay = fn(ActorSystem, DeviceId, serde_json::Value) -> BoxFuture<'static, ReplayOutcome>;
+
+/// Feeds recorded messages back into a device, one after another
+#[derive(Default)]
+pub struct MessageReplay(HashMap<&'static str, Replay>);
+
+impl MessageReplay {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn with_message<TMsg: WireActorMessage + DeserializeOwned>(mut self) -> Self {
+        self.0.insert(TMsg::WIRE_NAME, replay::<TMsg>);
+        self
+    }
+
+    /// `device` must be registered with `device_id` on `system`. Returns the final state and an outcome per message
+    pub async fn run<TState: Send + 'static>(
+        &self,
+        system: &ActorSystem,
+        device_id: DeviceId,
+        device: ActorDevice<TState>,
+        state: TState,
+        messages: impl IntoIterator<Item = RecordedMessage>,
+    ) -> (TState, Vec<ReplayOutcome>) {
+        let sender = async {
+            let mut outcomes = Vec::new();
+            for message in messages {
+                let outcome = match (self.0.get(message.msg_type.as_str()), message.payload) {
+                    (Some(replay), Some(payload)) => {
+                        replay(system.clone(), device_id, payload).await
+                    }
+                    _ => ReplayOutcome::Skipped,
+                };
+                outcomes.push(outcome);
+            }
+            system.forget_sender(device_id);
+            outcomes
+        };
+        futures::future::join(device.execute(state), sender).await
+    }
+}
+
+fn replay<TMsg: WireActorMessage + DeserializeOwned>(
+    system: ActorSystem,
+    device_id: DeviceId,
+    payload: serde_json::Value,
+) -> BoxFuture<'static, ReplayOutcome> {
+    async move {
+        let msg = match serde_json::from_value::<TMsg>(payload) {
+            Ok(x) => x,
+            Err(e) => return ReplayOutcome::Failed(format!("Invalid payload: {e}")),
+        };
+        match system.ask(device_id, msg).await {
+            Ok(_) => ReplayOutcome::Handled,
+            Err(e) => ReplayOutcome::Failed(e.to_string()),
+        }
+    }
+    .boxed()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::device::{ActorError, ActorMessage, ActorResult};
+
+    #[derive(Debug, Serialize, Deserialize)]
+    struct Add(i32);
+
+    impl ActorMessage for Add {
+        type Output = i32;
+        type Error = String;
+    }
+
+    impl WireActorMessage for Add {
+        const WIRE_NAME: &'static str = "add";
+    }
+
+    struct Reset;
+
+    impl ActorMessage for Reset {
+        type Output = ();
+        type Error = ();
+    }
+
+    async fn add(state: &mut i32, msg: Add) -> ActorResult<Add> {
+        *state = state
+            .checked_add(msg.0)
+            .ok_or_else(|| ActorError::Custom("Overflow".to_string()))?;
+        Ok(*state)
+    }
+
+    async fn reset(state: &mut i32, _msg: Reset) -> ActorResult<Reset> {
+        *state = 0;
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn replay_recorded_messages() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("recording.jsonl");
+        let id = DeviceId::new_v4();
+
+        let system = ActorSystem::new();
+        let recorder = MessageRecorder::new(10).with_payload::<Add>();
+        system.record_messages(id, Some(recorder.clone()));
+        let device = system.register(id).add_handler(add).add_handler(reset);
+        let (state, _) = futures::future::join(device.execute(0), async {
+            system.ask(id, Reset).await.unwrap();
+            system.ask(id, Add(1)).await.unwrap();
+            assert!(system.ask(id, Add(i32::MAX)).await.is_err());
+            system.forget_sender(id);
+        })
+        .await;
+        assert_eq!(1, state);
+        recorder.dump(&path).unwrap();
+
+        let records = read_recording(&path).unwrap();
+        assert_eq!(3, records.len());
+        assert_eq!(std::any::type_name::<Reset>(), records[0].msg_type);
+        assert_eq!(None, records[0].payload);
+
+        let replay_system = ActorSystem::new();
+        let device = replay_system.register(id).add_handler(add);
+        let (state, outcomes) = MessageReplay::new()
+            .with_message::<Add>()
+            .run(&replay_system, id, device, 0, records)
+            .await;
+        assert_eq!(1, state);
+        assert_eq!(
+            vec![
+                ReplayOutcome::Skipped,
+                ReplayOutcome::Handled,
+                ReplayOutcome::Failed("Error occured within the device: \"Overflow\"".into()),
+            ],
+            outcomes
+        );
+    }
+
+    #[test]
+    fn keep_last_messages() {
+        let recorder = MessageRecorder::new(2).with_payload::<Add>();
+        for i in 0..3 {
+            recorder.record(std::any::type_name::<Add>(), &Add(i));
+        }
+        assert_eq!(
+            vec![Some(serde_json::json!(1)), Some(serde_json::json!(2))],
+            recorder
+                .messages()
+                .into_iter()
+                .map(|x| x.payload)
+                .collect::<Vec<_>>()
+        );
+    }
+
+    #[test]
+    fn zero_capacity_records_nothing() {
+        let recorder = MessageRecorder::new(0).with_payload::<Add>();
+        recorder.record(std::any::type_name::<Add>(), &Add(1));
+        assert_eq!(Vec::<RecordedMessage>::new(), recorder.messages());
+    }
+}
@@ -1,10 +1,14 @@
-use std::{any::TypeId, borrow::Cow, fmt::Debug, marker::PhantomData, sync::Weak};
+use std::{any::TypeId, fmt::Debug, marker::PhantomData, sync::Weak};
 
-use futures::channel::oneshot;
+use futures::channel::{mpsc, oneshot};
+
+use tracing::warn;
 
 use super::{
-    ActorError, ActorErrorBusy, ActorMessage, ActorResult, ActorWeakTellError, BoxMessage,
-    InternalSender, MessageWithResponse,
+    interceptor::{Interceptors, MessageMetadata, MessageOrigin},
+    progress::progress_stream,
+    ActorError, ActorErrorBusy, ActorMessage, ActorProgressStream, ActorResult, ActorWeakTellError,
+    BoxMessage, InternalSender, MessageWithResponse,
 };
 use crate::{device::ActorErrorUnknownDevice, device::DeviceId};
 
@@ -12,6 +16,8 @@ use crate::{device::ActorErrorUnknownDevice, device::DeviceId};
 pub struct UntypedActorMessageSender {
     device_id: DeviceId,
     mpsc_sender: InternalSender,
+    interceptors: Interceptors,
+    origin: MessageOrigin,
 }
 
 pub struct ActorMessageSender<T> {
@@ -32,6 +38,17 @@ impl<TMsg: ActorMessage> ActorMessageSender<TMsg> {
     pub async fn ask(&mut self, msg: TMsg) -> ActorResult<TMsg> {
         self.actor_message_sender.ask(msg).await
     }
+    pub fn ask_with_progress(
+        &mut self,
+        msg: TMsg,
+    ) -> Result<ActorProgressStream<TMsg>, ActorErrorBusy> {
+        self.actor_message_sender.ask_with_progress(msg)
+    }
+
+    pub(super) fn with_origin(mut self, origin: MessageOrigin) -> Self {
+        self.actor_message_sender = self.actor_message_sender.with_origin(origin);
+        self
+    }
 }
 
 impl UntypedActorMessageSender {
@@ -39,34 +56,71 @@ impl UntypedActorMessageSender {
         Self {
             device_id,
             mpsc_sender,
+            interceptors: Default::default(),
+            origin: Default::default(),
         }
     }
 
+    pub(super) fn with_interceptors(mut self, interceptors: Interceptors) -> Self {
+        self.interceptors = interceptors;
+        self
+    }
+
+    pub(super) fn with_origin(mut self, origin: MessageOrigin) -> Self {
+        self.origin = origin;
+        self
+    }
+
     /// Sends a message without awaiting a response. It's error-handling is therefore limited to see whether the Target-Actor accepts the message in it's queue
     pub fn tell<TMsg: ActorMessage>(&mut self, msg: TMsg) -> Result<(), ActorErrorBusy> {
-        let _ignore = self.get_channel(msg)?;
+        let _ignore = self.get_channel(msg, None)?;
         Ok(())
     }
 
     pub async fn ask<TMsg: ActorMessage>(&mut self, msg: TMsg) -> ActorResult<TMsg> {
-        match self.get_channel(msg)?.await {
+        match self.get_channel(msg, None)?.await {
             Ok(x) => x,
             Err(_) => Err(ActorError::UnknownMessageType(std::any::type_name::<TMsg>())),
         }
     }
 
+    pub fn ask_with_progress<TMsg: ActorMessage>(
+        &mut self,
+        msg: TMsg,
+    ) -> Result<ActorProgressStream<TMsg>, ActorErrorBusy> {
+        let (progress_tx, progress_rx) = mpsc::unbounded();
+        let response = self.get_channel(msg, Some(progress_tx))?;
+        Ok(progress_stream(progress_rx, response))
+    }
+
     #[allow(clippy::type_complexity)]
     fn get_channel<TMsg: ActorMessage>(
         &mut self,
         msg: TMsg,
+        progress: Option<mpsc::UnboundedSender<super::ActorProgress>>,
     ) -> Result<oneshot::Receiver<ActorResult<TMsg>>, ActorErrorBusy> {
         let (tx, rx) = oneshot::channel();
 
+        if let Err(reason) = self.interceptors.check(&MessageMetadata {
+            device_id: self.device_id,
+            msg_type: TypeId::of::<TMsg>(),
+            msg_type_name: std::any::type_name::<TMsg>(),
+            origin: &self.origin,
+        }) {
+            warn!(
+                "Rejected message '{}' to device {}: {reason}",
+                std::any::type_name::<TMsg>(),
+                self.device_id
+            );
+            let _ignore_for_tell = tx.send(Err(ActorError::Forbidden(reason)));
+            return Ok(rx);
+        }
+
         if self
             .mpsc_sender
             .try_send((
                 TypeId::of::<TMsg>(),
-                BoxMessage(Box::new(MessageWithResponse::new(msg, tx))),
+                BoxMessage(Box::new(MessageWithResponse::new(msg, tx, progress))),
             ))
             .is_err()
         {
@@ -80,6 +134,8 @@ impl UntypedActorMessageSender {
 pub struct WeakUntypedActorMessageSender {
     device_id: DeviceId,
     mpsc_sender: Weak<InternalSender>,
+    interceptors: Interceptors,
+    origin: MessageOrigin,
 }
 
 impl WeakUntypedActorMessageSender {
@@ -87,18 +143,31 @@ impl WeakUntypedActorMessageSender {
         Self {
             device_id,
             mpsc_sender,
+            interceptors: Default::default(),
+            origin: Default::default(),
         }
     }
 
+    pub(super) fn with_interceptors(
+        mut self,
+        interceptors: Interceptors,
+        origin: MessageOrigin,
+    ) -> Self {
+        self.interceptors = interceptors;
+        self.origin = origin;
+        self
+    }
+
     pub fn tell<TMsg: ActorMessage>(&mut self, msg: TMsg) -> Result<(), ActorWeakTellError> {
         if let Ok(mut x) = self.build_strong::<TMsg>() {
             x.tell(msg).map_err(Into::into)
         } else {
             Err(ActorWeakTellError::UnknownDevice(
-                ActorErrorUnknownDevice::UnknownDeviceId {
-                    device_id: self.device_id,
-                    details: "Device existed but is no longer available".into(),
-                },
+                ActorErrorUnknownDevice::unknown_id(
+                    self.device_id,
+                    "Device existed but is no longer available",
+                )
+                .with_message_type::<TMsg>(),
             ))
         }
     }
@@ -113,15 +182,18 @@ impl WeakUntypedActorMessageSender {
         let mpsc_sender = InternalSender::clone(
             self.mpsc_sender
                 .upgrade()
-                .ok_or(ActorErrorUnknownDevice::UnknownDeviceId {
-                    device_id: self.device_id,
-                    details: Cow::Borrowed(
+                .ok_or_else(|| {
+                    ActorErrorUnknownDevice::unknown_id(
+                        self.device_id,
                         "Channel from WeakUntypedActorMessageSender was dropped already",
-                    ),
+                    )
+                    .with_message_type::<TMsg>()
                 })?
                 .as_ref(),
         );
 
-        Ok(UntypedActorMessageSender::new(self.device_id, mpsc_sender))
+        Ok(UntypedActorMessageSender::new(self.device_id, mpsc_sender)
+            .with_interceptors(self.interceptors.clone())
+            .with_origin(self.origin.clone()))
     }
 }
@@ -18,7 +18,7 @@ pub struct DeviceValidationContext<'a> {
 impl<'a> DeviceValidationContext<'a> {
     pub fn params_as<T: DeserializeOwned>(&self) -> Result<T, UpdateParamsMessageError> {
         let resolved = self.raw.variables.resolve(&self.raw.params_with_vars)?;
-        Ok(resolved.params_as::<T>()?)
+        resolved.params_as_described::<T>()
     }
 
     pub fn device_id(&self) -> DeviceId {
@@ -35,8 +35,7 @@ impl<'a> DeviceValidationContext<'a> {
             let resolved = self.raw.variables.resolve(&self.raw.params_with_vars)?;
 
             resolved
-                .params_as::<T>()
-                .map_err(Into::into)
+                .params_as_described::<T>()
                 .and_then(|x| x.seal().map_err(Into::into))
         }
     }
@@ -0,0 +1,168 @@
+//! Lists which registrar provided which services, e.g. to find out why a device type isn't available
+//!
+//! The runtime registers a [`ServiceInfo::registrar`] before each registrar runs and the extension methods
+//! (`register_device`, `register_hosted_service`, `register_web`) register an entry for each service.
+//! minfac can't enumerate plain `register` and `register_shared` calls, so registrars list such services with
+//! [`ServiceInfo::transient`] or [`ServiceInfo::shared`] next to the registration.
+//! As minfac returns instances in registration order, each service belongs to the last registrar before it
+
+use std::borrow::Cow;
+
+use serde::Serialize;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ServiceKind {
+    Registrar,
+    Device,
+    HostedService,
+    Web,
+    Service,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ServiceLifetime {
+    /// Registered with `register_instance`, so it shares the lifetime of the ServiceProvider
+    Instance,
+    /// Registered with `register_shared`: Created once on first use and shared afterwards
+    Shared,
+    /// Registered with `register`: Created again whenever it's resolved
+    Transient,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct ServiceInfo {
+    pub kind: ServiceKind,
+    /// Device type, name of the hosted service, web prefix or rust type of the service
+    pub name: Cow<'static, str>,
+    /// Devices, hosted services and web prefixes are registered as instances
+    pub lifetime: ServiceLifetime,
+    /// Rust type of the dependencies, which are resolved when the service is started
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub dependencies: Option<&'static str>,
+}
+
+impl ServiceInfo {
+    /// `origin` is e.g. the crate name, the source location of `Runtime::register` or the plugin path
+    pub fn registrar(origin: impl Into<Cow<'static, str>>) -> Self {
+        Self {
+            kind: ServiceKind::Registrar,
+            name: origin.into(),
+            lifetime: ServiceLifetime::Instance,
+            dependencies: None,
+        }
+    }
+
+    pub fn device<TDep>(device_type: &'static str) -> Self {
+        Self::with_dependencies::<TDep>(ServiceKind::Device, device_type)
+    }
+
+    pub fn hosted_service<TDep>(name: &'static str) -> Self {
+        Self::with_dependencies::<TDep>(ServiceKind::HostedService, name)
+    }
+
+    pub fn web(prefix: &'static str) -> Self {
+        Self {
+            kind: ServiceKind::Web,
+            name: prefix.into(),
+            lifetime: ServiceLifetime::Instance,
+            dependencies: None,
+        }
+    }
+
+    /// For services registered with `register_shared`. `T` is the registered type, e.g. `Arc<MyService>`
+    pub fn shared<T: ?Sized>() -> Self {
+        Self::service::<T>(ServiceLifetime::Shared)
+    }
+
+    /// For services registered with `register`
+    pub fn transient<T: ?Sized>() -> Self {
+        Self::service::<T>(ServiceLifetime::Transient)
+    }
+
+    fn service<T: ?Sized>(lifetime: ServiceLifetime) -> Self {
+        Self {
+            kind: ServiceKind::Service,
+            name: std::any::type_name::<T>().into(),
+            lifetime,
+            dependencies: None,
+        }
+    }
+
+    fn with_dependencies<TDep>(kind: ServiceKind, name: &'static str) -> Self {
+        Self {
+            kind,
+            name: name.into(),
+            lifetime: ServiceLifetime::Instance,
+            dependencies: Some(std::any::type_name::<TDep>()),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct RegistrarServices {
+    pub registrar: Cow<'static, str>,
+    pub services: Vec<ServiceInfo>,
+}
+
+/// Expects the infos in registration order. Services without a preceding registrar are listed as "unknown"
+pub fn group_by_registrar(infos: impl IntoIterator<Item = ServiceInfo>) -> Vec<RegistrarServices> {
+    let mut result = Vec::<RegistrarServices>::new();
+    for info in infos {
+        if info.kind == ServiceKind::Registrar {
+            result.push(RegistrarServices {
+                registrar: info.name,
+                services: Vec::new(),
+            });
+            continue;
+        }
+        if result.is_empty() {
+            result.push(RegistrarServices {
+                registrar: "unknown".into(),
+                services: Vec::new(),
+            });
+        }
+        result.last_mut().expect("Pushed above").services.push(info);
+    }
+    result
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn attribute_services_to_previous_registrar() {
+        let grouped = group_by_registrar([
+            ServiceInfo::web("early"),
+            ServiceInfo::registrar("pilatus-rt"),
+            ServiceInfo::hosted_service::<()>("Notifier"),
+            ServiceInfo::registrar("plugins/camera.so"),
+            ServiceInfo::device::<(u8,)>("camera"),
+            ServiceInfo::shared::<std::sync::Arc<str>>(),
+        ]);
+        assert_eq!(
+            vec!["unknown", "pilatus-rt", "plugins/camera.so"],
+            grouped
+                .iter()
+                .map(|x| x.registrar.as_ref())
+                .collect::<Vec<_>>()
+        );
+        assert_eq!(
+            vec![
+                (ServiceKind::Device, "camera", ServiceLifetime::Instance),
+                (
+                    ServiceKind::Service,
+                    "alloc::sync::Arc<str>",
+                    ServiceLifetime::Shared
+                )
+            ],
+            grouped[2]
+                .services
+                .iter()
+                .map(|x| (x.kind, x.name.as_ref(), x.lifetime))
+                .collect::<Vec<_>>()
+        );
+    }
+}
@@ -1,6 +1,18 @@
 use futures::future::BoxFuture;
 use futures::io::AsyncRead;
 
+#[cfg(feature = "encryption")]
+mod encryption;
+mod manifest;
+#[cfg(feature = "tar")]
+mod tar;
+
+#[cfg(feature = "tar")]
+pub use self::tar::{TarEntryReader, TarEntryWriter};
+#[cfg(feature = "encryption")]
+pub use encryption::{decrypt_if_encrypted, EncryptingWriter, ENCRYPTED_ARCHIVE_MAGIC};
+pub use manifest::*;
+
 pub trait PinReader: AsyncRead + Unpin + Send {}
 impl<T> PinReader for T where T: AsyncRead + Unpin + Send {}
 
@@ -10,7 +22,19 @@ pub trait EntryWriter: Send {
         path: String,
         data: &'a mut dyn PinReader,
     ) -> BoxFuture<'a, std::io::Result<()>>;
+    /// Like `insert`, but the size is known in advance (e.g. from the file metadata), so formats which store it in front of the data can stream it.
+    /// Fails, if `data` doesn't provide exactly `size` bytes
+    fn insert_sized<'a>(
+        &'a mut self,
+        path: String,
+        _size: u64,
+        data: &'a mut dyn PinReader,
+    ) -> BoxFuture<'a, std::io::Result<()>> {
+        self.insert(path, data)
+    }
     fn close(self: Box<Self>) -> BoxFuture<'static, std::io::Result<()>>;
+    /// Number of entries which will be inserted, if the producer knows it in advance. Only used for progress reporting
+    fn announce_entries(&mut self, _total: usize) {}
 }
 
 pub trait EntryReader: Send {
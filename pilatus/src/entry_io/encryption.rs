@@ -0,0 +1,376 @@
+//! Password based encryption of whole archives (AES-256-GCM with a scrypt derived key)
+//!
+//! Format: `MAGIC | log_n: u8 | salt: [u8; 16] | nonce_prefix: [u8; 7] | chunk*`
+//! Each chunk is `u32::LE(len | LAST_CHUNK_FLAG) | ciphertext`. The nonce consists of the prefix, the chunk counter
+//! and the last-chunk flag, so reordered, truncated or extended streams fail to decrypt.
+
+use std::{
+    io,
+    pin::Pin,
+    task::{ready, Context, Poll},
+};
+
+use aes_gcm::{
+    aead::{rand_core::RngCore, Aead, KeyInit, OsRng},
+    Aes256Gcm, Key, Nonce,
+};
+use futures::{
+    io::{AsyncBufRead, AsyncRead, AsyncReadExt, AsyncWrite},
+    stream::{self, StreamExt, TryStreamExt},
+};
+
+pub const ENCRYPTED_ARCHIVE_MAGIC: &[u8; 8] = b"PILENC01";
+
+const CHUNK_SIZE: usize = 64 * 1024;
+const LAST_CHUNK_FLAG: u32 = 1 << 31;
+const TAG_SIZE: usize = 16;
+const SALT_SIZE: usize = 16;
+const NONCE_PREFIX_SIZE: usize = 7;
+/// Archives are only written with this cost. Higher costs in the header are rejected, so an uploaded archive can't
+/// keep the CPU busy for minutes
+const DEFAULT_LOG_N: u8 = 15;
+
+/// scrypt intentionally takes a considerable amount of CPU time, so it runs on the blocking pool
+async fn derive_cipher(
+    password: String,
+    salt: [u8; SALT_SIZE],
+    log_n: u8,
+) -> io::Result<Aes256Gcm> {
+    if log_n > DEFAULT_LOG_N {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidData,
+            format!("scrypt cost {log_n} exceeds the maximum of {DEFAULT_LOG_N}"),
+        ));
+    }
+    tokio::task::spawn_blocking(move || {
+        let params = scrypt::Params::new(log_n, 8, 1, 32)
+            .map_err(|e| io::Error::new(io::ErrorKind::InvalidInput, e.to_string()))?;
+        let mut key = [0u8; 32];
+        scrypt::scrypt(password.as_bytes(), &salt, &params, &mut key)
+            .map_err(|e| io::Error::new(io::ErrorKind::InvalidInput, e.to_string()))?;
+        Ok(Aes256Gcm::new(Key::<Aes256Gcm>::from_slice(&key)))
+    })
+    .await
+    .map_err(io::Error::other)?
+}
+
+fn nonce(
+    prefix: &[u8; NONCE_PREFIX_SIZE],
+    counter: u32,
+    last: bool,
+) -> Nonce<aes_gcm::aead::consts::U12> {
+    let mut nonce = [0u8; 12];
+    nonce[..NONCE_PREFIX_SIZE].copy_from_slice(prefix);
+    nonce[NONCE_PREFIX_SIZE..11].copy_from_slice(&counter.to_be_bytes());
+    nonce[11] = last as u8;
+    Nonce::clone_from_slice(&nonce)
+}
+
+fn crypto_error(_: aes_gcm::Error) -> io::Error {
+    io::Error::new(
+        io::ErrorKind::InvalidData,
+        "Decryption failed. The password is wrong or the archive was modified",
+    )
+}
+
+pub struct EncryptingWriter<W> {
+    inner: W,
+    cipher: Aes256Gcm,
+    nonce_prefix: [u8; NONCE_PREFIX_SIZE],
+    counter: u32,
+    plain: Vec<u8>,
+    out: Vec<u8>,
+    out_pos: usize,
+    finished: bool,
+}
+
+impl<W: AsyncWrite + Unpin> EncryptingWriter<W> {
+    /// Derives the key with scrypt on the blocking pool, which takes a considerable amount of time
+    pub async fn new(inner: W, password: &str) -> io::Result<Self> {
+        let mut salt = [0u8; SALT_SIZE];
+        let mut nonce_prefix = [0u8; NONCE_PREFIX_SIZE];
+        OsRng.fill_bytes(&mut salt);
+        OsRng.fill_bytes(&mut nonce_prefix);
+        let cipher = derive_cipher(password.to_string(), salt, DEFAULT_LOG_N).await?;
+
+        let mut out = Vec::with_capacity(CHUNK_SIZE + TAG_SIZE + 4);
+        out.extend_from_slice(ENCRYPTED_ARCHIVE_MAGIC);
+        out.push(DEFAULT_LOG_N);
+        out.extend_from_slice(&salt);
+        out.extend_from_slice(&nonce_prefix);
+
+        Ok(Self {
+            inner,
+            cipher,
+            nonce_prefix,
+            counter: 0,
+            plain: Vec::with_capacity(CHUNK_SIZE),
+            out,
+            out_pos: 0,
+            finished: false,
+        })
+    }
+
+    fn seal(&mut self, last: bool) -> io::Result<()> {
+        let ciphertext = self
+            .cipher
+            .encrypt(
+                &nonce(&self.nonce_prefix, self.counter, last),
+                &self.plain[..],
+            )
+            .map_err(|_| io::Error::other("Encryption failed"))?;
+        self.counter = self
+            .counter
+            .checked_add(1)
+            .ok_or_else(|| io::Error::other("Archive is too big for encryption"))?;
+        let len = ciphertext.len() as u32 | if last { LAST_CHUNK_FLAG } else { 0 };
+        self.out.extend_from_slice(&len.to_le_bytes());
+        self.out.extend_from_slice(&ciphertext);
+        self.plain.clear();
+        Ok(())
+    }
+
+    fn poll_drain(&mut self, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        while self.out_pos < self.out.len() {
+            let n = ready!(Pin::new(&mut self.inner).poll_write(cx, &self.out[self.out_pos..]))?;
+            if n == 0 {
+                return Poll::Ready(Err(io::ErrorKind::WriteZero.into()));
+            }
+            self.out_pos += n;
+        }
+        self.out.clear();
+        self.out_pos = 0;
+        Poll::Ready(Ok(()))
+    }
+}
+
+impl<W: AsyncWrite + Unpin> AsyncWrite for EncryptingWriter<W> {
+    fn poll_write(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &[u8],
+    ) -> Poll<io::Result<usize>> {
+        let this = &mut *self;
+        if this.finished {
+            return Poll::Ready(Err(io::Error::other("Writer is already closed")));
+        }
+        ready!(this.poll_drain(cx))?;
+        if this.plain.len() == CHUNK_SIZE {
+            this.seal(false)?;
+            ready!(this.poll_drain(cx))?;
+        }
+        let n = buf.len().min(CHUNK_SIZE - this.plain.len());
+        this.plain.extend_from_slice(&buf[..n]);
+        Poll::Ready(Ok(n))
+    }
+
+    fn poll_flush(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        let this = &mut *self;
+        ready!(this.poll_drain(cx))?;
+        Pin::new(&mut this.inner).poll_flush(cx)
+    }
+
+    fn poll_close(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        let this = &mut *self;
+        if !this.finished {
+            ready!(this.poll_drain(cx))?;
+            this.seal(true)?;
+            this.finished = true;
+        }
+        ready!(this.poll_drain(cx))?;
+        Pin::new(&mut this.inner).poll_close(cx)
+    }
+}
+
+/// Decrypts archives written by `EncryptingWriter` and passes through unencrypted ones unchanged.
+/// Fails with `PermissionDenied`, if the archive is encrypted but no password was provided and with `InvalidData`,
+/// if anything follows the last chunk
+pub fn decrypt_if_encrypted<'a, R: AsyncRead + Unpin + Send + 'a>(
+    raw: R,
+    password: Option<String>,
+) -> impl AsyncBufRead + Unpin + Send + 'a {
+    enum State<R> {
+        Start(R, Option<String>),
+        Plain(R),
+        Encrypted {
+            raw: R,
+            cipher: Box<Aes256Gcm>,
+            nonce_prefix: [u8; NONCE_PREFIX_SIZE],
+            counter: u32,
+        },
+        Done,
+    }
+
+    stream::try_unfold(State::Start(raw, password), |state| async move {
+        match state {
+            State::Start(mut raw, password) => {
+                let mut magic = [0u8; 8];
+                let read = read_up_to(&mut raw, &mut magic).await?;
+                if &magic[..read] != ENCRYPTED_ARCHIVE_MAGIC {
+                    return Ok(Some((magic[..read].to_vec(), State::Plain(raw))));
+                }
+                let password = password.ok_or_else(|| {
+                    io::Error::new(
+                        io::ErrorKind::PermissionDenied,
+                        "Archive is encrypted, but no password was provided",
+                    )
+                })?;
+                let mut header = [0u8; 1 + SALT_SIZE + NONCE_PREFIX_SIZE];
+                raw.read_exact(&mut header).await?;
+                let salt = header[1..1 + SALT_SIZE]
+                    .try_into()
+                    .expect("Header has correct size");
+                let cipher = derive_cipher(password, salt, header[0]).await?;
+                let nonce_prefix = header[1 + SALT_SIZE..]
+                    .try_into()
+                    .expect("Header has correct size");
+                Ok(Some((
+                    Vec::new(),
+                    State::Encrypted {
+                        raw,
+                        cipher: Box::new(cipher),
+                        nonce_prefix,
+                        counter: 0,
+                    },
+                )))
+            }
+            State::Plain(mut raw) => {
+                let mut buf = vec![0u8; CHUNK_SIZE];
+                let read = raw.read(&mut buf).await?;
+                if read == 0 {
+                    return Ok(None);
+                }
+                buf.truncate(read);
+                Ok(Some((buf, State::Plain(raw))))
+            }
+            State::Encrypted {
+                mut raw,
+                cipher,
+                nonce_prefix,
+                counter,
+            } => {
+                let mut len = [0u8; 4];
+                raw.read_exact(&mut len).await?;
+                let len = u32::from_le_bytes(len);
+                let last = len & LAST_CHUNK_FLAG != 0;
+                let len = (len & !LAST_CHUNK_FLAG) as usize;
+                if len > CHUNK_SIZE + TAG_SIZE {
+                    return Err(io::Error::new(
+                        io::ErrorKind::InvalidData,
+                        "Encrypted chunk is too big",
+                    ));
+                }
+                let mut ciphertext = vec![0u8; len];
+                raw.read_exact(&mut ciphertext).await?;
+                let plain = cipher
+                    .decrypt(&nonce(&nonce_prefix, counter, last), &ciphertext[..])
+                    .map_err(crypto_error)?;
+                let next = if last {
+                    if read_up_to(&mut raw, &mut [0u8; 1]).await? != 0 {
+                        return Err(io::Error::new(
+                            io::ErrorKind::InvalidData,
+                            "Trailing data after the last chunk",
+                        ));
+                    }
+                    State::Done
+                } else {
+                    State::Encrypted {
+                        raw,
+                        cipher,
+                        nonce_prefix,
+                        counter: counter.checked_add(1).ok_or_else(|| {
+                            io::Error::new(io::ErrorKind::InvalidData, "Too many chunks")
+                        })?,
+                    }
+                };
+                Ok(Some((plain, next)))
+            }
+            State::Done => Ok(None),
+        }
+    })
+    .boxed()
+    .into_async_read()
+}
+
+/// Like read_exact, but stops without error at the end of the stream
+async fn read_up_to(raw: &mut (impl AsyncRead + Unpin), buf: &mut [u8]) -> io::Result<usize> {
+    let mut read = 0;
+    while read < buf.len() {
+        match raw.read(&mut buf[read..]).await? {
+            0 => break,
+            n => read += n,
+        }
+    }
+    Ok(read)
+}
+
+#[cfg(test)]
+mod tests {
+    use futures::{io::Cursor, AsyncWriteExt};
+
+    use super::*;
+
+    async fn encrypt(data: &[u8], password: &str) -> Vec<u8> {
+        let mut writer = EncryptingWriter::new(Cursor::new(Vec::new()), password)
+            .await
+            .unwrap();
+        writer.write_all(data).await.unwrap();
+        writer.close().await.unwrap();
+        writer.inner.into_inner()
+    }
+
+    async fn decrypt(data: Vec<u8>, password: Option<&str>) -> io::Result<Vec<u8>> {
+        let mut out = Vec::new();
+        decrypt_if_encrypted(Cursor::new(data), password.map(Into::into))
+            .read_to_end(&mut out)
+            .await?;
+        Ok(out)
+    }
+
+    #[tokio::test]
+    async fn roundtrip_multiple_chunks() {
+        let data = (0..CHUNK_SIZE * 2 + 10)
+            .map(|x| x as u8)
+            .collect::<Vec<_>>();
+        let encrypted = encrypt(&data, "secret").await;
+        assert_ne!(&encrypted[8..], &data[..]);
+        assert_eq!(data, decrypt(encrypted, Some("secret")).await.unwrap());
+    }
+
+    #[tokio::test]
+    async fn reject_wrong_password_truncation_and_missing_password() {
+        let encrypted = encrypt(b"parameters", "secret").await;
+        assert!(decrypt(encrypted.clone(), Some("wrong")).await.is_err());
+        assert_eq!(
+            io::ErrorKind::PermissionDenied,
+            decrypt(encrypted.clone(), None).await.unwrap_err().kind()
+        );
+        let mut truncated = encrypted;
+        truncated.truncate(truncated.len() - 1);
+        assert!(decrypt(truncated, Some("secret")).await.is_err());
+    }
+
+    #[tokio::test]
+    async fn reject_trailing_data_and_excessive_cost() {
+        let mut extended = encrypt(b"parameters", "secret").await;
+        extended.push(0);
+        assert_eq!(
+            io::ErrorKind::InvalidData,
+            decrypt(extended, Some("secret")).await.unwrap_err().kind()
+        );
+
+        let mut expensive = encrypt(b"parameters", "secret").await;
+        expensive[ENCRYPTED_ARCHIVE_MAGIC.len()] = DEFAULT_LOG_N + 1;
+        let error = decrypt(expensive, Some("secret")).await.unwrap_err();
+        assert!(error.to_string().contains("scrypt cost"), "{error}");
+    }
+
+    #[tokio::test]
+    async fn pass_through_unencrypted_data() {
+        assert_eq!(
+            b"PK".to_vec(),
+            decrypt(b"PK".to_vec(), Some("secret")).await.unwrap()
+        );
+        assert_eq!(Vec::<u8>::new(), decrypt(Vec::new(), None).await.unwrap());
+    }
+}
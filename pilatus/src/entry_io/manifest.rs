@@ -0,0 +1,252 @@
+//! Integrity manifest which is appended to exported archives
+//! Importers verify it after all entries were extracted into a temporary location, so truncated or corrupted uploads
+//! are rejected before anything is written into the recipe directory.
+
+use std::{
+    collections::BTreeMap,
+    io,
+    pin::Pin,
+    task::{Context, Poll},
+};
+
+use futures::{future::BoxFuture, io::AsyncRead, FutureExt};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+
+use super::{EntryWriter, PinReader};
+
+pub const MANIFEST_FILENAME: &str = "manifest.json";
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ExportManifest {
+    pub pilatus_version: String,
+    pub entry_count: usize,
+    /// Hex encoded SHA-256 of each entry
+    pub entries: BTreeMap<String, String>,
+}
+
+#[derive(Debug, thiserror::Error, PartialEq, Eq)]
+pub enum ManifestMismatch {
+    #[error("Expected {expected} entries, but found {actual}")]
+    Count { expected: usize, actual: usize },
+    #[error("Entry '{0}' is missing")]
+    Missing(String),
+    #[error("Entry '{0}' is not listed in the manifest")]
+    Unexpected(String),
+    #[error("Checksum of '{0}' doesn't match")]
+    Checksum(String),
+}
+
+impl Default for ExportManifest {
+    fn default() -> Self {
+        Self {
+            pilatus_version: env!("CARGO_PKG_VERSION").to_string(),
+            entry_count: 0,
+            entries: BTreeMap::new(),
+        }
+    }
+}
+
+impl ExportManifest {
+    pub fn insert(&mut self, filename: String, checksum: String) {
+        self.entries.insert(filename, checksum);
+        self.entry_count = self.entries.len();
+    }
+
+    /// `actual` contains the checksums of all entries found in the archive (except the manifest itself)
+    pub fn verify(&self, actual: &BTreeMap<String, String>) -> Result<(), ManifestMismatch> {
+        if self.entry_count != actual.len() {
+            return Err(ManifestMismatch::Count {
+                expected: self.entry_count,
+                actual: actual.len(),
+            });
+        }
+        for (filename, checksum) in self.entries.iter() {
+            match actual.get(filename) {
+                None => return Err(ManifestMismatch::Missing(filename.clone())),
+                Some(x) if x != checksum => {
+                    return Err(ManifestMismatch::Checksum(filename.clone()))
+                }
+                Some(_) => {}
+            }
+        }
+        if let Some(unexpected) = actual.keys().find(|x| !self.entries.contains_key(*x)) {
+            return Err(ManifestMismatch::Unexpected(unexpected.clone()));
+        }
+        Ok(())
+    }
+}
+
+/// Calculates the SHA-256 of everything read through it
+pub struct HashingReader<R> {
+    inner: R,
+    hasher: Sha256,
+}
+
+impl<R> HashingReader<R> {
+    pub fn new(inner: R) -> Self {
+        Self {
+            inner,
+            hasher: Sha256::new(),
+        }
+    }
+
+    /// Hex encoded checksum of all bytes read so far
+    pub fn finalize(self) -> String {
+        self.hasher
+            .finalize()
+            .iter()
+            .map(|x| format!("{x:02x}"))
+            .collect()
+    }
+}
+
+impl<R: AsyncRead + Unpin> AsyncRead for HashingReader<R> {
+    fn poll_read(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &mut [u8],
+    ) -> Poll<io::Result<usize>> {
+        let this = &mut *self;
+        let result = Pin::new(&mut this.inner).poll_read(cx, buf);
+        if let Poll::Ready(Ok(n)) = result {
+            this.hasher.update(&buf[..n]);
+        }
+        result
+    }
+}
+
+/// Writes the manifest as last entry when the archive is closed
+pub struct ManifestEntryWriter {
+    inner: Box<dyn EntryWriter>,
+    manifest: ExportManifest,
+}
+
+impl ManifestEntryWriter {
+    pub fn new_boxed(inner: Box<dyn EntryWriter>) -> Box<Self> {
+        Box::new(Self {
+            inner,
+            manifest: ExportManifest::default(),
+        })
+    }
+}
+
+impl EntryWriter for ManifestEntryWriter {
+    fn insert<'a>(
+        &'a mut self,
+        path: String,
+        data: &'a mut dyn PinReader,
+    ) -> BoxFuture<'a, io::Result<()>> {
+        async move {
+            let mut reader = HashingReader::new(data);
+            self.inner.insert(path.clone(), &mut reader).await?;
+            self.manifest.insert(path, reader.finalize());
+            Ok(())
+        }
+        .boxed()
+    }
+
+    fn insert_sized<'a>(
+        &'a mut self,
+        path: String,
+        size: u64,
+        data: &'a mut dyn PinReader,
+    ) -> BoxFuture<'a, io::Result<()>> {
+        async move {
+            let mut reader = HashingReader::new(data);
+            self.inner
+                .insert_sized(path.clone(), size, &mut reader)
+                .await?;
+            self.manifest.insert(path, reader.finalize());
+            Ok(())
+        }
+        .boxed()
+    }
+
+    fn close(mut self: Box<Self>) -> BoxFuture<'static, io::Result<()>> {
+        async move {
+            let data = serde_json::to_vec_pretty(&self.manifest)?;
+            self.inner
+                .insert(MANIFEST_FILENAME.into(), &mut &data[..])
+                .await?;
+            self.inner.close().await
+        }
+        .boxed()
+    }
+
+    fn announce_entries(&mut self, total: usize) {
+        self.inner.announce_entries(total + 1);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::{Arc, Mutex};
+
+    use futures::AsyncReadExt;
+
+    use super::*;
+
+    #[derive(Default, Clone)]
+    struct CollectingWriter(Arc<Mutex<BTreeMap<String, Vec<u8>>>>);
+
+    impl EntryWriter for CollectingWriter {
+        fn insert<'a>(
+            &'a mut self,
+            path: String,
+            data: &'a mut dyn PinReader,
+        ) -> BoxFuture<'a, io::Result<()>> {
+            async move {
+                let mut buf = Vec::new();
+                data.read_to_end(&mut buf).await?;
+                self.0.lock().unwrap().insert(path, buf);
+                Ok(())
+            }
+            .boxed()
+        }
+
+        fn close(self: Box<Self>) -> BoxFuture<'static, io::Result<()>> {
+            async { Ok(()) }.boxed()
+        }
+    }
+
+    #[test]
+    fn manifest_detects_modified_entries() {
+        let collector = CollectingWriter::default();
+        futures::executor::block_on(async {
+            let mut writer = ManifestEntryWriter::new_boxed(Box::new(collector.clone()));
+            writer.insert("a".into(), &mut &b"foo"[..]).await.unwrap();
+            writer.insert("b".into(), &mut &b"bar"[..]).await.unwrap();
+            writer.close().await.unwrap();
+        });
+        let mut entries = collector.0.lock().unwrap().clone();
+        let manifest: ExportManifest =
+            serde_json::from_slice(&entries.remove(MANIFEST_FILENAME).unwrap()).unwrap();
+        assert_eq!(2, manifest.entry_count);
+
+        let checksum = |data: &[u8]| {
+            let mut reader = HashingReader::new(data);
+            futures::executor::block_on(reader.read_to_end(&mut Vec::new())).unwrap();
+            reader.finalize()
+        };
+        let mut actual: BTreeMap<_, _> = entries
+            .iter()
+            .map(|(k, v)| (k.clone(), checksum(v)))
+            .collect();
+        assert_eq!(Ok(()), manifest.verify(&actual));
+
+        actual.insert("b".into(), checksum(b"ba"));
+        assert_eq!(
+            Err(ManifestMismatch::Checksum("b".into())),
+            manifest.verify(&actual)
+        );
+        actual.remove("b");
+        assert_eq!(
+            Err(ManifestMismatch::Count {
+                expected: 2,
+                actual: 1
+            }),
+            manifest.verify(&actual)
+        );
+    }
+}
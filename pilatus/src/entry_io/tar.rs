@@ -0,0 +1,339 @@
+//! Tar-Archives with optional zstd-compression
+//! Tar is much cheaper to produce than zip for image-heavy device folders, as entries aren't compressed individually.
+//! Only headers are handled by the `tar` crate, so no runtime-specific async implementation is required.
+
+use std::{
+    io,
+    pin::Pin,
+    task::{Context, Poll},
+};
+
+use async_compression::futures::{bufread::ZstdDecoder, write::ZstdEncoder};
+use futures::{
+    future::BoxFuture,
+    io::{AsyncBufRead, AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt},
+    FutureExt,
+};
+
+use super::{EntryItem, EntryReader, EntryWriter, PinReader};
+
+const BLOCK_SIZE: u64 = 512;
+
+pub struct TarEntryWriter<W> {
+    inner: W,
+}
+
+impl<W: AsyncWrite + Unpin + Send + 'static> TarEntryWriter<W> {
+    pub fn new_boxed(raw: W) -> Box<Self> {
+        Box::new(Self { inner: raw })
+    }
+}
+
+impl<W: AsyncWrite + Unpin + Send + 'static> TarEntryWriter<ZstdEncoder<W>> {
+    pub fn new_zstd_boxed(raw: W) -> Box<Self> {
+        Self::new_boxed(ZstdEncoder::new(raw))
+    }
+}
+
+impl<W: AsyncWrite + Unpin + Send + 'static> TarEntryWriter<W> {
+    async fn write_entry(
+        &mut self,
+        path: &str,
+        size: u64,
+        data: &mut dyn PinReader,
+    ) -> io::Result<()> {
+        let mut header = tar::Header::new_ustar();
+        header.set_path(path)?;
+        header.set_size(size);
+        header.set_mode(0o644);
+        header.set_entry_type(tar::EntryType::Regular);
+        header.set_mtime(
+            std::time::SystemTime::now()
+                .duration_since(std::time::UNIX_EPOCH)
+                .map(|x| x.as_secs())
+                .unwrap_or_default(),
+        );
+        header.set_cksum();
+
+        self.inner.write_all(header.as_bytes()).await?;
+        let written = futures::io::copy((&mut *data).take(size), &mut self.inner).await?;
+        if written != size {
+            return Err(io::Error::new(
+                io::ErrorKind::UnexpectedEof,
+                format!("'{path}' provided {written} of {size} announced bytes"),
+            ));
+        }
+        if data.read(&mut [0]).await? != 0 {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!("'{path}' provided more than {size} announced bytes"),
+            ));
+        }
+        self.inner
+            .write_all(&[0; BLOCK_SIZE as usize][..padding(size) as usize])
+            .await
+    }
+}
+
+impl<W: AsyncWrite + Unpin + Send + 'static> EntryWriter for TarEntryWriter<W> {
+    fn insert<'a>(
+        &'a mut self,
+        path: String,
+        data: &'a mut dyn PinReader,
+    ) -> BoxFuture<'a, io::Result<()>> {
+        async move {
+            // The header contains the size, so the data has to be materialized first. Use `insert_sized` to stream it
+            let mut materialized = Vec::new();
+            data.read_to_end(&mut materialized).await?;
+            self.write_entry(&path, materialized.len() as u64, &mut &materialized[..])
+                .await
+        }
+        .boxed()
+    }
+
+    fn insert_sized<'a>(
+        &'a mut self,
+        path: String,
+        size: u64,
+        data: &'a mut dyn PinReader,
+    ) -> BoxFuture<'a, io::Result<()>> {
+        async move { self.write_entry(&path, size, data).await }.boxed()
+    }
+
+    fn close(mut self: Box<Self>) -> BoxFuture<'static, io::Result<()>> {
+        async move {
+            // Archives end with two empty blocks
+            self.inner.write_all(&[0; 2 * BLOCK_SIZE as usize]).await?;
+            self.inner.close().await
+        }
+        .boxed()
+    }
+}
+
+pub struct TarEntryReader<R> {
+    inner: CountingReader<R>,
+    next_header: u64,
+    finished: bool,
+}
+
+impl<R: AsyncRead + Unpin + Send> TarEntryReader<R> {
+    pub fn new(raw: R) -> Self {
+        Self {
+            inner: CountingReader {
+                inner: raw,
+                position: 0,
+            },
+            next_header: 0,
+            finished: false,
+        }
+    }
+}
+
+impl<R: AsyncBufRead + Unpin + Send> TarEntryReader<ZstdDecoder<R>> {
+    pub fn new_zstd(raw: R) -> Self {
+        Self::new(ZstdDecoder::new(raw))
+    }
+}
+
+impl<R: AsyncRead + Unpin + Send> TarEntryReader<R> {
+    async fn next_entry(&mut self) -> io::Result<Option<EntryItem<'_>>> {
+        // Stays set if reading the header fails or the end of the archive is reached
+        self.finished = true;
+        loop {
+            // Skip what wasn't consumed from the previous entry
+            let unread = self.next_header - self.inner.position;
+            futures::io::copy((&mut self.inner).take(unread), &mut futures::io::sink()).await?;
+            if self.inner.position != self.next_header {
+                return Err(io::ErrorKind::UnexpectedEof.into());
+            }
+
+            let mut header = tar::Header::new_old();
+            self.inner.read_exact(header.as_mut_bytes()).await?;
+            if header.as_bytes().iter().all(|&x| x == 0) {
+                return Ok(None);
+            }
+            let size = header.entry_size()?;
+            self.next_header = self.inner.position + size + padding(size);
+            if !header.entry_type().is_file() {
+                continue;
+            }
+            let filename = header.path()?.to_string_lossy().replace('\\', "/");
+            self.finished = false;
+
+            return Ok(Some(EntryItem {
+                filename,
+                reader: Box::new((&mut self.inner).take(size)),
+            }));
+        }
+    }
+}
+
+impl<R: AsyncRead + Unpin + Send> EntryReader for TarEntryReader<R> {
+    fn next(&mut self) -> BoxFuture<'_, Option<io::Result<EntryItem>>> {
+        async move {
+            if self.finished {
+                return None;
+            }
+            self.next_entry().await.transpose()
+        }
+        .boxed()
+    }
+}
+
+fn padding(size: u64) -> u64 {
+    (BLOCK_SIZE - size % BLOCK_SIZE) % BLOCK_SIZE
+}
+
+struct CountingReader<R> {
+    inner: R,
+    position: u64,
+}
+
+impl<R: AsyncRead + Unpin> AsyncRead for CountingReader<R> {
+    fn poll_read(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &mut [u8],
+    ) -> Poll<io::Result<usize>> {
+        let this = &mut *self;
+        let result = Pin::new(&mut this.inner).poll_read(cx, buf);
+        if let Poll::Ready(Ok(n)) = result {
+            this.position += n as u64;
+        }
+        result
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::{Arc, Mutex};
+
+    use futures::io::Cursor;
+
+    use super::*;
+
+    #[derive(Clone, Default)]
+    struct SharedBuffer(Arc<Mutex<Vec<u8>>>);
+
+    impl SharedBuffer {
+        fn take(&self) -> Vec<u8> {
+            std::mem::take(&mut self.0.lock().unwrap())
+        }
+    }
+
+    impl AsyncWrite for SharedBuffer {
+        fn poll_write(
+            self: Pin<&mut Self>,
+            _cx: &mut Context<'_>,
+            buf: &[u8],
+        ) -> Poll<io::Result<usize>> {
+            self.0.lock().unwrap().extend_from_slice(buf);
+            Poll::Ready(Ok(buf.len()))
+        }
+
+        fn poll_flush(self: Pin<&mut Self>, _cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+            Poll::Ready(Ok(()))
+        }
+
+        fn poll_close(self: Pin<&mut Self>, _cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+            Poll::Ready(Ok(()))
+        }
+    }
+
+    async fn write_archive(writer: Box<dyn EntryWriter>) {
+        let mut writer = writer;
+        writer
+            .insert("recipes.json".into(), &mut &b"{}"[..])
+            .await
+            .unwrap();
+        writer
+            .insert("device/file.bin".into(), &mut &[1u8; 600][..])
+            .await
+            .unwrap();
+        writer.close().await.unwrap();
+    }
+
+    async fn read_all(reader: &mut dyn EntryReader) -> Vec<(String, Vec<u8>)> {
+        let mut result = Vec::new();
+        while let Some(item) = reader.next().await {
+            let mut item = item.unwrap();
+            let mut data = Vec::new();
+            item.reader.read_to_end(&mut data).await.unwrap();
+            result.push((item.filename, data));
+        }
+        result
+    }
+
+    #[test]
+    fn roundtrip_tar() {
+        futures::executor::block_on(async {
+            let shared = SharedBuffer::default();
+            write_archive(TarEntryWriter::new_boxed(shared.clone())).await;
+            let buf = shared.take();
+            assert_eq!(0, buf.len() as u64 % BLOCK_SIZE);
+            let entries = read_all(&mut TarEntryReader::new(Cursor::new(buf))).await;
+            assert_eq!(
+                vec![
+                    ("recipes.json".to_string(), b"{}".to_vec()),
+                    ("device/file.bin".to_string(), vec![1u8; 600])
+                ],
+                entries
+            );
+        });
+    }
+
+    #[test]
+    fn skip_unread_entries_in_zstd() {
+        futures::executor::block_on(async {
+            let shared = SharedBuffer::default();
+            write_archive(TarEntryWriter::new_zstd_boxed(shared.clone())).await;
+            let buf = shared.take();
+            let mut reader = TarEntryReader::new_zstd(Cursor::new(buf));
+            assert_eq!(
+                "recipes.json",
+                reader.next().await.unwrap().unwrap().filename
+            );
+            let mut second = reader.next().await.unwrap().unwrap();
+            let mut data = Vec::new();
+            second.reader.read_to_end(&mut data).await.unwrap();
+            assert_eq!(vec![1u8; 600], data);
+            drop(second);
+            assert!(reader.next().await.is_none());
+        });
+    }
+
+    #[test]
+    fn stream_sized_entries() {
+        futures::executor::block_on(async {
+            let shared = SharedBuffer::default();
+            let mut writer = TarEntryWriter::new_boxed(shared.clone());
+            writer
+                .insert_sized("device/file.bin".into(), 600, &mut &[1u8; 600][..])
+                .await
+                .unwrap();
+            writer.close().await.unwrap();
+            let entries = read_all(&mut TarEntryReader::new(Cursor::new(shared.take()))).await;
+            assert_eq!(
+                vec![("device/file.bin".to_string(), vec![1u8; 600])],
+                entries
+            );
+        });
+    }
+
+    #[test]
+    fn reject_sized_entries_with_other_size() {
+        futures::executor::block_on(async {
+            let mut writer = TarEntryWriter::new_boxed(SharedBuffer::default());
+            let shorter = writer
+                .insert_sized("shorter.bin".into(), 601, &mut &[1u8; 600][..])
+                .await
+                .unwrap_err();
+            assert_eq!(io::ErrorKind::UnexpectedEof, shorter.kind());
+            let longer = writer
+                .insert_sized("longer.bin".into(), 599, &mut &[1u8; 600][..])
+                .await
+                .unwrap_err();
+            assert_eq!(io::ErrorKind::InvalidData, longer.kind());
+        });
+    }
+}
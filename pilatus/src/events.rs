@@ -0,0 +1,378 @@
+//! System wide history of noteworthy events, so there is a single place to see what happened on a machine
+//!
+//! The most recent events are kept in memory. The runtime persists them periodically, so they survive restarts
+
+use std::{
+    collections::VecDeque,
+    sync::{Arc, Mutex},
+};
+
+use chrono::{DateTime, Utc};
+use futures::{stream::BoxStream, StreamExt};
+use serde::{Deserialize, Serialize};
+use tokio::sync::broadcast::{self, error::RecvError};
+
+use crate::{device::DeviceId, RecipeId, ResourceKind};
+
+const DEFAULT_CAPACITY: usize = 1000;
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case", tag = "type")]
+#[non_exhaustive]
+pub enum SystemEventKind {
+    DeviceStarted {
+        device_id: DeviceId,
+        device_type: String,
+    },
+    DeviceStopped {
+        device_id: DeviceId,
+        device_type: String,
+        /// Set, if the device stopped due to an error
+        error: Option<String>,
+    },
+    RecipeActivated {
+        recipe_id: RecipeId,
+    },
+    Error {
+        /// Component which reports the error, e.g. a service name
+        source: String,
+        message: String,
+    },
+    /// Reported by frontends, e.g. when an operator acknowledges an alarm
+    UserAction {
+        action: String,
+        details: Option<String>,
+        /// Name of the authenticated user, if any
+        #[serde(default)]
+        user: Option<String>,
+    },
+    /// A threshold of the resource watchdog was exceeded, e.g. disk space is running low
+    ResourceExceeded {
+        resource: ResourceKind,
+        message: String,
+    },
+    /// Published by devices for each inspection result, so scripts and notifiers can react to it.
+    /// Devices with high frame rates should publish results only, as the history is limited
+    MeasurementReceived {
+        device_id: DeviceId,
+        /// Device specific, e.g. `{"width_mm": 12.1, "ok": true}`
+        values: serde_json::Value,
+    },
+}
+
+impl SystemEventKind {
+    /// Value of the `type` field in JSON
+    pub fn name(&self) -> &'static str {
+        match self {
+            SystemEventKind::DeviceStarted { .. } => "device_started",
+            SystemEventKind::DeviceStopped { .. } => "device_stopped",
+            SystemEventKind::RecipeActivated { .. } => "recipe_activated",
+            SystemEventKind::Error { .. } => "error",
+            SystemEventKind::UserAction { .. } => "user_action",
+            SystemEventKind::ResourceExceeded { .. } => "resource_exceeded",
+            SystemEventKind::MeasurementReceived { .. } => "measurement_received",
+        }
+    }
+
+    /// Events which indicate that something went wrong
+    pub fn is_error(&self) -> bool {
+        matches!(
+            self,
+            SystemEventKind::DeviceStopped { error: Some(_), .. }
+                | SystemEventKind::Error { .. }
+                | SystemEventKind::ResourceExceeded { .. }
+        )
+    }
+
+    pub fn device_id(&self) -> Option<DeviceId> {
+        match self {
+            SystemEventKind::DeviceStarted { device_id, .. }
+            | SystemEventKind::DeviceStopped { device_id, .. }
+            | SystemEventKind::MeasurementReceived { device_id, .. } => Some(*device_id),
+            _ => None,
+        }
+    }
+}
+
+/// Short human readable description, e.g. for notifications
+impl std::fmt::Display for SystemEventKind {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            SystemEventKind::DeviceStarted {
+                device_id,
+                device_type,
+            } => write!(f, "Device '{device_type}' with id '{device_id}' started"),
+            SystemEventKind::DeviceStopped {
+                device_id,
+                device_type,
+                error: None,
+            } => write!(f, "Device '{device_type}' with id '{device_id}' stopped"),
+            SystemEventKind::DeviceStopped {
+                device_id,
+                device_type,
+                error: Some(error),
+            } => write!(
+                f,
+                "Device '{device_type}' with id '{device_id}' failed: {error}"
+            ),
+            SystemEventKind::RecipeActivated { recipe_id } => {
+                write!(f, "Recipe '{recipe_id}' activated")
+            }
+            SystemEventKind::Error { source, message } => write!(f, "{source}: {message}"),
+            SystemEventKind::UserAction {
+                action,
+                details,
+                user,
+            } => {
+                write!(f, "User action '{action}'")?;
+                if let Some(user) = user {
+                    write!(f, " by '{user}'")?;
+                }
+                if let Some(details) = details {
+                    write!(f, ": {details}")?;
+                }
+                Ok(())
+            }
+            SystemEventKind::ResourceExceeded { resource, message } => {
+                write!(f, "Resource threshold exceeded for {resource:?}: {message}")
+            }
+            SystemEventKind::MeasurementReceived { device_id, values } => {
+                write!(f, "Device with id '{device_id}' measured {values}")
+            }
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct SystemEvent {
+    /// Increases with every event, also across restarts
+    pub id: u64,
+    pub time: DateTime<Utc>,
+    #[serde(flatten)]
+    pub kind: SystemEventKind,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct EventQuery {
+    /// Only events with a larger id, e.g. the last id a client knows about
+    pub after: Option<u64>,
+    pub since: Option<DateTime<Utc>>,
+    pub until: Option<DateTime<Utc>>,
+    /// Comma separated event types, e.g. `device_stopped,error`
+    pub types: Option<String>,
+    pub device_id: Option<DeviceId>,
+    /// Only the newest events are returned, if more events match
+    pub limit: Option<usize>,
+}
+
+impl EventQuery {
+    pub fn matches(&self, event: &SystemEvent) -> bool {
+        self.after.map_or(true, |after| event.id > after)
+            && self.since.map_or(true, |since| event.time >= since)
+            && self.until.map_or(true, |until| event.time <= until)
+            && self.types.as_deref().map_or(true, |types| {
+                types.split(',').any(|t| t.trim() == event.kind.name())
+            })
+            && self
+                .device_id
+                .map_or(true, |id| event.kind.device_id() == Some(id))
+    }
+}
+
+/// Cheap to clone, all clones share the same events
+#[derive(Clone)]
+pub struct EventBus(Arc<EventBusInner>);
+
+struct EventBusInner {
+    capacity: usize,
+    state: Mutex<EventBusState>,
+    live: broadcast::Sender<SystemEvent>,
+}
+
+struct EventBusState {
+    events: VecDeque<SystemEvent>,
+    next_id: u64,
+    /// Number of published events since creation. Allows to skip persisting if nothing changed
+    revision: u64,
+}
+
+impl Default for EventBus {
+    fn default() -> Self {
+        Self::new(DEFAULT_CAPACITY)
+    }
+}
+
+impl std::fmt::Debug for EventBus {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("EventBus")
+            .field("capacity", &self.0.capacity)
+            .finish_non_exhaustive()
+    }
+}
+
+impl EventBus {
+    /// Oldest events are dropped, if there are more than `capacity`
+    pub fn new(capacity: usize) -> Self {
+        Self::with_events(capacity, [])
+    }
+
+    /// Restores persisted events. Ids of new events continue after the last restored one
+    pub fn with_events(capacity: usize, events: impl IntoIterator<Item = SystemEvent>) -> Self {
+        let capacity = capacity.max(1);
+        let mut events = events.into_iter().collect::<VecDeque<_>>();
+        while events.len() > capacity {
+            events.pop_front();
+        }
+        let next_id = events.back().map_or(0, |e| e.id + 1);
+        Self(Arc::new(EventBusInner {
+            capacity,
+            state: Mutex::new(EventBusState {
+                events,
+                next_id,
+                revision: 0,
+            }),
+            live: broadcast::channel(64).0,
+        }))
+    }
+
+    pub fn publish(&self, kind: SystemEventKind) -> SystemEvent {
+        let mut state = self.0.state.lock().expect("Never poisoned");
+        let event = SystemEvent {
+            id: state.next_id,
+            time: Utc::now(),
+            kind,
+        };
+        state.next_id += 1;
+        state.revision += 1;
+        if state.events.len() == self.0.capacity {
+            state.events.pop_front();
+        }
+        state.events.push_back(event.clone());
+        // Sent while locked, so live subscribers receive events in the order of their ids
+        let _ignore_without_subscribers = self.0.live.send(event.clone());
+        event
+    }
+
+    /// Matching events, oldest first
+    pub fn query(&self, query: &EventQuery) -> Vec<SystemEvent> {
+        let state = self.0.state.lock().expect("Never poisoned");
+        let mut result = state
+            .events
+            .iter()
+            .rev()
+            .filter(|e| query.matches(e))
+            .take(query.limit.unwrap_or(usize::MAX))
+            .cloned()
+            .collect::<Vec<_>>();
+        result.reverse();
+        result
+    }
+
+    /// All events and the revision they belong to
+    pub fn snapshot(&self) -> (u64, Vec<SystemEvent>) {
+        let state = self.0.state.lock().expect("Never poisoned");
+        (state.revision, state.events.iter().cloned().collect())
+    }
+
+    /// Events published from now on. Slow subscribers skip events instead of blocking the publisher
+    pub fn subscribe(&self) -> BoxStream<'static, SystemEvent> {
+        futures::stream::unfold(self.0.live.subscribe(), |mut receiver| async move {
+            loop {
+                match receiver.recv().await {
+                    Ok(event) => return Some((event, receiver)),
+                    Err(RecvError::Lagged(_)) => continue,
+                    Err(RecvError::Closed) => return None,
+                }
+            }
+        })
+        .boxed()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn error(message: &str) -> SystemEventKind {
+        SystemEventKind::Error {
+            source: "test".into(),
+            message: message.into(),
+        }
+    }
+
+    #[test]
+    fn drop_oldest_events_and_continue_ids_after_restore() {
+        let bus = EventBus::new(2);
+        for i in 0..3 {
+            bus.publish(error(&i.to_string()));
+        }
+        let (revision, events) = bus.snapshot();
+        assert_eq!(3, revision);
+        assert_eq!(vec![1, 2], events.iter().map(|e| e.id).collect::<Vec<_>>());
+
+        let restored = EventBus::with_events(10, events);
+        assert_eq!(3, restored.publish(error("after restart")).id);
+    }
+
+    #[test]
+    fn query_by_type_device_and_limit() {
+        let bus = EventBus::default();
+        let device_id = DeviceId::new_v4();
+        bus.publish(SystemEventKind::DeviceStarted {
+            device_id,
+            device_type: "camera".into(),
+        });
+        bus.publish(error("first"));
+        bus.publish(error("second"));
+        bus.publish(SystemEventKind::DeviceStarted {
+            device_id: DeviceId::new_v4(),
+            device_type: "camera".into(),
+        });
+
+        let errors = bus.query(&EventQuery {
+            types: Some("error, user_action".into()),
+            limit: Some(1),
+            ..Default::default()
+        });
+        assert_eq!(
+            vec![error("second")],
+            errors.into_iter().map(|e| e.kind).collect::<Vec<_>>()
+        );
+
+        let device = bus.query(&EventQuery {
+            device_id: Some(device_id),
+            ..Default::default()
+        });
+        assert_eq!(vec![0], device.iter().map(|e| e.id).collect::<Vec<_>>());
+        assert_eq!(
+            3,
+            bus.query(&EventQuery {
+                after: Some(0),
+                ..Default::default()
+            })
+            .len()
+        );
+    }
+
+    #[test]
+    fn serialize_flat() {
+        let event = SystemEvent {
+            id: 1,
+            time: DateTime::default(),
+            kind: error("boom"),
+        };
+        let json = serde_json::to_value(&event).unwrap();
+        assert_eq!("error", json["type"]);
+        assert_eq!("boom", json["message"]);
+        assert_eq!(event, serde_json::from_value(json).unwrap());
+    }
+
+    #[tokio::test]
+    async fn subscribe_to_live_events() {
+        let bus = EventBus::default();
+        let mut live = bus.subscribe();
+        bus.publish(error("live"));
+        assert_eq!(error("live"), live.next().await.unwrap().kind);
+    }
+}
@@ -0,0 +1,184 @@
+use std::{
+    collections::{HashMap, HashSet},
+    sync::{Arc, RwLock},
+};
+
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    device::{DeviceId, SelfTestCheck},
+    CycleTimeStats, MaintenanceStatus, RecipeId,
+};
+
+/// Snapshot of the resources used by the process and the data directory
+/// Values are None, if they are not available on the current platform
+#[derive(Debug, Clone, Default, PartialEq, Serialize)]
+pub struct ResourceUsage {
+    pub cpu_percent: Option<f32>,
+    pub memory_bytes: Option<u64>,
+    pub disk_available_bytes: Option<u64>,
+    pub disk_total_bytes: Option<u64>,
+    pub open_file_descriptors: Option<u64>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ResourceKind {
+    Cpu,
+    Memory,
+    Disk,
+    FileDescriptors,
+}
+
+/// Actions which are activated by the resource watchdog while a threshold is exceeded
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ResourceAction {
+    PauseRecording,
+    RefuseSubscriptions,
+}
+
+/// Result of a single device. `error` is set, if the device didn't respond to the `SelfTestMessage` in time
+#[derive(Debug, Clone, Default, PartialEq, Serialize)]
+pub struct DeviceSelfTestResult {
+    pub checks: Vec<SelfTestCheck>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+}
+
+impl DeviceSelfTestResult {
+    pub fn is_passed(&self) -> bool {
+        self.error.is_none() && self.checks.iter().all(|c| c.passed || !c.mandatory)
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct SelfTestReport {
+    pub recipe_id: RecipeId,
+    pub finished: DateTime<Utc>,
+    pub devices: HashMap<DeviceId, DeviceSelfTestResult>,
+}
+
+impl SelfTestReport {
+    pub fn is_passed(&self) -> bool {
+        self.devices.values().all(DeviceSelfTestResult::is_passed)
+    }
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Serialize)]
+#[serde(rename_all = "snake_case", tag = "state")]
+pub enum SelfTestStatus {
+    #[default]
+    Disabled,
+    Running,
+    Finished(SelfTestReport),
+}
+
+/// Result of the last check of the system clock
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct TimeSyncStatus {
+    /// Where the status comes from, e.g. "chrony" or "ntp:pool.ntp.org:123"
+    pub source: String,
+    pub checked: DateTime<Utc>,
+    /// None, if the source doesn't report it
+    pub synchronized: Option<bool>,
+    /// Positive, if the local clock is ahead of the reference
+    pub offset_ms: Option<f64>,
+    /// The offset exceeds the configured maximum or the clock is not synchronized
+    pub drift_exceeded: bool,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+}
+
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct HealthReport {
+    pub usage: ResourceUsage,
+    pub exceeded: HashSet<ResourceKind>,
+    pub active_actions: HashSet<ResourceAction>,
+    pub self_test: SelfTestStatus,
+    /// None, if time synchronization isn't monitored. Drift is reported as a warning and doesn't affect health
+    pub time_sync: Option<TimeSyncStatus>,
+    /// Set while the system is in maintenance mode. The system isn't ready in the meantime
+    pub maintenance: Option<MaintenanceStatus>,
+    /// Devices with configured cycle times. An exceeded takt is reported as a warning and doesn't affect health
+    pub cycle_times: HashMap<DeviceId, CycleTimeStats>,
+}
+
+impl HealthReport {
+    pub fn is_healthy(&self) -> bool {
+        self.exceeded.is_empty()
+    }
+
+    /// Healthy, not in maintenance mode and all mandatory self-tests of the active recipe passed (if self-tests are enabled)
+    pub fn is_ready(&self) -> bool {
+        self.is_healthy()
+            && self.maintenance.is_none()
+            && match &self.self_test {
+                SelfTestStatus::Disabled => true,
+                SelfTestStatus::Running => false,
+                SelfTestStatus::Finished(report) => report.is_passed(),
+            }
+    }
+}
+
+/// Updated by the resource watchdog. Devices and services can check it before starting resource-hungry work
+#[derive(Debug, Clone, Default)]
+pub struct HealthState(Arc<RwLock<HealthReport>>);
+
+impl HealthState {
+    pub fn report(&self) -> HealthReport {
+        self.0.read().expect("Never poisoned").clone()
+    }
+
+    pub fn set_report(&self, report: HealthReport) {
+        *self.0.write().expect("Never poisoned") = report;
+    }
+
+    /// Allows multiple services to update their part of the report without overwriting each other
+    pub fn update(&self, f: impl FnOnce(&mut HealthReport)) {
+        f(&mut self.0.write().expect("Never poisoned"));
+    }
+
+    pub fn is_action_active(&self, action: ResourceAction) -> bool {
+        self.0
+            .read()
+            .expect("Never poisoned")
+            .active_actions
+            .contains(&action)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn optional_checks_dont_affect_readiness() {
+        let mut report = HealthReport::default();
+        assert!(report.is_ready());
+        report.self_test = SelfTestStatus::Running;
+        assert!(!report.is_ready());
+
+        let mut result = DeviceSelfTestResult {
+            checks: vec![
+                SelfTestCheck::passed("connected"),
+                SelfTestCheck::failed("temperature", "too hot").optional(),
+            ],
+            error: None,
+        };
+        let self_test = |result: &DeviceSelfTestResult| {
+            SelfTestStatus::Finished(SelfTestReport {
+                recipe_id: RecipeId::default(),
+                finished: Utc::now(),
+                devices: HashMap::from([(DeviceId::new_v4(), result.clone())]),
+            })
+        };
+        report.self_test = self_test(&result);
+        assert!(report.is_ready());
+
+        result.checks.push(SelfTestCheck::failed("focus", "blurry"));
+        report.self_test = self_test(&result);
+        assert!(!report.is_ready());
+    }
+}
@@ -37,6 +37,8 @@ where
     {
         let p =
             DepServiceProviderHandler::<HostedServiceResult, TDep, TFut>::new_boxed(name, handler);
+        self.0
+            .register_instance(crate::ServiceInfo::hosted_service::<TDep>(name));
         self.0.register_instance(HostedService::new(p))
     }
 }
@@ -53,6 +55,7 @@ impl ServiceCollectionExtensions for ServiceCollection {
         TFut: Future<Output = HostedServiceResult> + Send + 'static,
     {
         let p = NodepServiceProviderHandler::<HostedServiceResult, TFut>::new_boxed(name, handler);
+        self.register_instance(crate::ServiceInfo::hosted_service::<()>(name));
         self.register_instance(HostedService::new(p))
     }
 }
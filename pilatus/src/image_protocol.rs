@@ -0,0 +1,285 @@
+//! Wire types of the image websocket protocol
+//! They don't depend on any runtime, so frontends compiled to wasm can decode frames with the same definitions as the server
+
+use serde::{Deserialize, Serialize};
+
+#[derive(Default, Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "ts", derive(ts_rs::TS), ts(export))]
+pub enum StreamingImageFormat {
+    #[default]
+    Jpeg,
+    Raw,
+}
+
+/// Stored in the upper 4 bits of the first byte of each frame
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[repr(u8)]
+pub enum ImageFrameCode {
+    Ok = 0,
+    MissedItem = 1,
+    /// Frame contains the error message as meta and possibly the image which caused it
+    Processing = 2,
+    ActorError = 3,
+    /// Part of a frame which exceeded the negotiated message size. See [`ImageChunk`]
+    Chunk = 4,
+    /// Last frame of a subscription. Meta contains the hash of the new parameters or null. Clients should subscribe again
+    ProducerRestarted = 5,
+}
+
+impl ImageFrameCode {
+    pub const fn header_byte(self) -> u8 {
+        (self as u8) << 4
+    }
+
+    pub fn from_header_byte(byte: u8) -> Option<Self> {
+        Some(match byte >> 4 {
+            0 => Self::Ok,
+            1 => Self::MissedItem,
+            2 => Self::Processing,
+            3 => Self::ActorError,
+            4 => Self::Chunk,
+            5 => Self::ProducerRestarted,
+            _ => return None,
+        })
+    }
+}
+
+/// Pixel type in the header of raw images
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[repr(u8)]
+pub enum RawPixelKind {
+    U8 = 0,
+    U16 = 1,
+}
+
+impl RawPixelKind {
+    pub fn from_byte(byte: u8) -> Option<Self> {
+        match byte {
+            0 => Some(Self::U8),
+            1 => Some(Self::U16),
+            _ => None,
+        }
+    }
+
+    pub const fn bytes_per_pixel(self) -> usize {
+        match self {
+            Self::U8 => 1,
+            Self::U16 => 2,
+        }
+    }
+}
+
+/// Image sent with `StreamingImageFormat::Raw`
+///
+///                   | 1 | 2 | 3 | 4 | 5 | 6 | 7 | 8 |
+///                   |  zeros, until 8 byte aligned  |
+/// 0..1              |reserved|kind|  u16::LE chan  |
+/// 4..8              |      u32::LE_bytes of width   |
+/// 8..               |  pixels, u16 as little endian |
+#[derive(Debug, PartialEq, Eq)]
+pub struct RawImage<'a> {
+    pub pixel_kind: RawPixelKind,
+    pub channels: u16,
+    pub width: u32,
+    pub height: u32,
+    pub pixels: &'a [u8],
+}
+
+/// A frame split into its parts without decoding the images
+#[derive(Debug, PartialEq, Eq)]
+pub struct ImageFrame<'a> {
+    pub code: ImageFrameCode,
+    /// JSON encoded meta
+    pub meta: &'a [u8],
+    /// Size prefixed images in the requested order. Empty for frames without images
+    pub images: &'a [u8],
+}
+
+impl<'a> ImageFrame<'a> {
+    pub fn parse(frame: &'a [u8]) -> Option<Self> {
+        let code = ImageFrameCode::from_header_byte(*frame.first()?)?;
+        let meta_len = u32::from_le_bytes(frame.get(4..8)?.try_into().ok()?) as usize;
+        let meta = frame.get(8..8 + meta_len)?;
+        Some(Self {
+            code,
+            meta,
+            images: &frame[8 + meta_len..],
+        })
+    }
+
+    /// Decodes an image of [`Self::iter_images`], which was requested with `StreamingImageFormat::Raw`.
+    /// The alignment depends on the position within the frame, which is why this is a method of the frame
+    pub fn raw_image(&self, image: &'a [u8]) -> Option<RawImage<'a>> {
+        // meta starts right after the 8 byte header
+        let frame_start = (self.meta.as_ptr() as usize).checked_sub(8)?;
+        let offset = (image.as_ptr() as usize).checked_sub(frame_start)?;
+        let alignment = offset.next_multiple_of(8) - offset;
+        let header = image.get(alignment..alignment + 8)?;
+        let pixel_kind = RawPixelKind::from_byte(header[1])?;
+        let channels = u16::from_le_bytes(header[2..4].try_into().ok()?);
+        let width = u32::from_le_bytes(header[4..8].try_into().ok()?);
+        let pixels = &image[alignment + 8..];
+        let row_size = pixel_kind.bytes_per_pixel() * channels as usize * width as usize;
+        if row_size == 0 || pixels.len() % row_size != 0 {
+            return None;
+        }
+        Some(RawImage {
+            pixel_kind,
+            channels,
+            width,
+            height: (pixels.len() / row_size) as u32,
+            pixels,
+        })
+    }
+
+    /// Iterates over the images, which are `None` if the producer didn't provide a requested image
+    pub fn iter_images(&self) -> impl Iterator<Item = Option<&'a [u8]>> + 'a {
+        let mut rest = self.images;
+        std::iter::from_fn(move || {
+            let len = u32::from_le_bytes(rest.get(..4)?.try_into().ok()?) as usize;
+            let image = rest.get(4..4 + len)?;
+            rest = &rest[4 + len..];
+            Some((len > 0).then_some(image))
+        })
+    }
+}
+
+/// Subscribers which limit the message size receive larger frames split into chunks.
+///
+///                   | 1 | 2 | 3 | 4 | 5 | 6 | 7 | 8 |
+/// 0..1              |  Chunk code   |    reserved   |
+/// 1..4              |           reserved            |
+/// 4..8              |   u32::LE_bytes of sequence   |
+/// 8..12             | u16::LE index | u16::LE total |
+/// 12..              |            payload            |
+///
+/// All chunks of a frame share the sequence number, which increments with each chunked frame.
+/// Chunks are sent in order and without interleaving other messages, so clients append the payloads
+/// until `index + 1 == total` and parse the result like an unchunked frame (see [`ChunkAssembler`]).
+/// Frames which fit into a single message are never chunked.
+#[derive(Debug, PartialEq, Eq)]
+pub struct ImageChunk<'a> {
+    pub sequence: u32,
+    pub index: u16,
+    pub total: u16,
+    pub payload: &'a [u8],
+}
+
+impl<'a> ImageChunk<'a> {
+    pub const HEADER_SIZE: usize = 12;
+
+    pub fn parse(message: &'a [u8]) -> Option<Self> {
+        if ImageFrameCode::from_header_byte(*message.first()?)? != ImageFrameCode::Chunk {
+            return None;
+        }
+        let total = u16::from_le_bytes(message.get(10..12)?.try_into().ok()?);
+        let index = u16::from_le_bytes(message.get(8..10)?.try_into().ok()?);
+        (index < total).then_some(Self {
+            sequence: u32::from_le_bytes(message.get(4..8)?.try_into().ok()?),
+            index,
+            total,
+            payload: &message[Self::HEADER_SIZE..],
+        })
+    }
+
+    /// Splits `frame` into messages of at most `max_message_size` bytes. Chunks are larger if the frame
+    /// would need more than `u16::MAX` chunks otherwise
+    pub fn split(frame: &[u8], sequence: u32, max_message_size: usize) -> Vec<Vec<u8>> {
+        let payload_size = max_message_size
+            .saturating_sub(Self::HEADER_SIZE)
+            .max(frame.len().div_ceil(u16::MAX as usize))
+            .max(1);
+        let total = frame.len().div_ceil(payload_size).max(1) as u16;
+        let mut chunks = Vec::with_capacity(total as usize);
+        for index in 0..total {
+            let start = index as usize * payload_size;
+            let payload = &frame[start..(start + payload_size).min(frame.len())];
+            let mut chunk = Vec::with_capacity(Self::HEADER_SIZE + payload.len());
+            chunk.extend_from_slice(&[ImageFrameCode::Chunk.header_byte(), 0, 0, 0]);
+            chunk.extend_from_slice(&sequence.to_le_bytes());
+            chunk.extend_from_slice(&index.to_le_bytes());
+            chunk.extend_from_slice(&total.to_le_bytes());
+            chunk.extend_from_slice(payload);
+            chunks.push(chunk);
+        }
+        chunks
+    }
+}
+
+/// Reassembles chunked frames on the client side
+#[derive(Debug, Default)]
+pub struct ChunkAssembler {
+    sequence: Option<u32>,
+    next_index: u16,
+    frame: Vec<u8>,
+}
+
+impl ChunkAssembler {
+    /// Returns the frame once the last chunk arrived. Incomplete frames are dropped,
+    /// if a chunk of another frame arrives (e.g. after reconnecting)
+    pub fn push(&mut self, chunk: ImageChunk) -> Option<Vec<u8>> {
+        if self.sequence != Some(chunk.sequence) || self.next_index != chunk.index {
+            self.frame.clear();
+            if chunk.index != 0 {
+                self.sequence = None;
+                return None;
+            }
+            self.sequence = Some(chunk.sequence);
+        }
+        self.frame.extend_from_slice(chunk.payload);
+        self.next_index = chunk.index + 1;
+        if self.next_index == chunk.total {
+            self.sequence = None;
+            self.next_index = 0;
+            return Some(std::mem::take(&mut self.frame));
+        }
+        None
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_frame_with_images() {
+        let mut frame = vec![ImageFrameCode::Processing.header_byte(), 0, 0, 0];
+        frame.extend_from_slice(&4u32.to_le_bytes());
+        frame.extend_from_slice(b"\"e\"\0");
+        frame.extend_from_slice(&2u32.to_le_bytes());
+        frame.extend_from_slice(&[1, 2]);
+        frame.extend_from_slice(&0u32.to_le_bytes());
+
+        let parsed = ImageFrame::parse(&frame).unwrap();
+        assert_eq!(ImageFrameCode::Processing, parsed.code);
+        assert_eq!(b"\"e\"\0", parsed.meta);
+        assert_eq!(
+            vec![Some(&[1u8, 2][..]), None],
+            parsed.iter_images().collect::<Vec<_>>()
+        );
+        assert_eq!(None, ImageFrame::parse(&frame[..6]));
+    }
+
+    #[test]
+    fn split_and_reassemble_chunks() {
+        let frame = (0..100u8).collect::<Vec<_>>();
+        let chunks = ImageChunk::split(&frame, 7, ImageChunk::HEADER_SIZE + 30);
+        assert_eq!(4, chunks.len());
+        assert!(chunks
+            .iter()
+            .all(|x| x.len() <= ImageChunk::HEADER_SIZE + 30));
+
+        let mut assembler = ChunkAssembler::default();
+        // Leftovers of an interrupted frame are ignored
+        assert_eq!(
+            None,
+            assembler.push(ImageChunk::parse(&ImageChunk::split(&frame, 6, 50)[1]).unwrap())
+        );
+        let mut result = None;
+        for chunk in chunks.iter() {
+            assert_eq!(None, result);
+            result = assembler.push(ImageChunk::parse(chunk).unwrap());
+        }
+        assert_eq!(Some(frame), result);
+    }
+}
@@ -1,12 +1,22 @@
 mod config;
+mod cycle_time;
 pub mod device;
+mod diagnostics;
 mod entry_io;
 #[cfg(feature = "tokio")]
+mod events;
+#[cfg(feature = "tokio")]
 mod file;
+mod health;
 #[cfg(all(feature = "tokio", feature = "minfac"))]
 mod hosted_service;
+mod image_protocol;
+mod localization;
 mod logo;
+mod maintenance;
 mod name;
+#[cfg(feature = "minfac")]
+pub mod plugin;
 mod recipe;
 mod relative;
 mod settings;
@@ -14,17 +24,27 @@ mod shutdown;
 #[cfg(feature = "subscribe")]
 mod subscribe;
 mod sync;
+mod system_info;
 mod tracing;
+mod user;
 mod uuid_wrapper;
 
 pub use crate::config::GenericConfig;
 pub use crate::tracing::*;
+pub use cycle_time::*;
+pub use diagnostics::*;
 pub use entry_io::*;
 #[cfg(feature = "tokio")]
+pub use events::*;
+#[cfg(feature = "tokio")]
 pub use file::*;
+pub use health::*;
 #[cfg(all(feature = "tokio", feature = "minfac"))]
 pub use hosted_service::HostedService;
+pub use image_protocol::*;
+pub use localization::*;
 pub use logo::*;
+pub use maintenance::*;
 pub use name::*;
 pub use recipe::*;
 pub use relative::*;
@@ -33,9 +53,12 @@ pub use shutdown::*;
 #[cfg(feature = "subscribe")]
 pub use subscribe::*;
 pub use sync::*;
+pub use system_info::*;
+pub use user::*;
 
 #[cfg(all(feature = "tokio", feature = "minfac"))]
 pub mod prelude {
+    pub use crate::device::ActorErrorContextExtensions;
     pub use crate::device::ActorErrorResultExtensions;
     pub use crate::device::ServiceBuilderExtensions as DeviceServiceBuilderExtensions;
     pub use crate::hosted_service::ServiceBuilderExtensions as HostedServiceServiceServiceBuilderExtensions;
@@ -0,0 +1,170 @@
+//! Stable error codes and message catalogs, so frontends can present translated error messages
+//!
+//! Templates refer to the arguments of an error by name, e.g. `"Rezept {recipe_id} existiert bereits"`
+
+use std::collections::{BTreeMap, HashMap};
+
+use serde::{Deserialize, Serialize};
+
+/// Errors which are presented to users
+pub trait LocalizableError: std::fmt::Display {
+    /// Stable identifier in snake_case, which doesn't change if the english message is reworded
+    fn error_code(&self) -> &'static str;
+
+    /// Values which can be used in message templates
+    fn error_args(&self) -> BTreeMap<&'static str, String> {
+        BTreeMap::new()
+    }
+
+    fn to_localized(&self) -> LocalizedError {
+        LocalizedError {
+            code: self.error_code().into(),
+            message: self.to_string(),
+            args: self
+                .error_args()
+                .into_iter()
+                .map(|(k, v)| (k.into(), v))
+                .collect(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[cfg_attr(feature = "ts", derive(ts_rs::TS), ts(export))]
+pub struct LocalizedError {
+    pub code: String,
+    /// English message, used if no catalog contains a template for `code`
+    pub message: String,
+    pub args: BTreeMap<String, String>,
+}
+
+/// Templates per locale (e.g. "de" or "de-ch") and error code
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize)]
+#[serde(transparent)]
+pub struct MessageCatalog(HashMap<String, HashMap<String, String>>);
+
+impl<'de> Deserialize<'de> for MessageCatalog {
+    fn deserialize<D: serde::Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        HashMap::deserialize(deserializer).map(Self::new)
+    }
+}
+
+impl MessageCatalog {
+    pub fn new(locales: HashMap<String, HashMap<String, String>>) -> Self {
+        Self(
+            locales
+                .into_iter()
+                .map(|(locale, templates)| (locale.to_lowercase(), templates))
+                .collect(),
+        )
+    }
+
+    pub fn locale(&self, locale: &str) -> Option<&HashMap<String, String>> {
+        self.0.get(&locale.to_lowercase())
+    }
+
+    /// Message in the most preferred locale of an `Accept-Language` header, which has a template for the error
+    /// Returns the locale and the message
+    pub fn translate(
+        &self,
+        accept_language: &str,
+        error: &LocalizedError,
+    ) -> Option<(String, String)> {
+        preferred_locales(accept_language)
+            .into_iter()
+            .find_map(|locale| {
+                let template = self.0.get(&locale)?.get(&error.code)?;
+                Some((locale, render(template, &error.args)))
+            })
+    }
+}
+
+/// Locales ordered by their quality. Regional locales are followed by their language, e.g. `de-ch, de`
+/// Locales with `q=0` are not acceptable and therefore omitted
+pub fn preferred_locales(accept_language: &str) -> Vec<String> {
+    let mut weighted = accept_language
+        .split(',')
+        .filter_map(|entry| {
+            let mut parts = entry.split(';');
+            let tag = parts.next()?.trim().to_lowercase();
+            if tag.is_empty() || tag == "*" {
+                return None;
+            }
+            let quality = parts
+                .find_map(|p| p.trim().strip_prefix("q="))
+                .map_or(Some(1.), |q| q.trim().parse::<f32>().ok())?;
+            (quality > 0.).then_some((tag, quality))
+        })
+        .collect::<Vec<_>>();
+    weighted.sort_by(|(_, a), (_, b)| b.total_cmp(a));
+
+    let mut result = Vec::new();
+    for (tag, _) in weighted {
+        let language = tag.split('-').next().map(str::to_string);
+        for locale in std::iter::once(tag).chain(language) {
+            if !result.contains(&locale) {
+                result.push(locale);
+            }
+        }
+    }
+    result
+}
+
+fn render(template: &str, args: &BTreeMap<String, String>) -> String {
+    args.iter().fold(template.to_string(), |acc, (key, value)| {
+        acc.replace(&format!("{{{key}}}"), value)
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn order_locales_by_quality() {
+        assert_eq!(
+            vec!["fr", "de-ch", "de", "en"],
+            preferred_locales("de-CH;q=0.9, en;q=0.5, fr, *;q=0.1")
+        );
+    }
+
+    #[test]
+    fn omit_locales_with_zero_quality() {
+        assert_eq!(
+            vec!["de", "fr"],
+            preferred_locales("en;q=0, de, fr;q=0.2, it;q=0.000")
+        );
+    }
+
+    #[test]
+    fn lowercase_deserialized_locales() {
+        let catalog: MessageCatalog =
+            serde_json::from_str(r#"{"DE-CH": {"unknown_recipe_id": "Unbekannt"}}"#).unwrap();
+        assert!(catalog.locale("de-ch").is_some());
+        assert_eq!(
+            r#"{"de-ch":{"unknown_recipe_id":"Unbekannt"}}"#,
+            serde_json::to_string(&catalog).unwrap()
+        );
+    }
+
+    #[test]
+    fn translate_with_fallback_to_language() {
+        let catalog = MessageCatalog::new(HashMap::from([(
+            "DE".into(),
+            HashMap::from([(
+                "unknown_recipe_id".into(),
+                "Unbekanntes Rezept {recipe_id}".into(),
+            )]),
+        )]));
+        let error = LocalizedError {
+            code: "unknown_recipe_id".into(),
+            message: "Invalid recipe id foo".into(),
+            args: BTreeMap::from([("recipe_id".into(), "foo".into())]),
+        };
+        assert_eq!(
+            Some(("de".into(), "Unbekanntes Rezept foo".into())),
+            catalog.translate("fr, de-CH;q=0.8", &error)
+        );
+        assert_eq!(None, catalog.translate("fr", &error));
+    }
+}
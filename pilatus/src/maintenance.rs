@@ -0,0 +1,125 @@
+//! System-wide maintenance mode, which guarantees that nothing moves or changes during mechanical maintenance
+//!
+//! While it is enabled, the runtime pauses all devices handling [`PauseProducerMessage`](crate::device::PauseProducerMessage)
+//! and recipe mutations fail with [`MaintenanceError`]
+
+use std::collections::BTreeMap;
+
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+
+use crate::{LocalizableError, TransactionError};
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct MaintenanceStatus {
+    pub since: DateTime<Utc>,
+    /// E.g. "Replacing the conveyor belt"
+    pub reason: Option<String>,
+    /// Name of the authenticated user, who enabled the maintenance mode
+    pub user: Option<String>,
+}
+
+#[derive(Debug, thiserror::Error, PartialEq, Eq)]
+#[error("Rejected, as the system is in maintenance mode since {}{}", .0.since, .0.reason.as_ref().map(|r| format!(": {r}")).unwrap_or_default())]
+pub struct MaintenanceError(pub MaintenanceStatus);
+
+impl From<MaintenanceError> for TransactionError {
+    fn from(e: MaintenanceError) -> Self {
+        Self::Other(e.into())
+    }
+}
+
+impl LocalizableError for MaintenanceError {
+    fn error_code(&self) -> &'static str {
+        "maintenance_mode"
+    }
+
+    fn error_args(&self) -> BTreeMap<&'static str, String> {
+        BTreeMap::from([
+            ("since", self.0.since.to_rfc3339()),
+            ("reason", self.0.reason.clone().unwrap_or_default()),
+        ])
+    }
+}
+
+/// Cheap to clone. Services which change the machine check it with [`MaintenanceMode::ensure_inactive`]
+#[cfg(feature = "tokio")]
+#[derive(Debug, Clone)]
+pub struct MaintenanceMode(std::sync::Arc<tokio::sync::watch::Sender<Option<MaintenanceStatus>>>);
+
+#[cfg(feature = "tokio")]
+impl Default for MaintenanceMode {
+    fn default() -> Self {
+        Self::new(None)
+    }
+}
+
+#[cfg(feature = "tokio")]
+impl MaintenanceMode {
+    /// E.g. with the persisted status after a restart
+    pub fn new(status: Option<MaintenanceStatus>) -> Self {
+        Self(std::sync::Arc::new(tokio::sync::watch::channel(status).0))
+    }
+
+    /// None, if the system is operating normally
+    pub fn status(&self) -> Option<MaintenanceStatus> {
+        self.0.borrow().clone()
+    }
+
+    /// Keeps the original status, if the maintenance mode is enabled already
+    pub fn enable(&self, reason: Option<String>, user: Option<String>) -> MaintenanceStatus {
+        self.0.send_if_modified(|status| {
+            if status.is_some() {
+                return false;
+            }
+            *status = Some(MaintenanceStatus {
+                since: Utc::now(),
+                reason,
+                user,
+            });
+            true
+        });
+        self.status().expect("Enabled above")
+    }
+
+    /// Returns false, if the maintenance mode wasn't enabled
+    pub fn disable(&self) -> bool {
+        self.0.send_if_modified(|status| status.take().is_some())
+    }
+
+    pub fn ensure_inactive(&self) -> Result<(), MaintenanceError> {
+        match self.status() {
+            Some(status) => Err(MaintenanceError(status)),
+            None => Ok(()),
+        }
+    }
+
+    /// Notified whenever the maintenance mode is enabled or disabled
+    pub fn subscribe(&self) -> tokio::sync::watch::Receiver<Option<MaintenanceStatus>> {
+        self.0.subscribe()
+    }
+}
+
+#[cfg(all(test, feature = "tokio"))]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn notify_changes_only() {
+        let mode = MaintenanceMode::default();
+        let mut changes = mode.subscribe();
+        assert_eq!(Ok(()), mode.ensure_inactive());
+        assert!(!mode.disable());
+
+        let status = mode.enable(Some("Belt".into()), None);
+        assert!(changes.has_changed().unwrap());
+        changes.borrow_and_update();
+        assert_eq!(status, mode.enable(Some("Other".into()), None));
+        assert!(!changes.has_changed().unwrap());
+        assert_eq!(Err(MaintenanceError(status)), mode.ensure_inactive());
+
+        assert!(mode.disable());
+        assert!(changes.has_changed().unwrap());
+        assert_eq!(None, mode.status());
+    }
+}
@@ -1,9 +1,12 @@
 use std::fmt::{Debug, Display};
 
-use sealedstruct::{ValidationError, ValidationErrors, ValidationResultExtensions};
+use sealedstruct::ValidationErrors;
 use serde::{Deserialize, Serialize};
 
 pub(crate) mod name_wrapper;
+mod policy;
+
+pub use policy::*;
 
 #[derive(
     PartialEq, Eq, Debug, PartialOrd, Ord, Clone, Hash, sealedstruct::Seal, Serialize, Deserialize,
@@ -33,7 +36,9 @@ impl Name {
             (1, self.to_string())
         };
 
-        (base_number..).map(move |n| NameWrapper(NameRaw(format!("{base_name}_{n}"))))
+        let policy = NamePolicy::current();
+        (base_number..)
+            .map(move |n| NameWrapper(NameRaw(policy.with_suffix(&base_name, &format!("_{n}")))))
     }
 }
 
@@ -64,42 +69,6 @@ impl From<NameRaw> for String {
 
 impl sealedstruct::Validator for NameRaw {
     fn check(&self) -> sealedstruct::Result<()> {
-        let name = &self.0;
-        let mut result: sealedstruct::Result<()> = Ok(());
-        match name.len() {
-            0 => {
-                result = result.append_error(ValidationError::new("Empty name is not allowed"));
-            }
-            1..=30 => {}
-            _ => {
-                result =
-                    result.append_error(ValidationError::new(format!("(len={}) > 30", name.len())));
-            }
-        }
-
-        if name.starts_with(' ') {
-            result = result.append_error(ValidationError::new(format!(
-                "'{name}' is prefixed with whitespace"
-            )));
-        }
-
-        if name.ends_with(' ') {
-            result = result.append_error(ValidationError::new(format!(
-                "'{name}' is suffixed with whitespace"
-            )));
-        }
-
-        for c in name.chars() {
-            match c {
-                'a'..='z' | 'A'..='Z' | '0'..='9' | '-' | '_' | ' ' | '.' => continue,
-                illegal_char => {
-                    result = result.append_error(ValidationError::new(format!(
-                        "invalid character {illegal_char}"
-                    )));
-                }
-            }
-        }
-        result?;
-        Ok(())
+        NamePolicy::current().check(&self.0)
     }
 }
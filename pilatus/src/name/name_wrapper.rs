@@ -1,7 +1,10 @@
 macro_rules! wrapped_name {
     ($name:ident) => {
         #[derive(Clone, PartialEq, Eq, PartialOrd, Ord, Hash, Debug)]
-        pub struct $name(std::sync::Arc<crate::Name>);
+        #[cfg_attr(feature = "ts", derive(ts_rs::TS))]
+        pub struct $name(
+            #[cfg_attr(feature = "ts", ts(type = "string"))] std::sync::Arc<crate::Name>,
+        );
 
         impl $name {
             pub fn suggest_unique(&self) -> impl Iterator<Item = Self> {
@@ -0,0 +1,195 @@
+use std::sync::{Arc, OnceLock, RwLock};
+
+use sealedstruct::{ValidationError, ValidationResultExtensions};
+use serde::{Deserialize, Serialize};
+
+/// Filenames are limited to 255 bytes on most filesystems, independent of the configured policy
+const MAX_BYTES: usize = 255;
+
+/// Which letters are allowed in names in addition to ascii digits and `-`, `_`, `.` and ` `
+/// Characters which are unsafe in paths (e.g. `/`, `:`, control characters) are never allowed
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ScriptClass {
+    /// a-z and A-Z
+    Ascii,
+    /// Latin letters including diacritics (e.g. ä, é, ł)
+    Latin,
+    Greek,
+    Cyrillic,
+    Arabic,
+    Hebrew,
+    Devanagari,
+    Thai,
+    /// Han, Hiragana, Katakana and Hangul
+    Cjk,
+    /// Every alphanumeric unicode character
+    Any,
+}
+
+impl ScriptClass {
+    /// Ranges include combining marks (e.g. Devanagari vowel signs), which aren't alphanumeric on their own
+    fn contains(self, c: char) -> bool {
+        let x = c as u32;
+        match self {
+            ScriptClass::Ascii => c.is_ascii_alphabetic(),
+            ScriptClass::Latin => {
+                c.is_ascii_alphabetic()
+                    || (matches!(x, 0x00C0..=0x024F | 0x1E00..=0x1EFF) && c != '×' && c != '÷')
+            }
+            ScriptClass::Greek => matches!(x, 0x0370..=0x03FF | 0x1F00..=0x1FFF),
+            ScriptClass::Cyrillic => matches!(x, 0x0400..=0x052F),
+            ScriptClass::Arabic => matches!(x, 0x0600..=0x06FF | 0x0750..=0x077F),
+            ScriptClass::Hebrew => matches!(x, 0x0590..=0x05FF),
+            ScriptClass::Devanagari => matches!(x, 0x0900..=0x097F),
+            ScriptClass::Thai => matches!(x, 0x0E00..=0x0E7F),
+            ScriptClass::Cjk => matches!(
+                x,
+                0x3040..=0x30FF | 0x3400..=0x4DBF | 0x4E00..=0x9FFF | 0xAC00..=0xD7AF
+            ),
+            ScriptClass::Any => c.is_alphanumeric(),
+        }
+    }
+}
+
+/// Validation rules for [`crate::Name`]
+/// The process-wide policy is set once during startup (see `name_policy` in the config) and applies to all names
+/// validated afterwards.
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct NamePolicy {
+    /// Maximum number of characters
+    pub max_len: usize,
+    pub scripts: Vec<ScriptClass>,
+}
+
+impl Default for NamePolicy {
+    fn default() -> Self {
+        Self {
+            max_len: 30,
+            scripts: vec![ScriptClass::Ascii],
+        }
+    }
+}
+
+fn global() -> &'static RwLock<Arc<NamePolicy>> {
+    static POLICY: OnceLock<RwLock<Arc<NamePolicy>>> = OnceLock::new();
+    POLICY.get_or_init(Default::default)
+}
+
+impl NamePolicy {
+    pub fn current() -> Arc<NamePolicy> {
+        global().read().expect("Never poisoned").clone()
+    }
+
+    /// Should be called before any names are deserialized. Names which were valid under the previous policy stay valid.
+    pub fn set_global(policy: NamePolicy) {
+        *global().write().expect("Never poisoned") = Arc::new(policy);
+    }
+
+    pub fn check(&self, name: &str) -> sealedstruct::Result<()> {
+        let mut result: sealedstruct::Result<()> = Ok(());
+        let len = name.chars().count();
+        if len == 0 {
+            result = result.append_error(ValidationError::new("Empty name is not allowed"));
+        } else if len > self.max_len {
+            result = result.append_error(ValidationError::new(format!(
+                "(len={len}) > {}",
+                self.max_len
+            )));
+        }
+        if name.len() > MAX_BYTES {
+            result = result.append_error(ValidationError::new(format!(
+                "(bytes={}) > {MAX_BYTES}",
+                name.len()
+            )));
+        }
+
+        if name.starts_with(' ') {
+            result = result.append_error(ValidationError::new(format!(
+                "'{name}' is prefixed with whitespace"
+            )));
+        }
+
+        if name.ends_with(' ') {
+            result = result.append_error(ValidationError::new(format!(
+                "'{name}' is suffixed with whitespace"
+            )));
+        }
+
+        if !name.is_empty() && name.chars().all(|c| c == '.') {
+            result = result.append_error(ValidationError::new(format!(
+                "'{name}' is not a valid path segment"
+            )));
+        }
+
+        for c in name.chars() {
+            match c {
+                '0'..='9' | '-' | '_' | ' ' | '.' => continue,
+                c if self.scripts.iter().any(|s| s.contains(c)) => continue,
+                illegal_char => {
+                    result = result.append_error(ValidationError::new(format!(
+                        "invalid character {illegal_char}"
+                    )));
+                }
+            }
+        }
+        result
+    }
+
+    /// Shortens `base` so `{base}{suffix}` doesn't exceed the limits
+    pub(super) fn with_suffix(&self, base: &str, suffix: &str) -> String {
+        let max_chars = self.max_len.saturating_sub(suffix.chars().count());
+        let max_bytes = MAX_BYTES.saturating_sub(suffix.len());
+        let mut bytes = 0;
+        let truncated: String = base
+            .chars()
+            .take(max_chars)
+            .take_while(|c| {
+                bytes += c.len_utf8();
+                bytes <= max_bytes
+            })
+            .collect();
+        format!("{}{suffix}", truncated.trim_end())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn default_policy_rejects_unicode() {
+        let policy = NamePolicy::default();
+        assert!(policy.check("Recipe 1.2_a-b").is_ok());
+        assert!(policy.check("Rezept Größe").is_err());
+        assert!(policy.check(&"a".repeat(31)).is_err());
+        assert!(policy.check("..").is_err());
+    }
+
+    #[test]
+    fn configured_policy_allows_scripts_but_no_path_separators() {
+        let policy = NamePolicy {
+            max_len: 40,
+            scripts: vec![ScriptClass::Latin, ScriptClass::Cjk, ScriptClass::Cyrillic],
+        };
+        assert!(policy.check("Rezept Größe").is_ok());
+        assert!(policy.check("配方 Рецепт").is_ok());
+        assert!(policy.check(&"ä".repeat(40)).is_ok());
+        assert!(policy.check(&"ä".repeat(41)).is_err());
+        assert!(policy.check("a/b").is_err());
+        assert!(policy.check("a\\b").is_err());
+        assert!(policy.check("a:b").is_err());
+        assert!(policy.check("مرحبا").is_err());
+    }
+
+    #[test]
+    fn suffix_respects_limit() {
+        let policy = NamePolicy {
+            max_len: 6,
+            scripts: vec![ScriptClass::Latin],
+        };
+        assert_eq!("abc_12", policy.with_suffix("abcdef", "_12"));
+        assert_eq!("äbc_1", policy.with_suffix("äbc d", "_1"));
+    }
+}
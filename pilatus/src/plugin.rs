@@ -0,0 +1,72 @@
+//! Device libraries which are loaded at runtime (see `Runtime::load_plugins` in pilatus-rt).
+//! Rust has no stable ABI, so a plugin must be built with the same compiler and pilatus version as the host.
+//! Both are checked during the handshake before `register` is called.
+
+use std::{ffi::CStr, path::PathBuf};
+
+pub use minfac::ServiceCollection;
+use serde::Serialize;
+
+/// Increased whenever the exported plugin symbols change
+pub const PLUGIN_ABI_VERSION: u32 = 2;
+
+pub const PILATUS_VERSION: &CStr =
+    match CStr::from_bytes_with_nul(concat!(env!("CARGO_PKG_VERSION"), "\0").as_bytes()) {
+        Ok(x) => x,
+        Err(_) => panic!("CARGO_PKG_VERSION contains a nul byte"),
+    };
+
+/// Output of `rustc --version` of the compiler which built pilatus
+pub const RUSTC_VERSION: &CStr =
+    match CStr::from_bytes_with_nul(concat!(env!("PILATUS_RUSTC_VERSION"), "\0").as_bytes()) {
+        Ok(x) => x,
+        Err(_) => panic!("PILATUS_RUSTC_VERSION contains a nul byte"),
+    };
+
+/// Registered by the plugin loader for each successfully loaded plugin
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct LoadedPlugin {
+    /// File name without extension
+    pub name: String,
+    pub path: PathBuf,
+}
+
+pub const ABI_VERSION_SYMBOL: &[u8] = b"pilatus_plugin_abi_version\0";
+pub const PILATUS_VERSION_SYMBOL: &[u8] = b"pilatus_plugin_pilatus_version\0";
+pub const RUSTC_VERSION_SYMBOL: &[u8] = b"pilatus_plugin_rustc_version\0";
+pub const REGISTER_SYMBOL: &[u8] = b"pilatus_plugin_register\0";
+
+/// Exports the symbols expected by the plugin loader. Use it in a crate with `crate-type = ["cdylib"]`
+///
+/// ```ignore
+/// extern "C" fn register(c: &mut minfac::ServiceCollection) { ... }
+/// pilatus::export_plugin!(register);
+/// ```
+/// Panics within `register` are caught, so a faulty plugin doesn't bring down the host
+#[macro_export]
+macro_rules! export_plugin {
+    ($register:path) => {
+        #[no_mangle]
+        pub extern "C" fn pilatus_plugin_abi_version() -> u32 {
+            $crate::plugin::PLUGIN_ABI_VERSION
+        }
+
+        #[no_mangle]
+        pub extern "C" fn pilatus_plugin_pilatus_version() -> *const ::std::ffi::c_char {
+            $crate::plugin::PILATUS_VERSION.as_ptr()
+        }
+
+        #[no_mangle]
+        pub extern "C" fn pilatus_plugin_rustc_version() -> *const ::std::ffi::c_char {
+            $crate::plugin::RUSTC_VERSION.as_ptr()
+        }
+
+        #[no_mangle]
+        pub extern "C" fn pilatus_plugin_register(
+            collection: &mut $crate::plugin::ServiceCollection,
+        ) -> bool {
+            ::std::panic::catch_unwind(::std::panic::AssertUnwindSafe(|| $register(collection)))
+                .is_ok()
+        }
+    };
+}
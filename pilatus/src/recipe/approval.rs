@@ -0,0 +1,158 @@
+use serde::{Deserialize, Serialize};
+
+use crate::{LocalizableError, RecipeId, Role, TransactionError};
+
+/// Lifecycle of a recipe. Installations configured with `"production": true` only activate released or locked recipes
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[cfg_attr(feature = "ts", derive(ts_rs::TS))]
+#[serde(rename_all = "snake_case")]
+pub enum ApprovalState {
+    #[default]
+    Draft,
+    Review,
+    Released,
+    /// Released and protected against any changes until an admin unlocks it
+    Locked,
+}
+
+impl ApprovalState {
+    pub fn is_draft(&self) -> bool {
+        *self == ApprovalState::Draft
+    }
+
+    /// Production installations are allowed to activate the recipe
+    pub fn is_released(&self) -> bool {
+        matches!(self, ApprovalState::Released | ApprovalState::Locked)
+    }
+
+    /// Role needed to move from `self` to `to`. None, if the transition is not allowed at all
+    pub fn required_role(self, to: ApprovalState) -> Option<Role> {
+        use ApprovalState::*;
+        match (self, to) {
+            (Draft, Review) | (Review, Draft) => Some(Role::Operator),
+            (Review, Released) | (Released, Draft) | (Released, Locked) | (Locked, Released) => {
+                Some(Role::Admin)
+            }
+            _ => None,
+        }
+    }
+
+    /// Checks whether `role` may move the recipe from `self` to `to`
+    pub fn check_transition(
+        self,
+        recipe_id: &RecipeId,
+        to: ApprovalState,
+        role: Option<Role>,
+    ) -> Result<(), ApprovalError> {
+        let required = self
+            .required_role(to)
+            .ok_or(ApprovalError::InvalidTransition { from: self, to })?;
+        if role.map_or(true, |r| r < required) {
+            return Err(ApprovalError::MissingRole {
+                recipe_id: recipe_id.clone(),
+                required,
+            });
+        }
+        Ok(())
+    }
+}
+
+#[derive(Debug, thiserror::Error, PartialEq, Eq)]
+pub enum ApprovalError {
+    #[error("Recipe state can't change from {from:?} to {to:?}")]
+    InvalidTransition {
+        from: ApprovalState,
+        to: ApprovalState,
+    },
+    #[error("Changing the state of recipe {recipe_id} requires the role {required:?}")]
+    MissingRole { recipe_id: RecipeId, required: Role },
+    #[error("Recipe {0} is not released and can't be activated on production installations")]
+    NotReleased(RecipeId),
+    #[error("Recipe {0} is released. Move it back to draft or duplicate it to change it")]
+    Released(RecipeId),
+    #[error("Recipe {0} is locked and can't be changed")]
+    Locked(RecipeId),
+}
+
+impl From<ApprovalError> for TransactionError {
+    fn from(e: ApprovalError) -> Self {
+        Self::Other(e.into())
+    }
+}
+
+impl LocalizableError for ApprovalError {
+    fn error_code(&self) -> &'static str {
+        match self {
+            ApprovalError::InvalidTransition { .. } => "recipe_invalid_transition",
+            ApprovalError::MissingRole { .. } => "recipe_missing_role",
+            ApprovalError::NotReleased(_) => "recipe_not_released",
+            ApprovalError::Released(_) => "recipe_released",
+            ApprovalError::Locked(_) => "recipe_locked",
+        }
+    }
+
+    fn error_args(&self) -> std::collections::BTreeMap<&'static str, String> {
+        match self {
+            ApprovalError::InvalidTransition { from, to } => [
+                ("from", format!("{from:?}").to_lowercase()),
+                ("to", format!("{to:?}").to_lowercase()),
+            ]
+            .into(),
+            ApprovalError::MissingRole {
+                recipe_id,
+                required,
+            } => [
+                ("recipe_id", recipe_id.to_string()),
+                ("role", format!("{required:?}").to_lowercase()),
+            ]
+            .into(),
+            ApprovalError::NotReleased(id)
+            | ApprovalError::Released(id)
+            | ApprovalError::Locked(id) => [("recipe_id", id.to_string())].into(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn releasing_requires_admin() {
+        let id = RecipeId::default();
+        assert_eq!(
+            Ok(()),
+            ApprovalState::Draft.check_transition(&id, ApprovalState::Review, Some(Role::Operator))
+        );
+        assert_eq!(
+            Err(ApprovalError::MissingRole {
+                recipe_id: id.clone(),
+                required: Role::Admin
+            }),
+            ApprovalState::Review.check_transition(
+                &id,
+                ApprovalState::Released,
+                Some(Role::Operator)
+            )
+        );
+        assert_eq!(
+            Ok(()),
+            ApprovalState::Review.check_transition(&id, ApprovalState::Released, Some(Role::Admin))
+        );
+    }
+
+    #[test]
+    fn drafts_cant_be_released_directly() {
+        assert_eq!(
+            Err(ApprovalError::InvalidTransition {
+                from: ApprovalState::Draft,
+                to: ApprovalState::Released
+            }),
+            ApprovalState::Draft.check_transition(
+                &RecipeId::default(),
+                ApprovalState::Released,
+                Some(Role::Admin)
+            )
+        );
+    }
+}
@@ -1,6 +1,9 @@
-use std::any::Any;
+use std::{any::Any, collections::BTreeMap};
 
-use crate::device::{ActorError, ActorMessage};
+use crate::{
+    device::{ActorError, ActorMessage},
+    LocalizableError,
+};
 
 #[derive(thiserror::Error, Debug)]
 pub enum UpdateParamsMessageError {
@@ -17,12 +20,130 @@ pub enum UpdateParamsMessageError {
     File(String),
     #[error("VariableError: {0}")]
     VariableError(String),
+    #[error("Unknown field '{field}'{}", did_you_mean(.suggestion))]
+    UnknownField {
+        field: String,
+        suggestion: Option<String>,
+    },
+    #[error("Missing field '{field}'{}", did_you_mean(.suggestion))]
+    MissingField {
+        field: String,
+        suggestion: Option<String>,
+    },
+}
+
+fn did_you_mean(suggestion: &Option<String>) -> String {
+    suggestion
+        .as_ref()
+        .map(|x| format!(", did you mean '{x}'?"))
+        .unwrap_or_default()
+}
+
+impl UpdateParamsMessageError {
+    /// Unknown and missing fields are usually caused by renamed fields. They get a dedicated variant
+    /// with the most similar field as suggestion. `present` are the fields of the params which failed to deserialize
+    pub fn from_params_error<'a>(
+        e: serde_json::Error,
+        present: impl Iterator<Item = &'a str>,
+    ) -> Self {
+        let msg = e.to_string();
+        if let Some((field, expected)) = msg
+            .strip_prefix("unknown field `")
+            .and_then(|x| x.split_once('`'))
+        {
+            // e.g. ", expected one of `exposure`, `gain`"
+            let expected = expected.split('`').skip(1).step_by(2);
+            return Self::UnknownField {
+                suggestion: most_similar(field, expected),
+                field: field.into(),
+            };
+        }
+        if let Some((field, _)) = msg
+            .strip_prefix("missing field `")
+            .and_then(|x| x.split_once('`'))
+        {
+            return Self::MissingField {
+                suggestion: most_similar(field, present),
+                field: field.into(),
+            };
+        }
+        Self::InvalidFormat(e)
+    }
+}
+
+fn most_similar<'a>(field: &str, candidates: impl Iterator<Item = &'a str>) -> Option<String> {
+    let max_distance = (field.chars().count() / 3).max(2);
+    candidates
+        .filter(|x| *x != field)
+        .map(|x| (edit_distance(field, x), x))
+        .filter(|(d, _)| *d <= max_distance)
+        .min_by_key(|(d, _)| *d)
+        .map(|(_, x)| x.to_string())
+}
+
+/// Levenshtein distance
+fn edit_distance(a: &str, b: &str) -> usize {
+    let b = b.chars().collect::<Vec<_>>();
+    let mut row = (0..=b.len()).collect::<Vec<_>>();
+    for (i, ca) in a.chars().enumerate() {
+        let mut diagonal = row[0];
+        row[0] = i + 1;
+        for (j, cb) in b.iter().enumerate() {
+            let above = row[j + 1];
+            row[j + 1] = (above + 1)
+                .min(row[j] + 1)
+                .min(diagonal + (ca != *cb) as usize);
+            diagonal = above;
+        }
+    }
+    row[b.len()]
 }
 
 #[derive(thiserror::Error, Debug)]
 #[error("Not applied: {0}")]
 pub struct NotAppliedError(#[from] pub anyhow::Error);
 
+impl LocalizableError for UpdateParamsMessageError {
+    fn error_code(&self) -> &'static str {
+        match self {
+            UpdateParamsMessageError::InvalidField { .. } => "invalid_field",
+            UpdateParamsMessageError::InvalidFormat(_) => "invalid_format",
+            UpdateParamsMessageError::ValidationError(_) => "validation_error",
+            UpdateParamsMessageError::NotApplied(_) => "params_not_applied",
+            UpdateParamsMessageError::File(_) => "file_error",
+            UpdateParamsMessageError::VariableError(_) => "variable_error",
+            UpdateParamsMessageError::UnknownField { .. } => "unknown_field",
+            UpdateParamsMessageError::MissingField { .. } => "missing_field",
+        }
+    }
+
+    fn error_args(&self) -> BTreeMap<&'static str, String> {
+        match self {
+            UpdateParamsMessageError::InvalidField { path, message } => {
+                BTreeMap::from([("path", path.to_string()), ("reason", message.clone())])
+            }
+            UpdateParamsMessageError::InvalidFormat(e) => {
+                BTreeMap::from([("reason", e.to_string())])
+            }
+            UpdateParamsMessageError::ValidationError(e) => {
+                BTreeMap::from([("reason", format!("{e:?}"))])
+            }
+            UpdateParamsMessageError::NotApplied(e) => BTreeMap::from([("reason", e.to_string())]),
+            UpdateParamsMessageError::File(e) | UpdateParamsMessageError::VariableError(e) => {
+                BTreeMap::from([("reason", e.clone())])
+            }
+            UpdateParamsMessageError::UnknownField { field, suggestion }
+            | UpdateParamsMessageError::MissingField { field, suggestion } => {
+                let mut args = BTreeMap::from([("field", field.clone())]);
+                if let Some(suggestion) = suggestion {
+                    args.insert("suggestion", suggestion.clone());
+                }
+                args
+            }
+        }
+    }
+}
+
 impl From<NotAppliedError> for UpdateParamsMessageError {
     fn from(e: NotAppliedError) -> Self {
         UpdateParamsMessageError::NotApplied(e.0)
@@ -49,3 +170,45 @@ impl<T> UpdateParamsMessage<T> {
         Self { params }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use serde::Deserialize;
+
+    use super::*;
+
+    #[derive(Debug, Deserialize)]
+    #[serde(deny_unknown_fields)]
+    #[allow(dead_code)]
+    struct Params {
+        exposure: u32,
+        gain: u32,
+    }
+
+    fn parse(value: serde_json::Value) -> UpdateParamsMessageError {
+        let present = value
+            .as_object()
+            .unwrap()
+            .keys()
+            .cloned()
+            .collect::<Vec<_>>();
+        let e = serde_json::from_value::<Params>(value).unwrap_err();
+        UpdateParamsMessageError::from_params_error(e, present.iter().map(String::as_str))
+    }
+
+    #[test]
+    fn suggest_similar_fields() {
+        let e = parse(serde_json::json!({ "exposure": 1, "gian": 2 }));
+        assert_eq!("Unknown field 'gian', did you mean 'gain'?", e.to_string());
+        assert_eq!("unknown_field", e.error_code());
+
+        let e = parse(serde_json::json!({ "exposure": 1, "temperature": 2 }));
+        assert!(matches!(
+            e,
+            UpdateParamsMessageError::UnknownField {
+                suggestion: None,
+                ..
+            }
+        ));
+    }
+}
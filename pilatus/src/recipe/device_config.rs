@@ -1,19 +1,64 @@
+use std::collections::BTreeMap;
+
 use serde::{Deserialize, Serialize};
 
-use crate::{Name, TransactionError, UntypedDeviceParamsWithVariables};
+use crate::{
+    DeviceGroupId, DeviceResources, LocalizableError, Name, TransactionError,
+    UntypedDeviceParamsWithVariables,
+};
 
 #[derive(Debug, PartialEq, Eq, Clone, Deserialize, Serialize)]
+#[cfg_attr(feature = "ts", derive(ts_rs::TS))]
 #[serde(deny_unknown_fields)]
 pub struct DeviceConfig {
     pub device_type: String,
+    #[cfg_attr(feature = "ts", ts(type = "string"))]
     pub device_name: Name,
     pub params: UntypedDeviceParamsWithVariables,
 
+    /// Devices for real hardware switch to their internal emulation, if they support it.
+    /// Changes take effect when the device is started the next time
+    #[serde(default)]
+    pub simulated: bool,
+
+    /// Disabled devices are not started, but keep their configuration and files
+    #[serde(default = "enabled_by_default")]
+    pub enabled: bool,
+
+    /// Locked devices can only be changed or deleted with the unlock token (see [`crate::TransactionOptions::with_unlock_token`])
+    #[serde(default)]
+    pub locked: bool,
+
+    /// Free-text documentation, e.g. why a parameter deviates on this line
+    /// Limited to [`crate::MAX_DESCRIPTION_LEN`] characters
+    #[serde(default, skip_serializing_if = "String::is_empty")]
+    pub notes: String,
+
+    /// Must reference a group of the recipe containing this device
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    #[cfg_attr(feature = "ts", ts(optional))]
+    pub group: Option<DeviceGroupId>,
+
+    /// Checked against the machine capacity when the recipe is activated
+    #[serde(default, skip_serializing_if = "DeviceResources::is_empty")]
+    pub resources: DeviceResources,
+
+    /// Fields which must be set before the recipe can be activated, with the value they had in the template.
+    /// Keys are JSON pointers. Placeholders are filled as soon as the value of the field differs
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    #[cfg_attr(feature = "ts", ts(type = "Record<string, unknown>"))]
+    placeholders: BTreeMap<String, serde_json::Value>,
+
     /// Stores the original Parameters if parameters are saved uncommitted
     #[serde(skip_serializing_if = "Option::is_none")]
+    #[cfg_attr(feature = "ts", ts(optional))]
     committed_params: Option<UntypedDeviceParamsWithVariables>,
 }
 
+fn enabled_by_default() -> bool {
+    true
+}
+
 #[derive(thiserror::Error, Debug)]
 #[error("No committed configuration found")]
 pub struct NoCommittedConfigurationFound;
@@ -34,6 +79,13 @@ impl DeviceConfig {
             device_type: device_type.into(),
             device_name,
             params: UntypedDeviceParamsWithVariables::from_serializable(&params)?,
+            simulated: false,
+            enabled: true,
+            locked: false,
+            notes: String::new(),
+            group: None,
+            resources: Default::default(),
+            placeholders: Default::default(),
             committed_params: None,
         })
     }
@@ -45,6 +97,50 @@ impl DeviceConfig {
         }
     }
 
+    pub fn with_simulated(self, simulated: bool) -> Self {
+        Self { simulated, ..self }
+    }
+
+    pub fn with_enabled(self, enabled: bool) -> Self {
+        Self { enabled, ..self }
+    }
+
+    pub fn with_locked(self, locked: bool) -> Self {
+        Self { locked, ..self }
+    }
+
+    pub fn with_notes(self, notes: impl Into<String>) -> Self {
+        Self {
+            notes: notes.into(),
+            ..self
+        }
+    }
+
+    pub fn with_resources(self, resources: DeviceResources) -> Self {
+        Self { resources, ..self }
+    }
+
+    /// Marks the field at `pointer` (e.g. `/ip`) as must-be-set. Used by `InitRecipeListener`s for defaults without a sensible value
+    pub fn with_placeholder(mut self, pointer: impl Into<String>) -> Self {
+        let pointer = pointer.into();
+        let template = self.params.pointer(&pointer).cloned().unwrap_or_default();
+        self.placeholders.insert(pointer, template);
+        self
+    }
+
+    /// JSON pointers of fields which still have their template value
+    pub fn unfilled_placeholders(&self) -> impl Iterator<Item = &str> {
+        self.placeholders
+            .iter()
+            .filter(|(pointer, template)| {
+                self.params
+                    .pointer(pointer)
+                    .unwrap_or(&serde_json::Value::Null)
+                    == *template
+            })
+            .map(|(pointer, _)| pointer.as_str())
+    }
+
     pub fn new_unchecked(
         device_type: impl Into<String>,
         device_name: impl Into<String>,
@@ -70,6 +166,11 @@ impl DeviceConfig {
         }
     }
 
+    /// True if parameters were saved uncommitted since the last commit
+    pub fn has_uncommitted_params(&self) -> bool {
+        self.committed_params.is_some()
+    }
+
     pub fn restore_committed(
         &mut self,
     ) -> Result<&UntypedDeviceParamsWithVariables, NoCommittedConfigurationFound> {
@@ -92,11 +193,45 @@ impl DeviceConfig {
             device_type: "testdevice".into(),
             device_name: Name::new("testdevicename").unwrap(),
             params: UntypedDeviceParamsWithVariables::from_serializable(&params).unwrap(),
+            simulated: false,
+            enabled: true,
+            locked: false,
+            notes: String::new(),
+            group: None,
+            resources: Default::default(),
+            placeholders: Default::default(),
             committed_params: None,
         }
     }
 }
 
+#[derive(thiserror::Error, Debug, PartialEq, Eq)]
+#[error("Device '{device}' requires values for {}", fields.join(", "))]
+pub struct UnfilledPlaceholdersError {
+    pub device: Name,
+    pub fields: Vec<String>,
+}
+
+impl From<UnfilledPlaceholdersError> for TransactionError {
+    fn from(e: UnfilledPlaceholdersError) -> Self {
+        Self::Other(e.into())
+    }
+}
+
+impl LocalizableError for UnfilledPlaceholdersError {
+    fn error_code(&self) -> &'static str {
+        "unfilled_placeholders"
+    }
+
+    fn error_args(&self) -> BTreeMap<&'static str, String> {
+        [
+            ("device", self.device.to_string()),
+            ("fields", self.fields.join(", ")),
+        ]
+        .into()
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -112,11 +247,33 @@ mod tests {
             UntypedDeviceParamsWithVariables::from_serializable(10).unwrap(),
         );
         assert_eq!(Some(10), p.params.0.as_i64());
+        assert!(p.has_uncommitted_params());
         p.restore_committed().unwrap();
         assert_eq!(Some(1), p.params.0.as_i64());
+        assert!(!p.has_uncommitted_params());
         assert!(p.restore_committed().is_err());
     }
 
+    #[test]
+    fn configs_without_simulated_flag_use_hardware() {
+        let config = serde_json::to_value(DeviceConfig::mock(1)).unwrap();
+        let mut object = config.as_object().unwrap().clone();
+        object.remove("simulated");
+        let config: DeviceConfig = serde_json::from_value(object.into()).unwrap();
+        assert!(!config.simulated);
+        assert!(config.with_simulated(true).simulated);
+    }
+
+    #[test]
+    fn configs_without_enabled_flag_are_enabled() {
+        let config = serde_json::to_value(DeviceConfig::mock(1)).unwrap();
+        let mut object = config.as_object().unwrap().clone();
+        object.remove("enabled");
+        let config: DeviceConfig = serde_json::from_value(object.into()).unwrap();
+        assert!(config.enabled);
+        assert!(!config.with_enabled(false).enabled);
+    }
+
     #[test]
     fn test_read_write_params() {
         #[derive(Serialize, Deserialize)]
@@ -138,4 +295,26 @@ mod tests {
         assert_eq!(p.foo, "hallo".to_string());
         assert_eq!(p.bar, 9);
     }
+
+    #[test]
+    fn placeholders_are_filled_by_changing_their_value() {
+        let mut device =
+            DeviceConfig::new_unchecked("camera", "Camera", serde_json::json!({ "ip": "" }))
+                .with_placeholder("/ip");
+        assert_eq!(
+            vec!["/ip"],
+            device.unfilled_placeholders().collect::<Vec<_>>()
+        );
+
+        device.update_params_uncommitted(
+            UntypedDeviceParamsWithVariables::from_serializable(
+                serde_json::json!({ "ip": "192.168.1.10" }),
+            )
+            .unwrap(),
+        );
+        assert_eq!(0, device.unfilled_placeholders().count());
+
+        device.restore_committed().unwrap();
+        assert_eq!(1, device.unfilled_placeholders().count());
+    }
 }
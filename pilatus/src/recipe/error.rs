@@ -1,8 +1,12 @@
+use std::collections::BTreeMap;
 use std::fmt::Debug;
 use std::io;
 use std::path::{Path, PathBuf};
 
-use crate::{RecipeId, UnknownDeviceError, UpdateParamsMessageError};
+use crate::{
+    AdmissionError, ApprovalError, DeviceLockedError, LocalizableError, MaintenanceError, RecipeId,
+    UnfilledPlaceholdersError, UnknownDeviceError, UpdateParamsMessageError,
+};
 use sealedstruct::ValidationErrors;
 
 #[derive(thiserror::Error, Debug)]
@@ -46,6 +50,76 @@ impl TransactionError {
         TransactionError::Other(e.into())
     }
 }
+impl LocalizableError for TransactionError {
+    fn error_code(&self) -> &'static str {
+        match self {
+            TransactionError::RecipeAlreadyExists(_) => "recipe_already_exists",
+            TransactionError::UnknownRecipeId(_) => "unknown_recipe_id",
+            TransactionError::UnknownDevice(_) => "unknown_device",
+            TransactionError::UnknownFilePath(_) => "unknown_file_path",
+            TransactionError::FileSystemError(_) => "file_system_error",
+            TransactionError::InvalidDeviceConfig(_) => "invalid_device_config",
+            TransactionError::InvalidVariable(_) => "invalid_variable",
+            TransactionError::Other(e) => {
+                if e.is::<DeviceLockedError>() {
+                    "device_locked"
+                } else if let Some(e) = e.downcast_ref::<ApprovalError>() {
+                    e.error_code()
+                } else if let Some(e) = e.downcast_ref::<UpdateParamsMessageError>() {
+                    e.error_code()
+                } else if let Some(e) = e.downcast_ref::<AdmissionError>() {
+                    e.error_code()
+                } else if let Some(e) = e.downcast_ref::<UnfilledPlaceholdersError>() {
+                    e.error_code()
+                } else if let Some(e) = e.downcast_ref::<MaintenanceError>() {
+                    e.error_code()
+                } else {
+                    "other"
+                }
+            }
+        }
+    }
+
+    fn error_args(&self) -> BTreeMap<&'static str, String> {
+        match self {
+            TransactionError::RecipeAlreadyExists(id) | TransactionError::UnknownRecipeId(id) => {
+                BTreeMap::from([("recipe_id", id.to_string())])
+            }
+            TransactionError::UnknownDevice(UnknownDeviceError(id)) => {
+                BTreeMap::from([("device_id", id.to_string())])
+            }
+            TransactionError::UnknownFilePath(path) => {
+                BTreeMap::from([("path", path.display().to_string())])
+            }
+            TransactionError::FileSystemError(e) => BTreeMap::from([("reason", e.to_string())]),
+            TransactionError::InvalidDeviceConfig(e) => {
+                BTreeMap::from([("reason", format!("{e:?}"))])
+            }
+            TransactionError::InvalidVariable(e) => BTreeMap::from([
+                ("recipe_id", e.recipe_id.to_string()),
+                ("reason", e.reason.to_string()),
+            ]),
+            TransactionError::Other(e) => {
+                if let Some(DeviceLockedError(id)) = e.downcast_ref::<DeviceLockedError>() {
+                    BTreeMap::from([("device_id", id.to_string())])
+                } else if let Some(e) = e.downcast_ref::<ApprovalError>() {
+                    e.error_args()
+                } else if let Some(e) = e.downcast_ref::<UpdateParamsMessageError>() {
+                    e.error_args()
+                } else if let Some(e) = e.downcast_ref::<AdmissionError>() {
+                    e.error_args()
+                } else if let Some(e) = e.downcast_ref::<UnfilledPlaceholdersError>() {
+                    e.error_args()
+                } else if let Some(e) = e.downcast_ref::<MaintenanceError>() {
+                    e.error_args()
+                } else {
+                    BTreeMap::from([("reason", e.to_string())])
+                }
+            }
+        }
+    }
+}
+
 impl From<UpdateParamsMessageError> for TransactionError {
     fn from(x: UpdateParamsMessageError) -> Self {
         match x {
@@ -1,47 +1,88 @@
 use anyhow::anyhow;
 use bytes::Bytes;
+use futures::{stream::BoxStream, TryStreamExt};
 
 use crate::{
     device::{ActorDevice, ActorError, ActorMessage},
     recipe::file::RelativeFilePath,
-    FileService, FileServiceExt, RelativeDirectoryPathBuf, TransactionError,
+    FileListQuery, FilePage, FileService, FileServiceExt, FileVersion, RelativeDirectoryPathBuf,
+    TransactionError,
 };
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, ActorMessage)]
+#[actor_message(crate = crate, output = Vec<u8>, error = TransactionError, name = "get_file")]
 pub struct GetFileMessage {
     pub path: RelativeFilePath,
 }
-impl ActorMessage for GetFileMessage {
-    type Output = Vec<u8>;
-    type Error = TransactionError;
+
+/// Reads the file chunk by chunk. Use for files which are too large for `GetFileMessage`
+#[derive(Debug, Clone, ActorMessage)]
+#[actor_message(crate = crate, output = BoxStream<'static, std::io::Result<Bytes>>, error = TransactionError, name = "stream_file")]
+pub struct StreamFileMessage {
+    pub path: RelativeFilePath,
 }
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, ActorMessage)]
+#[actor_message(crate = crate, output = (), error = TransactionError, name = "delete_file")]
 pub struct DeleteFileMessage {
     pub path: RelativeFilePath,
 }
-impl ActorMessage for DeleteFileMessage {
-    type Output = ();
-    type Error = TransactionError;
-}
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, ActorMessage)]
+#[actor_message(crate = crate, output = (), error = anyhow::Error, name = "add_file")]
 pub struct AddFileMessage {
     pub path: RelativeFilePath,
     pub data: Bytes,
 }
-impl ActorMessage for AddFileMessage {
-    type Output = ();
-    type Error = anyhow::Error;
-}
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, ActorMessage)]
+#[actor_message(crate = crate, output = Vec<RelativeFilePath>, error = TransactionError, name = "list_files")]
 pub struct ListFilesMessage {
     pub path: RelativeDirectoryPathBuf,
 }
-impl ActorMessage for ListFilesMessage {
-    type Output = Vec<RelativeFilePath>;
-    type Error = TransactionError;
+
+#[derive(Debug, Clone, ActorMessage)]
+#[actor_message(crate = crate, output = Vec<FileVersion>, error = TransactionError, name = "list_file_versions")]
+pub struct ListFileVersionsMessage {
+    pub path: RelativeFilePath,
+}
+
+#[derive(Debug, Clone, ActorMessage)]
+#[actor_message(crate = crate, output = (), error = TransactionError, name = "restore_file_version")]
+pub struct RestoreFileVersionMessage {
+    pub path: RelativeFilePath,
+    pub version: u64,
+}
+
+/// Lists files in all subdirectories of `path`
+#[derive(Debug, Clone, ActorMessage)]
+#[actor_message(crate = crate, output = Vec<RelativeFilePath>, error = TransactionError, name = "list_files_recursive")]
+pub struct ListFilesRecursiveMessage {
+    pub path: RelativeDirectoryPathBuf,
+}
+
+/// Lists one page of the files in `path`. Use for directories which might contain too many files for `ListFilesMessage`
+#[derive(Debug, Clone, ActorMessage)]
+#[actor_message(crate = crate, output = FilePage, error = TransactionError, name = "list_files_paged")]
+pub struct ListFilesPagedMessage {
+    pub path: RelativeDirectoryPathBuf,
+    pub query: FileListQuery,
+}
+
+/// The copy is validated like a newly added file, unless the same validator is responsible for both paths (see `FileServiceExt::copy_file_validated`)
+#[derive(Debug, Clone, ActorMessage)]
+#[actor_message(crate = crate, output = (), error = anyhow::Error, name = "copy_file")]
+pub struct CopyFileMessage {
+    pub from: RelativeFilePath,
+    pub to: RelativeFilePath,
+}
+
+/// The source is only removed after the target passed validation (see `FileServiceExt::copy_file_validated`)
+#[derive(Debug, Clone, ActorMessage)]
+#[actor_message(crate = crate, output = (), error = anyhow::Error, name = "move_file")]
+pub struct MoveFileMessage {
+    pub from: RelativeFilePath,
+    pub to: RelativeFilePath,
 }
 
 pub trait RegisterFileHandlersExtension {
@@ -63,6 +104,18 @@ impl<T: AsMut<FileService<T>> + AsRef<FileService<T>> + Send + Sync + 'static>
                 .map_err(ActorError::Custom)
         }
 
+        async fn stream_file<T: AsMut<FileService<T>> + Send + 'static>(
+            state: &mut T,
+            msg: StreamFileMessage,
+        ) -> Result<BoxStream<'static, std::io::Result<Bytes>>, ActorError<TransactionError>>
+        {
+            state
+                .as_mut()
+                .read_stream(&msg.path)
+                .await
+                .map_err(ActorError::Custom)
+        }
+
         async fn add_file<
             T: AsMut<FileService<T>> + AsRef<FileService<T>> + Sync + Send + 'static,
         >(
@@ -109,9 +162,110 @@ impl<T: AsMut<FileService<T>> + AsRef<FileService<T>> + Send + Sync + 'static>
                 .map_err(ActorError::Custom)
         }
 
+        async fn list_file_versions<T: AsMut<FileService<T>> + Send + 'static>(
+            state: &mut T,
+            ListFileVersionsMessage { path }: ListFileVersionsMessage,
+        ) -> Result<Vec<FileVersion>, ActorError<TransactionError>> {
+            state
+                .as_mut()
+                .list_versions(&path)
+                .await
+                .map_err(ActorError::Custom)
+        }
+
+        async fn restore_file_version<
+            T: AsMut<FileService<T>> + AsRef<FileService<T>> + Send + Sync + 'static,
+        >(
+            state: &mut T,
+            RestoreFileVersionMessage { path, version }: RestoreFileVersionMessage,
+        ) -> Result<(), ActorError<TransactionError>> {
+            if !state.has_validator_for(&path) {
+                return Err(ActorError::custom(anyhow!("Access denied")));
+            }
+            state
+                .as_mut()
+                .restore_version(&path, version)
+                .await
+                .map_err(ActorError::Custom)
+        }
+
+        async fn list_files_recursive<T: AsMut<FileService<T>> + Send + 'static>(
+            state: &mut T,
+            ListFilesRecursiveMessage { path }: ListFilesRecursiveMessage,
+        ) -> Result<Vec<RelativeFilePath>, ActorError<TransactionError>> {
+            let service = state.as_mut();
+            let mut result = Vec::new();
+            let mut pending = vec![path];
+            while let Some(dir) = pending.pop() {
+                result.extend(service.list_files(&dir).await.map_err(ActorError::Custom)?);
+                pending.extend(
+                    service
+                        .stream_directories(&dir)
+                        .try_collect::<Vec<_>>()
+                        .await
+                        .map_err(ActorError::Custom)?,
+                );
+            }
+            Ok(result)
+        }
+
+        async fn list_files_paged<T: AsMut<FileService<T>> + Send + 'static>(
+            state: &mut T,
+            ListFilesPagedMessage { path, query }: ListFilesPagedMessage,
+        ) -> Result<FilePage, ActorError<TransactionError>> {
+            state
+                .as_mut()
+                .list_files_paged(&path, &query)
+                .await
+                .map_err(ActorError::Custom)
+        }
+
+        async fn copy_file<
+            T: AsMut<FileService<T>> + AsRef<FileService<T>> + Sync + Send + 'static,
+        >(
+            state: &mut T,
+            CopyFileMessage { from, to }: CopyFileMessage,
+        ) -> Result<(), ActorError<anyhow::Error>> {
+            if !state.has_validator_for(&to) {
+                return Err(ActorError::custom(anyhow!("Access denied")));
+            }
+            FileServiceExt::copy_file_validated(state, &from, &to)
+                .await
+                .map_err(ActorError::custom)
+        }
+
+        async fn move_file<
+            T: AsMut<FileService<T>> + AsRef<FileService<T>> + Sync + Send + 'static,
+        >(
+            state: &mut T,
+            MoveFileMessage { from, to }: MoveFileMessage,
+        ) -> Result<(), ActorError<anyhow::Error>> {
+            if !state.has_validator_for(&from) || !state.has_validator_for(&to) {
+                return Err(ActorError::custom(anyhow!("Access denied")));
+            }
+            if from == to {
+                return Ok(());
+            }
+            FileServiceExt::copy_file_validated(state, &from, &to)
+                .await
+                .map_err(ActorError::custom)?;
+            state
+                .as_mut()
+                .remove_file(&from)
+                .await
+                .map_err(ActorError::custom)
+        }
+
         self.add_handler(get_file)
+            .add_handler(stream_file)
             .add_handler(add_file)
             .add_handler(delete_file)
             .add_handler(list_files)
+            .add_handler(list_file_versions)
+            .add_handler(restore_file_version)
+            .add_handler(list_files_recursive)
+            .add_handler(list_files_paged)
+            .add_handler(copy_file)
+            .add_handler(move_file)
     }
 }
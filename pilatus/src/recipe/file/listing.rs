@@ -0,0 +1,242 @@
+//! Paging for directories with too many files to be listed at once (e.g. recorded frames)
+//!
+//! Continuation tokens contain the sort key of the last returned file, so files which are added or removed
+//! between two requests don't shift the following pages
+
+use std::cmp::Ordering;
+
+use chrono::{DateTime, Utc};
+use glob::{MatchOptions, Pattern};
+use serde::{Deserialize, Serialize};
+
+use crate::{RelativeDirectoryPath, RelativeFilePath, TransactionError};
+
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct FileListQuery {
+    /// Glob pattern relative to the listed directory (e.g. `*.png`). `*` doesn't match `/`, use `**/*.png` for subdirectories
+    pub filter: Option<String>,
+    pub sort: FileSort,
+    pub descending: bool,
+    /// Include files of all subdirectories
+    pub recursive: bool,
+    /// Maximum number of files per page. All remaining files are returned if missing
+    pub limit: Option<usize>,
+    /// Token of the previous page to continue from
+    pub continuation: Option<String>,
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum FileSort {
+    #[default]
+    Name,
+    Modified,
+    Size,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct FileListEntry {
+    pub path: RelativeFilePath,
+    pub size: u64,
+    pub modified: DateTime<Utc>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct FilePage {
+    pub files: Vec<FileListEntry>,
+    /// Pass as `continuation` to get the next page. None, if this is the last page
+    pub continuation: Option<String>,
+}
+
+impl FileListQuery {
+    /// Matcher for files below `dir`. Fails if `filter` is not a valid glob pattern
+    pub fn filter_for<'a>(
+        &self,
+        dir: &'a RelativeDirectoryPath,
+    ) -> Result<impl Fn(&RelativeFilePath) -> bool + 'a, TransactionError> {
+        let pattern = self
+            .filter
+            .as_deref()
+            .map(Pattern::new)
+            .transpose()
+            .map_err(TransactionError::other)?;
+        Ok(move |file: &RelativeFilePath| {
+            let Some(pattern) = &pattern else {
+                return true;
+            };
+            let Ok(relative) = file.get_path().strip_prefix(dir) else {
+                return false;
+            };
+            pattern.matches_path_with(
+                relative,
+                MatchOptions {
+                    require_literal_separator: true,
+                    ..Default::default()
+                },
+            )
+        })
+    }
+
+    /// Sorts `files` and returns the page following `continuation`. `files` must already be filtered
+    pub fn paginate(&self, mut files: Vec<FileListEntry>) -> Result<FilePage, TransactionError> {
+        let after = self
+            .continuation
+            .as_deref()
+            .map(SortKey::parse)
+            .transpose()?;
+        files.sort_by(|a, b| self.compare(&self.key(a), &self.key(b)));
+
+        let start = match &after {
+            Some(after) => {
+                files.partition_point(|f| self.compare(&self.key(f), after) != Ordering::Greater)
+            }
+            None => 0,
+        };
+        let end = self
+            .limit
+            .map_or(files.len(), |limit| files.len().min(start + limit));
+        let continuation =
+            (end < files.len() && end > start).then(|| self.key(&files[end - 1]).to_string());
+        files.truncate(end);
+        files.drain(..start);
+        Ok(FilePage {
+            files,
+            continuation,
+        })
+    }
+
+    fn key(&self, entry: &FileListEntry) -> SortKey {
+        SortKey {
+            primary: match self.sort {
+                FileSort::Name => 0,
+                FileSort::Modified => {
+                    entry.modified.timestamp_nanos_opt().unwrap_or_default() as i128
+                }
+                FileSort::Size => entry.size as i128,
+            },
+            path: entry.path.to_string(),
+        }
+    }
+
+    fn compare(&self, a: &SortKey, b: &SortKey) -> Ordering {
+        let ordering = a.cmp(b);
+        if self.descending {
+            ordering.reverse()
+        } else {
+            ordering
+        }
+    }
+}
+
+/// The path is used as tiebreaker, so the order is total even if many files have the same size
+#[derive(Debug, PartialEq, Eq, PartialOrd, Ord)]
+struct SortKey {
+    primary: i128,
+    path: String,
+}
+
+impl SortKey {
+    fn parse(token: &str) -> Result<Self, TransactionError> {
+        token
+            .split_once(':')
+            .and_then(|(primary, path)| {
+                Some(SortKey {
+                    primary: primary.parse().ok()?,
+                    path: path.to_string(),
+                })
+            })
+            .ok_or_else(|| TransactionError::other(anyhow::anyhow!("Invalid continuation token")))
+    }
+}
+
+impl std::fmt::Display for SortKey {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}:{}", self.primary, self.path)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn entry(path: &str, size: u64) -> FileListEntry {
+        FileListEntry {
+            path: RelativeFilePath::new(path).unwrap(),
+            size,
+            modified: DateTime::default(),
+        }
+    }
+
+    #[test]
+    fn paginate_by_size_descending() {
+        let files = vec![
+            entry("a.png", 1),
+            entry("b.png", 3),
+            entry("c.png", 2),
+            entry("d.png", 3),
+        ];
+        let mut query = FileListQuery {
+            sort: FileSort::Size,
+            descending: true,
+            limit: Some(3),
+            ..Default::default()
+        };
+        let first = query.paginate(files.clone()).unwrap();
+        assert_eq!(
+            vec!["d.png", "b.png", "c.png"],
+            first
+                .files
+                .iter()
+                .map(|f| f.path.to_string())
+                .collect::<Vec<_>>()
+        );
+
+        query.continuation = first.continuation;
+        let second = query.paginate(files).unwrap();
+        assert_eq!(vec![entry("a.png", 1)], second.files);
+        assert_eq!(None, second.continuation);
+    }
+
+    #[test]
+    fn continuation_is_stable_if_files_are_added() {
+        let query = FileListQuery {
+            limit: Some(1),
+            ..Default::default()
+        };
+        let first = query
+            .paginate(vec![entry("b.png", 0), entry("c.png", 0)])
+            .unwrap();
+        let second = FileListQuery {
+            continuation: first.continuation,
+            ..query
+        }
+        .paginate(vec![
+            entry("a.png", 0),
+            entry("b.png", 0),
+            entry("c.png", 0),
+        ])
+        .unwrap();
+        assert_eq!(vec![entry("c.png", 0)], second.files);
+    }
+
+    #[test]
+    fn filter_doesnt_match_subdirectories_without_double_star() {
+        let dir = RelativeDirectoryPath::new("frames").unwrap();
+        let flat = FileListQuery {
+            filter: Some("*.png".into()),
+            ..Default::default()
+        };
+        let filter = flat.filter_for(dir).unwrap();
+        assert!(filter(&RelativeFilePath::new("frames/a.png").unwrap()));
+        assert!(!filter(&RelativeFilePath::new("frames/sub/a.png").unwrap()));
+        assert!(!filter(&RelativeFilePath::new("frames/a.jpg").unwrap()));
+
+        let recursive = FileListQuery {
+            filter: Some("**/*.png".into()),
+            ..Default::default()
+        };
+        let filter = recursive.filter_for(dir).unwrap();
+        assert!(filter(&RelativeFilePath::new("frames/sub/a.png").unwrap()));
+    }
+}
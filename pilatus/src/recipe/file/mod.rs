@@ -10,6 +10,7 @@ use std::{
 
 pub use device::*;
 use futures::{future::BoxFuture, stream::BoxStream, FutureExt};
+pub use listing::*;
 use tracing::trace;
 
 use crate::{
@@ -18,6 +19,7 @@ use crate::{
 };
 
 mod device;
+mod listing;
 
 type InnerService = Box<dyn FileServiceTrait + Send + Sync>;
 type InnerFactory = Arc<dyn Fn(DeviceId) -> InnerService + Send + Sync>;
@@ -108,8 +110,20 @@ pub trait FileServiceTrait {
         file_path: &RelativeFilePath,
         data: &[u8],
     ) -> Result<(), anyhow::Error>;
+    /// Writes large files chunk by chunk. Like `add_file_unchecked`, validators are not applied.
+    /// Readers see either the previous or the complete new content, never a partially written file
+    async fn write_stream(
+        &mut self,
+        file_path: &RelativeFilePath,
+        data: BoxStream<'_, std::io::Result<bytes::Bytes>>,
+    ) -> Result<(), anyhow::Error>;
     async fn remove_file(&self, filename: &RelativeFilePath) -> Result<(), TransactionError>;
     async fn get_file(&self, filename: &RelativeFilePath) -> Result<Vec<u8>, TransactionError>;
+    /// Reads large files chunk by chunk instead of loading them into memory like `get_file`
+    async fn read_stream(
+        &self,
+        filename: &RelativeFilePath,
+    ) -> Result<BoxStream<'static, std::io::Result<bytes::Bytes>>, TransactionError>;
     async fn list_files(
         &self,
         path: &RelativeDirectoryPath,
@@ -129,6 +143,46 @@ pub trait FileServiceTrait {
     fn get_filepath(&self, file_path: &RelativeFilePath) -> PathBuf;
     fn get_directory_path(&self, file_path: &RelativeDirectoryPath) -> PathBuf;
     fn get_root(&self) -> &Path;
+    /// Previous contents of the file, newest first. Always empty, if versioning is disabled
+    async fn list_versions(
+        &self,
+        filename: &RelativeFilePath,
+    ) -> Result<Vec<FileVersion>, TransactionError>;
+    /// The current content is kept as a version itself, so restoring can be undone
+    async fn restore_version(
+        &mut self,
+        filename: &RelativeFilePath,
+        version: u64,
+    ) -> Result<(), TransactionError>;
+    /// Notifies about changes of files in `path` and its subdirectories, no matter who changed them (e.g. HTTP file routes).
+    /// The directory is created if it doesn't exist yet
+    fn watch(
+        &self,
+        path: &RelativeDirectoryPath,
+    ) -> BoxStream<'static, Result<FileEvent, TransactionError>>;
+    /// One page of the files in `path`, filtered and sorted according to `query`
+    async fn list_files_paged(
+        &self,
+        path: &RelativeDirectoryPath,
+        query: &FileListQuery,
+    ) -> Result<FilePage, TransactionError>;
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum FileEvent {
+    /// File was created or its content was replaced
+    Changed(RelativeFilePath),
+    Removed(RelativeFilePath),
+}
+
+/// Overwritten or removed content of a file, if the FileService keeps versions
+#[derive(Debug, Clone, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+#[cfg_attr(feature = "ts", derive(ts_rs::TS), ts(export))]
+pub struct FileVersion {
+    pub id: u64,
+    /// When the content was overwritten or removed
+    pub replaced: chrono::DateTime<chrono::Utc>,
+    pub size: u64,
 }
 
 pub trait FileServiceExt {
@@ -138,6 +192,13 @@ pub trait FileServiceExt {
         file_path: &'a RelativeFilePath,
         data: &'a [u8],
     ) -> BoxFuture<'a, Result<(), anyhow::Error>>;
+    /// If the same validator is responsible for both paths, the content already passed it and is streamed without validation.
+    /// Otherwise, the content is loaded into memory to validate it like a newly added file
+    fn copy_file_validated<'a>(
+        &'a mut self,
+        from: &'a RelativeFilePath,
+        to: &'a RelativeFilePath,
+    ) -> BoxFuture<'a, Result<(), anyhow::Error>>;
 }
 
 impl<T: AsMut<FileService<T>> + AsRef<FileService<T>> + Send + Sync> FileServiceExt for T {
@@ -166,4 +227,29 @@ impl<T: AsMut<FileService<T>> + AsRef<FileService<T>> + Send + Sync> FileService
         }
         .boxed()
     }
+
+    fn copy_file_validated<'a>(
+        &'a mut self,
+        from: &'a RelativeFilePath,
+        to: &'a RelativeFilePath,
+    ) -> BoxFuture<'a, Result<(), anyhow::Error>> {
+        trace!(?from, ?to, "Copy file validated");
+        async move {
+            let same_validator = {
+                let validators = &self.as_ref().validators;
+                let responsible = |path| validators.iter().position(|x| x.is_responsible(path));
+                let target = responsible(to)
+                    .ok_or_else(|| anyhow::anyhow!("Coultn't find responsible validator"))?;
+                responsible(from) == Some(target)
+            };
+            if same_validator {
+                let data = self.as_ref().read_stream(from).await?;
+                self.as_mut().write_stream(to, data).await
+            } else {
+                let data = self.as_ref().get_file(from).await?;
+                self.add_file_validated(to, &data).await
+            }
+        }
+        .boxed()
+    }
 }
@@ -0,0 +1,34 @@
+use serde::{Deserialize, Serialize};
+
+use crate::Name;
+
+crate::uuid_wrapper::wrapped_uuid!(DeviceGroupId);
+
+/// Devices which belong together (e.g. one inspection station). Devices reference their group via `DeviceConfig::group`
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize, Serialize)]
+#[cfg_attr(feature = "ts", derive(ts_rs::TS))]
+#[serde(deny_unknown_fields)]
+pub struct DeviceGroup {
+    #[cfg_attr(feature = "ts", ts(type = "string"))]
+    pub name: Name,
+}
+
+impl DeviceGroup {
+    pub fn new(name: Name) -> Self {
+        Self { name }
+    }
+}
+
+#[derive(Debug, thiserror::Error, PartialEq, Eq)]
+pub enum DeviceGroupError {
+    #[error("No group with id {0}")]
+    UnknownGroup(DeviceGroupId),
+    #[error("Group with name '{0}' exists already")]
+    DuplicateName(Name),
+}
+
+impl From<DeviceGroupError> for crate::TransactionError {
+    fn from(value: DeviceGroupError) -> Self {
+        crate::TransactionError::Other(value.into())
+    }
+}
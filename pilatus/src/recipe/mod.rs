@@ -1,24 +1,34 @@
+mod approval;
 mod device;
 mod device_config;
 mod duplicate_recipe;
 mod error;
 mod file;
+mod group;
 mod ord_hash_map;
+mod preview;
 #[allow(clippy::module_inception)]
 mod recipe;
 mod recipes;
+mod resources;
 mod service;
+mod stats;
 mod variable;
 
+pub use approval::*;
 pub use device::*;
-pub use device_config::DeviceConfig;
+pub use device_config::{DeviceConfig, UnfilledPlaceholdersError};
 pub use duplicate_recipe::*;
 pub use error::*;
 pub use file::*;
+pub use group::*;
+pub use preview::*;
 pub use recipe::*;
 pub use recipes::*;
+pub use resources::*;
 use serde::{Deserialize, Serialize};
 pub use service::*;
+pub use stats::*;
 
 pub use variable::*;
 
@@ -32,23 +42,63 @@ impl std::default::Default for RecipeId {
 /// Every Object with a key __var is guaranteed not to contain any other key and a string as value
 /// If UntypedDeviceParamsWithVariables is constructible anyway, this is a bug.
 #[derive(Debug, PartialEq, Eq, Clone, Serialize)]
+#[cfg_attr(feature = "ts", derive(ts_rs::TS))]
 pub struct UntypedDeviceParamsWithVariables(serde_json::Value);
 pub use UntypedDeviceParamsWithoutVariables;
 
 #[derive(Deserialize, Serialize)]
+#[cfg_attr(feature = "ts", derive(ts_rs::TS), ts(export))]
 pub struct ParameterUpdate {
     pub parameters: UntypedDeviceParamsWithVariables,
     pub variables: VariablesPatch,
 }
 
-pub struct InitRecipeListener(Box<dyn Fn(&mut Recipe) + Send + Sync>);
+/// Adds default devices to newly created recipes.
+/// Listeners with higher priority run first, listeners with equal priority in registration order.
+/// This allows conditional defaults, e.g. only adding an emulation device if no real device was added before.
+pub struct InitRecipeListener {
+    priority: i32,
+    callback: Box<dyn Fn(&mut Recipe, &InitRecipeContext) + Send + Sync>,
+}
+
+/// Information about the environment for InitRecipeListeners.
+/// Services like detected hardware can be captured by the listener when it's registered
+#[non_exhaustive]
+pub struct InitRecipeContext<'a> {
+    pub config: &'a crate::GenericConfig,
+}
+
+impl<'a> InitRecipeContext<'a> {
+    pub fn new(config: &'a crate::GenericConfig) -> Self {
+        Self { config }
+    }
+}
 
 impl InitRecipeListener {
     pub fn new(i: impl Fn(&mut Recipe) + Send + Sync + 'static) -> Self {
-        Self(Box::new(i) as _)
+        Self::with_context(move |r, _| i(r))
     }
-    pub fn call(&self, x: &mut Recipe) {
-        (self.0)(x)
+
+    pub fn with_context(
+        i: impl Fn(&mut Recipe, &InitRecipeContext) + Send + Sync + 'static,
+    ) -> Self {
+        Self {
+            priority: 0,
+            callback: Box::new(i),
+        }
+    }
+
+    /// Default: 0
+    pub fn with_priority(self, priority: i32) -> Self {
+        Self { priority, ..self }
+    }
+
+    pub fn priority(&self) -> i32 {
+        self.priority
+    }
+
+    pub fn call(&self, x: &mut Recipe, ctx: &InitRecipeContext) {
+        (self.callback)(x, ctx)
     }
 }
 
@@ -80,6 +130,44 @@ impl UntypedDeviceParamsWithVariables {
         Self::add_variable_names(&self.0, &mut result);
         result.into_iter()
     }
+
+    /// Variable names with the JSON pointer (RFC 6901) of the field referencing them, e.g. `("/rois/0/x", "roi_x")`
+    pub fn variable_paths(&self) -> Vec<(String, String)> {
+        let mut result = Vec::new();
+        Self::add_variable_paths(&self.0, &mut String::new(), &mut result);
+        result
+    }
+
+    fn add_variable_paths(
+        value: &serde_json::Value,
+        path: &mut String,
+        found: &mut Vec<(String, String)>,
+    ) {
+        let len = path.len();
+        match value {
+            serde_json::Value::Array(list) => {
+                for (i, x) in list.iter().enumerate() {
+                    path.push_str(&format!("/{i}"));
+                    Self::add_variable_paths(x, path, found);
+                    path.truncate(len);
+                }
+            }
+            serde_json::Value::Object(o) => {
+                if let Some(serde_json::Value::String(x)) = o.get(JSON_VAR_KEYWORD) {
+                    found.push((path.clone(), x.clone()));
+                } else {
+                    for (k, x) in o {
+                        path.push('/');
+                        path.push_str(&k.replace('~', "~0").replace('/', "~1"));
+                        Self::add_variable_paths(x, path, found);
+                        path.truncate(len);
+                    }
+                }
+            }
+            _ => {}
+        }
+    }
+
     fn add_variable_names(value: &serde_json::Value, found: &mut smallvec::SmallVec<[String; 8]>) {
         match value {
             serde_json::Value::Array(list) => {
@@ -59,6 +59,9 @@ impl<K: Eq + Hash, V> OrdHashMap<K, V> {
     pub fn len(&self) -> usize {
         self.0.len()
     }
+    pub fn is_empty(&self) -> bool {
+        self.0.is_empty()
+    }
     pub fn contains_key(&self, key: &K) -> bool {
         self.0.contains_key(key)
     }
@@ -0,0 +1,105 @@
+use serde::Serialize;
+use serde_json::Value;
+
+use crate::UntypedDeviceParamsWithVariables;
+
+/// Result of validating new params without applying them
+#[derive(Debug, Clone, PartialEq, Serialize)]
+#[cfg_attr(feature = "ts", derive(ts_rs::TS), ts(export))]
+pub struct ParamsPreview {
+    /// Params as they would be stored, including migrations of the device
+    pub parameters: UntypedDeviceParamsWithVariables,
+    /// Changes of the params with variables resolved
+    pub changes: Vec<ParamChange>,
+}
+
+/// A leaf which differs. Missing values are `None`, e.g. for newly added fields
+#[derive(Debug, Clone, PartialEq, Serialize)]
+#[cfg_attr(feature = "ts", derive(ts_rs::TS))]
+pub struct ParamChange {
+    /// JSON pointer within the device params
+    pub path: String,
+    #[cfg_attr(feature = "ts", ts(type = "unknown"))]
+    pub old: Option<Value>,
+    #[cfg_attr(feature = "ts", ts(type = "unknown"))]
+    pub new: Option<Value>,
+}
+
+impl ParamChange {
+    /// Objects are compared per key, arrays per index
+    pub fn diff(old: &Value, new: &Value) -> Vec<ParamChange> {
+        let mut changes = Vec::new();
+        Self::add_changes(&mut String::new(), Some(old), Some(new), &mut changes);
+        changes
+    }
+
+    fn add_changes(
+        path: &mut String,
+        old: Option<&Value>,
+        new: Option<&Value>,
+        changes: &mut Vec<ParamChange>,
+    ) {
+        let len = path.len();
+        match (old, new) {
+            (Some(Value::Object(old)), Some(Value::Object(new))) => {
+                for key in old
+                    .keys()
+                    .chain(new.keys().filter(|k| !old.contains_key(*k)))
+                {
+                    path.push('/');
+                    path.push_str(&key.replace('~', "~0").replace('/', "~1"));
+                    Self::add_changes(path, old.get(key), new.get(key), changes);
+                    path.truncate(len);
+                }
+            }
+            (Some(Value::Array(old)), Some(Value::Array(new))) => {
+                for i in 0..old.len().max(new.len()) {
+                    path.push_str(&format!("/{i}"));
+                    Self::add_changes(path, old.get(i), new.get(i), changes);
+                    path.truncate(len);
+                }
+            }
+            (old, new) if old != new => changes.push(ParamChange {
+                path: path.clone(),
+                old: old.cloned(),
+                new: new.cloned(),
+            }),
+            _ => {}
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use super::*;
+
+    #[test]
+    fn diff_nested_values() {
+        let changes = ParamChange::diff(
+            &json!({ "exposure": 3, "rois": [{ "x": 1 }], "removed": true }),
+            &json!({ "exposure": 5, "rois": [{ "x": 1 }, { "x": 2 }] }),
+        );
+        assert_eq!(
+            vec![
+                ParamChange {
+                    path: "/exposure".into(),
+                    old: Some(json!(3)),
+                    new: Some(json!(5)),
+                },
+                ParamChange {
+                    path: "/removed".into(),
+                    old: Some(json!(true)),
+                    new: None,
+                },
+                ParamChange {
+                    path: "/rois/1".into(),
+                    old: None,
+                    new: Some(json!({ "x": 2 })),
+                },
+            ],
+            changes
+        );
+    }
+}
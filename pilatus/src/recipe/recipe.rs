@@ -5,15 +5,46 @@ use sealedstruct::{Seal, ValidationResultExtensions, Validator};
 use serde::{Deserialize, Serialize};
 
 use super::{
-    device_config::DeviceConfig, duplicate_recipe::DuplicateRecipe, ord_hash_map::OrdHashMap,
+    approval::ApprovalState,
+    device_config::{DeviceConfig, UnfilledPlaceholdersError},
+    duplicate_recipe::DuplicateRecipe,
+    group::{DeviceGroup, DeviceGroupError, DeviceGroupId},
+    ord_hash_map::OrdHashMap,
 };
 use crate::{device::DeviceId, Name, RecipeId, UntypedDeviceParamsWithVariables};
 
+/// Number of annotations kept per recipe. Older ones are dropped
+pub const MAX_CHANGE_ANNOTATIONS: usize = 100;
+
 #[derive(Debug, PartialEq, Eq, Clone, Deserialize, Seal)]
 #[serde(deny_unknown_fields)]
 pub struct RecipeMetadataRaw {
     pub new_id: RecipeId,
     pub tags: Vec<Name>,
+    /// The stored description is kept if `None`, so clients which don't know about descriptions don't clear it
+    #[serde(default)]
+    pub description: Option<String>,
+}
+
+/// Maximum number of characters in free-text descriptions of recipes and notes of devices
+pub const MAX_DESCRIPTION_LEN: usize = 4000;
+
+/// Maximum number of characters of messages and authors in the change history
+pub const MAX_CHANGE_ANNOTATION_LEN: usize = 500;
+
+pub fn check_description(text: &str) -> sealedstruct::Result<()> {
+    check_text_len(text, MAX_DESCRIPTION_LEN)
+}
+
+pub(crate) fn check_text_len(text: &str, max: usize) -> sealedstruct::Result<()> {
+    let len = text.chars().count();
+    let result: sealedstruct::Result<()> = Ok(());
+    if len > max {
+        return result.append_error(sealedstruct::ValidationError::new(format!(
+            "(len={len}) > {max}"
+        )));
+    }
+    result
 }
 
 #[derive(Debug, thiserror::Error)]
@@ -24,6 +55,23 @@ pub struct DeviceWithSameIdExists(DeviceId);
 #[error("No device with id {0}")]
 pub struct UnknownDeviceError(pub DeviceId);
 
+#[derive(Debug, thiserror::Error, PartialEq, Eq)]
+pub enum MoveDeviceToGroupError {
+    #[error("{0}")]
+    UnknownDevice(#[from] UnknownDeviceError),
+    #[error("{0}")]
+    Group(#[from] DeviceGroupError),
+}
+
+impl From<MoveDeviceToGroupError> for crate::TransactionError {
+    fn from(value: MoveDeviceToGroupError) -> Self {
+        match value {
+            MoveDeviceToGroupError::UnknownDevice(x) => x.into(),
+            MoveDeviceToGroupError::Group(x) => x.into(),
+        }
+    }
+}
+
 impl Validator for RecipeMetadataRaw {
     fn check(&self) -> sealedstruct::Result<()> {
         let mut tags = HashSet::new();
@@ -40,17 +88,54 @@ impl Validator for RecipeMetadataRaw {
         RecipeMetadataResult {
             new_id: Ok(()),
             tags: errors,
+            description: self
+                .description
+                .as_deref()
+                .map_or(Ok(()), check_description),
         }
         .into()
     }
 }
 
+/// Records who changed a recipe and why. Created from [`crate::TransactionOptions`] with a message or author
+#[derive(Debug, Clone, Deserialize, Serialize, PartialEq, Eq)]
+#[cfg_attr(feature = "ts", derive(ts_rs::TS))]
+#[serde(deny_unknown_fields)]
+pub struct ChangeAnnotation {
+    pub transaction: uuid::Uuid,
+    pub created: DateTime<Utc>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub author: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub message: Option<String>,
+}
+
 #[derive(Debug, Clone, Deserialize, Serialize, PartialEq)]
+#[cfg_attr(feature = "ts", derive(ts_rs::TS))]
 #[serde(deny_unknown_fields)]
 pub struct Recipe {
     pub created: DateTime<Utc>,
+    #[cfg_attr(feature = "ts", ts(type = "Array<string>"))]
     pub tags: Vec<Name>,
+    /// Free-text documentation, e.g. why this recipe deviates from others
+    #[serde(default, skip_serializing_if = "String::is_empty")]
+    pub description: String,
+    #[cfg_attr(
+        feature = "ts",
+        ts(as = "std::collections::HashMap<DeviceId, DeviceConfig>")
+    )]
     pub devices: OrdHashMap<DeviceId, DeviceConfig>,
+    #[serde(default, skip_serializing_if = "OrdHashMap::is_empty")]
+    #[cfg_attr(
+        feature = "ts",
+        ts(as = "std::collections::HashMap<DeviceGroupId, DeviceGroup>")
+    )]
+    pub groups: OrdHashMap<DeviceGroupId, DeviceGroup>,
+    /// Oldest first
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub changes: Vec<ChangeAnnotation>,
+    #[serde(default, skip_serializing_if = "ApprovalState::is_draft")]
+    pub approval: ApprovalState,
 }
 
 impl Default for Recipe {
@@ -58,7 +143,11 @@ impl Default for Recipe {
         Self {
             created: Utc::now(),
             tags: Default::default(),
+            description: Default::default(),
             devices: Default::default(),
+            groups: Default::default(),
+            changes: Default::default(),
+            approval: Default::default(),
         }
     }
 }
@@ -78,6 +167,79 @@ impl Recipe {
         DuplicateRecipe::new_unwrap(mappings, serde_json::from_str(&config).expect("Valid json"))
     }
 
+    pub fn annotate(&mut self, annotation: ChangeAnnotation) {
+        self.changes.push(annotation);
+        let overflow = self.changes.len().saturating_sub(MAX_CHANGE_ANNOTATIONS);
+        self.changes.drain(..overflow);
+    }
+
+    pub fn last_change(&self) -> Option<&ChangeAnnotation> {
+        self.changes.last()
+    }
+
+    pub fn add_group(&mut self, name: Name) -> Result<DeviceGroupId, DeviceGroupError> {
+        self.ensure_unique_group_name(&name)?;
+        let id = DeviceGroupId::new_v4();
+        self.groups.insert(id, DeviceGroup::new(name));
+        Ok(id)
+    }
+
+    pub fn rename_group(&mut self, id: DeviceGroupId, name: Name) -> Result<(), DeviceGroupError> {
+        if self.groups.get(&id).map(|x| &x.name) == Some(&name) {
+            return Ok(());
+        }
+        self.ensure_unique_group_name(&name)?;
+        self.groups
+            .get_mut(&id)
+            .ok_or(DeviceGroupError::UnknownGroup(id))?
+            .name = name;
+        Ok(())
+    }
+
+    /// Devices of the group stay in the recipe without a group
+    pub fn remove_group(&mut self, id: DeviceGroupId) -> Result<DeviceGroup, DeviceGroupError> {
+        let removed = self
+            .groups
+            .remove(&id)
+            .ok_or(DeviceGroupError::UnknownGroup(id))?;
+        for device in self.devices.values_mut() {
+            if device.group == Some(id) {
+                device.group = None;
+            }
+        }
+        Ok(removed)
+    }
+
+    /// `None` removes the device from its group
+    pub fn move_device_to_group(
+        &mut self,
+        device_id: DeviceId,
+        group: Option<DeviceGroupId>,
+    ) -> Result<(), MoveDeviceToGroupError> {
+        if let Some(group) = group {
+            if !self.groups.contains_key(&group) {
+                return Err(DeviceGroupError::UnknownGroup(group).into());
+            }
+        }
+        self.device_by_id_mut(device_id)?.group = group;
+        Ok(())
+    }
+
+    pub fn devices_in_group(&self, group: DeviceGroupId) -> impl Iterator<Item = DeviceId> + '_ {
+        self.devices
+            .iter_ordered()
+            .filter(move |(_, d)| d.group == Some(group))
+            .map(|(id, _)| *id)
+    }
+
+    fn ensure_unique_group_name(&self, name: &Name) -> Result<(), DeviceGroupError> {
+        if self.groups.values().any(|x| &x.name == name) {
+            Err(DeviceGroupError::DuplicateName(name.clone()))
+        } else {
+            Ok(())
+        }
+    }
+
     pub fn has_device(&self, id: &DeviceId) -> bool {
         self.devices.contains_key(id)
     }
@@ -86,6 +248,23 @@ impl Recipe {
         self.devices.get(&id).ok_or(UnknownDeviceError(id))
     }
 
+    /// Disabled devices are not started and may therefore keep their placeholders
+    pub fn check_placeholders(&self) -> Result<(), UnfilledPlaceholdersError> {
+        for (_, device) in self.devices.iter_ordered().filter(|(_, d)| d.enabled) {
+            let fields = device
+                .unfilled_placeholders()
+                .map(str::to_string)
+                .collect::<Vec<_>>();
+            if !fields.is_empty() {
+                return Err(UnfilledPlaceholdersError {
+                    device: device.device_name.clone(),
+                    fields,
+                });
+            }
+        }
+        Ok(())
+    }
+
     pub fn count_devices(&self) -> usize {
         self.devices.len()
     }
@@ -165,4 +344,84 @@ mod tests {
         let _id = recipe.add_device(device);
         assert_eq!(1, recipe.devices.len());
     }
+
+    #[test]
+    fn metadata_rejects_too_long_description() {
+        let metadata = |description: String| RecipeMetadataRaw {
+            new_id: RecipeId::default(),
+            tags: Vec::new(),
+            description: Some(description),
+        };
+        assert!(metadata("a".repeat(MAX_DESCRIPTION_LEN)).seal().is_ok());
+        assert!(metadata("a".repeat(MAX_DESCRIPTION_LEN + 1))
+            .seal()
+            .is_err());
+    }
+
+    #[test]
+    fn metadata_without_description_keeps_it() {
+        let raw: RecipeMetadataRaw =
+            serde_json::from_value(serde_json::json!({ "new_id": "r1", "tags": [] })).unwrap();
+        assert_eq!(None, raw.description);
+    }
+
+    #[test]
+    fn empty_description_is_not_serialized() {
+        let json = serde_json::to_value(Recipe::default()).unwrap();
+        assert!(json.get("description").is_none());
+        let recipe: Recipe = serde_json::from_value(json).unwrap();
+        assert_eq!("", recipe.description);
+    }
+
+    #[test]
+    fn keep_latest_change_annotations() {
+        let mut recipe = Recipe::default();
+        for i in 0..MAX_CHANGE_ANNOTATIONS + 5 {
+            let options = crate::TransactionOptions::default().with_message(i.to_string());
+            recipe.annotate(options.annotation().unwrap().unwrap());
+        }
+        assert!(crate::TransactionOptions::default()
+            .annotation()
+            .unwrap()
+            .is_none());
+        assert_eq!(MAX_CHANGE_ANNOTATIONS, recipe.changes.len());
+        assert_eq!(Some("5"), recipe.changes[0].message.as_deref());
+        assert_eq!(
+            Some((MAX_CHANGE_ANNOTATIONS + 4).to_string()),
+            recipe.last_change().unwrap().message.clone()
+        );
+    }
+
+    #[test]
+    fn reject_too_long_change_messages() {
+        let options = |len| crate::TransactionOptions::default().with_message("a".repeat(len));
+        assert!(options(MAX_CHANGE_ANNOTATION_LEN).annotation().is_ok());
+        assert!(options(MAX_CHANGE_ANNOTATION_LEN + 1).annotation().is_err());
+    }
+
+    #[test]
+    fn removing_group_keeps_devices() {
+        let mut recipe = Recipe::default();
+        let station = recipe.add_group(Name::new("Station").unwrap()).unwrap();
+        assert_eq!(
+            Err(DeviceGroupError::DuplicateName(
+                Name::new("Station").unwrap()
+            )),
+            recipe.add_group(Name::new("Station").unwrap())
+        );
+        let device = recipe.add_device(DeviceConfig::mock("Test"));
+        let other = recipe.add_device(DeviceConfig::mock("Test"));
+        recipe.move_device_to_group(device, Some(station)).unwrap();
+        assert_eq!(
+            vec![device],
+            recipe.devices_in_group(station).collect::<Vec<_>>()
+        );
+        assert!(recipe
+            .move_device_to_group(other, Some(DeviceGroupId::new_v4()))
+            .is_err());
+
+        recipe.remove_group(station).unwrap();
+        assert_eq!(2, recipe.count_devices());
+        assert_eq!(None, recipe.device_by_id(device).unwrap().group);
+    }
 }
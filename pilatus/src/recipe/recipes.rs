@@ -1,4 +1,5 @@
 use std::borrow::Borrow;
+use std::collections::BTreeMap;
 use std::fmt::Debug;
 use std::io::{self, BufWriter, Read};
 use std::path::Path;
@@ -12,17 +13,23 @@ use crate::{TransactionError, UntypedDeviceParamsWithVariables};
 use super::duplicate_recipe::DuplicateRecipe;
 use super::ord_hash_map::OrdHashMap;
 use super::recipe::Recipe;
-use super::variable::{Variables, VariablesPatch};
+use super::variable::{VariableUsage, Variables, VariablesPatch};
 
 // Ensures Recipes to be unique and that there is always an active recipe
 // The uncommitted Recipe is stored in `all` to allow changes via id to affect the temporary Recipe
 #[derive(Debug, Clone, Serialize)]
+#[cfg_attr(feature = "ts", derive(ts_rs::TS))]
 pub struct Recipes {
     active_id: RecipeId,
     // used to check for changes/restore
     active_backup: Recipe,
+    #[cfg_attr(feature = "ts", ts(as = "std::collections::HashMap<RecipeId, Recipe>"))]
     all: OrdHashMap<RecipeId, Recipe>,
+    #[cfg_attr(feature = "ts", ts(as = "crate::VariablesPatch"))]
     variables: Variables,
+    /// Variables at the last commit of the active recipe, used to restore it
+    #[cfg_attr(feature = "ts", ts(as = "crate::VariablesPatch"))]
+    variables_backup: Variables,
 }
 
 impl<'de> Deserialize<'de> for Recipes {
@@ -37,6 +44,9 @@ impl<'de> Deserialize<'de> for Recipes {
             active_backup: Recipe,
             all: OrdHashMap<RecipeId, Recipe>,
             variables: Variables,
+            /// Missing in files written before it was introduced
+            #[serde(default)]
+            variables_backup: Option<Variables>,
         }
 
         let raw = DeserializeRecipes::deserialize(deserializer)?;
@@ -52,6 +62,9 @@ impl<'de> Deserialize<'de> for Recipes {
             active_id: raw.active_id,
             active_backup: raw.active_backup,
             all: raw.all,
+            variables_backup: raw
+                .variables_backup
+                .unwrap_or_else(|| raw.variables.clone()),
             variables: raw.variables,
         })
     }
@@ -66,6 +79,7 @@ impl Default for Recipes {
             active_backup: active.clone(),
             all: OrdHashMap::from([(id, active)]),
             variables: Default::default(),
+            variables_backup: Default::default(),
         }
     }
 }
@@ -100,6 +114,36 @@ impl Recipes {
 
     pub fn commit_active(&mut self) {
         self.active_backup = self.active().1.clone();
+        self.variables_backup = self.variables.clone();
+    }
+
+    /// State of the active recipe at the last commit
+    pub fn committed_active(&self) -> &Recipe {
+        &self.active_backup
+    }
+
+    /// Variables at the last commit of the active recipe
+    pub fn committed_variables(&self) -> &Variables {
+        &self.variables_backup
+    }
+
+    /// Replaces the active recipe and the variables with their state at the last commit.
+    /// Returns the devices which were added since and are therefore removed
+    pub fn restore_active(&mut self) -> Vec<DeviceId> {
+        self.variables = self.variables_backup.clone();
+        let backup = self.active_backup.clone();
+        let running = self
+            .all
+            .get_mut(&self.active_id)
+            .expect("Active must always exist");
+        let added = running
+            .devices
+            .iter_unordered()
+            .filter(|(id, _)| !backup.has_device(id))
+            .map(|(id, _)| *id)
+            .collect();
+        *running = backup;
+        added
     }
 
     pub fn iter_running_join_backup(
@@ -169,6 +213,33 @@ impl Recipes {
         })
     }
 
+    /// Usages of each variable in all recipes. Unused variables map to an empty list,
+    /// referenced but undefined variables are included as well
+    pub fn variable_usages(&self) -> BTreeMap<String, Vec<VariableUsage>> {
+        let mut result: BTreeMap<String, Vec<VariableUsage>> = self
+            .variables
+            .names()
+            .map(|name| (name.to_string(), Vec::new()))
+            .collect();
+        for (recipe_id, recipe) in self.iter_without_backup() {
+            for (device_id, device) in recipe.devices.iter_ordered() {
+                for (path, name) in device.params.variable_paths() {
+                    result.entry(name).or_default().push(VariableUsage {
+                        recipe_id: recipe_id.clone(),
+                        device_id: *device_id,
+                        device_name: device.device_name.clone(),
+                        path,
+                    });
+                }
+            }
+        }
+        // Stable, so devices keep their order within a recipe
+        for usages in result.values_mut() {
+            usages.sort_by(|a, b| a.recipe_id.cmp(&b.recipe_id));
+        }
+        result
+    }
+
     pub fn recipeid_per_deviceid(&self) -> impl Iterator<Item = (DeviceId, RecipeId)> + '_ {
         self.iter_with_backup().flat_map(|(rid, v)| {
             v.devices
@@ -204,6 +275,7 @@ impl Recipes {
                     .map(|(id, _)| *id)
                     .collect::<Vec<_>>();
                 self.active_backup = recipe.clone();
+                self.variables_backup = self.variables.clone();
                 self.active_id = id.clone();
                 Ok(ids)
             }
@@ -308,6 +380,7 @@ impl Recipes {
             active_backup: r.clone(),
             all: OrdHashMap::from([(id, r)]),
             variables: Default::default(),
+            variables_backup: Default::default(),
         }
     }
 
@@ -382,7 +455,34 @@ impl From<RemoveRecipeError> for TransactionError {
 mod tests {
     use super::*;
     use serde_json::json;
-    use std::{ops::Deref, sync::Arc};
+    use std::{collections::HashMap, ops::Deref, sync::Arc};
+
+    #[test]
+    fn variable_usages_contain_paths_and_unused_variables() {
+        let mut recipes = Recipes::new();
+        *recipes.as_mut() = Variables::default().patch(HashMap::from([
+            ("exposure".into(), 10.into()),
+            ("unused".into(), 1.into()),
+        ]));
+        let (recipe_id, r) = recipes.get_active();
+        let device_id = r.add_device(DeviceConfig::mock(json!({
+            "rois": [{ "x": 1 }, { "x": { "__var": "exposure" } }],
+            "a/b": { "__var": "missing" }
+        })));
+
+        let usages = recipes.variable_usages();
+        assert_eq!(Some(&Vec::new()), usages.get("unused"));
+        assert_eq!(
+            vec![VariableUsage {
+                recipe_id,
+                device_id,
+                device_name: Name::new("testdevicename").unwrap(),
+                path: "/rois/1/x".into()
+            }],
+            usages["exposure"]
+        );
+        assert_eq!("/a~1b", usages["missing"][0].path);
+    }
 
     #[test]
     fn test_update_recipe_id() {
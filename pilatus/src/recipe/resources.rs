@@ -0,0 +1,216 @@
+use std::collections::{BTreeMap, HashMap};
+
+use serde::{Deserialize, Serialize};
+
+use crate::{LocalizableError, Name, Recipe, TransactionError};
+
+/// Resources a device claims while it's running. Checked against [`MachineCapacity`] when a recipe is activated
+#[derive(Debug, Default, PartialEq, Eq, Clone, Deserialize, Serialize)]
+#[cfg_attr(feature = "ts", derive(ts_rs::TS))]
+#[serde(deny_unknown_fields)]
+pub struct DeviceResources {
+    /// Estimated memory in megabytes
+    #[serde(default, skip_serializing_if = "is_zero")]
+    pub memory_mb: u64,
+
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub gpu: bool,
+
+    /// Hardware which can only be used by a single device, e.g. "gige:192.168.1.10" or the serial of a camera
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    #[cfg_attr(feature = "ts", ts(optional))]
+    pub exclusive_interface: Option<String>,
+}
+
+fn is_zero(x: &u64) -> bool {
+    *x == 0
+}
+
+impl DeviceResources {
+    pub fn is_empty(&self) -> bool {
+        self == &Self::default()
+    }
+}
+
+/// Configured with `"machine_capacity": { "memory_mb": 4096, "gpus": 1 }`. Missing limits are not checked
+#[derive(Debug, Default, PartialEq, Eq, Clone, Deserialize, Serialize)]
+#[serde(deny_unknown_fields)]
+pub struct MachineCapacity {
+    #[serde(default)]
+    pub memory_mb: Option<u64>,
+    #[serde(default)]
+    pub gpus: Option<u32>,
+}
+
+#[derive(Debug, thiserror::Error, PartialEq, Eq)]
+pub enum AdmissionError {
+    #[error(
+        "Enabled devices require {required_mb} MB memory, but only {available_mb} MB are available"
+    )]
+    InsufficientMemory { required_mb: u64, available_mb: u64 },
+    #[error("{required} enabled devices require a GPU, but only {available} are available")]
+    InsufficientGpus { required: u32, available: u32 },
+    #[error("Devices '{first}' and '{second}' both claim the exclusive interface '{interface}'")]
+    InterfaceClaimedTwice {
+        interface: String,
+        first: Name,
+        second: Name,
+    },
+}
+
+impl From<AdmissionError> for TransactionError {
+    fn from(e: AdmissionError) -> Self {
+        Self::Other(e.into())
+    }
+}
+
+impl LocalizableError for AdmissionError {
+    fn error_code(&self) -> &'static str {
+        match self {
+            AdmissionError::InsufficientMemory { .. } => "admission_insufficient_memory",
+            AdmissionError::InsufficientGpus { .. } => "admission_insufficient_gpus",
+            AdmissionError::InterfaceClaimedTwice { .. } => "admission_interface_claimed_twice",
+        }
+    }
+
+    fn error_args(&self) -> BTreeMap<&'static str, String> {
+        match self {
+            AdmissionError::InsufficientMemory {
+                required_mb,
+                available_mb,
+            } => [
+                ("required", required_mb.to_string()),
+                ("available", available_mb.to_string()),
+            ]
+            .into(),
+            AdmissionError::InsufficientGpus {
+                required,
+                available,
+            } => [
+                ("required", required.to_string()),
+                ("available", available.to_string()),
+            ]
+            .into(),
+            AdmissionError::InterfaceClaimedTwice {
+                interface,
+                first,
+                second,
+            } => [
+                ("interface", interface.clone()),
+                ("first", first.to_string()),
+                ("second", second.to_string()),
+            ]
+            .into(),
+        }
+    }
+}
+
+impl MachineCapacity {
+    /// Disabled devices are not started and therefore don't claim any resources
+    pub fn check(&self, recipe: &Recipe) -> Result<(), AdmissionError> {
+        let mut memory_mb = 0;
+        let mut gpus = 0;
+        let mut interfaces = HashMap::<&str, &Name>::new();
+        for (_, device) in recipe.devices.iter_ordered().filter(|(_, d)| d.enabled) {
+            let resources = &device.resources;
+            memory_mb = resources.memory_mb.saturating_add(memory_mb);
+            gpus += resources.gpu as u32;
+            if let Some(interface) = resources.exclusive_interface.as_deref() {
+                if let Some(first) = interfaces.insert(interface, &device.device_name) {
+                    return Err(AdmissionError::InterfaceClaimedTwice {
+                        interface: interface.to_string(),
+                        first: first.clone(),
+                        second: device.device_name.clone(),
+                    });
+                }
+            }
+        }
+        if let Some(available_mb) = self.memory_mb.filter(|x| memory_mb > *x) {
+            return Err(AdmissionError::InsufficientMemory {
+                required_mb: memory_mb,
+                available_mb,
+            });
+        }
+        if let Some(available) = self.gpus.filter(|x| gpus > *x) {
+            return Err(AdmissionError::InsufficientGpus {
+                required: gpus,
+                available,
+            });
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::DeviceConfig;
+
+    fn camera(name: &str, interface: &str) -> DeviceConfig {
+        DeviceConfig::new_unchecked("camera", name, ()).with_resources(DeviceResources {
+            memory_mb: 512,
+            gpu: false,
+            exclusive_interface: Some(interface.into()),
+        })
+    }
+
+    #[test]
+    fn reject_interface_claimed_twice() {
+        let mut recipe = Recipe::default();
+        recipe.add_device(camera("Left", "gige:10.0.0.1"));
+        recipe.add_device(camera("Right", "gige:10.0.0.1"));
+        assert_eq!(
+            Err(AdmissionError::InterfaceClaimedTwice {
+                interface: "gige:10.0.0.1".into(),
+                first: Name::new("Left").unwrap(),
+                second: Name::new("Right").unwrap(),
+            }),
+            MachineCapacity::default().check(&recipe)
+        );
+    }
+
+    #[test]
+    fn disabled_devices_dont_claim_resources() {
+        let mut recipe = Recipe::default();
+        recipe.add_device(camera("Left", "gige:10.0.0.1"));
+        recipe.add_device(camera("Right", "gige:10.0.0.1").with_enabled(false));
+        let capacity = MachineCapacity {
+            memory_mb: Some(1000),
+            gpus: Some(0),
+        };
+        assert_eq!(Ok(()), capacity.check(&recipe));
+
+        recipe.add_device(camera("Third", "gige:10.0.0.3"));
+        assert_eq!(
+            Err(AdmissionError::InsufficientMemory {
+                required_mb: 1024,
+                available_mb: 1000
+            }),
+            capacity.check(&recipe)
+        );
+    }
+
+    #[test]
+    fn huge_reservations_dont_overflow() {
+        let mut recipe = Recipe::default();
+        for name in ["Left", "Right"] {
+            recipe.add_device(
+                DeviceConfig::new_unchecked("camera", name, ()).with_resources(DeviceResources {
+                    memory_mb: u64::MAX,
+                    ..Default::default()
+                }),
+            );
+        }
+        let capacity = MachineCapacity {
+            memory_mb: Some(1000),
+            gpus: None,
+        };
+        assert_eq!(
+            Err(AdmissionError::InsufficientMemory {
+                required_mb: u64::MAX,
+                available_mb: 1000
+            }),
+            capacity.check(&recipe)
+        );
+    }
+}
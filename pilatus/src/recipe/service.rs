@@ -11,13 +11,15 @@ use serde::Deserialize;
 
 use uuid::Uuid;
 
-use crate::device::{ActiveState, DeviceId};
+use crate::device::{ActiveState, ActorSystem, DeviceId, MessageOrigin};
 use crate::{
-    EntryReader, EntryWriter, Name, ParameterUpdate, RecipeId, RecipeMetadata, TransactionError,
-    UntypedDeviceParamsWithVariables, VariableConflict,
+    DeviceConfig, DeviceGroupId, EntryReader, EntryWriter, Name, ParameterUpdate, ParamsPreview,
+    RecipeId, RecipeMetadata, Role, TransactionError, UntypedDeviceParamsWithVariables,
+    VariableConflict, VariablesPatch,
 };
 
-use super::recipe::{Recipe, UnknownDeviceError};
+use super::approval::{ApprovalError, ApprovalState};
+use super::recipe::{ChangeAnnotation, Recipe, UnknownDeviceError};
 
 pub type RecipeExporter = Arc<dyn RecipeExporterTrait + Send + Sync>;
 #[async_trait]
@@ -30,6 +32,7 @@ pub trait RecipeExporterTrait {
 }
 
 #[derive(Debug, Default, PartialEq, Eq, serde::Deserialize)]
+#[cfg_attr(feature = "ts", derive(ts_rs::TS), ts(export))]
 pub enum IntoMergeStrategy {
     #[default]
     Unspecified,
@@ -41,6 +44,9 @@ pub enum IntoMergeStrategy {
 pub struct ImportRecipesOptions {
     pub merge_strategy: IntoMergeStrategy,
     pub is_dry_run: bool,
+    /// Archives without manifest are rejected, as their integrity can't be verified.
+    /// Only set it for archives exported before manifests were introduced
+    pub allow_without_manifest: bool,
 }
 
 impl Default for ImportRecipesOptions {
@@ -48,6 +54,7 @@ impl Default for ImportRecipesOptions {
         Self {
             merge_strategy: Default::default(),
             is_dry_run: true,
+            allow_without_manifest: false,
         }
     }
 }
@@ -94,6 +101,9 @@ pub enum ImportRecipeError {
 
     #[error("{0:?}")]
     Irreversible(#[from] IrreversibleError),
+
+    #[error("{0}")]
+    Maintenance(#[from] crate::MaintenanceError),
 }
 
 #[derive(Debug, thiserror::Error)]
@@ -151,6 +161,14 @@ pub trait RecipeServiceTrait {
         self.activate_recipe_with(id, Default::default()).await
     }
 
+    /// Requires the role of the transition in `options` (see [`ApprovalState::required_role`])
+    async fn update_recipe_approval_with(
+        &self,
+        recipe_id: RecipeId,
+        state: ApprovalState,
+        options: TransactionOptions,
+    ) -> Result<(), TransactionError>;
+
     async fn update_device_params_with(
         &self,
         recipe_id: RecipeId,
@@ -168,14 +186,36 @@ pub trait RecipeServiceTrait {
             .await
     }
 
-    async fn restore_active_with(&self, transaction_key: Uuid) -> Result<(), TransactionError>;
+    /// Changes variables without editing a device. Devices of other recipes have to accept the new values.
+    /// Running devices only follow, if all their references are live-bound (see [`crate::device::LiveBindings`])
+    async fn update_variables_with(
+        &self,
+        variables: VariablesPatch,
+        options: TransactionOptions,
+    ) -> Result<(), TransactionError>;
+
+    /// Validates the params like [`Self::update_device_params_with`], but doesn't change the recipe
+    async fn preview_device_params(
+        &self,
+        recipe_id: RecipeId,
+        device_id: DeviceId,
+        values: ParameterUpdate,
+    ) -> Result<ParamsPreview, TransactionError>;
+
+    /// Discards the uncommitted changes of the active recipe. Like all edits, this is rejected for released recipes
+    async fn restore_active_with(
+        &self,
+        options: TransactionOptions,
+    ) -> Result<(), TransactionError>;
     async fn restore_active(&self) -> Result<(), TransactionError> {
-        self.restore_active_with(Uuid::new_v4()).await
+        self.restore_active_with(Default::default()).await
     }
 
-    async fn commit_active_with(&self, transaction_key: Uuid) -> Result<(), TransactionError>;
+    /// Like all edits, this is rejected for released recipes
+    async fn commit_active_with(&self, options: TransactionOptions)
+        -> Result<(), TransactionError>;
     async fn commit_active(&self) -> Result<(), TransactionError> {
-        self.commit_active_with(Uuid::new_v4()).await
+        self.commit_active_with(Default::default()).await
     }
 
     async fn delete_device_with(
@@ -193,12 +233,20 @@ pub trait RecipeServiceTrait {
             .await
     }
 
-    async fn restore_committed(
+    async fn restore_committed_with(
         &self,
         recipe_id: RecipeId,
         device_id: DeviceId,
-        transaction: Uuid,
+        options: TransactionOptions,
     ) -> Result<(), TransactionError>;
+    async fn restore_committed(
+        &self,
+        recipe_id: RecipeId,
+        device_id: DeviceId,
+    ) -> Result<(), TransactionError> {
+        self.restore_committed_with(recipe_id, device_id, Default::default())
+            .await
+    }
     async fn update_device_name_with(
         &self,
         recipe_id: RecipeId,
@@ -206,7 +254,71 @@ pub trait RecipeServiceTrait {
         name: Name,
         options: TransactionOptions,
     ) -> Result<(), TransactionError>;
+    /// Takes effect when the device is started the next time
+    async fn update_device_simulated_with(
+        &self,
+        recipe_id: RecipeId,
+        device_id: DeviceId,
+        simulated: bool,
+        options: TransactionOptions,
+    ) -> Result<(), TransactionError>;
+    /// Disabled devices keep their configuration but are not started.
+    /// Takes effect when the recipe is started the next time. The web API stops or restarts devices of the active recipe right away
+    async fn update_device_enabled_with(
+        &self,
+        recipe_id: RecipeId,
+        device_id: DeviceId,
+        enabled: bool,
+        options: TransactionOptions,
+    ) -> Result<(), TransactionError>;
+    /// Requires the unlock token in `options`, if the runtime is configured with one
+    async fn update_device_locked_with(
+        &self,
+        recipe_id: RecipeId,
+        device_id: DeviceId,
+        locked: bool,
+        options: TransactionOptions,
+    ) -> Result<(), TransactionError>;
+    /// Fails if the notes exceed [`crate::MAX_DESCRIPTION_LEN`]
+    async fn update_device_notes_with(
+        &self,
+        recipe_id: RecipeId,
+        device_id: DeviceId,
+        notes: String,
+        options: TransactionOptions,
+    ) -> Result<(), TransactionError>;
+    async fn add_device_group_with(
+        &self,
+        recipe_id: RecipeId,
+        name: Name,
+        options: TransactionOptions,
+    ) -> Result<DeviceGroupId, TransactionError>;
+    async fn rename_device_group_with(
+        &self,
+        recipe_id: RecipeId,
+        group_id: DeviceGroupId,
+        name: Name,
+        options: TransactionOptions,
+    ) -> Result<(), TransactionError>;
+    /// Devices of the group remain in the recipe
+    async fn delete_device_group_with(
+        &self,
+        recipe_id: RecipeId,
+        group_id: DeviceGroupId,
+        options: TransactionOptions,
+    ) -> Result<(), TransactionError>;
+    /// `None` removes the device from its group
+    async fn update_device_group_with(
+        &self,
+        recipe_id: RecipeId,
+        device_id: DeviceId,
+        group_id: Option<DeviceGroupId>,
+        options: TransactionOptions,
+    ) -> Result<(), TransactionError>;
     fn get_update_receiver(&self) -> BoxStream<'static, Uuid>;
+
+    /// Overview of all recipes. Folder sizes might be outdated by a few seconds, as they are expensive to calculate
+    async fn stats(&self) -> Result<Vec<RecipeStats>, TransactionError>;
 }
 
 #[derive(Deserialize, Clone)]
@@ -215,9 +327,157 @@ pub trait RecipeServiceTrait {
 pub struct TransactionOptions {
     pub key: Uuid,
     pub committed: bool,
+    /// Why the change was made. Recorded in the recipe's change history
+    pub message: Option<String>,
+    /// The web layer sets the authenticated user, so clients can't impersonate others
+    #[serde(skip)]
+    pub author: Option<String>,
+    /// Required to change locked devices, if the runtime is configured with an unlock token.
+    /// The web layer takes it from a header, so it doesn't end up in access logs and browser histories
+    #[serde(skip)]
+    pub unlock_token: Option<String>,
+    /// Set by devices updating their own parameters. Clients can't set it
+    #[serde(skip)]
+    bypass_lock: bool,
+    /// Role of the authenticated user. Clients can't set it
+    #[serde(skip)]
+    role: Option<Role>,
+    /// Passed on to the messages which update running devices, so interceptors can apply their policies
+    #[serde(skip)]
+    origin: MessageOrigin,
 }
 
 impl TransactionOptions {
+    pub fn with_message(self, message: impl Into<String>) -> Self {
+        Self {
+            message: Some(message.into()),
+            ..self
+        }
+    }
+
+    pub fn with_author(self, author: impl Into<String>) -> Self {
+        Self {
+            author: Some(author.into()),
+            ..self
+        }
+    }
+
+    pub fn with_unlock_token(self, token: impl Into<String>) -> Self {
+        Self {
+            unlock_token: Some(token.into()),
+            ..self
+        }
+    }
+
+    /// Set by the web layer from the authenticated user
+    pub fn with_role(self, role: Role) -> Self {
+        Self {
+            role: Some(role),
+            ..self
+        }
+    }
+
+    pub fn role(&self) -> Option<Role> {
+        self.role
+    }
+
+    /// Takes the origin of a tagged system (see [`ActorSystem::with_web_origin`]), as origins can't be constructed directly
+    pub fn with_origin_of(self, system: &ActorSystem) -> Self {
+        Self {
+            origin: system.origin().clone(),
+            ..self
+        }
+    }
+
+    pub fn origin(&self) -> &MessageOrigin {
+        &self.origin
+    }
+
+    /// For changes which don't originate from users (e.g. devices adjusting their own parameters)
+    pub fn bypassing_lock(self) -> Self {
+        Self {
+            bypass_lock: true,
+            ..self
+        }
+    }
+
+    /// Devices can always be changed if no token is `configured`. Otherwise, the provided token has to match
+    pub fn check_unlocked(
+        &self,
+        device_id: DeviceId,
+        device: &DeviceConfig,
+        configured: Option<&str>,
+    ) -> Result<(), DeviceLockedError> {
+        if !device.locked || self.bypass_lock {
+            return Ok(());
+        }
+        match configured {
+            Some(token) if self.has_unlock_token(token) => Ok(()),
+            _ => Err(DeviceLockedError(device_id)),
+        }
+    }
+
+    /// Locking and unlocking devices requires the token, if one is configured
+    pub fn check_may_lock(
+        &self,
+        device_id: DeviceId,
+        configured: Option<&str>,
+    ) -> Result<(), DeviceLockedError> {
+        match configured {
+            Some(token) if !self.bypass_lock && !self.has_unlock_token(token) => {
+                Err(DeviceLockedError(device_id))
+            }
+            _ => Ok(()),
+        }
+    }
+
+    /// Takes the same time for all tokens of the same length, so the token can't be guessed byte by byte
+    fn has_unlock_token(&self, configured: &str) -> bool {
+        let Some(provided) = self.unlock_token.as_deref() else {
+            return false;
+        };
+        provided.len() == configured.len()
+            && provided
+                .bytes()
+                .zip(configured.bytes())
+                .fold(0, |acc, (a, b)| acc | (a ^ b))
+                == 0
+    }
+
+    /// Approval of a recipe after it was changed with these options. Changes by users reset reviews to draft.
+    /// Released and locked recipes reject them, as they must not differ from what was approved.
+    /// Devices adjusting their own parameters keep the approval
+    pub fn approval_after_change(
+        &self,
+        recipe_id: &RecipeId,
+        state: ApprovalState,
+    ) -> Result<ApprovalState, ApprovalError> {
+        match state {
+            _ if self.bypass_lock => Ok(state),
+            ApprovalState::Released => Err(ApprovalError::Released(recipe_id.clone())),
+            ApprovalState::Locked => Err(ApprovalError::Locked(recipe_id.clone())),
+            _ => Ok(ApprovalState::Draft),
+        }
+    }
+
+    /// None, if neither message nor author were provided.
+    /// Fails if one of them is longer than [`super::recipe::MAX_CHANGE_ANNOTATION_LEN`] characters
+    pub fn annotation(&self) -> Result<Option<ChangeAnnotation>, TransactionError> {
+        if self.message.is_none() && self.author.is_none() {
+            return Ok(None);
+        }
+        for text in [&self.message, &self.author].into_iter().flatten() {
+            super::recipe::check_text_len(text, super::recipe::MAX_CHANGE_ANNOTATION_LEN)
+                .map_err(TransactionError::InvalidDeviceConfig)?;
+        }
+        Ok(Some(ChangeAnnotation {
+            transaction: self.key,
+            created: chrono::Utc::now(),
+            author: self.author.clone(),
+            message: self.message.clone(),
+        }))
+    }
+
     pub fn update_device_params(
         &self,
         recipe: &mut Recipe,
@@ -237,6 +497,22 @@ impl Default for TransactionOptions {
         Self {
             key: Uuid::new_v4(),
             committed: true,
+            message: None,
+            author: None,
+            unlock_token: None,
+            bypass_lock: false,
+            role: None,
+            origin: MessageOrigin::Internal,
         }
     }
 }
+
+#[derive(Debug, thiserror::Error)]
+#[error("Device {0} is locked. Changes require a valid unlock token")]
+pub struct DeviceLockedError(pub DeviceId);
+
+impl From<DeviceLockedError> for TransactionError {
+    fn from(e: DeviceLockedError) -> Self {
+        Self::Other(e.into())
+    }
+}
@@ -0,0 +1,45 @@
+use std::collections::BTreeMap;
+
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+
+use super::{Recipe, RecipeId};
+
+/// Aggregated information for overviews, without the device parameters
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[cfg_attr(feature = "ts", derive(ts_rs::TS), ts(export))]
+pub struct RecipeStats {
+    pub id: RecipeId,
+    pub is_active: bool,
+    pub device_count: usize,
+    pub devices_per_type: BTreeMap<String, usize>,
+    /// Sum of all files in the device folders in bytes
+    pub disk_size: u64,
+    pub created: DateTime<Utc>,
+    /// Time of the last annotated change. None if the recipe wasn't changed since it was created
+    pub modified: Option<DateTime<Utc>>,
+    pub last_committer: Option<String>,
+}
+
+impl RecipeStats {
+    /// Stats of everything stored in the recipe itself. `disk_size` is 0
+    pub fn from_recipe(id: RecipeId, recipe: &Recipe, is_active: bool) -> Self {
+        let mut devices_per_type = BTreeMap::new();
+        for device in recipe.devices.values() {
+            *devices_per_type
+                .entry(device.device_type.clone())
+                .or_default() += 1;
+        }
+        let last_change = recipe.last_change();
+        Self {
+            id,
+            is_active,
+            device_count: recipe.count_devices(),
+            devices_per_type,
+            disk_size: 0,
+            created: recipe.created,
+            modified: last_change.map(|c| c.created),
+            last_committer: last_change.and_then(|c| c.author.clone()),
+        }
+    }
+}
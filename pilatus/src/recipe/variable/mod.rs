@@ -11,7 +11,10 @@ use serde::{
 };
 use serde_json::Value;
 
-use crate::{recipe::UntypedDeviceParamsWithVariables, UpdateParamsMessageError};
+use crate::{
+    device::DeviceId, recipe::UntypedDeviceParamsWithVariables, Name, RecipeId,
+    UpdateParamsMessageError,
+};
 
 pub(crate) const JSON_VAR_KEYWORD: &str = "__var";
 
@@ -20,14 +23,27 @@ mod maybe;
 pub use maybe::*;
 
 #[derive(Debug, Serialize, PartialEq, Eq)]
+#[cfg_attr(feature = "ts", derive(ts_rs::TS))]
 pub struct VariableConflict {
     pub name: String,
     pub existing: Variable,
     pub imported: Variable,
 }
 
+/// Field of a device which references a variable
 #[derive(Debug, Clone, PartialEq, Eq, Serialize)]
-pub struct Variable(Value);
+#[cfg_attr(feature = "ts", derive(ts_rs::TS), ts(export))]
+pub struct VariableUsage {
+    pub recipe_id: RecipeId,
+    pub device_id: DeviceId,
+    pub device_name: Name,
+    /// JSON pointer within the device params
+    pub path: String,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+#[cfg_attr(feature = "ts", derive(ts_rs::TS))]
+pub struct Variable(#[cfg_attr(feature = "ts", ts(type = "number | string"))] Value);
 impl From<i32> for Variable {
     fn from(x: i32) -> Self {
         Self(serde_json::to_value(x).expect("Never fails with i32"))
@@ -69,7 +85,7 @@ impl<'de> Deserialize<'de> for Variable {
 }
 pub type VariablesPatch = HashMap<String, Variable>;
 
-#[derive(Debug, Clone, Default)]
+#[derive(Debug, Clone, Default, PartialEq)]
 pub struct Variables {
     mappings: Arc<HashMap<String, Variable>>,
 }
@@ -100,6 +116,10 @@ impl Variables {
             .collect()
     }
 
+    pub fn names(&self) -> impl Iterator<Item = &str> {
+        self.mappings.keys().map(String::as_str)
+    }
+
     fn borrow_mappings(&mut self) -> &mut HashMap<String, Variable> {
         if Arc::get_mut(&mut self.mappings).is_none() {
             self.mappings = Arc::new(HashMap::clone(&self.mappings));
@@ -134,6 +154,16 @@ impl UntypedDeviceParamsWithoutVariables {
         T::deserialize(self.0.clone())
     }
 
+    /// Like [`Self::params_as`], but reports unknown and missing fields with a suggestion
+    pub(crate) fn params_as_described<T: DeserializeOwned>(
+        &self,
+    ) -> Result<T, UpdateParamsMessageError> {
+        self.params_as().map_err(|e| {
+            let present = self.0.as_object().into_iter().flat_map(|x| x.keys());
+            UpdateParamsMessageError::from_params_error(e, present.map(String::as_str))
+        })
+    }
+
     pub fn from_serializable<S: Serialize>(x: &S) -> serde_json::Result<Self> {
         Ok(Self(serde_json::to_value(x.borrow())?))
     }
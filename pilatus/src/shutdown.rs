@@ -3,11 +3,17 @@
 /// For testing, the function `register_test_services` can be used.
 /// When register_test_services is used, SystemShutdown terminates too if SystemTerminator.shutdown() is called
 use std::{
+    borrow::Cow,
     pin::Pin,
+    sync::{Arc, Mutex, Weak},
     task::{self, Poll},
 };
 
-use futures::{future::Shared, stream::AbortHandle, Future, FutureExt};
+use futures::{
+    future::{BoxFuture, Shared},
+    stream::AbortHandle,
+    Future, FutureExt,
+};
 
 type InnerPrivateState =
     Shared<Pin<std::boxed::Box<(dyn futures::Future<Output = ()> + 'static + Send + Sync)>>>;
@@ -40,3 +46,176 @@ impl SystemTerminator {
         self.0.abort();
     }
 }
+
+/// Phases are executed in the order of declaration when the system shuts down
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, serde::Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ShutdownPhase {
+    StopProducers,
+    FlushWriters,
+    CloseSockets,
+}
+
+impl ShutdownPhase {
+    pub const ALL: [ShutdownPhase; 3] = [
+        ShutdownPhase::StopProducers,
+        ShutdownPhase::FlushWriters,
+        ShutdownPhase::CloseSockets,
+    ];
+}
+
+type ShutdownHookFn = Box<dyn FnOnce() -> BoxFuture<'static, ()> + Send>;
+
+struct ShutdownHook {
+    id: u64,
+    phase: ShutdownPhase,
+    name: Cow<'static, str>,
+    callback: ShutdownHookFn,
+}
+
+#[derive(Default)]
+struct ShutdownHooksState {
+    next_id: u64,
+    hooks: Vec<ShutdownHook>,
+}
+
+/// Hosted services and devices can register callbacks, which are executed phase by phase before devices are stopped
+#[derive(Clone, Default)]
+pub struct ShutdownHooks(Arc<Mutex<ShutdownHooksState>>);
+
+impl ShutdownHooks {
+    /// The hook is removed when the guard is dropped (e.g. when a device stops before the system shuts down)
+    #[must_use = "The hook is unregistered when the guard is dropped"]
+    pub fn register<TFut: Future<Output = ()> + Send + 'static>(
+        &self,
+        phase: ShutdownPhase,
+        name: impl Into<Cow<'static, str>>,
+        callback: impl FnOnce() -> TFut + Send + 'static,
+    ) -> ShutdownHookGuard {
+        let mut lock = self.0.lock().expect("Never poisoned");
+        let id = lock.next_id;
+        lock.next_id += 1;
+        lock.hooks.push(ShutdownHook {
+            id,
+            phase,
+            name: name.into(),
+            callback: Box::new(move || callback().boxed()),
+        });
+        ShutdownHookGuard {
+            id,
+            hooks: Arc::downgrade(&self.0),
+        }
+    }
+
+    /// Runs all registered hooks phase by phase. Hooks which don't finish within the timeout of their phase are logged and abandoned
+    #[cfg(feature = "tokio")]
+    pub async fn run(&self, timeout: impl Fn(ShutdownPhase) -> std::time::Duration) {
+        let mut hooks = std::mem::take(&mut self.0.lock().expect("Never poisoned").hooks);
+        for phase in ShutdownPhase::ALL {
+            let (current, rest) = hooks.into_iter().partition(|h| h.phase == phase);
+            hooks = rest;
+            run_phase(phase, current, timeout(phase)).await;
+        }
+    }
+}
+
+#[cfg(feature = "tokio")]
+async fn run_phase(phase: ShutdownPhase, hooks: Vec<ShutdownHook>, timeout: std::time::Duration) {
+    use futures::StreamExt;
+
+    if hooks.is_empty() {
+        return;
+    }
+    tracing::debug!("Run {} shutdown hooks of phase {phase:?}", hooks.len());
+    let mut remaining = hooks
+        .iter()
+        .map(|h| (h.id, h.name.clone()))
+        .collect::<std::collections::HashMap<_, _>>();
+    let mut pending = hooks
+        .into_iter()
+        .map(|h| (h.callback)().map(move |_| h.id))
+        .collect::<futures::stream::FuturesUnordered<_>>();
+    let deadline = tokio::time::Instant::now() + timeout;
+
+    loop {
+        match tokio::time::timeout_at(deadline, pending.next()).await {
+            Ok(Some(id)) => {
+                remaining.remove(&id);
+            }
+            Ok(None) => break,
+            Err(_) => {
+                tracing::warn!(
+                    "Shutdown phase {phase:?} timed out after {timeout:?}. Laggards: {:?}",
+                    remaining.values().collect::<Vec<_>>()
+                );
+                break;
+            }
+        }
+    }
+}
+
+pub struct ShutdownHookGuard {
+    id: u64,
+    hooks: Weak<Mutex<ShutdownHooksState>>,
+}
+
+impl Drop for ShutdownHookGuard {
+    fn drop(&mut self) {
+        if let Some(hooks) = self.hooks.upgrade() {
+            hooks
+                .lock()
+                .expect("Never poisoned")
+                .hooks
+                .retain(|h| h.id != self.id);
+        }
+    }
+}
+
+#[cfg(all(test, feature = "tokio"))]
+mod tests {
+    use std::time::Duration;
+
+    use super::*;
+
+    #[tokio::test]
+    async fn run_phases_in_order() {
+        let hooks = ShutdownHooks::default();
+        let log = Arc::new(Mutex::new(Vec::new()));
+        let guards = [
+            ShutdownPhase::CloseSockets,
+            ShutdownPhase::StopProducers,
+            ShutdownPhase::FlushWriters,
+        ]
+        .map(|phase| {
+            let log = log.clone();
+            hooks.register(phase, format!("{phase:?}"), move || async move {
+                log.lock().unwrap().push(phase);
+            })
+        });
+        let dropped = hooks.register(ShutdownPhase::StopProducers, "dropped", || async {
+            panic!("Hook was unregistered");
+        });
+        drop(dropped);
+
+        hooks.run(|_| Duration::from_secs(1)).await;
+        drop(guards);
+        assert_eq!(*log.lock().unwrap(), ShutdownPhase::ALL.to_vec());
+    }
+
+    #[tokio::test]
+    async fn continue_after_laggard() {
+        let hooks = ShutdownHooks::default();
+        let finished = Arc::new(Mutex::new(false));
+        let finished_clone = finished.clone();
+        let _laggard = hooks.register(
+            ShutdownPhase::StopProducers,
+            "laggard",
+            std::future::pending::<()>,
+        );
+        let _writer = hooks.register(ShutdownPhase::FlushWriters, "writer", move || async move {
+            *finished_clone.lock().unwrap() = true;
+        });
+        hooks.run(|_| Duration::from_millis(10)).await;
+        assert!(*finished.lock().unwrap());
+    }
+}
@@ -1,6 +1,12 @@
-use std::{fmt::Debug, marker::PhantomData, num::Saturating};
+use std::{
+    collections::VecDeque,
+    fmt::Debug,
+    marker::PhantomData,
+    num::Saturating,
+    sync::{Arc, Mutex},
+};
 
-use futures::{stream::BoxStream, StreamExt};
+use futures::{stream::BoxStream, Stream, StreamExt};
 use serde::{Deserialize, Serialize};
 use stream_broadcast::StreamBroadcast;
 use tracing::{error, trace};
@@ -65,11 +71,91 @@ impl MissedItemsError {
     }
 }
 
+/// Retains the last `capacity` items of a producer, so new subscribers don't start empty:
+/// ```ignore
+/// let replay = ReplayBuffer::new(1);
+/// replay.push(value.clone());
+/// let _ignore_without_subscribers = state.sender.send(value);
+/// ...
+/// Ok(replay.replay_before(BroadcastStream::new(state.sender.subscribe())).boxed())
+/// ```
+pub struct ReplayBuffer<T>(Arc<ReplayBufferInner<T>>);
+
+struct ReplayBufferInner<T> {
+    capacity: usize,
+    items: Mutex<VecDeque<T>>,
+}
+
+impl<T> Clone for ReplayBuffer<T> {
+    fn clone(&self) -> Self {
+        Self(self.0.clone())
+    }
+}
+
+impl<T: Clone> ReplayBuffer<T> {
+    /// Retains nothing with `capacity` 0
+    pub fn new(capacity: usize) -> Self {
+        Self(Arc::new(ReplayBufferInner {
+            capacity,
+            items: Mutex::new(VecDeque::with_capacity(capacity)),
+        }))
+    }
+
+    pub fn push(&self, item: T) {
+        if self.0.capacity == 0 {
+            return;
+        }
+        let mut lock = self.0.items.lock().expect("Never poisoned");
+        if lock.len() >= self.0.capacity {
+            lock.pop_front();
+        }
+        lock.push_back(item);
+    }
+
+    /// Oldest first
+    pub fn items(&self) -> Vec<T> {
+        self.0
+            .items
+            .lock()
+            .expect("Never poisoned")
+            .iter()
+            .cloned()
+            .collect()
+    }
+
+    pub fn clear(&self) {
+        self.0.items.lock().expect("Never poisoned").clear();
+    }
+
+    /// Yields the retained items before the ones of `live`. Call it before `live` receives new items, so nothing is missed
+    pub fn replay_before<S: Stream<Item = T>>(&self, live: S) -> impl Stream<Item = T> {
+        futures::stream::iter(self.items()).chain(live)
+    }
+}
+
+/// Latest item of a subscription, e.g. to mirror device state in a UI.
+/// The subscription is polled in the background until the stream ends or all handles are dropped
+#[cfg(feature = "tokio")]
+pub fn into_latest<T: Send + Sync + 'static>(
+    stream: impl Stream<Item = T> + Send + 'static,
+) -> tokio::sync::watch::Receiver<Option<T>> {
+    let (sender, receiver) = tokio::sync::watch::channel(None);
+    tokio::spawn(async move {
+        let forward = std::pin::pin!(stream.for_each(|item| {
+            let _ignore_closed = sender.send(Some(item));
+            std::future::ready(())
+        }));
+        futures::future::select(forward, std::pin::pin!(sender.closed())).await;
+    });
+    receiver
+}
+
 pub struct SubscribeState<TResult> {
     params: SubscribeParams,
     actor_system: ActorSystem,
     self_sender: WeakUntypedActorMessageSender,
     pipeline: Box<dyn Fn() -> Option<BoxStream<'static, TResult>> + Send>,
+    replay: ReplayBuffer<TResult>,
 }
 
 impl<T> SubscribeState<T> {
@@ -82,8 +168,22 @@ impl<T> SubscribeState<T> {
             actor_system,
             self_sender,
             pipeline: Box::new(|| None),
+            replay: ReplayBuffer(Arc::new(ReplayBufferInner {
+                capacity: 0,
+                items: Default::default(),
+            })),
         }
     }
+
+    /// Subscribers which join a running pipeline receive the last `capacity` processed items first
+    pub fn with_replay(mut self, capacity: usize) -> Self
+    where
+        T: Clone,
+    {
+        self.replay = ReplayBuffer::new(capacity);
+        self
+    }
+
     pub fn update_params(&mut self, params: SubscribeParams) {
         self.params = params;
     }
@@ -120,8 +220,11 @@ impl<TOutput: Send + 'static, EOutput: Send + Debug + 'static>
     {
         let this = as_ref_state.as_mut();
         if let Some(x) = (this.pipeline)() {
-            return Ok(x);
+            return Ok(this.replay.replay_before(x).boxed());
         }
+        // Items of a previous pipeline might be outdated, e.g. after the provider changed
+        this.replay.clear();
+        let replay = this.replay.clone();
         let self_sender = this.self_sender.clone();
         let provider = this.params.provider;
         let inner = this
@@ -152,7 +255,8 @@ impl<TOutput: Send + 'static, EOutput: Send + Debug + 'static>
                         Err(e) => Err(e),
                     }
                 }
-            });
+            })
+            .inspect(move |item| replay.push(item.clone()));
 
         let stream = StreamBroadcast::new(inner.fuse(), 2);
         let downgraded = stream.downgrade();
@@ -180,3 +284,34 @@ impl<TOutput: Send + 'static, EOutput: Send + Debug + 'static>
         })) as _)
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn replay_retained_items_before_live_ones() {
+        let replay = ReplayBuffer::new(2);
+        for i in 0..3 {
+            replay.push(i);
+        }
+        let items = replay
+            .replay_before(futures::stream::iter([3, 4]))
+            .collect::<Vec<_>>()
+            .await;
+        assert_eq!(vec![1, 2, 3, 4], items);
+    }
+
+    #[cfg(feature = "tokio")]
+    #[tokio::test]
+    async fn mirror_latest_item() {
+        let (sender, receiver) = futures::channel::mpsc::unbounded::<i32>();
+        let mut latest = into_latest(receiver);
+        assert_eq!(None, *latest.borrow());
+        sender.unbounded_send(1).unwrap();
+        sender.unbounded_send(2).unwrap();
+        latest.wait_for(|x| *x == Some(2)).await.unwrap();
+        drop(sender);
+        assert!(latest.changed().await.is_err());
+    }
+}
@@ -38,6 +38,27 @@ impl Future for AbortRegistration {
     }
 }
 
+/// Aborts the handle as soon as it is dropped.
+/// Used to propagate the end of a request (e.g. a disconnecting http-client) to computations, which are observing the AbortRegistration
+pub struct AbortOnDrop(futures::stream::AbortHandle);
+
+impl AbortOnDrop {
+    pub fn new(handle: futures::stream::AbortHandle) -> Self {
+        Self(handle)
+    }
+
+    pub fn new_pair() -> (Self, futures::stream::AbortRegistration) {
+        let (handle, reg) = futures::stream::AbortHandle::new_pair();
+        (Self(handle), reg)
+    }
+}
+
+impl Drop for AbortOnDrop {
+    fn drop(&mut self) {
+        self.0.abort();
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use futures::stream::Aborted;
@@ -54,4 +75,12 @@ mod tests {
             reg.abortable(std::future::pending::<()>()).await
         );
     }
+
+    #[tokio::test]
+    async fn abort_on_drop() {
+        let (guard, reg) = AbortOnDrop::new_pair();
+        let fut = Abortable::new(std::future::pending::<()>(), reg);
+        drop(guard);
+        assert_eq!(Err(Aborted), fut.await);
+    }
 }
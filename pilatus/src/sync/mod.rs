@@ -4,6 +4,7 @@ mod accessor;
 #[cfg(any(feature = "tokio", feature = "rayon", test))]
 mod execute_blocking;
 mod once_extractor;
+mod worker_pool;
 
 pub use abort::*;
 #[cfg(feature = "tokio")]
@@ -12,3 +13,4 @@ pub use accessor::*;
 #[cfg(any(feature = "tokio", feature = "rayon", test))]
 pub use execute_blocking::*;
 pub use once_extractor::*;
+pub use worker_pool::*;
@@ -0,0 +1,256 @@
+//! Dedicated threads for blocking work, so e.g. image encoding can't starve file IO.
+//!
+//! `execute_blocking` shares one pool for everything. Devices which know the kind of their work target a pool instead:
+//! ```ignore
+//! c.with::<Registered<WorkerPools>>().register_device("my-device", validator, |ctx, params, pools| async move {
+//!     let png = pools.get(WorkerPoolKind::Encode).execute_blocking(move || image.encode_png()).await?;
+//!     ...
+//! });
+//! ```
+
+use std::{
+    fmt::Debug,
+    panic::AssertUnwindSafe,
+    sync::{
+        atomic::{AtomicU64, AtomicUsize, Ordering},
+        mpsc, Arc, Mutex,
+    },
+};
+
+use futures::{channel::oneshot, Future, FutureExt};
+use serde::{Deserialize, Serialize};
+use tracing::warn;
+
+use crate::device::ActorError;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum WorkerPoolKind {
+    /// CPU bound work which produces data for clients, e.g. image encoding
+    Encode,
+    /// Blocking file or device IO, which mostly waits
+    Io,
+    /// CPU bound processing, e.g. image algorithms
+    Compute,
+}
+
+/// Number of threads per pool. Read from the `worker_pools` section of the config
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct WorkerPoolsConfig {
+    pub encode: usize,
+    pub io: usize,
+    pub compute: usize,
+}
+
+impl Default for WorkerPoolsConfig {
+    fn default() -> Self {
+        let parallelism = std::thread::available_parallelism()
+            .map(|x| x.get())
+            .unwrap_or(4);
+        Self {
+            encode: (parallelism / 2).max(1),
+            io: 4,
+            compute: parallelism,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct WorkerPoolUtilization {
+    pub kind: WorkerPoolKind,
+    pub threads: usize,
+    /// Jobs which are currently executed
+    pub busy: usize,
+    /// Jobs which wait for a free thread
+    pub queued: usize,
+    pub completed: u64,
+}
+
+/// Cheap to clone. The threads stop after the last clone is dropped and the queued jobs are done
+#[derive(Debug, Clone)]
+pub struct WorkerPools {
+    encode: WorkerPool,
+    io: WorkerPool,
+    compute: WorkerPool,
+}
+
+impl WorkerPools {
+    pub fn new(config: &WorkerPoolsConfig) -> Self {
+        Self {
+            encode: WorkerPool::new(WorkerPoolKind::Encode, config.encode),
+            io: WorkerPool::new(WorkerPoolKind::Io, config.io),
+            compute: WorkerPool::new(WorkerPoolKind::Compute, config.compute),
+        }
+    }
+
+    pub fn get(&self, kind: WorkerPoolKind) -> &WorkerPool {
+        match kind {
+            WorkerPoolKind::Encode => &self.encode,
+            WorkerPoolKind::Io => &self.io,
+            WorkerPoolKind::Compute => &self.compute,
+        }
+    }
+
+    pub fn utilization(&self) -> Vec<WorkerPoolUtilization> {
+        [&self.encode, &self.io, &self.compute]
+            .into_iter()
+            .map(WorkerPool::utilization)
+            .collect()
+    }
+}
+
+type Job = Box<dyn FnOnce() + Send>;
+
+#[derive(Clone)]
+pub struct WorkerPool(Arc<WorkerPoolInner>);
+
+struct WorkerPoolInner {
+    kind: WorkerPoolKind,
+    threads: usize,
+    sender: Mutex<mpsc::Sender<Job>>,
+    busy: Arc<AtomicUsize>,
+    queued: Arc<AtomicUsize>,
+    completed: Arc<AtomicU64>,
+}
+
+impl Debug for WorkerPool {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_tuple("WorkerPool")
+            .field(&self.utilization())
+            .finish()
+    }
+}
+
+impl WorkerPool {
+    /// Uses at least one thread
+    pub fn new(kind: WorkerPoolKind, threads: usize) -> Self {
+        let threads = threads.max(1);
+        let (sender, receiver) = mpsc::channel::<Job>();
+        let receiver = Arc::new(Mutex::new(receiver));
+        let busy = Arc::new(AtomicUsize::new(0));
+        let queued = Arc::new(AtomicUsize::new(0));
+        let completed = Arc::new(AtomicU64::new(0));
+
+        for i in 0..threads {
+            let receiver = receiver.clone();
+            let (busy, queued, completed) = (busy.clone(), queued.clone(), completed.clone());
+            let spawned = std::thread::Builder::new()
+                .name(format!("pilatus-{kind:?}-{i}").to_lowercase())
+                .spawn(move || loop {
+                    let job = match receiver.lock().expect("Never poisoned").recv() {
+                        Ok(x) => x,
+                        Err(_) => break,
+                    };
+                    queued.fetch_sub(1, Ordering::Relaxed);
+                    busy.fetch_add(1, Ordering::Relaxed);
+                    // The caller gets ActorError::Aborted, as the response channel is dropped
+                    let _ignore_panic = std::panic::catch_unwind(AssertUnwindSafe(job));
+                    busy.fetch_sub(1, Ordering::Relaxed);
+                    completed.fetch_add(1, Ordering::Relaxed);
+                });
+            if let Err(e) = spawned {
+                warn!("Cannot spawn thread {i} of the {kind:?} pool: {e}");
+            }
+        }
+
+        Self(Arc::new(WorkerPoolInner {
+            kind,
+            threads,
+            sender: Mutex::new(sender),
+            busy,
+            queued,
+            completed,
+        }))
+    }
+
+    pub fn execute_blocking<TOk: Send + 'static, TErr: Send + 'static + Debug>(
+        &self,
+        f: impl FnOnce() -> Result<TOk, TErr> + Send + 'static,
+    ) -> impl Future<Output = Result<TOk, ActorError<TErr>>> {
+        self.process_blocking(move || (f)().map_err(ActorError::custom))
+    }
+
+    pub fn process_blocking<TOk: Send + 'static, TErr: Send + 'static + Debug>(
+        &self,
+        f: impl FnOnce() -> Result<TOk, ActorError<TErr>> + Send + 'static,
+    ) -> impl Future<Output = Result<TOk, ActorError<TErr>>> {
+        let (tx, rx) = oneshot::channel();
+        let job: Job = Box::new(move || {
+            let _ignore_abortion = tx.send((f)());
+        });
+        self.0.queued.fetch_add(1, Ordering::Relaxed);
+        if self
+            .0
+            .sender
+            .lock()
+            .expect("Never poisoned")
+            .send(job)
+            .is_err()
+        {
+            self.0.queued.fetch_sub(1, Ordering::Relaxed);
+        }
+        rx.map(|x| x?)
+    }
+
+    pub fn utilization(&self) -> WorkerPoolUtilization {
+        WorkerPoolUtilization {
+            kind: self.0.kind,
+            threads: self.0.threads,
+            busy: self.0.busy.load(Ordering::Relaxed),
+            queued: self.0.queued.load(Ordering::Relaxed),
+            completed: self.0.completed.load(Ordering::Relaxed),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use super::*;
+
+    #[tokio::test]
+    async fn busy_pool_doesnt_block_others() {
+        let pools = WorkerPools::new(&WorkerPoolsConfig {
+            encode: 1,
+            io: 1,
+            compute: 1,
+        });
+        let (started_tx, started_rx) = std::sync::mpsc::channel::<()>();
+        let (release_tx, release_rx) = std::sync::mpsc::channel::<()>();
+        let blocked = pools.get(WorkerPoolKind::Encode).execute_blocking(move || {
+            started_tx.send(()).ok();
+            release_rx.recv()
+        });
+        started_rx.recv_timeout(Duration::from_secs(10)).unwrap();
+        let queued = pools
+            .get(WorkerPoolKind::Encode)
+            .execute_blocking(|| Ok::<_, ()>(1));
+
+        let io = tokio::time::timeout(
+            Duration::from_secs(10),
+            pools
+                .get(WorkerPoolKind::Io)
+                .execute_blocking(|| Ok::<_, ()>(2)),
+        )
+        .await
+        .expect("Io pool is independent");
+        assert_eq!(Ok(2), io);
+
+        let encode = pools.get(WorkerPoolKind::Encode).utilization();
+        assert_eq!((1, 1), (encode.busy, encode.queued));
+
+        release_tx.send(()).unwrap();
+        assert_eq!(Ok(()), blocked.await);
+        assert_eq!(Ok(1), queued.await);
+    }
+
+    #[tokio::test]
+    async fn panics_abort_the_job_only() {
+        let pool = WorkerPool::new(WorkerPoolKind::Compute, 1);
+        let panicked = pool.execute_blocking(|| -> Result<(), ()> { panic!("Expected") });
+        assert_eq!(Err(ActorError::Aborted), panicked.await);
+        assert_eq!(Ok(1), pool.execute_blocking(|| Ok::<_, ()>(1)).await);
+    }
+}
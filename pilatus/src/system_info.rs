@@ -0,0 +1,166 @@
+//! Identity of the machine and the license it runs with
+//!
+//! Device crates gate licensed features by injecting `LicenseState`:
+//! ```ignore
+//! c.with::<Registered<LicenseState>>().register_device("my-device", validator, |ctx, params, license| async move {
+//!     license.require("my-device")?;
+//!     ...
+//! });
+//! ```
+
+use std::{collections::BTreeSet, sync::Arc};
+
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use uuid::Uuid;
+
+/// Identifies a machine across restarts and updates. It is derived from identifiers of the OS or the hardware, so it doesn't move with the data directory
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(transparent)]
+pub struct MachineId(pub Uuid);
+
+impl std::fmt::Display for MachineId {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        self.0.fmt(f)
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct License {
+    pub licensee: String,
+    /// License is only valid on this machine, if set
+    #[serde(default)]
+    pub machine_id: Option<MachineId>,
+    #[serde(default)]
+    pub expires: Option<DateTime<Utc>>,
+    /// Feature flags which can be checked by device crates
+    #[serde(default)]
+    pub features: BTreeSet<String>,
+}
+
+#[derive(Debug, thiserror::Error, PartialEq, Eq)]
+pub enum LicenseError {
+    #[error("No valid license is installed")]
+    Missing,
+    #[error("License expired at {0}")]
+    Expired(DateTime<Utc>),
+    #[error("Feature '{0}' is not licensed")]
+    Unlicensed(String),
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+#[serde(rename_all = "snake_case", tag = "state")]
+pub enum LicenseStatus {
+    Missing,
+    Valid {
+        license: License,
+    },
+    /// License file exists, but cannot be used (e.g. wrong signature or different machine)
+    Invalid {
+        reason: String,
+    },
+}
+
+/// Cheap to clone. The license is verified once during startup
+#[derive(Debug, Clone)]
+pub struct LicenseState(Arc<LicenseStatus>);
+
+impl Default for LicenseState {
+    fn default() -> Self {
+        Self::new(LicenseStatus::Missing)
+    }
+}
+
+impl LicenseState {
+    pub fn new(status: LicenseStatus) -> Self {
+        Self(Arc::new(status))
+    }
+
+    pub fn status(&self) -> &LicenseStatus {
+        &self.0
+    }
+
+    pub fn has_feature(&self, feature: &str) -> bool {
+        self.require(feature).is_ok()
+    }
+
+    pub fn require(&self, feature: &str) -> Result<(), LicenseError> {
+        let LicenseStatus::Valid { license } = &*self.0 else {
+            return Err(LicenseError::Missing);
+        };
+        if let Some(expires) = license.expires {
+            if expires < Utc::now() {
+                return Err(LicenseError::Expired(expires));
+            }
+        }
+        if license.features.contains(feature) {
+            Ok(())
+        } else {
+            Err(LicenseError::Unlicensed(feature.into()))
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct SystemInfo {
+    pub machine_id: MachineId,
+    pub pilatus_version: &'static str,
+    pub os: &'static str,
+    pub arch: &'static str,
+    /// Plugins which were loaded from dynamic libraries
+    pub plugins: Vec<String>,
+    pub license: LicenseStatus,
+}
+
+impl SystemInfo {
+    pub fn new(machine_id: MachineId, plugins: Vec<String>, license: &LicenseState) -> Self {
+        Self {
+            machine_id,
+            pilatus_version: env!("CARGO_PKG_VERSION"),
+            os: std::env::consts::OS,
+            arch: std::env::consts::ARCH,
+            plugins,
+            license: license.status().clone(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn license(expires: Option<DateTime<Utc>>) -> LicenseState {
+        LicenseState::new(LicenseStatus::Valid {
+            license: License {
+                licensee: "ACME".into(),
+                machine_id: None,
+                expires,
+                features: BTreeSet::from(["ocr".into()]),
+            },
+        })
+    }
+
+    #[test]
+    fn require_licensed_feature() {
+        let state = license(None);
+        assert_eq!(Ok(()), state.require("ocr"));
+        assert_eq!(
+            Err(LicenseError::Unlicensed("3d".into())),
+            state.require("3d")
+        );
+        assert_eq!(
+            Err(LicenseError::Missing),
+            LicenseState::default().require("ocr")
+        );
+    }
+
+    #[test]
+    fn expired_license_grants_nothing() {
+        let expires = Utc::now() - chrono::Duration::days(1);
+        assert_eq!(
+            Err(LicenseError::Expired(expires)),
+            license(Some(expires)).require("ocr")
+        );
+    }
+}
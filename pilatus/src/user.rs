@@ -0,0 +1,83 @@
+//! Users which authenticate with API tokens, so changes can be attributed to actual people
+
+use std::sync::Arc;
+
+use async_trait::async_trait;
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use uuid::Uuid;
+
+/// Ordered by privileges, so `role >= Role::Operator` includes admins
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Role {
+    Viewer,
+    Operator,
+    Admin,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct User {
+    pub name: String,
+    pub role: Role,
+}
+
+/// Metadata of an issued token. The token itself is only returned once by `UserServiceTrait::login`
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct TokenInfo {
+    pub id: Uuid,
+    /// Chosen by the user, e.g. "ci-pipeline"
+    pub label: String,
+    pub created: DateTime<Utc>,
+    pub expires: Option<DateTime<Utc>>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct IssuedToken {
+    pub token: String,
+    #[serde(flatten)]
+    pub info: TokenInfo,
+}
+
+#[derive(Debug, thiserror::Error)]
+pub enum UserError {
+    #[error("Invalid user name or password")]
+    InvalidCredentials,
+    #[error("User '{0}' doesn't exist")]
+    UnknownUser(String),
+    #[error("Token {0} doesn't exist")]
+    UnknownToken(Uuid),
+    #[error("Invalid user: {0}")]
+    Invalid(String),
+    #[error("Users exist already, so only admins can add users")]
+    AlreadyInitialized,
+    #[error("{0}")]
+    Other(#[from] anyhow::Error),
+}
+
+pub type UserService = Arc<dyn UserServiceTrait + Send + Sync>;
+
+#[async_trait]
+pub trait UserServiceTrait {
+    /// Resolves the user of a token, unless it's unknown, revoked or expired
+    fn authenticate(&self, token: &str) -> Option<User>;
+    /// Without users, the first one can be created without authentication
+    fn has_users(&self) -> bool;
+    async fn users(&self) -> Vec<User>;
+    /// Creates a user or replaces password and role of an existing one. Changing the password revokes all tokens of the user
+    async fn set_user(&self, user: User, password: String) -> Result<(), UserError>;
+    /// Creates the first user without authentication. Fails with [`UserError::AlreadyInitialized`] if any user exists,
+    /// which is checked atomically with the insert, so concurrent requests can't create multiple initial users
+    async fn create_first_user(&self, user: User, password: String) -> Result<(), UserError>;
+    /// Revokes all tokens of the user as well
+    async fn delete_user(&self, name: &str) -> Result<(), UserError>;
+    async fn login(
+        &self,
+        name: &str,
+        password: &str,
+        label: String,
+        expires: Option<DateTime<Utc>>,
+    ) -> Result<IssuedToken, UserError>;
+    async fn tokens(&self, name: &str) -> Vec<TokenInfo>;
+    async fn revoke_token(&self, name: &str, id: Uuid) -> Result<(), UserError>;
+}
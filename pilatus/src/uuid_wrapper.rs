@@ -1,6 +1,7 @@
 macro_rules! wrapped_uuid {
     ($name:ident) => {
         #[derive(Copy, Clone, PartialEq, Eq, PartialOrd, Ord, Hash, Debug)]
+        #[cfg_attr(feature = "ts", derive(ts_rs::TS))]
         pub struct $name(uuid::Uuid);
 
         impl $name {